@@ -0,0 +1,125 @@
+//! Bakes the feature flags declared in `wextrunk.toml` into the build, as the
+//! `flags!` invocation included by `src/flags.rs`, and the message keys of the
+//! single-file catalog (`messages.toml`) into `src/i18n.rs`, for `t!` to check.
+//!
+//! Each flag's value is its `default`, overridden by `[profiles.<profile>.flags]` for
+//! the profile named by `WEXTRUNK_PROFILE`, falling back to Cargo's profile (`debug`
+//! or `release`), like wextrunk's own profile selection.
+
+use std::{collections::BTreeMap, env, fmt::Write, fs, path::Path};
+
+use serde::Deserialize;
+
+const CONFIG_FILE: &str = "wextrunk.toml";
+
+/// The parts of `wextrunk.toml` that affect compilation. wextrunk validates the rest.
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Config {
+    flags: BTreeMap<String, Flag>,
+    profiles: BTreeMap<String, Profile>,
+    i18n: I18n,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Flag {
+    description: String,
+    default: bool,
+    soft: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(default)]
+struct I18n {
+    messages_file: String,
+}
+
+impl Default for I18n {
+    fn default() -> Self {
+        I18n {
+            messages_file: "messages.toml".to_string(),
+        }
+    }
+}
+
+#[derive(Default, Deserialize)]
+#[serde(default)]
+struct Profile {
+    flags: BTreeMap<String, bool>,
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed={CONFIG_FILE}");
+    println!("cargo:rerun-if-env-changed=WEXTRUNK_PROFILE");
+
+    let config: Config = match fs::read_to_string(CONFIG_FILE) {
+        Ok(contents) => toml::from_str(&contents)
+            .unwrap_or_else(|e| panic!("Failed to parse {CONFIG_FILE}: {e}")),
+        Err(_) => Config::default(),
+    };
+    for (profile, settings) in &config.profiles {
+        if let Some(name) = settings
+            .flags
+            .keys()
+            .find(|name| !config.flags.contains_key(*name))
+        {
+            panic!("[profiles.{profile}.flags] sets {name:?}, which isn't declared under [flags].");
+        }
+    }
+
+    let profile = env::var("WEXTRUNK_PROFILE")
+        .or_else(|_| env::var("PROFILE"))
+        .unwrap_or_default();
+    let overrides = config.profiles.get(&profile).map(|profile| &profile.flags);
+
+    let mut output = String::from("flags! {\n");
+    for (name, flag) in &config.flags {
+        let valid = name.starts_with(|c: char| c.is_ascii_lowercase())
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+        if !valid {
+            panic!("Flag names must be snake_case, e.g. `new_popup`, but found {name:?}.");
+        }
+        let enabled = overrides
+            .and_then(|overrides| overrides.get(name))
+            .copied()
+            .unwrap_or(flag.default);
+        writeln!(
+            output,
+            "    {}: {name:?}, {:?}, soft = {}, enabled = {enabled};",
+            name.to_uppercase(),
+            flag.description,
+            flag.soft,
+        )
+        .unwrap();
+    }
+    output.push_str("}\n");
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("flags.rs"),
+        output,
+    )
+    .unwrap();
+
+    write_message_keys(&config.i18n.messages_file);
+}
+
+/// Write the catalog's message keys, or `None` without a single-file catalog (keys in
+/// `_locales` are only checked by wextrunk).
+fn write_message_keys(messages_file: &str) {
+    println!("cargo:rerun-if-changed={messages_file}");
+    let output = match fs::read_to_string(messages_file) {
+        Ok(contents) => {
+            let catalog: toml::Table = toml::from_str(&contents)
+                .unwrap_or_else(|e| panic!("Failed to parse {messages_file}: {e}"));
+            format!("Some(&{:?})\n", catalog.keys().collect::<Vec<_>>())
+        }
+        Err(_) => "None\n".to_string(),
+    };
+    fs::write(
+        Path::new(&env::var("OUT_DIR").unwrap()).join("messages.rs"),
+        output,
+    )
+    .unwrap();
+}
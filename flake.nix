@@ -36,6 +36,9 @@
             trunk
             leptosfmt
             symbolicator
+            binaryen
+            oxipng
+            (python3.withPackages (ps: [ ps.fonttools ps.brotli ]))
           ];
         };
       }
@@ -0,0 +1,61 @@
+// Trunk initializer (`data-initializer` on the rust link in index.html): shows a
+// loading bar at the top of extension pages while the wasm downloads, until the entry
+// function returns. The Rust side can report its own startup work on the same bar with
+// `wext::loading`. This also runs in the background's service worker, which has no
+// document, so everything here checks for one first.
+
+const ID = 'wext-loading';
+
+const bar = () => {
+  if (typeof document === 'undefined') {
+    return null;
+  }
+  let container = document.getElementById(ID);
+  if (!container) {
+    container = document.createElement('div');
+    container.id = ID;
+    container.setAttribute('role', 'progressbar');
+    container.setAttribute('aria-label', 'Loading');
+    container.style.cssText =
+      'position: fixed; inset: 0 0 auto 0; height: 3px; z-index: 2147483647; pointer-events: none;';
+    const fill = document.createElement('div');
+    fill.style.cssText =
+      'height: 100%; width: 0; background: currentColor; opacity: 0.6; transition: width 0.15s;';
+    container.append(fill);
+    document.documentElement.append(container);
+  }
+  return container;
+};
+
+const setProgress = (fraction) => {
+  const container = bar();
+  if (!container) {
+    return;
+  }
+  const percent = Math.round(Math.min(Math.max(fraction, 0), 1) * 100);
+  container.setAttribute('aria-valuenow', String(percent));
+  container.firstElementChild.style.width = `${percent}%`;
+};
+
+const remove = () => {
+  if (typeof document !== 'undefined') {
+    document.getElementById(ID)?.remove();
+  }
+};
+
+export default function initializer() {
+  if (typeof window !== 'undefined') {
+    window.addEventListener('TrunkApplicationStarted', remove, { once: true });
+  }
+  return {
+    onStart: () => setProgress(0),
+    onProgress: ({ current, total }) => {
+      // The size is unknown when the server compresses the wasm.
+      if (total) {
+        setProgress(current / total);
+      }
+    },
+    onSuccess: () => setProgress(1),
+    onFailure: remove,
+  };
+}
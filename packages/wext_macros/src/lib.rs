@@ -0,0 +1,173 @@
+//! Procedural macros for the extension runtime.
+
+use proc_macro::TokenStream;
+use proc_macro2::Span;
+use quote::quote;
+use syn::{
+    parse::{Parse, ParseStream},
+    parse_macro_input, Error, Ident, ItemFn, Lit, LitByteStr, LitStr, Token,
+};
+
+/// Contexts an entry point can run in, and the `entry::Context` variant for each.
+const CONTEXTS: &[(&str, &str)] = &[
+    ("background", "Background"),
+    ("popup", "Popup"),
+    ("options", "Options"),
+    ("content", "Content"),
+    ("page", "Page"),
+    ("report", "Report"),
+    ("newtab", "NewTab"),
+    ("history", "History"),
+    ("bookmarks", "Bookmarks"),
+];
+
+/// Mark a function as the entry point of an extension context, e.g.
+/// `#[wext_entry(popup)] async fn popup_page() { .. }`.
+///
+/// Generates the `#[wasm_bindgen]` export, and calls `entry::start` first, which
+/// installs the panic hook, records the current environment and loads the feature
+/// flag overrides and installation record. The entry name is also recorded in the
+/// `wext_entries` custom section of the wasm, which wextrunk checks every `wasm-fn` in
+/// index.html against.
+#[proc_macro_attribute]
+pub fn wext_entry(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let context = parse_macro_input!(attr as Ident);
+    let function = parse_macro_input!(item as ItemFn);
+
+    let Some((_, variant)) = CONTEXTS.iter().find(|(name, _)| context == name) else {
+        let expected: Vec<_> = CONTEXTS.iter().map(|(name, _)| *name).collect();
+        return Error::new(
+            context.span(),
+            format!("unknown context; expected one of: {}", expected.join(", ")),
+        )
+        .to_compile_error()
+        .into();
+    };
+    if function.sig.asyncness.is_none() || !function.sig.inputs.is_empty() {
+        return Error::new_spanned(
+            &function.sig,
+            "#[wext_entry] functions must be `async fn` without arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+
+    let variant = Ident::new(variant, Span::call_site());
+    let name = function.sig.ident.to_string();
+    let mut section = name.clone().into_bytes();
+    section.push(b'\n');
+    let section_len = section.len();
+    let section = LitByteStr::new(&section, Span::call_site());
+
+    let attrs = &function.attrs;
+    let vis = &function.vis;
+    let sig = &function.sig;
+    let block = &function.block;
+    quote! {
+        #(#attrs)*
+        #[::wasm_bindgen::prelude::wasm_bindgen]
+        #vis #sig {
+            #[cfg_attr(target_family = "wasm", link_section = "wext_entries")]
+            #[used]
+            static ENTRY: [u8; #section_len] = *#section;
+
+            crate::entry::start(crate::entry::Context::#variant, #name).await;
+            #block
+        }
+    }
+    .into()
+}
+
+/// The arguments of `#[on_alarm(..)]`.
+struct AlarmArgs {
+    name: LitStr,
+    period_minutes: Option<f64>,
+    delay_minutes: Option<f64>,
+}
+
+impl Parse for AlarmArgs {
+    fn parse(input: ParseStream) -> syn::Result<Self> {
+        let mut args = AlarmArgs {
+            name: input.parse()?,
+            period_minutes: None,
+            delay_minutes: None,
+        };
+        while !input.is_empty() {
+            input.parse::<Token![,]>()?;
+            if input.is_empty() {
+                break;
+            }
+            let key: Ident = input.parse()?;
+            input.parse::<Token![=]>()?;
+            let minutes = match input.parse()? {
+                Lit::Int(value) => value.base10_parse::<u32>()? as f64,
+                Lit::Float(value) => value.base10_parse()?,
+                value => return Err(Error::new_spanned(value, "expected a number of minutes")),
+            };
+            if key == "period_minutes" {
+                args.period_minutes = Some(minutes);
+            } else if key == "delay_minutes" {
+                args.delay_minutes = Some(minutes);
+            } else {
+                return Err(Error::new(
+                    key.span(),
+                    "unknown argument; expected period_minutes or delay_minutes",
+                ));
+            }
+        }
+        Ok(args)
+    }
+}
+
+/// Run an async function periodically in the background, e.g.
+/// `#[on_alarm("sync-data", period_minutes = 15)] async fn sync_data() { .. }`.
+///
+/// Generates an `alarms::AlarmRoute` constant named after the function in upper case,
+/// e.g. `SYNC_DATA`, which the background passes to `alarms::route`. The function may
+/// return `()` or a `Result` whose error is displayable. `delay_minutes` sets the delay
+/// before the first run, the period by default.
+#[proc_macro_attribute]
+pub fn on_alarm(attr: TokenStream, item: TokenStream) -> TokenStream {
+    let args = parse_macro_input!(attr as AlarmArgs);
+    let function = parse_macro_input!(item as ItemFn);
+
+    if function.sig.asyncness.is_none() || !function.sig.inputs.is_empty() {
+        return Error::new_spanned(
+            &function.sig,
+            "#[on_alarm] functions must be `async fn` without arguments",
+        )
+        .to_compile_error()
+        .into();
+    }
+    let Some(period_minutes) = args.period_minutes else {
+        return Error::new(args.name.span(), "#[on_alarm] needs `period_minutes = ..`")
+            .to_compile_error()
+            .into();
+    };
+    let delay_minutes = match args.delay_minutes {
+        Some(minutes) => quote!(::std::option::Option::Some(#minutes)),
+        None => quote!(::std::option::Option::None),
+    };
+
+    let name = &args.name;
+    let vis = &function.vis;
+    let ident = &function.sig.ident;
+    let route = Ident::new(&ident.to_string().to_uppercase(), ident.span());
+    let doc = format!("Routes the `{}` alarm to [`{ident}`].", name.value());
+    quote! {
+        #function
+
+        #[doc = #doc]
+        #vis const #route: crate::alarms::AlarmRoute = crate::alarms::AlarmRoute {
+            name: #name,
+            period_minutes: #period_minutes,
+            delay_minutes: #delay_minutes,
+            handler: || -> crate::alarms::AlarmFuture {
+                ::std::boxed::Box::pin(async {
+                    crate::alarms::AlarmOutcome::into_result(#ident().await)
+                })
+            },
+        };
+    }
+    .into()
+}
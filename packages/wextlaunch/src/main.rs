@@ -0,0 +1,384 @@
+//! Launches the extension in several isolated browser profiles at once.
+//!
+//! Some behaviour only shows with more than one browser: `storage.sync` between two
+//! installs signed in to the same account, or a companion site messaging the
+//! extension in one window while another is left alone. This starts one browser per
+//! profile, each with its own user data directory under `target/profiles` and the
+//! extension loaded unpacked from `dist`, with their windows side by side.
+//!
+//! Profiles are kept between runs, so sign-ins and extension storage survive;
+//! `--fresh` starts them over. Run it next to `trunk serve`, which rebuilds `dist`
+//! and reloads the extension in every profile.
+//!
+//! With `--package`, Firefox profiles get a packaged build instead, without signing
+//! it on AMO: as a temporary add-on, installed over the remote debugging protocol
+//! (any channel; gone when Firefox quits), or with `--install permanent`, sideloaded
+//! into the profile with `xpinstall.signatures.required` off, which only Nightly,
+//! Developer Edition and unbranded builds honour.
+//!
+//! ```sh
+//! cargo wextlaunch --profiles 2 --url https://example.com/
+//! cargo wextlaunch --target firefox --profile alice --profile bob
+//! cargo wextlaunch --target firefox --package target/dist/leptos_extension-1.0.0-firefox.zip
+//! ```
+
+use std::{
+    env, fs,
+    io::Read,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use serde_json::Value;
+
+use rdp::Rdp;
+
+mod rdp;
+
+/// Where the profiles go, relative to the source dir, followed by the target.
+const PROFILES_DIR: &str = "target/profiles";
+/// The size of each profile's window, which are laid out left to right.
+const WINDOW_SIZE: (u32, u32) = (960, 900);
+/// How often to check whether the browsers are still running.
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+/// How long to wait for Firefox's debugger server.
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Firefox prefs for installing unsigned packages, written to the profile's `user.js`.
+/// The signature pref is ignored by release and beta builds.
+const UNSIGNED_PREFS: &[(&str, &str)] = &[
+    ("xpinstall.signatures.required", "false"),
+    // Enable sideloaded add-ons without asking.
+    ("extensions.autoDisableScopes", "0"),
+    ("extensions.enabledScopes", "15"),
+    // The debugger server, without the prompt for every connection.
+    ("devtools.debugger.remote-enabled", "true"),
+    ("devtools.chrome.enabled", "true"),
+    ("devtools.debugger.prompt-connection", "false"),
+    ("browser.shell.checkDefaultBrowser", "false"),
+];
+
+const USAGE: &str = "Usage: cargo wextlaunch [--profiles <count> | --profile <name>...]
+       [--target chrome|firefox] [--dist <dir>] [--url <page>] [--browser <path>] [--fresh]
+       [--package <zip> [--install temporary|permanent]]";
+
+/// How `--package` installs the extension in Firefox.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Install {
+    /// Over the remote debugging protocol, until Firefox quits.
+    Temporary,
+    /// Sideloaded into the profile, which needs unsigned installs.
+    Permanent,
+}
+
+/// Command-line options.
+#[derive(Debug)]
+struct Options {
+    profiles: Vec<String>,
+    target: String,
+    dist: PathBuf,
+    url: Option<String>,
+    browser: Option<String>,
+    fresh: bool,
+    package: Option<PathBuf>,
+    install: Install,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Options {
+            profiles: Vec::new(),
+            target: env::var("WEXTRUNK_TARGET").unwrap_or_else(|_| "chrome".to_string()),
+            dist: "dist".into(),
+            url: None,
+            browser: None,
+            fresh: false,
+            package: None,
+            install: Install::Temporary,
+        };
+        let mut count = None;
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--profiles" => {
+                    let value = value()?;
+                    count = Some(
+                        value
+                            .parse::<usize>()
+                            .ok()
+                            .filter(|count| *count > 0)
+                            .ok_or_else(|| format!("Invalid profile count {value:?}"))?,
+                    );
+                }
+                "--profile" => options.profiles.push(value()?),
+                "--target" => options.target = value()?,
+                "--dist" => options.dist = value()?.into(),
+                "--url" => options.url = Some(value()?),
+                "--browser" => options.browser = Some(value()?),
+                "--fresh" => options.fresh = true,
+                "--package" => options.package = Some(value()?.into()),
+                "--install" => {
+                    options.install = match value()?.as_str() {
+                        "temporary" => Install::Temporary,
+                        "permanent" => Install::Permanent,
+                        other => return Err(format!("Unknown install mode {other:?}")),
+                    }
+                }
+                _ => return Err(format!("Unknown argument {arg:?}")),
+            }
+        }
+        if count.is_some() && !options.profiles.is_empty() {
+            return Err("Pass either --profiles or --profile, not both".to_string());
+        }
+        if options.profiles.is_empty() {
+            options.profiles = (1..=count.unwrap_or(2))
+                .map(|index| format!("profile-{index}"))
+                .collect();
+        }
+        for name in &options.profiles {
+            if name.is_empty()
+                || !name
+                    .chars()
+                    .all(|c| c.is_alphanumeric() || "-_".contains(c))
+            {
+                return Err(format!(
+                    "Invalid profile name {name:?}; use letters, digits, `-` and `_`"
+                ));
+            }
+        }
+        if !["chrome", "firefox"].contains(&options.target.as_str()) {
+            return Err(format!(
+                "Unknown target {:?}; use chrome or firefox",
+                options.target
+            ));
+        }
+        if options.package.is_some() && options.target != "firefox" {
+            return Err("--package is only supported with --target firefox".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn main() {
+    let options = Options::parse().unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        process::exit(2);
+    });
+    let source_dir = env::current_dir().unwrap();
+    let dist = source_dir.join(&options.dist);
+    let package = options
+        .package
+        .as_ref()
+        .map(|package| source_dir.join(package));
+    if let Some(package) = &package {
+        if !package.is_file() {
+            eprintln!("{} doesn't exist.", package.display());
+            process::exit(1);
+        }
+    } else if !dist.join("manifest.json").exists() {
+        eprintln!(
+            "{} has no manifest.json; build the extension first (e.g. with `trunk serve`).",
+            dist.display()
+        );
+        process::exit(1);
+    }
+
+    let profiles_dir = source_dir.join(PROFILES_DIR).join(&options.target);
+    let mut browsers: Vec<(String, Child)> = Vec::new();
+    for (index, name) in options.profiles.iter().enumerate() {
+        let profile_dir = profiles_dir.join(name);
+        if options.fresh && profile_dir.exists() {
+            fs::remove_dir_all(&profile_dir).unwrap();
+        }
+        fs::create_dir_all(&profile_dir).unwrap();
+        let child = if let Some(package) = &package {
+            launch_firefox_package(&options, package, &profile_dir)
+        } else if options.target == "firefox" {
+            launch_firefox(&options, &dist, &profile_dir)
+        } else {
+            launch_chrome(&options, index, &dist, &profile_dir)
+        };
+        println!("Launched {name} ({})", profile_dir.display());
+        browsers.push((name.clone(), child));
+    }
+
+    // Profiles can be closed one by one; stop once every browser is gone.
+    while !browsers.is_empty() {
+        browsers.retain_mut(|(name, child)| match child.try_wait() {
+            Ok(None) => true,
+            Ok(Some(status)) => {
+                println!("{name} exited ({status})");
+                false
+            }
+            Err(e) => {
+                println!("Lost track of {name}: {e}");
+                false
+            }
+        });
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Launch Chromium with `profile_dir` as its user data directory, which makes it a
+/// separate browser instance, with its window in the `index`th slot.
+fn launch_chrome(options: &Options, index: usize, dist: &Path, profile_dir: &Path) -> Child {
+    let (width, height) = WINDOW_SIZE;
+    let mut args = vec![
+        format!("--user-data-dir={}", profile_dir.display()),
+        format!("--load-extension={}", dist.display()),
+        format!("--window-position={},0", index as u32 * width),
+        format!("--window-size={width},{height}"),
+        "--no-first-run".to_string(),
+        "--no-default-browser-check".to_string(),
+        // Branded Chrome ignores --load-extension since version 137.
+        "--disable-features=DisableLoadExtensionCommandLineSwitch".to_string(),
+    ];
+    args.push(
+        options
+            .url
+            .clone()
+            .unwrap_or_else(|| "chrome://extensions".to_string()),
+    );
+
+    let candidates = match &options.browser {
+        Some(browser) => vec![browser.clone()],
+        None => ["chromium", "chromium-browser", "google-chrome", "chrome"]
+            .map(str::to_string)
+            .to_vec(),
+    };
+    for browser in &candidates {
+        if let Ok(child) = Command::new(browser)
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            return child;
+        }
+    }
+    panic!(
+        "Couldn't launch a browser (tried {}); pass --browser <path>.",
+        candidates.join(", ")
+    );
+}
+
+/// Launch Firefox through `web-ext`, the only way to load an unpacked extension from
+/// the command line, keeping what happens in the profile.
+fn launch_firefox(options: &Options, dist: &Path, profile_dir: &Path) -> Child {
+    let mut command = Command::new("web-ext");
+    command
+        .arg("run")
+        .arg("--source-dir")
+        .arg(dist)
+        .arg("--firefox-profile")
+        .arg(profile_dir)
+        .args(["--profile-create-if-missing", "--keep-profile-changes"])
+        // `trunk serve` reloads the extension already.
+        .arg("--no-reload");
+    if let Some(browser) = &options.browser {
+        command.arg("--firefox").arg(browser);
+    }
+    command.arg("--start-url").arg(
+        options
+            .url
+            .as_deref()
+            .unwrap_or("about:debugging#/runtime/this-firefox"),
+    );
+    command
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap_or_else(|e| {
+            panic!("Couldn't run web-ext ({e}); install it with `npm install --global web-ext`.")
+        })
+}
+
+/// Launch Firefox with the packaged extension installed without a signature, and
+/// its debugger server on for the temporary install.
+fn launch_firefox_package(options: &Options, package: &Path, profile_dir: &Path) -> Child {
+    let prefs: String = UNSIGNED_PREFS
+        .iter()
+        .map(|(name, value)| format!("user_pref(\"{name}\", {value});\n"))
+        .collect();
+    fs::write(profile_dir.join("user.js"), prefs).unwrap();
+    if options.install == Install::Permanent {
+        let id = gecko_id(package);
+        let extensions_dir = profile_dir.join("extensions");
+        fs::create_dir_all(&extensions_dir).unwrap();
+        fs::copy(package, extensions_dir.join(format!("{id}.xpi"))).unwrap();
+        println!(
+            "Sideloaded {id}; Firefox only keeps it enabled on Nightly and Developer Edition."
+        );
+    }
+
+    let port = free_port();
+    let candidates = match &options.browser {
+        Some(browser) => vec![browser.clone()],
+        None => ["firefox-nightly", "firefox-developer-edition", "firefox"]
+            .map(str::to_string)
+            .to_vec(),
+    };
+    let Some(mut child) = candidates.iter().find_map(|browser| {
+        Command::new(browser)
+            .arg("-profile")
+            .arg(profile_dir)
+            .args(["-no-remote", "-new-instance", "-start-debugger-server"])
+            .arg(port.to_string())
+            .arg(options.url.as_deref().unwrap_or("about:addons"))
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .ok()
+    }) else {
+        panic!(
+            "Couldn't launch Firefox (tried {}); pass --browser <path>.",
+            candidates.join(", ")
+        );
+    };
+
+    if options.install == Install::Temporary {
+        let installed = Rdp::connect(port, TIMEOUT)
+            .and_then(|mut rdp| rdp.install_temporary_addon(&package.canonicalize().unwrap()));
+        match installed {
+            Ok(id) => println!("Installed {id} as a temporary add-on."),
+            Err(e) => {
+                let _ = child.kill();
+                panic!("Couldn't install {}: {e}", package.display());
+            }
+        }
+    }
+    child
+}
+
+/// The add-on ID in the package's manifest, which names the sideloaded file.
+fn gecko_id(package: &Path) -> String {
+    let file = fs::File::open(package).unwrap();
+    let mut archive = zip::ZipArchive::new(file)
+        .unwrap_or_else(|e| panic!("{} isn't a zip: {e}", package.display()));
+    let mut manifest = String::new();
+    archive
+        .by_name("manifest.json")
+        .unwrap_or_else(|e| panic!("{} has no manifest.json: {e}", package.display()))
+        .read_to_string(&mut manifest)
+        .unwrap();
+    let manifest: Value = serde_json::from_str(&manifest).unwrap();
+    ["browser_specific_settings", "applications"]
+        .iter()
+        .find_map(|key| manifest[key]["gecko"]["id"].as_str())
+        .unwrap_or_else(|| {
+            panic!("A permanent install needs `browser_specific_settings.gecko.id` in the manifest")
+        })
+        .to_string()
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
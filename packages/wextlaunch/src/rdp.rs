@@ -0,0 +1,103 @@
+//! Just enough of Firefox's remote debugging protocol to install a temporary add-on:
+//! packets are JSON objects prefixed with their length and a colon, sent to and from
+//! actors, starting with the root actor, which names the add-ons actor.
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde_json::{json, Value};
+
+/// A connection to Firefox's debugger server.
+pub struct Rdp {
+    stream: TcpStream,
+}
+
+impl Rdp {
+    /// Connect to the debugger server on `port`, waiting for Firefox to start it.
+    pub fn connect(port: u16, timeout: Duration) -> Result<Self, String> {
+        let start = Instant::now();
+        let stream = loop {
+            match TcpStream::connect(("127.0.0.1", port)) {
+                Ok(stream) => break stream,
+                Err(e) if start.elapsed() > timeout => {
+                    return Err(format!("Firefox's debugger server didn't start: {e}"))
+                }
+                Err(_) => thread::sleep(Duration::from_millis(250)),
+            }
+        };
+        stream.set_read_timeout(Some(timeout)).unwrap();
+        let mut rdp = Rdp { stream };
+        // The root actor greets every new connection.
+        rdp.receive()?;
+        Ok(rdp)
+    }
+
+    /// Install the add-on at `path` (a directory, or an `.xpi`) until Firefox quits,
+    /// returning its ID. Temporary add-ons don't need to be signed.
+    pub fn install_temporary_addon(&mut self, path: &Path) -> Result<String, String> {
+        let root = self.request(json!({ "to": "root", "type": "getRoot" }))?;
+        let addons = root["addonsActor"]
+            .as_str()
+            .ok_or("Firefox didn't name its add-ons actor; is it too old?")?
+            .to_string();
+        let installed = self.request(json!({
+            "to": addons,
+            "type": "installTemporaryAddon",
+            "addonPath": path.to_string_lossy(),
+        }))?;
+        Ok(installed["addon"]["id"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    /// Send a packet, and wait for its actor's reply.
+    fn request(&mut self, packet: Value) -> Result<Value, String> {
+        let to = packet["to"].as_str().unwrap_or_default().to_string();
+        let body = packet.to_string();
+        self.stream
+            .write_all(format!("{}:{body}", body.len()).as_bytes())
+            .map_err(|e| e.to_string())?;
+        loop {
+            let reply = self.receive()?;
+            if reply["from"] != to {
+                // An event from another actor.
+                continue;
+            }
+            if let Some(error) = reply["error"].as_str() {
+                return Err(format!(
+                    "{error}: {}",
+                    reply["message"].as_str().unwrap_or_default()
+                ));
+            }
+            return Ok(reply);
+        }
+    }
+
+    fn receive(&mut self) -> Result<Value, String> {
+        let mut length = String::new();
+        let mut byte = [0; 1];
+        loop {
+            self.stream
+                .read_exact(&mut byte)
+                .map_err(|e| format!("Lost the connection to Firefox: {e}"))?;
+            if byte[0] == b':' {
+                break;
+            }
+            length.push(byte[0] as char);
+        }
+        let length: usize = length
+            .parse()
+            .map_err(|_| format!("Malformed packet length {length:?}"))?;
+        let mut body = vec![0; length];
+        self.stream
+            .read_exact(&mut body)
+            .map_err(|e| format!("Lost the connection to Firefox: {e}"))?;
+        serde_json::from_slice(&body).map_err(|e| e.to_string())
+    }
+}
@@ -0,0 +1,230 @@
+//! Checking the manifest's permissions against the browser APIs the sources use.
+//!
+//! Calling an API without its permission only fails at runtime (`browser.alarms` is
+//! simply `undefined`), and a permission that's no longer used still shows in the
+//! install prompt and slows down store review. The Rust sources (`[compat] sources`)
+//! are scanned for calls to the accessors of the facade in `[compat] bindings`, like
+//! `browser::alarms()` or `browser::management().uninstall_self()`, and for
+//! `api("...")` lookups. Each API is mapped to the permission it needs (by longest
+//! matching prefix, e.g. `management.getSelf` needs none, but the rest of
+//! `management` does), and `[permissions] lint` decides what happens when:
+//!
+//! - an API is called, but its permission is in neither `permissions` nor
+//!   `optional_permissions`;
+//! - a permission is declared, but nothing uses the APIs it gates.
+//!
+//! Permissions that don't gate a namespace (`tabs`, `activeTab`, host permissions, ...)
+//! aren't checked, nor are ones the target's browser doesn't have.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::Path,
+};
+
+use serde_json::Value;
+
+use crate::{
+    compat::{api_lookups, collect_rust_files},
+    config::{Compat, Lint, Permissions},
+    schema::{browser_of, has_permission},
+};
+
+/// The permission each API needs, by longest matching prefix; `None` if it needs none.
+const API_PERMISSIONS: &[(&str, Option<&str>)] = &[
+    ("alarms", Some("alarms")),
+    ("bookmarks", Some("bookmarks")),
+    ("browsingData", Some("browsingData")),
+    ("contextMenus", Some("contextMenus")),
+    ("cookies", Some("cookies")),
+    ("declarativeNetRequest", Some("declarativeNetRequest")),
+    ("downloads", Some("downloads")),
+    ("history", Some("history")),
+    ("idle", Some("idle")),
+    ("management", Some("management")),
+    ("management.getSelf", None),
+    ("management.uninstallSelf", None),
+    ("notifications", Some("notifications")),
+    ("offscreen", Some("offscreen")),
+    ("runtime.connectNative", Some("nativeMessaging")),
+    ("runtime.sendNativeMessage", Some("nativeMessaging")),
+    ("scripting", Some("scripting")),
+    ("sessions", Some("sessions")),
+    ("sidePanel", Some("sidePanel")),
+    ("storage", Some("storage")),
+    ("tabGroups", Some("tabGroups")),
+    ("topSites", Some("topSites")),
+    ("tts", Some("tts")),
+    ("webNavigation", Some("webNavigation")),
+    ("webRequest", Some("webRequest")),
+];
+
+/// Compare `manifest`'s permissions for `target` with the APIs the sources use.
+pub fn lint_permissions(
+    config: &Permissions,
+    compat: &Compat,
+    manifest: &Value,
+    target: &str,
+    source_dir: &str,
+) {
+    if matches!(config.lint, Lint::Off) {
+        return;
+    }
+    let bindings_path = Path::new(source_dir).join(&compat.bindings);
+    let bindings = fs::read_to_string(&bindings_path)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", compat.bindings));
+    let accessors = scan_accessors(&bindings);
+
+    let mut files = Vec::new();
+    for dir in &compat.sources {
+        collect_rust_files(&Path::new(source_dir).join(dir), &mut files);
+    }
+    // Where each permission is needed, and the APIs that are only feature-detected.
+    let mut called: BTreeMap<&str, BTreeSet<String>> = BTreeMap::new();
+    let mut detected = BTreeSet::new();
+    for file in files.iter().filter(|file| **file != bindings_path) {
+        let contents = fs::read_to_string(file).unwrap();
+        for api in accessor_calls(&contents, &accessors) {
+            if let Some(permission) = permission_for(&api) {
+                called.entry(permission).or_default().insert(api);
+            }
+        }
+        detected.extend(
+            api_lookups(&contents)
+                .iter()
+                .filter_map(|api| permission_for(api)),
+        );
+    }
+
+    let browser = browser_of(target);
+    let declared: BTreeSet<&str> = ["permissions", "optional_permissions"]
+        .iter()
+        .filter_map(|key| manifest.get(key).and_then(Value::as_array))
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let mut problems = Vec::new();
+    for (permission, apis) in &called {
+        if !declared.contains(permission) && has_permission(browser, permission) {
+            let apis: Vec<_> = apis.iter().map(String::as_str).collect();
+            problems.push(format!(
+                "{} needs the `{permission}` permission, which the {target} manifest doesn't declare.",
+                apis.join(", ")
+            ));
+        }
+    }
+    let gated: BTreeSet<&str> = API_PERMISSIONS
+        .iter()
+        .filter_map(|(_, permission)| *permission)
+        .collect();
+    for permission in &declared {
+        if gated.contains(permission)
+            && !called.contains_key(permission)
+            && !detected.contains(permission)
+        {
+            problems.push(format!(
+                "The {target} manifest declares the `{permission}` permission, but nothing uses it."
+            ));
+        }
+    }
+
+    if problems.is_empty() {
+        return;
+    }
+    match config.lint {
+        Lint::Error => panic!(
+            "The permissions don't match the APIs used:\n  {}\nSet `[permissions] lint = \"warn\"` in wextrunk.toml to build anyway.",
+            problems.join("\n  ")
+        ),
+        _ => {
+            for problem in problems {
+                println!("Warning: {problem}");
+            }
+        }
+    }
+}
+
+/// The permission `api` needs, if any.
+fn permission_for(api: &str) -> Option<&'static str> {
+    API_PERMISSIONS
+        .iter()
+        .filter(|(prefix, _)| api == *prefix || api.starts_with(&format!("{prefix}.")))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .and_then(|(_, permission)| *permission)
+}
+
+/// The API path of each accessor in the bindings, e.g. `context_menus` →
+/// `contextMenus`, from `pub fn name() -> Type {` followed by `api("path")`.
+fn scan_accessors(bindings: &str) -> BTreeMap<String, String> {
+    let mut accessors = BTreeMap::new();
+    let mut lines = bindings.lines().peekable();
+    while let Some(line) = lines.next() {
+        let Some(name) = line
+            .trim()
+            .strip_prefix("pub fn ")
+            .and_then(|rest| rest.split_once("() ->"))
+            .map(|(name, _)| name)
+        else {
+            continue;
+        };
+        if let Some(path) = lines.peek().and_then(|next| api_lookups(next).pop()) {
+            accessors.insert(name.to_string(), path);
+        }
+    }
+    accessors
+}
+
+/// The APIs called through accessors in `source`, with the method if there's one,
+/// e.g. `management.uninstallSelf` for `browser::management().uninstall_self()`.
+fn accessor_calls(source: &str, accessors: &BTreeMap<String, String>) -> Vec<String> {
+    let source: String = source
+        .lines()
+        .filter(|line| !line.trim_start().starts_with("//"))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let mut calls = Vec::new();
+    let mut rest = source.as_str();
+    while let Some(index) = rest.find("browser::") {
+        rest = &rest[index + "browser::".len()..];
+        let name: String = rest
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        let Some(path) = accessors.get(&name) else {
+            continue;
+        };
+        let Some(after) = rest[name.len()..].strip_prefix("()") else {
+            continue;
+        };
+        let method: String = after
+            .trim_start()
+            .strip_prefix('.')
+            .map(|method| {
+                method
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .collect()
+            })
+            .unwrap_or_default();
+        if method.is_empty() {
+            calls.push(path.clone());
+        } else {
+            calls.push(format!("{path}.{}", camel_case(&method)));
+        }
+    }
+    calls
+}
+
+/// `uninstall_self` → `uninstallSelf`.
+fn camel_case(name: &str) -> String {
+    let mut parts = name.split('_');
+    let mut camel = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            camel.extend(first.to_uppercase());
+            camel.push_str(chars.as_str());
+        }
+    }
+    camel
+}
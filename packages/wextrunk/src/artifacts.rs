@@ -0,0 +1,97 @@
+//! Several wasm binaries in one extension.
+//!
+//! Every page and script binds to Trunk's main wasm by default, so a content script
+//! ships everything the pages use, Leptos included. Other binaries (e.g. a small
+//! `content` crate without a UI) are built by Trunk as worker assets, which it builds
+//! and stages without loading them:
+//!
+//! ```html
+//! <link data-trunk rel="rust" href="crates/content/Cargo.toml"
+//!   data-type="worker" data-bindgen-target="web" data-target-name="content">
+//! ```
+//!
+//! A page, script or content script binds to one with `wasm="content"` on its link
+//! (`wasm = "content"` in `wextrunk.toml`). Its shim is the main one's, with the
+//! module and wasm paths swapped for the artifact's: `content.js` and
+//! `content_bg.wasm`, or their hashed names.
+
+use std::{collections::BTreeMap, fs};
+
+use crate::{stable_names::split_hash, ScriptTemplate};
+
+/// The shim template of every wasm artifact: Trunk's main one, and the named others.
+#[derive(Debug)]
+pub struct Artifacts {
+    pub main: ScriptTemplate,
+    pub named: BTreeMap<String, ScriptTemplate>,
+}
+
+impl Artifacts {
+    /// Find the staged files of every artifact in `names`, and derive their templates
+    /// from the main one.
+    pub fn load<'a>(
+        main: ScriptTemplate,
+        names: impl IntoIterator<Item = &'a str>,
+        staging_dir: &str,
+    ) -> Self {
+        let mut named = BTreeMap::new();
+        for name in names {
+            if named.contains_key(name) {
+                continue;
+            }
+            let (module, wasm) = find_files(name, staging_dir);
+            let mut template = main.clone();
+            for text in [&mut template.import_line, &mut template.init] {
+                *text = text
+                    .replace(&main.module_path, &format!("/{module}"))
+                    .replace(&main.wasm_path, &format!("/{wasm}"));
+            }
+            template.module_path = format!("/{module}");
+            template.wasm_path = format!("/{wasm}");
+            named.insert(name.to_string(), template);
+        }
+        Artifacts { main, named }
+    }
+
+    /// The template of the artifact a declaration binds to.
+    pub fn template(&self, wasm: Option<&str>) -> &ScriptTemplate {
+        match wasm {
+            None => &self.main,
+            Some(name) => &self.named[name],
+        }
+    }
+
+    /// The staged wasm file of every artifact, main first, relative to the staging
+    /// directory.
+    pub fn wasm_files(&self) -> Vec<(Option<&str>, String)> {
+        let mut files = vec![(None, self.main.module_paths().1)];
+        for (name, template) in &self.named {
+            files.push((Some(name.as_str()), template.module_paths().1));
+        }
+        files
+    }
+}
+
+/// The staged module and wasm of the artifact `name`, e.g. `content-0123.js` and
+/// `content-0123_bg.wasm`.
+fn find_files(name: &str, staging_dir: &str) -> (String, String) {
+    let mut module = None;
+    let mut wasm = None;
+    for entry in fs::read_dir(staging_dir).unwrap() {
+        let file = entry.unwrap().file_name().to_string_lossy().to_string();
+        let stable = split_hash(&file).map_or_else(|| file.clone(), |(stable, _)| stable);
+        if stable == format!("{name}.js") {
+            module = Some(file);
+        } else if stable == format!("{name}_bg.wasm") {
+            wasm = Some(file);
+        }
+    }
+    match (module, wasm) {
+        (Some(module), Some(wasm)) => (module, wasm),
+        _ => panic!(
+            "No wasm artifact named {name:?} was staged; add it to index.html as \
+             <link data-trunk rel=\"rust\" data-type=\"worker\" data-bindgen-target=\"web\" \
+             data-target-name=\"{name}\" href=\"...\">"
+        ),
+    }
+}
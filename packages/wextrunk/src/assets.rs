@@ -0,0 +1,143 @@
+//! Image optimisation for release builds.
+//!
+//! Every PNG and SVG in the staging directory (copied with `data-trunk rel="copy-dir"`
+//! or `copy-file`, plus generated icons) is optimised in place: PNGs are recompressed
+//! losslessly with `oxipng`, and SVGs are minified by stripping comments, metadata
+//! and insignificant whitespace. Files are only replaced if they got smaller.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::{
+    cache::{Cache, Fingerprint},
+    config::Assets,
+    report::AssetReport,
+};
+
+/// Optimise every image in the staging directory, returning the savings per file.
+pub fn optimize_assets(config: &Assets, staging_dir: &str, cache: &Cache) -> Vec<AssetReport> {
+    let mut files = Vec::new();
+    collect_images(Path::new(staging_dir), &mut files);
+    files.sort();
+
+    let mut oxipng_missing = false;
+    let mut reports = Vec::new();
+    for file in files {
+        let size_before = fs::metadata(&file).unwrap().len();
+        let optimized = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("png") if config.png && !oxipng_missing => {
+                let fingerprint = Fingerprint::new()
+                    .add_file(&file)
+                    .add(config.oxipng.join("\0"));
+                cache.run("oxipng", fingerprint, &[&file], || {
+                    match Command::new("oxipng")
+                        .args(&config.oxipng)
+                        .arg(&file)
+                        .output()
+                    {
+                        Ok(output) if output.status.success() => true,
+                        Ok(output) => panic!(
+                            "oxipng failed for {}:\n{}",
+                            file.display(),
+                            String::from_utf8_lossy(&output.stderr)
+                        ),
+                        Err(_) => {
+                            println!("Warning: oxipng isn't installed; PNGs won't be optimised.");
+                            oxipng_missing = true;
+                            false
+                        }
+                    }
+                });
+                !oxipng_missing
+            }
+            Some("svg") if config.svg => {
+                let svg = fs::read_to_string(&file).unwrap();
+                let minified = minify_svg(&svg);
+                if minified.len() < svg.len() {
+                    fs::write(&file, minified).unwrap();
+                }
+                true
+            }
+            _ => false,
+        };
+        if optimized {
+            reports.push(AssetReport {
+                file: file
+                    .strip_prefix(staging_dir)
+                    .unwrap()
+                    .to_string_lossy()
+                    .replace('\\', "/"),
+                size_before,
+                size_after: fs::metadata(&file).unwrap().len(),
+            });
+        }
+    }
+    reports
+}
+
+fn collect_images(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_images(&path, files);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "png" || ext == "svg")
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Strip comments, the XML declaration, doctype and `<metadata>`, and whitespace
+/// between tags. Whitespace is kept in SVGs with `<text>`, where it can be visible.
+fn minify_svg(svg: &str) -> String {
+    let mut output = svg.to_string();
+    for (open, close) in [
+        ("<!--", "-->"),
+        ("<?xml", "?>"),
+        ("<!DOCTYPE", ">"),
+        ("<metadata", "</metadata>"),
+    ] {
+        output = remove_between(&output, open, close);
+    }
+
+    if !output.contains("<text") {
+        let mut collapsed = String::with_capacity(output.len());
+        let mut pending = String::new();
+        for c in output.chars() {
+            if c.is_whitespace() && collapsed.ends_with('>') {
+                pending.push(c);
+                continue;
+            }
+            if c != '<' {
+                collapsed.push_str(&pending);
+            }
+            pending.clear();
+            collapsed.push(c);
+        }
+        output = collapsed;
+    }
+    output.trim().to_string()
+}
+
+/// Remove every `open`..`close` span, inclusive.
+fn remove_between(input: &str, open: &str, close: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find(open) {
+        output.push_str(&rest[..start]);
+        match rest[start..].find(close) {
+            Some(end) => rest = &rest[start + end + close.len()..],
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
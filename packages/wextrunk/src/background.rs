@@ -0,0 +1,115 @@
+//! How the background script runs, per target.
+//!
+//! Chrome's Manifest V3 only runs the background as a service worker, while Firefox
+//! runs it as an event page (`background.scripts`, stopped when idle but with a DOM),
+//! and Manifest V2 builds can keep it loaded for good. The background script's
+//! declaration picks the mode with `mode="..."` (`mode = "..."` in `wextrunk.toml`):
+//! either one for every target, or per target, e.g.
+//! `mode="chrome:service-worker firefox:event-page"`, which is also the default.
+//!
+//! - `service-worker`: `background.service_worker`, and the shim is wrapped in an
+//!   async IIFE, as service workers don't allow top-level await.
+//! - `event-page`: `background.scripts` (with `persistent: false` in MV2), and the shim
+//!   is a plain module.
+//! - `persistent`: `background.scripts` with `persistent: true`; MV2 only.
+//!
+//! Either way the shim is an ES module, so `background.type` is `module`.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+use crate::{registry::fill, schema::browser_of, Script};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BackgroundMode {
+    ServiceWorker,
+    EventPage,
+    Persistent,
+}
+
+impl BackgroundMode {
+    fn parse(mode: &str) -> Self {
+        match mode {
+            "service-worker" => BackgroundMode::ServiceWorker,
+            "event-page" => BackgroundMode::EventPage,
+            "persistent" => BackgroundMode::Persistent,
+            _ => panic!(
+                "Unknown background mode {mode:?}; expected service-worker, event-page or persistent"
+            ),
+        }
+    }
+
+    /// The mode `spec` (a background script's `mode`) gives `target`, checked against
+    /// what the target's browser supports in `manifest_version`.
+    pub fn resolve(spec: Option<&str>, target: &str, manifest_version: u8) -> Self {
+        let mut default = None;
+        let mut targets = BTreeMap::new();
+        for mode in spec.unwrap_or_default().split_whitespace() {
+            match mode.split_once(':') {
+                Some((name, mode)) => {
+                    targets.insert(name, BackgroundMode::parse(mode));
+                }
+                None => default = Some(BackgroundMode::parse(mode)),
+            }
+        }
+        let browser = browser_of(target);
+        let mode = targets.get(target).copied().or(default).unwrap_or(
+            if browser == "firefox" || manifest_version == 2 {
+                BackgroundMode::EventPage
+            } else {
+                BackgroundMode::ServiceWorker
+            },
+        );
+
+        if manifest_version >= 3 {
+            if browser == "chrome" && mode != BackgroundMode::ServiceWorker {
+                panic!("Chrome's Manifest V3 only runs the background as a service worker, so the {target} target needs mode service-worker.");
+            }
+            if mode == BackgroundMode::Persistent {
+                panic!("Manifest V3 backgrounds can't be persistent; the {target} target needs mode event-page or service-worker.");
+            }
+        }
+        if browser == "firefox" && mode == BackgroundMode::ServiceWorker {
+            panic!("Firefox doesn't run background service workers; the {target} target needs mode event-page.");
+        }
+        mode
+    }
+}
+
+/// Point the manifest's `background` at the background scripts, in their mode for
+/// `target`.
+pub fn fill_background(
+    scripts: &[Script],
+    manifest: &mut Value,
+    target: &str,
+    manifest_version: u8,
+) {
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    for script in scripts.iter().filter(|script| script.background_script) {
+        let mode = BackgroundMode::resolve(script.mode.as_deref(), target, manifest_version);
+        match mode {
+            BackgroundMode::ServiceWorker => {
+                fill(
+                    manifest,
+                    &["background", "service_worker"],
+                    json!(script.js),
+                );
+            }
+            BackgroundMode::EventPage | BackgroundMode::Persistent => {
+                fill(manifest, &["background", "scripts"], json!([script.js]));
+                if manifest_version == 2 {
+                    fill(
+                        manifest,
+                        &["background", "persistent"],
+                        json!(mode == BackgroundMode::Persistent),
+                    );
+                }
+            }
+        }
+        // The shim is an ES module.
+        fill(manifest, &["background", "type"], json!("module"));
+    }
+}
@@ -0,0 +1,45 @@
+//! `build-info.json`: build settings the runtime needs to know about.
+//!
+//! Written to the staging directory, and read by the extension with
+//! `runtime.getURL("build-info.json")`. Keeps per-channel settings (such as the
+//! uninstall survey URL) in `wextrunk.toml`, rather than compiled into the wasm.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Config;
+
+#[derive(Debug, Serialize)]
+struct BuildInfo<'a> {
+    target: &'a str,
+    profile: Option<String>,
+    version: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    uninstall_url: Option<String>,
+    /// Feature flag values baked into this build.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    flags: BTreeMap<String, bool>,
+}
+
+/// Write `build-info.json` for `target` to the staging directory.
+pub fn write_build_info(config: &Config, manifest: &Value, target: &str, staging_dir: &str) {
+    let profile = config.profile().map(|(name, _)| name);
+    let version = manifest.get("version").and_then(Value::as_str);
+    let uninstall_url = config.uninstall_url().map(|url| {
+        url.replace("{version}", version.unwrap_or_default())
+            .replace("{target}", target)
+            .replace("{profile}", profile.as_deref().unwrap_or_default())
+    });
+    let info = BuildInfo {
+        target,
+        profile,
+        version,
+        uninstall_url,
+        flags: config.flags(),
+    };
+    let mut output = serde_json::to_string_pretty(&info).unwrap();
+    output.push('\n');
+    fs::write(Path::new(staging_dir).join("build-info.json"), output).unwrap();
+}
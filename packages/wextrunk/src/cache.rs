@@ -0,0 +1,146 @@
+//! Build cache for the expensive stages, keyed by fingerprints of their inputs.
+//!
+//! Trunk empties the staging directory on every build, so wasm-opt, icon rendering,
+//! font subsetting and PNG optimisation would redo the same work for every rebuild,
+//! even when nothing they read changed. Each of those stages fingerprints its inputs
+//! (the file contents, flags and settings that decide its output) with SHA-256, and
+//! looks the fingerprint up under `[cache] dir` (`target/wextrunk-cache` by default):
+//!
+//! ```text
+//! target/wextrunk-cache/<stage>/<fingerprint>/0, 1, ...
+//! ```
+//!
+//! On a hit, the stored outputs are copied into place instead of running the stage;
+//! on a miss, the stage runs and its outputs are stored. The cheap stages (rewriting
+//! pages, merging manifests, ...) always run. The directory can be deleted at any
+//! time, and CI can restore it between runs. `--no-cache` (in Trunk.toml's
+//! `command_arguments`), `WEXTRUNK_NO_CACHE=1` or `[cache] enabled = false` turn it
+//! off, for when a tool the fingerprints don't cover (e.g. a new `wasm-opt`) changed.
+
+use std::{
+    cell::RefCell,
+    collections::BTreeMap,
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use sha2::{Digest, Sha256};
+
+use crate::{config, report::CacheReport};
+
+/// The cache, or a stand-in that always misses when it's disabled.
+#[derive(Debug)]
+pub struct Cache {
+    dir: Option<PathBuf>,
+    /// Hits and misses per stage since the last [`Cache::take_report`].
+    stats: RefCell<BTreeMap<&'static str, (usize, usize)>>,
+}
+
+/// The inputs of one run of a stage, hashed together.
+#[derive(Default)]
+pub struct Fingerprint(Sha256);
+
+impl Fingerprint {
+    pub fn new() -> Self {
+        Fingerprint(Sha256::new())
+    }
+
+    /// Add an input. Each is length-prefixed, so `["ab", "c"]` and `["a", "bc"]`
+    /// differ.
+    pub fn add(mut self, input: impl AsRef<[u8]>) -> Self {
+        let input = input.as_ref();
+        self.0.update((input.len() as u64).to_le_bytes());
+        self.0.update(input);
+        self
+    }
+
+    /// Add the contents of a file.
+    pub fn add_file(self, path: &Path) -> Self {
+        let contents =
+            fs::read(path).unwrap_or_else(|e| panic!("Failed to read {}: {e}", path.display()));
+        self.add(contents)
+    }
+
+    fn hex(self) -> String {
+        format!("{:x}", self.0.finalize())
+    }
+}
+
+impl Cache {
+    pub fn open(config: &config::Cache, source_dir: &str) -> Self {
+        let disabled = !config.enabled
+            || env::args().any(|arg| arg == "--no-cache")
+            || env::var("WEXTRUNK_NO_CACHE").is_ok_and(|value| value == "1");
+        if disabled {
+            println!("The build cache is disabled.");
+        }
+        Cache {
+            dir: (!disabled).then(|| Path::new(source_dir).join(&config.dir)),
+            stats: RefCell::default(),
+        }
+    }
+
+    /// Write `outputs` by running `produce`, unless a run of `stage` with the same
+    /// `fingerprint` stored them. `produce` returns whether its outputs may be stored,
+    /// e.g. not when it skipped its work because a tool is missing. Returns whether it
+    /// was a hit.
+    pub fn run(
+        &self,
+        stage: &'static str,
+        fingerprint: Fingerprint,
+        outputs: &[&Path],
+        produce: impl FnOnce() -> bool,
+    ) -> bool {
+        let Some(dir) = &self.dir else {
+            produce();
+            return false;
+        };
+        let entry = dir.join(stage).join(fingerprint.hex());
+        let hit = entry.is_dir() && (0..outputs.len()).all(|i| entry.join(i.to_string()).is_file());
+        let mut stats = self.stats.borrow_mut();
+        let (hits, misses) = stats.entry(stage).or_default();
+        if hit {
+            *hits += 1;
+            for (i, output) in outputs.iter().enumerate() {
+                fs::create_dir_all(output.parent().unwrap()).unwrap();
+                fs::copy(entry.join(i.to_string()), output).unwrap();
+            }
+            return true;
+        }
+        *misses += 1;
+        drop(stats);
+        if produce() {
+            store(&entry, outputs);
+        }
+        false
+    }
+
+    /// The hits and misses since the last call, if the cache is enabled.
+    pub fn take_report(&self) -> Option<CacheReport> {
+        self.dir.as_ref()?;
+        let stages = self.stats.take();
+        Some(CacheReport {
+            hits: stages.values().map(|(hits, _)| hits).sum(),
+            misses: stages.values().map(|(_, misses)| misses).sum(),
+            stages: stages
+                .into_iter()
+                .map(|(stage, stats)| (stage.to_string(), stats))
+                .collect(),
+        })
+    }
+}
+
+/// Copy `outputs` into a new entry, replacing it in one rename so a build that's
+/// interrupted never leaves half an entry behind.
+fn store(entry: &Path, outputs: &[&Path]) {
+    let partial = entry.with_extension("partial");
+    let _ = fs::remove_dir_all(&partial);
+    fs::create_dir_all(&partial).unwrap();
+    for (i, output) in outputs.iter().enumerate() {
+        fs::copy(output, partial.join(i.to_string())).unwrap_or_else(|e| {
+            panic!("Failed to cache {}: {e}", output.display());
+        });
+    }
+    let _ = fs::remove_dir_all(entry);
+    fs::rename(&partial, entry).unwrap();
+}
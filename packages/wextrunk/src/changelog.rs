@@ -0,0 +1,111 @@
+//! Release notes from `CHANGELOG.md`.
+//!
+//! The changelog (in the [Keep a Changelog](https://keepachangelog.com) format) is
+//! parsed into `changelog.json` in the staging directory, so the update page can show
+//! what changed without fetching anything. Only `## [version]` headings, `###`
+//! section headings and `-` list items are picked up; the `[Unreleased]` section is
+//! left out.
+
+use std::{fs, path::Path};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::config::Changelog;
+
+/// A released version and its notes.
+#[derive(Debug, Serialize)]
+pub struct Release {
+    pub version: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub date: Option<String>,
+    pub sections: Vec<Section>,
+}
+
+/// A `### Added`, `### Fixed`, ... section of a release.
+#[derive(Debug, Serialize)]
+pub struct Section {
+    pub title: String,
+    pub items: Vec<String>,
+}
+
+/// Parse the changelog and write `changelog.json` to the staging directory, returning
+/// the number of releases, or `None` if there's no changelog.
+pub fn write_changelog(
+    config: &Changelog,
+    manifest: &Value,
+    source_dir: &str,
+    staging_dir: &str,
+) -> Option<usize> {
+    let path = Path::new(source_dir).join(&config.file);
+    let contents = fs::read_to_string(&path).ok()?;
+    let releases = parse(&contents);
+
+    let version = manifest.get("version").and_then(Value::as_str);
+    if let Some(version) = version {
+        if !releases.iter().any(|release| release.version == version) {
+            println!(
+                "Warning: {} has no entry for version {version}, so the update page won't show any notes.",
+                config.file
+            );
+        }
+    }
+
+    let mut output = serde_json::to_string(&releases).unwrap();
+    output.push('\n');
+    fs::write(Path::new(staging_dir).join("changelog.json"), output).unwrap();
+    Some(releases.len())
+}
+
+fn parse(contents: &str) -> Vec<Release> {
+    let mut releases: Vec<Release> = Vec::new();
+    let mut unreleased = false;
+    for line in contents.lines() {
+        let trimmed = line.trim();
+        if let Some(heading) = trimmed.strip_prefix("## ") {
+            let (version, date) = match heading.split_once(" - ") {
+                Some((version, date)) => (version, Some(date.trim().to_string())),
+                None => (heading, None),
+            };
+            let version = version.trim().trim_start_matches('[').trim_end_matches(']');
+            unreleased = version.eq_ignore_ascii_case("unreleased");
+            if !unreleased {
+                releases.push(Release {
+                    version: version.to_string(),
+                    date,
+                    sections: Vec::new(),
+                });
+            }
+            continue;
+        }
+        let Some(release) = releases.last_mut().filter(|_| !unreleased) else {
+            continue;
+        };
+        if let Some(title) = trimmed.strip_prefix("### ") {
+            release.sections.push(Section {
+                title: title.to_string(),
+                items: Vec::new(),
+            });
+        } else if let Some(item) = trimmed.strip_prefix("- ").or(trimmed.strip_prefix("* ")) {
+            if release.sections.is_empty() {
+                release.sections.push(Section {
+                    title: String::new(),
+                    items: Vec::new(),
+                });
+            }
+            let section = release.sections.last_mut().unwrap();
+            section.items.push(item.to_string());
+        } else if !trimmed.is_empty() && line.starts_with(char::is_whitespace) {
+            // Continuation of a wrapped list item.
+            if let Some(item) = release
+                .sections
+                .last_mut()
+                .and_then(|section| section.items.last_mut())
+            {
+                item.push(' ');
+                item.push_str(trimmed);
+            }
+        }
+    }
+    releases
+}
@@ -0,0 +1,271 @@
+//! Checking the browser APIs the extension binds against its minimum browser versions.
+//!
+//! Every browser API goes through the facade in `src/browser.rs`: accessors like
+//! `pub fn tabs() -> Tabs { api("tabs")... }` name the namespaces, and the methods
+//! and getters bound on their types name the members. Other `api("...")` lookups in
+//! the sources are feature detection, so everything under those paths is optional.
+//!
+//! Each API is looked up in the embedded dataset (`compat.json`, by longest matching
+//! prefix, e.g. `storage.session.get` falls back to `storage.session`), and compared
+//! against the declared minimum version of each target: `[compat] targets`, or the
+//! manifest's `minimum_chrome_version` / `browser_specific_settings.gecko.strict_min_version`
+//! for the target being built. An API that a declared target lacks fails the build,
+//! unless it's optional (feature-detected, or listed under `[compat] optional`).
+
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+
+use crate::{config::Compat, report::CompatReport, schema::browser_of};
+
+/// The first version of each browser with Manifest V3, used when no minimum is declared.
+const MV3_BASELINES: &[(&str, &str)] = &[("chrome", "88"), ("firefox", "109")];
+
+/// Browser support per API: the first version with it, or `false` if it has none.
+const DATASET: &str = include_str!("compat.json");
+
+/// Check the APIs used by the sources against the declared targets.
+pub fn check_compat(
+    config: &Compat,
+    manifest: &Value,
+    target: &str,
+    source_dir: &str,
+) -> CompatReport {
+    let dataset: BTreeMap<String, BTreeMap<String, Value>> = serde_json::from_str(DATASET).unwrap();
+    let bindings = fs::read_to_string(Path::new(source_dir).join(&config.bindings))
+        .unwrap_or_else(|e| panic!("Failed to read {}: {e}", config.bindings));
+    let mut apis = scan_bindings(&bindings);
+
+    let mut files = Vec::new();
+    for dir in &config.sources {
+        collect_rust_files(&Path::new(source_dir).join(dir), &mut files);
+    }
+    let bindings_path = Path::new(source_dir).join(&config.bindings);
+    let mut detected: BTreeSet<String> = config.optional.iter().cloned().collect();
+    for file in files.iter().filter(|file| **file != bindings_path) {
+        let contents = fs::read_to_string(file).unwrap();
+        for path in api_lookups(&contents) {
+            apis.insert(path.clone());
+            detected.insert(path);
+        }
+    }
+
+    let targets = declared_targets(config, manifest, target);
+    let mut report = CompatReport {
+        targets: targets.clone(),
+        apis: apis.len(),
+        ..CompatReport::default()
+    };
+    let mut missing = Vec::new();
+    for api in &apis {
+        let optional = detected
+            .iter()
+            .any(|prefix| api == prefix || api.starts_with(&format!("{prefix}.")));
+        let support = lookup(&dataset, api);
+        for (browser, min_version) in &targets {
+            let problem = match support.and_then(|support| support.get(browser)) {
+                Some(Value::String(since)) if is_older(min_version, since) => {
+                    format!(
+                        "{api} needs {browser} {since}, but {browser} {min_version} is supported"
+                    )
+                }
+                Some(Value::Bool(false)) => format!("{api} isn't available in {browser}"),
+                Some(_) => continue,
+                None => {
+                    if !optional {
+                        report.unknown.push(format!("{api} ({browser})"));
+                    }
+                    continue;
+                }
+            };
+            if optional {
+                report.unavailable_optional.push(problem);
+            } else {
+                missing.push(problem);
+            }
+        }
+    }
+    if !report.unknown.is_empty() {
+        println!(
+            "Warning: no compatibility data for {}.",
+            report.unknown.join(", ")
+        );
+    }
+    if !missing.is_empty() {
+        panic!(
+            "The extension uses browser APIs its declared targets don't have:\n  {}\nRaise the minimum version, or if the code checks for the API before using it, list it under [compat] optional in wextrunk.toml.",
+            missing.join("\n  ")
+        );
+    }
+    report
+}
+
+/// The minimum version of every declared target browser.
+fn declared_targets(config: &Compat, manifest: &Value, target: &str) -> BTreeMap<String, String> {
+    if !config.targets.is_empty() {
+        return config.targets.clone();
+    }
+    let browser = browser_of(target);
+    let declared = match browser {
+        "chrome" => manifest.get("minimum_chrome_version"),
+        "firefox" => manifest.pointer("/browser_specific_settings/gecko/strict_min_version"),
+        _ => None,
+    };
+    let version = declared
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .or_else(|| {
+            MV3_BASELINES
+                .iter()
+                .find(|(name, _)| *name == browser)
+                .map(|(_, version)| version.to_string())
+        });
+    version
+        .map(|version| BTreeMap::from([(browser.to_string(), version)]))
+        .unwrap_or_default()
+}
+
+/// The dataset entry for `api`, or its closest ancestor's.
+fn lookup<'a>(
+    dataset: &'a BTreeMap<String, BTreeMap<String, Value>>,
+    api: &str,
+) -> Option<&'a BTreeMap<String, Value>> {
+    let mut path = api;
+    loop {
+        if let Some(support) = dataset.get(path) {
+            return Some(support);
+        }
+        path = &path[..path.rfind('.')?];
+    }
+}
+
+/// Whether dotted version `a` is older than `b`, e.g. `115.0` is older than `128`.
+fn is_older(a: &str, b: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|part| part.parse().unwrap_or_default())
+            .collect()
+    };
+    let (mut a, mut b) = (parse(a), parse(b));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a < b
+}
+
+/// The API paths bound by the facade: each accessor's path, and its type's members
+/// under it.
+fn scan_bindings(source: &str) -> BTreeSet<String> {
+    let mut members: HashMap<String, Vec<String>> = HashMap::new();
+    let mut accessors: Vec<(String, String)> = Vec::new();
+    let mut attribute = String::new();
+    // A bound method waiting for its `this: &Type` argument.
+    let mut pending: Option<String> = None;
+    // An accessor waiting for its `api("...")` lookup.
+    let mut accessor: Option<String> = None;
+
+    for line in source.lines() {
+        let line = line.trim();
+        if line.starts_with("#[wasm_bindgen(") {
+            attribute = line.to_string();
+            continue;
+        }
+        if let Some(ty) = &accessor {
+            if let Some(path) = api_lookups(line).into_iter().next() {
+                accessors.push((ty.clone(), path));
+            }
+            accessor = None;
+            continue;
+        }
+        let signature = line
+            .strip_prefix("pub fn ")
+            .or_else(|| line.strip_prefix("pub async fn "));
+        if let Some(signature) = signature {
+            let name = signature.split('(').next().unwrap_or_default();
+            if attribute.contains("method") {
+                pending = Some(js_name(&attribute).unwrap_or_else(|| name.to_string()));
+            } else if let Some(ty) = signature
+                .strip_prefix(&format!("{name}() -> "))
+                .and_then(|rest| rest.strip_suffix(" {"))
+            {
+                accessor = Some(ty.to_string());
+            }
+            attribute.clear();
+        }
+        if let Some(name) = &pending {
+            if let Some((_, rest)) = line.split_once("this: &") {
+                let ty: String = rest
+                    .chars()
+                    .take_while(|c| c.is_alphanumeric() || *c == '_')
+                    .collect();
+                members.entry(ty).or_default().push(name.clone());
+                pending = None;
+            }
+        }
+    }
+
+    let mut apis = BTreeSet::new();
+    for (ty, path) in accessors {
+        for member in members.get(&ty).into_iter().flatten() {
+            apis.insert(format!("{path}.{member}"));
+        }
+        apis.insert(path);
+    }
+    apis
+}
+
+/// The JS name set with `js_name = ..` or `getter = ..` in a `#[wasm_bindgen]` attribute.
+fn js_name(attribute: &str) -> Option<String> {
+    ["js_name = ", "getter = "].iter().find_map(|key| {
+        let rest = &attribute[attribute.find(key)? + key.len()..];
+        Some(
+            rest.chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect(),
+        )
+    })
+}
+
+/// The paths looked up with `api("...")` in `source`, skipping comments.
+pub fn api_lookups(source: &str) -> Vec<String> {
+    let mut paths = Vec::new();
+    for line in source.lines() {
+        if line.trim_start().starts_with("//") {
+            continue;
+        }
+        let mut rest = line;
+        while let Some(index) = rest.find("api(\"") {
+            let preceded_by_ident = rest[..index]
+                .chars()
+                .next_back()
+                .is_some_and(|c| c.is_alphanumeric() || c == '_');
+            rest = &rest[index + 5..];
+            if preceded_by_ident {
+                continue;
+            }
+            if let Some(end) = rest.find('"') {
+                paths.push(rest[..end].to_string());
+            }
+        }
+    }
+    paths
+}
+
+pub fn collect_rust_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_rust_files(&path, files);
+        } else if path.extension().is_some_and(|ext| ext == "rs") {
+            files.push(path);
+        }
+    }
+}
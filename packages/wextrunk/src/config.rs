@@ -0,0 +1,659 @@
+//! Optional `wextrunk.toml` configuration, read from the Trunk source directory, or
+//! `[package.metadata.wextrunk]` in its `Cargo.toml`.
+//!
+//! Everything in here is optional; without the file, wextrunk behaves exactly as if
+//! it was configured purely through `data-wextrunk` tags in index.html.
+
+use std::{collections::BTreeMap, env, fs, path::Path};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Name of the config file, relative to the source directory.
+const CONFIG_FILE: &str = "wextrunk.toml";
+
+/// Parsed `wextrunk.toml`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Settings per profile (or release channel), e.g. `[profiles.staging]`.
+    pub profiles: BTreeMap<String, Profile>,
+    /// Settings per target browser, e.g. `[targets.firefox]`.
+    pub targets: BTreeMap<String, Target>,
+    /// The target built without `WEXTRUNK_TARGET`, unless a manifest link in index.html
+    /// is marked `default`.
+    pub default_target: Option<String>,
+    /// Pages, from `[[pages]]`, like `rel="htmlpage"` links.
+    pub pages: Vec<Page>,
+    /// Scripts, from `[[scripts]]`, like `rel="script"` links.
+    pub scripts: Vec<ScriptEntry>,
+    /// Content scripts, from `[[content_scripts]]`, like `rel="contentscript"` links.
+    pub content_scripts: Vec<ContentScriptEntry>,
+    pub i18n: I18n,
+    /// Fonts to subset and self-host, from `[[fonts]]`.
+    pub fonts: Vec<Font>,
+    /// The icon, like a `rel="icon"` link.
+    pub icon: Option<Icon>,
+    pub sprite: Sprite,
+    /// Static files to copy, from `[[copy]]`, like `rel="assets"` links.
+    pub copy: Vec<CopyAssets>,
+    pub assets: Assets,
+    pub changelog: Changelog,
+    pub version: Version,
+    pub permissions: Permissions,
+    pub print: Print,
+    pub package: Package,
+    pub output: Output,
+    pub review: Review,
+    pub cache: Cache,
+    pub compat: Compat,
+    pub csp: Csp,
+    /// Feature flags by name, from `[flags.<name>]`. Baked into the wasm by the
+    /// extension's build script; recorded here in `build-info.json`.
+    pub flags: BTreeMap<String, Flag>,
+    /// Page opened after the extension is uninstalled, e.g. a feedback survey.
+    /// `{version}`, `{target}` and `{profile}` are substituted.
+    pub uninstall_url: Option<String>,
+    /// Whether the options page opens in a tab (the default) rather than embedded in
+    /// the browser's extensions page, unless its declaration says otherwise.
+    pub options_open_in_tab: Option<bool>,
+    /// The icon files of the registry's popup by size, e.g. `{ 16 = "icons/16.png" }`,
+    /// referenced as `action.default_icon`.
+    pub popup_icons: BTreeMap<String, String>,
+    /// Page layouts by name, from `[layouts.<name>]`, replacing or adding to the
+    /// built-in `popup` and `fullpage`.
+    pub layouts: BTreeMap<String, Layout>,
+    /// Skeleton files by page file, e.g. `{ "popup.html" = "skeletons/popup.html" }`,
+    /// shown until the wasm mounts.
+    pub skeletons: BTreeMap<String, String>,
+}
+
+/// A page, from `[[pages]]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Page {
+    /// The name used by `data-wextrunk-include`, e.g. `WEXTRUNK_POPUP`.
+    pub name: String,
+    /// The output file, e.g. `popup.html`.
+    pub html: String,
+    pub wasm_fn: String,
+    /// The wasm artifact it binds to, if not Trunk's main one. See [`crate::artifacts`].
+    #[serde(default)]
+    pub wasm: Option<String>,
+    #[serde(default)]
+    pub no_reload: bool,
+    /// Whether the page is meant for printing, and gets the print stylesheet.
+    #[serde(default)]
+    pub report: bool,
+    /// `popup` for the popup, `options` for the options page.
+    pub kind: Option<String>,
+    /// Whether the options page opens in a tab; see `options_open_in_tab`.
+    pub open_in_tab: Option<bool>,
+    /// The popup's icon files by size, e.g. `{ 16 = "icons/16.png" }`.
+    #[serde(default)]
+    pub icons: BTreeMap<String, String>,
+    /// The page's layout, e.g. `popup`; `fullpage` by default.
+    pub layout: Option<String>,
+}
+
+/// A script, from `[[scripts]]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScriptEntry {
+    /// The output file, e.g. `background.js`.
+    pub js: String,
+    pub wasm_fn: String,
+    /// The wasm artifact it binds to, if not Trunk's main one. See [`crate::artifacts`].
+    #[serde(default)]
+    pub wasm: Option<String>,
+    #[serde(default)]
+    pub no_reload: bool,
+    /// Whether it's the background script, wrapped for service workers.
+    #[serde(default)]
+    pub background: bool,
+    /// Whether to keep the background's service worker from being stopped when idle.
+    #[serde(default)]
+    pub keep_alive: bool,
+    /// How the background runs, e.g. `event-page`, or per target,
+    /// `chrome:service-worker firefox:event-page`. See [`crate::background`].
+    #[serde(default)]
+    pub mode: Option<String>,
+}
+
+/// A content script, from `[[content_scripts]]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ContentScriptEntry {
+    /// The loader's output file, e.g. `content.js`.
+    pub js: String,
+    pub wasm_fn: String,
+    /// The wasm artifact it binds to, if not Trunk's main one. See [`crate::artifacts`].
+    #[serde(default)]
+    pub wasm: Option<String>,
+    /// Match patterns of the pages it runs in.
+    pub matches: Vec<String>,
+    /// `document_start`, `document_end` or `document_idle` (the default).
+    pub run_at: Option<String>,
+    #[serde(default)]
+    pub all_frames: bool,
+}
+
+/// Permission guard settings, from `[permissions]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Permissions {
+    /// Snapshot of the last release's permissions, relative to the source directory.
+    pub snapshot: String,
+    /// What to do when a packaged build adds permissions.
+    pub on_new: NewPermissions,
+    /// What to do when the permissions don't match the browser APIs the sources use.
+    pub lint: Lint,
+}
+
+impl Default for Permissions {
+    fn default() -> Self {
+        Permissions {
+            snapshot: "permissions.snapshot.json".to_string(),
+            on_new: NewPermissions::Error,
+            lint: Lint::Warn,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NewPermissions {
+    Error,
+    Warn,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Lint {
+    Off,
+    Warn,
+    Error,
+}
+
+/// Output file settings, from `[output]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Output {
+    /// Strip Trunk's content hashes from the output file names, and record them in
+    /// `asset-manifest.json` instead.
+    pub stable_names: bool,
+}
+
+/// Packaging settings, from `[package]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Package {
+    /// Where the packages are written, relative to the source directory. Not Trunk's
+    /// `dist`, which it empties on every build.
+    pub output_dir: String,
+}
+
+impl Default for Package {
+    fn default() -> Self {
+        Package {
+            output_dir: "target/dist".to_string(),
+        }
+    }
+}
+
+/// A page layout, from `[layouts.<name>]`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Layout {
+    /// HTML added to the end of the page's `<head>`.
+    pub head: String,
+    /// Classes added to the page's `<body>`.
+    pub body_class: String,
+}
+
+/// The source image of the icon set, from `[icon]` or a `rel="icon"` link.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Icon {
+    /// A PNG or SVG, relative to the source directory.
+    pub source: String,
+    /// The sizes to generate, in pixels.
+    #[serde(default = "default_icon_sizes")]
+    pub sizes: Vec<u32>,
+}
+
+pub fn default_icon_sizes() -> Vec<u32> {
+    vec![16, 32, 48, 96, 128]
+}
+
+/// Static files to copy into the build, from `[[copy]]` or a `rel="assets"` link.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CopyAssets {
+    /// Files and directories to copy, relative to the source directory, e.g.
+    /// `images/**/*.png`.
+    pub glob: String,
+    /// Match patterns of the pages that can load the files, for
+    /// `web_accessible_resources`.
+    #[serde(default)]
+    pub web_accessible: Vec<String>,
+}
+
+/// SVG sprite settings, from `[sprite]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Sprite {
+    /// Directory of SVG icons, relative to the source directory. No sprite without it.
+    pub dir: Option<String>,
+    /// Inject the sprite into every page, rather than writing `sprite.svg`.
+    pub inline: bool,
+}
+
+/// Report page settings, from `[print]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Print {
+    /// Print stylesheet added after wextrunk's defaults, relative to the source
+    /// directory. Skipped if it doesn't exist.
+    pub stylesheet: String,
+}
+
+impl Default for Print {
+    fn default() -> Self {
+        Print {
+            stylesheet: "print.css".to_string(),
+        }
+    }
+}
+
+/// Browser compatibility settings, from `[compat]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Compat {
+    /// The browser API facade, relative to the source directory.
+    pub bindings: String,
+    /// Directories scanned for `api("...")` feature detection, relative to the source
+    /// directory.
+    pub sources: Vec<String>,
+    /// Minimum version per browser, e.g. `{ chrome = "102", firefox = "115" }`. If
+    /// empty, only the target being built is checked, against the minimum version
+    /// in its manifest.
+    pub targets: BTreeMap<String, String>,
+    /// APIs (or whole namespaces) the code only uses where they exist, beyond the
+    /// ones it looks up with `api("...")`.
+    pub optional: Vec<String>,
+}
+
+impl Default for Compat {
+    fn default() -> Self {
+        Compat {
+            bindings: "src/browser.rs".to_string(),
+            sources: vec!["src".to_string()],
+            targets: BTreeMap::new(),
+            optional: Vec::new(),
+        }
+    }
+}
+
+/// Content Security Policy settings, from `[csp]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Csp {
+    /// Allow extension pages to compile wasm, with `'wasm-unsafe-eval'`. By default,
+    /// only if the build has a wasm file.
+    pub wasm: Option<bool>,
+    /// Origins extension pages connect to, e.g. `https://api.example.com`, added to
+    /// `connect-src`.
+    pub connect_src: Vec<String>,
+}
+
+/// A feature flag, from `[flags.<name>]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Flag {
+    pub description: String,
+    /// Its value, unless the profile overrides it.
+    pub default: bool,
+    /// Whether it can be overridden at runtime.
+    pub soft: bool,
+}
+
+/// Review build settings, from `[review]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Review {
+    /// Where the review artifacts are written, per target, relative to the source
+    /// directory.
+    pub output_dir: String,
+    /// Hand-written notes for the reviewer (e.g. how to test features behind a login),
+    /// relative to the source directory. Added to `REVIEW_NOTES.md` if it exists.
+    pub notes: String,
+}
+
+impl Default for Review {
+    fn default() -> Self {
+        Review {
+            output_dir: "target/review".to_string(),
+            notes: "REVIEW.md".to_string(),
+        }
+    }
+}
+
+/// Build cache settings, from `[cache]`. See [`crate::cache`].
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Cache {
+    pub enabled: bool,
+    /// Where the cached outputs are kept, relative to the source directory.
+    pub dir: String,
+}
+
+impl Default for Cache {
+    fn default() -> Self {
+        Cache {
+            enabled: true,
+            dir: "target/wextrunk-cache".to_string(),
+        }
+    }
+}
+
+/// Release notes settings, from `[changelog]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Changelog {
+    /// Changelog file, relative to the source directory. Skipped if it doesn't exist.
+    pub file: String,
+}
+
+impl Default for Changelog {
+    fn default() -> Self {
+        Changelog {
+            file: "CHANGELOG.md".to_string(),
+        }
+    }
+}
+
+/// Version settings, from `[version]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Version {
+    /// Environment variable with a build number to use as the version's fourth part,
+    /// e.g. `GITHUB_RUN_NUMBER`.
+    pub build_number_env: Option<String>,
+    /// Use the number of git commits as the build number, when the environment
+    /// variable isn't set.
+    pub build_number_from_git: bool,
+}
+
+/// Image optimisation settings for release builds, from `[assets]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Assets {
+    /// Recompress PNGs with `oxipng`.
+    pub png: bool,
+    /// Flags passed to `oxipng`.
+    pub oxipng: Vec<String>,
+    /// Minify SVGs.
+    pub svg: bool,
+}
+
+impl Default for Assets {
+    fn default() -> Self {
+        Assets {
+            png: true,
+            oxipng: vec![
+                "-o".to_string(),
+                "4".to_string(),
+                "--strip".to_string(),
+                "safe".to_string(),
+            ],
+            svg: true,
+        }
+    }
+}
+
+/// A font file to subset, from `[[fonts]]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Font {
+    /// Source font file, relative to the source directory, as referenced by the CSS.
+    pub file: String,
+    /// Unicode ranges to keep, in `pyftsubset` syntax, e.g. `["U+0020-007E"]`.
+    #[serde(default = "default_unicodes")]
+    pub unicodes: Vec<String>,
+    /// Also keep every character used in `_locales`.
+    #[serde(default = "default_true")]
+    pub locale_text: bool,
+}
+
+fn default_unicodes() -> Vec<String> {
+    vec!["U+0020-007E".to_string()]
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Localization settings, from `[i18n]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct I18n {
+    /// Message catalog directory, relative to the source directory. Localization is
+    /// only enabled if it or `messages_file` exists.
+    pub locales_dir: String,
+    /// Single-file message catalog, relative to the source directory, with every
+    /// locale's translations of a message together. Used instead of `locales_dir` when
+    /// it exists, and generates the `_locales` tree.
+    pub messages_file: String,
+    /// Written to the manifest's `default_locale`.
+    pub default_locale: String,
+    /// Whether to manage the manifest's `name`, `short_name` and `description` through
+    /// the message catalog.
+    pub localize_manifest: bool,
+    /// Replace every message with pseudo-localized text. Also enabled by setting
+    /// `WEXTRUNK_PSEUDO_LOCALE=1`. Refused for release builds.
+    pub pseudo_localize: bool,
+    /// Directories scanned for `t!("key")` usages, relative to the source directory.
+    pub sources: Vec<String>,
+    /// If set, write stub `messages.json` entries for every key a locale is missing to
+    /// this file (relative to the source directory), for translators to fill in.
+    pub stubs_file: Option<String>,
+}
+
+impl Default for I18n {
+    fn default() -> Self {
+        I18n {
+            locales_dir: "_locales".to_string(),
+            messages_file: "messages.toml".to_string(),
+            default_locale: "en".to_string(),
+            localize_manifest: true,
+            pseudo_localize: false,
+            sources: vec!["src".to_string()],
+            stubs_file: None,
+        }
+    }
+}
+
+/// Settings that only apply when a given profile is selected.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Profile {
+    /// Fields to deep-merge into the output manifest. Tables are merged key by key;
+    /// anything else (strings, numbers, arrays) replaces the existing value.
+    pub manifest: Map<String, Value>,
+    /// wasm-opt flags for every target built with this profile.
+    pub wasm_opt: Option<Vec<String>>,
+    /// Overrides the top-level `uninstall_url` for this profile. An empty string
+    /// disables it.
+    pub uninstall_url: Option<String>,
+    /// Feature flag values for this profile, overriding their `default`.
+    pub flags: BTreeMap<String, bool>,
+    /// More `connect-src` origins for this profile, e.g. a staging API.
+    pub connect_src: Vec<String>,
+    /// Settings that only apply to a given target within this profile.
+    pub targets: BTreeMap<String, Target>,
+}
+
+/// Settings that only apply when building for a given target.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Target {
+    /// wasm-opt flags, e.g. `["-Oz"]`. An empty list disables wasm-opt.
+    pub wasm_opt: Option<Vec<String>>,
+    /// The manifest version to output, 3 by default. With 2, the (MV3) source manifest
+    /// is converted to MV2.
+    pub manifest_version: Option<u8>,
+    /// The target's source manifest, relative to the source directory, like a
+    /// `rel="manifest"` link. Only read from the top-level `[targets]`.
+    pub manifest: Option<String>,
+}
+
+impl Config {
+    /// Load `wextrunk.toml` from the source directory, or the default config if it
+    /// doesn't exist.
+    pub fn load(source_dir: &str) -> Self {
+        let path = Path::new(source_dir).join(CONFIG_FILE);
+        let metadata = Self::cargo_metadata(source_dir);
+        let Ok(contents) = fs::read_to_string(&path) else {
+            return metadata.unwrap_or_default();
+        };
+        if metadata.is_some() {
+            panic!("Both {CONFIG_FILE} and [package.metadata.wextrunk] in Cargo.toml configure wextrunk; use only one of them.");
+        }
+        toml::from_str(&contents)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()))
+    }
+
+    /// The config in `[package.metadata.wextrunk]`, if `Cargo.toml` has it.
+    fn cargo_metadata(source_dir: &str) -> Option<Self> {
+        let path = Path::new(source_dir).join("Cargo.toml");
+        let contents = fs::read_to_string(&path).ok()?;
+        let mut cargo: toml::Table = toml::from_str(&contents)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+        let metadata = cargo
+            .get_mut("package")?
+            .get_mut("metadata")?
+            .as_table_mut()?
+            .remove("wextrunk")?;
+        Some(metadata.try_into().unwrap_or_else(|e| {
+            panic!(
+                "Failed to parse [package.metadata.wextrunk] in {}: {e}",
+                path.display()
+            )
+        }))
+    }
+
+    /// The currently selected profile, if any.
+    ///
+    /// The profile is named by `WEXTRUNK_PROFILE`, falling back to Trunk's own
+    /// `TRUNK_PROFILE` (`debug` or `release`). Explicitly asking for a profile that
+    /// isn't configured is an error, to catch typos in CI setups.
+    pub fn profile(&self) -> Option<(String, &Profile)> {
+        if let Ok(name) = env::var("WEXTRUNK_PROFILE") {
+            let profile = self.profiles.get(&name).unwrap_or_else(|| {
+                panic!("WEXTRUNK_PROFILE is set to {name:?}, but {CONFIG_FILE} has no [profiles.{name}] section.")
+            });
+            return Some((name, profile));
+        }
+        let name = env::var("TRUNK_PROFILE").ok()?;
+        let profile = self.profiles.get(&name)?;
+        Some((name, profile))
+    }
+
+    /// wasm-opt flags for `target`, if wasm-opt should run at all.
+    ///
+    /// The most specific setting wins: `[profiles.<profile>.targets.<target>]`, then
+    /// `[profiles.<profile>]`, then `[targets.<target>]`. Checking the profile before
+    /// the target means e.g. `[profiles.debug] wasm_opt = []` keeps debug builds fast
+    /// regardless of per-target release settings.
+    pub fn wasm_opt_flags(&self, target: &str) -> Option<Vec<String>> {
+        let profile = self.profile().map(|(_, profile)| profile);
+        profile
+            .and_then(|profile| profile.targets.get(target))
+            .and_then(|target| target.wasm_opt.clone())
+            .or_else(|| profile.and_then(|profile| profile.wasm_opt.clone()))
+            .or_else(|| self.targets.get(target)?.wasm_opt.clone())
+            .filter(|flags| !flags.is_empty())
+    }
+
+    /// The manifest version to output for `target`. The profile's target settings win
+    /// over the top-level ones.
+    pub fn manifest_version(&self, target: &str) -> u8 {
+        let version = self
+            .profile()
+            .and_then(|(_, profile)| profile.targets.get(target)?.manifest_version)
+            .or_else(|| self.targets.get(target)?.manifest_version)
+            .unwrap_or(3);
+        if !matches!(version, 2 | 3) {
+            panic!("manifest_version for {target} must be 2 or 3, not {version}.");
+        }
+        version
+    }
+
+    /// The `connect-src` origins: the `[csp]` ones, then the selected profile's.
+    pub fn connect_src(&self) -> Vec<String> {
+        let mut origins = self.csp.connect_src.clone();
+        if let Some((_, profile)) = self.profile() {
+            origins.extend(profile.connect_src.iter().cloned());
+        }
+        origins
+    }
+
+    /// The uninstall URL for the selected profile, if any.
+    pub fn uninstall_url(&self) -> Option<String> {
+        self.profile()
+            .and_then(|(_, profile)| profile.uninstall_url.clone())
+            .or_else(|| self.uninstall_url.clone())
+            .filter(|url| !url.is_empty())
+    }
+
+    /// The value of every feature flag for the selected profile.
+    pub fn flags(&self) -> BTreeMap<String, bool> {
+        let profile = self.profile().map(|(_, profile)| profile);
+        self.flags
+            .iter()
+            .map(|(name, flag)| {
+                let value = profile
+                    .and_then(|profile| profile.flags.get(name))
+                    .copied()
+                    .unwrap_or(flag.default);
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// Whether this is a release build, according to Trunk.
+    pub fn is_release(&self) -> bool {
+        env::var("TRUNK_PROFILE").is_ok_and(|profile| profile == "release")
+    }
+
+    /// Whether to package the extension: for release builds, or with
+    /// `WEXTRUNK_PACKAGE=1`.
+    pub fn is_package(&self) -> bool {
+        self.is_release() || env::var("WEXTRUNK_PACKAGE").is_ok_and(|value| value == "1")
+    }
+
+    /// Whether this is a review build, set with `WEXTRUNK_REVIEW=1`. Only allowed
+    /// for release builds, as reviewers get release builds.
+    pub fn is_review(&self) -> bool {
+        let enabled = env::var("WEXTRUNK_REVIEW").is_ok_and(|value| value == "1");
+        if enabled && !self.is_release() {
+            panic!(
+                "Review builds are release builds; run `WEXTRUNK_REVIEW=1 trunk build --release`."
+            );
+        }
+        enabled
+    }
+
+    /// Whether to pseudo-localize messages for this build.
+    pub fn pseudo_localize(&self) -> bool {
+        let enabled = self.i18n.pseudo_localize
+            || env::var("WEXTRUNK_PSEUDO_LOCALE").is_ok_and(|value| value == "1");
+        if enabled && self.is_release() {
+            panic!(
+                "Pseudo-localization is a development aid and can't be used for release builds."
+            );
+        }
+        enabled
+    }
+}
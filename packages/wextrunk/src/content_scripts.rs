@@ -0,0 +1,152 @@
+//! Content scripts, declared with `rel="contentscript"` links in index.html, or
+//! `[[content_scripts]]` in wextrunk.toml.
+//!
+//! Content scripts are classic scripts, so they can't import the wasm-bindgen module
+//! the way page shims do. Instead, each one gets a small loader that imports the module
+//! with a dynamic `import()` of its `runtime.getURL(..)`, instantiates the wasm from
+//! the extension's origin, and calls the script's entry function. The manifest gets
+//! the matching `content_scripts` entry, and a `web_accessible_resources` entry that
+//! lets pages matching the same patterns load the module and the wasm.
+
+use std::{fs, path::Path};
+
+use serde_json::{json, Map, Value};
+
+use crate::{artifacts::Artifacts, ScriptTemplate};
+
+/// When a content script runs, as in the manifest's `run_at`.
+const RUN_AT: &[&str] = &["document_start", "document_end", "document_idle"];
+
+/// A content script declared in index.html or the config.
+#[derive(Debug)]
+pub struct ContentScript {
+    /// The loader's file name, e.g. `content.js`.
+    pub js: String,
+    pub wasm_fn: String,
+    /// The wasm artifact it binds to, if not Trunk's main one.
+    pub wasm: Option<String>,
+    /// Match patterns of the pages it runs in.
+    pub matches: Vec<String>,
+    /// One of [`RUN_AT`].
+    pub run_at: String,
+    pub all_frames: bool,
+}
+
+impl ContentScript {
+    pub fn new(
+        js: String,
+        wasm_fn: String,
+        matches: Vec<String>,
+        wasm: Option<String>,
+        run_at: Option<String>,
+        all_frames: bool,
+    ) -> Self {
+        if matches.is_empty() {
+            panic!("contentscript link {js:?} must have at least one match pattern");
+        }
+        let run_at = run_at.unwrap_or_else(|| "document_idle".to_string());
+        if !RUN_AT.contains(&run_at.as_str()) {
+            panic!(
+                "contentscript link {js:?} has run-at {run_at:?}, but it must be one of {}",
+                RUN_AT.join(", ")
+            );
+        }
+        ContentScript {
+            js,
+            wasm_fn,
+            wasm,
+            matches,
+            run_at,
+            all_frames,
+        }
+    }
+}
+
+/// Split a link's `matches` attribute, separated by whitespace or commas.
+pub fn split_patterns(matches: &str) -> Vec<String> {
+    matches
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|pattern| !pattern.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Add the `content_scripts` and `web_accessible_resources` entries for every content
+/// script. Scripts the manifest already lists are left alone.
+pub fn add_content_scripts(
+    content_scripts: &[ContentScript],
+    manifest: &mut Value,
+    artifacts: &Artifacts,
+    staging_dir: &str,
+) {
+    if content_scripts.is_empty() {
+        return;
+    }
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    // wasm-bindgen puts inline JS snippets next to the module, which imports them.
+    let snippets = Path::new(staging_dir).join("snippets").is_dir();
+
+    for script in content_scripts {
+        let (module, wasm) = artifacts.template(script.wasm.as_deref()).module_paths();
+        let mut resources = vec![json!(module), json!(wasm)];
+        if snippets {
+            resources.push(json!("snippets/*"));
+        }
+        let entries = array(manifest, "content_scripts");
+        if entries.iter().any(|entry| {
+            entry["js"]
+                .as_array()
+                .is_some_and(|js| js.contains(&json!(script.js)))
+        }) {
+            println!(
+                "The manifest already declares content script {}; not adding it.",
+                script.js
+            );
+            continue;
+        }
+        let mut entry = Map::new();
+        entry.insert("matches".to_string(), json!(script.matches));
+        entry.insert("js".to_string(), json!([script.js]));
+        entry.insert("run_at".to_string(), json!(script.run_at));
+        if script.all_frames {
+            entry.insert("all_frames".to_string(), json!(true));
+        }
+        entries.push(Value::Object(entry));
+
+        array(manifest, "web_accessible_resources").push(json!({
+            "resources": resources,
+            "matches": script.matches,
+        }));
+    }
+}
+
+/// Write a content script's loader to the staging directory.
+pub fn write_content_script(
+    script: &ContentScript,
+    staging_dir: &str,
+    script_template: &ScriptTemplate,
+) {
+    let (module, wasm) = script_template.module_paths();
+    let loader = format!(
+        "(async () => {{
+  const runtime = (globalThis.browser ?? globalThis.chrome).runtime;
+  const {{ default: init }} = await import(runtime.getURL('{module}'));
+  const wasm = await init({{module_or_path: runtime.getURL('{wasm}')}});
+  await wasm.{}();
+}})();
+",
+        script.wasm_fn
+    );
+    fs::write(Path::new(staging_dir).join(&script.js), loader).unwrap();
+}
+
+/// The array at `key`, created if it's missing.
+fn array<'a>(manifest: &'a mut Map<String, Value>, key: &str) -> &'a mut Vec<Value> {
+    manifest
+        .entry(key.to_string())
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .unwrap_or_else(|| panic!("Manifest field {key:?} must be an array"))
+}
@@ -0,0 +1,83 @@
+//! Copying extra static files (fonts, images, data files, ...) into the build.
+//!
+//! `<link data-wextrunk rel="assets" glob="images/**/*.png">`, or `[[copy]]` with a
+//! `glob` in `wextrunk.toml`, copies every file and directory matching the pattern,
+//! relative to the source directory, to the same path in the build. With
+//! `web-accessible="https://*.example.com/*"` (`web_accessible = [...]`), the copied
+//! files are also listed in `web_accessible_resources` for pages matching those
+//! patterns, e.g. for images a content script shows on them.
+
+use std::{fs, path::Path};
+
+use serde_json::{json, Value};
+
+use crate::config::CopyAssets;
+
+/// Copy the files matching every declaration into `staging_dir`, and add the
+/// `web_accessible_resources` entries.
+pub fn copy_assets(
+    copies: &[CopyAssets],
+    manifest: &mut Value,
+    source_dir: &str,
+    staging_dir: &str,
+) {
+    for copy in copies {
+        if copy.glob.starts_with('/') || copy.glob.split('/').any(|part| part == "..") {
+            panic!(
+                "Asset pattern {:?} must stay inside the source directory",
+                copy.glob
+            );
+        }
+        let pattern = Path::new(source_dir).join(&copy.glob);
+        let matches: Vec<_> = glob::glob(&pattern.to_string_lossy())
+            .unwrap_or_else(|e| panic!("Invalid asset pattern {:?}: {e}", copy.glob))
+            .map(Result::unwrap)
+            .collect();
+        if matches.is_empty() {
+            println!("Warning: no files match the asset pattern {:?}.", copy.glob);
+            continue;
+        }
+        let mut files = Vec::new();
+        for path in matches {
+            let relative = path.strip_prefix(source_dir).unwrap();
+            copy_path(
+                &path,
+                &Path::new(staging_dir).join(relative),
+                relative,
+                &mut files,
+            );
+        }
+        if !copy.web_accessible.is_empty() {
+            manifest
+                .as_object_mut()
+                .expect("The manifest must be a JSON object")
+                .entry("web_accessible_resources")
+                .or_insert_with(|| json!([]))
+                .as_array_mut()
+                .expect("Manifest field \"web_accessible_resources\" must be an array")
+                .push(json!({
+                    "resources": files,
+                    "matches": copy.web_accessible,
+                }));
+        }
+    }
+}
+
+/// Copy a file or directory, recording the copied files' paths in `files`.
+fn copy_path(from: &Path, to: &Path, relative: &Path, files: &mut Vec<String>) {
+    if from.is_dir() {
+        for entry in fs::read_dir(from).unwrap() {
+            let name = entry.unwrap().file_name();
+            copy_path(
+                &from.join(&name),
+                &to.join(&name),
+                &relative.join(&name),
+                files,
+            );
+        }
+        return;
+    }
+    fs::create_dir_all(to.parent().unwrap()).unwrap();
+    fs::copy(from, to).unwrap();
+    files.push(relative.to_string_lossy().replace('\\', "/"));
+}
@@ -0,0 +1,464 @@
+//! The manifest's Content Security Policy, assembled from what the extension needs.
+//!
+//! Instead of maintaining `content_security_policy` by hand, the policies are built
+//! from what the build contains and `[csp]` in `wextrunk.toml`: `'wasm-unsafe-eval'`
+//! if there's wasm for extension pages to instantiate (missing it makes Chrome refuse
+//! to compile the wasm, and the pages stay blank), and `connect-src` entries for the
+//! API endpoints they talk to (per profile, too, e.g. for a staging API). Anything the
+//! manifest already declares, as an MV3 object or an MV2 string, is kept and added to.
+//! If the manifest has sandboxed pages, they get a `sandbox` policy. The policy is
+//! written as an MV3 object; MV2 targets get it flattened to a string (see `mv2`).
+//!
+//! Under `trunk serve` with auto-reload, the dev server's origin is added to
+//! `connect-src`, so the auto-reload script can open its websocket even when the policy
+//! restricts connections. Release builds strip dev server origins (`localhost`,
+//! `127.0.0.1` or the `trunk serve` address) from the policies again, wherever they came
+//! from, and then verify the result: a policy with `'unsafe-eval'` or `'unsafe-inline'`
+//! fails the build, as it would be rejected by store review or weaken the shipped
+//! extension; other builds warn about unsafe sources. Once the
+//! pages are written, they're checked for inline scripts, inline event handlers and
+//! `javascript:` URLs, which the policy blocks without any error but in the console.
+
+use std::{cell::RefCell, env, fmt, fs, path::Path};
+
+use lol_html::{element, text, HtmlRewriter, Settings};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+
+/// Sources of a directive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    /// `'self'`
+    SelfOrigin,
+    /// `'none'`
+    None,
+    /// `'wasm-unsafe-eval'`, needed to compile wasm in MV3 extension pages.
+    WasmUnsafeEval,
+    /// `'unsafe-eval'`
+    UnsafeEval,
+    /// `'unsafe-inline'`
+    UnsafeInline,
+    /// Any other quoted keyword, e.g. `'unsafe-hashes'`, without the quotes.
+    Keyword(String),
+    /// A host or scheme source, e.g. `https://api.example.com`, or another unquoted
+    /// value, e.g. the `sandbox` directive's `allow-scripts`.
+    Host(String),
+}
+
+impl Source {
+    fn parse(token: &str) -> Source {
+        match token {
+            "'self'" => Source::SelfOrigin,
+            "'none'" => Source::None,
+            "'wasm-unsafe-eval'" => Source::WasmUnsafeEval,
+            "'unsafe-eval'" => Source::UnsafeEval,
+            "'unsafe-inline'" => Source::UnsafeInline,
+            _ => match token.strip_prefix('\'').and_then(|t| t.strip_suffix('\'')) {
+                Some(keyword) => Source::Keyword(keyword.to_string()),
+                None => Source::Host(token.to_string()),
+            },
+        }
+    }
+
+    /// Whether this weakens the policy enough that release builds refuse it.
+    fn is_unsafe(&self) -> bool {
+        match self {
+            Source::UnsafeEval | Source::UnsafeInline => true,
+            Source::Keyword(keyword) => keyword.starts_with("unsafe-"),
+            _ => false,
+        }
+    }
+
+    /// Whether this points at a local dev server, rather than anything users can reach.
+    fn is_dev_server(&self) -> bool {
+        let Source::Host(host) = self else {
+            return false;
+        };
+        let rest = host
+            .split_once("://")
+            .map_or(host.as_str(), |(_, rest)| rest);
+        let rest = rest.split('/').next().unwrap_or_default();
+        let hostname = match rest.strip_prefix('[') {
+            Some(ipv6) => ipv6.split(']').next().unwrap_or_default(),
+            None => rest.split(':').next().unwrap_or_default(),
+        };
+        let serve_address = env::var("TRUNK_SERVE_ADDRESS").ok();
+        matches!(hostname, "localhost" | "127.0.0.1" | "::1" | "0.0.0.0")
+            || hostname.ends_with(".localhost")
+            || serve_address.as_deref() == Some(hostname)
+    }
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::SelfOrigin => f.write_str("'self'"),
+            Source::None => f.write_str("'none'"),
+            Source::WasmUnsafeEval => f.write_str("'wasm-unsafe-eval'"),
+            Source::UnsafeEval => f.write_str("'unsafe-eval'"),
+            Source::UnsafeInline => f.write_str("'unsafe-inline'"),
+            Source::Keyword(keyword) => write!(f, "'{keyword}'"),
+            Source::Host(host) => f.write_str(host),
+        }
+    }
+}
+
+/// A Content Security Policy: directives and their sources, in order.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Policy {
+    directives: Vec<(String, Vec<Source>)>,
+}
+
+impl Policy {
+    /// Parse a policy string, e.g. `script-src 'self'; object-src 'self';`.
+    pub fn parse(policy: &str) -> Policy {
+        let mut parsed = Policy::default();
+        for directive in policy.split(';') {
+            let mut tokens = directive.split_whitespace();
+            let Some(name) = tokens.next() else {
+                continue;
+            };
+            parsed = parsed.directive(name);
+            for token in tokens {
+                parsed = parsed.allow(name, Source::parse(token));
+            }
+        }
+        parsed
+    }
+
+    /// Add `directive` without sources, if it isn't there yet, e.g. `sandbox`.
+    pub fn directive(mut self, directive: &str) -> Self {
+        let directive = directive.to_ascii_lowercase();
+        if !self.directives.iter().any(|(name, _)| *name == directive) {
+            self.directives.push((directive, Vec::new()));
+        }
+        self
+    }
+
+    /// Whether the policy has `directive`.
+    pub fn has(&self, directive: &str) -> bool {
+        let directive = directive.to_ascii_lowercase();
+        self.directives.iter().any(|(name, _)| *name == directive)
+    }
+
+    /// Add `source` to `directive`, adding the directive if needed. `'none'` is dropped
+    /// once the directive allows anything else.
+    pub fn allow(mut self, directive: &str, source: Source) -> Self {
+        self = self.directive(directive);
+        let directive = directive.to_ascii_lowercase();
+        let (_, sources) = self
+            .directives
+            .iter_mut()
+            .find(|(name, _)| *name == directive)
+            .unwrap();
+        if source != Source::None {
+            sources.retain(|existing| *existing != Source::None);
+        } else if !sources.is_empty() {
+            return self;
+        }
+        if !sources.contains(&source) {
+            sources.push(source);
+        }
+        self
+    }
+
+    /// Remove the sources matching `remove`, returning what was removed. Directives left
+    /// without sources are removed too.
+    pub fn remove(&mut self, remove: impl Fn(&Source) -> bool) -> Vec<String> {
+        let mut removed = Vec::new();
+        self.directives.retain_mut(|(directive, sources)| {
+            let before = sources.len();
+            sources.retain(|source| {
+                if remove(source) {
+                    removed.push(format!("{directive} {source}"));
+                    false
+                } else {
+                    true
+                }
+            });
+            sources.len() == before || !sources.is_empty()
+        });
+        removed
+    }
+
+    /// Every source of every directive, with the directive's name.
+    fn sources(&self) -> impl Iterator<Item = (&str, &Source)> {
+        self.directives
+            .iter()
+            .flat_map(|(name, sources)| sources.iter().map(move |source| (name.as_str(), source)))
+    }
+
+    /// The unsafe sources this policy allows, with their directives.
+    pub fn unsafe_sources(&self) -> Vec<String> {
+        self.sources()
+            .filter(|(_, source)| source.is_unsafe())
+            .map(|(directive, source)| format!("{directive} allows {source}"))
+            .collect()
+    }
+
+    /// What makes this policy unfit for a release build, if anything.
+    pub fn release_violations(&self) -> Vec<String> {
+        self.sources()
+            .filter_map(|(directive, source)| {
+                if source.is_unsafe() {
+                    Some(format!("{directive} allows {source}"))
+                } else if source.is_dev_server() {
+                    Some(format!("{directive} allows the dev server origin {source}"))
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+impl fmt::Display for Policy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (name, sources) in &self.directives {
+            f.write_str(name)?;
+            for source in sources {
+                write!(f, " {source}")?;
+            }
+            f.write_str("; ")?;
+        }
+        Ok(())
+    }
+}
+
+/// Build the manifest's `content_security_policy` from the build and the config, on
+/// top of what the manifest declares, and verify it for release builds.
+///
+/// `dev_server` is the `trunk serve` address (host and port) when the build is served
+/// with auto-reload.
+pub fn build_csp(
+    config: &Config,
+    manifest: &mut Value,
+    staging_dir: &str,
+    dev_server: Option<&str>,
+) {
+    let declared = manifest.get("content_security_policy");
+    let declared = |key: &str| {
+        let policy = match declared {
+            // An MV2 policy only covers extension pages.
+            Some(Value::String(policy)) => (key == "extension_pages").then_some(policy.as_str()),
+            Some(policies) => policies.get(key).and_then(Value::as_str),
+            None => None,
+        };
+        policy.map(Policy::parse)
+    };
+    let wasm = config.csp.wasm.unwrap_or_else(|| has_wasm(staging_dir));
+
+    let mut extension_pages = declared("extension_pages")
+        .unwrap_or_default()
+        .allow("script-src", Source::SelfOrigin);
+    // A declared `object-src` is kept as it is; adding `'self'` would drop its `'none'`.
+    if !extension_pages.has("object-src") {
+        extension_pages = extension_pages.allow("object-src", Source::SelfOrigin);
+    }
+    if wasm {
+        extension_pages = extension_pages.allow("script-src", Source::WasmUnsafeEval);
+    }
+    for endpoint in config.connect_src() {
+        extension_pages = extension_pages
+            .allow("connect-src", Source::SelfOrigin)
+            .allow("connect-src", Source::Host(endpoint));
+    }
+    if let Some(address) = dev_server.filter(|_| !config.is_release()) {
+        extension_pages = ["ws", "http"].into_iter().fold(
+            extension_pages.allow("connect-src", Source::SelfOrigin),
+            |policy, scheme| {
+                policy.allow("connect-src", Source::Host(format!("{scheme}://{address}")))
+            },
+        );
+    }
+
+    let has_sandbox = manifest
+        .pointer("/sandbox/pages")
+        .and_then(Value::as_array)
+        .is_some_and(|pages| !pages.is_empty());
+    let sandbox = declared("sandbox").or_else(|| {
+        has_sandbox.then(|| {
+            [
+                "allow-scripts",
+                "allow-forms",
+                "allow-popups",
+                "allow-modals",
+            ]
+            .into_iter()
+            .fold(Policy::default(), |policy, flag| {
+                policy.allow("sandbox", Source::Host(flag.to_string()))
+            })
+            .allow("script-src", Source::SelfOrigin)
+            .allow("child-src", Source::SelfOrigin)
+        })
+    });
+    let mut sandbox = match sandbox {
+        Some(sandbox) if wasm => Some(sandbox.allow("script-src", Source::WasmUnsafeEval)),
+        sandbox => sandbox,
+    };
+
+    if config.is_release() {
+        for (name, policy) in [
+            ("extension_pages", Some(&mut extension_pages)),
+            ("sandbox", sandbox.as_mut()),
+        ] {
+            for removed in policy
+                .map(|policy| policy.remove(Source::is_dev_server))
+                .unwrap_or_default()
+            {
+                println!("Removed the dev server source {removed} from the {name} policy for the release build.");
+            }
+        }
+        let violations: Vec<String> = [
+            ("extension_pages", Some(&extension_pages)),
+            ("sandbox", sandbox.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(name, policy)| Some((name, policy?)))
+        .flat_map(|(name, policy)| {
+            policy
+                .release_violations()
+                .into_iter()
+                .map(move |violation| format!("{name}: {violation}"))
+        })
+        .collect();
+        if !violations.is_empty() {
+            panic!(
+                "The Content Security Policy isn't fit for a release build:\n  {}",
+                violations.join("\n  ")
+            );
+        }
+    } else {
+        for (name, policy) in [
+            ("extension_pages", Some(&extension_pages)),
+            ("sandbox", sandbox.as_ref()),
+        ] {
+            for source in policy.map(Policy::unsafe_sources).unwrap_or_default() {
+                println!("Warning: the {name} Content Security Policy {source}; release builds will fail.");
+            }
+        }
+    }
+
+    let mut policies = json!({ "extension_pages": extension_pages.to_string().trim_end() });
+    println!("Content Security Policy for extension pages: {extension_pages}");
+    if let Some(sandbox) = sandbox {
+        println!("Content Security Policy for sandboxed pages: {sandbox}");
+        policies["sandbox"] = json!(sandbox.to_string().trim_end());
+    }
+    manifest["content_security_policy"] = policies;
+}
+
+/// Warn about code in the generated pages that the Content Security Policy blocks:
+/// inline scripts, `on*` event handler attributes and `javascript:` URLs.
+pub fn check_inline_code(staging_dir: &str) {
+    let mut pages: Vec<_> = fs::read_dir(staging_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "html"))
+        .collect();
+    pages.sort();
+    for page in pages {
+        let name = page.file_name().unwrap().to_string_lossy().to_string();
+        for problem in inline_code(&fs::read_to_string(&page).unwrap()) {
+            println!("Warning: {name} has {problem}, which the Content Security Policy blocks.");
+        }
+    }
+}
+
+/// Whether the staging directory has a wasm file.
+fn has_wasm(staging_dir: &str) -> bool {
+    fs::read_dir(Path::new(staging_dir)).unwrap().any(|entry| {
+        entry
+            .unwrap()
+            .path()
+            .extension()
+            .is_some_and(|ext| ext == "wasm")
+    })
+}
+
+/// Descriptions of the inline code in an HTML page.
+fn inline_code(html: &str) -> Vec<String> {
+    // Both handlers add to it.
+    let problems = RefCell::new(Vec::new());
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![
+                element!("*", |el| {
+                    let tag = el.tag_name();
+                    for attribute in el.attributes() {
+                        let name = attribute.name();
+                        if name.starts_with("on") {
+                            problems
+                                .borrow_mut()
+                                .push(format!("an inline {name} handler on <{tag}>"));
+                        } else if matches!(name.as_str(), "href" | "src" | "action")
+                            && attribute
+                                .value()
+                                .trim_start()
+                                .to_ascii_lowercase()
+                                .starts_with("javascript:")
+                        {
+                            problems
+                                .borrow_mut()
+                                .push(format!("a javascript: URL in <{tag} {name}>"));
+                        }
+                    }
+                    Ok(())
+                }),
+                // Data blocks, e.g. JSON, aren't scripts.
+                text!(
+                    r#"script:not([src]):not([type="application/json"]):not([type="application/ld+json"])"#,
+                    |text| {
+                        if !text.as_str().trim().is_empty() {
+                            problems.borrow_mut().push("an inline <script>".to_string());
+                        }
+                        Ok(())
+                    }
+                ),
+            ],
+            ..Settings::default()
+        },
+        |_: &[u8]| {},
+    );
+    rewriter.write(html.as_bytes()).unwrap();
+    rewriter.end().unwrap();
+    let mut problems = problems.into_inner();
+    problems.dedup();
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn extension_pages(manifest: Value) -> String {
+        let mut config = Config::default();
+        config.csp.wasm = Some(false);
+        let mut manifest = manifest;
+        build_csp(&config, &mut manifest, "", None);
+        manifest["content_security_policy"]["extension_pages"]
+            .as_str()
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn object_src_defaults_to_self() {
+        assert_eq!(
+            extension_pages(json!({})),
+            "script-src 'self'; object-src 'self';"
+        );
+    }
+
+    #[test]
+    fn declared_object_src_is_kept() {
+        let manifest = json!({
+            "content_security_policy": { "extension_pages": "object-src 'none'" },
+        });
+        assert_eq!(
+            extension_pages(manifest),
+            "object-src 'none'; script-src 'self';"
+        );
+    }
+}
@@ -0,0 +1,202 @@
+//! Pages, scripts, content scripts and manifests declared in the config, merged with
+//! the `data-wextrunk` links in index.html.
+//!
+//! Everything a link can declare can also go in `wextrunk.toml` (or
+//! `[package.metadata.wextrunk]`): `[[pages]]`, `[[scripts]]`, `[[content_scripts]]`,
+//! and a `manifest` per `[targets.<target>]`, with `default_target` selecting the one
+//! built without `WEXTRUNK_TARGET`. A link in index.html overrides the config entry for
+//! the same page name or output file, or the same target. Two config entries for the
+//! same file, or a page and a script writing the same file, are an error.
+
+use std::collections::BTreeSet;
+
+use crate::{
+    config::Config,
+    content_scripts::ContentScript,
+    layouts,
+    manifest::{Manifest, ManifestLink},
+    page_kinds::PageKind,
+    targets::ALL_TARGETS,
+    HtmlPage, Script,
+};
+
+/// Add the config's pages, scripts and content scripts that index.html doesn't
+/// declare.
+pub fn add_declarations(
+    config: &Config,
+    html_pages: &mut Vec<HtmlPage>,
+    scripts: &mut Vec<Script>,
+    content_scripts: &mut Vec<ContentScript>,
+) {
+    check_unique(
+        "wextrunk.toml",
+        config
+            .pages
+            .iter()
+            .map(|page| page.html.as_str())
+            .chain(config.scripts.iter().map(|script| script.js.as_str()))
+            .chain(
+                config
+                    .content_scripts
+                    .iter()
+                    .map(|script| script.js.as_str()),
+            ),
+    );
+
+    for page in &config.pages {
+        if html_pages
+            .iter()
+            .any(|existing| existing.name == page.name || existing.html == page.html)
+        {
+            println!("index.html overrides the {} page in the config.", page.html);
+            continue;
+        }
+        let kind = PageKind::from_declaration(
+            page.kind.as_deref(),
+            page.open_in_tab,
+            page.icons.clone(),
+            &page.html,
+        );
+        html_pages.push(HtmlPage {
+            name: page.name.clone(),
+            html: page.html.clone(),
+            no_reload: page.no_reload,
+            wasm_fn: page.wasm_fn.clone(),
+            wasm: page.wasm.clone(),
+            report: page.report,
+            layout: page
+                .layout
+                .clone()
+                .unwrap_or_else(|| layouts::default_for(kind.as_ref()).to_string()),
+            kind,
+        });
+    }
+    for script in &config.scripts {
+        if scripts.iter().any(|existing| existing.js == script.js) {
+            println!(
+                "index.html overrides the {} script in the config.",
+                script.js
+            );
+            continue;
+        }
+        scripts.push(Script {
+            js: script.js.clone(),
+            no_reload: script.no_reload,
+            background_script: script.background,
+            keep_alive: script.keep_alive,
+            mode: script.mode.clone(),
+            wasm_fn: script.wasm_fn.clone(),
+            wasm: script.wasm.clone(),
+            html_page: false,
+        });
+    }
+    for script in &config.content_scripts {
+        if content_scripts
+            .iter()
+            .any(|existing| existing.js == script.js)
+        {
+            println!(
+                "index.html overrides the {} content script in the config.",
+                script.js
+            );
+            continue;
+        }
+        content_scripts.push(ContentScript::new(
+            script.js.clone(),
+            script.wasm_fn.clone(),
+            script.matches.clone(),
+            script.wasm.clone(),
+            script.run_at.clone(),
+            script.all_frames,
+        ));
+    }
+
+    check_unique(
+        "index.html and wextrunk.toml",
+        html_pages
+            .iter()
+            .map(|page| page.html.as_str())
+            .chain(scripts.iter().map(|script| script.js.as_str()))
+            .chain(content_scripts.iter().map(|script| script.js.as_str())),
+    );
+}
+
+/// Select the manifests to build: the one for `requested`, the default one, or every
+/// target's with [`ALL_TARGETS`]. Links in index.html override the config's manifest
+/// for the same target.
+pub fn select_manifests(
+    links: &[ManifestLink],
+    config: &Config,
+    requested: Option<&str>,
+) -> Vec<Manifest> {
+    let mut manifests: Vec<ManifestLink> = links.to_vec();
+    for (target, settings) in &config.targets {
+        let Some(href) = &settings.manifest else {
+            continue;
+        };
+        if links.iter().any(|link| link.target == *target) {
+            println!("index.html overrides the {target} manifest in the config.");
+            continue;
+        }
+        manifests.push(ManifestLink {
+            target: target.clone(),
+            href: href.clone(),
+            default: false,
+        });
+    }
+
+    let selected: Vec<&ManifestLink> = match requested {
+        Some(ALL_TARGETS) => {
+            let targets: Vec<&str> = manifests
+                .iter()
+                .map(|manifest| manifest.target.as_str())
+                .collect();
+            check_unique("index.html and wextrunk.toml", targets.iter().copied());
+            if manifests.is_empty() {
+                panic!("WEXTRUNK_TARGET is {ALL_TARGETS}, but no manifests are declared.");
+            }
+            manifests.iter().collect()
+        }
+        Some(requested) => manifests
+            .iter()
+            .filter(|manifest| manifest.target == requested)
+            .collect(),
+        None => {
+            let linked: Vec<&ManifestLink> = manifests
+                .iter()
+                .filter(|manifest| manifest.default)
+                .collect();
+            if linked.is_empty() {
+                manifests
+                    .iter()
+                    .filter(|manifest| config.default_target.as_ref() == Some(&manifest.target))
+                    .collect()
+            } else {
+                linked
+            }
+        }
+    };
+    match selected[..] {
+        [] => panic!("No manifest was selected, but one is required. You must specify a manifest as default (or `default_target` in wextrunk.toml), or specify a target with the WEXTRUNK_TARGET environment variable."),
+        [_, _, ..] if requested != Some(ALL_TARGETS) => {
+            panic!("Multiple manifests were selected, but only one is allowed.")
+        }
+        _ => selected
+            .into_iter()
+            .map(|manifest| Manifest {
+                href: manifest.href.clone(),
+                target: manifest.target.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// Panic if two declarations write the same file, or are for the same target.
+fn check_unique<'a>(source: &str, files: impl IntoIterator<Item = &'a str>) {
+    let mut seen = BTreeSet::new();
+    for file in files {
+        if !seen.insert(file) {
+            panic!("{source} declares {file} more than once.");
+        }
+    }
+}
@@ -0,0 +1,85 @@
+//! Checking `wasm-fn` attributes against the entry points in the wasm.
+//!
+//! Functions marked with `#[wext_entry(..)]` record their name in the `wext_entries`
+//! custom section of the wasm. A `wasm-fn` in index.html that isn't one of them would
+//! otherwise only fail at runtime, when the page or script loads.
+
+use std::{fs, path::Path};
+
+/// Name of the custom section written by `#[wext_entry]`.
+const SECTION: &str = "wext_entries";
+
+/// Check every entry function bound to the staged `wasm_file` exists, if the wasm
+/// records entries.
+pub fn check_entries<'a>(
+    staging_dir: &str,
+    wasm_file: &str,
+    wasm_fns: impl IntoIterator<Item = &'a str>,
+) {
+    let Some(wasm) = staged_wasm(staging_dir, wasm_file) else {
+        return;
+    };
+    let Some(entries) = custom_section(&wasm, SECTION) else {
+        return;
+    };
+    let entries = String::from_utf8_lossy(entries);
+    let entries: Vec<&str> = entries.lines().collect();
+
+    let missing: Vec<&str> = wasm_fns
+        .into_iter()
+        .filter(|wasm_fn| !entries.contains(wasm_fn))
+        .collect();
+    if !missing.is_empty() {
+        panic!(
+            "index.html or the registry uses wasm-fn(s) that aren't #[wext_entry] functions in {wasm_file}: {}\nAvailable entry points: {}",
+            missing.join(", "),
+            entries.join(", ")
+        );
+    }
+}
+
+/// The contents of `wasm_file` in the staging directory, if it's there.
+pub fn staged_wasm(staging_dir: &str, wasm_file: &str) -> Option<Vec<u8>> {
+    fs::read(Path::new(staging_dir).join(wasm_file)).ok()
+}
+
+/// The payload of the custom section `name`, if present. Same-named sections are
+/// merged by the linker, so there's at most one.
+pub fn custom_section<'a>(wasm: &'a [u8], name: &str) -> Option<&'a [u8]> {
+    if wasm.get(..4)? != b"\0asm" {
+        return None;
+    }
+    let mut offset = 8;
+    while offset < wasm.len() {
+        let id = wasm[offset];
+        let (size, start) = leb128(wasm, offset + 1)?;
+        let end = start.checked_add(size)?;
+        if id == 0 {
+            let (name_len, name_start) = leb128(wasm, start)?;
+            let payload_start = name_start + name_len;
+            if wasm.get(name_start..payload_start)? == name.as_bytes() {
+                return wasm.get(payload_start..end);
+            }
+        }
+        offset = end;
+    }
+    None
+}
+
+/// Decode an unsigned LEB128 number, returning it and the offset after it.
+fn leb128(bytes: &[u8], mut offset: usize) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    let mut shift = 0;
+    loop {
+        let byte = *bytes.get(offset)?;
+        offset += 1;
+        value |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some((value, offset));
+        }
+        shift += 7;
+        if shift >= usize::BITS {
+            return None;
+        }
+    }
+}
@@ -0,0 +1,149 @@
+//! Self-hosted font subsetting.
+//!
+//! Extension pages can't load web fonts from a CDN under a strict CSP, and shipping
+//! whole font files bloats the package. Fonts configured under `[[fonts]]` in
+//! `wextrunk.toml` are subset with `pyftsubset` (from fonttools) to the configured
+//! unicode ranges plus every character used in `_locales`, written to the staging
+//! directory as woff2, and the staged CSS `@font-face` URLs pointing at the source
+//! files are rewritten to the subset files.
+
+use std::{collections::BTreeSet, fs, path::Path, process::Command};
+
+use crate::{
+    cache::{Cache, Fingerprint},
+    config::Font,
+    i18n::Locales,
+    report::FontReport,
+};
+
+/// Subset every configured font into the staging directory, and point the staged CSS
+/// at the results.
+pub fn subset_fonts(
+    fonts: &[Font],
+    locales: Option<&Locales>,
+    source_dir: &str,
+    staging_dir: &str,
+    cache: &Cache,
+) -> Vec<FontReport> {
+    let mut reports = Vec::new();
+    let mut rewrites = Vec::new();
+    for font in fonts {
+        let source = Path::new(source_dir).join(&font.file);
+        let output = Path::new(&font.file).with_extension("woff2");
+        let output = output.to_string_lossy().replace('\\', "/");
+        let staged = Path::new(staging_dir).join(&output);
+        fs::create_dir_all(staged.parent().unwrap()).unwrap();
+
+        let mut args = vec!["--flavor=woff2".to_string()];
+        if !font.unicodes.is_empty() {
+            args.push(format!("--unicodes={}", font.unicodes.join(",")));
+        }
+        if font.locale_text {
+            if let Some(text) = locales.map(locale_text).filter(|text| !text.is_empty()) {
+                args.push(format!("--text={text}"));
+            }
+        }
+        let fingerprint = Fingerprint::new().add_file(&source).add(args.join("\0"));
+        cache.run("fonts", fingerprint, &[&staged], || {
+            let result = Command::new("pyftsubset")
+                .arg(&source)
+                .arg(format!("--output-file={}", staged.display()))
+                .args(&args)
+                .output()
+                .expect("Failed to run pyftsubset. Is fonttools (with brotli) installed?");
+            if !result.status.success() {
+                panic!(
+                    "pyftsubset failed for {}:\n{}",
+                    font.file,
+                    String::from_utf8_lossy(&result.stderr)
+                );
+            }
+            true
+        });
+
+        reports.push(FontReport {
+            file: font.file.clone(),
+            output: output.clone(),
+            size_before: fs::metadata(&source).unwrap().len(),
+            size_after: fs::metadata(&staged).unwrap().len(),
+        });
+        rewrites.push((font.file.trim_start_matches('/').to_string(), output));
+    }
+
+    for entry in fs::read_dir(staging_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.extension().is_some_and(|ext| ext == "css") {
+            let css = fs::read_to_string(&path).unwrap();
+            warn_remote_fonts(&path, &css);
+            let rewritten = rewrite_font_urls(&css, &rewrites);
+            if rewritten != css {
+                fs::write(&path, rewritten).unwrap();
+            }
+        }
+    }
+
+    reports
+}
+
+/// Every distinct character used by any message in any locale.
+fn locale_text(locales: &Locales) -> String {
+    let chars: BTreeSet<char> = locales
+        .messages
+        .values()
+        .flat_map(|messages| messages.values())
+        .filter_map(|entry| entry.get("message")?.as_str())
+        .flat_map(str::chars)
+        .filter(|c| !c.is_control())
+        .collect();
+    chars.into_iter().collect()
+}
+
+/// Replace `url(..)` references to source font files with their subset, along with
+/// the `format(..)` hint that follows them.
+fn rewrite_font_urls(css: &str, rewrites: &[(String, String)]) -> String {
+    let mut output = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(start) = rest.find("url(") {
+        output.push_str(&rest[..start]);
+        let after = &rest[start + 4..];
+        let Some(end) = after.find(')') else {
+            rest = &rest[start..];
+            break;
+        };
+        let url = after[..end].trim().trim_matches(['"', '\'']);
+        let Some((_, subset)) = rewrites
+            .iter()
+            .find(|(file, _)| url.trim_start_matches("./").trim_start_matches('/') == file)
+        else {
+            output.push_str(&rest[start..start + 4 + end + 1]);
+            rest = &after[end + 1..];
+            continue;
+        };
+        output.push_str(&format!("url(\"/{subset}\")"));
+        rest = &after[end + 1..];
+
+        // Fix up the format hint, since the subset is always woff2.
+        let trimmed = rest.trim_start();
+        if trimmed.starts_with("format(") {
+            if let Some(format_end) = trimmed.find(')') {
+                output.push_str(&rest[..rest.len() - trimmed.len()]);
+                output.push_str("format(\"woff2\")");
+                rest = &trimmed[format_end + 1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Web font CDNs are blocked by the default extension CSP, so point them out.
+fn warn_remote_fonts(path: &Path, css: &str) {
+    for host in ["fonts.googleapis.com", "fonts.gstatic.com"] {
+        if css.contains(host) {
+            println!(
+                "Warning: {} loads fonts from {host}, which extension pages can't do under the default CSP. Self-host them with [[fonts]] in wextrunk.toml instead.",
+                path.display()
+            );
+        }
+    }
+}
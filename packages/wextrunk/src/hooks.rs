@@ -0,0 +1,236 @@
+//! Checks of the Trunk hook setup.
+//!
+//! wextrunk and wextsplit are `post_build` hooks: they need the files Trunk stages,
+//! and wextrunk rewrites them, so wextsplit has to come first. A hook configured for
+//! another stage, or in the wrong order, used to fail far from the cause (e.g. an
+//! `unwrap` on a missing wasm). [`check_staging`] runs first thing, and explains what
+//! the staging directory says about the setup. `cargo wextrunk doctor`, run from the
+//! source directory, checks the whole setup without building: the hooks in
+//! `Trunk.toml`, the cargo aliases they call, `index.html`'s rust link,
+//! `wextrunk.toml`, and the tools the configuration needs.
+
+use std::{
+    fs,
+    path::Path,
+    process::{self, Command},
+};
+
+use crate::config::Config;
+
+/// The `[[hooks]]` entries wextrunk and wextsplit need, for the messages.
+const HOOKS_EXAMPLE: &str = "[[hooks]]
+stage = \"post_build\"
+command = \"cargo\"
+command_arguments = [\"wextsplit\"]
+
+[[hooks]]
+stage = \"post_build\"
+command = \"cargo\"
+command_arguments = [\"wextrunk\"]";
+
+/// Fail with an actionable message if the staging directory isn't what a
+/// `post_build` hook gets: Trunk's finished output, not yet processed by wextrunk.
+pub fn check_staging(staging_dir: &str) {
+    let staging = Path::new(staging_dir);
+    if !staging.is_dir() {
+        panic!("The staging directory {staging_dir} doesn't exist. wextrunk only runs as a Trunk hook; build with `trunk build` or `trunk serve`.");
+    }
+    let files = staged_files(staging);
+    if !staging.join("index.html").exists() {
+        if is_processed(staging) {
+            panic!(
+                "wextrunk already processed {staging_dir}. Is its hook listed twice in Trunk.toml?"
+            );
+        }
+        panic!("Trunk hasn't written index.html to {staging_dir} yet, so wextrunk ran too early. It must be a post_build hook; Trunk.toml needs:\n\n{HOOKS_EXAMPLE}");
+    }
+    if files.iter().any(|file| file.ends_with(".wasm.orig"))
+        && !files.iter().any(|file| file.ends_with(".wasm"))
+    {
+        panic!("wextsplit is still splitting the wasm in {staging_dir}. Its hook must finish before wextrunk's; list it first in Trunk.toml.");
+    }
+    if !files.iter().any(|file| file.ends_with(".wasm")) {
+        panic!("{staging_dir} has no wasm. Either index.html has no `<link data-trunk rel=\"rust\">`, or wextrunk ran before Trunk built it; it must be a post_build hook:\n\n{HOOKS_EXAMPLE}");
+    }
+}
+
+/// Whether wextrunk's output is in `staging`, for the one target or every one.
+fn is_processed(staging: &Path) -> bool {
+    staging.join("manifest.json").exists()
+        || fs::read_dir(staging)
+            .into_iter()
+            .flatten()
+            .any(|entry| entry.unwrap().path().join("manifest.json").exists())
+}
+
+fn staged_files(staging: &Path) -> Vec<String> {
+    fs::read_dir(staging)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+        .collect()
+}
+
+/// A `[[hooks]]` entry of Trunk.toml.
+#[derive(Debug)]
+struct Hook {
+    stage: String,
+    command: String,
+    arguments: Vec<String>,
+}
+
+impl Hook {
+    /// Whether the hook runs `tool`, through its cargo alias or directly.
+    fn runs(&self, tool: &str) -> bool {
+        self.command.contains(tool) || self.arguments.first().is_some_and(|arg| arg == tool)
+    }
+}
+
+/// Check the hook setup in `source_dir`, print what's wrong, and exit with an error
+/// if anything is.
+pub fn doctor(source_dir: &str) {
+    let source = Path::new(source_dir);
+    let mut problems = Vec::new();
+    let mut check = |ok: bool, passed: &str, problem: String| {
+        if ok {
+            println!("ok: {passed}");
+        } else {
+            println!("Problem: {problem}");
+            problems.push(problem);
+        }
+    };
+
+    let hooks = read_hooks(&source.join("Trunk.toml"));
+    let position = |tool: &str| hooks.iter().position(|hook| hook.runs(tool));
+    let wextrunk = position("wextrunk");
+    check(
+        wextrunk.is_some(),
+        "Trunk.toml runs wextrunk",
+        format!("Trunk.toml has no wextrunk hook; add:\n\n{HOOKS_EXAMPLE}"),
+    );
+    let wextsplit = position("wextsplit");
+    for (tool, index) in [("wextrunk", wextrunk), ("wextsplit", wextsplit)] {
+        let Some(hook) = index.map(|index| &hooks[index]) else {
+            continue;
+        };
+        check(
+            hook.stage == "post_build",
+            &format!("{tool} is a post_build hook"),
+            format!(
+                "{tool}'s hook runs at the {} stage, before Trunk has staged its output; set `stage = \"post_build\"`.",
+                hook.stage
+            ),
+        );
+        if hook.command == "cargo" {
+            check(
+                has_alias(source, tool),
+                &format!("`cargo {tool}` is aliased in .cargo/config.toml"),
+                format!(
+                    "The hook runs `cargo {tool}`, but .cargo/config.toml has no `{tool}` alias."
+                ),
+            );
+        }
+    }
+    if let (Some(wextrunk), Some(wextsplit)) = (wextrunk, wextsplit) {
+        check(
+            wextsplit < wextrunk,
+            "wextsplit runs before wextrunk",
+            "wextsplit's hook is listed after wextrunk's, which rewrites the wasm it splits; list it first.".to_string(),
+        );
+    }
+
+    let index = fs::read_to_string(source.join("index.html")).unwrap_or_default();
+    check(
+        index.contains("rel=\"rust\""),
+        "index.html has a rust link",
+        "index.html has no `<link data-trunk rel=\"rust\">`, so Trunk builds no wasm.".to_string(),
+    );
+
+    let config = Config::load(source_dir);
+    println!("ok: wextrunk.toml is valid");
+    let wasm_opt = config
+        .profiles
+        .values()
+        .flat_map(|profile| {
+            profile
+                .targets
+                .values()
+                .map(|target| &target.wasm_opt)
+                .chain([&profile.wasm_opt])
+        })
+        .chain(config.targets.values().map(|target| &target.wasm_opt))
+        .any(|flags| flags.as_ref().is_some_and(|flags| !flags.is_empty()));
+    let tools = [
+        ("wasm-split", wextsplit.is_some(), "the wextsplit hook"),
+        ("wasm-opt", wasm_opt, "the `wasm_opt` flags"),
+        ("pyftsubset", !config.fonts.is_empty(), "`[[fonts]]`"),
+    ];
+    for (tool, needed, reason) in tools {
+        if needed {
+            check(
+                is_installed(tool),
+                &format!("{tool} is installed"),
+                format!("{reason} needs {tool}, which isn't on the PATH."),
+            );
+        }
+    }
+    if !is_installed("oxipng") {
+        println!("Warning: oxipng isn't installed; release builds won't optimise PNGs.");
+    }
+
+    if !problems.is_empty() {
+        println!("{} problem(s) found.", problems.len());
+        process::exit(1);
+    }
+    println!("The hook setup looks right.");
+}
+
+fn read_hooks(path: &Path) -> Vec<Hook> {
+    let Ok(contents) = fs::read_to_string(path) else {
+        panic!(
+            "{} doesn't exist; run `cargo wextrunk doctor` from the directory with Trunk.toml.",
+            path.display()
+        );
+    };
+    let trunk: toml::Table = toml::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+    let field = |hook: &toml::Value, key: &str| {
+        hook.get(key)
+            .and_then(toml::Value::as_str)
+            .unwrap_or_default()
+            .to_string()
+    };
+    trunk
+        .get("hooks")
+        .and_then(toml::Value::as_array)
+        .into_iter()
+        .flatten()
+        .map(|hook| Hook {
+            stage: field(hook, "stage"),
+            command: field(hook, "command"),
+            arguments: hook
+                .get("command_arguments")
+                .and_then(toml::Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(|arg| arg.as_str().map(str::to_string))
+                .collect(),
+        })
+        .collect()
+}
+
+/// Whether `.cargo/config.toml` defines the cargo alias `tool`.
+fn has_alias(source: &Path, tool: &str) -> bool {
+    fs::read_to_string(source.join(".cargo/config.toml"))
+        .ok()
+        .and_then(|contents| contents.parse::<toml::Table>().ok())
+        .is_some_and(|config| {
+            config
+                .get("alias")
+                .and_then(|aliases| aliases.get(tool))
+                .is_some()
+        })
+}
+
+fn is_installed(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok()
+}
@@ -0,0 +1,464 @@
+//! `_locales` handling.
+//!
+//! If the source directory contains a locales directory (`_locales` by default), it's
+//! copied into the staging directory, and the manifest's user-visible strings are
+//! managed through it: `name`, `description` and `short_name` are rewritten to
+//! `__MSG_*__` references, seeded into the default locale if they're only given in the
+//! manifest, and checked to exist in every shipped locale, so store listings in every
+//! language come out complete.
+//!
+//! The catalog can also be kept in a single TOML file (`messages.toml` by default),
+//! one table per message with its translations keyed by locale, from which the
+//! `_locales/<locale>/messages.json` tree is generated (see [`Locales::load_file`]).
+//!
+//! Message keys used from Rust through `t!("key")` are extracted from the sources and
+//! checked against the catalog as well (see [`Locales::check_usages`]), since a
+//! runtime-only lookup would silently render an empty string for a broken key.
+//!
+//! For development, every message can also be pseudo-localized (see
+//! [`Locales::pseudo_localize`]).
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde_json::{Map, Value};
+
+use crate::config::I18n;
+
+/// Manifest fields managed through the message catalog, and their message keys.
+/// The key names follow the Chrome Web Store's documented convention.
+const MANIFEST_MESSAGES: &[(&str, &str)] = &[
+    ("name", "extName"),
+    ("short_name", "extShortName"),
+    ("description", "extDescription"),
+];
+
+/// The contents of every `_locales/<locale>/messages.json`, keyed by locale.
+#[derive(Debug)]
+pub struct Locales {
+    pub default_locale: String,
+    pub messages: BTreeMap<String, Map<String, Value>>,
+}
+
+impl Locales {
+    /// Read every locale from the source directory, if there's a messages file or a
+    /// locales directory.
+    pub fn load(config: &I18n, source_dir: &str) -> Option<Self> {
+        let file = Path::new(source_dir).join(&config.messages_file);
+        let dir = Path::new(source_dir).join(&config.locales_dir);
+        let messages = if file.is_file() {
+            if dir.is_dir() {
+                panic!(
+                    "Both {} and {} exist; keep the catalog in one of them.",
+                    config.messages_file, config.locales_dir
+                );
+            }
+            Self::load_file(&file)
+        } else if dir.is_dir() {
+            Self::load_dir(&dir)
+        } else {
+            return None;
+        };
+
+        if !messages.contains_key(&config.default_locale) {
+            panic!(
+                "The default locale {:?} has no messages in {}.",
+                config.default_locale,
+                if file.is_file() {
+                    config.messages_file.clone()
+                } else {
+                    format!(
+                        "{}/{}/messages.json",
+                        config.locales_dir, config.default_locale
+                    )
+                }
+            );
+        }
+
+        Some(Locales {
+            default_locale: config.default_locale.clone(),
+            messages,
+        })
+    }
+
+    /// Read a single-file catalog, like:
+    ///
+    /// ```toml
+    /// [itemCount]
+    /// description = "Shown above the list."
+    /// placeholders = { count = { content = "$1", example = "3" } }
+    /// en = "$count$ items"
+    /// fr = "$count$ éléments"
+    /// ```
+    ///
+    /// Every key other than `description` and `placeholders` is a locale. Those two
+    /// are shared by every locale's entry.
+    fn load_file(path: &Path) -> BTreeMap<String, Map<String, Value>> {
+        let contents = fs::read_to_string(path).unwrap();
+        let catalog: Map<String, Value> = toml::from_str(&contents)
+            .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+
+        let mut messages: BTreeMap<String, Map<String, Value>> = BTreeMap::new();
+        for (key, translations) in catalog {
+            let Value::Object(translations) = translations else {
+                panic!("Message {key:?} in {} must be a table.", path.display());
+            };
+            let shared: Vec<_> = ["description", "placeholders"]
+                .into_iter()
+                .filter_map(|field| Some((field, translations.get(field)?.clone())))
+                .collect();
+            for (locale, text) in &translations {
+                if shared.iter().any(|(field, _)| field == locale) {
+                    continue;
+                }
+                let Value::String(text) = text else {
+                    panic!(
+                        "The {locale:?} translation of {key:?} in {} must be a string.",
+                        path.display()
+                    );
+                };
+                let mut entry = Map::new();
+                entry.insert("message".to_string(), Value::String(text.clone()));
+                for (field, value) in &shared {
+                    entry.insert(field.to_string(), value.clone());
+                }
+                messages
+                    .entry(locale.clone())
+                    .or_default()
+                    .insert(key.clone(), Value::Object(entry));
+            }
+        }
+        messages
+    }
+
+    /// Read every `<locale>/messages.json` in a locales directory.
+    fn load_dir(dir: &Path) -> BTreeMap<String, Map<String, Value>> {
+        let mut messages = BTreeMap::new();
+        for entry in fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path().join("messages.json");
+            if !path.is_file() {
+                continue;
+            }
+            let locale = path
+                .parent()
+                .unwrap()
+                .file_name()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned();
+            let contents = fs::read_to_string(&path).unwrap();
+            let catalog = serde_json::from_str(&contents)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+            messages.insert(locale, catalog);
+        }
+        messages
+    }
+
+    /// Move the manifest's user-visible strings into the message catalog.
+    ///
+    /// Fields that are already `__MSG_*__` references are left alone. Literal values
+    /// are replaced by a reference; if the default locale doesn't define the message
+    /// yet, it's added there from the literal.
+    pub fn localize_manifest(&mut self, manifest: &mut Value) {
+        let manifest = manifest
+            .as_object_mut()
+            .expect("manifest must be an object");
+        manifest.insert(
+            "default_locale".to_string(),
+            Value::String(self.default_locale.clone()),
+        );
+
+        let default_messages = self.messages.get_mut(&self.default_locale).unwrap();
+        for (field, key) in MANIFEST_MESSAGES {
+            let Some(Value::String(value)) = manifest.get(*field) else {
+                continue;
+            };
+            if value.starts_with("__MSG_") {
+                continue;
+            }
+            match default_messages.get(*key).and_then(message_text) {
+                Some(message) if message != value => println!(
+                    "Manifest {field} {value:?} is superseded by {key} {message:?} from the {:?} locale.",
+                    self.default_locale
+                ),
+                Some(_) => {}
+                None => {
+                    let mut entry = Map::new();
+                    entry.insert("message".to_string(), Value::String(value.clone()));
+                    default_messages.insert(key.to_string(), Value::Object(entry));
+                }
+            }
+            manifest.insert(field.to_string(), Value::String(format!("__MSG_{key}__")));
+        }
+    }
+
+    /// Check that every locale defines every message the manifest references.
+    pub fn validate_manifest(&self, manifest: &Value) {
+        let mut missing = Vec::new();
+        for (field, _) in MANIFEST_MESSAGES {
+            let Some(key) = manifest
+                .get(*field)
+                .and_then(Value::as_str)
+                .and_then(|value| value.strip_prefix("__MSG_"))
+                .and_then(|value| value.strip_suffix("__"))
+            else {
+                continue;
+            };
+            for (locale, messages) in &self.messages {
+                if find_message(messages, key).and_then(message_text).is_none() {
+                    missing.push(format!("{locale}: {key} (for manifest {field})"));
+                }
+            }
+        }
+        if !missing.is_empty() {
+            panic!(
+                "Some locales are missing messages used by the manifest:\n  {}",
+                missing.join("\n  ")
+            );
+        }
+    }
+
+    /// Check that every `t!` key exists in the catalog.
+    ///
+    /// Keys missing from the default locale are an error, since there is nothing for
+    /// the browser to fall back to. Keys missing from other locales fall back to the
+    /// default locale, so they're only reported. Either way, stub entries for the
+    /// missing keys are written to the configured stubs file, if any.
+    pub fn check_usages(
+        &self,
+        usages: &BTreeMap<String, Vec<String>>,
+        config: &I18n,
+        source_dir: &str,
+    ) {
+        let mut stubs = Map::new();
+        for (locale, messages) in &self.messages {
+            let mut locale_stubs = Map::new();
+            for (key, locations) in usages {
+                if find_message(messages, key).is_some() {
+                    continue;
+                }
+                let default_text = find_message(&self.messages[&self.default_locale], key)
+                    .and_then(message_text)
+                    .unwrap_or_default();
+                let mut entry = Map::new();
+                entry.insert(
+                    "message".to_string(),
+                    Value::String(default_text.to_string()),
+                );
+                entry.insert(
+                    "description".to_string(),
+                    Value::String(format!("Used at {}", locations.join(", "))),
+                );
+                locale_stubs.insert(key.clone(), Value::Object(entry));
+            }
+            if !locale_stubs.is_empty() {
+                stubs.insert(locale.clone(), Value::Object(locale_stubs));
+            }
+        }
+
+        if let Some(stubs_file) = &config.stubs_file {
+            let path = Path::new(source_dir).join(stubs_file);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent).unwrap();
+            }
+            let mut output = serde_json::to_string_pretty(&stubs).unwrap();
+            output.push('\n');
+            fs::write(&path, output).unwrap();
+            if !stubs.is_empty() {
+                println!(
+                    "Wrote stub entries for missing messages to {}.",
+                    path.display()
+                );
+            }
+        }
+
+        for (locale, missing) in &stubs {
+            if *locale == self.default_locale {
+                continue;
+            }
+            let keys: Vec<_> = missing.as_object().unwrap().keys().cloned().collect();
+            println!(
+                "Locale {locale:?} is missing {} message(s), falling back to {:?}: {}",
+                keys.len(),
+                self.default_locale,
+                keys.join(", ")
+            );
+        }
+        if let Some(missing) = stubs.get(&self.default_locale) {
+            let missing: Vec<_> = missing
+                .as_object()
+                .unwrap()
+                .iter()
+                .map(|(key, entry)| format!("{key} ({})", entry["description"].as_str().unwrap()))
+                .collect();
+            panic!(
+                "The default locale {:?} is missing messages used by t!():\n  {}",
+                self.default_locale,
+                missing.join("\n  ")
+            );
+        }
+    }
+
+    /// Replace every message in every locale with pseudo-localized text.
+    ///
+    /// Whichever locale the browser picks, localized strings come out accented,
+    /// padded by roughly 40% and wrapped in brackets, so truncation and layout issues
+    /// show up early, and any hard-coded (unlocalized) text in the views stands out.
+    /// The manifest messages aren't padded, since browsers enforce length limits on
+    /// them.
+    pub fn pseudo_localize(&mut self) {
+        for messages in self.messages.values_mut() {
+            for (key, entry) in messages.iter_mut() {
+                let Some(Value::String(message)) = entry.get_mut("message") else {
+                    continue;
+                };
+                let pad = !MANIFEST_MESSAGES.iter().any(|(_, name)| name == key);
+                *message = pseudo(message, pad);
+            }
+        }
+    }
+
+    /// Write every locale to the staging directory. Browsers only look in `_locales`,
+    /// wherever the source catalog lives.
+    pub fn write(&self, staging_dir: &str) {
+        let staging_locales = Path::new(staging_dir).join("_locales");
+        for (locale, messages) in &self.messages {
+            let locale_dir = staging_locales.join(locale);
+            fs::create_dir_all(&locale_dir).unwrap();
+            let mut output = serde_json::to_string_pretty(messages).unwrap();
+            output.push('\n');
+            fs::write(locale_dir.join("messages.json"), output).unwrap();
+        }
+    }
+}
+
+/// Find `key` in a catalog. Message names are case-insensitive.
+fn find_message<'a>(messages: &'a Map<String, Value>, key: &str) -> Option<&'a Value> {
+    messages
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, entry)| entry)
+}
+
+/// Collect every `t!("key")` usage in the configured source directories, as a map of
+/// key to `file:line` locations.
+///
+/// This is a plain text scan rather than a parse, so it only picks up keys given as a
+/// string literal directly inside `t!(..)`, and ignores `//` comment lines.
+pub fn scan_usages(config: &I18n, source_dir: &str) -> BTreeMap<String, Vec<String>> {
+    let mut files = Vec::new();
+    for dir in &config.sources {
+        collect_rust_files(&Path::new(source_dir).join(dir), &mut files);
+    }
+    files.sort();
+
+    let mut usages: BTreeMap<String, Vec<String>> = BTreeMap::new();
+    for file in files {
+        let contents = fs::read_to_string(&file).unwrap();
+        let relative = file
+            .strip_prefix(source_dir)
+            .unwrap_or(&file)
+            .display()
+            .to_string();
+        for (number, line) in contents.lines().enumerate() {
+            if line.trim_start().starts_with("//") {
+                continue;
+            }
+            let mut rest = line;
+            while let Some(index) = rest.find("t!(") {
+                let preceded_by_ident = rest[..index]
+                    .chars()
+                    .next_back()
+                    .is_some_and(|c| c.is_alphanumeric() || c == '_');
+                rest = &rest[index + 3..];
+                if preceded_by_ident {
+                    continue;
+                }
+                let Some(literal) = rest.trim_start().strip_prefix('"') else {
+                    continue;
+                };
+                let Some(end) = literal.find('"') else {
+                    continue;
+                };
+                usages
+                    .entry(literal[..end].to_string())
+                    .or_default()
+                    .push(format!("{relative}:{}", number + 1));
+            }
+        }
+    }
+    usages
+}
+
+fn collect_rust_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_rust_files(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// The `message` of a messages.json entry.
+fn message_text(entry: &Value) -> Option<&str> {
+    entry.get("message")?.as_str()
+}
+
+/// Pseudo-localize a single message, keeping `$placeholder$`, `$1` and `$$`
+/// substitutions intact.
+fn pseudo(message: &str, pad: bool) -> String {
+    let mut output = String::from("[");
+    let mut letters = 0usize;
+    let mut chars = message.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            if c.is_alphabetic() {
+                letters += 1;
+            }
+            output.push(accent(c));
+            continue;
+        }
+        output.push('$');
+        match chars.peek() {
+            Some('$') | Some('0'..='9') => output.push(chars.next().unwrap()),
+            Some(_) => {
+                // Copy a `$name$` placeholder reference verbatim.
+                let name: String = chars
+                    .clone()
+                    .take_while(|c| c.is_ascii_alphanumeric() || *c == '_' || *c == '@')
+                    .collect();
+                if chars.clone().nth(name.len()) == Some('$') {
+                    output.push_str(&name);
+                    output.push('$');
+                    chars.nth(name.len());
+                }
+            }
+            None => {}
+        }
+    }
+    if pad {
+        output.push(' ');
+        output.extend(std::iter::repeat_n('~', (letters * 2).div_ceil(5).max(1)));
+    }
+    output.push(']');
+    output
+}
+
+/// Map ASCII letters to an accented look-alike.
+fn accent(c: char) -> char {
+    const LOWER: &str = "åƀçđéƒĝĥîĵķļɱñöþǫŕšţûṽŵẋýž";
+    const UPPER: &str = "ÅƁÇĐÉƑĜĤÎĴĶĻṀÑÖÞǪŔŠŢÛṼŴẊÝŽ";
+    match c {
+        'a'..='z' => LOWER.chars().nth(c as usize - 'a' as usize).unwrap(),
+        'A'..='Z' => UPPER.chars().nth(c as usize - 'A' as usize).unwrap(),
+        _ => c,
+    }
+}
@@ -0,0 +1,131 @@
+//! Icon sets generated from a single source image.
+//!
+//! The source is declared with a `rel="icon"` link in index.html,
+//! `<link data-wextrunk rel="icon" href="icon.svg">`, or `[icon]` in `wextrunk.toml`.
+//! It can be a PNG (ideally 128 px or larger) or an SVG. wextrunk renders it at every
+//! size in `sizes` (16, 32, 48, 96 and 128 px by default) to `icons/icon-<size>.png`
+//! in the staging directory, and fills in the manifest's `icons` and, unless it has
+//! its own (e.g. `popup_icons`), `action.default_icon`. Images that aren't square are
+//! centred on a transparent background. Text in SVGs isn't rendered; convert it to
+//! paths first.
+
+use std::{fs, path::Path};
+
+use image::{imageops::FilterType, DynamicImage, RgbaImage};
+use resvg::{tiny_skia, usvg};
+use serde_json::{json, Value};
+
+use crate::{
+    cache::{Cache, Fingerprint},
+    config::Icon,
+    registry::fill,
+};
+
+/// Where the generated icons go, relative to the staging directory.
+const ICON_DIR: &str = "icons";
+
+/// The source image.
+enum Source {
+    Raster(DynamicImage),
+    Vector(Box<usvg::Tree>),
+}
+
+impl Source {
+    fn load(file: &str, data: &[u8]) -> Self {
+        if file.ends_with(".svg") {
+            let tree = usvg::Tree::from_data(data, &usvg::Options::default())
+                .unwrap_or_else(|e| panic!("Failed to parse the icon {file}: {e}"));
+            Source::Vector(Box::new(tree))
+        } else {
+            let image = image::load_from_memory(data)
+                .unwrap_or_else(|e| panic!("Failed to decode the icon {file}: {e}"));
+            Source::Raster(image)
+        }
+    }
+}
+
+/// Render the icon at every size, and point the manifest at the results. Sizes
+/// rendered from the same source before come from the cache, and the source is only
+/// decoded if one doesn't.
+pub fn write_icons(
+    icon: &Icon,
+    manifest: &mut Value,
+    source_dir: &str,
+    staging_dir: &str,
+    cache: &Cache,
+) {
+    let file = &icon.source;
+    let data = fs::read(Path::new(source_dir).join(file))
+        .unwrap_or_else(|e| panic!("Failed to read the icon {file}: {e}"));
+    let mut source = None;
+    fs::create_dir_all(Path::new(staging_dir).join(ICON_DIR)).unwrap();
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    let has_action_icon = manifest
+        .get("action")
+        .is_some_and(|action| action.get("default_icon").is_some());
+    for &size in &icon.sizes {
+        let file = format!("{ICON_DIR}/icon-{size}.png");
+        let path = Path::new(staging_dir).join(&file);
+        let fingerprint = Fingerprint::new()
+            .add(&data)
+            .add(&icon.source)
+            .add(size.to_le_bytes());
+        cache.run("icons", fingerprint, &[&path], || {
+            let rendered = match source.get_or_insert_with(|| Source::load(&icon.source, &data)) {
+                Source::Raster(image) => {
+                    if image.width().max(image.height()) < size {
+                        println!(
+                            "Warning: the icon {} is smaller than {size} px, so it's upscaled.",
+                            icon.source
+                        );
+                    }
+                    resize(image, size)
+                }
+                Source::Vector(svg) => render_svg(svg, size),
+            };
+            rendered
+                .save(&path)
+                .unwrap_or_else(|e| panic!("Failed to write {file}: {e}"));
+            true
+        });
+        let size = size.to_string();
+        fill(manifest, &["icons", &size], json!(file));
+        if !has_action_icon {
+            fill(manifest, &["action", "default_icon", &size], json!(file));
+        }
+    }
+}
+
+/// Scale an image to fit a `size` square, centred.
+fn resize(image: &DynamicImage, size: u32) -> RgbaImage {
+    let scaled = image.resize(size, size, FilterType::Lanczos3).to_rgba8();
+    let mut square = RgbaImage::new(size, size);
+    let x = (size - scaled.width()) / 2;
+    let y = (size - scaled.height()) / 2;
+    image::imageops::overlay(&mut square, &scaled, x.into(), y.into());
+    square
+}
+
+/// Render an SVG to fit a `size` square, centred.
+fn render_svg(svg: &usvg::Tree, size: u32) -> RgbaImage {
+    let (width, height) = (svg.size().width(), svg.size().height());
+    let scale = size as f32 / width.max(height);
+    let transform = tiny_skia::Transform::from_scale(scale, scale).post_translate(
+        (size as f32 - width * scale) / 2.0,
+        (size as f32 - height * scale) / 2.0,
+    );
+    let mut pixmap = tiny_skia::Pixmap::new(size, size).unwrap();
+    resvg::render(svg, transform, &mut pixmap.as_mut());
+    // tiny-skia's pixels are premultiplied.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    RgbaImage::from_raw(size, size, pixels).unwrap()
+}
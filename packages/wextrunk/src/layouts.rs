@@ -0,0 +1,54 @@
+//! Page layouts: the shell each HTML page is wrapped in.
+//!
+//! Every page is generated from index.html, but a page's `layout` (`layout="..."` on
+//! an `rel="htmlpage"` link, `layout = "..."` in `[[pages]]`, and for registry pages
+//! `popup` for the popup and `fullpage` for the rest) adds its own parts to it:
+//!
+//! - `<html data-layout="<name>">`, for the stylesheet to target;
+//! - the layout's `head`, e.g. a viewport `<meta>`;
+//! - the layout's `body_class` on `<body>`;
+//! - elements of index.html marked `data-wextrunk-layout="<name>"`, which other
+//!   layouts leave out.
+//!
+//! `popup` and `fullpage` are built in; `[layouts.<name>]` in `wextrunk.toml` replaces
+//! them or adds more, e.g. for devtools panels.
+
+use std::collections::BTreeMap;
+
+use crate::{config::Layout, page_kinds::PageKind};
+
+/// The layout of the popup.
+pub const POPUP: &str = "popup";
+/// The layout of other pages, unless they declare one.
+pub const FULLPAGE: &str = "fullpage";
+
+/// The layout of pages that don't declare one.
+pub fn default_for(kind: Option<&PageKind>) -> &'static str {
+    match kind {
+        Some(PageKind::Popup { .. }) => POPUP,
+        _ => FULLPAGE,
+    }
+}
+
+/// The layout called `name`, from the config or built in.
+pub fn resolve(name: &str, layouts: &BTreeMap<String, Layout>) -> Layout {
+    if let Some(layout) = layouts.get(name) {
+        return layout.clone();
+    }
+    match name {
+        POPUP => Layout::default(),
+        FULLPAGE => Layout {
+            head: r#"<meta name="viewport" content="width=device-width, initial-scale=1">"#
+                .to_string(),
+            body_class: String::new(),
+        },
+        _ => {
+            let mut known: Vec<&str> = layouts.keys().map(String::as_str).collect();
+            known.extend([POPUP, FULLPAGE]);
+            panic!(
+                "Unknown page layout {name:?}; the layouts are {}. Add it as [layouts.{name}] in wextrunk.toml.",
+                known.join(", ")
+            )
+        }
+    }
+}
@@ -2,21 +2,89 @@
 //! by Trunk, and post-process it such that it can be used in a WebExtension.
 //!
 //! The main functions of this script are to:
+//! - Optionally strip Trunk's content hashes from the output file names, recording them
+//!   in `asset-manifest.json`.
+//! - Merge the pages, scripts, content scripts and manifests declared in `wextrunk.toml`
+//!   with the links in index.html, which override them.
+//! - With `WEXTRUNK_TARGET=all`, build every declared target into its own directory in
+//!   one pass.
 //! - Split the index.html file into multiple endpoints, so it can be use in various
 //!   WebExtension contexts (e.g. popup, background, content script, options page).
+//! - For content scripts, write a loader that imports the wasm from the extension's
+//!   origin, and add their `content_scripts` and `web_accessible_resources` entries to
+//!   the manifest.
 //! - Move the inline script into a separate "shim" file, as WebExtensions don't allow inline
 //!   scripts.
 //! - Remove preloads, as they're incompatible with WebExtensions.
 //! - Remove integrity attributes, as they're incompatible with WebExtensions.
 //! - For background scripts, wrap Trunk's output in an async IIFE, as top-level await is not
 //!   allowed in service workers, as used in background scripts.
+//! - Run the background as a service worker, an event page or a persistent page per
+//!   target, and point the manifest's `background` at it accordingly.
+//! - For background scripts, buffer the events that arrive while the wasm loads, and replay
+//!   them into the listeners it registers.
+//! - For background scripts declared with `keep-alive`, keep the service worker from being
+//!   stopped when idle.
+//! - Add the pages and scripts declared in the Rust context registry, and point the
+//!   manifest's popup, options page and background at them.
+//! - Check every `wasm-fn` against the `#[wext_entry]` functions in the wasm.
+//! - Bind pages and scripts to other wasm binaries Trunk builds, with a shim per binary.
+//! - Optionally run wasm-opt with per-target/per-profile flags.
+//! - Cache the outputs of wasm-opt, icon rendering, font subsetting and PNG optimisation
+//!   by fingerprints of their inputs, so rebuilds skip the ones that didn't change.
+//! - Copy `_locales`, or generate it from `messages.toml`, and manage the manifest's name
+//!   and description through it.
+//! - Subset self-hosted fonts and rewrite the CSS `@font-face` URLs to them.
+//! - For release builds, optimise copied PNG and SVG assets.
+//! - For release builds, fail if a generated page, script or stylesheet references a
+//!   remote URL other than the declared API endpoints.
+//! - For release builds (or with `WEXTRUNK_PACKAGE=1`), zip the extension for the
+//!   stores, deterministically.
+//! - For review builds, package the extension and sources, and write the license
+//!   bundle, permission audit and review notes reviewers need.
+//! - Check that every `t!("key")` used in the Rust sources exists in the catalog.
+//! - Build the manifest from a shared base and the target's overlay, filling in
+//!   `{{placeholders}}` from `Cargo.toml`.
+//! - Set the manifest's version from the crate's, with an optional build number, and
+//!   check it's one stores accept.
+//! - Apply per-profile overrides from `wextrunk.toml` to the output manifest.
+//! - Build the Content Security Policy from the extension's contents and declared needs,
+//!   letting the auto-reload script reach the dev server under `trunk serve`. For release
+//!   builds, strip dev server origins, and fail if it allows unsafe sources.
+//! - Warn about inline scripts, event handlers and `javascript:` URLs in the pages,
+//!   which the Content Security Policy blocks.
+//! - Convert the manifest to MV2 for targets configured with `manifest_version = 2`.
+//! - Fail on unknown manifest keys and permissions, keys from the wrong manifest
+//!   version, missing required keys and malformed match patterns.
+//! - Fail if the extension binds browser APIs its minimum browser versions lack.
+//! - Warn about browser APIs the sources call without their permission, and permissions
+//!   nothing uses.
+//! - For release builds, fail if the manifest adds permissions since the last release.
+//! - Write `build-info.json` with per-profile settings for the runtime.
+//! - Ship the release notes from `CHANGELOG.md` as `changelog.json`.
+//! - For report pages, link a print stylesheet with defaults for printing and PDF export.
+//! - Copy the static files matching `rel="assets"` globs, optionally web accessible.
+//! - Render the icon set from a single source image, and reference it in the manifest.
+//! - Collect the SVG icons into a sprite, written as an asset or inlined in every page.
+//! - For HTML pages, apply their layout's head, body class and layout-specific elements.
+//! - For HTML pages, show their skeleton markup until the entry function returns.
+//! - For HTML pages, set the document direction and language from the UI locale.
+//! - Keep Trunk's `data-initializer` hook in every shim, so pages can show the wasm's
+//!   loading progress.
+//! - For HTML pages, show an error panel with a reload button if the wasm fails to start,
+//!   instead of a blank page.
 //! - For automatic reloading, substitutes the dev server variables in the auto-reload script,
 //!   so they don't need to be run through the `trunk serve` web server.
 //!
+//! Before anything else, it checks that it runs as a `post_build` hook, after wextsplit;
+//! `cargo wextrunk doctor` checks the whole hook setup without building.
+//!
+//! `cargo wextrunk new-page <name>` adds a page: its entry file, registry entry, index.html
+//! title and, for sidebars, manifest entries.
+//!
 //! There's also functionality to remove reload functionality from scripts on a per-page and
 //! per-script basis.
 
-use core::panic;
 use std::{
     env,
     fs::{self, File},
@@ -27,6 +95,83 @@ use std::{
 
 use lol_html::{element, html_content::ContentType, text, HtmlRewriter, Settings};
 
+use api_permissions::lint_permissions;
+use artifacts::Artifacts;
+use assets::optimize_assets;
+use background::{fill_background, BackgroundMode};
+use build_info::write_build_info;
+use cache::Cache;
+use changelog::write_changelog;
+use compat::check_compat;
+use config::{default_icon_sizes, Config, CopyAssets, Icon, Layout};
+use content_scripts::{add_content_scripts, split_patterns, write_content_script, ContentScript};
+use copy_assets::copy_assets;
+use csp::{build_csp, check_inline_code};
+use declarations::{add_declarations, select_manifests};
+use entries::check_entries;
+use fonts::subset_fonts;
+use hooks::{check_staging, doctor};
+use i18n::{scan_usages, Locales};
+use icons::write_icons;
+use manifest::{apply_overrides, read_manifest, write_manifest, Manifest, ManifestLink};
+use mv2::convert_to_mv2;
+use package::write_package;
+use page_kinds::{fill_pages, parse_icons, PageKind};
+use permissions::check_permissions;
+use print::write_print_stylesheet;
+use registry::{add_contexts, fill_manifest, read_protocol, read_registry, Context};
+use remote::check_remote_free;
+use report::BuildReport;
+use review::write_review;
+use scaffold::new_page;
+use schema::validate_manifest;
+use skeleton::{is_for_page, load_skeleton, REMOVE_SKELETONS, SKELETON_ATTRIBUTE};
+use sprite::{build_sprite, write_sprite};
+use stable_names::stabilize_names;
+use targets::{remove_shared, stage_target, ALL_TARGETS};
+use trunk_script::{TrunkScript, TRUNK_ADDRESS};
+use version::sync_version;
+use wasm_opt::run_wasm_opt;
+
+mod api_permissions;
+mod artifacts;
+mod assets;
+mod background;
+mod build_info;
+mod cache;
+mod changelog;
+mod compat;
+mod config;
+mod content_scripts;
+mod copy_assets;
+mod csp;
+mod declarations;
+mod entries;
+mod fonts;
+mod hooks;
+mod i18n;
+mod icons;
+mod layouts;
+mod manifest;
+mod mv2;
+mod package;
+mod page_kinds;
+mod permissions;
+mod print;
+mod registry;
+mod remote;
+mod report;
+mod review;
+mod scaffold;
+mod schema;
+mod skeleton;
+mod sprite;
+mod stable_names;
+mod targets;
+mod trunk_script;
+mod version;
+mod wasm_opt;
+
 /// HTML page to output. Will more or less clone the output index.html file,
 /// but with a changed name, and the inline script moved elsewhere.
 #[derive(Debug)]
@@ -35,6 +180,14 @@ struct HtmlPage {
     html: String,
     no_reload: bool,
     wasm_fn: String,
+    /// The wasm artifact it binds to, if not Trunk's main one.
+    wasm: Option<String>,
+    /// Whether the page is meant for printing, and gets the print stylesheet.
+    report: bool,
+    /// Set if this is the popup or the options page.
+    kind: Option<PageKind>,
+    /// The name of its layout, see [`layouts`].
+    layout: String,
 }
 
 /// Script to output. Will basically just be what's normally in the inline script.
@@ -44,14 +197,15 @@ struct Script {
     js: String,
     no_reload: bool,
     background_script: bool,
+    /// Whether the background script keeps its service worker from being stopped.
+    keep_alive: bool,
+    /// How the background script runs, per target, see [`background`].
+    mode: Option<String>,
     wasm_fn: String,
-}
-
-/// Manifest file to output. Will be copied from the source directory to the
-/// staging directory.
-#[derive(Debug)]
-struct Manifest {
-    href: String,
+    /// The wasm artifact it binds to, if not Trunk's main one.
+    wasm: Option<String>,
+    /// Whether this is the shim of an HTML page, rather than a standalone script.
+    html_page: bool,
 }
 
 /// Results of processing the index.html file. This should contain everything
@@ -60,19 +214,27 @@ struct Manifest {
 struct CollectOutput {
     html_pages: Vec<HtmlPage>,
     scripts: Vec<Script>,
-    manifest: Manifest,
+    content_scripts: Vec<ContentScript>,
+    manifests: Vec<ManifestLink>,
+    /// From a `rel="icon"` link.
+    icon: Option<Icon>,
+    /// From `rel="assets"` links.
+    copies: Vec<CopyAssets>,
     html_template: String,
     script_contents: String,
 }
 
 /// Create an HTML template from Trunk-generated index.html,
 /// collecting wextrunk-specific values along the way.
-fn process_index_html(html_path: &Path, target: Option<&str>) -> CollectOutput {
+fn process_index_html(html_path: &Path) -> CollectOutput {
     let mut html_pages = Vec::new();
     let mut scripts = Vec::new();
+    let mut content_scripts = Vec::new();
     let mut script_contents = String::new();
 
-    let mut selected_manifest: Option<Manifest> = None;
+    let mut manifests = Vec::new();
+    let mut icon = None;
+    let mut copies = Vec::new();
 
     let mut html_template_bytes = Vec::new();
     let mut rewriter = HtmlRewriter::new(
@@ -83,20 +245,38 @@ fn process_index_html(html_path: &Path, target: Option<&str>) -> CollectOutput {
                 element!("link[data-wextrunk]", |el| {
                     match el.get_attribute("rel").as_deref() {
                         Some("htmlpage") => {
+                            let html = el
+                                .get_attribute("html")
+                                .expect("htmlpage link must have an html field");
+                            let open_in_tab = el
+                                .get_attribute("open-in-tab")
+                                .map(|value| value != "false");
+                            let kind = PageKind::from_declaration(
+                                el.get_attribute("kind").as_deref(),
+                                open_in_tab,
+                                el.get_attribute("icons")
+                                    .map(|icons| parse_icons(&icons))
+                                    .unwrap_or_default(),
+                                &html,
+                            );
+                            let layout = el
+                                .get_attribute("layout")
+                                .unwrap_or_else(|| layouts::default_for(kind.as_ref()).to_string());
                             html_pages.push(HtmlPage {
                                 name: el
                                     .get_attribute("name")
                                     .expect("htmlpage link must have a name")
                                     .to_string(),
-                                html: el
-                                    .get_attribute("html")
-                                    .expect("htmlpage link must have an html field")
-                                    .to_string(),
+                                kind,
+                                html,
                                 no_reload: el.has_attribute("no-reload"),
                                 wasm_fn: el
                                     .get_attribute("wasm-fn")
                                     .expect("htmlpage link must have a wasm-fn field")
                                     .to_string(),
+                                wasm: el.get_attribute("wasm"),
+                                report: el.has_attribute("report"),
+                                layout,
                             });
                         }
                         Some("script") => {
@@ -107,39 +287,72 @@ fn process_index_html(html_path: &Path, target: Option<&str>) -> CollectOutput {
                                     .to_string(),
                                 no_reload: el.has_attribute("no-reload"),
                                 background_script: el.has_attribute("background-script"),
+                                keep_alive: el.has_attribute("keep-alive"),
+                                mode: el.get_attribute("mode"),
+                                html_page: false,
                                 wasm_fn: el
                                     .get_attribute("wasm-fn")
                                     .expect("script link must have a wasm-fn field")
                                     .to_string(),
+                                wasm: el.get_attribute("wasm"),
+                            });
+                        }
+                        Some("contentscript") => {
+                            content_scripts.push(ContentScript::new(
+                                el.get_attribute("js")
+                                    .expect("contentscript link must have a js field"),
+                                el.get_attribute("wasm-fn")
+                                    .expect("contentscript link must have a wasm-fn field"),
+                                split_patterns(
+                                    &el.get_attribute("matches")
+                                        .expect("contentscript link must have matches"),
+                                ),
+                                el.get_attribute("wasm"),
+                                el.get_attribute("run-at"),
+                                el.has_attribute("all-frames"),
+                            ));
+                        }
+                        Some("icon") => {
+                            icon = Some(Icon {
+                                source: el
+                                    .get_attribute("href")
+                                    .expect("icon link must have an href"),
+                                sizes: el
+                                    .get_attribute("sizes")
+                                    .map(|sizes| {
+                                        sizes
+                                            .split_whitespace()
+                                            .map(|size| {
+                                                size.parse().unwrap_or_else(|_| {
+                                                    panic!("Malformed icon size {size:?}")
+                                                })
+                                            })
+                                            .collect()
+                                    })
+                                    .unwrap_or_else(default_icon_sizes),
+                            });
+                        }
+                        Some("assets") => {
+                            copies.push(CopyAssets {
+                                glob: el
+                                    .get_attribute("glob")
+                                    .expect("assets link must have a glob"),
+                                web_accessible: el
+                                    .get_attribute("web-accessible")
+                                    .map(|matches| split_patterns(&matches))
+                                    .unwrap_or_default(),
                             });
                         }
                         Some("manifest") => {
-                            if let Some(requested_target) = target {
-                                let manifest_target = el
+                            manifests.push(ManifestLink {
+                                target: el
                                     .get_attribute("target")
-                                    .expect("manifest link must have an href");
-                                if manifest_target == requested_target {
-                                    if selected_manifest.is_some() {
-                                        panic!("Multiple manifests were selected, but only one is allowed.");
-                                    }
-                                    selected_manifest = Some(Manifest {
-                                        href: el
-                                            .get_attribute("href")
-                                            .expect("manifest link must have an href")
-                                            .to_string(),
-                                    });
-                                }
-                            } else if el.has_attribute("default") {
-                                if selected_manifest.is_some() {
-                                    panic!("Multiple default manifests were selected, but only one is allowed.");
-                                }
-                                selected_manifest = Some(Manifest {
-                                    href: el
-                                        .get_attribute("href")
-                                        .expect("manifest link must have an href")
-                                        .to_string(),
-                                });
-                            }
+                                    .expect("manifest link must have a target"),
+                                href: el
+                                    .get_attribute("href")
+                                    .expect("manifest link must have an href"),
+                                default: el.has_attribute("default"),
+                            });
                         }
                         _ => {}
                     }
@@ -184,10 +397,6 @@ fn process_index_html(html_path: &Path, target: Option<&str>) -> CollectOutput {
     }
     rewriter.end().unwrap();
 
-    let Some(manifest) = selected_manifest else {
-        panic!("No manifest was selected, but one is required. You must specify a manifest as default, or specify a target with the WEXTRUNK_TARGET environment variable.");
-    };
-
     let html_template = std::str::from_utf8(&html_template_bytes)
         .unwrap()
         .to_string();
@@ -195,7 +404,10 @@ fn process_index_html(html_path: &Path, target: Option<&str>) -> CollectOutput {
     CollectOutput {
         html_pages,
         scripts,
-        manifest,
+        content_scripts,
+        manifests,
+        icon,
+        copies,
         html_template,
         script_contents,
     }
@@ -204,7 +416,7 @@ fn process_index_html(html_path: &Path, target: Option<&str>) -> CollectOutput {
 /// Template used for the auto-reload script.
 /// This just splits the auto-reload script into hardcoded parts,
 /// where variables are interspersed between them.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct AutoReloadTemplate {
     /// Everything before the TRUNK_ADDRESS varable.
     before_address: String,
@@ -216,7 +428,6 @@ struct AutoReloadTemplate {
 
 impl AutoReloadTemplate {
     fn new(auto_reload_contents: &str) -> Self {
-        const TRUNK_ADDRESS: &str = "{{__TRUNK_ADDRESS__}}";
         let address_start = auto_reload_contents
             .find(TRUNK_ADDRESS)
             .expect("Should find address in auto-reload script output");
@@ -251,168 +462,337 @@ impl AutoReloadTemplate {
 /// however it's similar to the AutoReloadTemplate in that each
 /// parsed section is handled differently depending on how
 /// the ScriptTemplate is called.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 struct ScriptTemplate {
     /// Import init line.
     import_line: String,
     /// Everything before `dispatchEvent`. This is where we want to put wasm_fn's call.
     init: String,
-    /// DispatchEvent itself. We want to keep this separate from auto-reload code.
+    /// Whether init goes through Trunk's initializer (`data-initializer`).
+    initializer: bool,
+    /// DispatchEvent itself, if any. We want to keep this separate from auto-reload code.
     dispatch_event: String,
     /// Auto-reload code, if it exists. Otherwise, just an empty string.
     auto_reload: Option<AutoReloadTemplate>,
+    /// Path of the wasm-bindgen module, as imported.
+    module_path: String,
+    /// Path of the wasm, as passed to init().
+    wasm_path: String,
+    /// `messaging::PROTOCOL_VERSION` of the main wasm, for the init failure report.
+    protocol: Option<u32>,
 }
 
 impl ScriptTemplate {
-    fn find_dispatch_event(script_contents: &str, start_offset: usize) -> Option<(usize, usize)> {
-        let dispatch_event_start =
-            script_contents[start_offset..].find("\ndispatchEvent")? + start_offset + 1;
-        let dispatch_event_end =
-            script_contents[dispatch_event_start..].find(";\n")? + dispatch_event_start + 1;
-
-        Some((dispatch_event_start, dispatch_event_end))
-    }
-
     fn new(script_contents: &str) -> Self {
-        let import_start = script_contents
-            .find("import")
-            .expect("Should find import line in Trunk script output");
-        let import_end = script_contents[import_start..]
-            .find(";\n")
-            .expect("Should find end of import line in Trunk script output")
-            + import_start
-            + 1;
-        let (dispatch_event_start, dispatch_event_end) =
-            match Self::find_dispatch_event(&script_contents, import_end) {
-                Some((start, end)) => (start, end),
-                None => {
-                    let init_end = script_contents[import_end..]
-                        .find(".wasm');\n")
-                        .expect("Should find end of init line in Trunk script output")
-                        + import_end
-                        + 1;
-
-                    (init_end, init_end)
-                }
-            };
-
-        let import_line = format!("{}\n", &script_contents[import_start..import_end]);
-        let dispatch_event = script_contents[dispatch_event_start..dispatch_event_end].to_string();
-        let pre_init = fix_init_line(script_contents[import_end..dispatch_event_start].trim());
-        let auto_reload_contents = script_contents[dispatch_event_end..].to_string();
-        let auto_reload = if auto_reload_contents.contains("function") {
-            Some(AutoReloadTemplate::new(&auto_reload_contents))
-        } else {
-            None
-        };
-
+        let script = TrunkScript::parse(script_contents).unwrap_or_else(|problem| {
+            panic!("Trunk's script in index.html {problem}; wextrunk may not support this Trunk version.\n{script_contents}")
+        });
         ScriptTemplate {
-            import_line,
-            init: pre_init,
-            dispatch_event,
-            auto_reload,
+            import_line: script.import,
+            init: script.init,
+            initializer: script.initializer,
+            dispatch_event: script.dispatch_event.unwrap_or_default(),
+            auto_reload: script.auto_reload.as_deref().map(AutoReloadTemplate::new),
+            module_path: script.module_path,
+            wasm_path: script.wasm_path,
+            protocol: None,
         }
     }
 
+    /// The paths of the wasm-bindgen module and the wasm, relative to the extension
+    /// root, as imported and instantiated by Trunk's script.
+    fn module_paths(&self) -> (String, String) {
+        (
+            self.module_path.trim_start_matches('/').to_string(),
+            self.wasm_path.trim_start_matches('/').to_string(),
+        )
+    }
+
     /// Render to a writer, to reduce String clones.
     ///
-    /// Adds a wrapper depending on if we're writing to a background script or not.
-    fn render(&self, wasm_fn: &str, no_reload: bool, bg_wrapper: bool, writer: &mut impl Write) {
-        let address = env::var("TRUNK_SERVE_ADDRESS").unwrap_or_else(|_| "127.0.0.1".to_string());
-        let port = env::var("TRUNK_SERVE_PORT").unwrap_or_else(|_| "8080".to_string());
+    /// Adds a wrapper depending on if we're writing to a background script or not,
+    /// and in which `mode` it runs.
+    fn render(&self, script: &Script, mode: Option<BackgroundMode>, writer: &mut impl Write) {
         let ws_base = env::var("TRUNK_SERVE_WS_BASE").unwrap_or_else(|_| "/".to_string());
-        let address = format!("{address}:{port}");
+        let address = dev_server_address();
 
-        if bg_wrapper {
-            self.render_with_wrapper(wasm_fn, no_reload, &address, &ws_base, writer);
-        } else {
-            self.render_without_wrapper(wasm_fn, no_reload, &address, &ws_base, writer);
+        if script.keep_alive {
+            match mode {
+                None => panic!(
+                    "Script {} has keep-alive, but only background scripts can be kept alive",
+                    script.js
+                ),
+                Some(BackgroundMode::Persistent) => panic!(
+                    "Script {} has keep-alive, but persistent backgrounds are never stopped",
+                    script.js
+                ),
+                Some(_) => {}
+            }
+        }
+        match mode {
+            Some(mode) => self.render_background(script, mode, &address, &ws_base, writer),
+            None => self.render_without_wrapper(script, &address, &ws_base, writer),
         }
     }
 
     /// Render without a wrapper, for scripts that don't need to be background scripts.
     fn render_without_wrapper(
         &self,
-        wasm_fn: &str,
-        no_reload: bool,
+        script: &Script,
         address: &str,
         ws_base: &str,
         writer: &mut impl Write,
     ) {
         writer.write_all(self.import_line.as_bytes()).unwrap();
-        writer.write_all(self.init.as_bytes()).unwrap();
-        let wasm_fn = format!("await wasm.{wasm_fn}();\n");
+        if script.html_page {
+            writer.write_all(DOCUMENT_DIRECTION.as_bytes()).unwrap();
+            let protocol = self
+                .protocol
+                .map_or_else(|| "null".to_string(), |protocol| protocol.to_string());
+            let init_failure = INIT_FAILURE.replace("{{PROTOCOL_VERSION}}", &protocol);
+            writer.write_all(init_failure.as_bytes()).unwrap();
+            writer.write_all("try {\n".as_bytes()).unwrap();
+        }
+        self.render_init(writer);
+        let wasm_fn = format!("await wasm.{}();\n", script.wasm_fn);
         writer.write_all(wasm_fn.as_bytes()).unwrap();
+        if script.html_page {
+            writer.write_all(REMOVE_SKELETONS.as_bytes()).unwrap();
+        }
         writer.write_all(self.dispatch_event.as_bytes()).unwrap();
-        if !no_reload {
+        if script.html_page {
+            writer
+                .write_all("\n} catch (error) {\n  showInitFailure(error);\n}\n".as_bytes())
+                .unwrap();
+        }
+        if !script.no_reload {
             if let Some(auto_reload) = &self.auto_reload {
                 auto_reload.render(address, ws_base, writer);
             }
         }
     }
 
-    /// Render to a writer with a wrapper that allows using this as a
-    /// background service worker in Chrome.
-    fn render_with_wrapper(
+    /// Render the init part. Trunk's initializer reports a failed init to `onFailure`
+    /// and resolves with the error, so it's rethrown, for the failure handling here.
+    fn render_init(&self, writer: &mut impl Write) {
+        writer.write_all(self.init.as_bytes()).unwrap();
+        if self.initializer {
+            writer
+                .write_all("if (wasm instanceof Error) throw wasm;\n".as_bytes())
+                .unwrap();
+        }
+    }
+
+    /// Render a background script. As a service worker, it's wrapped in an async IIFE,
+    /// since service workers don't allow top-level await; event pages and persistent
+    /// pages are plain modules.
+    fn render_background(
         &self,
-        wasm_fn: &str,
-        no_reload: bool,
+        script: &Script,
+        mode: BackgroundMode,
         address: &str,
         ws_base: &str,
         writer: &mut impl Write,
     ) {
+        let wrap = mode == BackgroundMode::ServiceWorker;
         writer.write_all(self.import_line.as_bytes()).unwrap();
-        writer.write_all("(async () => {\n\n".as_bytes()).unwrap();
-        writer.write_all(self.init.as_bytes()).unwrap();
-        let wasm_fn = format!("await wasm.{wasm_fn}();\n");
+        if script.keep_alive {
+            writer.write_all(KEEP_ALIVE.as_bytes()).unwrap();
+        }
+        writer.write_all(EVENT_BUFFER.as_bytes()).unwrap();
+        if wrap {
+            writer.write_all("(async () => {\n\n".as_bytes()).unwrap();
+        }
+        self.render_init(writer);
+        let wasm_fn = format!("await wasm.{}();\nearlyEvents.drain();\n", script.wasm_fn);
         writer.write_all(wasm_fn.as_bytes()).unwrap();
         writer.write_all(self.dispatch_event.as_bytes()).unwrap();
-        if !no_reload {
+        if !script.no_reload {
             if let Some(auto_reload) = &self.auto_reload {
                 auto_reload.render(address, ws_base, writer);
             }
         }
-        writer.write_all("\n\n})();\n".as_bytes()).unwrap();
+        if wrap {
+            writer.write_all("\n\n})();\n".as_bytes()).unwrap();
+        }
     }
 }
 
-/// The init() call takes a string, when it should take an object with a key of `module_or_path`.
-/// This stops wasm-bindgen from complaining via console.warn.
-fn fix_init_line(input: &str) -> String {
-    input
-        .replace("init(", "init({module_or_path: ")
-        .replace(");", "});\n")
+/// Set the page's text direction and language from the browser UI locale before the
+/// wasm loads, so RTL locales (Arabic, Hebrew, ...) lay out correctly from the first
+/// paint. `@@bidi_dir` is predefined by the i18n API, with or without `_locales`.
+const DOCUMENT_DIRECTION: &str = "if (globalThis.chrome?.i18n) {
+  document.documentElement.dir = chrome.i18n.getMessage('@@bidi_dir') || 'ltr';
+  document.documentElement.lang = chrome.i18n.getUILanguage();
+}
+";
+
+/// The `trunk serve` address the auto-reload script connects to, e.g. `127.0.0.1:8080`.
+fn dev_server_address() -> String {
+    let address = env::var("TRUNK_SERVE_ADDRESS").unwrap_or_else(|_| "127.0.0.1".to_string());
+    let port = env::var("TRUNK_SERVE_PORT").unwrap_or_else(|_| "8080".to_string());
+    format!("{address}:{port}")
 }
 
-/// Write a script file (either a shim or background script) to the staging directory.
-fn write_script(script: Script, staging_dir: &str, script_template: &ScriptTemplate) {
-    let js_path = Path::new(staging_dir).join(script.js);
+/// Keeps the service worker from being stopped, for background scripts declared with
+/// `keep-alive`. Chrome stops it after 30 seconds without extension events or API
+/// calls; calling one every 20 seconds resets that timer (Chrome 110 and later). If
+/// it's stopped anyway (older versions, or an update), the alarm starts it again
+/// within 30 seconds; its events reach the wasm's `onAlarm` listeners, which ignore
+/// alarms they don't know. Needs the `alarms` permission for that part. Added before
+/// the event buffer, so the buffer's listeners are the first.
+const KEEP_ALIVE: &str = "(() => {
+  const api = globalThis.browser ?? globalThis.chrome;
+  setInterval(() => api.runtime.getPlatformInfo(), 20000);
+  api.alarms?.create('wext.keepAlive', { periodInMinutes: 0.5 });
+})();
+";
+
+/// Events that can wake the service worker, and would be lost if they fired while the
+/// wasm loads, before the Rust side registers its listeners. These are listened to
+/// synchronously on startup and queued. When the wasm adds its own listener to one of
+/// them, the queue is replayed into it, in order; message senders wait for the replayed
+/// listeners' response. Once the entry function returns, events nobody listens to are
+/// dropped (and their senders get an empty response).
+const EVENT_BUFFER: &str = "const earlyEvents = (() => {
+  const api = globalThis.browser ?? globalThis.chrome;
+  const buffered = [
+    ['runtime', 'onInstalled'], ['runtime', 'onStartup'],
+    ['runtime', 'onMessage'], ['runtime', 'onMessageExternal'],
+    ['alarms', 'onAlarm'], ['action', 'onClicked'], ['browserAction', 'onClicked'],
+    ['contextMenus', 'onClicked'], ['commands', 'onCommand'], ['notifications', 'onClicked'],
+  ];
+  // Messages are the only events whose last argument is a function: sendResponse.
+  const responder = (args) => typeof args[args.length - 1] === 'function' ? args[args.length - 1] : null;
+  const replay = (args, listeners) => {
+    const respond = responder(args);
+    let responding = false;
+    for (const listener of listeners) {
+      const result = listener(...args);
+      if (result === true) {
+        responding = true;
+      } else if (typeof result?.then === 'function') {
+        responding = true;
+        result.then((value) => respond?.(value), () => respond?.());
+      }
+    }
+    if (respond && !responding) respond();
+  };
+  const buffers = [];
+  for (const [namespace, name] of buffered) {
+    const event = api?.[namespace]?.[name];
+    if (!event) continue;
+    const queue = [];
+    const buffer = (...args) => {
+      queue.push(args);
+      // Keep the message channel open until the replayed listeners respond.
+      return responder(args) ? true : undefined;
+    };
+    const addListener = event.addListener;
+    const listeners = [];
+    const release = () => {
+      event.removeListener(buffer);
+      event.addListener = addListener;
+    };
+    event.addListener = (listener) => {
+      addListener.call(event, listener);
+      if (listeners.push(listener) > 1) return;
+      // Events are dispatched as tasks, so nothing arrives before this runs, and every
+      // listener registered in the same turn gets the replay.
+      queueMicrotask(() => {
+        release();
+        for (const args of queue.splice(0)) replay(args, listeners);
+      });
+    };
+    addListener.call(event, buffer);
+    buffers.push({ queue, listeners, release });
+  }
+  return {
+    drain() {
+      for (const { queue, listeners, release } of buffers) {
+        if (listeners.length > 0) continue;
+        release();
+        for (const args of queue.splice(0)) responder(args)?.();
+      }
+    },
+  };
+})();
+";
+
+/// Shown instead of a blank page when the wasm fails to load or its entry function
+/// throws, e.g. after a corrupted update or with a CSP that blocks wasm: a static panel
+/// with the error and a button that reloads the extension. The error is also reported
+/// to the background's health records, if it's running; the envelope mirrors
+/// `messaging::Envelope`, with the `PROTOCOL_VERSION` the main wasm records in place of
+/// `{{PROTOCOL_VERSION}}`, or `null` (no report) if it doesn't record one.
+const INIT_FAILURE: &str = "const showInitFailure = (error) => {
+  console.error('Failed to start the extension:', error);
+  const api = globalThis.browser ?? globalThis.chrome;
+  const message = String(error?.message ?? error);
+  const protocol = {{PROTOCOL_VERSION}};
+  try {
+    if (protocol !== null) {
+      api?.runtime?.sendMessage({
+        protocol,
+        name: 'wext.health.error',
+        body: { at: Date.now(), source: 'init', message: `${location.pathname}: ${message}` },
+      })?.catch?.(() => {});
+    }
+  } catch {}
+  const panel = document.createElement('div');
+  panel.setAttribute('role', 'alert');
+  panel.style.cssText = 'font: 14px system-ui, sans-serif; padding: 16px; max-width: 480px;';
+  const title = document.createElement('strong');
+  title.textContent = \"The extension couldn't start.\";
+  const details = document.createElement('pre');
+  details.style.cssText = 'white-space: pre-wrap; color: #b00020;';
+  details.textContent = message;
+  const reload = document.createElement('button');
+  reload.textContent = 'Reload extension';
+  reload.addEventListener('click', () => api?.runtime?.reload ? api.runtime.reload() : location.reload());
+  panel.append(title, details, reload);
+  document.body.replaceChildren(panel);
+};
+";
+
+/// Write a script file (either a shim or background script, running in `mode`) to the
+/// staging directory.
+fn write_script(
+    script: &Script,
+    mode: Option<BackgroundMode>,
+    staging_dir: &str,
+    script_template: &ScriptTemplate,
+) {
+    let js_path = Path::new(staging_dir).join(&script.js);
 
     let mut js_file = File::create(js_path).unwrap();
 
-    script_template.render(
-        &script.wasm_fn,
-        script.no_reload,
-        script.background_script,
-        &mut js_file,
-    );
+    script_template.render(script, mode, &mut js_file);
 }
 
 /// Write an HTML file to the staging directory.
 fn write_html_page(
-    page: HtmlPage,
+    page: &HtmlPage,
+    layout: &Layout,
+    skeleton: Option<&str>,
+    sprite: Option<&str>,
     staging_dir: &str,
     script_template: &ScriptTemplate,
     html_template: &str,
 ) {
     let js_path = format!("{}_shim.js", page.html.replace(".", "_"));
     write_script(
-        Script {
+        &Script {
             js: js_path.clone(),
             no_reload: page.no_reload,
             background_script: false,
+            keep_alive: false,
+            mode: None,
             wasm_fn: page.wasm_fn.clone(),
+            wasm: page.wasm.clone(),
+            html_page: true,
         },
+        None,
         staging_dir,
         script_template,
     );
@@ -438,7 +818,62 @@ fn write_html_page(
                 // good enough for the quick hack that this entire script is.
                 element!("script[nonce]", |el| {
                     el.remove_attribute("nonce");
-                    el.set_attribute("src", &format!("/{}", &js_path)).unwrap();
+                    el.set_attribute("src", &format!("/{}", js_path)).unwrap();
+                    Ok(())
+                }),
+                element!("html", |el| {
+                    el.set_attribute("data-layout", &page.layout).unwrap();
+                    Ok(())
+                }),
+                element!("body", |el| {
+                    if !layout.body_class.is_empty() {
+                        let class = match el.get_attribute("class") {
+                            Some(class) => format!("{class} {}", layout.body_class),
+                            None => layout.body_class.clone(),
+                        };
+                        el.set_attribute("class", &class).unwrap();
+                    }
+                    if let Some(skeleton) = skeleton {
+                        el.prepend(skeleton, ContentType::Html);
+                    }
+                    if let Some(sprite) = sprite {
+                        el.prepend(sprite, ContentType::Html);
+                    }
+                    Ok(())
+                }),
+                // Skeletons for other pages are left out.
+                element!("[data-wextrunk-skeleton]", |el| {
+                    let value = el.get_attribute("data-wextrunk-skeleton");
+                    if !is_for_page(value.as_deref(), &page.name) {
+                        el.remove();
+                    }
+                    el.remove_attribute("data-wextrunk-skeleton");
+                    el.set_attribute(SKELETON_ATTRIBUTE, "").unwrap();
+                    el.set_attribute("aria-busy", "true").unwrap();
+                    Ok(())
+                }),
+                // The layout's head, and the print stylesheet for report pages.
+                element!("head", |el| {
+                    el.append(&layout.head, ContentType::Html);
+                    if page.report {
+                        el.append(
+                            &format!(
+                                r#"<link rel="stylesheet" media="print" href="/{}">"#,
+                                print::STYLESHEET
+                            ),
+                            ContentType::Html,
+                        );
+                    }
+                    Ok(())
+                }),
+                // Elements for other layouts are left out.
+                element!("[data-wextrunk-layout]", |el| {
+                    if el.get_attribute("data-wextrunk-layout").as_deref()
+                        != Some(page.layout.as_str())
+                    {
+                        el.remove();
+                    }
+                    el.remove_attribute("data-wextrunk-layout");
                     Ok(())
                 }),
                 // If data-wextrunk-include is set to page.name, keep the element.
@@ -472,44 +907,345 @@ fn write_html_page(
     rewriter.end().unwrap();
 }
 
-/// Write out the manifest file. If any post-processing occurred, it would happen here.
-/// Perhaps it would be nice to have a manifest input that works for both Firefox and Chrome,
-/// but for now, just copy from source to staging.
-fn write_manifest(manifest: Manifest, source_dir: &str, staging_dir: &str) {
-    let source_manifest_path = Path::new(source_dir).join(&manifest.href);
-    let staging_manifest_path = Path::new(staging_dir).join("manifest.json");
-
-    std::fs::copy(source_manifest_path, staging_manifest_path).unwrap();
+/// Everything parsed from Trunk's output and the config, shared by every target.
+struct Build {
+    config: Config,
+    source_dir: String,
+    html_pages: Vec<HtmlPage>,
+    scripts: Vec<Script>,
+    content_scripts: Vec<ContentScript>,
+    registry: Vec<Context>,
+    /// The icon from index.html, or else the config.
+    icon: Option<Icon>,
+    /// Static files to copy, from index.html and the config.
+    copies: Vec<CopyAssets>,
+    html_template: String,
+    /// The shim templates of Trunk's main wasm and the other artifacts.
+    artifacts: Artifacts,
+    stable_names: usize,
+    cache: Cache,
 }
 
 fn main() {
+    if env::args().nth(1).as_deref() == Some("doctor") {
+        doctor(&env::current_dir().unwrap().to_string_lossy());
+        return;
+    }
+    if env::args().nth(1).as_deref() == Some("new-page") {
+        let args: Vec<String> = env::args().skip(2).collect();
+        new_page(&env::current_dir().unwrap().to_string_lossy(), &args);
+        return;
+    }
     let start_time = Instant::now();
-    let source_dir = env::var("TRUNK_SOURCE_DIR").unwrap();
-    let staging_dir = env::var("TRUNK_STAGING_DIR").unwrap();
+    let (Ok(source_dir), Ok(staging_dir)) =
+        (env::var("TRUNK_SOURCE_DIR"), env::var("TRUNK_STAGING_DIR"))
+    else {
+        panic!("wextrunk runs as a Trunk hook, which sets TRUNK_SOURCE_DIR and TRUNK_STAGING_DIR. Build with `trunk build`, or run `cargo wextrunk doctor` to check the hook setup.");
+    };
+    check_staging(&staging_dir);
     let target = env::var("WEXTRUNK_TARGET").ok();
     let index_path = Path::new(&staging_dir).join("index.html");
+    let config = Config::load(&source_dir);
+    let stable_names = if config.output.stable_names {
+        stabilize_names(&staging_dir)
+    } else {
+        0
+    };
     let CollectOutput {
+        mut html_pages,
+        mut scripts,
+        mut content_scripts,
+        manifests,
+        icon,
+        mut copies,
+        html_template,
+        script_contents,
+    } = process_index_html(&index_path);
+    add_declarations(&config, &mut html_pages, &mut scripts, &mut content_scripts);
+    let manifests = select_manifests(&manifests, &config, target.as_deref());
+
+    let mut main_template = ScriptTemplate::new(&script_contents);
+    main_template.protocol = read_protocol(&staging_dir, &main_template.module_paths().1);
+    let artifacts = Artifacts::load(
+        main_template,
+        scripts
+            .iter()
+            .filter_map(|script| script.wasm.as_deref())
+            .chain(html_pages.iter().filter_map(|page| page.wasm.as_deref()))
+            .chain(
+                content_scripts
+                    .iter()
+                    .filter_map(|script| script.wasm.as_deref()),
+            ),
+        &staging_dir,
+    );
+    let registry = read_registry(&staging_dir, &artifacts.main.module_paths().1);
+    add_contexts(&registry, &mut html_pages, &mut scripts);
+
+    for (artifact, wasm_file) in artifacts.wasm_files() {
+        check_entries(
+            &staging_dir,
+            &wasm_file,
+            scripts
+                .iter()
+                .filter(|script| script.wasm.as_deref() == artifact)
+                .map(|script| script.wasm_fn.as_str())
+                .chain(
+                    html_pages
+                        .iter()
+                        .filter(|page| page.wasm.as_deref() == artifact)
+                        .map(|page| page.wasm_fn.as_str()),
+                )
+                .chain(
+                    content_scripts
+                        .iter()
+                        .filter(|script| script.wasm.as_deref() == artifact)
+                        .map(|script| script.wasm_fn.as_str()),
+                ),
+        );
+    }
+
+    let icon = icon.or_else(|| config.icon.clone());
+    copies.extend(config.copy.iter().cloned());
+    let cache = Cache::open(&config.cache, &source_dir);
+    let build = Build {
+        config,
+        source_dir,
         html_pages,
         scripts,
-        manifest,
+        content_scripts,
+        registry,
+        icon,
+        copies,
         html_template,
-        script_contents,
-    } = process_index_html(&index_path, target.as_deref());
+        artifacts,
+        stable_names,
+        cache,
+    };
+
+    if target.as_deref() == Some(ALL_TARGETS) {
+        let targets: Vec<String> = manifests
+            .iter()
+            .map(|manifest| manifest.target.clone())
+            .collect();
+        for manifest in &manifests {
+            println!("Building the {} target.", manifest.target);
+            let link_wasm = build.config.wasm_opt_flags(&manifest.target).is_none();
+            let target_dir = stage_target(&staging_dir, &manifest.target, &targets, link_wasm);
+            let report = build_target(&build, manifest, &target_dir);
+            report.print();
+            report.write(&build.source_dir, Some(&manifest.target));
+        }
+        remove_shared(&staging_dir, &targets);
+    } else {
+        fs::remove_file(index_path).unwrap();
+        let report = build_target(&build, &manifests[0], &staging_dir);
+        report.print();
+        report.write(&build.source_dir, None);
+    }
 
-    write_manifest(manifest, &source_dir, &staging_dir);
+    let duration = start_time.elapsed();
+    println!("Wextrunk finished in {:?}", duration);
+}
 
-    let script_template = ScriptTemplate::new(&script_contents);
+/// Run the per-target stages on `staging_dir`, and write the pages and scripts to it.
+fn build_target(build: &Build, manifest: &Manifest, staging_dir: &str) -> BuildReport {
+    let Build {
+        config,
+        source_dir,
+        html_pages,
+        scripts,
+        content_scripts,
+        registry,
+        icon,
+        copies,
+        html_template,
+        artifacts,
+        stable_names,
+        cache,
+    } = build;
+    let source_dir = source_dir.as_str();
+
+    let mut report = BuildReport {
+        target: manifest.target.clone(),
+        profile: config.profile().map(|(name, _)| name),
+        stable_names: *stable_names,
+        ..BuildReport::default()
+    };
+
+    if let Some(flags) = config.wasm_opt_flags(&manifest.target) {
+        report.wasm_opt = run_wasm_opt(staging_dir, &flags, cache);
+    }
+
+    let mut manifest_output = read_manifest(manifest, source_dir);
+    sync_version(
+        &config.version,
+        &mut manifest_output,
+        &manifest.target,
+        source_dir,
+    );
+    fill_manifest(registry, &mut manifest_output, &manifest.target);
+    let manifest_version = config.manifest_version(&manifest.target);
+    fill_background(
+        scripts,
+        &mut manifest_output,
+        &manifest.target,
+        manifest_version,
+    );
+    fill_pages(
+        html_pages,
+        &mut manifest_output,
+        &manifest.target,
+        config,
+        source_dir,
+        staging_dir,
+    );
+    if let Some(icon) = icon {
+        write_icons(icon, &mut manifest_output, source_dir, staging_dir, cache);
+    }
+    copy_assets(copies, &mut manifest_output, source_dir, staging_dir);
+    add_content_scripts(
+        content_scripts,
+        &mut manifest_output,
+        artifacts,
+        staging_dir,
+    );
+    let mut locales = Locales::load(&config.i18n, source_dir);
+    if let Some(locales) = &mut locales {
+        if config.i18n.localize_manifest {
+            locales.localize_manifest(&mut manifest_output);
+        }
+        locales.validate_manifest(&manifest_output);
+        let usages = scan_usages(&config.i18n, source_dir);
+        locales.check_usages(&usages, &config.i18n, source_dir);
+        if config.pseudo_localize() {
+            println!("Pseudo-localizing all messages.");
+            locales.pseudo_localize();
+        }
+        locales.write(staging_dir);
+        report.locales = locales.messages.keys().cloned().collect();
+    }
+    if html_pages.iter().any(|page| page.report) {
+        write_print_stylesheet(&config.print, source_dir, staging_dir);
+    }
+    if !config.fonts.is_empty() {
+        report.fonts = subset_fonts(
+            &config.fonts,
+            locales.as_ref(),
+            source_dir,
+            staging_dir,
+            cache,
+        );
+    }
+    apply_overrides(&mut manifest_output, config);
+    let dev_server = artifacts
+        .main
+        .auto_reload
+        .is_some()
+        .then(dev_server_address);
+    build_csp(
+        config,
+        &mut manifest_output,
+        staging_dir,
+        dev_server.as_deref(),
+    );
+    report.compat = Some(check_compat(
+        &config.compat,
+        &manifest_output,
+        &manifest.target,
+        source_dir,
+    ));
+    lint_permissions(
+        &config.permissions,
+        &config.compat,
+        &manifest_output,
+        &manifest.target,
+        source_dir,
+    );
+    write_build_info(config, &manifest_output, &manifest.target, staging_dir);
+    report.changelog_releases =
+        write_changelog(&config.changelog, &manifest_output, source_dir, staging_dir);
+    if config.is_package() {
+        report.new_permissions = check_permissions(
+            &config.permissions,
+            &manifest_output,
+            &manifest.target,
+            source_dir,
+        );
+    }
+    if manifest_version == 2 {
+        convert_to_mv2(&mut manifest_output);
+    }
+    validate_manifest(&manifest_output, &manifest.target);
+    write_manifest(&manifest_output, staging_dir);
 
     for script in scripts {
-        write_script(script, &staging_dir, &script_template);
+        let mode = script.background_script.then(|| {
+            BackgroundMode::resolve(script.mode.as_deref(), &manifest.target, manifest_version)
+        });
+        write_script(
+            script,
+            mode,
+            staging_dir,
+            artifacts.template(script.wasm.as_deref()),
+        );
     }
 
+    for script in content_scripts {
+        write_content_script(
+            script,
+            staging_dir,
+            artifacts.template(script.wasm.as_deref()),
+        );
+    }
+
+    let sprite = build_sprite(&config.sprite, source_dir);
+    let inline_sprite = sprite.as_deref().filter(|_| config.sprite.inline);
+    if let (Some(sprite), None) = (&sprite, inline_sprite) {
+        write_sprite(sprite, staging_dir);
+    }
     for page in html_pages {
-        write_html_page(page, &staging_dir, &script_template, &html_template);
+        let layout = layouts::resolve(&page.layout, &config.layouts);
+        let skeleton = config
+            .skeletons
+            .get(&page.html)
+            .map(|file| load_skeleton(file, source_dir));
+        write_html_page(
+            page,
+            &layout,
+            skeleton.as_deref(),
+            inline_sprite,
+            staging_dir,
+            artifacts.template(page.wasm.as_deref()),
+            html_template,
+        );
     }
+    check_inline_code(staging_dir);
 
-    fs::remove_file(index_path).unwrap();
+    if config.is_release() {
+        report.assets = optimize_assets(&config.assets, staging_dir, cache);
+        check_remote_free(config, staging_dir);
+    }
+    report.cache = cache.take_report();
+    if config.is_package() {
+        report.package = Some(write_package(
+            &config.package,
+            &manifest_output,
+            &manifest.target,
+            source_dir,
+            staging_dir,
+        ));
+    }
+    if config.is_review() {
+        report.review = Some(write_review(
+            &config.review,
+            &report,
+            &manifest_output,
+            &manifest.target,
+            source_dir,
+            staging_dir,
+        ));
+    }
 
-    let duration = start_time.elapsed();
-    println!("Wextrunk finished in {:?}", duration);
+    report
 }
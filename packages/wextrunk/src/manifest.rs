@@ -0,0 +1,225 @@
+//! Manifest post-processing.
+//!
+//! The selected manifest is read from the source directory, adjusted by the various
+//! pipeline stages and according to the config, and written to the staging directory
+//! as `manifest.json`.
+//!
+//! A manifest named `<name>.base.json` is a template shared by every target: the
+//! target's overlay, `<name>.<target>.json`, is merged into it if there is one. Objects
+//! merge recursively, arrays are appended to (skipping values the base already has),
+//! `null` removes a field, and anything else replaces the base's value. `{{field}}`
+//! placeholders in any manifest are then filled in from the `[package]` table of
+//! `Cargo.toml`, e.g. `{{version}}` or `{{description}}`.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_json::{Map, Value};
+
+use crate::config::Config;
+
+/// A manifest declared for a target, in index.html or the config.
+#[derive(Debug, Clone)]
+pub struct ManifestLink {
+    pub target: String,
+    pub href: String,
+    /// Whether it's built without `WEXTRUNK_TARGET`.
+    pub default: bool,
+}
+
+/// Manifest file to output. Will be read from the source directory, post-processed,
+/// and written to the staging directory.
+#[derive(Debug)]
+pub struct Manifest {
+    pub href: String,
+    /// The target this manifest is for, e.g. `chrome` or `firefox`.
+    pub target: String,
+}
+
+/// Read the selected manifest from the source directory, merging in the target's
+/// overlay and filling in placeholders.
+pub fn read_manifest(manifest: &Manifest, source_dir: &str) -> Value {
+    let mut output = read_json(&Path::new(source_dir).join(&manifest.href));
+    if let Some(name) = manifest.href.strip_suffix(".base.json") {
+        let overlay_path = Path::new(source_dir).join(format!("{name}.{}.json", manifest.target));
+        if overlay_path.exists() {
+            let overlay = read_json(&overlay_path);
+            let Value::Object(overlay) = overlay else {
+                panic!(
+                    "Manifest overlay {} isn't an object",
+                    overlay_path.display()
+                );
+            };
+            apply_overlay(&mut output, overlay);
+        }
+    }
+    let variables = package_variables(source_dir);
+    interpolate(&mut output, &variables);
+    output
+}
+
+fn read_json(path: &Path) -> Value {
+    let contents = fs::read_to_string(path)
+        .unwrap_or_else(|e| panic!("Failed to read manifest {}: {e}", path.display()));
+    serde_json::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Failed to parse manifest {}: {e}", path.display()))
+}
+
+/// Merge a target's overlay into the base manifest.
+fn apply_overlay(target: &mut Value, overlay: Map<String, Value>) {
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in overlay {
+        match (target.get_mut(&key), value) {
+            (_, Value::Null) => {
+                target.remove(&key);
+            }
+            (Some(existing @ Value::Object(_)), Value::Object(value)) => {
+                apply_overlay(existing, value)
+            }
+            (Some(Value::Array(existing)), Value::Array(values)) => {
+                for value in values {
+                    if !existing.contains(&value) {
+                        existing.push(value);
+                    }
+                }
+            }
+            (_, value) => {
+                target.insert(key, value);
+            }
+        }
+    }
+}
+
+/// The string fields of `Cargo.toml`'s `[package]` table, by name. Lists of strings,
+/// like `authors`, are joined with commas.
+pub fn package_variables(source_dir: &str) -> BTreeMap<String, String> {
+    let path = Path::new(source_dir).join("Cargo.toml");
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return BTreeMap::new();
+    };
+    let cargo: toml::Table = toml::from_str(&contents)
+        .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()));
+    let Some(toml::Value::Table(package)) = cargo.get("package") else {
+        return BTreeMap::new();
+    };
+    let mut variables = BTreeMap::new();
+    for (key, value) in package {
+        let value = match value {
+            toml::Value::String(value) => value.clone(),
+            toml::Value::Array(values) => {
+                let values: Option<Vec<&str>> = values.iter().map(toml::Value::as_str).collect();
+                match values {
+                    Some(values) => values.join(", "),
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        variables.insert(key.clone(), value);
+    }
+    variables
+}
+
+/// Fill in the `{{field}}` placeholders in every string of the manifest.
+fn interpolate(value: &mut Value, variables: &BTreeMap<String, String>) {
+    match value {
+        Value::String(text) => {
+            if text.contains("{{") {
+                *text = fill(text, variables);
+            }
+        }
+        Value::Array(values) => {
+            for value in values {
+                interpolate(value, variables);
+            }
+        }
+        Value::Object(fields) => {
+            for value in fields.values_mut() {
+                interpolate(value, variables);
+            }
+        }
+        _ => {}
+    }
+}
+
+fn fill(text: &str, variables: &BTreeMap<String, String>) -> String {
+    let mut output = String::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            panic!("Unterminated placeholder in manifest value {text:?}");
+        };
+        let name = rest[start + 2..start + end].trim();
+        let Some(value) = variables.get(name) else {
+            panic!(
+                "Unknown placeholder {{{{{name}}}}} in the manifest. Placeholders are filled in from the [package] table of Cargo.toml, which has: {}",
+                variables.keys().cloned().collect::<Vec<_>>().join(", ")
+            );
+        };
+        output.push_str(&rest[..start]);
+        output.push_str(value);
+        rest = &rest[start + end + 2..];
+    }
+    output.push_str(rest);
+    output
+}
+
+/// Apply the selected profile's manifest overrides, printing what changed.
+pub fn apply_overrides(output: &mut Value, config: &Config) {
+    if let Some((name, profile)) = config.profile() {
+        if !profile.manifest.is_empty() {
+            println!("Applying manifest overrides for profile {name:?}:");
+            let before = output.clone();
+            merge(output, &profile.manifest);
+            print_diff("", &before, output);
+        }
+    }
+}
+
+/// Write the processed manifest to the staging directory as `manifest.json`.
+pub fn write_manifest(output: &Value, staging_dir: &str) {
+    let staging_manifest_path = Path::new(staging_dir).join("manifest.json");
+    let mut output = serde_json::to_string_pretty(output).unwrap();
+    output.push('\n');
+    fs::write(staging_manifest_path, output).unwrap();
+}
+
+/// Deep-merge `overrides` into `target`. Objects are merged recursively, anything
+/// else is replaced.
+fn merge(target: &mut Value, overrides: &Map<String, Value>) {
+    if !target.is_object() {
+        *target = Value::Object(Map::new());
+    }
+    let target = target.as_object_mut().unwrap();
+    for (key, value) in overrides {
+        match (target.get_mut(key), value) {
+            (Some(existing @ Value::Object(_)), Value::Object(value)) => merge(existing, value),
+            _ => {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+    }
+}
+
+/// Print every changed leaf between `before` and `after`, one dotted path per line.
+fn print_diff(path: &str, before: &Value, after: &Value) {
+    match (before, after) {
+        (Value::Object(before), Value::Object(after)) => {
+            for (key, value) in after {
+                let child = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{path}.{key}")
+                };
+                match before.get(key) {
+                    Some(old) => print_diff(&child, old, value),
+                    None => println!("  + {child}: {value}"),
+                }
+            }
+        }
+        _ if before != after => println!("  ~ {path}: {before} -> {after}"),
+        _ => {}
+    }
+}
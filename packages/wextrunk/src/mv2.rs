@@ -0,0 +1,102 @@
+//! Manifest V2 output for targets that ask for it with `manifest_version = 2`.
+//!
+//! The source manifest is always written for MV3, and every other stage works on it as
+//! such. Just before the manifest is written, it's rewritten for MV2: `action` becomes
+//! `browser_action`, host permissions move into `permissions`, the background service
+//! worker becomes a non-persistent background script, and the CSP and
+//! `web_accessible_resources` take their MV2 shapes.
+
+use serde_json::{json, Map, Value};
+
+/// Rewrite an MV3 manifest as MV2, printing what couldn't be carried over.
+pub fn convert_to_mv2(manifest: &mut Value) {
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    println!("Converting the manifest to Manifest V2.");
+    manifest.insert("manifest_version".to_string(), json!(2));
+
+    if let Some(action) = manifest.remove("action") {
+        manifest.insert("browser_action".to_string(), action);
+    }
+    if let Some(Value::Object(commands)) = manifest.get_mut("commands") {
+        if let Some(command) = commands.remove("_execute_action") {
+            commands.insert("_execute_browser_action".to_string(), command);
+        }
+    }
+
+    move_permissions(manifest, "host_permissions", "permissions");
+    move_permissions(
+        manifest,
+        "optional_host_permissions",
+        "optional_permissions",
+    );
+
+    if let Some(Value::Object(background)) = manifest.get_mut("background") {
+        if let Some(worker) = background.remove("service_worker") {
+            background
+                .entry("scripts")
+                .or_insert_with(|| json!([worker]));
+        }
+        // MV3 backgrounds are event-driven; keep them that way, unless the background
+        // script's mode is persistent.
+        background
+            .entry("persistent")
+            .or_insert_with(|| json!(false));
+    }
+
+    match manifest.remove("content_security_policy") {
+        Some(Value::Object(policies)) => {
+            if policies.contains_key("sandbox") {
+                println!("Warning: MV2 has no separate sandbox CSP; dropping content_security_policy.sandbox.");
+            }
+            if let Some(pages) = policies.get("extension_pages") {
+                manifest.insert("content_security_policy".to_string(), pages.clone());
+            }
+        }
+        Some(policy) => {
+            manifest.insert("content_security_policy".to_string(), policy);
+        }
+        None => {}
+    }
+
+    if let Some(Value::Array(entries)) = manifest.remove("web_accessible_resources") {
+        let mut resources: Vec<Value> = Vec::new();
+        for entry in &entries {
+            let files = match entry.get("resources") {
+                Some(Value::Array(files)) => files.clone(),
+                // Already an MV2 entry.
+                _ => vec![entry.clone()],
+            };
+            for file in files {
+                if !resources.contains(&file) {
+                    resources.push(file);
+                }
+            }
+        }
+        if entries.iter().any(|entry| entry.get("matches").is_some()) {
+            println!("Warning: MV2 web_accessible_resources are available to every page; dropping their `matches`.");
+        }
+        manifest.insert(
+            "web_accessible_resources".to_string(),
+            Value::Array(resources),
+        );
+    }
+}
+
+/// Append the entries of `from` to `to`, without duplicates, and remove `from`.
+fn move_permissions(manifest: &mut Map<String, Value>, from: &str, to: &str) {
+    let Some(Value::Array(moved)) = manifest.remove(from) else {
+        return;
+    };
+    let permissions = manifest
+        .entry(to.to_string())
+        .or_insert_with(|| json!([]))
+        .as_array_mut()
+        .unwrap_or_else(|| panic!("Manifest field {to:?} must be an array"));
+    for permission in moved {
+        if !permissions.contains(&permission) {
+            permissions.push(permission);
+        }
+    }
+}
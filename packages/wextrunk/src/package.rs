@@ -0,0 +1,93 @@
+//! Packaging the staged extension as a zip for the stores.
+//!
+//! Release builds (and any build with `WEXTRUNK_PACKAGE=1`) zip the staging directory
+//! into `<name>-<version>-<target>.zip` under `[package] output_dir`, ready to upload
+//! to AMO or the Chrome Web Store. The name is the crate's, the version the manifest's.
+//!
+//! The archive only depends on the files' paths and contents: entries are sorted, and
+//! every one gets the same timestamp and permissions, so building the same sources
+//! twice gives byte-identical packages.
+
+use std::{
+    fs::{self, File},
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use serde_json::Value;
+use zip::{
+    write::{SimpleFileOptions, ZipWriter},
+    CompressionMethod, DateTime,
+};
+
+use crate::{config::Package, manifest::package_variables};
+
+/// Zip the staging directory into the package for `target`, returning its path
+/// relative to the source directory.
+pub fn write_package(
+    config: &Package,
+    manifest: &Value,
+    target: &str,
+    source_dir: &str,
+    staging_dir: &str,
+) -> String {
+    let name = package_variables(source_dir)
+        .remove("name")
+        .unwrap_or_else(|| "extension".to_string());
+    let version = manifest["version"]
+        .as_str()
+        .expect("The manifest must have a version");
+    let output_dir = Path::new(source_dir).join(&config.output_dir);
+    fs::create_dir_all(&output_dir).unwrap();
+    let output = output_dir.join(format!("{name}-{version}-{target}.zip"));
+    zip_dir(staging_dir, &output);
+
+    let output = output
+        .strip_prefix(source_dir)
+        .unwrap_or(&output)
+        .to_string_lossy()
+        .replace('\\', "/");
+    println!("Packaged the extension as {output}.");
+    output
+}
+
+/// Zip the contents of `dir` into `output`, deterministically.
+pub fn zip_dir(dir: &str, output: &Path) {
+    let mut files = Vec::new();
+    collect_files(Path::new(dir), &mut files);
+    let mut files: Vec<(String, PathBuf)> = files
+        .into_iter()
+        .map(|file| {
+            let name = file
+                .strip_prefix(dir)
+                .unwrap()
+                .to_string_lossy()
+                .replace('\\', "/");
+            (name, file)
+        })
+        .collect();
+    files.sort();
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Deflated)
+        .compression_level(Some(9))
+        .last_modified_time(DateTime::default())
+        .unix_permissions(0o644);
+    let mut zip = ZipWriter::new(File::create(output).unwrap());
+    for (name, file) in files {
+        zip.start_file(name, options).unwrap();
+        zip.write_all(&fs::read(file).unwrap()).unwrap();
+    }
+    zip.finish().unwrap();
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
@@ -0,0 +1,185 @@
+//! Wiring the popup and the options page into the manifest.
+//!
+//! A page becomes the popup when it's the registry's `Popup` context, or is declared
+//! with `kind="popup"` (`kind = "popup"` in `[[pages]]`). wextrunk sets
+//! `action.default_popup` to it, which becomes `browser_action.default_popup` in MV2
+//! builds. Its icon set, `icons="16:icons/16.png 32:icons/32.png"` on the link
+//! (`icons = { 16 = "icons/16.png" }` in `[[pages]]`, or the top-level `popup_icons`
+//! for the registry's page), is copied into the build, with the same paths, and
+//! referenced as `action.default_icon`.
+//!
+//! A page becomes the options page when it's the registry's `Options` context, or is
+//! declared with `kind="options"`. wextrunk points the manifest at it:
+//! `options_ui.page` in Firefox, and in Chrome `options_page` when it opens in a tab or
+//! `options_ui.page` when it's embedded in the extensions page. `open-in-tab` on the
+//! link (`open_in_tab` in `[[pages]]`, or the top-level `options_open_in_tab` for the
+//! registry's page) chooses which, and defaults to a tab. A manifest that already
+//! declares `options_page` or `options_ui` keeps that field, and only gets the page
+//! filled in.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_json::{json, Map, Value};
+
+use crate::{config::Config, registry::fill, schema::browser_of, HtmlPage};
+
+/// What a page is to the manifest.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PageKind {
+    Popup {
+        /// Icon files by size in pixels, relative to the source directory. Empty uses
+        /// `popup_icons` from the config.
+        icons: BTreeMap<String, String>,
+    },
+    Options {
+        /// Whether it opens in a tab rather than embedded in the browser's extensions
+        /// page. `None` uses `options_open_in_tab` from the config.
+        open_in_tab: Option<bool>,
+    },
+}
+
+impl PageKind {
+    /// The kind of a page declared with `kind` and its settings, or `None` if it has
+    /// none. `page` names it in errors.
+    pub fn from_declaration(
+        kind: Option<&str>,
+        open_in_tab: Option<bool>,
+        icons: BTreeMap<String, String>,
+        page: &str,
+    ) -> Option<Self> {
+        let has_icons = !icons.is_empty();
+        let kind = match kind {
+            None => None,
+            Some("popup") => Some(PageKind::Popup { icons }),
+            Some("options") => Some(PageKind::Options { open_in_tab }),
+            Some(kind) => {
+                panic!("Unknown page kind {kind:?} for {page}; the kinds are \"popup\" and \"options\".")
+            }
+        };
+        if open_in_tab.is_some() && !matches!(kind, Some(PageKind::Options { .. })) {
+            println!(
+                "Warning: {page} sets open-in-tab, which only applies to kind=\"options\" pages."
+            );
+        }
+        if has_icons && !matches!(kind, Some(PageKind::Popup { .. })) {
+            println!("Warning: {page} sets icons, which only apply to kind=\"popup\" pages.");
+        }
+        kind
+    }
+
+    /// The kind of a registry context, if it has one.
+    pub fn of_context(kind: &str) -> Option<Self> {
+        match kind {
+            "Popup" => Some(PageKind::Popup {
+                icons: BTreeMap::new(),
+            }),
+            "Options" => Some(PageKind::Options { open_in_tab: None }),
+            _ => None,
+        }
+    }
+}
+
+/// Parse an `icons` attribute, e.g. `16:icons/16.png 32:icons/32.png`.
+pub fn parse_icons(icons: &str) -> BTreeMap<String, String> {
+    icons
+        .split_whitespace()
+        .map(|icon| {
+            let (size, path) = icon
+                .split_once(':')
+                .filter(|(size, _)| size.parse::<u32>().is_ok())
+                .unwrap_or_else(|| {
+                    panic!("Malformed icon {icon:?}; expected e.g. 16:icons/16.png")
+                });
+            (size.to_string(), path.to_string())
+        })
+        .collect()
+}
+
+/// Point the manifest at the popup and the options page, if there are any, and copy
+/// the popup's icons from `source_dir` into `staging_dir`.
+pub fn fill_pages(
+    html_pages: &[HtmlPage],
+    manifest: &mut Value,
+    target: &str,
+    config: &Config,
+    source_dir: &str,
+    staging_dir: &str,
+) {
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    let popups = of_kind(html_pages, "popup", |kind| match kind {
+        PageKind::Popup { icons } => Some(icons),
+        _ => None,
+    });
+    if let Some((page, icons)) = popups {
+        fill(manifest, &["action", "default_popup"], json!(page.html));
+        let icons = if icons.is_empty() {
+            &config.popup_icons
+        } else {
+            icons
+        };
+        for (size, path) in icons {
+            copy_icon(path, source_dir, staging_dir);
+            fill(manifest, &["action", "default_icon", size], json!(path));
+        }
+    }
+
+    let options = of_kind(html_pages, "options", |kind| match kind {
+        PageKind::Options { open_in_tab } => Some(*open_in_tab),
+        _ => None,
+    });
+    if let Some((page, open_in_tab)) = options {
+        let open_in_tab = open_in_tab.or(config.options_open_in_tab).unwrap_or(true);
+        fill_options(manifest, &page.html, target, open_in_tab);
+    }
+}
+
+/// The page of a kind, and its settings. There can only be one.
+fn of_kind<'a, T>(
+    html_pages: &'a [HtmlPage],
+    name: &str,
+    settings: impl Fn(&'a PageKind) -> Option<T>,
+) -> Option<(&'a HtmlPage, T)> {
+    let mut pages: Vec<(&HtmlPage, T)> = html_pages
+        .iter()
+        .filter_map(|page| Some((page, settings(page.kind.as_ref()?)?)))
+        .collect();
+    if pages.len() > 1 {
+        let files: Vec<&str> = pages.iter().map(|(page, _)| page.html.as_str()).collect();
+        panic!(
+            "{} are all declared as the {name} page, but an extension has only one.",
+            files.join(" and ")
+        );
+    }
+    pages.pop()
+}
+
+fn fill_options(manifest: &mut Map<String, Value>, file: &str, target: &str, open_in_tab: bool) {
+    let file = json!(file);
+    if manifest.contains_key("options_ui") {
+        fill(manifest, &["options_ui", "page"], file);
+    } else if manifest.contains_key("options_page") {
+        fill(manifest, &["options_page"], file);
+    } else if browser_of(target) == "firefox" || !open_in_tab {
+        // Firefox only has `options_ui`, which can open in a tab too.
+        fill(manifest, &["options_ui", "page"], file);
+        fill(manifest, &["options_ui", "open_in_tab"], json!(open_in_tab));
+    } else {
+        fill(manifest, &["options_page"], file);
+    }
+}
+
+/// Copy an icon into the build, unless it's already there (e.g. copied by Trunk).
+fn copy_icon(path: &str, source_dir: &str, staging_dir: &str) {
+    let staged = Path::new(staging_dir).join(path);
+    if staged.exists() {
+        return;
+    }
+    let source = Path::new(source_dir).join(path);
+    if !source.exists() {
+        panic!("The popup icon {path} doesn't exist in {source_dir}");
+    }
+    fs::create_dir_all(staged.parent().unwrap()).unwrap();
+    fs::copy(&source, &staged).unwrap();
+}
@@ -0,0 +1,88 @@
+//! Guarding against new permissions between releases.
+//!
+//! Adding a permission (or host permission) in an update disables the extension for
+//! existing users until they approve it, which is easy to do by accident. Builds that
+//! produce a package (release builds, or with `WEXTRUNK_PACKAGE=1`) compare the output
+//! manifest against the permissions snapshot of the last release,
+//! `permissions.snapshot.json` in the source directory, and fail (or warn, depending
+//! on `[permissions] on_new`) when anything was added.
+//!
+//! After a deliberate change, update the snapshot by building with
+//! `WEXTRUNK_UPDATE_PERMISSIONS=1`, and commit it with the release.
+
+use std::{collections::BTreeSet, env, fs, path::Path};
+
+use serde_json::{Map, Value};
+
+use crate::config::{NewPermissions, Permissions};
+
+/// Manifest keys whose additions need user approval. `optional_permissions` are
+/// requested at runtime instead, so adding them is fine.
+const GUARDED_KEYS: &[&str] = &["permissions", "host_permissions"];
+
+/// Compare `manifest`'s permissions for `target` against the snapshot, returning the
+/// added ones. Also writes the snapshot if it's missing or an update was requested.
+pub fn check_permissions(
+    config: &Permissions,
+    manifest: &Value,
+    target: &str,
+    source_dir: &str,
+) -> Vec<String> {
+    let path = Path::new(source_dir).join(&config.snapshot);
+    let mut snapshot: Map<String, Value> = fs::read_to_string(&path)
+        .ok()
+        .map(|contents| {
+            serde_json::from_str(&contents)
+                .unwrap_or_else(|e| panic!("Failed to parse {}: {e}", path.display()))
+        })
+        .unwrap_or_default();
+    let current = guarded(manifest);
+
+    let update = env::var("WEXTRUNK_UPDATE_PERMISSIONS").is_ok_and(|value| value == "1");
+    let Some(previous) = snapshot.get(target).filter(|_| !update) else {
+        println!(
+            "Writing the {target} permissions snapshot to {}.",
+            config.snapshot
+        );
+        snapshot.insert(target.to_string(), serde_json::to_value(&current).unwrap());
+        let mut output = serde_json::to_string_pretty(&snapshot).unwrap();
+        output.push('\n');
+        fs::write(&path, output).unwrap();
+        return Vec::new();
+    };
+    let previous: BTreeSet<String> = serde_json::from_value(previous.clone())
+        .unwrap_or_else(|e| panic!("Invalid {target} entry in {}: {e}", path.display()));
+
+    for removed in previous.difference(&current) {
+        println!("Permission removed since the last release: {removed}");
+    }
+    let added: Vec<String> = current.difference(&previous).cloned().collect();
+    if !added.is_empty() {
+        let message = format!(
+            "The {target} manifest adds permissions since the last release, which disables the extension for existing users until they approve them:\n  {}\nIf this is intended, build with WEXTRUNK_UPDATE_PERMISSIONS=1 to update {}.",
+            added.join("\n  "),
+            config.snapshot
+        );
+        match config.on_new {
+            NewPermissions::Error => panic!("{message}"),
+            NewPermissions::Warn => println!("Warning: {message}"),
+        }
+    }
+    added
+}
+
+/// The guarded permissions, prefixed by their key, e.g. `permissions:tabs`.
+fn guarded(manifest: &Value) -> BTreeSet<String> {
+    GUARDED_KEYS
+        .iter()
+        .flat_map(|key| {
+            manifest
+                .get(*key)
+                .and_then(Value::as_array)
+                .into_iter()
+                .flatten()
+                .filter_map(Value::as_str)
+                .map(move |permission| format!("{key}:{permission}"))
+        })
+        .collect()
+}
@@ -0,0 +1,75 @@
+//! Print stylesheet for report pages.
+//!
+//! Pages of the `Report` kind (or `htmlpage` links with a `report` attribute) are
+//! meant to be printed or saved as PDF. They get a `media="print"` link to
+//! `print.css`, written to the staging directory before the rest of the CSS pipeline
+//! runs (so font URLs in it are rewritten too). It holds wextrunk's print defaults,
+//! followed by the stylesheet configured under `[print]`, if it exists.
+
+use std::{fs, path::Path};
+
+use crate::config::Print;
+
+/// Output file, relative to the staging directory.
+pub const STYLESHEET: &str = "print.css";
+
+/// Sensible print defaults: no backgrounds, no page breaks inside blocks or right
+/// after headings, link targets spelled out, and `.no-print` elements hidden.
+const DEFAULTS: &str = r#"@page {
+  margin: 18mm 16mm;
+}
+
+html,
+body {
+  background: #fff !important;
+  color: #000 !important;
+}
+
+.no-print,
+[data-no-print] {
+  display: none !important;
+}
+
+h1,
+h2,
+h3,
+h4,
+h5,
+h6 {
+  break-after: avoid;
+}
+
+pre,
+blockquote,
+figure,
+img,
+tr {
+  break-inside: avoid;
+}
+
+thead {
+  display: table-header-group;
+}
+
+p {
+  orphans: 3;
+  widows: 3;
+}
+
+a[href^="http"]::after {
+  content: " (" attr(href) ")";
+  font-size: 0.85em;
+  word-break: break-all;
+}
+"#;
+
+/// Write `print.css` to the staging directory.
+pub fn write_print_stylesheet(print: &Print, source_dir: &str, staging_dir: &str) {
+    let mut css = DEFAULTS.to_string();
+    let source = Path::new(source_dir).join(&print.stylesheet);
+    if let Ok(custom) = fs::read_to_string(&source) {
+        css.push('\n');
+        css.push_str(&custom);
+    }
+    fs::write(Path::new(staging_dir).join(STYLESHEET), css).unwrap();
+}
@@ -0,0 +1,214 @@
+//! Pages and scripts declared in the Rust context registry.
+//!
+//! `src/registry.rs` records every context in the `wext_contexts` custom section of
+//! the wasm, as one `name kind entry file reload` line each. wextrunk adds a page or
+//! script for each of them, and fills in the manifest fields that point at them, so
+//! they're only declared once. `data-wextrunk` links in index.html with the same name
+//! or file take precedence over the registry.
+//!
+//! Pages of the `NewTab`, `History` and `Bookmarks` kinds replace the browser's page
+//! through `chrome_url_overrides`. An extension can override only one of them, and
+//! Firefox only supports the new tab page, so the others are left out of its manifest.
+
+use serde_json::{json, Map, Value};
+
+use crate::{
+    entries::{custom_section, staged_wasm},
+    layouts,
+    page_kinds::PageKind,
+    schema::browser_of,
+    HtmlPage, Script,
+};
+
+/// Name of the custom section written by the registry.
+const SECTION: &str = "wext_contexts";
+/// Name of the custom section with `messaging::PROTOCOL_VERSION`, little-endian.
+const PROTOCOL_SECTION: &str = "wext_protocol";
+
+/// A context from the registry.
+#[derive(Debug)]
+pub struct Context {
+    /// The registry name, e.g. `Popup`.
+    pub name: String,
+    /// The `entry::Context` variant, e.g. `Popup` or `Background`.
+    pub kind: String,
+    pub entry: String,
+    pub file: String,
+    pub reload: bool,
+}
+
+impl Context {
+    /// The name used by `data-wextrunk-include`, e.g. `WEXTRUNK_POPUP`.
+    fn include_name(&self) -> String {
+        format!("WEXTRUNK_{}", self.name.to_uppercase())
+    }
+
+    fn is_page(&self) -> bool {
+        self.file.ends_with(".html")
+    }
+}
+
+/// Read the registry from the staged main wasm, `wasm_file`. Empty if the wasm
+/// doesn't record one.
+pub fn read_registry(staging_dir: &str, wasm_file: &str) -> Vec<Context> {
+    let Some(wasm) = staged_wasm(staging_dir, wasm_file) else {
+        return Vec::new();
+    };
+    let Some(section) = custom_section(&wasm, SECTION) else {
+        return Vec::new();
+    };
+    String::from_utf8_lossy(section)
+        .lines()
+        .map(|line| {
+            let fields: Vec<&str> = line.split(' ').collect();
+            let [name, kind, entry, file, reload] = fields[..] else {
+                panic!("Malformed {SECTION} entry in the wasm: {line:?}");
+            };
+            Context {
+                name: name.to_string(),
+                kind: kind.to_string(),
+                entry: entry.to_string(),
+                file: file.to_string(),
+                reload: reload == "true",
+            }
+        })
+        .collect()
+}
+
+/// Read `messaging::PROTOCOL_VERSION` from the staged main wasm, `wasm_file`. `None` if
+/// the wasm doesn't record it.
+pub fn read_protocol(staging_dir: &str, wasm_file: &str) -> Option<u32> {
+    let wasm = staged_wasm(staging_dir, wasm_file)?;
+    let section = custom_section(&wasm, PROTOCOL_SECTION)?;
+    let bytes = section.try_into().unwrap_or_else(|_| {
+        panic!("Malformed {PROTOCOL_SECTION} section in the wasm: {section:?}")
+    });
+    Some(u32::from_le_bytes(bytes))
+}
+
+/// Add a page or script for every registry context that index.html doesn't declare.
+pub fn add_contexts(
+    registry: &[Context],
+    html_pages: &mut Vec<HtmlPage>,
+    scripts: &mut Vec<Script>,
+) {
+    for context in registry {
+        if context.is_page() {
+            let name = context.include_name();
+            let kind = PageKind::of_context(&context.kind);
+            if let Some(page) = html_pages
+                .iter_mut()
+                .find(|page| page.name == name || page.html == context.file)
+            {
+                println!("index.html overrides the registry's {} page.", context.name);
+                // It's still the popup or options page, without needing a kind.
+                if page.kind.is_none() {
+                    page.kind = kind;
+                }
+                continue;
+            }
+            html_pages.push(HtmlPage {
+                name,
+                html: context.file.clone(),
+                no_reload: !context.reload,
+                wasm_fn: context.entry.clone(),
+                // The registry is read from the main wasm, so its contexts are in it.
+                wasm: None,
+                report: context.kind == "Report",
+                layout: layouts::default_for(kind.as_ref()).to_string(),
+                kind,
+            });
+        } else {
+            if scripts.iter().any(|script| script.js == context.file) {
+                println!(
+                    "index.html overrides the registry's {} script.",
+                    context.name
+                );
+                continue;
+            }
+            scripts.push(Script {
+                js: context.file.clone(),
+                no_reload: !context.reload,
+                background_script: context.kind == "Background",
+                keep_alive: false,
+                mode: None,
+                wasm_fn: context.entry.clone(),
+                wasm: None,
+                html_page: false,
+            });
+        }
+    }
+}
+
+/// The `chrome_url_overrides` key for each overriding context kind.
+const URL_OVERRIDES: &[(&str, &str)] = &[
+    ("NewTab", "newtab"),
+    ("History", "history"),
+    ("Bookmarks", "bookmarks"),
+];
+
+/// Point the manifest's overridden pages at the registry's contexts (the popup and
+/// options page are filled in by [`fill_pages`](crate::page_kinds::fill_pages), and
+/// the background by [`fill_background`](crate::background::fill_background)). Fields the manifest already sets
+/// are kept, with a warning if they disagree.
+pub fn fill_manifest(registry: &[Context], manifest: &mut Value, target: &str) {
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    let overrides: Vec<&Context> = registry
+        .iter()
+        .filter(|context| URL_OVERRIDES.iter().any(|(kind, _)| context.kind == *kind))
+        .collect();
+    if let [_, _, ..] = overrides[..] {
+        let names: Vec<&str> = overrides
+            .iter()
+            .map(|context| context.name.as_str())
+            .collect();
+        panic!(
+            "The registry declares {} pages overriding browser pages, but an extension can only override one.",
+            names.join(" and ")
+        );
+    }
+    for context in registry {
+        if let Some((_, key)) = URL_OVERRIDES.iter().find(|(kind, _)| context.kind == *kind) {
+            if browser_of(target) == "firefox" && *key != "newtab" {
+                println!(
+                    "Warning: Firefox can't override the {key} page, so {} isn't in its manifest.",
+                    context.file
+                );
+            } else {
+                fill(
+                    manifest,
+                    &["chrome_url_overrides", key],
+                    json!(context.file),
+                );
+            }
+            continue;
+        }
+    }
+}
+
+/// Set the field at `path` if it's missing, or warn if it has a different value.
+pub fn fill(manifest: &mut Map<String, Value>, path: &[&str], value: Value) {
+    let (last, parents) = path.split_last().unwrap();
+    let mut object = manifest;
+    for key in parents {
+        object = object
+            .entry(key.to_string())
+            .or_insert_with(|| Value::Object(Map::new()))
+            .as_object_mut()
+            .unwrap_or_else(|| panic!("Manifest field {key:?} must be an object"));
+    }
+    match object.get(*last) {
+        None => {
+            object.insert(last.to_string(), value);
+        }
+        Some(existing) if *existing != value => {
+            println!(
+                "Warning: the manifest sets {} to {existing}, but the registry declares {value}. Keeping the manifest's value.",
+                path.join(".")
+            );
+        }
+        Some(_) => {}
+    }
+}
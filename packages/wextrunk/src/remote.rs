@@ -0,0 +1,144 @@
+//! Checking release builds don't load anything from remote servers.
+//!
+//! MV3 forbids remotely hosted code, and store review rejects extensions that load
+//! scripts, styles or fonts from CDNs or analytics services. Release builds scan the
+//! generated pages, scripts and stylesheets in the staging directory for remote URLs:
+//! resource attributes in HTML (`src`, `href` other than links, ...), URLs in JS, and
+//! `url(..)` and `@import` in CSS. The API endpoints declared in `[csp] connect_src`
+//! (and the profile's) are allowed, as are XML namespaces. Anything else fails the
+//! build before it's packaged.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use lol_html::{element, HtmlRewriter, Settings};
+
+use crate::config::Config;
+
+/// URL prefixes that identify rather than load anything.
+const NAMESPACES: &[&str] = &["http://www.w3.org/"];
+
+/// HTML attributes that load a resource. `href` does too, except on links.
+const RESOURCE_ATTRIBUTES: &[&str] = &["src", "srcset", "data", "poster", "action", "href"];
+
+/// Fail if a generated file references a remote URL that isn't allowed.
+pub fn check_remote_free(config: &Config, staging_dir: &str) {
+    let endpoints = config.connect_src();
+    let mut files = Vec::new();
+    collect_sources(Path::new(staging_dir), &mut files);
+    files.sort();
+
+    let mut violations = Vec::new();
+    for file in files {
+        let contents = fs::read_to_string(&file).unwrap();
+        let urls = match file.extension().and_then(|ext| ext.to_str()) {
+            Some("html") => html_urls(&contents),
+            Some("css") => text_urls(&strip_comments(&contents)),
+            _ => text_urls(&contents),
+        };
+        let name = file
+            .strip_prefix(staging_dir)
+            .unwrap()
+            .display()
+            .to_string();
+        for url in urls {
+            let allowed = NAMESPACES.iter().any(|prefix| url.starts_with(prefix))
+                || endpoints
+                    .iter()
+                    .any(|prefix| url.starts_with(prefix.as_str()));
+            if !allowed {
+                violations.push(format!("{name}: {url}"));
+            }
+        }
+    }
+    if !violations.is_empty() {
+        panic!(
+            "Release builds can't load remote resources, as MV3 forbids remotely hosted code:\n  {}\nSelf-host them, or declare API endpoints under [csp] connect_src in wextrunk.toml.",
+            violations.join("\n  ")
+        );
+    }
+}
+
+/// Every HTML, JS and CSS file in `dir` and its subdirectories.
+fn collect_sources(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_sources(&path, files);
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "js" || ext == "css")
+        {
+            files.push(path);
+        }
+    }
+}
+
+/// Remote URLs in the resource attributes and inline styles of an HTML page.
+fn html_urls(html: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rewriter = HtmlRewriter::new(
+        Settings {
+            element_content_handlers: vec![element!("*", |el| {
+                let is_link = matches!(el.tag_name().as_str(), "a" | "area");
+                for attribute in el.attributes() {
+                    let name = attribute.name();
+                    let value = attribute.value();
+                    if name == "style" {
+                        urls.extend(text_urls(&value));
+                    } else if RESOURCE_ATTRIBUTES.contains(&name.as_str())
+                        && !(is_link && name == "href")
+                    {
+                        if let Some(host) = value.trim().strip_prefix("//") {
+                            urls.push(format!("//{host}"));
+                        } else {
+                            urls.extend(text_urls(&value));
+                        }
+                    }
+                }
+                Ok(())
+            })],
+            ..Settings::default()
+        },
+        |_: &[u8]| {},
+    );
+    rewriter.write(html.as_bytes()).unwrap();
+    rewriter.end().unwrap();
+    urls
+}
+
+/// Every `http(s)://` and `ws(s)://` URL in `text`.
+fn text_urls(text: &str) -> Vec<String> {
+    let mut urls = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find("://") {
+        let scheme_start = rest[..start]
+            .rfind(|c: char| !c.is_ascii_alphabetic())
+            .map_or(0, |i| i + 1);
+        let scheme = &rest[scheme_start..start];
+        let end = rest[start..]
+            .find(|c: char| c.is_whitespace() || "\"'`()<>\\,".contains(c))
+            .map_or(rest.len(), |end| start + end);
+        if matches!(scheme, "http" | "https" | "ws" | "wss") && end > start + 3 {
+            urls.push(rest[scheme_start..end].to_string());
+        }
+        rest = &rest[end.max(start + 3)..];
+    }
+    urls
+}
+
+/// `text` without `/* .. */` comments, e.g. license headers in CSS.
+fn strip_comments(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("/*") {
+        output.push_str(&rest[..start]);
+        rest = rest[start + 2..]
+            .find("*/")
+            .map_or("", |end| &rest[start + 2 + end + 2..]);
+    }
+    output.push_str(rest);
+    output
+}
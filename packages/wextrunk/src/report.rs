@@ -0,0 +1,204 @@
+//! Build report, summarising what each pipeline stage did.
+//!
+//! The report is printed at the end of the run, and written as JSON to
+//! `target/wextrunk-report.json` in the source directory so CI can archive it (one
+//! `wextrunk-report.<target>.json` per target when building them all).
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde::Serialize;
+
+/// Everything worth knowing about a single wextrunk run.
+#[derive(Debug, Default, Serialize)]
+pub struct BuildReport {
+    pub target: String,
+    pub profile: Option<String>,
+    /// Number of hashed files renamed to stable names.
+    #[serde(skip_serializing_if = "is_zero")]
+    pub stable_names: usize,
+    /// Locales shipped in `_locales`.
+    pub locales: Vec<String>,
+    /// Permissions added since the last release's snapshot.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub new_permissions: Vec<String>,
+    /// Number of releases with notes in `changelog.json`, if there's a changelog.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub changelog_releases: Option<usize>,
+    /// One per wasm file, if wasm-opt was run.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub wasm_opt: Vec<WasmOptReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fonts: Vec<FontReport>,
+    /// Images optimised in release builds.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub assets: Vec<AssetReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub compat: Option<CompatReport>,
+    /// Build cache hits and misses, if the cache is enabled.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cache: Option<CacheReport>,
+    /// The packaged extension, for release builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub package: Option<String>,
+    /// Where the review artifacts were written, for review builds.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub review: Option<String>,
+}
+
+/// What wasm-opt was run with, and how much it saved.
+#[derive(Debug, Serialize)]
+pub struct WasmOptReport {
+    pub file: String,
+    pub flags: Vec<String>,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// A subset font, and how much subsetting saved.
+#[derive(Debug, Serialize)]
+pub struct FontReport {
+    pub file: String,
+    pub output: String,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+/// Which browser versions the bound APIs were checked against.
+#[derive(Debug, Default, Serialize)]
+pub struct CompatReport {
+    /// Minimum version per browser.
+    pub targets: BTreeMap<String, String>,
+    /// Number of APIs checked.
+    pub apis: usize,
+    /// Optional APIs a target lacks, which the code has to do without there.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unavailable_optional: Vec<String>,
+    /// APIs missing from the compatibility dataset, with the browser.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub unknown: Vec<String>,
+}
+
+/// How many stage runs the build cache saved.
+#[derive(Debug, Default, Serialize)]
+pub struct CacheReport {
+    pub hits: usize,
+    pub misses: usize,
+    /// Hits and misses per stage.
+    pub stages: BTreeMap<String, (usize, usize)>,
+}
+
+/// An optimised image, and how much it saved.
+#[derive(Debug, Serialize)]
+pub struct AssetReport {
+    pub file: String,
+    pub size_before: u64,
+    pub size_after: u64,
+}
+
+impl BuildReport {
+    /// Print a human-readable summary.
+    pub fn print(&self) {
+        println!("Build report:");
+        println!("  target: {}", self.target);
+        println!("  profile: {}", self.profile.as_deref().unwrap_or("(none)"));
+        if self.stable_names > 0 {
+            println!("  stable names: {} file(s)", self.stable_names);
+        }
+        if !self.locales.is_empty() {
+            println!("  locales: {}", self.locales.join(", "));
+        }
+        if !self.new_permissions.is_empty() {
+            println!("  new permissions: {}", self.new_permissions.join(", "));
+        }
+        if let Some(releases) = self.changelog_releases {
+            println!("  changelog: {releases} release(s)");
+        }
+        if let Some(compat) = &self.compat {
+            let targets: Vec<String> = compat
+                .targets
+                .iter()
+                .map(|(browser, version)| format!("{browser} {version}"))
+                .collect();
+            println!(
+                "  compat: {} APIs checked against {}",
+                compat.apis,
+                targets.join(", ")
+            );
+            for problem in &compat.unavailable_optional {
+                println!("    optional: {problem}");
+            }
+        }
+        for wasm_opt in &self.wasm_opt {
+            println!(
+                "  wasm-opt: {} on {} ({} -> {} bytes)",
+                wasm_opt.flags.join(" "),
+                wasm_opt.file,
+                wasm_opt.size_before,
+                wasm_opt.size_after
+            );
+        }
+        if self.wasm_opt.is_empty() {
+            println!("  wasm-opt: not run");
+        }
+        for font in &self.fonts {
+            println!(
+                "  font: {} -> {} ({} -> {} bytes)",
+                font.file, font.output, font.size_before, font.size_after
+            );
+        }
+        if !self.assets.is_empty() {
+            let before: u64 = self.assets.iter().map(|asset| asset.size_before).sum();
+            let after: u64 = self.assets.iter().map(|asset| asset.size_after).sum();
+            println!(
+                "  images: {} optimised ({before} -> {after} bytes)",
+                self.assets.len()
+            );
+            for asset in &self.assets {
+                println!(
+                    "    {}: {} -> {} bytes",
+                    asset.file, asset.size_before, asset.size_after
+                );
+            }
+        }
+        if let Some(cache) = &self.cache {
+            let stages: Vec<String> = cache
+                .stages
+                .iter()
+                .map(|(stage, (hits, misses))| format!("{stage} {hits}/{}", hits + misses))
+                .collect();
+            println!(
+                "  cache: {} hit(s), {} miss(es){}",
+                cache.hits,
+                cache.misses,
+                if stages.is_empty() {
+                    String::new()
+                } else {
+                    format!(" ({})", stages.join(", "))
+                }
+            );
+        }
+        if let Some(package) = &self.package {
+            println!("  package: {package}");
+        }
+        if let Some(review) = &self.review {
+            println!("  review artifacts: {review}");
+        }
+    }
+
+    /// Write the report as JSON to `target/wextrunk-report.json`, or
+    /// `target/wextrunk-report.<target>.json` when building every target.
+    pub fn write(&self, source_dir: &str, target: Option<&str>) {
+        let target_dir = Path::new(source_dir).join("target");
+        fs::create_dir_all(&target_dir).unwrap();
+        let report = serde_json::to_string_pretty(self).unwrap();
+        let file = match target {
+            Some(target) => format!("wextrunk-report.{target}.json"),
+            None => "wextrunk-report.json".to_string(),
+        };
+        fs::write(target_dir.join(file), report).unwrap();
+    }
+}
+
+fn is_zero(count: &usize) -> bool {
+    *count == 0
+}
@@ -0,0 +1,402 @@
+//! Review builds: the artifacts a store reviewer sees, in one place.
+//!
+//! `WEXTRUNK_REVIEW=1 trunk build --release` runs the normal release pipeline, then
+//! writes to `target/review/<target>` (or the `[review] output_dir`):
+//!
+//! - `extension.zip`: the staged extension, as uploaded to the store.
+//! - `source.zip`: the committed sources (`git archive HEAD`) plus `Cargo.lock`, for
+//!   stores that require the source of minified or compiled code.
+//! - `LICENSES.txt`: the project's licenses, followed by every crate dependency's
+//!   license files, from `cargo metadata`.
+//! - `permissions.md`: every permission and host the manifest asks for, what the
+//!   install prompt says about it, and whether it's new since the last release.
+//! - `REVIEW_NOTES.md`: the build inputs (commit, toolchain, tool versions, flags),
+//!   how to reproduce the build, the packaged files, and the hand-written notes from
+//!   the `[review] notes` file, if it exists.
+//!
+//! Needs `git` (2.30 or later).
+
+use std::{
+    collections::BTreeSet,
+    fmt::Write as _,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use serde_json::Value;
+
+use crate::{config::Review, package::zip_dir, report::BuildReport};
+
+/// Install-time warnings for permissions, as Chrome words them. Permissions not
+/// listed here don't show a warning.
+const WARNINGS: &[(&str, &str)] = &[
+    ("bookmarks", "Read and change your bookmarks"),
+    ("clipboardRead", "Read data you copy and paste"),
+    ("clipboardWrite", "Modify data you copy and paste"),
+    ("contentSettings", "Change your settings that control websites' access to features such as cookies, JavaScript, plugins, geolocation, microphone, camera etc."),
+    ("debugger", "Access the page debugger backend"),
+    ("declarativeNetRequest", "Block content on any page"),
+    ("desktopCapture", "Capture content of your screen"),
+    ("downloads", "Manage your downloads"),
+    ("geolocation", "Detect your physical location"),
+    ("history", "Read and change your browsing history on all your signed-in devices"),
+    ("management", "Manage your apps, extensions, and themes"),
+    ("nativeMessaging", "Communicate with cooperating native applications"),
+    ("notifications", "Display notifications"),
+    ("pageCapture", "Read and change all your data on all websites"),
+    ("privacy", "Change your privacy-related settings"),
+    ("proxy", "Read and change all your data on all websites"),
+    ("tabCapture", "Read and change all your data on all websites"),
+    ("tabs", "Read your browsing history"),
+    ("topSites", "Read a list of your most frequently visited websites"),
+    ("webNavigation", "Read your browsing history"),
+];
+
+/// Write the review artifacts for the staged build of `target`.
+pub fn write_review(
+    config: &Review,
+    report: &BuildReport,
+    manifest: &Value,
+    target: &str,
+    source_dir: &str,
+    staging_dir: &str,
+) -> String {
+    let output_dir = Path::new(source_dir).join(&config.output_dir).join(target);
+    if output_dir.exists() {
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+    fs::create_dir_all(&output_dir).unwrap();
+
+    zip_dir(staging_dir, &output_dir.join("extension.zip"));
+    let commit = archive_sources(source_dir, &output_dir.join("source.zip"));
+    fs::write(output_dir.join("LICENSES.txt"), licenses(source_dir)).unwrap();
+    fs::write(
+        output_dir.join("permissions.md"),
+        permission_audit(manifest, &report.new_permissions),
+    )
+    .unwrap();
+
+    let mut notes = review_notes(report, manifest, target, &commit, staging_dir);
+    if let Ok(extra) = fs::read_to_string(Path::new(source_dir).join(&config.notes)) {
+        notes.push_str("\n## Notes for the reviewer\n\n");
+        notes.push_str(extra.trim());
+        notes.push('\n');
+    }
+    fs::write(output_dir.join("REVIEW_NOTES.md"), notes).unwrap();
+
+    let output_dir = output_dir
+        .strip_prefix(source_dir)
+        .unwrap_or(&output_dir)
+        .to_string_lossy()
+        .replace('\\', "/");
+    println!("Wrote the review artifacts to {output_dir}.");
+    output_dir
+}
+
+/// Archive the committed sources, returning the commit they're from.
+fn archive_sources(source_dir: &str, output: &Path) -> String {
+    let commit = git(source_dir, &["rev-parse", "HEAD"]);
+    if !git(source_dir, &["status", "--porcelain"]).is_empty() {
+        println!(
+            "Warning: the working tree has uncommitted changes, which aren't in the source archive."
+        );
+    }
+    let mut args = vec![
+        "archive".to_string(),
+        "--format=zip".to_string(),
+        format!("--output={}", output.display()),
+    ];
+    // The lock file isn't committed, but pins the dependencies the build used.
+    if Path::new(source_dir).join("Cargo.lock").exists() {
+        args.push("--add-file=Cargo.lock".to_string());
+    }
+    args.push("HEAD".to_string());
+    git(
+        source_dir,
+        &args.iter().map(String::as_str).collect::<Vec<_>>(),
+    );
+    commit
+}
+
+fn git(source_dir: &str, args: &[&str]) -> String {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(source_dir)
+        .output()
+        .expect("Failed to run git. Review builds need it to archive the sources.");
+    if !output.status.success() {
+        panic!(
+            "git {} failed:\n{}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    String::from_utf8_lossy(&output.stdout).trim().to_string()
+}
+
+/// The project's license files, then every dependency's.
+fn licenses(source_dir: &str) -> String {
+    let mut output = String::new();
+    for file in license_files(Path::new(source_dir)) {
+        append_file(&mut output, &file);
+    }
+
+    let metadata = Command::new("cargo")
+        .args([
+            "metadata",
+            "--format-version=1",
+            "--filter-platform=wasm32-unknown-unknown",
+        ])
+        .current_dir(source_dir)
+        .output()
+        .expect("Failed to run cargo metadata");
+    if !metadata.status.success() {
+        panic!(
+            "cargo metadata failed:\n{}",
+            String::from_utf8_lossy(&metadata.stderr)
+        );
+    }
+    let metadata: Value = serde_json::from_slice(&metadata.stdout).unwrap();
+    let members: BTreeSet<&str> = metadata["workspace_members"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let mut packages: Vec<&Value> = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|package| !members.contains(package["id"].as_str().unwrap_or_default()))
+        .collect();
+    packages.sort_by_key(|package| {
+        (
+            package["name"].as_str().unwrap_or_default().to_string(),
+            package["version"].as_str().unwrap_or_default().to_string(),
+        )
+    });
+
+    for package in packages {
+        let name = package["name"].as_str().unwrap_or_default();
+        let version = package["version"].as_str().unwrap_or_default();
+        let license = package["license"].as_str().unwrap_or("(not declared)");
+        let _ = writeln!(output, "{}\n{name} {version}: {license}\n", "=".repeat(80));
+        let Some(dir) = package["manifest_path"]
+            .as_str()
+            .and_then(|path| Path::new(path).parent())
+        else {
+            continue;
+        };
+        let mut files = license_files(dir);
+        if let Some(file) = package["license_file"].as_str() {
+            files.push(dir.join(file));
+        }
+        files.dedup();
+        if files.is_empty() {
+            println!("Warning: {name} {version} ships no license file.");
+        }
+        for file in files {
+            append_file(&mut output, &file);
+        }
+    }
+    output
+}
+
+/// `LICENSE*`, `LICENCE*`, `COPYING*` and `NOTICE*` files directly in `dir`.
+fn license_files(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = fs::read_dir(dir)
+        .into_iter()
+        .flatten()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .to_ascii_uppercase();
+            path.is_file()
+                && ["LICENSE", "LICENCE", "COPYING", "NOTICE"]
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix))
+        })
+        .collect();
+    files.sort();
+    files
+}
+
+fn append_file(output: &mut String, file: &Path) {
+    let Ok(contents) = fs::read_to_string(file) else {
+        return;
+    };
+    let name = file.file_name().unwrap_or_default().to_string_lossy();
+    let _ = writeln!(output, "--- {name} ---\n\n{}\n", contents.trim_end());
+}
+
+/// Every permission the manifest asks for, and what users are told about it.
+fn permission_audit(manifest: &Value, new_permissions: &[String]) -> String {
+    let strings = |key: &str| -> Vec<String> {
+        manifest
+            .get(key)
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(Value::as_str)
+            .map(str::to_string)
+            .collect()
+    };
+    let new = |key: &str, permission: &str| {
+        if new_permissions.contains(&format!("{key}:{permission}")) {
+            "yes"
+        } else {
+            ""
+        }
+    };
+
+    let mut output = String::from("# Permission audit\n");
+    let _ = writeln!(
+        output,
+        "\n## Permissions\n\n| Permission | Install warning | New |\n| --- | --- | --- |"
+    );
+    for permission in strings("permissions") {
+        let warning = WARNINGS
+            .iter()
+            .find(|(name, _)| *name == permission)
+            .map_or("", |(_, warning)| warning);
+        let _ = writeln!(
+            output,
+            "| `{permission}` | {warning} | {} |",
+            new("permissions", &permission)
+        );
+    }
+
+    let _ = writeln!(
+        output,
+        "\n## Host permissions\n\nEach host permission shows \"Read and change your data on\" the matching sites.\n\n| Host | New |\n| --- | --- |"
+    );
+    for host in strings("host_permissions") {
+        let _ = writeln!(output, "| `{host}` | {} |", new("host_permissions", &host));
+    }
+
+    let optional = strings("optional_permissions")
+        .into_iter()
+        .chain(strings("optional_host_permissions"))
+        .collect::<Vec<_>>();
+    if !optional.is_empty() {
+        let _ = writeln!(
+            output,
+            "\n## Optional permissions\n\nRequested at runtime, when the feature that needs them is used.\n"
+        );
+        for permission in optional {
+            let _ = writeln!(output, "- `{permission}`");
+        }
+    }
+
+    let matches: BTreeSet<String> = manifest
+        .get("content_scripts")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .flat_map(|script| script.get("matches").and_then(Value::as_array))
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(str::to_string)
+        .collect();
+    if !matches.is_empty() {
+        let _ = writeln!(
+            output,
+            "\n## Content script matches\n\nThese grant host access like host permissions do.\n"
+        );
+        for pattern in matches {
+            let _ = writeln!(output, "- `{pattern}`");
+        }
+    }
+    output
+}
+
+/// The build inputs and how to reproduce the build.
+fn review_notes(
+    report: &BuildReport,
+    manifest: &Value,
+    target: &str,
+    commit: &str,
+    staging_dir: &str,
+) -> String {
+    let field = |key: &str| manifest.get(key).and_then(Value::as_str).unwrap_or("?");
+    let mut output = format!("# Review notes: {} {}\n", field("name"), field("version"));
+
+    let _ = writeln!(output, "\n## Build inputs\n");
+    let _ = writeln!(output, "- Target: {target}");
+    let _ = writeln!(
+        output,
+        "- Profile: {}",
+        report.profile.as_deref().unwrap_or("(none)")
+    );
+    let _ = writeln!(output, "- Commit: `{commit}` (see `source.zip`)");
+    for tool in ["rustc", "cargo", "trunk", "wasm-bindgen", "wasm-opt"] {
+        let _ = writeln!(output, "- {tool}: {}", tool_version(tool));
+    }
+    match report.wasm_opt.first() {
+        Some(wasm_opt) => {
+            let _ = writeln!(output, "- wasm-opt flags: `{}`", wasm_opt.flags.join(" "));
+        }
+        None => {
+            let _ = writeln!(output, "- wasm-opt: not run");
+        }
+    }
+    if !report.locales.is_empty() {
+        let _ = writeln!(output, "- Locales: {}", report.locales.join(", "));
+    }
+
+    let _ = writeln!(
+        output,
+        "\n## Reproducing the build\n\nThe Rust toolchain is pinned by `rust-toolchain.toml`, and dependencies by \
+         `Cargo.lock`. From the root of `source.zip`:\n\n```sh\nWEXTRUNK_TARGET={target} trunk build --release\n```\n\n\
+         The output in `dist` is what `extension.zip` contains. The wasm file is compiled from the Rust sources in `src`, and \
+         the JavaScript next to it is generated by `wasm-bindgen` and `wextrunk`; none of it is hand-written or \
+         obfuscated."
+    );
+
+    let _ = writeln!(
+        output,
+        "\n## Packaged files\n\n| File | Bytes |\n| --- | --- |"
+    );
+    let mut files = Vec::new();
+    collect_files(Path::new(staging_dir), &mut files);
+    files.sort();
+    for file in files {
+        let size = fs::metadata(&file).unwrap().len();
+        let name = file
+            .strip_prefix(staging_dir)
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        let _ = writeln!(output, "| `{name}` | {size} |");
+    }
+    output
+}
+
+/// The first line of `tool --version`, or why it couldn't be run.
+fn tool_version(tool: &str) -> String {
+    match Command::new(tool).arg("--version").output() {
+        Ok(output) if output.status.success() => String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+        Ok(_) => "(failed to get the version)".to_string(),
+        Err(_) => "(not installed)".to_string(),
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            collect_files(&path, files);
+        } else {
+            files.push(path);
+        }
+    }
+}
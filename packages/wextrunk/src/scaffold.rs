@@ -0,0 +1,288 @@
+//! `cargo wextrunk new-page <name> [--kind <kind>] [--title <title>]`: adds a page.
+//!
+//! A page is declared in several places that have to agree: its entry file (with the
+//! `#[wext_entry]` function) and `mod` in `src/lib.rs`, its line in the context
+//! registry (`src/registry.rs`), its `<title>` in index.html, and, for sidebars, the
+//! manifests' `side_panel` (Chrome) and `sidebar_action` (Firefox). This writes all of
+//! them from the name, e.g. `reading-list` gives `src/reading_list.rs` with
+//! `reading_list_page`, the `ReadingList` context and `reading_list.html`.
+//!
+//! The kinds are the registry's page kinds (`page`, `popup`, `options`, `report`,
+//! `newtab`, `history`, `bookmarks`), whose manifest entries wextrunk fills in from
+//! the registry, plus `sidebar`, a plain page the manifests point at. Nothing is
+//! written if any of the places already has the page.
+
+use std::{fs, path::Path, process};
+
+use serde_json::{json, Map, Value};
+
+/// The kinds, with the `#[wext_entry]` context and registry kind of each.
+const KINDS: &[(&str, &str, &str)] = &[
+    ("page", "page", "Page"),
+    ("popup", "popup", "Popup"),
+    ("options", "options", "Options"),
+    ("report", "report", "Report"),
+    ("newtab", "newtab", "NewTab"),
+    ("history", "history", "History"),
+    ("bookmarks", "bookmarks", "Bookmarks"),
+    ("sidebar", "page", "Page"),
+];
+
+const USAGE: &str = "Usage: cargo wextrunk new-page <name> [--kind <kind>] [--title <title>]";
+
+/// The names a page goes by, derived from the name it's created with.
+struct Names {
+    /// `reading_list`, for the module and the output file.
+    snake: String,
+    /// `ReadingList`, for the registry.
+    camel: String,
+    /// `Reading list`, the default title.
+    title: String,
+}
+
+impl Names {
+    fn new(name: &str) -> Self {
+        let words: Vec<String> = name
+            .split(['-', '_', ' '])
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect();
+        if words.is_empty()
+            || !words
+                .iter()
+                .all(|word| word.chars().all(|c| c.is_ascii_alphanumeric()))
+            || words[0].starts_with(|c: char| c.is_ascii_digit())
+        {
+            fail(&format!(
+                "{name:?} isn't a valid page name; use letters, digits and dashes, e.g. reading-list."
+            ));
+        }
+        let capitalize = |word: &str| {
+            let mut chars = word.chars();
+            chars
+                .next()
+                .map(|first| first.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        };
+        Names {
+            snake: words.join("_"),
+            camel: words.iter().map(|word| capitalize(word)).collect(),
+            title: capitalize(&words.join(" ")),
+        }
+    }
+}
+
+/// Run `new-page` with the arguments after it.
+pub fn new_page(source_dir: &str, args: &[String]) {
+    let mut name = None;
+    let mut kind = "page".to_string();
+    let mut title = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .cloned()
+                .unwrap_or_else(|| fail(&format!("{arg} needs a value")))
+        };
+        match arg.as_str() {
+            "--kind" => kind = value(),
+            "--title" => title = Some(value()),
+            _ if arg.starts_with("--") || name.is_some() => {
+                fail(&format!("Unexpected argument {arg:?}"))
+            }
+            _ => name = Some(arg.clone()),
+        }
+    }
+    let Some(name) = name else {
+        fail("The page needs a name");
+    };
+    let Some(&(_, entry_kind, registry_kind)) = KINDS.iter().find(|(k, _, _)| *k == kind) else {
+        let kinds: Vec<_> = KINDS.iter().map(|(kind, _, _)| *kind).collect();
+        fail(&format!(
+            "Unknown kind {kind:?}; the kinds are {}",
+            kinds.join(", ")
+        ));
+    };
+    let names = Names::new(&name);
+    let title = title.unwrap_or_else(|| names.title.clone());
+    let source = Path::new(source_dir);
+    let entry = format!("{}_page", names.snake);
+    let file = format!("{}.html", names.snake);
+
+    // Read and check everything before writing anything.
+    let entry_path = source.join("src").join(format!("{}.rs", names.snake));
+    if entry_path.exists() {
+        fail(&format!("{} already exists", entry_path.display()));
+    }
+    let lib = read(&source.join("src/lib.rs"));
+    let lib = add_mod(&lib, &names.snake);
+    let registry = read(&source.join("src/registry.rs"));
+    for (taken, what) in [
+        (format!("    {}:", names.camel), "context"),
+        (format!("\"{entry}\""), "entry function"),
+        (format!("\"{file}\""), "file"),
+    ] {
+        if registry.contains(&taken) {
+            fail(&format!(
+                "The registry already has the {what} {}",
+                taken.trim()
+            ));
+        }
+    }
+    if matches!(
+        registry_kind,
+        "Popup" | "Options" | "NewTab" | "History" | "Bookmarks"
+    ) && registry.contains(&format!(": {registry_kind}, "))
+    {
+        fail(&format!(
+            "The registry already has a {kind} page, and an extension can only have one"
+        ));
+    }
+    let registry = add_context(
+        &registry,
+        &format!(
+            "{}: {registry_kind}, \"{entry}\", \"{file}\", reload = true;",
+            names.camel
+        ),
+    );
+    let index = read(&source.join("index.html"));
+    let index = add_title(&index, &names.camel.to_uppercase(), &title);
+
+    fs::write(&entry_path, entry_file(entry_kind, &entry, &title)).unwrap();
+    fs::write(source.join("src/lib.rs"), lib).unwrap();
+    fs::write(source.join("src/registry.rs"), registry).unwrap();
+    fs::write(source.join("index.html"), index).unwrap();
+    println!("Created {}", entry_path.display());
+    println!(
+        "Added {} to src/lib.rs, src/registry.rs and index.html",
+        names.snake
+    );
+    if kind == "sidebar" {
+        add_sidebar(source, &file, &title);
+    }
+}
+
+fn read(path: &Path) -> String {
+    fs::read_to_string(path)
+        .unwrap_or_else(|e| fail(&format!("Failed to read {}: {e}", path.display())))
+}
+
+/// Add `mod name;` to the private modules at the top of lib.rs, in order.
+fn add_mod(lib: &str, name: &str) -> String {
+    let line = format!("mod {name};");
+    let mut lines: Vec<&str> = lib.lines().collect();
+    let mods = lines
+        .iter()
+        .take_while(|line| line.starts_with("mod ") || line.starts_with("//"))
+        .count();
+    if lines[..mods].contains(&line.as_str()) {
+        fail(&format!("src/lib.rs already has `{line}`"));
+    }
+    let at = lines[..mods]
+        .iter()
+        .position(|existing| existing.starts_with("mod ") && *existing > line.as_str())
+        .unwrap_or(mods);
+    lines.insert(at, &line);
+    lines.join("\n") + "\n"
+}
+
+/// Add a context line to the registry, before the background (scripts come last).
+fn add_context(registry: &str, context: &str) -> String {
+    let start = registry
+        .find("contexts! {\n")
+        .unwrap_or_else(|| fail("src/registry.rs has no `contexts! { .. }` block"));
+    let block = &registry[start..];
+    let end = start + block.find("\n}").unwrap();
+    let at = registry[start..end]
+        .find("    Background:")
+        .map_or(end + 1, |offset| start + offset);
+    format!("{}    {context}\n{}", &registry[..at], &registry[at..])
+}
+
+/// Add the page's `<title>` after the other pages' ones.
+fn add_title(index: &str, include: &str, title: &str) -> String {
+    let marker = "<title data-wextrunk-include=";
+    let Some(last) = index.rfind(marker) else {
+        fail("index.html has no `<title data-wextrunk-include=..>` to add the page's after");
+    };
+    let line_start = index[..last].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = last + index[last..].find('\n').unwrap_or(index.len() - last);
+    let indent = &index[line_start..last];
+    format!(
+        "{}\n{indent}<title data-wextrunk-include=\"WEXTRUNK_{include}\">{title}</title>{}",
+        &index[..line_end],
+        &index[line_end..]
+    )
+}
+
+fn entry_file(kind: &str, entry: &str, title: &str) -> String {
+    format!(
+        "use leptos::prelude::*;
+
+use crate::{{entry::wext_entry, i18n}};
+
+#[wext_entry({kind})]
+pub async fn {entry}() {{
+    mount_to_body(|| {{
+        i18n::provide_direction();
+        view! {{
+            <main class=\"p-4\">
+                <h1 class=\"text-lg font-semibold\">\"{title}\"</h1>
+            </main>
+        }}
+    }})
+}}
+"
+    )
+}
+
+/// Point the Chrome and Firefox overlays at the sidebar page.
+fn add_sidebar(source: &Path, file: &str, title: &str) {
+    let chrome = json!({
+        "permissions": ["sidePanel"],
+        "side_panel": { "default_path": file },
+    });
+    let firefox = json!({
+        "sidebar_action": { "default_panel": file, "default_title": title },
+    });
+    for (target, overlay) in [("chrome", chrome), ("firefox", firefox)] {
+        let path = source.join(format!("manifest.{target}.json"));
+        let mut manifest: Map<String, Value> = match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents)
+                .unwrap_or_else(|e| fail(&format!("Failed to parse {}: {e}", path.display()))),
+            Err(_) => Map::new(),
+        };
+        for (key, value) in overlay.as_object().unwrap() {
+            match (manifest.get_mut(key), value) {
+                (Some(Value::Array(existing)), Value::Array(values)) => {
+                    let missing: Vec<Value> = values
+                        .iter()
+                        .filter(|v| !existing.contains(v))
+                        .cloned()
+                        .collect();
+                    existing.extend(missing);
+                }
+                (Some(existing), _) => {
+                    println!(
+                        "Warning: {} already sets {key}; left as it is: {existing}",
+                        path.display()
+                    );
+                }
+                (None, _) => {
+                    manifest.insert(key.clone(), value.clone());
+                }
+            }
+        }
+        fs::write(
+            &path,
+            serde_json::to_string_pretty(&manifest).unwrap() + "\n",
+        )
+        .unwrap();
+        println!("Pointed {} at {file}", path.display());
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{message}\n{USAGE}");
+    process::exit(1);
+}
@@ -0,0 +1,518 @@
+//! Validating the output manifest before it's written.
+//!
+//! Browsers mostly ignore what they don't understand in a manifest, with at best a
+//! warning on the extensions page, so a misspelt key or permission silently does
+//! nothing, and the stores reject some mistakes only at upload. The final manifest
+//! (after the MV2 conversion, if any) is checked against the embedded schema
+//! (`schema.json`: which browsers and manifest versions support each key, and which
+//! browsers have each permission) for the target's browser (Firefox for the `firefox`
+//! target, Chrome otherwise):
+//!
+//! - `name`, `version` and `manifest_version` must be there;
+//! - unknown keys and permissions fail the build, with the closest known one
+//!   suggested; ones only the other browser supports are reported, as they're ignored;
+//! - MV3-only keys and shapes (`action`, `host_permissions`, a service worker, ...)
+//!   fail an MV2 manifest, and MV2 ones an MV3 manifest;
+//! - every match pattern (host permissions, content scripts, web accessible resources,
+//!   `externally_connectable`) must be well-formed.
+
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Manifest keys and permissions per browser.
+const DATASET: &str = include_str!("schema.json");
+
+/// The schema of every manifest key and permission.
+#[derive(Deserialize)]
+struct Schema {
+    /// The manifest versions each browser supports a key in.
+    keys: BTreeMap<String, BTreeMap<String, Vec<u8>>>,
+    /// The browsers that have each permission.
+    permissions: BTreeMap<String, Vec<String>>,
+}
+
+/// Keys every manifest must have.
+const REQUIRED_KEYS: &[&str] = &["manifest_version", "name", "version"];
+
+/// What to use instead of a key that's only in the other manifest version.
+const REPLACEMENTS: &[(&str, &str)] = &[
+    ("action", "browser_action"),
+    ("browser_action", "action"),
+    ("host_permissions", "permissions"),
+    ("optional_host_permissions", "optional_permissions"),
+];
+
+/// The browser a target is for: Firefox for `firefox` and its variants, like
+/// `firefox-android`, Chrome otherwise.
+pub fn browser_of(target: &str) -> &'static str {
+    if target == "firefox" || target.starts_with("firefox-") {
+        "firefox"
+    } else {
+        "chrome"
+    }
+}
+
+/// Whether `browser` has the API permission `permission`.
+pub fn has_permission(browser: &str, permission: &str) -> bool {
+    let schema: Schema = serde_json::from_str(DATASET).unwrap();
+    schema
+        .permissions
+        .get(permission)
+        .is_some_and(|browsers| browsers.iter().any(|name| name == browser))
+}
+
+/// Check the output manifest for `target`, failing with every problem found.
+pub fn validate_manifest(manifest: &Value, target: &str) {
+    let browser = browser_of(target);
+    let (errors, ignored) = problems(manifest, browser);
+    if !ignored.is_empty() {
+        println!(
+            "Warning: {browser} ignores {} in the manifest.",
+            ignored.join(", ")
+        );
+    }
+    if !errors.is_empty() {
+        panic!(
+            "The {target} manifest isn't valid:\n  {}",
+            errors.join("\n  ")
+        );
+    }
+}
+
+/// The manifest's errors, and what `browser` ignores in it.
+fn problems(manifest: &Value, browser: &str) -> (Vec<String>, Vec<String>) {
+    let schema: Schema = serde_json::from_str(DATASET).unwrap();
+    let manifest = manifest
+        .as_object()
+        .expect("The manifest must be a JSON object");
+    let version = manifest
+        .get("manifest_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(3) as u8;
+
+    let mut errors = Vec::new();
+    let mut ignored = Vec::new();
+    for key in REQUIRED_KEYS {
+        if !manifest.contains_key(*key) {
+            errors.push(format!("`{key}` is missing."));
+        }
+    }
+
+    for key in manifest.keys() {
+        let Some(support) = schema.keys.get(key) else {
+            errors.push(format!(
+                "`{key}` isn't a manifest key.{}",
+                suggestion(key, schema.keys.keys())
+            ));
+            continue;
+        };
+        match support.get(browser) {
+            None => ignored.push(format!("`{key}`")),
+            Some(versions) if !versions.contains(&version) => {
+                let replacement = REPLACEMENTS
+                    .iter()
+                    .find(|(from, _)| from == key)
+                    .map(|(_, to)| format!("; use `{to}` instead"))
+                    .unwrap_or_default();
+                errors.push(format!(
+                    "`{key}` isn't supported in Manifest V{version}{replacement}."
+                ));
+            }
+            Some(_) => {}
+        }
+    }
+    check_shapes(manifest, version, browser, &mut errors);
+
+    for key in ["permissions", "optional_permissions"] {
+        for permission in strings(manifest.get(key)) {
+            if permission == "<all_urls>" || permission.contains("://") {
+                if version >= 3 {
+                    errors.push(format!(
+                        "{key} lists the match pattern `{permission}`; in Manifest V3, it goes in `{}`.",
+                        key.replace("permissions", "host_permissions")
+                    ));
+                } else if let Err(problem) = check_pattern(permission) {
+                    errors.push(format!("{key}: `{permission}` {problem}."));
+                }
+                continue;
+            }
+            match schema.permissions.get(permission) {
+                None => errors.push(format!(
+                    "{key} lists `{permission}`, which isn't a permission.{}",
+                    suggestion(permission, schema.permissions.keys())
+                )),
+                Some(browsers) if !browsers.iter().any(|name| name == browser) => {
+                    ignored.push(format!("the `{permission}` permission"));
+                }
+                Some(_) => {}
+            }
+        }
+    }
+
+    let mut patterns: Vec<(String, &str)> = Vec::new();
+    for key in ["host_permissions", "optional_host_permissions"] {
+        patterns.extend(strings(manifest.get(key)).map(|pattern| (key.to_string(), pattern)));
+    }
+    for (index, script) in array(manifest.get("content_scripts")).iter().enumerate() {
+        for key in ["matches", "exclude_matches"] {
+            let at = format!("content_scripts[{index}].{key}");
+            patterns.extend(strings(script.get(key)).map(|pattern| (at.clone(), pattern)));
+        }
+    }
+    for (index, entry) in array(manifest.get("web_accessible_resources"))
+        .iter()
+        .enumerate()
+    {
+        let at = format!("web_accessible_resources[{index}].matches");
+        patterns.extend(strings(entry.get("matches")).map(|pattern| (at.clone(), pattern)));
+    }
+    if let Some(connectable) = manifest.get("externally_connectable") {
+        patterns.extend(
+            strings(connectable.get("matches"))
+                .map(|pattern| ("externally_connectable.matches".to_string(), pattern)),
+        );
+    }
+    for (at, pattern) in patterns {
+        if let Err(problem) = check_pattern(pattern) {
+            errors.push(format!("{at}: `{pattern}` {problem}."));
+        }
+    }
+
+    (errors, ignored)
+}
+
+/// Check the shapes of values that differ between manifest versions.
+fn check_shapes(
+    manifest: &Map<String, Value>,
+    version: u8,
+    browser: &str,
+    errors: &mut Vec<String>,
+) {
+    let background = manifest.get("background");
+    let has = |key: &str| background.is_some_and(|background| background.get(key).is_some());
+    if version >= 3 {
+        if browser == "chrome" && (has("scripts") || has("page")) {
+            errors.push(
+                "`background` needs a `service_worker` in Chrome's Manifest V3, not `scripts` or `page`."
+                    .to_string(),
+            );
+        }
+        if background.and_then(|background| background.get("persistent"))
+            == Some(&Value::Bool(true))
+        {
+            errors.push("Manifest V3 backgrounds can't be `persistent`.".to_string());
+        }
+        if manifest
+            .get("content_security_policy")
+            .is_some_and(Value::is_string)
+        {
+            errors.push(
+                "`content_security_policy` must be an object in Manifest V3, e.g. `{ \"extension_pages\": \"...\" }`."
+                    .to_string(),
+            );
+        }
+        if array(manifest.get("web_accessible_resources"))
+            .iter()
+            .any(Value::is_string)
+        {
+            errors.push(
+                "`web_accessible_resources` entries must be objects with `resources` and `matches` in Manifest V3."
+                    .to_string(),
+            );
+        }
+    } else {
+        if has("service_worker") {
+            errors.push(
+                "`background.service_worker` isn't supported in Manifest V2; use `scripts`."
+                    .to_string(),
+            );
+        }
+        if manifest
+            .get("content_security_policy")
+            .is_some_and(Value::is_object)
+        {
+            errors.push("`content_security_policy` must be a string in Manifest V2.".to_string());
+        }
+        if array(manifest.get("web_accessible_resources"))
+            .iter()
+            .any(Value::is_object)
+        {
+            errors.push(
+                "`web_accessible_resources` must be a list of paths in Manifest V2.".to_string(),
+            );
+        }
+    }
+}
+
+/// Check a match pattern, e.g. `https://*.example.com/*`.
+fn check_pattern(pattern: &str) -> Result<(), &'static str> {
+    const SCHEMES: &[&str] = &["*", "http", "https", "ws", "wss", "ftp", "file", "urn"];
+    if pattern == "<all_urls>" {
+        return Ok(());
+    }
+    let Some((scheme, rest)) = pattern.split_once("://") else {
+        return Err("isn't a match pattern; they look like `https://*.example.com/*`");
+    };
+    if !SCHEMES.contains(&scheme) {
+        return Err("has a scheme match patterns don't support; use `*`, `http`, `https`, `ws`, `wss`, `ftp`, `file` or `urn`");
+    }
+    let Some(slash) = rest.find('/') else {
+        return Err("has no path; end it with `/*` to match every page");
+    };
+    let host = &rest[..slash];
+    if host.is_empty() && scheme != "file" {
+        return Err("has no host; use `*` to match every host");
+    }
+    if host != "*" && host.strip_prefix("*.").unwrap_or(host).contains('*') {
+        return Err("can only have `*` as the whole host, or at its start, as in `*.example.com`");
+    }
+    Ok(())
+}
+
+/// " Did you mean `x`?" for the closest known name, if one is close.
+fn suggestion<'a>(name: &str, known: impl Iterator<Item = &'a String>) -> String {
+    known
+        .map(|candidate| (distance(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= 2)
+        .min()
+        .map(|(_, candidate)| format!(" Did you mean `{candidate}`?"))
+        .unwrap_or_default()
+}
+
+/// The edit distance between two names, ignoring case.
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<_> = a.to_lowercase().chars().collect();
+    let b: Vec<_> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a != b);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// The strings in an array value.
+fn strings(value: Option<&Value>) -> impl Iterator<Item = &str> {
+    array(value).iter().filter_map(Value::as_str)
+}
+
+fn array(value: Option<&Value>) -> &[Value] {
+    value.and_then(Value::as_array).map_or(&[], Vec::as_slice)
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{json, Value};
+
+    use super::{browser_of, check_pattern, problems, validate_manifest};
+
+    /// A valid manifest of `version`, with `extra` keys added.
+    fn manifest(version: u8, extra: Value) -> Value {
+        let mut manifest = json!({
+            "manifest_version": version,
+            "name": "Test",
+            "version": "1.0",
+        });
+        for (key, value) in extra.as_object().unwrap() {
+            manifest[key] = value.clone();
+        }
+        manifest
+    }
+
+    fn errors(manifest: &Value, browser: &str) -> Vec<String> {
+        problems(manifest, browser).0
+    }
+
+    #[test]
+    fn tells_the_browser_of_a_target() {
+        assert_eq!(browser_of("chrome"), "chrome");
+        assert_eq!(browser_of("edge"), "chrome");
+        assert_eq!(browser_of("firefox"), "firefox");
+        assert_eq!(browser_of("firefox-android"), "firefox");
+        assert_eq!(browser_of("firefoxish"), "chrome");
+    }
+
+    #[test]
+    fn accepts_valid_manifests() {
+        let mv3 = manifest(
+            3,
+            json!({
+                "action": { "default_popup": "popup.html" },
+                "background": { "service_worker": "background.js" },
+                "permissions": ["storage", "tabs"],
+                "host_permissions": ["https://*.example.com/*"],
+                "content_scripts": [{ "matches": ["<all_urls>"], "js": ["content.js"] }],
+                "content_security_policy": { "extension_pages": "script-src 'self'" },
+                "web_accessible_resources": [{ "resources": ["a.png"], "matches": ["*://*/*"] }],
+            }),
+        );
+        assert_eq!(problems(&mv3, "chrome"), (vec![], vec![]));
+
+        let mv2 = manifest(
+            2,
+            json!({
+                "browser_action": { "default_popup": "popup.html" },
+                "background": { "scripts": ["background.js"], "persistent": false },
+                "permissions": ["storage", "https://example.com/*"],
+                "content_security_policy": "script-src 'self'",
+                "web_accessible_resources": ["a.png"],
+            }),
+        );
+        assert_eq!(problems(&mv2, "firefox"), (vec![], vec![]));
+
+        // Firefox runs MV3 backgrounds as event pages.
+        let scripts = manifest(3, json!({ "background": { "scripts": ["background.js"] } }));
+        assert_eq!(errors(&scripts, "firefox"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn rejects_missing_and_unknown_keys() {
+        let mut manifest = manifest(3, json!({ "permisions": ["storage"] }));
+        manifest.as_object_mut().unwrap().remove("version");
+        assert_eq!(
+            errors(&manifest, "chrome"),
+            [
+                "`version` is missing.",
+                "`permisions` isn't a manifest key. Did you mean `permissions`?",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_keys_of_the_other_manifest_version() {
+        let mv2 = manifest(
+            2,
+            json!({ "action": {}, "host_permissions": ["https://example.com/*"] }),
+        );
+        assert_eq!(
+            errors(&mv2, "chrome"),
+            [
+                "`action` isn't supported in Manifest V2; use `browser_action` instead.",
+                "`host_permissions` isn't supported in Manifest V2; use `permissions` instead.",
+            ]
+        );
+
+        let mv3 = manifest(3, json!({ "browser_action": {} }));
+        assert_eq!(
+            errors(&mv3, "chrome"),
+            ["`browser_action` isn't supported in Manifest V3; use `action` instead."]
+        );
+    }
+
+    #[test]
+    fn rejects_shapes_of_the_other_manifest_version() {
+        let mv3 = manifest(
+            3,
+            json!({
+                "background": { "scripts": ["background.js"], "persistent": true },
+                "content_security_policy": "script-src 'self'",
+                "web_accessible_resources": ["a.png"],
+            }),
+        );
+        assert_eq!(
+            errors(&mv3, "chrome"),
+            [
+                "`background` needs a `service_worker` in Chrome's Manifest V3, not `scripts` or `page`.",
+                "Manifest V3 backgrounds can't be `persistent`.",
+                "`content_security_policy` must be an object in Manifest V3, e.g. `{ \"extension_pages\": \"...\" }`.",
+                "`web_accessible_resources` entries must be objects with `resources` and `matches` in Manifest V3.",
+            ]
+        );
+
+        let mv2 = manifest(
+            2,
+            json!({
+                "background": { "service_worker": "background.js" },
+                "content_security_policy": { "extension_pages": "script-src 'self'" },
+                "web_accessible_resources": [{ "resources": ["a.png"], "matches": ["<all_urls>"] }],
+            }),
+        );
+        assert_eq!(
+            errors(&mv2, "firefox"),
+            [
+                "`background.service_worker` isn't supported in Manifest V2; use `scripts`.",
+                "`content_security_policy` must be a string in Manifest V2.",
+                "`web_accessible_resources` must be a list of paths in Manifest V2.",
+            ]
+        );
+    }
+
+    #[test]
+    fn checks_permissions() {
+        let manifest = manifest(
+            3,
+            json!({
+                "permissions": ["storag", "https://example.com/*", "menus"],
+                "optional_permissions": ["<all_urls>"],
+            }),
+        );
+        let (errors, ignored) = problems(&manifest, "chrome");
+        assert_eq!(
+            errors,
+            [
+                "permissions lists `storag`, which isn't a permission. Did you mean `storage`?",
+                "permissions lists the match pattern `https://example.com/*`; in Manifest V3, it goes in `host_permissions`.",
+                "optional_permissions lists the match pattern `<all_urls>`; in Manifest V3, it goes in `optional_host_permissions`.",
+            ]
+        );
+        assert_eq!(ignored, ["the `menus` permission"]);
+    }
+
+    #[test]
+    fn reports_keys_only_the_other_browser_has() {
+        let manifest = manifest(
+            3,
+            json!({
+                "browser_specific_settings": { "gecko": { "id": "test@example.com" } },
+                "side_panel": { "default_path": "sidebar.html" },
+            }),
+        );
+        assert_eq!(
+            problems(&manifest, "chrome"),
+            (vec![], vec!["`browser_specific_settings`".to_string()])
+        );
+        assert_eq!(
+            problems(&manifest, "firefox"),
+            (vec![], vec!["`side_panel`".to_string()])
+        );
+    }
+
+    #[test]
+    fn checks_match_patterns() {
+        let manifest = manifest(
+            3,
+            json!({
+                "host_permissions": ["example.com"],
+                "content_scripts": [{ "matches": ["https://*.example.com/*", "chrome://newtab/*"] }],
+                "web_accessible_resources": [{ "resources": ["a.png"], "matches": ["https://example.com"] }],
+                "externally_connectable": { "matches": ["https://www.*.com/*", "file:///*"] },
+            }),
+        );
+        assert_eq!(
+            errors(&manifest, "chrome"),
+            [
+                "host_permissions: `example.com` isn't a match pattern; they look like `https://*.example.com/*`.",
+                "content_scripts[0].matches: `chrome://newtab/*` has a scheme match patterns don't support; use `*`, `http`, `https`, `ws`, `wss`, `ftp`, `file` or `urn`.",
+                "web_accessible_resources[0].matches: `https://example.com` has no path; end it with `/*` to match every page.",
+                "externally_connectable.matches: `https://www.*.com/*` can only have `*` as the whole host, or at its start, as in `*.example.com`.",
+            ]
+        );
+        let no_host = check_pattern("https:///*");
+        assert_eq!(no_host, Err("has no host; use `*` to match every host"));
+    }
+
+    #[test]
+    #[should_panic(expected = "The firefox manifest isn't valid:\n  `name` is missing.")]
+    fn validate_manifest_fails_the_build() {
+        validate_manifest(
+            &json!({ "manifest_version": 3, "version": "1.0" }),
+            "firefox",
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Skeleton markup shown before the wasm mounts.
+//!
+//! A page is blank until the wasm has loaded and the entry function has mounted its
+//! view, which for a cold popup can be hundreds of milliseconds of white box. A
+//! skeleton is static markup rendered with the page instead, from an element of
+//! index.html marked `data-wextrunk-skeleton="<page name>"` (or with no value, for
+//! every page), or from a file set for the page in `[skeletons]` in `wextrunk.toml`,
+//! e.g. `"popup.html" = "skeletons/popup.html"`. Either way, it's marked
+//! `data-wext-skeleton` and `aria-busy`, and the shim removes it once the entry
+//! function returns (or the runtime's `loading::done()` does, earlier).
+
+use std::{fs, path::Path};
+
+/// The attribute marking skeletons in the output, which the shim and runtime look for.
+pub const SKELETON_ATTRIBUTE: &str = "data-wext-skeleton";
+
+/// Removes the page's skeletons, once the entry function has returned.
+pub const REMOVE_SKELETONS: &str =
+    "document.querySelectorAll('[data-wext-skeleton]').forEach((skeleton) => skeleton.remove());\n";
+
+/// A page's skeleton file, wrapped to be prepended to its `<body>`.
+pub fn load_skeleton(file: &str, source_dir: &str) -> String {
+    let contents = fs::read_to_string(Path::new(source_dir).join(file))
+        .unwrap_or_else(|e| panic!("Failed to read the skeleton {file}: {e}"));
+    format!(r#"<div {SKELETON_ATTRIBUTE} aria-busy="true">{contents}</div>"#)
+}
+
+/// Whether a `data-wextrunk-skeleton` element in index.html is for the page `name`.
+pub fn is_for_page(value: Option<&str>, name: &str) -> bool {
+    value.is_none_or(|value| value.is_empty() || value == name)
+}
@@ -0,0 +1,73 @@
+//! An SVG sprite of the extension's icons.
+//!
+//! Every `.svg` file in `[sprite] dir` becomes a `<symbol id="icon-<file stem>">` of
+//! one sprite, so pages show any number of icons (with the runtime's `<Icon>`) without
+//! a request per icon. The sprite is written to `sprite.svg` and referenced with
+//! `<use href="/sprite.svg#icon-name">`, or with `inline = true`, injected at the top
+//! of every page's `<body>` and referenced by id. Icons should use `currentColor`, so
+//! they take the text colour; ids inside icons aren't renamed, so keep them unique.
+
+use std::{fs, path::Path};
+
+use crate::config::Sprite;
+
+/// The sprite's file, relative to the staging directory.
+pub const SPRITE_FILE: &str = "sprite.svg";
+
+/// The id of the inlined sprite, which the runtime looks for.
+const SPRITE_ID: &str = "wext-sprite";
+
+/// Build the sprite from the icons in `config.dir`, as an `<svg>` element.
+pub fn build_sprite(config: &Sprite, source_dir: &str) -> Option<String> {
+    let dir = Path::new(source_dir).join(config.dir.as_ref()?);
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("Failed to read the sprite directory {}: {e}", dir.display()))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "svg"))
+        .collect();
+    files.sort();
+
+    let mut sprite = format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" id="{SPRITE_ID}" style="display: none">"#
+    );
+    for file in files {
+        let name = file.file_stem().unwrap().to_string_lossy();
+        let svg = fs::read_to_string(&file).unwrap();
+        let (view_box, content) = parse_svg(&svg)
+            .unwrap_or_else(|| panic!("{} isn't an SVG with a viewBox", file.display()));
+        sprite.push_str(&format!(
+            r#"<symbol id="icon-{name}" viewBox="{view_box}">{content}</symbol>"#
+        ));
+    }
+    sprite.push_str("</svg>");
+    Some(sprite)
+}
+
+/// Write the sprite to the staging directory, for pages that don't inline it.
+pub fn write_sprite(sprite: &str, staging_dir: &str) {
+    fs::write(Path::new(staging_dir).join(SPRITE_FILE), sprite).unwrap();
+}
+
+/// The `viewBox` of an SVG document's root element, and its content.
+fn parse_svg(svg: &str) -> Option<(String, &str)> {
+    let start = svg.find("<svg")?;
+    let tag_end = start + svg[start..].find('>')?;
+    let tag = &svg[start..tag_end];
+    let end = svg.rfind("</svg>")?;
+    Some((
+        attribute(tag, "viewBox")?.to_string(),
+        svg[tag_end + 1..end].trim(),
+    ))
+}
+
+/// An attribute of a start tag.
+fn attribute<'a>(tag: &'a str, name: &str) -> Option<&'a str> {
+    let pattern = format!("{name}=");
+    let (at, _) = tag
+        .match_indices(&pattern)
+        .find(|(at, _)| tag[..*at].ends_with(char::is_whitespace))?;
+    let value = &tag[at + pattern.len()..];
+    let quote = value.chars().next()?;
+    let value = &value[1..];
+    Some(&value[..value.find(quote)?])
+}
@@ -0,0 +1,119 @@
+//! Stable output file names, with `[output] stable_names = true`.
+//!
+//! Trunk names the files it emits after their content hash, e.g.
+//! `leptos_extension-5f1c0a8e2b7d9e34_bg.wasm`, so the names change with every build.
+//! Extensions don't need the cache busting, and anything referring to these files by
+//! path (the manifest's `web_accessible_resources`, other tooling, store diffs) is
+//! easier to keep right with fixed names. In this mode, the hashes are stripped before
+//! anything else runs: the files are renamed, every reference in the staged HTML, JS
+//! and CSS is rewritten, and the hashes are recorded in `asset-manifest.json`, keyed by
+//! the stable name.
+
+use std::{collections::BTreeMap, fs, path::Path};
+
+use serde_json::json;
+
+/// Extensions of the files Trunk hashes.
+const HASHED_EXTENSIONS: &[&str] = &["wasm", "js", "css"];
+
+/// Written to the staging directory: the stable names, with their hashes.
+const ASSET_MANIFEST: &str = "asset-manifest.json";
+
+/// Rename the hashed files in the staging directory and rewrite the references to
+/// them, returning how many were renamed.
+pub fn stabilize_names(staging_dir: &str) -> usize {
+    // Stable name, hashed name, hash.
+    let mut renames: Vec<(String, String, String)> = Vec::new();
+    for entry in fs::read_dir(staging_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if !path.is_file() {
+            continue;
+        }
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if let Some((stable, hash)) = split_hash(&name) {
+            renames.push((stable, name, hash));
+        }
+    }
+    renames.sort();
+    for pair in renames.windows(2) {
+        if pair[0].0 == pair[1].0 {
+            panic!(
+                "{} and {} would both be named {}; remove stale files from the staging directory.",
+                pair[0].1, pair[1].1, pair[0].0
+            );
+        }
+    }
+    if renames.is_empty() {
+        return 0;
+    }
+
+    for (stable, hashed, _) in &renames {
+        fs::rename(
+            Path::new(staging_dir).join(hashed),
+            Path::new(staging_dir).join(stable),
+        )
+        .unwrap();
+    }
+    rewrite_references(Path::new(staging_dir), &renames);
+
+    let assets: BTreeMap<&str, _> = renames
+        .iter()
+        .map(|(stable, hashed, hash)| (stable.as_str(), json!({ "hash": hash, "file": hashed })))
+        .collect();
+    let mut output = serde_json::to_string_pretty(&assets).unwrap();
+    output.push('\n');
+    fs::write(Path::new(staging_dir).join(ASSET_MANIFEST), output).unwrap();
+    println!(
+        "Renamed {} hashed file(s) to stable names; hashes are in {ASSET_MANIFEST}.",
+        renames.len()
+    );
+    renames.len()
+}
+
+/// Split a Trunk file name into the stable name and the hash, e.g.
+/// `app-0123456789abcdef_bg.wasm` into `app_bg.wasm` and `0123456789abcdef`.
+pub fn split_hash(name: &str) -> Option<(String, String)> {
+    let (stem, extension) = name.rsplit_once('.')?;
+    if !HASHED_EXTENSIONS.contains(&extension) {
+        return None;
+    }
+    let (stem, suffix) = match stem.strip_suffix("_bg") {
+        Some(stem) => (stem, "_bg"),
+        None => (stem, ""),
+    };
+    let (base, hash) = stem.rsplit_once('-')?;
+    // Trunk writes the 64-bit hash as hex, without leading zeros.
+    let is_hash = (8..=16).contains(&hash.len())
+        && hash
+            .chars()
+            .all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c));
+    if base.is_empty() || !is_hash {
+        return None;
+    }
+    Some((format!("{base}{suffix}.{extension}"), hash.to_string()))
+}
+
+/// Replace the hashed names with the stable ones in every HTML, JS and CSS file.
+fn rewrite_references(dir: &Path, renames: &[(String, String, String)]) {
+    for entry in fs::read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            rewrite_references(&path, renames);
+            continue;
+        }
+        if !path
+            .extension()
+            .is_some_and(|ext| ext == "html" || ext == "js" || ext == "css")
+        {
+            continue;
+        }
+        let contents = fs::read_to_string(&path).unwrap();
+        let mut rewritten = contents.clone();
+        for (stable, hashed, _) in renames {
+            rewritten = rewritten.replace(hashed.as_str(), stable);
+        }
+        if rewritten != contents {
+            fs::write(&path, rewritten).unwrap();
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Building every target in one pass, with `WEXTRUNK_TARGET=all`.
+//!
+//! Trunk's output is parsed once; each target declared in index.html or `wextrunk.toml`
+//! then gets its own copy of the staging directory, `<target>/`, which goes through the
+//! per-target stages (manifest, wasm-opt, locales, packaging, ...). The wasm, the
+//! biggest file, is hard-linked into the targets that don't run wasm-opt on it, and
+//! copied into the ones that do. Once every target is built, only the target
+//! directories are left, so Trunk's `dist` ends up as `dist/chrome/`, `dist/firefox/`,
+//! and so on.
+
+use std::{fs, path::Path};
+
+/// The `WEXTRUNK_TARGET` value that builds every target.
+pub const ALL_TARGETS: &str = "all";
+
+/// Copy the staging directory's contents, except index.html and the other targets'
+/// directories, into `<staging_dir>/<target>`, returning its path.
+pub fn stage_target(
+    staging_dir: &str,
+    target: &str,
+    targets: &[String],
+    link_wasm: bool,
+) -> String {
+    let target_dir = Path::new(staging_dir).join(target);
+    if target_dir.exists() {
+        fs::remove_dir_all(&target_dir).unwrap();
+    }
+    fs::create_dir_all(&target_dir).unwrap();
+    for entry in fs::read_dir(staging_dir).unwrap() {
+        let path = entry.unwrap().path();
+        let name = path.file_name().unwrap().to_string_lossy().to_string();
+        if name == "index.html" || targets.contains(&name) {
+            continue;
+        }
+        copy(&path, &target_dir.join(&name), link_wasm);
+    }
+    target_dir.to_string_lossy().into_owned()
+}
+
+/// Remove everything but the target directories from the staging directory.
+pub fn remove_shared(staging_dir: &str, targets: &[String]) {
+    for entry in fs::read_dir(staging_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if targets.contains(&path.file_name().unwrap().to_string_lossy().to_string()) {
+            continue;
+        }
+        if path.is_dir() {
+            fs::remove_dir_all(&path).unwrap();
+        } else {
+            fs::remove_file(&path).unwrap();
+        }
+    }
+}
+
+/// Copy `from` to `to` recursively, hard-linking the wasm if `link_wasm`. Stages
+/// rewrite files in place, which would write through a hard link into every target,
+/// so nothing else is linked.
+fn copy(from: &Path, to: &Path, link_wasm: bool) {
+    if from.is_dir() {
+        fs::create_dir_all(to).unwrap();
+        for entry in fs::read_dir(from).unwrap() {
+            let path = entry.unwrap().path();
+            copy(&path, &to.join(path.file_name().unwrap()), link_wasm);
+        }
+        return;
+    }
+    let is_wasm = from.extension().is_some_and(|ext| ext == "wasm");
+    // Linking fails across file systems, and on some network and FAT drives.
+    if !(link_wasm && is_wasm && fs::hard_link(from, to).is_ok()) {
+        fs::copy(from, to).unwrap();
+    }
+}
@@ -0,0 +1,371 @@
+//! Parsing the inline script Trunk puts in index.html.
+//!
+//! The script imports the wasm-bindgen module, calls its `init` with the wasm's path,
+//! dispatches `TrunkApplicationStarted`, and with `trunk serve`, connects to the dev
+//! server's auto-reload socket. Every shim wextrunk writes is put together from these
+//! parts, so they're found by parsing the script rather than by searching its text,
+//! which would break with any change in Trunk's formatting: the import is the
+//! declaration importing a default binding, the init is the statement calling it, the
+//! dispatch is the `dispatchEvent(..)` statement after it, and the auto-reload code
+//! is everything from the first statement after those that mentions the dev server's
+//! address.
+//!
+//! With `data-initializer` on the rust link, Trunk instead imports the initializer
+//! module, defines `__trunkInitializer`, and passes `init` to it with the wasm's path,
+//! so it can report the download's progress to the initializer's callbacks
+//! (`onStart`, `onProgress`, `onComplete`, `onSuccess` and `onFailure`). That call
+//! counts as the init, and the import, the helper and the call are kept as they are.
+//!
+//! A script without these parts, e.g. from a Trunk version that writes it differently,
+//! fails with what's missing.
+
+use oxc::{
+    allocator::Allocator,
+    ast::ast::{
+        Argument, Expression, ImportDeclarationSpecifier, ObjectExpression, ObjectPropertyKind,
+        Statement,
+    },
+    parser::Parser,
+    span::{GetSpan, SourceType, Span},
+};
+
+/// Placeholder for the dev server's address in the auto-reload code.
+pub const TRUNK_ADDRESS: &str = "{{__TRUNK_ADDRESS__}}";
+
+/// The parts of Trunk's script, as source text.
+#[derive(Debug)]
+pub struct TrunkScript {
+    /// The module imports, e.g. `import init, * as bindings from '/app-0123.js';`,
+    /// and the initializer's, if there is one.
+    pub import: String,
+    /// Everything else up to the dispatch, with `init`'s argument wrapped in
+    /// `{module_or_path: ..}`, as wasm-bindgen now expects.
+    pub init: String,
+    /// Whether `init` is called through Trunk's initializer.
+    pub initializer: bool,
+    /// The `dispatchEvent(..)` statement, if there is one.
+    pub dispatch_event: Option<String>,
+    /// The auto-reload code, if there is any.
+    pub auto_reload: Option<String>,
+    /// The imported module's path, e.g. `/app-0123.js`.
+    pub module_path: String,
+    /// The wasm's path, e.g. `/app-0123_bg.wasm`.
+    pub wasm_path: String,
+}
+
+impl TrunkScript {
+    /// Find the parts of Trunk's script, or describe what it lacks.
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let allocator = Allocator::default();
+        let parsed = Parser::new(&allocator, source, SourceType::mjs()).parse();
+        if let Some(error) = parsed.errors.first() {
+            return Err(format!("isn't valid JavaScript: {error}"));
+        }
+        let body = &parsed.program.body;
+        let text = |span: Span| source[span.start as usize..span.end as usize].to_string();
+
+        let imports: Vec<_> = body
+            .iter()
+            .filter_map(|statement| match statement {
+                Statement::ImportDeclaration(import) => Some(import),
+                _ => None,
+            })
+            .collect();
+        // Every default import could be wasm-bindgen's `init`, or the initializer; it's
+        // the one the init call passes or calls.
+        let defaults: Vec<_> = imports
+            .iter()
+            .flat_map(|import| {
+                import.specifiers.iter().flatten().filter_map(|specifier| {
+                    let ImportDeclarationSpecifier::ImportDefaultSpecifier(default) = specifier
+                    else {
+                        return None;
+                    };
+                    Some((default.local.name.as_str(), import.source.value.as_str()))
+                })
+            })
+            .collect();
+        if defaults.is_empty() {
+            return Err("doesn't import the wasm-bindgen module".to_string());
+        }
+
+        let (init_index, call, module_path) = body
+            .iter()
+            .enumerate()
+            .find_map(|(index, statement)| {
+                defaults.iter().find_map(|&(name, path)| {
+                    Some((index, init_call(statement, name)?, path.to_string()))
+                })
+            })
+            .ok_or("doesn't call the wasm-bindgen module's init")?;
+
+        let dispatch_index = body
+            .iter()
+            .enumerate()
+            .skip(init_index + 1)
+            .find(|(_, statement)| is_dispatch_event(statement))
+            .map(|(index, _)| index);
+        let auto_reload_index = body
+            .iter()
+            .enumerate()
+            .skip(dispatch_index.unwrap_or(init_index) + 1)
+            .find(|(_, statement)| text(statement.span()).contains(TRUNK_ADDRESS))
+            .map(|(index, _)| index);
+
+        // The init part is every other statement up to the dispatch, or the auto-reload
+        // code, or the end. Imports can't go in it, as it may end up in a block.
+        let init_end = dispatch_index.or(auto_reload_index).unwrap_or(body.len());
+        let initializer = matches!(call, InitCall::Initializer(_));
+        let (init_call, wasm_path) = match call {
+            InitCall::Direct(Some(Argument::StringLiteral(wasm))) => {
+                let statement = body[init_index].span();
+                (
+                    format!(
+                        "{}{{module_or_path: {}}}{}",
+                        text(Span::new(statement.start, wasm.span.start)),
+                        text(wasm.span),
+                        text(Span::new(wasm.span.end, statement.end)),
+                    ),
+                    wasm.value.to_string(),
+                )
+            }
+            // Already in the new form.
+            InitCall::Direct(Some(Argument::ObjectExpression(options))) => (
+                text(body[init_index].span()),
+                module_or_path(options).ok_or("passes init options without module_or_path")?,
+            ),
+            InitCall::Initializer(Some(Argument::StringLiteral(wasm))) => {
+                (text(body[init_index].span()), wasm.value.to_string())
+            }
+            _ => return Err("doesn't pass the wasm's path to init".to_string()),
+        };
+        let mut init = String::new();
+        for (index, statement) in body.iter().enumerate().take(init_end) {
+            if matches!(statement, Statement::ImportDeclaration(_)) {
+                continue;
+            }
+            if index == init_index {
+                init.push_str(&init_call);
+            } else {
+                init.push_str(&text(statement.span()));
+            }
+            init.push('\n');
+        }
+
+        Ok(TrunkScript {
+            import: imports
+                .iter()
+                .map(|import| format!("{}\n", text(import.span)))
+                .collect(),
+            init,
+            initializer,
+            dispatch_event: dispatch_index.map(|index| format!("{}\n", text(body[index].span()))),
+            auto_reload: auto_reload_index
+                .map(|index| source[body[index].span().start as usize..].to_string()),
+            module_path,
+            wasm_path,
+        })
+    }
+}
+
+/// How a statement calls `init`.
+enum InitCall<'s, 'a> {
+    /// `const wasm = await init('/app_bg.wasm');`, with its first argument.
+    Direct(Option<&'s Argument<'a>>),
+    /// `const wasm = await __trunkInitializer(init, '/app_bg.wasm', ..);`, with the
+    /// wasm's path argument.
+    Initializer(Option<&'s Argument<'a>>),
+}
+
+/// How a statement calls `init`, if it does.
+fn init_call<'s, 'a>(statement: &'s Statement<'a>, init_name: &str) -> Option<InitCall<'s, 'a>> {
+    let expression = match statement {
+        Statement::VariableDeclaration(declaration) => declaration
+            .declarations
+            .iter()
+            .find_map(|declarator| declarator.init.as_ref())?,
+        Statement::ExpressionStatement(statement) => &statement.expression,
+        _ => return None,
+    };
+    let Expression::CallExpression(call) = unwrap(expression) else {
+        return None;
+    };
+    match unwrap(&call.callee) {
+        Expression::Identifier(callee) if callee.name.as_str() == init_name => {
+            Some(InitCall::Direct(call.arguments.first()))
+        }
+        Expression::Identifier(_) => match call.arguments.first()? {
+            Argument::Identifier(init) if init.name.as_str() == init_name => {
+                Some(InitCall::Initializer(call.arguments.get(1)))
+            }
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// The `module_or_path` string in `init`'s options.
+fn module_or_path(options: &ObjectExpression) -> Option<String> {
+    options.properties.iter().find_map(|property| {
+        let ObjectPropertyKind::ObjectProperty(property) = property else {
+            return None;
+        };
+        if property.key.static_name()? != "module_or_path" {
+            return None;
+        }
+        match &property.value {
+            Expression::StringLiteral(path) => Some(path.value.to_string()),
+            _ => None,
+        }
+    })
+}
+
+/// Whether a statement is `dispatchEvent(..)`, or `window.dispatchEvent(..)`.
+fn is_dispatch_event(statement: &Statement) -> bool {
+    let Statement::ExpressionStatement(statement) = statement else {
+        return false;
+    };
+    let Expression::CallExpression(call) = unwrap(&statement.expression) else {
+        return false;
+    };
+    match unwrap(&call.callee) {
+        Expression::Identifier(callee) => callee.name.as_str() == "dispatchEvent",
+        Expression::StaticMemberExpression(member) => {
+            member.property.name.as_str() == "dispatchEvent"
+        }
+        _ => false,
+    }
+}
+
+/// The expression inside any `await` and parentheses.
+fn unwrap<'s, 'a>(expression: &'s Expression<'a>) -> &'s Expression<'a> {
+    match expression {
+        Expression::AwaitExpression(inner) => unwrap(&inner.argument),
+        Expression::ParenthesizedExpression(inner) => unwrap(&inner.expression),
+        _ => expression,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TrunkScript;
+
+    const IMPORT: &str = "import init, * as bindings from '/app-0123.js';\n";
+    const DISPATCH: &str =
+        "dispatchEvent(new CustomEvent(\"TrunkApplicationStarted\", {detail: {wasm}}));\n";
+    const AUTO_RELOAD: &str = "(function () {
+    var protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
+    var url = protocol + '//' + '{{__TRUNK_ADDRESS__}}' + '{{__TRUNK_WS_BASE__}}.well-known/trunk/ws';
+    var ws = new WebSocket(url);
+    ws.onmessage = (ev) => { window.location.reload(); };
+})()
+";
+
+    /// Trunk's script, as `trunk build` writes it, with `init` taking options.
+    fn script() -> String {
+        format!(
+            "{IMPORT}const wasm = await init({{ module_or_path: '/app-0123_bg.wasm' }});\n\n\
+             window.wasmBindings = bindings;\n\n{DISPATCH}"
+        )
+    }
+
+    #[test]
+    fn parses_a_plain_script() {
+        let script = TrunkScript::parse(&script()).unwrap();
+        assert_eq!(script.import, IMPORT);
+        assert_eq!(
+            script.init,
+            "const wasm = await init({ module_or_path: '/app-0123_bg.wasm' });\n\
+             window.wasmBindings = bindings;\n"
+        );
+        assert!(!script.initializer);
+        assert_eq!(script.dispatch_event.as_deref(), Some(DISPATCH));
+        assert_eq!(script.auto_reload, None);
+        assert_eq!(script.module_path, "/app-0123.js");
+        assert_eq!(script.wasm_path, "/app-0123_bg.wasm");
+    }
+
+    #[test]
+    fn wraps_a_bare_wasm_path() {
+        let source = format!("{IMPORT}const wasm = await init('/app-0123_bg.wasm');\n{DISPATCH}");
+        let script = TrunkScript::parse(&source).unwrap();
+        assert_eq!(
+            script.init,
+            "const wasm = await init({module_or_path: '/app-0123_bg.wasm'});\n"
+        );
+        assert_eq!(script.wasm_path, "/app-0123_bg.wasm");
+    }
+
+    #[test]
+    fn parses_auto_reload() {
+        let script = TrunkScript::parse(&format!("{}{AUTO_RELOAD}", script())).unwrap();
+        assert_eq!(script.dispatch_event.as_deref(), Some(DISPATCH));
+        assert_eq!(script.auto_reload.as_deref(), Some(AUTO_RELOAD));
+
+        // Without a dispatch, the auto-reload code still ends the init part.
+        let source =
+            format!("{IMPORT}const wasm = await init('/app-0123_bg.wasm');\n{AUTO_RELOAD}");
+        let script = TrunkScript::parse(&source).unwrap();
+        assert_eq!(script.dispatch_event, None);
+        assert_eq!(
+            script.init,
+            "const wasm = await init({module_or_path: '/app-0123_bg.wasm'});\n"
+        );
+        assert_eq!(script.auto_reload.as_deref(), Some(AUTO_RELOAD));
+    }
+
+    #[test]
+    fn parses_an_initializer() {
+        let helper = "async function __trunkInitializer(init, source, sourceSize, initializer) {
+    initializer.onStart();
+    return await init({ module_or_path: source });
+}";
+        let call =
+            "const wasm = await __trunkInitializer(init, '/app-0123_bg.wasm', 42, initializer());";
+        let source = format!(
+            "{IMPORT}import initializer from '/initializer-4567.js';\n\n{helper}\n{call}\n\
+             window.wasmBindings = bindings;\n{DISPATCH}{AUTO_RELOAD}"
+        );
+        let script = TrunkScript::parse(&source).unwrap();
+        assert!(script.initializer);
+        assert_eq!(
+            script.import,
+            format!("{IMPORT}import initializer from '/initializer-4567.js';\n")
+        );
+        assert_eq!(
+            script.init,
+            format!("{helper}\n{call}\nwindow.wasmBindings = bindings;\n")
+        );
+        assert_eq!(script.module_path, "/app-0123.js");
+        assert_eq!(script.wasm_path, "/app-0123_bg.wasm");
+        assert_eq!(script.dispatch_event.as_deref(), Some(DISPATCH));
+        assert_eq!(script.auto_reload.as_deref(), Some(AUTO_RELOAD));
+    }
+
+    #[test]
+    fn describes_unexpected_scripts() {
+        let cases = [
+            ("const wasm = await (", "isn't valid JavaScript"),
+            (
+                "import * as bindings from '/app-0123.js';\nawait bindings.default();\n",
+                "doesn't import the wasm-bindgen module",
+            ),
+            (
+                "import init from '/app-0123.js';\nawait setup();\n",
+                "doesn't call the wasm-bindgen module's init",
+            ),
+            (
+                "import init from '/app-0123.js';\nconst wasm = await init(WASM_PATH);\n",
+                "doesn't pass the wasm's path to init",
+            ),
+            (
+                "import init from '/app-0123.js';\nawait init({ module: bytes });\n",
+                "passes init options without module_or_path",
+            ),
+        ];
+        for (source, expected) in cases {
+            let problem = TrunkScript::parse(source).unwrap_err();
+            assert!(problem.starts_with(expected), "{source}: {problem}");
+        }
+    }
+}
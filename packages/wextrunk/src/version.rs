@@ -0,0 +1,248 @@
+//! The extension's version, from `Cargo.toml`.
+//!
+//! The manifest's `version` is set from the crate's: its `major.minor.patch`, plus,
+//! with `[version] build_number_env` or `build_number_from_git`, a build number as the
+//! fourth part. Stores only accept one to four dot-separated integers from 0 to 65535
+//! there, so pre-release and build metadata (`1.2.0-beta.1+abc`) are left out of it,
+//! and the whole crate version goes in `version_name` instead, where browsers show it.
+//! Firefox has no `version_name`. The result is checked either way, so a bad version
+//! fails the build rather than the store upload.
+
+use std::{env, process::Command};
+
+use serde_json::Value;
+
+use crate::{config::Version, manifest::package_variables, schema::browser_of};
+
+/// The highest number stores accept in each part of a version.
+const MAX_PART: u32 = 65535;
+
+/// Set the manifest's `version` (and `version_name`) from the crate's version.
+pub fn sync_version(config: &Version, manifest: &mut Value, target: &str, source_dir: &str) {
+    let manifest = manifest
+        .as_object_mut()
+        .expect("The manifest must be a JSON object");
+    if let Some(crate_version) = package_variables(source_dir).remove("version") {
+        let build = build_number(config, source_dir);
+        let (version, version_name) = manifest_versions(&crate_version, build.as_deref());
+        if let Some(Value::String(existing)) = manifest.get("version") {
+            if *existing != crate_version && *existing != version {
+                println!(
+                    "Manifest version {existing:?} is replaced by {version:?} from Cargo.toml."
+                );
+            }
+        }
+        manifest.insert("version".to_string(), Value::String(version));
+        if let Some(version_name) = version_name.filter(|_| browser_of(target) != "firefox") {
+            manifest.insert("version_name".to_string(), Value::String(version_name));
+        }
+    }
+
+    let Some(version) = manifest.get("version").and_then(Value::as_str) else {
+        panic!("The manifest has no version; set one in Cargo.toml.");
+    };
+    if let Err(problem) = validate(version) {
+        panic!(
+            "The manifest version {version:?} {problem}. Stores require one to four dot-separated integers from 0 to {MAX_PART}, e.g. \"1.2.3.4\"."
+        );
+    }
+}
+
+/// The manifest's `version` and, if it leaves anything of the crate version out or
+/// adds a build number, `version_name`.
+fn manifest_versions(crate_version: &str, build: Option<&str>) -> (String, Option<String>) {
+    let (core, suffix) = match crate_version.find(['-', '+']) {
+        Some(index) => crate_version.split_at(index),
+        None => (crate_version, ""),
+    };
+    match build {
+        Some(build) => (
+            format!("{core}.{build}"),
+            Some(format!("{crate_version} (build {build})")),
+        ),
+        None => (
+            core.to_string(),
+            (!suffix.is_empty()).then(|| crate_version.to_string()),
+        ),
+    }
+}
+
+/// The build number, from the configured environment variable, or the number of git
+/// commits.
+fn build_number(config: &Version, source_dir: &str) -> Option<String> {
+    if let Some(name) = &config.build_number_env {
+        if let Ok(value) = env::var(name) {
+            return Some(value.trim().to_string());
+        }
+        if !config.build_number_from_git {
+            println!("Warning: {name} isn't set, so the version has no build number.");
+            return None;
+        }
+    }
+    if !config.build_number_from_git {
+        return None;
+    }
+    let output = Command::new("git")
+        .args(["rev-list", "--count", "HEAD"])
+        .current_dir(source_dir)
+        .output()
+        .ok()
+        .filter(|output| output.status.success());
+    match output {
+        Some(output) => Some(String::from_utf8_lossy(&output.stdout).trim().to_string()),
+        None => {
+            println!(
+                "Warning: couldn't count the git commits, so the version has no build number."
+            );
+            None
+        }
+    }
+}
+
+/// Check a manifest version against the stores' format.
+fn validate(version: &str) -> Result<(), String> {
+    let parts: Vec<_> = version.split('.').collect();
+    if parts.len() > 4 {
+        return Err("has more than four parts".to_string());
+    }
+    for part in parts {
+        if part.is_empty() || !part.bytes().all(|byte| byte.is_ascii_digit()) {
+            return Err(format!("has a part that isn't a number, {part:?}"));
+        }
+        if part.len() > 1 && part.starts_with('0') {
+            return Err(format!("has a part with a leading zero, {part:?}"));
+        }
+        if part.parse::<u32>().map_or(true, |part| part > MAX_PART) {
+            return Err(format!("has a part over {MAX_PART}, {part:?}"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs};
+
+    use serde_json::json;
+
+    use super::{manifest_versions, sync_version, validate};
+    use crate::config::Version;
+
+    #[test]
+    fn accepts_store_versions() {
+        for version in ["1", "1.2", "1.2.3", "1.2.3.4", "0.0.0.0", "65535.0.10.100"] {
+            assert_eq!(validate(version), Ok(()), "{version}");
+        }
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        let cases = [
+            ("1.2.3.4.5", "has more than four parts"),
+            ("", "has a part that isn't a number, \"\""),
+            ("1..2", "has a part that isn't a number, \"\""),
+            ("1.2.", "has a part that isn't a number, \"\""),
+            ("1.2.3-beta", "has a part that isn't a number, \"3-beta\""),
+            ("1.-2", "has a part that isn't a number, \"-2\""),
+            ("v1.2", "has a part that isn't a number, \"v1\""),
+            ("1.02", "has a part with a leading zero, \"02\""),
+            ("00.1", "has a part with a leading zero, \"00\""),
+            ("1.65536", "has a part over 65535, \"65536\""),
+            ("99999999999", "has a part over 65535, \"99999999999\""),
+        ];
+        for (version, problem) in cases {
+            assert_eq!(validate(version), Err(problem.to_string()), "{version}");
+        }
+    }
+
+    #[test]
+    fn names_versions_the_manifest_version_leaves_out() {
+        assert_eq!(
+            manifest_versions("1.2.3", None),
+            ("1.2.3".to_string(), None)
+        );
+        assert_eq!(
+            manifest_versions("1.2.0-beta.1+abc", None),
+            ("1.2.0".to_string(), Some("1.2.0-beta.1+abc".to_string()))
+        );
+        assert_eq!(
+            manifest_versions("1.2.0+abc", None),
+            ("1.2.0".to_string(), Some("1.2.0+abc".to_string()))
+        );
+        assert_eq!(
+            manifest_versions("1.2.3", Some("45")),
+            ("1.2.3.45".to_string(), Some("1.2.3 (build 45)".to_string()))
+        );
+        assert_eq!(
+            manifest_versions("1.2.0-rc.2", Some("7")),
+            (
+                "1.2.0.7".to_string(),
+                Some("1.2.0-rc.2 (build 7)".to_string())
+            )
+        );
+    }
+
+    /// A source directory whose Cargo.toml has `version`.
+    fn source_dir(name: &str, version: &str) -> String {
+        let dir = env::temp_dir().join(format!("wextrunk-version-{name}"));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!("[package]\nname = \"test\"\nversion = \"{version}\"\n"),
+        )
+        .unwrap();
+        dir.to_string_lossy().to_string()
+    }
+
+    #[test]
+    fn sets_version_name_except_for_firefox() {
+        let source_dir = source_dir("prerelease", "2.0.0-beta.3");
+        let mut chrome = json!({ "version": "0.1.0" });
+        sync_version(&Version::default(), &mut chrome, "chrome", &source_dir);
+        assert_eq!(
+            chrome,
+            json!({ "version": "2.0.0", "version_name": "2.0.0-beta.3" })
+        );
+
+        for target in ["firefox", "firefox-android"] {
+            let mut firefox = json!({});
+            sync_version(&Version::default(), &mut firefox, target, &source_dir);
+            assert_eq!(firefox, json!({ "version": "2.0.0" }), "{target}");
+        }
+    }
+
+    #[test]
+    fn uses_the_build_number_from_the_environment() {
+        let source_dir = source_dir("build", "1.4.0");
+        let config = Version {
+            build_number_env: Some("WEXTRUNK_TEST_BUILD_NUMBER".to_string()),
+            build_number_from_git: false,
+        };
+        env::set_var("WEXTRUNK_TEST_BUILD_NUMBER", " 312\n");
+        let mut manifest = json!({});
+        sync_version(&config, &mut manifest, "chrome", &source_dir);
+        assert_eq!(
+            manifest,
+            json!({ "version": "1.4.0.312", "version_name": "1.4.0 (build 312)" })
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The manifest version \"1.70000.0\" has a part over 65535")]
+    fn fails_on_an_invalid_version() {
+        let source_dir = source_dir("invalid", "1.70000.0");
+        sync_version(&Version::default(), &mut json!({}), "chrome", &source_dir);
+    }
+
+    #[test]
+    #[should_panic(expected = "The manifest has no version")]
+    fn fails_without_a_version() {
+        let missing = env::temp_dir().join("wextrunk-version-missing");
+        sync_version(
+            &Version::default(),
+            &mut json!({}),
+            "chrome",
+            &missing.to_string_lossy(),
+        );
+    }
+}
@@ -0,0 +1,68 @@
+//! Run wasm-opt on the staged wasm files with per-target/per-profile flags.
+//!
+//! Trunk only supports a single `data-wasm-opt` level for every build. Different
+//! stores have different size/performance trade-offs, so when `wextrunk.toml`
+//! configures `wasm_opt` flags, wextrunk runs wasm-opt itself instead. Set
+//! `data-wasm-opt="0"` on the rust links in index.html so Trunk doesn't optimise twice.
+
+use std::{fs, path::Path, process::Command};
+
+use crate::{
+    cache::{Cache, Fingerprint},
+    report::WasmOptReport,
+};
+
+/// Optimise every wasm file in `staging_dir` (Trunk's main one, and any other
+/// artifacts) in place with `flags`.
+pub fn run_wasm_opt(staging_dir: &str, flags: &[String], cache: &Cache) -> Vec<WasmOptReport> {
+    let mut wasm_files: Vec<_> = Path::new(staging_dir)
+        .read_dir()
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .collect();
+    if wasm_files.is_empty() {
+        panic!(
+            "wasm-opt flags are configured, but no wasm file was found in the staging directory"
+        );
+    }
+    wasm_files.sort();
+    wasm_files
+        .iter()
+        .map(|wasm_file| optimize(wasm_file, flags, cache))
+        .collect()
+}
+
+fn optimize(wasm_file: &Path, flags: &[String], cache: &Cache) -> WasmOptReport {
+    let size_before = fs::metadata(wasm_file).unwrap().len();
+    let fingerprint = Fingerprint::new().add_file(wasm_file).add(flags.join("\0"));
+    cache.run("wasm-opt", fingerprint, &[wasm_file], || {
+        let output = Command::new("wasm-opt")
+            .args(flags)
+            .arg(wasm_file)
+            .arg("-o")
+            .arg(wasm_file)
+            .output()
+            .expect("Failed to run wasm-opt. Is binaryen installed?");
+        if !output.status.success() {
+            panic!(
+                "wasm-opt {} failed:\n{}",
+                flags.join(" "),
+                String::from_utf8_lossy(&output.stderr)
+            );
+        }
+        true
+    });
+    let size_after = fs::metadata(wasm_file).unwrap().len();
+
+    WasmOptReport {
+        file: wasm_file
+            .file_name()
+            .unwrap()
+            .to_string_lossy()
+            .into_owned(),
+        flags: flags.to_vec(),
+        size_before,
+        size_after,
+    }
+}
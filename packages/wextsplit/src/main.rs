@@ -9,10 +9,20 @@
 //!
 //! We should only run this script in debug mode, since we don't want debug information
 //! in releases (as far as I assume).
+//!
+//! The split files are cached in `target/wextrunk-cache/wasm-split`, next to wextrunk's
+//! cache, keyed by the wasm and the debug file's URL, so rebuilds that don't change the
+//! wasm skip wasm-split. `--no-cache` or `WEXTRUNK_NO_CACHE=1` turns that off.
 
 use std::env;
+use std::path::Path;
 use std::time::Instant;
 
+use sha2::{Digest, Sha256};
+
+/// Where split files are cached, relative to the source directory.
+const CACHE_DIR: &str = "target/wextrunk-cache/wasm-split";
+
 fn main() {
     let start_time = Instant::now();
     // let trunk_profile = std::env::var("TRUNK_PROFILE").unwrap();
@@ -27,15 +37,24 @@ fn main() {
         }
     }
 
-    let trunk_staging_dir = std::env::var("TRUNK_STAGING_DIR").unwrap();
+    let trunk_staging_dir = std::env::var("TRUNK_STAGING_DIR").unwrap_or_else(|_| {
+        panic!("wextsplit runs as a Trunk hook, which sets TRUNK_STAGING_DIR. Build with `trunk serve`, or run `cargo wextrunk doctor` to check the hook setup.")
+    });
     let trunk_staging_dir = std::path::Path::new(&trunk_staging_dir);
+    // wextrunk rewrites the staged files (and moves them per target), so it must not
+    // have run yet
+    if trunk_staging_dir.join("manifest.json").exists() {
+        panic!("wextrunk already processed the staging directory. wextsplit must run first; list its hook before wextrunk's in Trunk.toml.");
+    }
     // get the first file that ends with _bg.wasm
     let wasm_file = trunk_staging_dir
         .read_dir()
         .unwrap()
         .map(|entry| entry.unwrap().path())
-        .find(|path| path.extension().unwrap() == "wasm")
-        .unwrap();
+        .find(|path| path.extension().is_some_and(|ext| ext == "wasm"))
+        .unwrap_or_else(|| {
+            panic!("{} has no wasm yet, so wextsplit ran before Trunk built it. It must be a post_build hook (`stage = \"post_build\"` in Trunk.toml).", trunk_staging_dir.display())
+        });
 
     // Move the _bg.wasm file to _bg.wasm.orig
     let wasm_file_orig = wasm_file.with_extension("wasm.orig");
@@ -47,8 +66,28 @@ fn main() {
     let port = env::var("TRUNK_SERVE_PORT").unwrap_or_else(|_| "8080".to_string());
     let address = format!("{address}:{port}");
 
+    let debug_url = format!(
+        "http://{}/{}",
+        address,
+        wasm_file_debug.file_name().unwrap().to_str().unwrap()
+    );
+
+    // Reuse the split files of an identical wasm, if they're cached
+    let cache_entry = cache_entry(&wasm_file_orig, &debug_url);
+    if let Some(entry) = &cache_entry {
+        if entry.join("wasm").is_file() && entry.join("debug").is_file() {
+            std::fs::copy(entry.join("wasm"), &wasm_file).unwrap();
+            std::fs::copy(entry.join("debug"), &wasm_file_debug).unwrap();
+            println!(
+                "Wextsplit reused the cached split in {:?}",
+                start_time.elapsed()
+            );
+            return;
+        }
+    }
+
     // Run wasm-split <.wasm.orig> -o <.wasm> --strip --debug-out=<.debug> --external-dwarf-url=<.debug>
-    std::process::Command::new("wasm-split")
+    let output = std::process::Command::new("wasm-split")
         .arg(&wasm_file_orig)
         .arg("-o")
         .arg(&wasm_file)
@@ -56,14 +95,42 @@ fn main() {
         .arg("--debug-out")
         .arg(&wasm_file_debug)
         .arg("--external-dwarf-url")
-        .arg(&format!(
-            "http://{}/{}",
-            address,
-            wasm_file_debug.file_name().unwrap().to_str().unwrap()
-        ))
+        .arg(&debug_url)
         .output()
-        .unwrap();
+        .expect("Failed to run wasm-split. Is symbolicator's wasm-split installed?");
+    if !output.status.success() {
+        // Put the wasm back, so the build still works without debug info
+        std::fs::rename(&wasm_file_orig, &wasm_file).unwrap();
+        panic!(
+            "wasm-split failed:\n{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    if let Some(entry) = cache_entry {
+        let partial = entry.with_extension("partial");
+        std::fs::create_dir_all(&partial).unwrap();
+        std::fs::copy(&wasm_file, partial.join("wasm")).unwrap();
+        std::fs::copy(&wasm_file_debug, partial.join("debug")).unwrap();
+        let _ = std::fs::remove_dir_all(&entry);
+        std::fs::rename(&partial, &entry).unwrap();
+    }
 
     let duration = start_time.elapsed();
     println!("Wextsplit finished in {:?}", duration);
 }
+
+/// The cache entry for splitting `wasm_file` with `debug_url`, unless caching is off.
+fn cache_entry(wasm_file: &Path, debug_url: &str) -> Option<std::path::PathBuf> {
+    let disabled = env::args().any(|arg| arg == "--no-cache")
+        || env::var("WEXTRUNK_NO_CACHE").is_ok_and(|value| value == "1");
+    if disabled {
+        return None;
+    }
+    let source_dir = env::var("TRUNK_SOURCE_DIR").ok()?;
+    let mut hasher = Sha256::new();
+    hasher.update(std::fs::read(wasm_file).unwrap());
+    hasher.update(debug_url);
+    let fingerprint = format!("{:x}", hasher.finalize());
+    Some(Path::new(&source_dir).join(CACHE_DIR).join(fingerprint))
+}
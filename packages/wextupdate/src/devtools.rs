@@ -0,0 +1,175 @@
+//! Just enough of the Chrome DevTools protocol to drive an update: listing and
+//! opening targets over the HTTP endpoint, and evaluating JavaScript in them over
+//! their WebSocket.
+
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    thread,
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tungstenite::{stream::MaybeTlsStream, Message, WebSocket};
+
+/// How long a single evaluation may take.
+const EVALUATE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A page, worker or other debuggable target, from `/json/list`.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    pub id: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub url: String,
+    pub web_socket_debugger_url: Option<String>,
+}
+
+/// The browser's DevTools HTTP endpoint.
+pub struct DevTools {
+    port: u16,
+}
+
+impl DevTools {
+    /// Wait for the browser to start listening on `port`.
+    pub fn wait(port: u16, timeout: Duration) -> Result<Self, String> {
+        let devtools = DevTools { port };
+        let start = Instant::now();
+        loop {
+            match devtools.request("GET", "/json/version") {
+                Ok(_) => return Ok(devtools),
+                Err(e) if start.elapsed() > timeout => {
+                    return Err(format!(
+                        "The browser's DevTools endpoint didn't come up: {e}"
+                    ))
+                }
+                Err(_) => thread::sleep(Duration::from_millis(200)),
+            }
+        }
+    }
+
+    pub fn targets(&self) -> Result<Vec<Target>, String> {
+        let body = self.request("GET", "/json/list")?;
+        serde_json::from_str(&body).map_err(|e| format!("Invalid target list: {e}"))
+    }
+
+    /// Poll the targets until one matches `predicate`.
+    pub fn wait_for(
+        &self,
+        timeout: Duration,
+        predicate: impl Fn(&Target) -> bool,
+    ) -> Result<Option<Target>, String> {
+        let start = Instant::now();
+        loop {
+            if let Some(target) = self.targets()?.into_iter().find(&predicate) {
+                return Ok(Some(target));
+            }
+            if start.elapsed() > timeout {
+                return Ok(None);
+            }
+            thread::sleep(Duration::from_millis(250));
+        }
+    }
+
+    /// Open `url` in a new tab.
+    pub fn open(&self, url: &str) -> Result<Target, String> {
+        let body = self.request("PUT", &format!("/json/new?{url}"))?;
+        serde_json::from_str(&body).map_err(|e| format!("Invalid new target: {e}"))
+    }
+
+    fn request(&self, method: &str, path: &str) -> Result<String, String> {
+        let mut stream = TcpStream::connect(("127.0.0.1", self.port)).map_err(|e| e.to_string())?;
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .map_err(|e| e.to_string())?;
+        write!(
+            stream,
+            "{method} {path} HTTP/1.1\r\nHost: 127.0.0.1:{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            self.port
+        )
+        .map_err(|e| e.to_string())?;
+        let mut response = String::new();
+        stream
+            .read_to_string(&mut response)
+            .map_err(|e| e.to_string())?;
+        let (head, body) = response
+            .split_once("\r\n\r\n")
+            .ok_or_else(|| "Malformed HTTP response".to_string())?;
+        if !head.starts_with("HTTP/1.1 200") {
+            return Err(format!(
+                "{method} {path}: {}",
+                head.lines().next().unwrap_or_default()
+            ));
+        }
+        Ok(body.to_string())
+    }
+}
+
+/// A DevTools session with one target.
+pub struct Session {
+    socket: WebSocket<MaybeTlsStream<TcpStream>>,
+    next_id: u64,
+}
+
+impl Session {
+    pub fn connect(target: &Target) -> Result<Self, String> {
+        let url = target
+            .web_socket_debugger_url
+            .as_deref()
+            .ok_or_else(|| format!("{} is already being debugged", target.url))?;
+        let (socket, _) = tungstenite::connect(url).map_err(|e| e.to_string())?;
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream
+                .set_read_timeout(Some(EVALUATE_TIMEOUT))
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(Session { socket, next_id: 1 })
+    }
+
+    /// Evaluate `expression`, waiting for it if it's a promise, and return its value
+    /// as JSON.
+    pub fn evaluate(&mut self, expression: &str) -> Result<Value, String> {
+        let result = self.call(
+            "Runtime.evaluate",
+            json!({
+                "expression": expression,
+                "awaitPromise": true,
+                "returnByValue": true,
+            }),
+        )?;
+        if let Some(exception) = result.get("exceptionDetails") {
+            let message = exception["exception"]["description"]
+                .as_str()
+                .or_else(|| exception["text"].as_str())
+                .unwrap_or("unknown exception");
+            return Err(message.to_string());
+        }
+        Ok(result["result"]["value"].clone())
+    }
+
+    fn call(&mut self, method: &str, params: Value) -> Result<Value, String> {
+        let id = self.next_id;
+        self.next_id += 1;
+        let request = json!({ "id": id, "method": method, "params": params });
+        self.socket
+            .send(Message::text(request.to_string()))
+            .map_err(|e| e.to_string())?;
+        loop {
+            let message = self.socket.read().map_err(|e| e.to_string())?;
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let response: Value = serde_json::from_str(text.as_str()).map_err(|e| e.to_string())?;
+            // Skip events and responses to other calls.
+            if response["id"].as_u64() != Some(id) {
+                continue;
+            }
+            if let Some(error) = response.get("error") {
+                return Err(format!("{method}: {}", error["message"]));
+            }
+            return Ok(response["result"].clone());
+        }
+    }
+}
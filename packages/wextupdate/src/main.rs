@@ -0,0 +1,504 @@
+//! Simulates an extension update in a real browser.
+//!
+//! Installs version N of the extension in a freshly launched Chromium, then swaps
+//! in version N+1 and reloads it, which the browser treats like a store update
+//! (`runtime.onInstalled` fires with `reason: "update"`). Then it checks what users
+//! would notice:
+//!
+//! - The new version's background script starts.
+//! - The update page opens.
+//! - `storage.local` is still readable, and which keys the update changed.
+//! - No errors were recorded by the `health` module during the update.
+//! - Content scripts in an open tab are replaced rather than left orphaned: exactly
+//!   one mounted view and no "refresh the page" notice. Skipped if the test page
+//!   has no content script.
+//!
+//! N is built from a git ref in a temporary worktree (or taken from an existing
+//! build), and N+1 from the working tree. The results are printed as a pass/fail
+//! report, written to `target/update-sim/report.json`, and reflected in the exit code.
+//!
+//! ```sh
+//! cargo wextupdate --from v1.0.0
+//! cargo wextupdate --from-dist old-dist --to-dist dist --url https://example.com/
+//! ```
+
+use std::{
+    env, fs,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    process::{self, Child, Command, Stdio},
+    thread,
+    time::Duration,
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use devtools::{DevTools, Session};
+
+mod devtools;
+
+/// Where builds, the browser profile and the report go, relative to the source dir.
+const OUTPUT_DIR: &str = "target/update-sim";
+/// How long to wait for the browser, background scripts and pages.
+const TIMEOUT: Duration = Duration::from_secs(30);
+/// How long to let the update settle (re-injection, orphan recovery) before checking.
+const SETTLE_TIME: Duration = Duration::from_secs(5);
+/// `storage.session` key of the errors recorded by the `health` module.
+const HEALTH_ERRORS_KEY: &str = "wext.health.errors";
+
+const USAGE: &str =
+    "Usage: cargo wextupdate (--from <git ref> | --from-dist <dir>) [--to-dist <dir>]
+       [--url <test page>] [--browser <path>] [--release] [--headed]";
+
+/// Command-line options.
+#[derive(Debug, Default)]
+struct Options {
+    from: Option<String>,
+    from_dist: Option<PathBuf>,
+    to_dist: Option<PathBuf>,
+    url: String,
+    browser: Option<String>,
+    release: bool,
+    headed: bool,
+}
+
+impl Options {
+    fn parse() -> Result<Self, String> {
+        let mut options = Options {
+            url: "https://example.com/".to_string(),
+            ..Options::default()
+        };
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("{arg} needs a value"));
+            match arg.as_str() {
+                "--from" => options.from = Some(value()?),
+                "--from-dist" => options.from_dist = Some(value()?.into()),
+                "--to-dist" => options.to_dist = Some(value()?.into()),
+                "--url" => options.url = value()?,
+                "--browser" => options.browser = Some(value()?),
+                "--release" => options.release = true,
+                "--headed" => options.headed = true,
+                _ => return Err(format!("Unknown argument {arg:?}")),
+            }
+        }
+        if options.from.is_some() == options.from_dist.is_some() {
+            return Err("Pass exactly one of --from and --from-dist".to_string());
+        }
+        Ok(options)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+enum Outcome {
+    Pass,
+    Fail,
+    Skip,
+}
+
+#[derive(Debug, Serialize)]
+struct Check {
+    name: &'static str,
+    outcome: Outcome,
+    detail: String,
+}
+
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Report {
+    from_version: String,
+    to_version: String,
+    checks: Vec<Check>,
+}
+
+impl Report {
+    fn check(&mut self, name: &'static str, outcome: Outcome, detail: impl Into<String>) {
+        self.checks.push(Check {
+            name,
+            outcome,
+            detail: detail.into(),
+        });
+    }
+
+    fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.outcome != Outcome::Fail)
+    }
+
+    fn print(&self) {
+        println!(
+            "Update simulation: {} -> {}",
+            self.from_version, self.to_version
+        );
+        for check in &self.checks {
+            let outcome = match check.outcome {
+                Outcome::Pass => "PASS",
+                Outcome::Fail => "FAIL",
+                Outcome::Skip => "SKIP",
+            };
+            println!("  {outcome} {}: {}", check.name, check.detail);
+        }
+        println!("{}", if self.passed() { "PASSED" } else { "FAILED" });
+    }
+}
+
+fn main() {
+    let options = Options::parse().unwrap_or_else(|e| {
+        eprintln!("{e}\n{USAGE}");
+        process::exit(2);
+    });
+    let source_dir = env::current_dir().unwrap();
+    let output_dir = source_dir.join(OUTPUT_DIR);
+    if output_dir.exists() {
+        fs::remove_dir_all(&output_dir).unwrap();
+    }
+    fs::create_dir_all(&output_dir).unwrap();
+
+    let from_dist = match (&options.from, &options.from_dist) {
+        (Some(git_ref), _) => build_ref(&source_dir, git_ref, &output_dir, options.release),
+        (_, Some(dist)) => source_dir.join(dist),
+        _ => unreachable!(),
+    };
+    let to_dist = match &options.to_dist {
+        Some(dist) => source_dir.join(dist),
+        None => {
+            let dist = output_dir.join("to");
+            build(&source_dir, &dist, options.release);
+            dist
+        }
+    };
+
+    let mut report = Report {
+        from_version: version(&from_dist),
+        to_version: version(&to_dist),
+        ..Report::default()
+    };
+    if is_newer(&report.to_version, &report.from_version) {
+        report.check("version", Outcome::Pass, "the new version is higher");
+    } else {
+        report.check(
+            "version",
+            Outcome::Fail,
+            "the new version isn't higher, so stores (and browsers) won't update to it",
+        );
+    }
+
+    let extension_dir = output_dir.join("extension");
+    copy_dir(&from_dist, &extension_dir);
+    let port = free_port();
+    let mut browser = launch(&options, port, &extension_dir, &output_dir.join("profile"));
+    let result = simulate(&options, port, &extension_dir, &to_dist, &mut report);
+    let _ = browser.kill();
+    let _ = browser.wait();
+    if let Err(e) = result {
+        report.check("simulation", Outcome::Fail, e);
+    }
+
+    report.print();
+    let mut json = serde_json::to_string_pretty(&report).unwrap();
+    json.push('\n');
+    fs::write(output_dir.join("report.json"), json).unwrap();
+    if !report.passed() {
+        process::exit(1);
+    }
+}
+
+/// Install the old version, update to the new one, and check the outcome.
+fn simulate(
+    options: &Options,
+    port: u16,
+    extension_dir: &Path,
+    to_dist: &Path,
+    report: &mut Report,
+) -> Result<(), String> {
+    let devtools = DevTools::wait(port, TIMEOUT)?;
+    let old_worker = devtools
+        .wait_for(TIMEOUT, |target| {
+            target.kind == "service_worker" && target.url.starts_with("chrome-extension://")
+        })?
+        .ok_or("The old version's background script didn't start")?;
+    let origin = origin(&old_worker.url);
+
+    let tab = devtools.open(&options.url)?;
+    thread::sleep(SETTLE_TIME);
+    let mut page = Session::connect(&tab)?;
+    let had_content_script = page.evaluate(ROOTS)?.as_u64().unwrap_or_default() > 0;
+
+    let mut worker = Session::connect(&old_worker)?;
+    let storage_before = worker.evaluate("chrome.storage.local.get(null)")?;
+    copy_dir(to_dist, extension_dir);
+    // Reloading an unpacked extension is an update as far as the extension can tell.
+    worker.evaluate("setTimeout(() => chrome.runtime.reload(), 100), null")?;
+    drop(worker);
+
+    let new_worker = devtools.wait_for(TIMEOUT, |target| {
+        target.kind == "service_worker"
+            && target.url.starts_with(&origin)
+            && target.id != old_worker.id
+    })?;
+    let Some(new_worker) = new_worker else {
+        report.check(
+            "background",
+            Outcome::Fail,
+            "the new version's background script didn't start",
+        );
+        return Ok(());
+    };
+    let mut worker = Session::connect(&new_worker)?;
+    let running = worker
+        .evaluate("chrome.runtime.getManifest().version")?
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    if running == report.to_version {
+        report.check(
+            "background",
+            Outcome::Pass,
+            format!("version {running} started"),
+        );
+    } else {
+        report.check(
+            "background",
+            Outcome::Fail,
+            format!(
+                "expected version {}, but {running} is running",
+                report.to_version
+            ),
+        );
+    }
+
+    let update_page = devtools.wait_for(TIMEOUT, |target| {
+        target.kind == "page" && target.url.starts_with(&format!("{origin}update.html"))
+    })?;
+    match update_page {
+        Some(page) => report.check("update page", Outcome::Pass, page.url),
+        None => report.check("update page", Outcome::Fail, "the update page didn't open"),
+    }
+
+    thread::sleep(SETTLE_TIME);
+    match worker.evaluate("chrome.storage.local.get(null)") {
+        Ok(storage_after) => report.check(
+            "storage",
+            Outcome::Pass,
+            storage_changes(&storage_before, &storage_after),
+        ),
+        Err(e) => report.check("storage", Outcome::Fail, e),
+    }
+
+    let errors = worker.evaluate(&format!(
+        "chrome.storage.session.get({HEALTH_ERRORS_KEY:?}).then((items) => items[{HEALTH_ERRORS_KEY:?}] ?? [])"
+    ))?;
+    let errors = errors.as_array().cloned().unwrap_or_default();
+    if errors.is_empty() {
+        report.check("errors", Outcome::Pass, "none recorded");
+    } else {
+        let messages: Vec<String> = errors
+            .iter()
+            .map(|error| format!("{}: {}", error["source"], error["message"]))
+            .collect();
+        report.check("errors", Outcome::Fail, messages.join("; "));
+    }
+
+    if !had_content_script {
+        report.check(
+            "content scripts",
+            Outcome::Skip,
+            format!("no content script mounted in {}", options.url),
+        );
+        return Ok(());
+    }
+    let roots = page.evaluate(ROOTS)?.as_u64().unwrap_or_default();
+    let notice = page
+        .evaluate("document.getElementById(\"wext-context-notice\") !== null")?
+        .as_bool()
+        .unwrap_or_default();
+    match (roots, notice) {
+        (1, false) => report.check(
+            "content scripts",
+            Outcome::Pass,
+            "the new version's content script took over",
+        ),
+        (_, true) => report.check(
+            "content scripts",
+            Outcome::Fail,
+            "the old content script asks the user to refresh the page",
+        ),
+        (0, _) => report.check(
+            "content scripts",
+            Outcome::Fail,
+            "no content script is mounted after the update",
+        ),
+        (roots, _) => report.check(
+            "content scripts",
+            Outcome::Fail,
+            format!("{roots} content script views are mounted; the old one wasn't torn down"),
+        ),
+    }
+    Ok(())
+}
+
+/// Counts the views mounted by `content::mount`.
+const ROOTS: &str = "document.querySelectorAll(\"[data-wext-root]\").length";
+
+/// Build the extension at `git_ref` in a temporary worktree.
+fn build_ref(source_dir: &Path, git_ref: &str, output_dir: &Path, release: bool) -> PathBuf {
+    let worktree = output_dir.join("from-src");
+    run(Command::new("git")
+        .args(["worktree", "add", "--detach"])
+        .arg(&worktree)
+        .arg(git_ref)
+        .current_dir(source_dir));
+    // The lock file isn't committed; use the current one rather than resolving anew.
+    let lock_file = source_dir.join("Cargo.lock");
+    if lock_file.exists() && !worktree.join("Cargo.lock").exists() {
+        fs::copy(&lock_file, worktree.join("Cargo.lock")).unwrap();
+    }
+    let dist = output_dir.join("from");
+    build(&worktree, &dist, release);
+    run(Command::new("git")
+        .args(["worktree", "remove", "--force"])
+        .arg(&worktree)
+        .current_dir(source_dir));
+    dist
+}
+
+fn build(dir: &Path, dist: &Path, release: bool) {
+    let mut command = Command::new("trunk");
+    command.arg("build");
+    if release {
+        command.arg("--release");
+    }
+    run(command
+        .arg("--dist")
+        .arg(dist)
+        .env("WEXTRUNK_TARGET", "chrome")
+        .current_dir(dir));
+}
+
+fn run(command: &mut Command) {
+    let status = command
+        .status()
+        .unwrap_or_else(|e| panic!("Failed to run {:?}: {e}", command.get_program()));
+    if !status.success() {
+        panic!("{command:?} failed with {status}");
+    }
+}
+
+/// Launch a throwaway browser profile with the extension loaded unpacked.
+fn launch(options: &Options, port: u16, extension_dir: &Path, profile_dir: &Path) -> Child {
+    let mut args = vec![
+        format!("--user-data-dir={}", profile_dir.display()),
+        format!("--load-extension={}", extension_dir.display()),
+        format!("--remote-debugging-port={port}"),
+        "--no-first-run".to_string(),
+        "--no-default-browser-check".to_string(),
+        // Branded Chrome ignores --load-extension since version 137.
+        "--disable-features=DisableLoadExtensionCommandLineSwitch".to_string(),
+    ];
+    if !options.headed {
+        args.push("--headless=new".to_string());
+    }
+    args.push("about:blank".to_string());
+
+    let candidates = match &options.browser {
+        Some(browser) => vec![browser.clone()],
+        None => ["chromium", "chromium-browser", "google-chrome", "chrome"]
+            .map(str::to_string)
+            .to_vec(),
+    };
+    for browser in &candidates {
+        if let Ok(child) = Command::new(browser)
+            .args(&args)
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            return child;
+        }
+    }
+    panic!(
+        "Couldn't launch a browser (tried {}); pass --browser <path>.",
+        candidates.join(", ")
+    );
+}
+
+fn free_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+/// `chrome-extension://<id>/` for a URL inside the extension.
+fn origin(url: &str) -> String {
+    let rest = url.trim_start_matches("chrome-extension://");
+    let id = rest.split('/').next().unwrap_or_default();
+    format!("chrome-extension://{id}/")
+}
+
+/// The version in a built extension's manifest.
+fn version(dist: &Path) -> String {
+    let manifest = fs::read_to_string(dist.join("manifest.json"))
+        .unwrap_or_else(|e| panic!("Failed to read {}/manifest.json: {e}", dist.display()));
+    let manifest: Value = serde_json::from_str(&manifest).unwrap();
+    manifest["version"].as_str().unwrap_or_default().to_string()
+}
+
+/// Whether dotted version `a` is higher than `b`, comparing numerically.
+fn is_newer(a: &str, b: &str) -> bool {
+    let parse = |version: &str| -> Vec<u64> {
+        version
+            .split('.')
+            .map(|part| part.parse().unwrap_or_default())
+            .collect()
+    };
+    let (mut a, mut b) = (parse(a), parse(b));
+    let len = a.len().max(b.len());
+    a.resize(len, 0);
+    b.resize(len, 0);
+    a > b
+}
+
+/// Which `storage.local` keys the update added, removed or changed.
+fn storage_changes(before: &Value, after: &Value) -> String {
+    let empty = serde_json::Map::new();
+    let before = before.as_object().unwrap_or(&empty);
+    let after = after.as_object().unwrap_or(&empty);
+    let mut changes = Vec::new();
+    for (key, value) in after {
+        match before.get(key) {
+            None => changes.push(format!("+{key}")),
+            Some(previous) if previous != value => changes.push(format!("~{key}")),
+            Some(_) => {}
+        }
+    }
+    for key in before.keys().filter(|key| !after.contains_key(*key)) {
+        changes.push(format!("-{key}"));
+    }
+    if changes.is_empty() {
+        "unchanged".to_string()
+    } else {
+        changes.join(", ")
+    }
+}
+
+/// Replace `to` with a copy of `from`.
+fn copy_dir(from: &Path, to: &Path) {
+    if to.exists() {
+        fs::remove_dir_all(to).unwrap();
+    }
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap() {
+        let path = entry.unwrap().path();
+        let target = to.join(path.file_name().unwrap());
+        if path.is_dir() {
+            copy_dir(&path, &target);
+        } else {
+            fs::copy(&path, &target).unwrap();
+        }
+    }
+}
@@ -0,0 +1,272 @@
+//! Accessible building blocks for popup UIs.
+//!
+//! Popups are small and disproportionately keyboard-driven, so these take care of the
+//! focus and key handling that's easy to get wrong:
+//!
+//! - [`FocusTrap`] focuses its first focusable child when mounted, keeps Tab inside,
+//!   and restores the previous focus when unmounted.
+//! - [`Menu`] and [`MenuItem`] implement a roving tabindex: the menu is one Tab stop,
+//!   and the arrow keys, Home and End move between items.
+//! - [`Toggle`] is an on/off switch with the `switch` role.
+//! - [`use_escape`] lets the innermost open layer (dialog, menu, ...) handle Escape.
+//!   Escape only reaches the browser, which closes the popup, once no layer is open.
+
+use std::{cell::RefCell, rc::Rc};
+
+use leptos::{ev::KeyboardEvent, html, prelude::*};
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, HtmlElement};
+
+/// Elements that can take keyboard focus.
+const FOCUSABLE: &str = "a[href], button:not([disabled]), input:not([disabled]), \
+     select:not([disabled]), textarea:not([disabled]), [tabindex]:not([tabindex=\"-1\"])";
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// Escape handlers of the currently open layers, innermost last.
+    escape_handlers: Vec<(u64, Rc<dyn Fn()>)>,
+    escape_listener: bool,
+    close_on_escape: bool,
+    /// Focus to restore when each [`FocusTrap`] unmounts.
+    restore_focus: Vec<(u64, Option<HtmlElement>)>,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::default();
+}
+
+fn next_id() -> u64 {
+    STATE.with_borrow_mut(|state| {
+        state.next_id += 1;
+        state.next_id
+    })
+}
+
+/// Keeps keyboard focus inside its children while mounted.
+#[component]
+pub fn FocusTrap(children: Children) -> impl IntoView {
+    let id = next_id();
+    let previous = document()
+        .active_element()
+        .and_then(|element| element.dyn_into::<HtmlElement>().ok());
+    STATE.with_borrow_mut(|state| state.restore_focus.push((id, previous)));
+    on_cleanup(move || {
+        let previous = STATE.with_borrow_mut(|state| {
+            let index = state
+                .restore_focus
+                .iter()
+                .position(|(other, _)| *other == id)?;
+            state.restore_focus.remove(index).1
+        });
+        if let Some(previous) = previous {
+            let _ = previous.focus();
+        }
+    });
+
+    let container = NodeRef::<html::Div>::new();
+    Effect::new(move |_| {
+        if let Some(container) = container.get() {
+            if let Some(first) = focusable(&container).first() {
+                let _ = first.focus();
+            }
+        }
+    });
+
+    let on_keydown = move |ev: KeyboardEvent| {
+        if ev.key() != "Tab" {
+            return;
+        }
+        let Some(container) = container.get_untracked() else {
+            return;
+        };
+        let elements = focusable(&container);
+        let (Some(first), Some(last)) = (elements.first(), elements.last()) else {
+            ev.prevent_default();
+            return;
+        };
+        let active = document().active_element();
+        let at_edge = if ev.shift_key() { first } else { last };
+        let outside = !container.contains(active.as_ref().map(AsRef::as_ref));
+        if active.as_ref() == Some(AsRef::<Element>::as_ref(at_edge)) || outside {
+            ev.prevent_default();
+            let _ = if ev.shift_key() { last } else { first }.focus();
+        }
+    };
+
+    view! {
+        <div node_ref=container on:keydown=on_keydown>
+            {children()}
+        </div>
+    }
+}
+
+/// A vertical menu with a roving tabindex. Use [`MenuItem`]s as children.
+#[component]
+pub fn Menu(children: Children, #[prop(into, optional)] label: String) -> impl IntoView {
+    let container = NodeRef::<html::Div>::new();
+    // Only the first item is a Tab stop initially.
+    Effect::new(move |_| {
+        if let Some(container) = container.get() {
+            for (index, item) in menu_items(&container).iter().enumerate() {
+                let tabindex = if index == 0 { "0" } else { "-1" };
+                item.set_attribute("tabindex", tabindex).unwrap();
+            }
+        }
+    });
+
+    let on_keydown = move |ev: KeyboardEvent| {
+        let Some(container) = container.get_untracked() else {
+            return;
+        };
+        let items = menu_items(&container);
+        if items.is_empty() {
+            return;
+        }
+        let active = document().active_element();
+        let current = items
+            .iter()
+            .position(|item| active.as_ref() == Some(AsRef::<Element>::as_ref(item)));
+        let last = items.len() - 1;
+        let next = match ev.key().as_str() {
+            "ArrowDown" => current.map_or(0, |index| if index == last { 0 } else { index + 1 }),
+            "ArrowUp" => current.map_or(last, |index| index.checked_sub(1).unwrap_or(last)),
+            "Home" => 0,
+            "End" => last,
+            _ => return,
+        };
+        ev.prevent_default();
+        for (index, item) in items.iter().enumerate() {
+            let tabindex = if index == next { "0" } else { "-1" };
+            item.set_attribute("tabindex", tabindex).unwrap();
+        }
+        let _ = items[next].focus();
+    };
+
+    view! {
+        <div node_ref=container role="menu" aria-label=label on:keydown=on_keydown>
+            {children()}
+        </div>
+    }
+}
+
+/// An item of a [`Menu`]. Activated by click, Enter or Space.
+#[component]
+pub fn MenuItem(children: Children, #[prop(into)] on_select: Callback<()>) -> impl IntoView {
+    view! {
+        <button
+            type="button"
+            role="menuitem"
+            tabindex="-1"
+            class="block w-full text-start px-3 py-1 focus:bg-gray-200 focus:outline-none"
+            on:click=move |_| on_select.run(())
+        >
+            {children()}
+        </button>
+    }
+}
+
+/// An accessible on/off switch, labelled by its children.
+#[component]
+pub fn Toggle(checked: RwSignal<bool>, children: Children) -> impl IntoView {
+    view! {
+        <button
+            type="button"
+            role="switch"
+            aria-checked=move || checked.get().to_string()
+            class="inline-flex items-center gap-2"
+            on:click=move |_| checked.update(|checked| *checked = !*checked)
+        >
+            <span class=move || {
+                if checked.get() {
+                    "relative inline-block h-5 w-9 rounded-full bg-blue-600 transition-colors"
+                } else {
+                    "relative inline-block h-5 w-9 rounded-full bg-gray-400 transition-colors"
+                }
+            }>
+                <span class=move || {
+                    if checked.get() {
+                        "absolute top-0.5 start-4 h-4 w-4 rounded-full bg-white transition-all"
+                    } else {
+                        "absolute top-0.5 start-0.5 h-4 w-4 rounded-full bg-white transition-all"
+                    }
+                } />
+            </span>
+            {children()}
+        </button>
+    }
+}
+
+/// Call `handler` when Escape is pressed while the calling component is the innermost
+/// open layer. The handler is unregistered when the component unmounts.
+///
+/// While any layer is open, Escape is kept from closing the popup itself.
+pub fn use_escape(handler: impl Fn() + 'static) {
+    install_escape_listener();
+    let id = next_id();
+    STATE.with_borrow_mut(|state| state.escape_handlers.push((id, Rc::new(handler))));
+    on_cleanup(move || {
+        STATE.with_borrow_mut(|state| state.escape_handlers.retain(|(other, _)| *other != id));
+    });
+}
+
+/// Close the popup on Escape when no layer registered with [`use_escape`] is open.
+///
+/// Firefox closes popups on Escape by itself; this makes Chrome behave the same.
+pub fn close_on_escape() {
+    install_escape_listener();
+    STATE.with_borrow_mut(|state| state.close_on_escape = true);
+}
+
+fn install_escape_listener() {
+    if STATE.with_borrow_mut(|state| std::mem::replace(&mut state.escape_listener, true)) {
+        return;
+    }
+    let listener = Closure::<dyn Fn(KeyboardEvent)>::new(|ev: KeyboardEvent| {
+        if ev.key() != "Escape" {
+            return;
+        }
+        let (handler, close) = STATE.with_borrow(|state| {
+            let handler = state
+                .escape_handlers
+                .last()
+                .map(|(_, handler)| handler.clone());
+            (handler, state.close_on_escape)
+        });
+        match handler {
+            Some(handler) => {
+                ev.prevent_default();
+                ev.stop_immediate_propagation();
+                handler();
+            }
+            None if close => {
+                let _ = window().close();
+            }
+            None => {}
+        }
+    });
+    // Capture, so layers get Escape before anything in the page.
+    window()
+        .add_event_listener_with_callback_and_bool(
+            "keydown",
+            listener.as_ref().unchecked_ref(),
+            true,
+        )
+        .unwrap();
+    listener.forget();
+}
+
+fn focusable(container: &Element) -> Vec<HtmlElement> {
+    elements(container, FOCUSABLE)
+}
+
+fn menu_items(container: &Element) -> Vec<HtmlElement> {
+    elements(container, "[role=\"menuitem\"]")
+}
+
+fn elements(container: &Element, selector: &str) -> Vec<HtmlElement> {
+    let list = container.query_selector_all(selector).unwrap();
+    (0..list.length())
+        .filter_map(|index| list.item(index)?.dyn_into::<HtmlElement>().ok())
+        .collect()
+}
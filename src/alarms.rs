@@ -0,0 +1,426 @@
+//! A facade over `chrome.alarms`, so code that schedules work can be tested against
+//! `mock::MockAlarms` instead of a browser.
+//!
+//! Chrome won't fire alarms sooner than 30 seconds from now, or more often than every
+//! 30 seconds, and logs a warning when asked to. Schedules are clamped to
+//! [`MIN_ALARM_DELAY_MS`] here, so every browser (and the mock) behaves the same. For
+//! anything sooner, [`Wakeup`] pairs the alarm with a timer, which fires on time as long
+//! as the background stays alive.
+//!
+//! ```ignore
+//! fn schedule_sync(alarms: &impl Alarms) {
+//!     alarms.on_alarm(Box::new(|alarm| if alarm.name == "sync" { sync() }));
+//! }
+//!
+//! BrowserAlarms.create("sync", &AlarmSchedule::every(30.0)).await?;
+//! ```
+//!
+//! Periodic background work is declared with [`on_alarm`], and routed with [`route`]
+//! in the background:
+//!
+//! ```ignore
+//! #[on_alarm("sync-data", period_minutes = 15)]
+//! async fn sync_data() -> Result<(), WextError> { .. }
+//!
+//! alarms::route(&[SYNC_DATA]);
+//! ```
+//!
+//! The alarms are created if they don't exist yet (or their period changed), and each
+//! time one fires, its function runs as a [job](crate::jobs): it survives the worker
+//! being shut down mid-run, and is retried if it fails. An alarm firing while its
+//! previous run is still queued or running is skipped.
+
+use std::{cell::Cell, fmt, rc::Rc};
+
+use futures::future::LocalBoxFuture;
+use gloo_console::warn;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    browser,
+    clock::{Clock, SystemClock},
+    error::WextError,
+    health,
+    jobs::{self, JobState},
+};
+
+pub use wext_macros::on_alarm;
+
+/// The shortest delay, and period, an alarm can have.
+pub const MIN_ALARM_DELAY_MS: f64 = 30_000.0;
+
+/// A scheduled alarm, from `alarms.Alarm`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Alarm {
+    pub name: String,
+    /// When it fires next, in milliseconds since the epoch.
+    pub scheduled_time: f64,
+    /// Set for repeating alarms.
+    #[serde(default)]
+    pub period_in_minutes: Option<f64>,
+}
+
+/// When an alarm fires, as in `alarms.create`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AlarmSchedule {
+    /// At this time, in milliseconds since the epoch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub when: Option<f64>,
+    /// After this many minutes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub delay_in_minutes: Option<f64>,
+    /// Then again every this many minutes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub period_in_minutes: Option<f64>,
+}
+
+impl AlarmSchedule {
+    /// Once, at `when` (in milliseconds since the epoch).
+    pub fn at(when: f64) -> Self {
+        AlarmSchedule {
+            when: Some(when),
+            ..Default::default()
+        }
+    }
+
+    /// Every `minutes`, starting `minutes` from now.
+    pub fn every(minutes: f64) -> Self {
+        AlarmSchedule {
+            delay_in_minutes: Some(minutes),
+            period_in_minutes: Some(minutes),
+            ..Default::default()
+        }
+    }
+
+    /// The schedule with its first run and period moved up to [`MIN_ALARM_DELAY_MS`],
+    /// as of `now`.
+    pub fn clamped(&self, now: f64) -> Self {
+        let min_minutes = MIN_ALARM_DELAY_MS / 60_000.0;
+        AlarmSchedule {
+            when: self.when.map(|when| when.max(now + MIN_ALARM_DELAY_MS)),
+            delay_in_minutes: self
+                .delay_in_minutes
+                .map(|minutes| minutes.max(min_minutes)),
+            period_in_minutes: self
+                .period_in_minutes
+                .map(|minutes| minutes.max(min_minutes)),
+        }
+    }
+}
+
+/// Alarm operations.
+pub trait Alarms {
+    /// Create the alarm `name`, replacing an existing one of that name. The schedule is
+    /// [`clamped`](AlarmSchedule::clamped).
+    fn create<'a>(
+        &'a self,
+        name: &'a str,
+        schedule: &'a AlarmSchedule,
+    ) -> LocalBoxFuture<'a, Result<(), WextError>>;
+
+    /// Cancel the alarm `name`, returning whether there was one.
+    fn clear<'a>(&'a self, name: &'a str) -> LocalBoxFuture<'a, Result<bool, WextError>>;
+
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Vec<Alarm>, WextError>>;
+
+    /// Call `callback` whenever any alarm fires.
+    fn on_alarm(&self, callback: Box<dyn FnMut(&Alarm)>);
+}
+
+/// The browser's `chrome.alarms`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BrowserAlarms;
+
+impl Alarms for BrowserAlarms {
+    fn create<'a>(
+        &'a self,
+        name: &'a str,
+        schedule: &'a AlarmSchedule,
+    ) -> LocalBoxFuture<'a, Result<(), WextError>> {
+        Box::pin(async move {
+            let info = schedule
+                .clamped(SystemClock.now())
+                .serialize(&serde_wasm_bindgen::Serializer::json_compatible())?;
+            browser::alarms().create(name, info).await?;
+            Ok(())
+        })
+    }
+
+    fn clear<'a>(&'a self, name: &'a str) -> LocalBoxFuture<'a, Result<bool, WextError>> {
+        Box::pin(async move {
+            let cleared = browser::alarms().clear(name).await?;
+            Ok(cleared.as_bool().unwrap_or_default())
+        })
+    }
+
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Vec<Alarm>, WextError>> {
+        Box::pin(async move {
+            let alarms = browser::alarms().get_all().await?;
+            Ok(serde_wasm_bindgen::from_value(alarms)?)
+        })
+    }
+
+    fn on_alarm(&self, mut callback: Box<dyn FnMut(&Alarm)>) {
+        browser::listen(&browser::alarms().on_alarm(), move |alarm, _| {
+            if let Ok(alarm) = serde_wasm_bindgen::from_value::<Alarm>(alarm) {
+                callback(&alarm);
+            }
+        });
+    }
+}
+
+/// Runs a callback at a given time, whether or not the background survives until
+/// then: with a timer while it's alive, and with an alarm (which restarts it) if it
+/// isn't. Only the earliest pending time is kept.
+#[derive(Clone)]
+pub struct Wakeup {
+    inner: Rc<WakeupInner>,
+}
+
+struct WakeupInner {
+    name: String,
+    clock: Rc<dyn Clock>,
+    alarms: Rc<dyn Alarms>,
+    on_wake: Box<dyn Fn()>,
+    next: Cell<Option<f64>>,
+}
+
+impl Wakeup {
+    /// Call `on_wake` when the alarm `name` fires or a scheduled time passes. In the
+    /// background script, this must be created synchronously during startup, so the
+    /// alarm listener is registered in time.
+    pub fn new(
+        name: &str,
+        clock: Rc<dyn Clock>,
+        alarms: Rc<dyn Alarms>,
+        on_wake: impl Fn() + 'static,
+    ) -> Self {
+        let inner = Rc::new(WakeupInner {
+            name: name.to_string(),
+            clock,
+            alarms,
+            on_wake: Box::new(on_wake),
+            next: Cell::new(None),
+        });
+        let weak = Rc::downgrade(&inner);
+        inner.alarms.on_alarm(Box::new(move |alarm| {
+            let Some(inner) = weak.upgrade() else {
+                return;
+            };
+            if alarm.name == inner.name {
+                inner.next.set(None);
+                (inner.on_wake)();
+            }
+        }));
+        Wakeup { inner }
+    }
+
+    /// In the extension: the real clock and `chrome.alarms`.
+    pub fn browser(name: &str, on_wake: impl Fn() + 'static) -> Self {
+        Wakeup::new(name, Rc::new(SystemClock), Rc::new(BrowserAlarms), on_wake)
+    }
+
+    /// The pending wake-up time, if any.
+    pub fn next(&self) -> Option<f64> {
+        self.inner.next.get()
+    }
+
+    /// Wake up at `when` (in milliseconds since the epoch), unless a wake-up at or
+    /// before then is already pending. Resolves once the alarm is set.
+    pub async fn schedule(&self, when: f64) -> Result<(), WextError> {
+        if self.next().is_some_and(|next| next <= when) {
+            return Ok(());
+        }
+        self.inner.next.set(Some(when));
+        let now = self.inner.clock.now();
+        let weak = Rc::downgrade(&self.inner);
+        self.inner.clock.set_timeout(
+            (when - now).max(0.0),
+            Box::new(move || {
+                let Some(inner) = weak.upgrade() else {
+                    return;
+                };
+                if inner.next.get() == Some(when) {
+                    inner.next.set(None);
+                    (inner.on_wake)();
+                }
+            }),
+        );
+        self.inner
+            .alarms
+            .create(&self.inner.name, &AlarmSchedule::at(when))
+            .await
+    }
+}
+
+/// What an [`AlarmRoute`]'s handler returns.
+pub type AlarmFuture = LocalBoxFuture<'static, Result<(), String>>;
+
+/// A periodic alarm and the function it runs, generated by [`on_alarm`].
+#[derive(Debug, Clone, Copy)]
+pub struct AlarmRoute {
+    pub name: &'static str,
+    pub period_minutes: f64,
+    /// Minutes before the first run, the period if not set.
+    pub delay_minutes: Option<f64>,
+    pub handler: fn() -> AlarmFuture,
+}
+
+impl AlarmRoute {
+    /// The job kind the handler runs as.
+    fn job_kind(&self) -> String {
+        format!("wext.alarm.{}", self.name)
+    }
+
+    fn schedule(&self) -> AlarmSchedule {
+        AlarmSchedule {
+            delay_in_minutes: Some(self.delay_minutes.unwrap_or(self.period_minutes)),
+            ..AlarmSchedule::every(self.period_minutes)
+        }
+    }
+}
+
+/// The return types an [`on_alarm`] function can have.
+pub trait AlarmOutcome {
+    fn into_result(self) -> Result<(), String>;
+}
+
+impl AlarmOutcome for () {
+    fn into_result(self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+impl<E: fmt::Display> AlarmOutcome for Result<(), E> {
+    fn into_result(self) -> Result<(), String> {
+        self.map_err(|e| e.to_string())
+    }
+}
+
+/// Create the alarms of `routes`, and run their handlers when they fire. Call this
+/// synchronously during startup in the background, before [`jobs::install`].
+pub fn route(routes: &[AlarmRoute]) {
+    let routes = routes.to_vec();
+    for route in &routes {
+        let handler = route.handler;
+        jobs::register(&route.job_kind(), move |(): ()| handler());
+    }
+
+    let fired = routes.clone();
+    BrowserAlarms.on_alarm(Box::new(move |alarm| {
+        let Some(route) = fired.iter().find(|route| route.name == alarm.name).copied() else {
+            return;
+        };
+        spawn_local(async move {
+            if let Err(e) = run(&route).await {
+                health::record_error("alarms", format!("{}: {e}", route.name));
+            }
+        });
+    }));
+
+    spawn_local(async move {
+        if let Err(e) = create_missing(&routes).await {
+            warn!(format!("Failed to create the alarms: {e}"));
+        }
+    });
+}
+
+/// Queue the route's job, unless one is already queued or running.
+async fn run(route: &AlarmRoute) -> Result<(), WextError> {
+    let kind = route.job_kind();
+    let busy = jobs::list()
+        .await?
+        .iter()
+        .any(|job| job.kind == kind && job.state != JobState::Failed);
+    if !busy {
+        jobs::enqueue(&kind, &()).await?;
+    }
+    Ok(())
+}
+
+/// Create the alarms that don't exist yet or have a different period, keeping the
+/// schedule of the others.
+async fn create_missing(routes: &[AlarmRoute]) -> Result<(), WextError> {
+    let existing = BrowserAlarms.get_all().await?;
+    for route in routes {
+        let current = existing.iter().find(|alarm| alarm.name == route.name);
+        let period = route
+            .schedule()
+            .clamped(SystemClock.now())
+            .period_in_minutes;
+        if current.is_none_or(|alarm| alarm.period_in_minutes != period) {
+            BrowserAlarms.create(route.name, &route.schedule()).await?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::Cell, rc::Rc};
+
+    use futures::executor::block_on;
+
+    use super::{Wakeup, MIN_ALARM_DELAY_MS};
+    use crate::mock::{MockAlarms, MockClock};
+
+    /// A wake-up named `wake`, with the number of times it woke.
+    fn wakeup(clock: &MockClock, alarms: &MockAlarms) -> (Wakeup, Rc<Cell<u32>>) {
+        let wakes = Rc::new(Cell::new(0));
+        let wakeup = Wakeup::new("wake", Rc::new(clock.clone()), Rc::new(alarms.clone()), {
+            let wakes = wakes.clone();
+            move || wakes.set(wakes.get() + 1)
+        });
+        (wakeup, wakes)
+    }
+
+    #[test]
+    fn wakeup_keeps_the_earliest_time() {
+        let clock = MockClock::default();
+        let alarms = MockAlarms::new(&clock);
+        let (wakeup, _) = wakeup(&clock, &alarms);
+        block_on(wakeup.schedule(90_000.0)).unwrap();
+        block_on(wakeup.schedule(120_000.0)).unwrap();
+        assert_eq!(wakeup.next(), Some(90_000.0));
+        block_on(wakeup.schedule(60_000.0)).unwrap();
+        assert_eq!(wakeup.next(), Some(60_000.0));
+
+        let scheduled = alarms.scheduled();
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].scheduled_time, 60_000.0);
+    }
+
+    #[test]
+    fn wakeup_fires_sooner_than_alarms_can() {
+        let clock = MockClock::default();
+        let alarms = MockAlarms::new(&clock);
+        let (wakeup, wakes) = wakeup(&clock, &alarms);
+        block_on(wakeup.schedule(5_000.0)).unwrap();
+        assert_eq!(alarms.scheduled()[0].scheduled_time, MIN_ALARM_DELAY_MS);
+
+        clock.advance(4_999.0);
+        assert_eq!(wakes.get(), 0);
+        clock.advance(1.0);
+        assert_eq!(wakes.get(), 1);
+        assert_eq!(wakeup.next(), None);
+    }
+
+    #[test]
+    fn wakeup_fires_by_alarm_after_a_restart() {
+        let clock = MockClock::default();
+        let alarms = MockAlarms::new(&clock);
+        let (before, woken_before) = wakeup(&clock, &alarms);
+        block_on(before.schedule(60_000.0)).unwrap();
+        // The background stops, taking the timer with it, and starts again.
+        drop(before);
+        let (after, woken_after) = wakeup(&clock, &alarms);
+
+        clock.advance(60_000.0);
+        assert_eq!(woken_before.get(), 0);
+        assert_eq!(woken_after.get(), 1);
+        assert_eq!(after.next(), None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Audio playback from any context, including Chrome's MV3 service worker.
+//!
+//! The service worker has no DOM, so it can't play audio itself. There, [`play`]
+//! creates an offscreen document (the registry's `offscreen.html`, which needs the
+//! `offscreen` permission) that plays the sound and closes itself when it's done.
+//! Contexts with a DOM, like extension pages and Firefox's event page, play directly.
+//!
+//! ```ignore
+//! audio::play(&browser::runtime().get_url("sounds/done.ogg"), 0.8).await?;
+//! ```
+
+use std::cell::RefCell;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::HtmlAudioElement;
+
+use crate::{browser, entry::wext_entry, error::WextError, pages::PageId};
+
+/// Why the offscreen document exists, as `offscreen.createDocument` requires.
+const JUSTIFICATION: &str = "Play audio from the background script";
+
+/// A command for the offscreen document.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "wextOffscreenAudio", rename_all = "snake_case")]
+enum Command {
+    Play { url: String, volume: f64 },
+}
+
+thread_local! {
+    /// What's playing in this context.
+    static PLAYING: RefCell<Option<HtmlAudioElement>> = const { RefCell::new(None) };
+}
+
+/// Play the audio at `url`, at `volume` from 0 to 1, replacing anything this
+/// extension is playing. Resolves once playback has started.
+pub async fn play(url: &str, volume: f64) -> Result<(), WextError> {
+    if web_sys::window().is_some() {
+        return play_here(url, volume, false).await;
+    }
+    let command = Command::Play {
+        url: url.to_string(),
+        volume,
+    };
+    if has_offscreen_document().await? {
+        send(&command).await
+    } else {
+        // A new document plays what's in its URL, so there's no waiting for it to
+        // start listening for commands.
+        let query = serde_json::to_string(&command).map_err(|e| e.to_string())?;
+        let url = format!(
+            "{}?{}",
+            PageId::Offscreen.url(),
+            String::from(js_sys::encode_uri_component(&query))
+        );
+        browser::offscreen()
+            .create_document(browser::object(&[
+                ("url", url.into()),
+                (
+                    "reasons",
+                    js_sys::Array::of1(&"AUDIO_PLAYBACK".into()).into(),
+                ),
+                ("justification", JUSTIFICATION.into()),
+            ]))
+            .await?;
+        Ok(())
+    }
+}
+
+/// Stop what [`play`] started.
+pub async fn stop() -> Result<(), WextError> {
+    if web_sys::window().is_some() {
+        stop_here();
+        return Ok(());
+    }
+    if has_offscreen_document().await? {
+        browser::offscreen().close_document().await?;
+    }
+    Ok(())
+}
+
+async fn play_here(url: &str, volume: f64, close_when_done: bool) -> Result<(), WextError> {
+    stop_here();
+    let audio = HtmlAudioElement::new_with_src(url)?;
+    audio.set_volume(volume.clamp(0.0, 1.0));
+    if close_when_done {
+        let close = Closure::<dyn Fn()>::new(|| {
+            if let Some(window) = web_sys::window() {
+                let _ = window.close();
+            }
+        });
+        audio.set_onended(Some(close.as_ref().unchecked_ref()));
+        audio.set_onerror(Some(close.as_ref().unchecked_ref()));
+        close.forget();
+    }
+    PLAYING.set(Some(audio.clone()));
+    JsFuture::from(audio.play()?).await?;
+    Ok(())
+}
+
+fn stop_here() {
+    if let Some(audio) = PLAYING.take() {
+        let _ = audio.pause();
+    }
+}
+
+async fn has_offscreen_document() -> Result<bool, WextError> {
+    let contexts = browser::runtime()
+        .get_contexts(browser::object(&[(
+            "contextTypes",
+            js_sys::Array::of1(&"OFFSCREEN_DOCUMENT".into()).into(),
+        )]))
+        .await?;
+    Ok(js_sys::Array::from(&contexts).length() > 0)
+}
+
+async fn send(command: &Command) -> Result<(), WextError> {
+    let message = command.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?;
+    // The offscreen document doesn't respond, which Chrome can report as an error.
+    let _ = browser::runtime().send_message(message).await;
+    Ok(())
+}
+
+/// The offscreen document: plays what its URL says, then what it's sent.
+#[wext_entry(page)]
+pub async fn offscreen_page() {
+    browser::listen(&browser::runtime().on_message(), |message, _| {
+        let Ok(command) = serde_wasm_bindgen::from_value::<Command>(message) else {
+            return;
+        };
+        wasm_bindgen_futures::spawn_local(run(command));
+    });
+
+    let query = web_sys::window()
+        .and_then(|window| window.location().search().ok())
+        .unwrap_or_default();
+    let query = js_sys::decode_uri_component(query.trim_start_matches('?'))
+        .ok()
+        .and_then(|query| query.as_string())
+        .unwrap_or_default();
+    if let Ok(command) = serde_json::from_str::<Command>(&query) {
+        run(command).await;
+    }
+}
+
+async fn run(command: Command) {
+    match command {
+        Command::Play { url, volume } => {
+            if let Err(e) = play_here(&url, volume, true).await {
+                gloo_console::warn!("Failed to play audio:", e);
+                if let Some(window) = web_sys::window() {
+                    let _ = window.close();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,113 @@
+//! Sizing the popup to its content.
+//!
+//! Browsers size a popup from the page's initial layout, and don't always follow when
+//! the content grows or shrinks later, which leaves scrollbars or clipped content.
+//! [`AutoSize`] watches its content with a `ResizeObserver` and sizes the document
+//! body to match, clamped to the browser's popup limits.
+//!
+//! In Firefox, a popup opened from the overflow menu is laid out in the menu panel,
+//! which is narrower than most popups. When the browser doesn't give the popup the
+//! requested width, the explicit width is dropped so the content wraps to the panel
+//! instead of scrolling sideways.
+
+use std::cell::Cell;
+
+use leptos::{ev, html, prelude::*};
+use wasm_bindgen::prelude::*;
+use web_sys::{ResizeObserver, ResizeObserverEntry};
+
+/// Size limits for the popup body, in CSS pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Constraints {
+    pub min_width: f64,
+    pub min_height: f64,
+    pub max_width: f64,
+    pub max_height: f64,
+}
+
+impl Default for Constraints {
+    /// The limits Chrome and Firefox both enforce for action popups.
+    fn default() -> Self {
+        Constraints {
+            min_width: 25.0,
+            min_height: 25.0,
+            max_width: 800.0,
+            max_height: 600.0,
+        }
+    }
+}
+
+thread_local! {
+    /// The width last requested for the body.
+    static REQUESTED_WIDTH: Cell<Option<f64>> = const { Cell::new(None) };
+}
+
+/// Size the popup to fit its children, within `constraints`.
+#[component]
+pub fn AutoSize(
+    children: Children,
+    #[prop(optional)] constraints: Option<Constraints>,
+) -> impl IntoView {
+    let constraints = constraints.unwrap_or_default();
+    let content = NodeRef::<html::Div>::new();
+    Effect::new(move |_| {
+        let Some(content) = content.get() else {
+            return;
+        };
+        let callback = Closure::<dyn Fn(js_sys::Array)>::new(move |entries: js_sys::Array| {
+            let Some(entry) = entries.get(0).dyn_into::<ResizeObserverEntry>().ok() else {
+                return;
+            };
+            let rect = entry.target().get_bounding_client_rect();
+            resize(rect.width().ceil(), rect.height().ceil(), constraints);
+        });
+        let observer = ResizeObserver::new(callback.as_ref().unchecked_ref()).unwrap();
+        observer.observe(&content);
+        // The popup lives as long as its page, so the observer does too.
+        callback.forget();
+    });
+    let handle = window_event_listener(ev::resize, |_| fit_panel());
+    on_cleanup(move || handle.remove());
+
+    view! {
+        <div node_ref=content style="display:inline-block;vertical-align:top">
+            {children()}
+        </div>
+    }
+}
+
+/// Apply a content size to the body.
+fn resize(width: f64, height: f64, constraints: Constraints) {
+    let body = document().body().unwrap();
+    let style = body.style();
+    let width = width.clamp(constraints.min_width, constraints.max_width);
+    let height = height.clamp(constraints.min_height, constraints.max_height);
+    style.set_property("margin", "0").unwrap();
+    style.set_property("width", &format!("{width}px")).unwrap();
+    style
+        .set_property("height", &format!("{height}px"))
+        .unwrap();
+    style.remove_property("max-width").unwrap();
+    // Scroll rather than clip if the content exceeds the limits.
+    style.set_property("overflow", "auto").unwrap();
+    REQUESTED_WIDTH.set(Some(width));
+    fit_panel();
+}
+
+/// If the browser gave the popup less width than requested (e.g. the Firefox overflow
+/// menu), fit the panel instead of scrolling horizontally.
+fn fit_panel() {
+    let Some(requested) = REQUESTED_WIDTH.get() else {
+        return;
+    };
+    let available = window()
+        .inner_width()
+        .unwrap()
+        .as_f64()
+        .unwrap_or(requested);
+    if available + 1.0 < requested {
+        let style = document().body().unwrap().style();
+        style.set_property("width", "auto").unwrap();
+        style.set_property("max-width", "100%").unwrap();
+    }
+}
@@ -1,8 +1,80 @@
-use wasm_bindgen::prelude::*;
-
 use gloo_console::log;
+use gloo_timers::callback::Interval;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    alarms::{self, on_alarm},
+    browser, content, diagnostics, downloads,
+    entry::wext_entry,
+    experiments, frames, health, installation, jobs, lifecycle, messaging, pages, ports, reader,
+    register_listeners, sessions, surface, translate, uninstall, update,
+};
 
-#[wasm_bindgen]
+#[wext_entry(background)]
 pub async fn background_script() {
     log!("Hello, background script!");
+    register_listeners! {
+        diagnostics::install();
+        health::install();
+        installation::install();
+
+        jobs::register("log", |message: String| async move {
+            log!("Job says:", message);
+            Ok(())
+        });
+        alarms::route(&[HEARTBEAT]);
+        jobs::install();
+        pages::install();
+        frames::install();
+        reader::install();
+        experiments::install();
+        translate::install("Translate “%s”");
+        sessions::install();
+        if cfg!(debug_assertions) {
+            messaging::middleware(messaging::log_calls);
+        }
+        messaging::install();
+        ports::install();
+        content::reinject_on_update();
+        downloads::install();
+        update::open_on_update();
+        lifecycle::install();
+        surface::install();
+        uninstall::install();
+    }
+}
+
+/// An example of periodic background work.
+#[on_alarm("heartbeat", period_minutes = 60)]
+async fn heartbeat() {
+    let _keep_alive = keep_alive();
+    log!("The hourly heartbeat alarm fired.");
+}
+
+/// How often to reset the service worker's idle timer, well within Chrome's 30 seconds.
+const KEEP_ALIVE_INTERVAL_MS: u32 = 20_000;
+
+/// Keeps the service worker running while held.
+///
+/// Chrome stops a service worker after 30 seconds without extension events or API
+/// calls, losing whatever the wasm kept in memory, even in the middle of an `await`.
+/// Hold one of these across work that has to finish in one go (a long download, a
+/// sync): until it's dropped, an extension API is called every 20 seconds, which
+/// resets the idle timer (Chrome 110 and later). To keep the worker up for its whole
+/// life, declare the background script with `keep-alive` instead.
+#[must_use = "the worker is only kept alive while this is held"]
+pub struct KeepAlive {
+    _interval: Interval,
+}
+
+/// Keep the service worker running until the returned guard is dropped.
+pub fn keep_alive() -> KeepAlive {
+    let interval = Interval::new(KEEP_ALIVE_INTERVAL_MS, || {
+        spawn_local(async {
+            let _ = browser::runtime().get_platform_info().await;
+        });
+    });
+    KeepAlive {
+        _interval: interval,
+    }
 }
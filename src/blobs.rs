@@ -0,0 +1,156 @@
+//! Moving large buffers (screenshots, rendered images) between contexts as Blob URLs,
+//! instead of copying them through messages.
+//!
+//! The context producing a buffer wraps it in a [`BlobUrl`], which owns the URL and
+//! revokes it when dropped, and sends the other context a [`BlobRef`] to it. The
+//! receiver reads it with [`BlobRef::read`], or hands the URL straight to the DOM, e.g.
+//! as an `<img>`'s `src`, without the bytes ever passing through messaging. Blob URLs
+//! are only valid within the extension's origin, so the producer has to be an extension
+//! page or offscreen document: content scripts create URLs in the page's origin, and
+//! Chrome's service worker can't create them at all.
+//!
+//! Use [`lend`] when the receiver only needs the buffer while handling a message: the
+//! URL is revoked as soon as the reply arrives.
+//!
+//! ```ignore
+//! let thumbnail = blobs::lend(&png, "image/png", |blob| async move {
+//!     Ok(messaging::send("thumbnail", &blob).await?)
+//! })
+//! .await?;
+//! ```
+//!
+//! [`live`] counts the URLs a context still owns, to spot the ones that leak.
+
+use std::{cell::RefCell, collections::BTreeSet, future::Future};
+
+use js_sys::{Array, ArrayBuffer, Reflect, Uint8Array};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Blob, BlobPropertyBag, Request, Response, Url};
+
+use crate::{
+    error::WextError,
+    fetch::{Fetch, Network},
+};
+
+thread_local! {
+    /// The URLs this context created and hasn't revoked yet.
+    static LIVE: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// A Blob URL owned by this context. Revoked when dropped, after which the
+/// [`BlobRef`]s to it can't be read anymore.
+#[derive(Debug)]
+pub struct BlobUrl {
+    blob: BlobRef,
+}
+
+impl BlobUrl {
+    /// Copy `bytes` into a new blob of type `mime`, and create a URL for it.
+    pub fn new(bytes: &[u8], mime: &str) -> Result<BlobUrl, WextError> {
+        let parts = Array::of1(&Uint8Array::from(bytes));
+        let options = BlobPropertyBag::new();
+        options.set_type(mime);
+        let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+        BlobUrl::from_blob(&blob)
+    }
+
+    /// Create a URL for an existing blob, e.g. from `canvas.toBlob`, without copying it.
+    pub fn from_blob(blob: &Blob) -> Result<BlobUrl, WextError> {
+        let url_class = Reflect::get(&js_sys::global(), &"URL".into())?;
+        if !Reflect::has(&url_class, &"createObjectURL".into())? {
+            return Err(WextError::Other(
+                "Blob URLs can't be created in this context".to_string(),
+            ));
+        }
+        let url = Url::create_object_url_with_blob(blob)?;
+        LIVE.with_borrow_mut(|live| live.insert(url.clone()));
+        Ok(BlobUrl {
+            blob: BlobRef {
+                url,
+                size: blob.size() as u64,
+                mime: blob.type_(),
+            },
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.blob.url
+    }
+
+    /// A reference to send to another context, readable until this is dropped.
+    pub fn share(&self) -> BlobRef {
+        self.blob.clone()
+    }
+}
+
+impl Drop for BlobUrl {
+    fn drop(&mut self) {
+        let _ = Url::revoke_object_url(&self.blob.url);
+        LIVE.with_borrow_mut(|live| live.remove(&self.blob.url));
+    }
+}
+
+/// A reference to another context's [`BlobUrl`], as sent in messages.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BlobRef {
+    pub url: String,
+    /// In bytes.
+    pub size: u64,
+    /// The blob's type, e.g. `image/png`, or empty if it has none.
+    pub mime: String,
+}
+
+impl BlobRef {
+    /// The blob, without copying it into Wasm memory.
+    pub async fn blob(&self) -> Result<Blob, WextError> {
+        let response = self.fetch().await?;
+        Ok(JsFuture::from(response.blob()?).await?.unchecked_into())
+    }
+
+    /// The blob's contents, without copying them into Wasm memory.
+    pub async fn array_buffer(&self) -> Result<ArrayBuffer, WextError> {
+        let response = self.fetch().await?;
+        Ok(JsFuture::from(response.array_buffer()?)
+            .await?
+            .unchecked_into())
+    }
+
+    /// The blob's contents.
+    pub async fn read(&self) -> Result<Vec<u8>, WextError> {
+        let buffer = self.array_buffer().await?;
+        Ok(Uint8Array::new(&buffer).to_vec())
+    }
+
+    async fn fetch(&self) -> Result<Response, WextError> {
+        let request = Request::new_with_str(&self.url)?;
+        let response = Network.fetch(&request).await?;
+        if !response.ok() {
+            return Err(WextError::Other(format!(
+                "failed to read {}: status {}",
+                self.url,
+                response.status()
+            )));
+        }
+        Ok(response)
+    }
+}
+
+/// Put `bytes` in a Blob URL for as long as `f` runs, e.g. while sending it to another
+/// context and waiting for the reply, then revoke it.
+pub async fn lend<T, F, Fut>(bytes: &[u8], mime: &str, f: F) -> Result<T, WextError>
+where
+    F: FnOnce(BlobRef) -> Fut,
+    Fut: Future<Output = Result<T, WextError>>,
+{
+    let url = BlobUrl::new(bytes, mime)?;
+    let result = f(url.share()).await;
+    drop(url);
+    result
+}
+
+/// How many Blob URLs this context owns.
+pub fn live() -> usize {
+    LIVE.with_borrow(BTreeSet::len)
+}
@@ -0,0 +1,475 @@
+//! Thin bindings over the WebExtension APIs.
+//!
+//! Everything is looked up through the global `chrome` object, which both Chrome and
+//! Firefox expose (Firefox additionally exposes `browser`, but `chrome` returns promises
+//! there as well under MV3). Namespaces are resolved at call time rather than at
+//! wasm instantiation time, so contexts that lack an API (e.g. content scripts and
+//! `chrome.alarms`) only fail when they actually use it.
+
+use std::{cell::Cell, panic::Location};
+
+use js_sys::{Function, Object, Reflect};
+use wasm_bindgen::prelude::*;
+
+use crate::entry;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A `chrome.events.Event`, e.g. `chrome.alarms.onAlarm`.
+    #[derive(Debug, Clone)]
+    pub type Event;
+
+    #[wasm_bindgen(method, js_name = addListener)]
+    pub fn add_listener(this: &Event, callback: &Function);
+
+    #[wasm_bindgen(method, js_name = removeListener)]
+    pub fn remove_listener(this: &Event, callback: &Function);
+
+    /// A `chrome.storage.StorageArea`, e.g. `chrome.storage.local`.
+    #[derive(Debug, Clone)]
+    pub type StorageArea;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn get(this: &StorageArea, keys: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn set(this: &StorageArea, items: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn remove(this: &StorageArea, keys: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn clear(this: &StorageArea) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.alarms` namespace.
+    #[derive(Debug, Clone)]
+    pub type Alarms;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn create(this: &Alarms, name: &str, info: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn clear(this: &Alarms, name: &str) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = getAll, catch)]
+    pub async fn get_all(this: &Alarms) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, getter = onAlarm)]
+    pub fn on_alarm(this: &Alarms) -> Event;
+
+    /// The `chrome.runtime` namespace.
+    #[derive(Debug, Clone)]
+    pub type Runtime;
+
+    #[wasm_bindgen(method, getter)]
+    pub fn id(this: &Runtime) -> Option<String>;
+
+    #[wasm_bindgen(method, js_name = sendMessage, catch)]
+    pub async fn send_message(this: &Runtime, message: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = getManifest)]
+    pub fn get_manifest(this: &Runtime) -> Object;
+
+    #[wasm_bindgen(method, js_name = getURL)]
+    pub fn get_url(this: &Runtime, path: &str) -> String;
+
+    #[wasm_bindgen(method, js_name = openOptionsPage, catch)]
+    pub async fn open_options_page(this: &Runtime) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = setUninstallURL, catch)]
+    pub async fn set_uninstall_url(this: &Runtime, url: &str) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = getContexts, catch)]
+    pub async fn get_contexts(this: &Runtime, filter: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = getPlatformInfo, catch)]
+    pub async fn get_platform_info(this: &Runtime) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, getter = onMessage)]
+    pub fn on_message(this: &Runtime) -> Event;
+
+    #[wasm_bindgen(method, getter = onMessageExternal)]
+    pub fn on_message_external(this: &Runtime) -> Event;
+
+    #[wasm_bindgen(method, getter = onInstalled)]
+    pub fn on_installed(this: &Runtime) -> Event;
+
+    #[wasm_bindgen(method, getter = onConnect)]
+    pub fn on_connect(this: &Runtime) -> Event;
+
+    #[wasm_bindgen(method, catch)]
+    pub fn connect(this: &Runtime, info: JsValue) -> Result<Port, JsValue>;
+
+    /// A `chrome.runtime.Port`, as returned by `runtime.connect` and passed to
+    /// `runtime.onConnect` listeners.
+    #[derive(Debug, Clone)]
+    pub type Port;
+
+    #[wasm_bindgen(method, getter)]
+    pub fn name(this: &Port) -> String;
+
+    #[wasm_bindgen(method, getter)]
+    pub fn sender(this: &Port) -> JsValue;
+
+    #[wasm_bindgen(method, getter = onDisconnect)]
+    pub fn on_disconnect(this: &Port) -> Event;
+
+    #[wasm_bindgen(method, getter = onMessage)]
+    pub fn on_message(this: &Port) -> Event;
+
+    /// Throws if the port is disconnected.
+    #[wasm_bindgen(method, js_name = postMessage, catch)]
+    pub fn post_message(this: &Port, message: &JsValue) -> Result<(), JsValue>;
+
+    #[wasm_bindgen(method)]
+    pub fn disconnect(this: &Port);
+
+    /// The `chrome.i18n` namespace.
+    #[derive(Debug, Clone)]
+    pub type I18n;
+
+    #[wasm_bindgen(method, js_name = getMessage)]
+    pub fn get_message(this: &I18n, name: &str, substitutions: JsValue) -> String;
+
+    #[wasm_bindgen(method, js_name = getUILanguage)]
+    pub fn get_ui_language(this: &I18n) -> String;
+
+    /// The `chrome.management` namespace.
+    #[derive(Debug, Clone)]
+    pub type Management;
+
+    #[wasm_bindgen(method, js_name = uninstallSelf, catch)]
+    pub async fn uninstall_self(this: &Management, options: JsValue) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.tabs` namespace.
+    #[derive(Debug, Clone)]
+    pub type Tabs;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn query(this: &Tabs, query_info: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn create(this: &Tabs, create_properties: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn update(
+        this: &Tabs,
+        tab_id: i32,
+        update_properties: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn remove(this: &Tabs, tab_id: i32) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = sendMessage, catch)]
+    pub async fn send_message(
+        this: &Tabs,
+        tab_id: i32,
+        message: JsValue,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, getter = onRemoved)]
+    pub fn on_removed(this: &Tabs) -> Event;
+
+    /// Resolves to the group's id.
+    #[wasm_bindgen(method, catch)]
+    pub async fn group(this: &Tabs, options: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn ungroup(this: &Tabs, tab_ids: JsValue) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.tabGroups` namespace.
+    #[derive(Debug, Clone)]
+    pub type TabGroups;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn get(this: &TabGroups, group_id: i32) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn query(this: &TabGroups, query_info: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn update(
+        this: &TabGroups,
+        group_id: i32,
+        update_properties: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = move, catch)]
+    pub async fn move_group(
+        this: &TabGroups,
+        group_id: i32,
+        move_properties: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.windows` namespace.
+    #[derive(Debug, Clone)]
+    pub type Windows;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn create(this: &Windows, create_data: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn remove(this: &Windows, window_id: i32) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = getCurrent, catch)]
+    pub async fn get_current(this: &Windows) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = getAll, catch)]
+    pub async fn get_all(this: &Windows, query_options: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn update(
+        this: &Windows,
+        window_id: i32,
+        update_info: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.contextMenus` namespace.
+    #[derive(Debug, Clone)]
+    pub type ContextMenus;
+
+    /// Errors are reported through `runtime.lastError` rather than thrown.
+    #[wasm_bindgen(method)]
+    pub fn create(this: &ContextMenus, create_properties: JsValue) -> JsValue;
+
+    #[wasm_bindgen(method, getter = onClicked)]
+    pub fn on_clicked(this: &ContextMenus) -> Event;
+
+    /// The `chrome.commands` namespace.
+    #[derive(Debug, Clone)]
+    pub type Commands;
+
+    #[wasm_bindgen(method, getter = onCommand)]
+    pub fn on_command(this: &Commands) -> Event;
+
+    /// The `chrome.tts` namespace.
+    #[derive(Debug, Clone)]
+    pub type Tts;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn speak(this: &Tts, utterance: &str, options: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method)]
+    pub fn stop(this: &Tts);
+
+    #[wasm_bindgen(method, js_name = getVoices, catch)]
+    pub async fn get_voices(this: &Tts) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.offscreen` namespace.
+    #[derive(Debug, Clone)]
+    pub type Offscreen;
+
+    #[wasm_bindgen(method, js_name = createDocument, catch)]
+    pub async fn create_document(this: &Offscreen, params: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, js_name = closeDocument, catch)]
+    pub async fn close_document(this: &Offscreen) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.downloads` namespace.
+    #[derive(Debug, Clone)]
+    pub type Downloads;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn download(this: &Downloads, options: JsValue) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn cancel(this: &Downloads, download_id: i32) -> Result<JsValue, JsValue>;
+
+    #[wasm_bindgen(method, catch)]
+    pub async fn erase(this: &Downloads, query: JsValue) -> Result<JsValue, JsValue>;
+
+    /// Chrome only.
+    #[wasm_bindgen(method, getter = onDeterminingFilename)]
+    pub fn on_determining_filename(this: &Downloads) -> Event;
+
+    #[wasm_bindgen(method, getter = onCreated)]
+    pub fn on_created(this: &Downloads) -> Event;
+
+    /// The `chrome.notifications` namespace.
+    #[derive(Debug, Clone)]
+    pub type Notifications;
+
+    /// `notification_id` may be empty, to have one generated.
+    #[wasm_bindgen(method, catch)]
+    pub async fn create(
+        this: &Notifications,
+        notification_id: &str,
+        options: JsValue,
+    ) -> Result<JsValue, JsValue>;
+
+    /// The `chrome.scripting` namespace.
+    #[derive(Debug, Clone)]
+    pub type Scripting;
+
+    #[wasm_bindgen(method, js_name = executeScript, catch)]
+    pub async fn execute_script(this: &Scripting, injection: JsValue) -> Result<JsValue, JsValue>;
+}
+
+/// Look up a dotted path (e.g. `"storage.local"`) under the global `chrome` object.
+///
+/// Returns `undefined` if any part of the path is missing.
+pub fn api(path: &str) -> JsValue {
+    let mut value = Reflect::get(&js_sys::global(), &"chrome".into()).unwrap_or_default();
+    for part in path.split('.') {
+        if !value.is_object() {
+            return JsValue::UNDEFINED;
+        }
+        value = Reflect::get(&value, &part.into()).unwrap_or_default();
+    }
+    value
+}
+
+/// `chrome.storage.local`.
+pub fn storage_local() -> StorageArea {
+    api("storage.local").unchecked_into()
+}
+
+/// `chrome.storage.sync`.
+pub fn storage_sync() -> StorageArea {
+    api("storage.sync").unchecked_into()
+}
+
+/// `chrome.storage.session`.
+pub fn storage_session() -> StorageArea {
+    api("storage.session").unchecked_into()
+}
+
+/// `chrome.storage.onChanged`.
+pub fn storage_on_changed() -> Event {
+    api("storage.onChanged").unchecked_into()
+}
+
+/// `chrome.alarms`.
+pub fn alarms() -> Alarms {
+    api("alarms").unchecked_into()
+}
+
+/// `chrome.runtime`.
+pub fn runtime() -> Runtime {
+    api("runtime").unchecked_into()
+}
+
+/// `chrome.i18n`.
+pub fn i18n() -> I18n {
+    api("i18n").unchecked_into()
+}
+
+/// `chrome.management`.
+pub fn management() -> Management {
+    api("management").unchecked_into()
+}
+
+/// `chrome.tabs`.
+pub fn tabs() -> Tabs {
+    api("tabs").unchecked_into()
+}
+
+/// `chrome.tabGroups`.
+pub fn tab_groups() -> TabGroups {
+    api("tabGroups").unchecked_into()
+}
+
+/// `chrome.windows`.
+pub fn windows() -> Windows {
+    api("windows").unchecked_into()
+}
+
+/// `chrome.contextMenus`.
+pub fn context_menus() -> ContextMenus {
+    api("contextMenus").unchecked_into()
+}
+
+/// `chrome.commands`.
+pub fn commands() -> Commands {
+    api("commands").unchecked_into()
+}
+
+/// `chrome.tts`.
+pub fn tts() -> Tts {
+    api("tts").unchecked_into()
+}
+
+/// `chrome.offscreen`.
+pub fn offscreen() -> Offscreen {
+    api("offscreen").unchecked_into()
+}
+
+/// `chrome.downloads`.
+pub fn downloads() -> Downloads {
+    api("downloads").unchecked_into()
+}
+
+/// `chrome.notifications`.
+pub fn notifications() -> Notifications {
+    api("notifications").unchecked_into()
+}
+
+/// `chrome.scripting`.
+pub fn scripting() -> Scripting {
+    api("scripting").unchecked_into()
+}
+
+/// The extension version from the manifest.
+pub fn extension_version() -> String {
+    Reflect::get(&runtime().get_manifest(), &"version".into())
+        .ok()
+        .and_then(|version| version.as_string())
+        .unwrap_or_default()
+}
+
+/// Register a listener on `event` for the lifetime of the context.
+///
+/// MV3 service workers only receive events for listeners registered synchronously
+/// during startup, so these are deliberately leaked rather than torn down.
+#[track_caller]
+pub fn listen<F>(event: &Event, mut callback: F)
+where
+    F: FnMut(JsValue, JsValue) + 'static,
+{
+    let closure = Closure::<dyn FnMut(JsValue, JsValue)>::new(move |a, b| {
+        let outer = IN_LISTENER.replace(true);
+        callback(a, b);
+        IN_LISTENER.set(outer);
+    });
+    add_listener(event, closure.as_ref().unchecked_ref());
+    closure.forget();
+}
+
+thread_local! {
+    /// Whether a [`listen`] callback is running.
+    static IN_LISTENER: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Add `callback` to a browser event, like [`Event::add_listener`], but checked by the
+/// background's listener audit in debug builds (see [`entry`]). Listeners added from a
+/// [`listen`] callback, e.g. to a port that just connected, aren't audited.
+#[track_caller]
+pub fn add_listener(event: &Event, callback: &Function) {
+    if !IN_LISTENER.get() {
+        entry::audit_listener(Location::caller());
+    }
+    event.add_listener(callback);
+}
+
+/// Build a plain JS object from key/value pairs.
+pub fn object(entries: &[(&str, JsValue)]) -> JsValue {
+    let object = Object::new();
+    for (key, value) in entries {
+        Reflect::set(&object, &(*key).into(), value).unwrap();
+    }
+    object.into()
+}
+
+/// The message of a rejected browser API call, e.g. `No tab with id: 5.`
+pub fn error_message(error: &JsValue) -> String {
+    error
+        .as_string()
+        .or_else(|| {
+            Reflect::get(error, &"message".into())
+                .ok()
+                .and_then(|message| message.as_string())
+        })
+        .unwrap_or_else(|| format!("{error:?}"))
+}
@@ -0,0 +1,302 @@
+//! Capturing the user's selection from a page, e.g. for clipper or note-taking
+//! extensions.
+//!
+//! [`selection`] reads the current selection in a content script, with the HTML
+//! around it and the page's metadata. Captures can be started from the page (with
+//! [`send`]) or from the browser: [`install`] in the background adds a context menu
+//! item for selections and listens for the [`COMMAND`] keyboard shortcut, then asks
+//! the content script in that tab, which needs [`listen`], for its selection.
+//! Captures end up with the handlers registered with [`on_capture`], and the latest
+//! one is kept for the popup ([`latest`]).
+//!
+//! The browser triggers need the `contextMenus` permission, and a `commands` entry in
+//! the manifest for the shortcut:
+//!
+//! ```json
+//! "commands": {
+//!   "capture-selection": {
+//!     "suggested_key": { "default": "Alt+Shift+S" },
+//!     "description": "Capture the selection"
+//!   }
+//! }
+//! ```
+
+use std::{cell::RefCell, rc::Rc};
+
+use js_sys::{Function, Reflect};
+use leptos::prelude::{document, window};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{Element, Node};
+
+use crate::{
+    browser,
+    error::WextError,
+    lifecycle,
+    messaging::{self, MessageError},
+    reader::{collapse, meta},
+    storage,
+};
+
+/// The `commands` entry that captures the selection.
+pub const COMMAND: &str = "capture-selection";
+
+/// The context menu item's id.
+const MENU_ID: &str = "wext-capture-selection";
+
+/// Message name used by the background to ask a content script for its selection.
+const REQUEST_MESSAGE: &str = "wext.capture.request";
+/// Message name used by content scripts to deliver a capture.
+const CAPTURED_MESSAGE: &str = "wext.capture.captured";
+
+/// Storage key for the latest capture.
+const LATEST_KEY: &str = "wext.capture.latest";
+
+/// Elements that count as the block around a selection.
+const BLOCKS: &str = "p, li, blockquote, pre, td, h1, h2, h3, h4, h5, h6, article, section, div";
+
+/// The surrounding block's HTML is left out when it's longer than this.
+const MAX_CONTEXT_HTML: usize = 16 * 1024;
+
+/// A captured selection.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Capture {
+    /// The selected text, as the browser renders it.
+    pub text: String,
+    /// The selected HTML.
+    pub html: String,
+    /// The text of the block the selection is in, e.g. its paragraph.
+    pub context_text: Option<String>,
+    /// The HTML of that block, if it isn't too large.
+    pub context_html: Option<String>,
+    pub page: PageInfo,
+    /// When it was captured, in milliseconds since the epoch.
+    pub captured_at: f64,
+}
+
+/// Metadata of the page a capture comes from.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PageInfo {
+    pub url: String,
+    pub title: String,
+    pub description: Option<String>,
+    pub canonical_url: Option<String>,
+    pub site_name: Option<String>,
+    pub lang: Option<String>,
+}
+
+/// What started a capture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Trigger {
+    ContextMenu,
+    Command,
+    /// The content script sent it with [`send`].
+    Page,
+}
+
+type Handler = Rc<dyn Fn(&Capture, Trigger)>;
+
+thread_local! {
+    static HANDLERS: RefCell<Vec<Handler>> = RefCell::default();
+}
+
+#[wasm_bindgen(
+    inline_js = "export function selection_reader() { return () => getSelection().toString(); }"
+)]
+extern "C" {
+    /// A function returning the page's selected text, for `scripting.executeScript`.
+    fn selection_reader() -> Function;
+}
+
+/// The current selection in this frame, or `None` if nothing is selected.
+pub fn selection() -> Option<Capture> {
+    let selection = window().get_selection().ok()??;
+    if selection.is_collapsed() || selection.range_count() == 0 {
+        return None;
+    }
+    let text = String::from(selection.to_string());
+    if text.trim().is_empty() {
+        return None;
+    }
+
+    let fragment = document().create_element("div").ok()?;
+    for index in 0..selection.range_count() {
+        let range = selection.get_range_at(index).ok()?;
+        let contents = range.clone_contents().ok()?;
+        fragment.append_child(&contents).ok()?;
+    }
+    let block = selection
+        .get_range_at(0)
+        .ok()
+        .and_then(|range| range.common_ancestor_container().ok())
+        .and_then(|node| closest_block(&node));
+
+    Some(Capture {
+        text,
+        html: fragment.inner_html(),
+        context_text: block
+            .as_ref()
+            .map(|block| collapse(&block.text_content().unwrap_or_default())),
+        context_html: block
+            .map(|block| block.outer_html())
+            .filter(|html| html.len() <= MAX_CONTEXT_HTML),
+        page: page_info(),
+        captured_at: js_sys::Date::now(),
+    })
+}
+
+/// The closest block element around `node`.
+fn closest_block(node: &Node) -> Option<Element> {
+    let element = match node.dyn_ref::<Element>() {
+        Some(element) => element.clone(),
+        None => node.parent_element()?,
+    };
+    element.closest(BLOCKS).ok().flatten()
+}
+
+fn page_info() -> PageInfo {
+    let document = document();
+    let canonical = document
+        .query_selector("link[rel='canonical']")
+        .ok()
+        .flatten()
+        .and_then(|link| Reflect::get(&link, &"href".into()).ok())
+        .and_then(|href| href.as_string());
+    PageInfo {
+        url: document.url().unwrap_or_default(),
+        title: collapse(&document.title()),
+        description: meta(&document, "meta[name='description']")
+            .or_else(|| meta(&document, "meta[property='og:description']")),
+        canonical_url: canonical,
+        site_name: meta(&document, "meta[property='og:site_name']"),
+        lang: document
+            .document_element()
+            .and_then(|root| root.get_attribute("lang"))
+            .filter(|lang| !lang.is_empty()),
+    }
+}
+
+/// The selected text in the active tab's top frame, without a content script. Needs
+/// host permissions for the page, or `activeTab` from the popup.
+pub async fn active_tab_text() -> Result<Option<String>, WextError> {
+    let tabs = browser::tabs()
+        .query(browser::object(&[
+            ("active", true.into()),
+            ("currentWindow", true.into()),
+        ]))
+        .await?;
+    let tab = js_sys::Array::from(&tabs).get(0);
+    if !tab.is_object() {
+        return Ok(None);
+    }
+    let tab_id = Reflect::get(&tab, &"id".into())?;
+    let results = browser::scripting()
+        .execute_script(browser::object(&[
+            ("target", browser::object(&[("tabId", tab_id)])),
+            ("func", selection_reader().into()),
+        ]))
+        .await?;
+    let result = js_sys::Array::from(&results).get(0);
+    if !result.is_object() {
+        return Ok(None);
+    }
+    let text = Reflect::get(&result, &"result".into())?.as_string();
+    Ok(text.filter(|text| !text.trim().is_empty()))
+}
+
+/// Capture the selection and deliver it to the background. Returns `Ok(None)` when
+/// nothing is selected.
+pub async fn send() -> Result<Option<Capture>, MessageError> {
+    let Some(capture) = selection() else {
+        return Ok(None);
+    };
+    messaging::send::<_, ()>(CAPTURED_MESSAGE, &capture).await?;
+    Ok(Some(capture))
+}
+
+/// Answer the background's requests for the selection. Call this in the content
+/// script, before [`messaging::install`].
+pub fn listen() {
+    messaging::handle(REQUEST_MESSAGE, |_: (), _| async move { Ok(selection()) });
+}
+
+/// Call `handler` in the background for every capture.
+pub fn on_capture(handler: impl Fn(&Capture, Trigger) + 'static) {
+    HANDLERS.with_borrow_mut(|handlers| handlers.push(Rc::new(handler)));
+}
+
+/// The latest capture, e.g. for the popup to show.
+pub async fn latest() -> Result<Option<Capture>, WextError> {
+    storage::get(LATEST_KEY).await
+}
+
+/// Add the context menu item (titled `menu_title`) and keyboard command, and receive
+/// captures. Call this in the background script, before [`messaging::install`] and
+/// [`lifecycle::install`].
+pub fn install(menu_title: &str) {
+    let menu_title = menu_title.to_string();
+    // Menu items persist, so they're only created on install and update.
+    lifecycle::on_installed(move |_| {
+        browser::context_menus().create(browser::object(&[
+            ("id", MENU_ID.into()),
+            ("title", menu_title.as_str().into()),
+            ("contexts", js_sys::Array::of1(&"selection".into()).into()),
+        ]));
+    });
+    browser::listen(&browser::context_menus().on_clicked(), |info, tab| {
+        let get =
+            |target: &JsValue, key: &str| Reflect::get(target, &key.into()).unwrap_or_default();
+        if get(&info, "menuItemId").as_string().as_deref() != Some(MENU_ID) {
+            return;
+        }
+        if let Some(tab_id) = get(&tab, "id").as_f64() {
+            let frame_id = get(&info, "frameId").as_f64().unwrap_or(0.0);
+            request(tab_id as i32, frame_id as i32, Trigger::ContextMenu);
+        }
+    });
+    browser::listen(&browser::commands().on_command(), |command, tab| {
+        if command.as_string().as_deref() != Some(COMMAND) {
+            return;
+        }
+        // Commands don't say which frame has focus, so only the top frame is asked.
+        if let Some(tab_id) = Reflect::get(&tab, &"id".into())
+            .ok()
+            .and_then(|id| id.as_f64())
+        {
+            request(tab_id as i32, 0, Trigger::Command);
+        }
+    });
+    messaging::handle(CAPTURED_MESSAGE, |capture: Capture, _| async move {
+        deliver(capture, Trigger::Page).await;
+        Ok(())
+    });
+}
+
+/// Ask a frame's content script for its selection.
+fn request(tab_id: i32, frame_id: i32, trigger: Trigger) {
+    spawn_local(async move {
+        match messaging::send_to_tab::<_, Option<Capture>>(
+            tab_id,
+            Some(frame_id),
+            REQUEST_MESSAGE,
+            &(),
+        )
+        .await
+        {
+            Ok(Some(capture)) => deliver(capture, trigger).await,
+            Ok(None) => {}
+            Err(e) => gloo_console::warn!(format!("Failed to capture the selection: {e}")),
+        }
+    });
+}
+
+async fn deliver(capture: Capture, trigger: Trigger) {
+    if let Err(e) = storage::set(LATEST_KEY, &capture).await {
+        gloo_console::warn!("Failed to store the capture:", e);
+    }
+    let handlers = HANDLERS.with_borrow(|handlers| handlers.clone());
+    for handler in handlers {
+        handler(&capture, trigger);
+    }
+}
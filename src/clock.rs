@@ -0,0 +1,70 @@
+//! The current time and timers, behind a trait so time-dependent logic can run
+//! against `mock::MockClock` in tests, where time only moves when the test says so.
+//!
+//! ```ignore
+//! fn is_fresh(clock: &impl Clock, fetched_at: f64) -> bool {
+//!     clock.now() - fetched_at < TTL_MS
+//! }
+//! ```
+
+use std::rc::Rc;
+
+use futures::{channel::oneshot, future::LocalBoxFuture};
+use gloo_timers::callback::Timeout;
+
+/// A source of time.
+pub trait Clock {
+    /// Milliseconds since the epoch.
+    fn now(&self) -> f64;
+
+    /// Call `callback` after `ms` milliseconds, if this context is still alive then.
+    fn set_timeout(&self, ms: f64, callback: Box<dyn FnOnce()>);
+
+    /// Resolve after `ms` milliseconds.
+    fn sleep(&self, ms: f64) -> LocalBoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        self.set_timeout(
+            ms,
+            Box::new(move || {
+                let _ = sender.send(());
+            }),
+        );
+        Box::pin(async move {
+            let _ = receiver.await;
+        })
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for Rc<C> {
+    fn now(&self) -> f64 {
+        (**self).now()
+    }
+
+    fn set_timeout(&self, ms: f64, callback: Box<dyn FnOnce()>) {
+        (**self).set_timeout(ms, callback)
+    }
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now(&self) -> f64 {
+        (**self).now()
+    }
+
+    fn set_timeout(&self, ms: f64, callback: Box<dyn FnOnce()>) {
+        (**self).set_timeout(ms, callback)
+    }
+}
+
+/// The real time, from `Date.now()` and `setTimeout`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> f64 {
+        js_sys::Date::now()
+    }
+
+    fn set_timeout(&self, ms: f64, callback: Box<dyn FnOnce()>) {
+        Timeout::new(ms.max(0.0) as u32, callback).forget();
+    }
+}
@@ -0,0 +1,240 @@
+//! Content script runtime: mounting Leptos into the host page, and surviving the
+//! extension being updated or reloaded underneath it.
+//!
+//! When the extension is updated, reloaded or disabled, content scripts that were
+//! already injected keep running, but every `chrome.*` call starts throwing
+//! "Extension context invalidated". Browser API access from content scripts goes
+//! through [`guard`], which notices this, tears the mounted view down exactly once,
+//! and then recovers according to the configured [`Recovery`].
+
+use std::{any::Any, cell::RefCell, future::Future};
+
+use gloo_console::warn;
+use gloo_timers::future::TimeoutFuture;
+use js_sys::Reflect;
+use leptos::{mount::mount_to, prelude::*};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+use web_sys::{CustomEvent, CustomEventInit, Element};
+
+use crate::{browser, lifecycle};
+
+/// Event a freshly injected content script dispatches on `window`, so an orphaned
+/// instance from a previous version knows it has been replaced.
+const READY_EVENT: &str = "wext:content-ready";
+
+/// How long an orphaned instance waits for its replacement before showing a notice.
+const REPLACEMENT_TIMEOUT_MS: u32 = 3_000;
+
+/// What an orphaned content script does once its extension context is gone.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Recovery {
+    /// Show a small "extension updated, refresh to continue" notice.
+    #[default]
+    Notice,
+    /// Wait for the new version's content script (injected by the background's
+    /// [`reinject_on_update`]) to take over, and show the notice if it doesn't.
+    AwaitReplacement,
+    /// Tear down and stay quiet.
+    Silent,
+}
+
+#[derive(Default)]
+struct State {
+    recovery: Recovery,
+    host: Option<Element>,
+    handle: Option<Box<dyn Any>>,
+    invalidated: bool,
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::default();
+}
+
+/// Choose how to recover from an invalidated context. Defaults to [`Recovery::Notice`].
+pub fn set_recovery(recovery: Recovery) {
+    STATE.with_borrow_mut(|state| state.recovery = recovery);
+}
+
+/// Mount a Leptos view into a dedicated host element at the end of the page body.
+/// Scripts that run at `document_start` mount it right away, and the host joins the
+/// body once there is one.
+///
+/// The view is unmounted automatically if the extension context is invalidated.
+/// Also announces this instance, so that an orphaned predecessor steps aside.
+pub fn mount<F, N>(f: F)
+where
+    F: FnOnce() -> N + 'static,
+    N: IntoView,
+    N::State: 'static,
+{
+    let document = document();
+    let host = document.create_element("div").unwrap();
+    host.set_attribute("data-wext-root", "").unwrap();
+    append_to_page(host.clone());
+
+    let handle = mount_to(host.clone().unchecked_into(), f);
+    STATE.with_borrow_mut(|state| {
+        state.host = Some(host);
+        state.handle = Some(Box::new(handle));
+    });
+
+    let init = CustomEventInit::new();
+    init.set_detail(&browser::extension_version().into());
+    let event = CustomEvent::new_with_event_init_dict(READY_EVENT, &init).unwrap();
+    window().dispatch_event(&event).unwrap();
+}
+
+/// Whether the extension that injected this script is still reachable.
+///
+/// `chrome.runtime.id` disappears once the context has been invalidated.
+pub fn is_context_valid() -> bool {
+    !browser::api("runtime.id").is_undefined()
+}
+
+/// Whether `error` is the exception browsers throw from an invalidated context.
+pub fn is_invalidation_error(error: &JsValue) -> bool {
+    let message = Reflect::get(error, &"message".into())
+        .ok()
+        .and_then(|message| message.as_string())
+        .or_else(|| error.as_string())
+        .unwrap_or_default();
+    message.contains("Extension context invalidated")
+}
+
+/// Run a browser API call, tearing down and recovering if the context turns out to
+/// have been invalidated. The error is still returned to the caller.
+pub async fn guard<T>(call: impl Future<Output = Result<T, JsValue>>) -> Result<T, JsValue> {
+    let result = call.await;
+    if let Err(error) = &result {
+        if is_invalidation_error(error) || !is_context_valid() {
+            invalidate();
+        }
+    }
+    result
+}
+
+/// Tear down the mounted view and start recovery. Only the first call has an effect.
+pub fn invalidate() {
+    let Some((recovery, host, handle)) = STATE.with_borrow_mut(|state| {
+        if state.invalidated {
+            return None;
+        }
+        state.invalidated = true;
+        Some((state.recovery, state.host.take(), state.handle.take()))
+    }) else {
+        return;
+    };
+    warn!("Extension context invalidated; tearing down the content script.");
+    // Dropping the handle unmounts the view and disposes of its reactive owner.
+    drop(handle);
+    if let Some(host) = host {
+        host.remove();
+    }
+
+    match recovery {
+        Recovery::Notice => show_notice("Extension updated. Refresh the page to continue."),
+        Recovery::AwaitReplacement => spawn_local(async {
+            let replaced = std::rc::Rc::new(std::cell::Cell::new(false));
+            let flag = replaced.clone();
+            let listener = Closure::<dyn Fn()>::new(move || flag.set(true));
+            window()
+                .add_event_listener_with_callback(READY_EVENT, listener.as_ref().unchecked_ref())
+                .unwrap();
+            TimeoutFuture::new(REPLACEMENT_TIMEOUT_MS).await;
+            window()
+                .remove_event_listener_with_callback(READY_EVENT, listener.as_ref().unchecked_ref())
+                .unwrap();
+            if !replaced.get() {
+                show_notice("Extension updated. Refresh the page to continue.");
+            }
+        }),
+        Recovery::Silent => {}
+    }
+}
+
+/// Show a static (non-Leptos) notice in the corner of the page that reloads it when
+/// clicked. Only one notice is shown at a time.
+pub fn show_notice(text: &str) {
+    const NOTICE_ID: &str = "wext-context-notice";
+    let document = document();
+    if document.get_element_by_id(NOTICE_ID).is_some() {
+        return;
+    }
+    let notice = document.create_element("div").unwrap();
+    notice.set_id(NOTICE_ID);
+    notice
+        .set_attribute(
+            "style",
+            "position:fixed;inset-block-end:16px;inset-inline-end:16px;z-index:2147483647;padding:8px 12px;\
+             background:#1f2937;color:#fff;font:13px system-ui,sans-serif;border-radius:6px;\
+             box-shadow:0 2px 8px rgba(0,0,0,.3);cursor:pointer",
+        )
+        .unwrap();
+    notice.set_text_content(Some(text));
+    let reload = Closure::<dyn Fn()>::new(|| {
+        let _ = window().location().reload();
+    });
+    notice
+        .add_event_listener_with_callback("click", reload.as_ref().unchecked_ref())
+        .unwrap();
+    reload.forget();
+    append_to_page(notice);
+}
+
+/// Append `element` to the page's body. Before the body exists (in scripts that run
+/// at `document_start`), it's appended once the DOM is loaded; pages that have no
+/// body even then, such as SVG or XML documents, get it on their root element.
+fn append_to_page(element: Element) {
+    let document = document();
+    if let Some(body) = document.body() {
+        body.append_child(&element).unwrap();
+    } else if document.ready_state() == "loading" {
+        let append = Closure::once_into_js(move || append_to_page(element));
+        document
+            .add_event_listener_with_callback("DOMContentLoaded", append.unchecked_ref())
+            .unwrap();
+    } else if let Some(root) = document.document_element() {
+        root.append_child(&element).unwrap();
+    }
+}
+
+/// Re-inject content scripts into open tabs after an update.
+///
+/// Call this in the background script, before [`lifecycle::install`]. When the
+/// extension is updated or reloaded, the new version's content scripts are injected
+/// into every open tab they match, so orphaned instances using
+/// [`Recovery::AwaitReplacement`] get replaced without a page reload.
+pub fn reinject_on_update() {
+    lifecycle::on_installed(|details| {
+        if !matches!(details.reason, lifecycle::Reason::Update { .. }) {
+            return;
+        }
+        spawn_local(async {
+            if let Err(e) = reinject_all().await {
+                warn!("Failed to re-inject content scripts:", e);
+            }
+        });
+    });
+}
+
+async fn reinject_all() -> Result<(), JsValue> {
+    let manifest = browser::runtime().get_manifest();
+    let content_scripts = Reflect::get(&manifest, &"content_scripts".into())?;
+    for entry in js_sys::Array::from(&content_scripts).iter() {
+        let matches = Reflect::get(&entry, &"matches".into())?;
+        let files = Reflect::get(&entry, &"js".into())?;
+        let all_frames = Reflect::get(&entry, &"all_frames".into())?.is_truthy();
+        let tabs = browser::tabs()
+            .query(browser::object(&[("url", matches)]))
+            .await?;
+        for tab in js_sys::Array::from(&tabs).iter() {
+            let tab_id = Reflect::get(&tab, &"id".into())?;
+            let target = browser::object(&[("tabId", tab_id), ("allFrames", all_frames.into())]);
+            let injection = browser::object(&[("target", target), ("files", files.clone())]);
+            // Tabs we can't script (e.g. the web store) are expected to fail.
+            let _ = browser::scripting().execute_script(injection).await;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use gloo_timers::callback::Interval;
+use leptos::{prelude::*, spawn::spawn_local};
+
+use crate::{
+    alarms::{Alarm, Alarms, BrowserAlarms},
+    clock::{Clock, SystemClock},
+    entry::wext_entry,
+    format::{self, DateStyle, RelativeTime},
+    health::{self, Status},
+    i18n,
+    jobs::{self, Job},
+};
+
+/// How often the worker's status is refreshed.
+const STATUS_POLL_MS: u32 = 2_000;
+
+#[wext_entry(page)]
+pub async fn debug_page() {
+    mount_to_body(|| {
+        i18n::provide_direction();
+        view! {
+            <main class="p-4 font-mono text-sm">
+                <h1 class="text-lg font-bold mb-2">"Debug"</h1>
+                <WorkerHealth />
+                <JobQueue />
+            </main>
+        }
+    })
+}
+
+/// Live view of the persisted job queue.
+#[component]
+fn JobQueue() -> impl IntoView {
+    let (jobs, set_jobs) = signal(Vec::<Job>::new());
+    leptos::spawn::spawn_local(async move {
+        match jobs::list().await {
+            Ok(list) => set_jobs.set(list),
+            Err(e) => gloo_console::error!("Failed to load job queue:", e),
+        }
+    });
+    jobs::on_change(move |list| set_jobs.set(list));
+
+    view! {
+        <section>
+            <h2 class="font-bold">"Jobs (" {move || jobs.with(Vec::len)} ")"</h2>
+            <table class="w-full text-start">
+                <thead>
+                    <tr>
+                        <th>"ID"</th>
+                        <th>"Kind"</th>
+                        <th>"State"</th>
+                        <th>"Attempts"</th>
+                        <th>"Next run"</th>
+                        <th>"Last error"</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        each=move || jobs.get()
+                        key=|job| (job.id.clone(), job.attempts, job.state)
+                        children=|job| {
+                            view! {
+                                <tr>
+                                    <td>{job.id}</td>
+                                    <td>{job.kind}</td>
+                                    <td>{format!("{:?}", job.state)}</td>
+                                    <td>{format!("{}/{}", job.attempts, job.max_attempts)}</td>
+                                    <td><RelativeTime value=job.run_at/></td>
+                                    <td>{job.last_error.unwrap_or_default()}</td>
+                                </tr>
+                            }
+                        }
+                    />
+                </tbody>
+            </table>
+        </section>
+    }
+}
+
+/// The background's status, alarms and recent errors, polled while the page is open.
+#[component]
+fn WorkerHealth() -> impl IntoView {
+    let status = RwSignal::new(None::<Status>);
+    let alarms = RwSignal::new(Vec::<Alarm>::new());
+    let error = RwSignal::new(None::<String>);
+    let now = RwSignal::new(SystemClock.now());
+    let refresh = move || {
+        now.set(SystemClock.now());
+        spawn_local(async move {
+            match health::status().await {
+                Ok(current) => {
+                    status.set(Some(current));
+                    error.set(None);
+                }
+                // Reaching the worker starts it, so this is rare.
+                Err(e) => error.set(Some(e.to_string())),
+            }
+            match BrowserAlarms.get_all().await {
+                Ok(list) => alarms.set(list),
+                Err(e) => gloo_console::error!(format!("Failed to load alarms: {e}")),
+            }
+        })
+    };
+    refresh();
+    Interval::new(STATUS_POLL_MS, refresh).forget();
+
+    let time = |ms: f64| format::date(ms, DateStyle::Time);
+    let worker = move || {
+        status.get().map(|status| {
+            let worker = status.worker;
+            let uptime = ((now.get() - worker.started_at) / 1000.0).max(0.0) as u64;
+            view! {
+                <dl class="grid grid-cols-2 gap-x-4 mb-2">
+                    <dt>"Last start"</dt>
+                    <dd>{time(worker.started_at)}</dd>
+                    <dt>"Uptime"</dt>
+                    <dd>{format!("{}:{:02}:{:02}", uptime / 3600, uptime / 60 % 60, uptime % 60)}</dd>
+                    <dt>"Restarts this session"</dt>
+                    <dd>{worker.restarts()}</dd>
+                    <dt>"First start this session"</dt>
+                    <dd>{time(worker.first_started_at)}</dd>
+                </dl>
+            }
+        })
+    };
+
+    view! {
+        <section class="mb-4">
+            <h2 class="font-bold">"Worker"</h2>
+            {move || error.get().map(|e| view! { <p class="text-red-700">{e}</p> })}
+            {worker}
+            <h2 class="font-bold">"Ports (" {move || status.with(|s| s.as_ref().map_or(0, |s| s.ports.len()))} ")"</h2>
+            <table class="w-full text-start mb-2">
+                <thead>
+                    <tr>
+                        <th>"Name"</th>
+                        <th>"Sender"</th>
+                        <th>"Tab"</th>
+                        <th>"Connected"</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        each=move || status.get().map(|s| s.ports).unwrap_or_default()
+                        key=|port| (port.name.clone(), port.url.clone(), port.connected_at.to_bits())
+                        children=move |port| {
+                            view! {
+                                <tr>
+                                    <td>{port.name}</td>
+                                    <td>{port.url.unwrap_or_default()}</td>
+                                    <td>{port.tab_id.map(|id| id.to_string()).unwrap_or_default()}</td>
+                                    <td>{time(port.connected_at)}</td>
+                                </tr>
+                            }
+                        }
+                    />
+                </tbody>
+            </table>
+            <h2 class="font-bold">"Alarms (" {move || alarms.with(Vec::len)} ")"</h2>
+            <table class="w-full text-start mb-2">
+                <thead>
+                    <tr>
+                        <th>"Name"</th>
+                        <th>"Scheduled"</th>
+                        <th>"Period"</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        each=move || alarms.get()
+                        key=|alarm| (alarm.name.clone(), alarm.scheduled_time.to_bits())
+                        children=move |alarm| {
+                            view! {
+                                <tr>
+                                    <td>{alarm.name}</td>
+                                    <td>{time(alarm.scheduled_time)}</td>
+                                    <td>
+                                        {alarm
+                                            .period_in_minutes
+                                            .map(|minutes| format!("{minutes} min"))
+                                            .unwrap_or_default()}
+                                    </td>
+                                </tr>
+                            }
+                        }
+                    />
+                </tbody>
+            </table>
+            <h2 class="font-bold">"Recent errors"</h2>
+            <table class="w-full text-start">
+                <thead>
+                    <tr>
+                        <th>"Time"</th>
+                        <th>"Source"</th>
+                        <th>"Message"</th>
+                    </tr>
+                </thead>
+                <tbody>
+                    <For
+                        // Newest first.
+                        each=move || {
+                            status
+                                .get()
+                                .map(|s| s.errors.into_iter().rev().collect::<Vec<_>>())
+                                .unwrap_or_default()
+                        }
+                        key=|error| (error.at.to_bits(), error.message.clone())
+                        children=move |error| {
+                            view! {
+                                <tr class="align-top">
+                                    <td><RelativeTime value=error.at/></td>
+                                    <td>{error.source}</td>
+                                    <td class="whitespace-pre-wrap">{error.message}</td>
+                                </tr>
+                            }
+                        }
+                    />
+                </tbody>
+            </table>
+        </section>
+    }
+}
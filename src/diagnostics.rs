@@ -0,0 +1,262 @@
+//! A diagnostics report for support requests.
+//!
+//! [`report`] gathers the extension version, the browser, the settings in
+//! `storage.local` (with anything that looks like a secret redacted), the background's
+//! status and recent errors from [`health`], and the background's recent console
+//! output, into a plain-text report users can paste into an issue or an email.
+//! [`copy`] puts it on the clipboard, and [`save`] downloads it as a text file. The
+//! options page offers both in its About section.
+//!
+//! The console output is recorded by [`install`], in the background, which keeps the
+//! last [`MAX_LOGS`] lines of `console.log`, `info`, `warn` and `error`.
+
+use std::{cell::RefCell, collections::VecDeque, fmt::Write};
+
+use leptos::prelude::window;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    blobs::BlobUrl,
+    browser,
+    clock::{Clock, SystemClock},
+    error::WextError,
+    format::{self, DateStyle},
+    health, i18n, messaging,
+    storage::{Area, StorageArea},
+};
+
+/// Message name used to ask the background for its recent console output.
+const LOGS_MESSAGE: &str = "wext.diagnostics.logs";
+
+/// How many console lines the background keeps.
+pub const MAX_LOGS: usize = 200;
+
+/// Settings, and fields of settings, whose key contains any of these (ignoring case)
+/// are redacted.
+const SECRET_KEYS: &[&str] = &[
+    "key", "token", "secret", "password", "auth", "cookie", "session", "email",
+];
+
+/// Longer setting values are cut, so large caches don't drown the report.
+const MAX_VALUE_LEN: usize = 500;
+
+#[wasm_bindgen(inline_js = r#"
+function show(value) {
+  if (typeof value === "string") return value;
+  if (value instanceof Error) return value.stack || String(value);
+  try {
+    const json = JSON.stringify(value);
+    if (json !== undefined) return json;
+  } catch (_) {}
+  return String(value);
+}
+
+export function capture_console(record) {
+  for (const level of ["log", "info", "warn", "error"]) {
+    const original = console[level];
+    console[level] = (...args) => {
+      try {
+        record(level, args.map(show).join(" "));
+      } catch (_) {}
+      original.apply(console, args);
+    };
+  }
+}
+
+export async function write_clipboard(text) {
+  await navigator.clipboard.writeText(text);
+}
+"#)]
+extern "C" {
+    /// Call `record(level, text)` for every console line, before logging it.
+    fn capture_console(record: &Closure<dyn Fn(String, String)>);
+
+    #[wasm_bindgen(catch)]
+    async fn write_clipboard(text: &str) -> Result<JsValue, JsValue>;
+}
+
+/// A line of console output.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LogLine {
+    /// When it was logged, in milliseconds since the epoch.
+    pub at: f64,
+    /// `log`, `info`, `warn` or `error`.
+    pub level: String,
+    pub message: String,
+}
+
+thread_local! {
+    /// Background only: the recent console output, oldest first.
+    static LOGS: RefCell<VecDeque<LogLine>> = RefCell::default();
+}
+
+/// Start recording the background's console output, and answer [`report`]'s requests
+/// for it. Call this first thing in the background script, so early output is kept.
+pub fn install() {
+    let record = Closure::<dyn Fn(String, String)>::new(|level: String, message: String| {
+        let line = LogLine {
+            at: SystemClock.now(),
+            level,
+            message,
+        };
+        // A line logged while recording one is dropped rather than panicking.
+        LOGS.with(|logs| {
+            if let Ok(mut logs) = logs.try_borrow_mut() {
+                logs.push_back(line);
+                while logs.len() > MAX_LOGS {
+                    logs.pop_front();
+                }
+            }
+        });
+    });
+    capture_console(&record);
+    record.forget();
+    messaging::handle(LOGS_MESSAGE, |_: (), _| async move {
+        Ok(LOGS.with_borrow(|logs| logs.iter().cloned().collect::<Vec<_>>()))
+    });
+}
+
+/// The diagnostics report, as plain text. Call this from an extension page; parts
+/// that can't be gathered, e.g. while the background is stuck, say so instead.
+pub async fn report() -> String {
+    let mut report = String::new();
+    let now = SystemClock.now();
+    let _ = writeln!(report, "# Diagnostics");
+    let _ = writeln!(
+        report,
+        "Generated: {}",
+        format::date(now, DateStyle::DateTime)
+    );
+    let _ = writeln!(report, "Version: {}", browser::extension_version());
+    let user_agent = window().navigator().user_agent().unwrap_or_default();
+    let _ = writeln!(report, "Browser: {user_agent}");
+    let _ = writeln!(report, "Language: {}", i18n::ui_language());
+
+    let _ = writeln!(report, "\n## Settings");
+    match Area::Local.get_all().await {
+        Ok(settings) => {
+            for (key, value) in settings {
+                let _ = writeln!(report, "{key}: {}", redact(&key, &value));
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(report, "Couldn't read the settings: {e}");
+        }
+    }
+
+    match health::status().await {
+        Ok(status) => {
+            let _ = writeln!(report, "\n## Background");
+            let _ = writeln!(
+                report,
+                "Started: {}, restarted {} times this session, {} ports open",
+                format::relative_time(status.worker.started_at, now),
+                status.worker.restarts(),
+                status.ports.len(),
+            );
+            let _ = writeln!(report, "\n## Recent errors");
+            if status.errors.is_empty() {
+                let _ = writeln!(report, "None");
+            }
+            for error in status.errors {
+                let _ = writeln!(
+                    report,
+                    "[{}] {}: {}",
+                    format::date(error.at, DateStyle::DateTime),
+                    error.source,
+                    error.message,
+                );
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(
+                report,
+                "\n## Background\nCouldn't reach the background: {e}"
+            );
+        }
+    }
+
+    let _ = writeln!(report, "\n## Recent logs");
+    match messaging::send::<_, Vec<LogLine>>(LOGS_MESSAGE, &()).await {
+        Ok(logs) => {
+            for line in logs {
+                let _ = writeln!(
+                    report,
+                    "[{}] {}: {}",
+                    format::date(line.at, DateStyle::Time),
+                    line.level,
+                    line.message,
+                );
+            }
+        }
+        Err(e) => {
+            let _ = writeln!(report, "Couldn't get the background's logs: {e}");
+        }
+    }
+    report
+}
+
+/// Copy the report to the clipboard.
+pub async fn copy() -> Result<(), WextError> {
+    write_clipboard(&report().await).await?;
+    Ok(())
+}
+
+/// Download the report as a text file, asking where to save it.
+pub async fn save() -> Result<(), WextError> {
+    let url = BlobUrl::new(report().await.as_bytes(), "text/plain")?;
+    let date = String::from(js_sys::Date::new_0().to_iso_string());
+    browser::downloads()
+        .download(browser::object(&[
+            ("url", url.url().into()),
+            (
+                "filename",
+                format!("diagnostics-{}.txt", &date[..10]).into(),
+            ),
+            ("saveAs", true.into()),
+        ]))
+        .await?;
+    // The download has started, so the blob can go.
+    drop(url);
+    Ok(())
+}
+
+/// A setting's value for the report: redacted if its key looks like a secret, with
+/// the same done for the fields of objects in it, and shortened if it's long.
+fn redact(key: &str, value: &Value) -> String {
+    if is_secret(key) {
+        return "[redacted]".to_string();
+    }
+    let mut value = value.clone();
+    redact_fields(&mut value);
+    let value = value.to_string();
+    match value.char_indices().nth(MAX_VALUE_LEN) {
+        Some((end, _)) => format!("{}… ({} bytes)", &value[..end], value.len()),
+        None => value,
+    }
+}
+
+/// Redact the secret-looking fields of objects anywhere in `value`, e.g. a settings
+/// object's `apiKey`.
+fn redact_fields(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields {
+                if is_secret(key) {
+                    *field = Value::from("[redacted]");
+                } else {
+                    redact_fields(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(redact_fields),
+        _ => {}
+    }
+}
+
+fn is_secret(key: &str) -> bool {
+    let lowercase = key.to_lowercase();
+    SECRET_KEYS.iter().any(|secret| lowercase.contains(secret))
+}
@@ -0,0 +1,160 @@
+//! Modal and confirmation dialogs that fit in a popup as well as a full page.
+//!
+//! A [`Modal`] is shown while its `open` signal is true. While it is, the page behind
+//! it doesn't scroll, focus is kept inside it (see [`FocusTrap`]), and Escape or a
+//! click on the backdrop closes it instead of the popup (see [`use_escape`]). Focus
+//! goes back where it was once it closes, and only the dialog's own
+//! [`hotkeys`](crate::hotkeys) work. The dialog is never larger than the
+//! viewport, so in a small popup its content scrolls instead of being clipped.
+//!
+//! [`Confirm`] is a modal asking to confirm an action, e.g. before deleting something:
+//!
+//! ```ignore
+//! let confirming = RwSignal::new(false);
+//! view! {
+//!     <button on:click=move |_| confirming.set(true)>"Reset"</button>
+//!     <Confirm open=confirming title="Reset all settings?" confirm_label="Reset" destructive=true
+//!         on_confirm=move |_| reset()>
+//!         "This can't be undone."
+//!     </Confirm>
+//! }
+//! ```
+
+use std::cell::RefCell;
+
+use leptos::{ev::MouseEvent, prelude::*};
+
+use crate::{
+    a11y::{use_escape, FocusTrap},
+    hotkeys,
+};
+
+thread_local! {
+    /// How many modals are open, and the body's `overflow` from before the first.
+    static SCROLL_LOCK: RefCell<(usize, String)> = RefCell::default();
+}
+
+/// A modal dialog, shown while `open` is true. `label` is its accessible name; show it
+/// as a heading in `children` too, if the dialog has one.
+#[component]
+pub fn Modal(
+    open: RwSignal<bool>,
+    #[prop(into)] label: String,
+    children: ChildrenFn,
+) -> impl IntoView {
+    let label = StoredValue::new(label);
+    let children = StoredValue::new(children);
+    view! {
+        <Show when=move || open.get()>
+            <ModalLayer open label=label.get_value()>
+                {children.with_value(|children| children())}
+            </ModalLayer>
+        </Show>
+    }
+}
+
+/// The open modal: the backdrop and the dialog itself.
+#[component]
+fn ModalLayer(open: RwSignal<bool>, label: String, children: Children) -> impl IntoView {
+    lock_scroll();
+    on_cleanup(unlock_scroll);
+    use_escape(move || open.set(false));
+    hotkeys::use_scope("Dialog", true);
+
+    // Only clicks on the backdrop itself, not ones bubbling up from the dialog.
+    let on_click = move |ev: MouseEvent| {
+        if ev.target() == ev.current_target() {
+            open.set(false);
+        }
+    };
+    view! {
+        <div
+            class="fixed inset-0 z-40 flex items-center justify-center p-2 bg-black/40"
+            on:click=on_click
+        >
+            <div
+                role="dialog"
+                aria-modal="true"
+                aria-label=label
+                class="w-full max-w-sm max-h-full overflow-auto rounded bg-white p-3 shadow-lg"
+            >
+                <FocusTrap>{children()}</FocusTrap>
+            </div>
+        </div>
+    }
+}
+
+/// A modal asking to confirm an action. Cancel comes first, and is focused when it
+/// opens, so pressing Enter by habit doesn't confirm; set `destructive` for actions
+/// that delete or reset something, to show the confirm button in red.
+#[component]
+pub fn Confirm(
+    open: RwSignal<bool>,
+    #[prop(into)] title: String,
+    /// The confirm button's text, e.g. "Delete".
+    #[prop(into)]
+    confirm_label: String,
+    #[prop(optional)] destructive: bool,
+    #[prop(into)] on_confirm: Callback<()>,
+    /// Details shown under the title.
+    children: ChildrenFn,
+) -> impl IntoView {
+    let confirm_class = if destructive {
+        "px-3 py-1 rounded bg-red-700 text-white"
+    } else {
+        "px-3 py-1 rounded bg-blue-600 text-white"
+    };
+    let heading = title.clone();
+    let confirm_label = StoredValue::new(confirm_label);
+    let children = StoredValue::new(children);
+    view! {
+        <Modal open label=title>
+            <h2 class="font-bold mb-2">{heading.clone()}</h2>
+            <div class="mb-3">{children.with_value(|children| children())}</div>
+            <div class="flex justify-end gap-2">
+                <button type="button" class="px-3 py-1 border rounded" on:click=move |_| open.set(false)>
+                    "Cancel"
+                </button>
+                <button
+                    type="button"
+                    class=confirm_class
+                    on:click=move |_| {
+                        open.set(false);
+                        on_confirm.run(());
+                    }
+                >
+                    {confirm_label.get_value()}
+                </button>
+            </div>
+        </Modal>
+    }
+}
+
+/// Keep the page behind modals from scrolling while any is open.
+fn lock_scroll() {
+    let Some(body) = document().body() else {
+        return;
+    };
+    SCROLL_LOCK.with_borrow_mut(|(count, overflow)| {
+        if *count == 0 {
+            *overflow = body
+                .style()
+                .get_property_value("overflow")
+                .unwrap_or_default();
+            let _ = body.style().set_property("overflow", "hidden");
+        }
+        *count += 1;
+    });
+}
+
+fn unlock_scroll() {
+    let Some(body) = document().body() else {
+        return;
+    };
+    SCROLL_LOCK.with_borrow_mut(|(count, overflow)| {
+        *count = count.saturating_sub(1);
+        if *count == 0 {
+            let _ = body.style().set_property("overflow", overflow);
+        }
+    });
+}
@@ -0,0 +1,256 @@
+//! Renaming and rerouting downloads by rule.
+//!
+//! The rules are edited on the options page and stored in `storage.local`. The
+//! background applies them with [`install`] (which needs the `downloads` permission):
+//!
+//! - Chrome asks extensions for a filename before saving, through
+//!   `downloads.onDeterminingFilename`, so the first matching rule's target is simply
+//!   suggested.
+//! - Firefox has no such event. There, new downloads matching a rule are cancelled
+//!   and started again with the new filename. Only `http(s)` downloads can be
+//!   restarted; others (e.g. `blob:` URLs created by the page) are left alone.
+
+use js_sys::Function;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    browser,
+    error::WextError,
+    match_pattern::{MatchPattern, PatternError},
+    storage,
+};
+
+/// Storage key for the rules.
+pub const RULES_KEY: &str = "options.downloadRules";
+
+/// A download rule. Empty conditions match every download.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Rule {
+    /// A match pattern for the download's URL, e.g. `https://*.example.com/*`.
+    pub url: String,
+    /// Comma-separated file extensions, e.g. `pdf, epub`.
+    pub extensions: String,
+    /// Where to save the file, relative to the downloads folder, e.g.
+    /// `Papers/{host}/{filename}`. `{filename}`, `{name}` (without the extension),
+    /// `{ext}` and `{host}` are substituted, and a target ending in `/` is a folder
+    /// that keeps the original filename.
+    pub target: String,
+}
+
+impl Rule {
+    /// Check the rule is complete and its URL pattern and target are valid.
+    pub fn check(&self) -> Result<(), String> {
+        if let Some(pattern) = self.pattern() {
+            pattern.map_err(|e| format!("Invalid URL pattern: {e}."))?;
+        }
+        let target = self.target.trim();
+        if target.is_empty() {
+            return Err("The target is required.".to_string());
+        }
+        let escapes = target.starts_with(['/', '\\'])
+            || target.contains(':')
+            || target.split(['/', '\\']).any(|part| part == "..");
+        if escapes {
+            return Err(
+                "The target must be a relative path inside the downloads folder.".to_string(),
+            );
+        }
+        Ok(())
+    }
+
+    /// Whether the rule applies to a download of `filename` from `url`.
+    pub fn matches(&self, url: &str, filename: &str) -> bool {
+        let url_matches = match self.pattern() {
+            Some(pattern) => pattern.is_ok_and(|pattern| pattern.matches(url)),
+            None => true,
+        };
+        let extensions: Vec<String> = self
+            .extensions
+            .split(',')
+            .map(|extension| {
+                extension
+                    .trim()
+                    .trim_start_matches('.')
+                    .to_ascii_lowercase()
+            })
+            .filter(|extension| !extension.is_empty())
+            .collect();
+        let extension_matches = extensions.is_empty()
+            || split_extension(filename)
+                .1
+                .is_some_and(|extension| extensions.contains(&extension.to_ascii_lowercase()));
+        url_matches && extension_matches
+    }
+
+    /// The path to save a download of `filename` from `url` at.
+    pub fn target_for(&self, url: &str, filename: &str) -> String {
+        let (name, extension) = split_extension(filename);
+        let mut target = self
+            .target
+            .trim()
+            .replace("{filename}", filename)
+            .replace("{name}", name)
+            .replace("{ext}", extension.unwrap_or_default())
+            .replace("{host}", &host(url));
+        if target.ends_with('/') {
+            target.push_str(filename);
+        }
+        target
+    }
+
+    fn pattern(&self) -> Option<Result<MatchPattern, PatternError>> {
+        let url = self.url.trim();
+        (!url.is_empty()).then(|| MatchPattern::parse(url))
+    }
+}
+
+/// Check every rule, naming the first invalid one.
+pub fn check_rules(rules: &[Rule]) -> Result<(), String> {
+    for (index, rule) in rules.iter().enumerate() {
+        rule.check()
+            .map_err(|e| format!("Rule {}: {e}", index + 1))?;
+    }
+    Ok(())
+}
+
+/// Where the first matching valid rule saves a download, if any matches.
+pub fn apply(rules: &[Rule], url: &str, filename: &str) -> Option<String> {
+    rules
+        .iter()
+        .find(|rule| rule.check().is_ok() && rule.matches(url, filename))
+        .map(|rule| rule.target_for(url, filename))
+}
+
+/// The stored rules.
+pub async fn rules() -> Result<Vec<Rule>, WextError> {
+    Ok(storage::get(RULES_KEY).await?.unwrap_or_default())
+}
+
+/// The parts of `downloads.DownloadItem` needed here.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Item {
+    id: i32,
+    url: String,
+    #[serde(default)]
+    final_url: Option<String>,
+    /// A bare name in `onDeterminingFilename`, an absolute path in `onCreated`.
+    #[serde(default)]
+    filename: String,
+    by_extension_id: Option<String>,
+}
+
+impl Item {
+    fn url(&self) -> &str {
+        self.final_url.as_deref().unwrap_or(&self.url)
+    }
+
+    fn basename(&self) -> &str {
+        self.filename.rsplit(['/', '\\']).next().unwrap_or_default()
+    }
+}
+
+/// Apply the rules to new downloads. Call this in the background script.
+pub fn install() {
+    if browser::api("downloads.onDeterminingFilename").is_undefined() {
+        browser::listen(&browser::downloads().on_created(), |item, _| {
+            let Ok(item) = serde_wasm_bindgen::from_value::<Item>(item) else {
+                return;
+            };
+            spawn_local(async move {
+                if let Err(e) = reissue(item).await {
+                    gloo_console::warn!("Failed to reroute a download:", e);
+                }
+            });
+        });
+        return;
+    }
+
+    let listener =
+        Closure::<dyn Fn(JsValue, Function) -> JsValue>::new(|item: JsValue, suggest: Function| {
+            let Ok(item) = serde_wasm_bindgen::from_value::<Item>(item) else {
+                return JsValue::FALSE;
+            };
+            spawn_local(async move {
+                let rules = rules().await.unwrap_or_else(|e| {
+                    gloo_console::warn!("Failed to load the download rules:", e);
+                    Vec::new()
+                });
+                // A listener that returned true must call `suggest`, or the download
+                // never starts. Without arguments, the browser's filename is kept.
+                let result = match apply(&rules, item.url(), item.basename()) {
+                    Some(filename) => suggest.call1(
+                        &JsValue::NULL,
+                        &browser::object(&[
+                            ("filename", filename.into()),
+                            ("conflictAction", "uniquify".into()),
+                        ]),
+                    ),
+                    None => suggest.call0(&JsValue::NULL),
+                };
+                if let Err(e) = result {
+                    gloo_console::warn!("Failed to suggest a download filename:", e);
+                }
+            });
+            // `suggest` is called asynchronously.
+            JsValue::TRUE
+        });
+    browser::add_listener(
+        &browser::downloads().on_determining_filename(),
+        listener.as_ref().unchecked_ref(),
+    );
+    listener.forget();
+}
+
+/// Cancel a download that matches a rule, and start it again under the rule's target.
+async fn reissue(item: Item) -> Result<(), WextError> {
+    // Downloads started here (including reissued ones) are already where they belong.
+    if item.by_extension_id.is_some() && item.by_extension_id == browser::runtime().id() {
+        return Ok(());
+    }
+    let url = item.url().to_string();
+    if !url.starts_with("http://") && !url.starts_with("https://") {
+        return Ok(());
+    }
+    let Some(filename) = apply(&rules().await?, &url, item.basename()) else {
+        return Ok(());
+    };
+    let downloads = browser::downloads();
+    downloads.cancel(item.id).await?;
+    downloads
+        .erase(browser::object(&[("id", item.id.into())]))
+        .await?;
+    downloads
+        .download(browser::object(&[
+            ("url", url.into()),
+            ("filename", filename.into()),
+            ("conflictAction", "uniquify".into()),
+        ]))
+        .await?;
+    Ok(())
+}
+
+/// Split `filename` into its name and extension, if it has one.
+fn split_extension(filename: &str) -> (&str, Option<&str>) {
+    match filename.rsplit_once('.') {
+        Some((name, extension)) if !name.is_empty() => (name, Some(extension)),
+        _ => (filename, None),
+    }
+}
+
+/// The host of `url`, or `unknown` if it has none.
+fn host(url: &str) -> String {
+    let authority = url
+        .split_once("://")
+        .map(|(_, rest)| rest.split(['/', '?', '#']).next().unwrap_or_default())
+        .unwrap_or_default();
+    let host = authority.rsplit('@').next().unwrap_or_default();
+    let host = host.split(':').next().unwrap_or_default();
+    if host.is_empty() {
+        "unknown".to_string()
+    } else {
+        host.to_ascii_lowercase()
+    }
+}
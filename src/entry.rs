@@ -0,0 +1,164 @@
+//! Entry point plumbing shared by every context.
+//!
+//! Each context's entry function is marked with [`wext_entry`], which calls
+//! [`start`] before the function body runs:
+//!
+//! ```ignore
+//! #[wext_entry(popup)]
+//! pub async fn popup_page() {
+//!     mount_to_body(|| view! { .. })
+//! }
+//! ```
+//!
+//! MV3 only delivers the events that wake the background to listeners registered
+//! synchronously at startup. In debug builds, the background audits this: a listener
+//! registered (through [`crate::browser::listen`] or [`crate::browser::add_listener`])
+//! once the entry function has first suspended at an `await` logs a warning with its
+//! location.
+//! Registering everything inside [`register_listeners!`](crate::register_listeners)
+//! enforces it, as the block can't `await`, and any listener registered after it
+//! warns too:
+//!
+//! ```ignore
+//! #[wext_entry(background)]
+//! pub async fn background_script() {
+//!     register_listeners! {
+//!         jobs::install();
+//!         messaging::install();
+//!     }
+//!     // Awaiting is fine from here on.
+//! }
+//! ```
+
+use std::{cell::Cell, panic::Location, sync::Once};
+
+use gloo_console::warn;
+use js_sys::Promise;
+use wasm_bindgen::prelude::*;
+pub use wext_macros::wext_entry;
+
+use crate::{
+    flags::{self, Flag},
+    installation::{self, Installation},
+};
+
+/// The kind of context the wasm is running in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Context {
+    Background,
+    Popup,
+    Options,
+    Content,
+    /// Any other extension page, e.g. the debug page.
+    Page,
+    /// An extension page laid out for printing or saving as PDF. See [`crate::print`].
+    Report,
+    /// A page replacing the browser's new tab page, through `chrome_url_overrides`.
+    NewTab,
+    /// A page replacing the browser's history page. Chrome only.
+    History,
+    /// A page replacing the browser's bookmarks manager. Chrome only.
+    Bookmarks,
+}
+
+/// Where the current instance is running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Environment {
+    pub context: Context,
+    /// The name of the entry function that started this instance.
+    pub entry: &'static str,
+}
+
+impl Environment {
+    /// Whether a feature flag is enabled. See [`crate::flags`].
+    pub fn is_enabled(&self, flag: Flag) -> bool {
+        flags::is_enabled(flag)
+    }
+
+    /// The install's ID and history, if the background has recorded them. See
+    /// [`crate::installation`].
+    pub fn installation(&self) -> Option<Installation> {
+        installation::current()
+    }
+}
+
+/// Where the background is in registering its listeners, for the audit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Listeners {
+    /// The runtime is still starting up.
+    Starting,
+    /// The entry function is running its first, synchronous, part.
+    Registering,
+    /// Too late to register listeners.
+    Done,
+}
+
+thread_local! {
+    static ENVIRONMENT: Cell<Option<Environment>> = const { Cell::new(None) };
+    static LISTENERS: Cell<Listeners> = const { Cell::new(Listeners::Starting) };
+}
+
+/// Register the background's listeners in a block that can't `await`. Listeners
+/// registered after it warn in debug builds. See [`crate::entry`].
+#[macro_export]
+macro_rules! register_listeners {
+    ($($body:tt)*) => {{
+        // Not `async`, so an `.await` in here doesn't compile.
+        let register = || {
+            $($body)*
+        };
+        register();
+        $crate::entry::listeners_registered();
+    }};
+}
+
+/// Set up the runtime for an entry point, and load the feature flag overrides and the
+/// installation record. Called by [`wext_entry`].
+pub async fn start(context: Context, entry: &'static str) {
+    static PANIC_HOOK: Once = Once::new();
+    PANIC_HOOK.call_once(|| {
+        std::panic::set_hook(Box::new(|info| {
+            gloo_console::error!(info.to_string());
+            crate::health::record_error("panic", info);
+        }));
+    });
+    ENVIRONMENT.set(Some(Environment { context, entry }));
+    gloo_console::debug!(format!("Starting {entry} ({context:?})"));
+    futures::join!(flags::load(), installation::load());
+    if context == Context::Background && cfg!(debug_assertions) {
+        LISTENERS.set(Listeners::Registering);
+        // Promise callbacks queued now run as soon as the entry function first
+        // suspends, before whatever it's waiting for resumes it.
+        let done = Closure::<dyn FnMut(JsValue)>::new(|_| listeners_registered());
+        let _ = Promise::resolve(&JsValue::UNDEFINED).then(&done);
+        done.forget();
+    }
+}
+
+/// End the background's listener registration. Called by
+/// [`register_listeners!`](crate::register_listeners).
+#[doc(hidden)]
+pub fn listeners_registered() {
+    LISTENERS.set(Listeners::Done);
+}
+
+/// Warn about a listener registered after the background's startup.
+pub(crate) fn audit_listener(location: &Location) {
+    if !cfg!(debug_assertions) || LISTENERS.get() != Listeners::Done {
+        return;
+    }
+    warn!(format!(
+        "A listener was registered at {location} after the background's startup. MV3 doesn't deliver the events that wake the worker to it; register it synchronously, in register_listeners!."
+    ));
+}
+
+/// The current environment.
+///
+/// # Panics
+///
+/// Panics if called before an entry point has started.
+pub fn environment() -> Environment {
+    ENVIRONMENT
+        .get()
+        .expect("No entry point has started; mark it with #[wext_entry]")
+}
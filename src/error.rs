@@ -0,0 +1,193 @@
+//! The runtime's error type.
+//!
+//! Runtime modules return [`WextError`], so callers can tell a missing permission from
+//! a full storage quota or an unreachable background instead of inspecting a
+//! `JsValue`. Lower-level modules keep their own error types
+//! ([`StorageError`], [`MessageError`]), which convert into it with `?`:
+//!
+//! ```ignore
+//! async fn open_settings() -> Result<(), WextError> {
+//!     pages::open(PageId::Options).await?;
+//!     let name: Option<String> = storage::get("options.displayName").await?;
+//!     ..
+//! }
+//! ```
+//!
+//! Exceptions thrown by browser APIs are kept as [`JsError`], with their name and
+//! stack, except permission failures, which become [`WextError::PermissionDenied`].
+
+use std::fmt;
+
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+
+use crate::{browser, messaging::MessageError, storage::StorageError};
+
+/// Why a runtime call failed.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WextError {
+    /// A browser API threw or rejected.
+    Js(JsError),
+    Storage(StorageError),
+    Message(MessageError),
+    /// The extension lacks a permission (or host permission) the call needs.
+    PermissionDenied {
+        /// The permission, if the browser named it.
+        permission: Option<String>,
+        message: String,
+    },
+    /// Anything else, described by the message.
+    Other(String),
+}
+
+/// A JavaScript exception.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsError {
+    /// The error's `name`, e.g. `TypeError`, or empty if it had none.
+    pub name: String,
+    pub message: String,
+    /// The JavaScript stack where it was thrown, if there was one.
+    pub stack: Option<String>,
+}
+
+impl JsError {
+    fn from_js(error: &JsValue) -> Self {
+        let get = |key: &str| {
+            Reflect::get(error, &key.into())
+                .ok()
+                .and_then(|value| value.as_string())
+        };
+        JsError {
+            name: get("name").unwrap_or_default(),
+            message: browser::error_message(error),
+            stack: get("stack"),
+        }
+    }
+}
+
+impl fmt::Display for JsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.name.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.name, self.message)
+        }
+    }
+}
+
+impl WextError {
+    /// Whether the call failed for lack of a permission.
+    pub fn is_permission_denied(&self) -> bool {
+        matches!(self, WextError::PermissionDenied { .. })
+    }
+
+    /// The JavaScript stack of the exception behind the error, if any.
+    pub fn stack(&self) -> Option<&str> {
+        match self {
+            WextError::Js(error) => error.stack.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for WextError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WextError::Js(error) => write!(f, "{error}"),
+            WextError::Storage(error) => write!(f, "{error}"),
+            WextError::Message(error) => write!(f, "{error}"),
+            WextError::PermissionDenied { message, .. } => {
+                write!(f, "permission denied: {message}")
+            }
+            WextError::Other(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for WextError {}
+
+impl From<JsValue> for WextError {
+    fn from(error: JsValue) -> Self {
+        let error = JsError::from_js(&error);
+        match permission_failure(&error.message) {
+            Some(permission) => WextError::PermissionDenied {
+                permission,
+                message: error.message,
+            },
+            None => WextError::Js(error),
+        }
+    }
+}
+
+impl From<StorageError> for WextError {
+    fn from(error: StorageError) -> Self {
+        WextError::Storage(error)
+    }
+}
+
+impl From<MessageError> for WextError {
+    fn from(error: MessageError) -> Self {
+        WextError::Message(error)
+    }
+}
+
+impl From<serde_wasm_bindgen::Error> for WextError {
+    fn from(error: serde_wasm_bindgen::Error) -> Self {
+        WextError::Other(error.to_string())
+    }
+}
+
+impl From<String> for WextError {
+    fn from(message: String) -> Self {
+        WextError::Other(message)
+    }
+}
+
+impl From<&str> for WextError {
+    fn from(message: &str) -> Self {
+        WextError::Other(message.to_string())
+    }
+}
+
+impl From<WextError> for JsValue {
+    fn from(error: WextError) -> Self {
+        let WextError::Js(error) = error else {
+            return js_sys::Error::new(&error.to_string()).into();
+        };
+        // Rebuild the original exception, so it reaches JavaScript with its stack.
+        let js_error = js_sys::Error::new(&error.message);
+        if !error.name.is_empty() {
+            js_error.set_name(&error.name);
+        }
+        if let Some(stack) = error.stack {
+            let _ = Reflect::set(&js_error, &"stack".into(), &stack.into());
+        }
+        js_error.into()
+    }
+}
+
+/// Whether `message` is a browser's "missing permission" error, and if so, the
+/// permission it names. E.g. Chrome's "'tabs' permission is required" or "Missing host
+/// permission for the tab", and Firefox's "Missing host permission for the tab".
+fn permission_failure(message: &str) -> Option<Option<String>> {
+    let lower = message.to_lowercase();
+    let denied = [
+        "permission is required",
+        "permission required",
+        "missing host permission",
+        "do not have permission",
+        "must request permission",
+        "permission denied",
+    ]
+    .iter()
+    .any(|pattern| lower.contains(pattern));
+    if !denied {
+        return None;
+    }
+    let quoted = message
+        .split(['\'', '"'])
+        .nth(1)
+        .filter(|name| !name.is_empty() && !name.contains(' '))
+        .map(str::to_string);
+    Some(quoted)
+}
@@ -0,0 +1,207 @@
+//! A/B experiments: assigning each install to a variant, deterministically.
+//!
+//! Experiments are declared as constants:
+//!
+//! ```ignore
+//! pub const POPUP_LAYOUT: Experiment = Experiment {
+//!     name: "popup_layout",
+//!     variants: &[("control", 1), ("compact", 1)],
+//! };
+//! ```
+//!
+//! The variant is picked by a stable hash of the install ID and the experiment's name,
+//! weighted by the variants' weights, so every context of an install agrees on it
+//! without coordinating. Assignments are persisted in `storage.local` the first time
+//! they're made, so changing the weights later only moves installs that weren't
+//! assigned yet.
+//!
+//! [`variant`] looks the variant up from any context; [`expose`] also records that the
+//! user saw it. Each experiment's exposure is recorded once, and passed to the
+//! handlers registered in the background with [`on_exposure`], which is where a
+//! telemetry layer reports it. Without one, nothing leaves the browser.
+//!
+//! The install ID comes from [`installation`].
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, BTreeSet},
+    rc::Rc,
+};
+
+use gloo_console::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::WextError,
+    installation, messaging, storage,
+};
+
+/// Message name used by other contexts to report an exposure to the background.
+const EXPOSURE_MESSAGE: &str = "wext.experiments.exposure";
+
+/// Storage key for the assigned variants, by experiment.
+pub const ASSIGNMENTS_KEY: &str = "wext.experiments.assignments";
+/// Storage key for the reported exposures: the variant, by experiment.
+const EXPOSURES_KEY: &str = "wext.experiments.exposures";
+
+type Handler = Rc<dyn Fn(&Exposure)>;
+
+/// An experiment and its variants.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Experiment {
+    /// A name that's unique among experiments, and never reused.
+    pub name: &'static str,
+    /// Each variant's name and relative weight. The first is the control, used
+    /// whenever a variant can't be assigned.
+    pub variants: &'static [(&'static str, u32)],
+}
+
+impl Experiment {
+    /// The variant an install with `install_id` is assigned to.
+    pub fn assign(&self, install_id: &str) -> &'static str {
+        let total: u64 = self.variants.iter().map(|(_, weight)| *weight as u64).sum();
+        if total == 0 {
+            return self.control();
+        }
+        let mut bucket = fnv1a(format!("{install_id}:{}", self.name).as_bytes()) % total;
+        for (variant, weight) in self.variants {
+            if bucket < *weight as u64 {
+                return variant;
+            }
+            bucket -= *weight as u64;
+        }
+        self.control()
+    }
+
+    /// The control variant.
+    pub fn control(&self) -> &'static str {
+        self.variants
+            .first()
+            .map(|(variant, _)| *variant)
+            .unwrap_or_default()
+    }
+
+    fn variant_named(&self, name: &str) -> Option<&'static str> {
+        self.variants
+            .iter()
+            .map(|(variant, _)| *variant)
+            .find(|variant| *variant == name)
+    }
+}
+
+/// The user seeing an experiment's variant, as passed to [`on_exposure`] handlers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Exposure {
+    pub experiment: String,
+    pub variant: String,
+    /// When it was first seen, in milliseconds since the epoch.
+    pub at: f64,
+}
+
+thread_local! {
+    /// Whether this is the background, receiving exposures.
+    static INSTALLED: Cell<bool> = const { Cell::new(false) };
+    static HANDLERS: RefCell<Vec<Handler>> = const { RefCell::new(Vec::new()) };
+    /// Experiments whose exposure this instance already reported.
+    static REPORTED: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Receive the exposures of other contexts. Call this in the background, before
+/// [`messaging::install`].
+pub fn install() {
+    INSTALLED.set(true);
+    messaging::handle(EXPOSURE_MESSAGE, |exposure: Exposure, _| async {
+        report(exposure).await.map_err(|e| e.to_string())
+    });
+}
+
+/// Call `handler` with every new exposure, from any context. Register handlers in the
+/// background.
+pub fn on_exposure(handler: impl Fn(&Exposure) + 'static) {
+    HANDLERS.with_borrow_mut(|handlers| handlers.push(Rc::new(handler)));
+}
+
+/// The variant of `experiment` this install is assigned to, assigning it if it isn't
+/// yet. Falls back to the control variant if that fails.
+pub async fn variant(experiment: &Experiment) -> &'static str {
+    match assigned(experiment).await {
+        Ok(variant) => variant,
+        Err(e) => {
+            warn!(format!(
+                "Failed to assign experiment {}: {e}",
+                experiment.name
+            ));
+            experiment.control()
+        }
+    }
+}
+
+/// Like [`variant`], and record that the user is seeing it now.
+pub async fn expose(experiment: &Experiment) -> &'static str {
+    let variant = variant(experiment).await;
+    let exposure = Exposure {
+        experiment: experiment.name.to_string(),
+        variant: variant.to_string(),
+        at: SystemClock.now(),
+    };
+    let result = if INSTALLED.get() {
+        report(exposure).await
+    } else {
+        messaging::send(EXPOSURE_MESSAGE, &exposure)
+            .await
+            .map_err(WextError::from)
+    };
+    if let Err(e) = result {
+        warn!(format!(
+            "Failed to report exposure to {}: {e}",
+            experiment.name
+        ));
+    }
+    variant
+}
+
+async fn assigned(experiment: &Experiment) -> Result<&'static str, WextError> {
+    let mut assignments: BTreeMap<String, String> =
+        storage::get(ASSIGNMENTS_KEY).await?.unwrap_or_default();
+    // A variant that was removed since is assigned again.
+    if let Some(variant) = assignments
+        .get(experiment.name)
+        .and_then(|name| experiment.variant_named(name))
+    {
+        return Ok(variant);
+    }
+    let variant = experiment.assign(&installation::id().await?);
+    // Contexts assigning at the same time may overwrite each other's assignments, but
+    // they're deterministic: the lost ones are made again the same way.
+    assignments.insert(experiment.name.to_string(), variant.to_string());
+    storage::set(ASSIGNMENTS_KEY, &assignments).await?;
+    Ok(variant)
+}
+
+/// Background only: pass a new exposure to the handlers.
+async fn report(exposure: Exposure) -> Result<(), WextError> {
+    let new = REPORTED.with_borrow_mut(|reported| reported.insert(exposure.experiment.clone()));
+    if !new {
+        return Ok(());
+    }
+    let mut exposures: BTreeMap<String, String> =
+        storage::get(EXPOSURES_KEY).await?.unwrap_or_default();
+    if exposures.get(&exposure.experiment) == Some(&exposure.variant) {
+        return Ok(());
+    }
+    exposures.insert(exposure.experiment.clone(), exposure.variant.clone());
+    storage::set(EXPOSURES_KEY, &exposures).await?;
+    let handlers = HANDLERS.with_borrow(Clone::clone);
+    for handler in handlers {
+        handler(&exposure);
+    }
+    Ok(())
+}
+
+/// 64-bit FNV-1a, which unlike `std`'s hashers is stable across builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x0100_0000_01b3)
+    })
+}
@@ -0,0 +1,87 @@
+use leptos::{prelude::*, spawn::spawn_local};
+
+use crate::{
+    entry::wext_entry,
+    i18n, print,
+    reader::{self, Article, Block},
+    toast::{self, Toasts},
+};
+
+#[wext_entry(report)]
+pub async fn export_page() {
+    let article = reader::latest().await.unwrap_or_else(|e| {
+        gloo_console::error!("Failed to load the article:", e);
+        None
+    });
+    let print_now = print::requested() && article.is_some();
+    mount_to_body(move || {
+        i18n::provide_direction();
+        let toaster = toast::provide_toasts();
+        view! {
+            <Toasts />
+            <main class="max-w-prose mx-auto p-4 print:p-0">
+                <div class="no-print flex justify-end mb-4">
+                    <button
+                        type="button"
+                        class="px-3 py-1 underline"
+                        on:click=move |_| {
+                            if let Err(e) = print::print() {
+                                toaster.error(format!("Couldn't print: {e}"));
+                            }
+                        }
+                    >
+                        "Print or save as PDF"
+                    </button>
+                </div>
+                {match article {
+                    Some(article) => view! { <ArticleReport article /> }.into_any(),
+                    None => view! { <p>"No article has been saved yet."</p> }.into_any(),
+                }}
+            </main>
+        }
+    });
+    if print_now {
+        spawn_local(async {
+            if let Err(e) = print::print_when_rendered().await {
+                gloo_console::warn!("Failed to print:", e);
+            }
+        });
+    }
+}
+
+/// The latest article saved by the reader, laid out for paper.
+#[component]
+fn ArticleReport(article: Article) -> impl IntoView {
+    view! {
+        <article>
+            <header class="mb-4">
+                <h1 class="text-2xl font-bold">{article.title}</h1>
+                {article.byline.map(|byline| view! { <p class="italic">{byline}</p> })}
+                <p class="text-sm">
+                    <a href=article.url.clone()>{article.url.clone()}</a>
+                    " · "
+                    {format!("{} words", article.word_count)}
+                </p>
+            </header>
+            {article
+                .blocks
+                .into_iter()
+                .map(|block| match block {
+                    Block::Heading(text) => {
+                        view! { <h2 class="text-lg font-bold mt-4 mb-2">{text}</h2> }.into_any()
+                    }
+                    Block::Paragraph(text) => view! { <p class="mb-2">{text}</p> }.into_any(),
+                    Block::Code(text) => {
+                        view! { <pre class="mb-2 whitespace-pre-wrap text-sm">{text}</pre> }
+                            .into_any()
+                    }
+                    Block::Quote(text) => {
+                        view! { <blockquote class="mb-2 ps-4 border-s-2">{text}</blockquote> }
+                            .into_any()
+                    }
+                    Block::ListItem(text) => view! { <p class="mb-1 ps-4">"• " {text}</p> }.into_any(),
+                })
+                .collect_view()}
+        </article>
+    }
+}
@@ -0,0 +1,68 @@
+use leptos::{prelude::*, spawn::spawn_local};
+
+use crate::{entry::wext_entry, i18n, uninstall};
+
+/// Reasons offered on the "before you go" page, as (value, label).
+const REASONS: &[(&str, &str)] = &[
+    ("not-useful", "It wasn't useful to me"),
+    ("broken", "Something didn't work"),
+    ("performance", "It slowed down my browser"),
+    ("privacy", "I'm worried about privacy"),
+    ("other", "Something else"),
+];
+
+#[wext_entry(page)]
+pub async fn farewell_page() {
+    mount_to_body(|| {
+        i18n::provide_direction();
+        view! {
+            <main class="max-w-prose mx-auto p-4">
+                <Farewell />
+            </main>
+        }
+    })
+}
+
+#[component]
+fn Farewell() -> impl IntoView {
+    let (reason, set_reason) = signal(None::<&'static str>);
+    let (error, set_error) = signal(None::<String>);
+    let on_uninstall = move |_| {
+        spawn_local(async move {
+            // Rejected if the user cancels the browser's confirmation dialog.
+            if let Err(e) = uninstall::uninstall(reason.get_untracked()).await {
+                gloo_console::warn!("Uninstall cancelled or failed:", e);
+                set_error.set(Some("The extension wasn't uninstalled.".to_string()));
+            }
+        });
+    };
+
+    view! {
+        <h1 class="text-xl font-bold mb-2">"Before you go"</h1>
+        <p class="mb-4">"Could you tell us why you're uninstalling? It's optional."</p>
+        <fieldset class="mb-4">
+            <legend class="sr-only">"Reason"</legend>
+            {REASONS
+                .iter()
+                .map(|(value, label)| {
+                    view! {
+                        <label class="flex items-center gap-2">
+                            <input
+                                type="radio"
+                                name="reason"
+                                value=*value
+                                on:change=move |_| set_reason.set(Some(value))
+                            />
+                            {*label}
+                        </label>
+                    }
+                })
+                .collect_view()}
+        </fieldset>
+        <p class="mb-4 text-sm">"Uninstalling also removes your synced settings."</p>
+        <button type="button" class="px-3 py-1 rounded bg-red-700 text-white" on:click=on_uninstall>
+            "Uninstall"
+        </button>
+        <p role="alert" class="mt-2 text-red-700">{move || error.get()}</p>
+    }
+}
@@ -0,0 +1,259 @@
+//! Layered `fetch` for the background script and extension pages.
+//!
+//! Every layer implements [`Fetch`] and wraps an inner [`Fetch`], so cross-cutting
+//! behaviour ([`Cache`], [`Retry`], [`RateLimit`], ...) composes by nesting:
+//!
+//! ```ignore
+//! let client = Cache::new(
+//!     Retry::new(RateLimit::new(Network, 1_000.0), RetryPolicy::default()),
+//!     60_000.0,
+//! );
+//! let response = client.fetch(&request).await?;
+//! ```
+//!
+//! Layers closer to [`Network`] see every individual attempt; layers further out see
+//! one logical request. Retries should generally sit inside a cache and outside a
+//! rate limiter, so cache hits skip retries entirely and every retry is throttled.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    rc::Rc,
+};
+
+use futures::future::LocalBoxFuture;
+use wasm_bindgen::prelude::*;
+use web_sys::{Request, Response};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    error::WextError,
+    retry::RetryPolicy,
+};
+
+#[wasm_bindgen]
+extern "C" {
+    /// The global `fetch`, available in both windows and service workers.
+    #[wasm_bindgen(js_name = fetch, catch)]
+    async fn global_fetch(input: &Request) -> Result<JsValue, JsValue>;
+}
+
+/// Something that can perform a request.
+pub trait Fetch {
+    fn fetch<'a>(&'a self, request: &'a Request)
+        -> LocalBoxFuture<'a, Result<Response, WextError>>;
+}
+
+/// The bottom layer: performs the request over the network.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Network;
+
+impl Fetch for Network {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a Request,
+    ) -> LocalBoxFuture<'a, Result<Response, WextError>> {
+        Box::pin(async move { Ok(global_fetch(request).await?.unchecked_into()) })
+    }
+}
+
+/// Whether an HTTP status is worth retrying: timeouts, throttling and transient
+/// server errors. Everything else (including other 5xx codes such as 501) is treated
+/// as a definitive answer.
+pub fn is_retryable_status(status: u16) -> bool {
+    matches!(status, 408 | 425 | 429 | 500 | 502 | 503 | 504)
+}
+
+/// Retries the inner layer according to a [`RetryPolicy`].
+///
+/// Network errors (rejected `fetch` promises) and responses for which `retryable`
+/// returns true are retried. A `Retry-After` header given in seconds is honoured as a
+/// lower bound for the delay. Once the policy is exhausted, the last response or error
+/// is returned as-is.
+pub struct Retry<F> {
+    inner: F,
+    policy: RetryPolicy,
+    retryable: fn(u16) -> bool,
+    clock: Rc<dyn Clock>,
+}
+
+impl<F: Fetch> Retry<F> {
+    pub fn new(inner: F, policy: RetryPolicy) -> Self {
+        Retry {
+            inner,
+            policy,
+            retryable: is_retryable_status,
+            clock: Rc::new(SystemClock),
+        }
+    }
+
+    /// Replace the default [`is_retryable_status`] classification.
+    pub fn with_classifier(mut self, retryable: fn(u16) -> bool) -> Self {
+        self.retryable = retryable;
+        self
+    }
+
+    /// Measure the deadline and wait between attempts by `clock`, rather than the
+    /// system clock.
+    pub fn with_clock(mut self, clock: Rc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+impl<F: Fetch> Fetch for Retry<F> {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a Request,
+    ) -> LocalBoxFuture<'a, Result<Response, WextError>> {
+        Box::pin(async move {
+            let started = self.clock.now();
+            let mut attempts = 0;
+            loop {
+                attempts += 1;
+                // Requests with bodies can only be sent once, so each attempt gets a copy.
+                let attempt = request.clone()?;
+                let result = self.inner.fetch(&attempt).await;
+                let min_delay = match &result {
+                    Ok(response) if !(self.retryable)(response.status()) => return result,
+                    Ok(response) => retry_after_ms(response),
+                    Err(_) => 0.0,
+                };
+                let elapsed = self.clock.now() - started;
+                let Some(delay) = self.policy.next_delay_ms(attempts, elapsed, min_delay) else {
+                    return result;
+                };
+                self.clock.sleep(delay).await;
+            }
+        })
+    }
+}
+
+/// Spaces requests through the inner layer at least `min_interval_ms` apart, e.g. to
+/// stay within an API's rate limit. Requests made sooner wait their turn, in order.
+pub struct RateLimit<F> {
+    inner: F,
+    min_interval_ms: f64,
+    /// When the next request may start, in milliseconds since the epoch.
+    next_at: Cell<f64>,
+    clock: Rc<dyn Clock>,
+}
+
+impl<F: Fetch> RateLimit<F> {
+    pub fn new(inner: F, min_interval_ms: f64) -> Self {
+        RateLimit {
+            inner,
+            min_interval_ms,
+            next_at: Cell::new(0.0),
+            clock: Rc::new(SystemClock),
+        }
+    }
+
+    /// Space requests by `clock`, rather than the system clock.
+    pub fn with_clock(mut self, clock: Rc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+impl<F: Fetch> Fetch for RateLimit<F> {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a Request,
+    ) -> LocalBoxFuture<'a, Result<Response, WextError>> {
+        Box::pin(async move {
+            // Claim a slot before waiting, so concurrent requests queue up behind it.
+            let now = self.clock.now();
+            let at = self.next_at.get().max(now);
+            self.next_at.set(at + self.min_interval_ms);
+            if at > now {
+                self.clock.sleep(at - now).await;
+            }
+            self.inner.fetch(request).await
+        })
+    }
+}
+
+/// Serves repeated `GET` requests from memory for `ttl_ms` after a successful response,
+/// by URL. Other methods and unsuccessful responses pass through uncached. Beyond
+/// `max_entries` (100 by default), the oldest response is dropped.
+pub struct Cache<F> {
+    inner: F,
+    ttl_ms: f64,
+    max_entries: usize,
+    /// URL, expiry and response of each cached request, oldest first.
+    entries: RefCell<VecDeque<(String, f64, Response)>>,
+    clock: Rc<dyn Clock>,
+}
+
+impl<F: Fetch> Cache<F> {
+    pub fn new(inner: F, ttl_ms: f64) -> Self {
+        Cache {
+            inner,
+            ttl_ms,
+            max_entries: 100,
+            entries: RefCell::default(),
+            clock: Rc::new(SystemClock),
+        }
+    }
+
+    /// Keep at most `max_entries` responses.
+    pub fn with_max_entries(mut self, max_entries: usize) -> Self {
+        self.max_entries = max_entries;
+        self
+    }
+
+    /// Expire responses by `clock`, rather than the system clock.
+    pub fn with_clock(mut self, clock: Rc<dyn Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+}
+
+impl<F: Fetch> Fetch for Cache<F> {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a Request,
+    ) -> LocalBoxFuture<'a, Result<Response, WextError>> {
+        Box::pin(async move {
+            if request.method() != "GET" {
+                return self.inner.fetch(request).await;
+            }
+            let url = request.url();
+            let now = self.clock.now();
+            let hit = {
+                let mut entries = self.entries.borrow_mut();
+                entries.retain(|(_, expires, _)| *expires > now);
+                entries
+                    .iter()
+                    .find(|(cached, _, _)| *cached == url)
+                    .map(|(_, _, response)| response.clone())
+            };
+            // A body can only be read once, so every caller gets a copy.
+            if let Some(response) = hit {
+                return Ok(response?);
+            }
+            let response = self.inner.fetch(request).await?;
+            if response.ok() {
+                let mut entries = self.entries.borrow_mut();
+                entries.retain(|(cached, _, _)| *cached != url);
+                entries.push_back((url, self.clock.now() + self.ttl_ms, response.clone()?));
+                if entries.len() > self.max_entries {
+                    entries.pop_front();
+                }
+            }
+            Ok(response)
+        })
+    }
+}
+
+/// Parse a `Retry-After` header given in seconds. HTTP dates are ignored.
+fn retry_after_ms(response: &Response) -> f64 {
+    response
+        .headers()
+        .get("retry-after")
+        .ok()
+        .flatten()
+        .and_then(|value| value.trim().parse::<f64>().ok())
+        .map_or(0.0, |seconds| seconds * 1_000.0)
+}
@@ -0,0 +1,118 @@
+//! Feature flags, declared in `wextrunk.toml`:
+//!
+//! ```toml
+//! [flags.reader_export]
+//! description = "Export articles from the reader page"
+//! default = true
+//! soft = true
+//!
+//! [profiles.release.flags]
+//! reader_export = false
+//! ```
+//!
+//! The build script bakes each flag's value for the selected profile into a constant
+//! named after it, e.g. `flags::READER_EXPORT`, listed in [`FLAGS`]. Hard flags (the
+//! default) can't change after the build, so code behind a disabled one, as in
+//! `if flags::is_enabled(flags::READER_EXPORT) { .. }`, is compiled out.
+//!
+//! Soft flags can be overridden at runtime, e.g. from the flags panel on the options
+//! page of debug builds. Overrides are kept in `storage.local` under
+//! [`OVERRIDES_KEY`], loaded before every entry point runs and followed as they
+//! change, so every context sees the same values. Query them with [`is_enabled`], or
+//! `entry::environment().is_enabled(..)`.
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+use crate::{browser, error::WextError, storage};
+
+/// Storage key of the runtime overrides of soft flags, by flag name.
+pub const OVERRIDES_KEY: &str = "wext.flags";
+
+/// A feature flag declared in `wextrunk.toml`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flag {
+    /// The flag's name in `wextrunk.toml`, e.g. `reader_export`.
+    pub name: &'static str,
+    pub description: &'static str,
+    /// Whether it can be overridden at runtime.
+    pub soft: bool,
+    /// Its value for this build.
+    pub baked: bool,
+}
+
+macro_rules! flags {
+    ($($id:ident: $name:literal, $description:literal, soft = $soft:literal, enabled = $enabled:literal;)*) => {
+        $(
+            #[doc = $description]
+            pub const $id: Flag = Flag {
+                name: $name,
+                description: $description,
+                soft: $soft,
+                baked: $enabled,
+            };
+        )*
+
+        /// Every flag declared in `wextrunk.toml`.
+        pub const FLAGS: &[Flag] = &[$($id,)*];
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/flags.rs"));
+
+thread_local! {
+    static OVERRIDES: RefCell<BTreeMap<String, bool>> = RefCell::default();
+}
+
+/// Whether `flag` is enabled in this context.
+#[inline(always)]
+pub fn is_enabled(flag: Flag) -> bool {
+    if !flag.soft {
+        return flag.baked;
+    }
+    OVERRIDES
+        .with_borrow(|overrides| overrides.get(flag.name).copied())
+        .unwrap_or(flag.baked)
+}
+
+/// The runtime override of a soft flag, if it has one.
+pub fn override_of(flag: Flag) -> Option<bool> {
+    OVERRIDES.with_borrow(|overrides| overrides.get(flag.name).copied())
+}
+
+/// Override a soft flag in every context, or with `None`, go back to its baked value.
+///
+/// # Panics
+///
+/// Panics if `flag` is a hard flag.
+pub async fn set_override(flag: Flag, value: Option<bool>) -> Result<(), WextError> {
+    assert!(
+        flag.soft,
+        "{} is a hard flag and can't be changed at runtime",
+        flag.name
+    );
+    let mut overrides: BTreeMap<String, bool> =
+        storage::get(OVERRIDES_KEY).await?.unwrap_or_default();
+    match value {
+        Some(value) => overrides.insert(flag.name.to_string(), value),
+        None => overrides.remove(flag.name),
+    };
+    storage::set(OVERRIDES_KEY, &overrides).await
+}
+
+/// Load the overrides and follow changes to them. Called by [`crate::entry::start`].
+pub(crate) async fn load() {
+    // Offscreen documents have no storage; their flags keep the baked values.
+    if browser::storage_local().is_undefined() {
+        return;
+    }
+    match storage::get::<BTreeMap<String, bool>>(OVERRIDES_KEY).await {
+        Ok(overrides) => OVERRIDES.set(overrides.unwrap_or_default()),
+        Err(e) => gloo_console::warn!("Failed to load the feature flag overrides:", e),
+    }
+    storage::on_change(
+        OVERRIDES_KEY,
+        |overrides: Option<BTreeMap<String, bool>>| {
+            OVERRIDES.set(overrides.unwrap_or_default());
+        },
+    );
+}
@@ -0,0 +1,259 @@
+//! Locale-aware formatting of numbers, byte sizes, dates and relative times.
+//!
+//! Everything is formatted with `Intl` in the browser UI locale (see
+//! [`i18n::ui_language`]), so views don't need to touch `js_sys::Intl` themselves:
+//! `format::number(1234.5)` is "1,234.5" in English and "1.234,5" in German, and
+//! `format::relative_time(at, now)` gives "3 minutes ago" or "yesterday". The
+//! [`FormattedNumber`], [`Bytes`] and [`FormattedDate`] components do the same
+//! reactively in `view!`, and [`RelativeTime`] keeps itself up to date as time passes.
+//! `Intl` formatters are slow to create, so each one is created once per context and
+//! reused.
+
+use std::{cell::RefCell, collections::HashMap};
+
+use gloo_timers::callback::Interval;
+use js_sys::{
+    Array, Date, Function,
+    Intl::{DateTimeFormat, NumberFormat, RelativeTimeFormat},
+    Object,
+};
+use leptos::prelude::*;
+use wasm_bindgen::{JsCast, JsValue};
+
+use crate::{
+    browser,
+    clock::{Clock, SystemClock},
+    i18n,
+};
+
+/// How much of a date to show.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum DateStyle {
+    /// The date, e.g. "Mar 4, 2025".
+    Date,
+    /// The time, e.g. "2:05 PM".
+    Time,
+    /// Both, e.g. "Mar 4, 2025, 2:05 PM".
+    #[default]
+    DateTime,
+}
+
+impl DateStyle {
+    fn options(self) -> JsValue {
+        let (date, time) = match self {
+            DateStyle::Date => (Some("medium"), None),
+            DateStyle::Time => (None, Some("short")),
+            DateStyle::DateTime => (Some("medium"), Some("short")),
+        };
+        let mut options = Vec::new();
+        if let Some(date) = date {
+            options.push(("dateStyle", JsValue::from_str(date)));
+        }
+        if let Some(time) = time {
+            options.push(("timeStyle", JsValue::from_str(time)));
+        }
+        browser::object(&options)
+    }
+}
+
+/// The kinds of formatters, each created once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum Formatter {
+    Number,
+    /// A number with a unit, e.g. `kilobyte`.
+    Unit(&'static str),
+    Date(DateStyle),
+    Relative,
+}
+
+thread_local! {
+    static FORMATTERS: RefCell<HashMap<Formatter, JsValue>> = RefCell::new(HashMap::new());
+    static TICKER: RefCell<Option<Ticker>> = const { RefCell::new(None) };
+}
+
+/// How often [`RelativeTime`]s update.
+const TICK_MS: u32 = 10_000;
+
+/// The current time, shared by every mounted [`RelativeTime`], and updated while any
+/// is.
+struct Ticker {
+    now: ArcRwSignal<f64>,
+    users: usize,
+    interval: Option<Interval>,
+}
+
+/// Units for [`bytes`], each 1024 of the previous one.
+const BYTE_UNITS: &[&str] = &["byte", "kilobyte", "megabyte", "gigabyte", "terabyte"];
+
+/// Format a number, e.g. "1,234.5".
+pub fn number(value: f64) -> String {
+    call_format(Formatter::Number, value)
+}
+
+/// Format a byte size in the largest unit it has at least one of, with at most one
+/// decimal, e.g. "1.5 MB". Units are powers of 1024, as file managers and the
+/// browser's downloads page show them.
+pub fn bytes(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < BYTE_UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    call_format(Formatter::Unit(BYTE_UNITS[unit]), value)
+}
+
+/// Format a timestamp in milliseconds since the epoch, e.g. from
+/// [`Clock::now`](crate::clock::Clock::now).
+pub fn date(at: f64, style: DateStyle) -> String {
+    call_format(Formatter::Date(style), at)
+}
+
+/// Format how long before or after `now` a timestamp is, in the largest unit that
+/// fits, e.g. "in 5 minutes", "2 hours ago" or "yesterday". Both are in milliseconds
+/// since the epoch.
+pub fn relative_time(at: f64, now: f64) -> String {
+    const UNITS: &[(&str, f64)] = &[
+        ("second", 1.0),
+        ("minute", 60.0),
+        ("hour", 60.0 * 60.0),
+        ("day", 24.0 * 60.0 * 60.0),
+        ("week", 7.0 * 24.0 * 60.0 * 60.0),
+        ("month", 30.0 * 24.0 * 60.0 * 60.0),
+        ("year", 365.0 * 24.0 * 60.0 * 60.0),
+    ];
+    let seconds = (at - now) / 1000.0;
+    let (unit, size) = UNITS
+        .iter()
+        .rev()
+        .find(|(_, size)| seconds.abs() >= *size)
+        .unwrap_or(&UNITS[0]);
+    with_formatter(Formatter::Relative, |formatter| {
+        formatter
+            .unchecked_ref::<RelativeTimeFormat>()
+            .format((seconds / size).round(), unit)
+            .into()
+    })
+}
+
+/// A number formatted with [`number`].
+#[component]
+pub fn FormattedNumber(#[prop(into)] value: Signal<f64>) -> impl IntoView {
+    move || number(value.get())
+}
+
+/// A byte size formatted with [`bytes`].
+#[component]
+pub fn Bytes(#[prop(into)] value: Signal<u64>) -> impl IntoView {
+    move || bytes(value.get())
+}
+
+/// A `<time>` element with a timestamp formatted with [`date`].
+#[component]
+pub fn FormattedDate(
+    #[prop(into)] value: Signal<f64>,
+    #[prop(optional)] style: DateStyle,
+) -> impl IntoView {
+    let datetime = move || String::from(Date::new(&JsValue::from_f64(value.get())).to_iso_string());
+    view! { <time datetime=datetime>{move || date(value.get(), style)}</time> }
+}
+
+/// A `<time>` element with how long ago (or until) a timestamp is, formatted with
+/// [`relative_time`], e.g. "3 minutes ago". It updates every ten seconds, on a timer
+/// shared by every instance, and shows the full date and time on hover.
+#[component]
+pub fn RelativeTime(#[prop(into)] value: Signal<f64>) -> impl IntoView {
+    let now = subscribe();
+    on_cleanup(unsubscribe);
+    let datetime = move || String::from(Date::new(&JsValue::from_f64(value.get())).to_iso_string());
+    let title = move || date(value.get(), DateStyle::DateTime);
+    view! {
+        <time datetime=datetime title=title>
+            {move || relative_time(value.get(), now.get())}
+        </time>
+    }
+}
+
+/// The shared current time, starting the timer for the first user.
+fn subscribe() -> ArcRwSignal<f64> {
+    TICKER.with_borrow_mut(|ticker| {
+        let ticker = ticker.get_or_insert_with(|| Ticker {
+            now: ArcRwSignal::new(SystemClock.now()),
+            users: 0,
+            interval: None,
+        });
+        ticker.users += 1;
+        if ticker.interval.is_none() {
+            ticker.now.set(SystemClock.now());
+            let now = ticker.now.clone();
+            ticker.interval = Some(Interval::new(TICK_MS, move || now.set(SystemClock.now())));
+        }
+        ticker.now.clone()
+    })
+}
+
+/// Stop the timer once the last user is gone.
+fn unsubscribe() {
+    TICKER.with_borrow_mut(|ticker| {
+        if let Some(ticker) = ticker {
+            ticker.users = ticker.users.saturating_sub(1);
+            if ticker.users == 0 {
+                ticker.interval = None;
+            }
+        }
+    });
+}
+
+/// Call a formatter's bound `format` function with `value`.
+fn call_format(formatter: Formatter, value: f64) -> String {
+    with_formatter(formatter, |format| {
+        format
+            .unchecked_ref::<Function>()
+            .call1(&JsValue::NULL, &JsValue::from_f64(value))
+            .ok()
+            .and_then(|formatted| formatted.as_string())
+            .unwrap_or_default()
+    })
+}
+
+fn with_formatter(formatter: Formatter, f: impl FnOnce(&JsValue) -> String) -> String {
+    FORMATTERS.with_borrow_mut(|formatters| {
+        f(formatters
+            .entry(formatter)
+            .or_insert_with(|| create(formatter)))
+    })
+}
+
+/// Create a formatter: the bound `format` function of a `NumberFormat` or
+/// `DateTimeFormat`, or a `RelativeTimeFormat`.
+fn create(formatter: Formatter) -> JsValue {
+    let locales = Array::new();
+    let language = i18n::ui_language();
+    // Without `chrome.i18n`, e.g. in tests, `Intl` falls back to the default locale.
+    if !language.is_empty() {
+        locales.push(&JsValue::from_str(&language));
+    }
+    let options = |options: JsValue| options.unchecked_into::<Object>();
+    match formatter {
+        Formatter::Number => NumberFormat::new(&locales, &Object::new()).format().into(),
+        Formatter::Unit(unit) => NumberFormat::new(
+            &locales,
+            &options(browser::object(&[
+                ("style", "unit".into()),
+                ("unit", unit.into()),
+                ("unitDisplay", "short".into()),
+                ("maximumFractionDigits", 1.into()),
+            ])),
+        )
+        .format()
+        .into(),
+        Formatter::Date(style) => DateTimeFormat::new(&locales, &options(style.options()))
+            .format()
+            .into(),
+        Formatter::Relative => RelativeTimeFormat::new(
+            &locales,
+            &options(browser::object(&[("numeric", "auto".into())])),
+        )
+        .into(),
+    }
+}
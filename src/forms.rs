@@ -0,0 +1,456 @@
+//! Form validation and dirty-state tracking for options pages.
+//!
+//! A [`Field`] holds a value, the last saved value, and its validators. Sync
+//! validators run on every change, async ones (e.g. checking an API key through the
+//! background with [`rpc`]) when the form is saved. A [`Form`] groups fields for the
+//! [`ErrorSummary`] and the [`SaveBar`], which appears while anything is unsaved, and
+//! reports each save with a toast if the page provides [`toast`]s.
+//!
+//! ```ignore
+//! let api_key = Field::new("API key", String::new())
+//!     .validate(forms::required)
+//!     .validate_async(forms::rpc("options.checkApiKey"));
+//! let form = Form::new().with(&api_key);
+//! view! {
+//!     <ErrorSummary form=form.clone() />
+//!     <TextField field=api_key />
+//!     <SaveBar form on_save=|| async { storage::set("apiKey", &key).await.map_err(..) } />
+//! }
+//! ```
+
+use std::{future::Future, sync::Arc};
+
+use futures::future::LocalBoxFuture;
+use leptos::{prelude::*, spawn::spawn_local};
+use serde::Serialize;
+
+use crate::{hotkeys, messaging, toast};
+
+type Validator<T> = Arc<dyn Fn(&T) -> Result<(), String> + Send + Sync>;
+type AsyncValidator<T> =
+    Arc<dyn Fn(T) -> LocalBoxFuture<'static, Result<(), String>> + Send + Sync>;
+
+/// A single form field.
+pub struct Field<T: Send + Sync + 'static> {
+    pub label: &'static str,
+    /// The current (edited) value.
+    pub value: RwSignal<T>,
+    saved: RwSignal<T>,
+    pub error: RwSignal<Option<String>>,
+    /// Whether an async validator is running.
+    pub validating: RwSignal<bool>,
+    /// Bumped on every validation, so stale async results are dropped.
+    generation: RwSignal<u64>,
+    validators: Vec<Validator<T>>,
+    async_validators: Vec<AsyncValidator<T>>,
+}
+
+impl<T: Send + Sync + 'static> Clone for Field<T> {
+    fn clone(&self) -> Self {
+        Field {
+            label: self.label,
+            value: self.value,
+            saved: self.saved,
+            error: self.error,
+            validating: self.validating,
+            generation: self.generation,
+            validators: self.validators.clone(),
+            async_validators: self.async_validators.clone(),
+        }
+    }
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> Field<T> {
+    pub fn new(label: &'static str, initial: T) -> Self {
+        Field {
+            label,
+            value: RwSignal::new(initial.clone()),
+            saved: RwSignal::new(initial),
+            error: RwSignal::new(None),
+            validating: RwSignal::new(false),
+            generation: RwSignal::new(0),
+            validators: Vec::new(),
+            async_validators: Vec::new(),
+        }
+    }
+
+    /// Add a validator that runs on every change.
+    pub fn validate(
+        mut self,
+        validator: impl Fn(&T) -> Result<(), String> + Send + Sync + 'static,
+    ) -> Self {
+        self.validators.push(Arc::new(validator));
+        self
+    }
+
+    /// Add a validator that runs when the form is saved, after the sync ones pass.
+    pub fn validate_async<F, Fut>(mut self, validator: F) -> Self
+    where
+        F: Fn(T) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), String>> + 'static,
+    {
+        self.async_validators
+            .push(Arc::new(move |value| Box::pin(validator(value))));
+        self
+    }
+
+    /// An id for the field's input, so the error summary can link to it.
+    pub fn id(&self) -> String {
+        format!("field-{}", self.label.to_lowercase().replace(' ', "-"))
+    }
+
+    /// Replace both the value and the saved value, e.g. after loading from storage.
+    pub fn load(&self, value: T) {
+        self.saved.set(value.clone());
+        self.value.set(value);
+        self.error.set(None);
+    }
+
+    /// Update the value and run the sync validators.
+    pub fn set(&self, value: T) {
+        self.value.set(value);
+        self.check();
+    }
+
+    /// Run the sync validators, returning whether they passed.
+    pub fn check(&self) -> bool {
+        self.generation.update(|generation| *generation += 1);
+        self.validating.set(false);
+        let error = self.value.with_untracked(|value| {
+            self.validators
+                .iter()
+                .find_map(|validator| validator(value).err())
+        });
+        let valid = error.is_none();
+        self.error.set(error);
+        valid
+    }
+
+    /// Run every validator, returning whether they all passed.
+    pub async fn check_all(&self) -> bool {
+        if !self.check() {
+            return false;
+        }
+        let generation = self.generation.get_untracked();
+        self.validating.set(true);
+        for validator in &self.async_validators {
+            let result = validator(self.value.get_untracked()).await;
+            if self.generation.get_untracked() != generation {
+                // Edited in the meantime; the newer validation wins.
+                return false;
+            }
+            if let Err(error) = result {
+                self.validating.set(false);
+                self.error.set(Some(error));
+                return false;
+            }
+        }
+        self.validating.set(false);
+        true
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.value
+            .with(|value| self.saved.with(|saved| value != saved))
+    }
+
+    /// Go back to the saved value.
+    pub fn reset(&self) {
+        self.value.set(self.saved.get_untracked());
+        self.error.set(None);
+    }
+
+    /// Mark the current value as saved.
+    pub fn mark_saved(&self) {
+        self.saved.set(self.value.get_untracked());
+    }
+}
+
+/// The type-erased operations a [`Form`] needs from its fields.
+trait FormField: Send + Sync {
+    fn label(&self) -> &'static str;
+    fn id(&self) -> String;
+    fn error(&self) -> Option<String>;
+    fn is_dirty(&self) -> bool;
+    fn check_all(&self) -> LocalBoxFuture<'_, bool>;
+    fn reset(&self);
+    fn mark_saved(&self);
+}
+
+impl<T: Clone + PartialEq + Send + Sync + 'static> FormField for Field<T> {
+    fn label(&self) -> &'static str {
+        self.label
+    }
+
+    fn id(&self) -> String {
+        Field::id(self)
+    }
+
+    fn error(&self) -> Option<String> {
+        self.error.get()
+    }
+
+    fn is_dirty(&self) -> bool {
+        Field::is_dirty(self)
+    }
+
+    fn check_all(&self) -> LocalBoxFuture<'_, bool> {
+        Box::pin(Field::check_all(self))
+    }
+
+    fn reset(&self) {
+        Field::reset(self)
+    }
+
+    fn mark_saved(&self) {
+        Field::mark_saved(self)
+    }
+}
+
+/// A group of fields that are validated and saved together.
+#[derive(Clone, Default)]
+pub struct Form {
+    fields: Vec<Arc<dyn FormField>>,
+    pub saving: RwSignal<bool>,
+    /// Error from the last save, if it failed.
+    pub save_error: RwSignal<Option<String>>,
+}
+
+impl Form {
+    pub fn new() -> Self {
+        Form::default()
+    }
+
+    pub fn with<T: Clone + PartialEq + Send + Sync + 'static>(mut self, field: &Field<T>) -> Self {
+        self.fields.push(Arc::new(field.clone()));
+        self
+    }
+
+    /// Whether any field differs from its saved value.
+    pub fn is_dirty(&self) -> bool {
+        self.fields.iter().any(|field| field.is_dirty())
+    }
+
+    /// Every current error, as (field id, label, message).
+    pub fn errors(&self) -> Vec<(String, &'static str, String)> {
+        self.fields
+            .iter()
+            .filter_map(|field| Some((field.id(), field.label(), field.error()?)))
+            .collect()
+    }
+
+    /// Validate every field and, if they're all valid, call `save`. Fields are only
+    /// marked as saved if `save` succeeds.
+    pub async fn save<F, Fut>(&self, save: F) -> bool
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<(), String>>,
+    {
+        self.saving.set(true);
+        self.save_error.set(None);
+        let mut valid = true;
+        for field in &self.fields {
+            // Check every field, so the summary lists every error at once.
+            valid &= field.check_all().await;
+        }
+        let saved = valid
+            && match save().await {
+                Ok(()) => {
+                    self.fields.iter().for_each(|field| field.mark_saved());
+                    true
+                }
+                Err(error) => {
+                    self.save_error.set(Some(error));
+                    false
+                }
+            };
+        self.saving.set(false);
+        saved
+    }
+
+    /// Discard every unsaved change.
+    pub fn discard(&self) {
+        self.fields.iter().for_each(|field| field.reset());
+        self.save_error.set(None);
+    }
+}
+
+/// Fails for empty (or whitespace-only) strings.
+pub fn required<T: AsRef<str>>(value: &T) -> Result<(), String> {
+    if value.as_ref().trim().is_empty() {
+        Err("This field is required.".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Fails for strings longer than `max` characters.
+pub fn max_len(max: usize) -> impl Fn(&String) -> Result<(), String> + Send + Sync {
+    move |value| {
+        if value.chars().count() > max {
+            Err(format!("Must be at most {max} characters."))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// An async validator that asks a [`messaging`] handler in the background, e.g. to
+/// check an API key against a server. The handler replies with `None` if the value is
+/// valid, or `Some(message)` otherwise.
+pub fn rpc<T: Serialize + 'static>(
+    name: &'static str,
+) -> impl Fn(T) -> LocalBoxFuture<'static, Result<(), String>> + Send + Sync {
+    move |value| {
+        Box::pin(async move {
+            match messaging::send::<T, Option<String>>(name, &value).await {
+                Ok(None) => Ok(()),
+                Ok(Some(message)) => Err(message),
+                Err(e) => Err(format!("Couldn't check this value: {e}")),
+            }
+        })
+    }
+}
+
+/// A labelled text input bound to a field, showing its error.
+#[component]
+pub fn TextField(
+    field: Field<String>,
+    #[prop(optional, into)] placeholder: String,
+) -> impl IntoView {
+    let id = field.id();
+    let error_id = format!("{id}-error");
+    let value = field.value;
+    let error = field.error;
+    let validating = field.validating;
+    view! {
+        <div class="mb-3">
+            <label for=id.clone() class="block font-medium">{field.label}</label>
+            <input
+                id=id
+                type="text"
+                class="border rounded px-2 py-1 w-full"
+                placeholder=placeholder
+                aria-invalid=move || error.with(Option::is_some).to_string()
+                aria-describedby=error_id.clone()
+                prop:value=move || value.get()
+                on:input=move |ev| field.set(event_target_value(&ev))
+            />
+            <p id=error_id class="text-sm text-red-700" aria-live="polite">
+                {move || {
+                    if validating.get() {
+                        "Checking…".to_string()
+                    } else {
+                        error.get().unwrap_or_default()
+                    }
+                }}
+            </p>
+        </div>
+    }
+}
+
+/// Lists every error in the form, linking to the fields.
+#[component]
+pub fn ErrorSummary(form: Form) -> impl IntoView {
+    let errors = {
+        let form = form.clone();
+        move || form.errors()
+    };
+    let save_error = form.save_error;
+    view! {
+        <Show when={
+            let errors = errors.clone();
+            move || !errors().is_empty() || save_error.with(Option::is_some)
+        }>
+            <div role="alert" class="mb-3 p-2 border border-red-700 rounded text-red-700">
+                <ul>
+                    {
+                        let errors = errors.clone();
+                        move || {
+                            errors()
+                                .into_iter()
+                                .map(|(id, label, message)| {
+                                    view! {
+                                        <li>
+                                            <a href=format!("#{id}") class="underline">
+                                                {label}
+                                            </a>
+                                            ": "
+                                            {message}
+                                        </li>
+                                    }
+                                })
+                                .collect_view()
+                        }
+                    }
+                    {move || save_error.get().map(|error| view! { <li>{error}</li> })}
+                </ul>
+            </div>
+        </Show>
+    }
+}
+
+/// A bar with Save and Discard buttons, shown while the form has unsaved changes.
+/// Mod+S (Cmd+S on macOS, Ctrl+S elsewhere) saves too.
+#[component]
+pub fn SaveBar<F, Fut>(form: Form, on_save: F) -> impl IntoView
+where
+    F: Fn() -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = Result<(), String>> + 'static,
+{
+    let saving = form.saving;
+    let toaster = toast::use_toaster();
+    let dirty = {
+        let form = form.clone();
+        move || form.is_dirty()
+    };
+    let save = {
+        let form = form.clone();
+        move || {
+            let form = form.clone();
+            let on_save = on_save.clone();
+            spawn_local(async move {
+                let saved = form.save(on_save).await;
+                let Some(toaster) = toaster else {
+                    return;
+                };
+                match form.save_error.get_untracked() {
+                    _ if saved => toaster.success("Saved."),
+                    Some(error) => toaster.error(error),
+                    None => toaster.error("Fix the errors above to save."),
+                };
+            });
+        }
+    };
+    hotkeys::use_hotkey("Mod+S", "Save", {
+        let save = save.clone();
+        let dirty = dirty.clone();
+        move || {
+            if dirty() && !saving.get_untracked() {
+                save();
+            }
+        }
+    });
+    let discard = move |_| form.discard();
+    view! {
+        <Show when=dirty>
+            <div class="fixed bottom-0 inset-x-0 flex justify-end gap-2 p-2 bg-gray-100 border-t">
+                <span class="me-auto self-center">"You have unsaved changes."</span>
+                <button type="button" class="px-3 py-1 border rounded" on:click=discard.clone()>
+                    "Discard"
+                </button>
+                <button
+                    type="button"
+                    class="px-3 py-1 rounded bg-blue-600 text-white"
+                    disabled=move || saving.get()
+                    on:click={
+                        let save = save.clone();
+                        move |_| save()
+                    }
+                >
+                    "Save"
+                </button>
+            </div>
+        </Show>
+    }
+}
@@ -0,0 +1,199 @@
+//! Content scripts in iframes.
+//!
+//! With `all_frames` in the manifest (or `allFrames` when injecting), a content
+//! script runs once per frame, and each instance is independent. This module lets an
+//! instance find out where it runs, decide whether it should run at all, and talk to
+//! the instance in the top frame of its tab.
+//!
+//! Frames [`register`] with the background, which keeps a per-tab registry of them
+//! (and needs [`install`]). The registry lives in memory, so it's empty again after
+//! the service worker restarts, until frames register again.
+//!
+//! ```ignore
+//! #[wext_entry(content)]
+//! pub async fn content_script() {
+//!     if !frames::should_run(FramePolicy::TopFrameOnly) {
+//!         return;
+//!     }
+//!     ..
+//! }
+//! ```
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+};
+
+use js_sys::Object;
+use leptos::prelude::window;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    browser,
+    messaging::{self, MessageError},
+};
+
+/// Message name used by frames to register with the background.
+const REGISTER_MESSAGE: &str = "wext.frames.register";
+/// Message name used by frames to list the frames registered in their tab.
+const LIST_MESSAGE: &str = "wext.frames.list";
+/// Message name used by frames to relay a message to their tab's top frame.
+const RELAY_MESSAGE: &str = "wext.frames.relay";
+
+/// The top frame always has frame id 0.
+const TOP_FRAME_ID: i32 = 0;
+
+/// Which frames a content script runs in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FramePolicy {
+    /// Only in the top frame of a tab, even if the manifest injects it into every frame.
+    #[default]
+    TopFrameOnly,
+    /// In every frame it's injected into.
+    AllFrames,
+}
+
+/// A registered frame.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Frame {
+    pub tab_id: i32,
+    /// `0` for the top frame.
+    pub frame_id: i32,
+    pub url: Option<String>,
+}
+
+impl Frame {
+    pub fn is_top(&self) -> bool {
+        self.frame_id == TOP_FRAME_ID
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RelayRequest {
+    name: String,
+    body: Value,
+}
+
+thread_local! {
+    /// This frame, once registered.
+    static THIS_FRAME: RefCell<Option<Frame>> = const { RefCell::new(None) };
+    /// Background only: registered frames by tab, then frame id.
+    static REGISTRY: RefCell<HashMap<i32, BTreeMap<i32, Frame>>> = RefCell::default();
+}
+
+/// Whether this script runs in the top frame of its tab, rather than in an iframe.
+///
+/// This doesn't need the background, so it works before [`register`].
+pub fn is_top_frame() -> bool {
+    let window = window();
+    match window.top() {
+        Ok(Some(top)) => Object::is(&top, &window),
+        // A missing or inaccessible `top` only happens in frames.
+        _ => false,
+    }
+}
+
+/// Whether a content script with `policy` should run in this frame. Call this first
+/// in the entry point, and return early if it's `false`.
+pub fn should_run(policy: FramePolicy) -> bool {
+    match policy {
+        FramePolicy::TopFrameOnly => is_top_frame(),
+        FramePolicy::AllFrames => true,
+    }
+}
+
+/// Register this frame with the background, and learn its tab and frame id.
+///
+/// Registering again (e.g. after the service worker restarted) is harmless; the
+/// result is cached either way, see [`this_frame`].
+pub async fn register() -> Result<Frame, MessageError> {
+    let frame: Frame = messaging::send(REGISTER_MESSAGE, &()).await?;
+    THIS_FRAME.set(Some(frame.clone()));
+    Ok(frame)
+}
+
+/// This frame, if it has [`register`]ed.
+pub fn this_frame() -> Option<Frame> {
+    THIS_FRAME.with_borrow(|frame| frame.clone())
+}
+
+/// This frame's id, registering first if needed. `0` is the top frame.
+pub async fn frame_id() -> Result<i32, MessageError> {
+    match this_frame() {
+        Some(frame) => Ok(frame.frame_id),
+        None => Ok(register().await?.frame_id),
+    }
+}
+
+/// Every registered frame in this frame's tab, top frame first.
+pub async fn frames_in_tab() -> Result<Vec<Frame>, MessageError> {
+    messaging::send(LIST_MESSAGE, &()).await
+}
+
+/// Send `request` to the `name` handler of the content script in this tab's top
+/// frame, through the background. The top frame's script registers the handler with
+/// [`messaging::handle`] and calls [`messaging::install`].
+pub async fn send_to_top<Req, Res>(name: &str, request: &Req) -> Result<Res, MessageError>
+where
+    Req: Serialize,
+    Res: DeserializeOwned,
+{
+    let body = serde_json::to_value(request).map_err(|e| MessageError::Codec(e.to_string()))?;
+    let request = RelayRequest {
+        name: name.to_string(),
+        body,
+    };
+    let response: Value = messaging::send(RELAY_MESSAGE, &request).await?;
+    serde_json::from_value(response).map_err(|e| MessageError::Codec(e.to_string()))
+}
+
+/// Keep the frame registry and relay messages to top frames. Call this in the
+/// background script, before [`messaging::install`].
+pub fn install() {
+    messaging::handle(REGISTER_MESSAGE, |_: (), sender| async move {
+        let (Some(tab_id), Some(frame_id)) = (sender.tab_id, sender.frame_id) else {
+            return Err("only content scripts can register frames".to_string());
+        };
+        let frame = Frame {
+            tab_id,
+            frame_id,
+            url: sender.url,
+        };
+        REGISTRY.with_borrow_mut(|registry| {
+            let frames = registry.entry(tab_id).or_default();
+            // The top frame registering again means the tab navigated, so the
+            // frames of the previous page are gone.
+            if frame.is_top() {
+                frames.clear();
+            }
+            frames.insert(frame_id, frame.clone());
+        });
+        Ok(frame)
+    });
+    messaging::handle(LIST_MESSAGE, |_: (), sender| async move {
+        let tab_id = sender
+            .tab_id
+            .ok_or_else(|| "only content scripts can list frames".to_string())?;
+        Ok(REGISTRY.with_borrow(|registry| {
+            registry
+                .get(&tab_id)
+                .map(|frames| frames.values().cloned().collect::<Vec<_>>())
+                .unwrap_or_default()
+        }))
+    });
+    messaging::handle(RELAY_MESSAGE, |request: RelayRequest, sender| async move {
+        let tab_id = sender
+            .tab_id
+            .ok_or_else(|| "only content scripts can message their top frame".to_string())?;
+        messaging::send_to_tab::<_, Value>(tab_id, Some(TOP_FRAME_ID), &request.name, &request.body)
+            .await
+            .map_err(|e| e.to_string())
+    });
+    browser::listen(&browser::tabs().on_removed(), |tab_id, _| {
+        if let Some(tab_id) = tab_id.as_f64() {
+            REGISTRY.with_borrow_mut(|registry| registry.remove(&(tab_id as i32)));
+        }
+    });
+}
@@ -0,0 +1,243 @@
+//! Health of the background script, for diagnosing MV3 lifecycle issues.
+//!
+//! [`install`] (in the background) records when the worker started and how often it
+//! has started this browser session, and tracks the ports other contexts open with
+//! `runtime.connect`. Runtime modules report failures with [`record_error`], from any
+//! context; the background keeps the most recent ones in `storage.session`, so they
+//! outlive the worker being stopped. The debug page shows it all through [`status`].
+
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, VecDeque},
+    fmt,
+};
+
+use gloo_console::warn;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsCast;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    browser,
+    clock::{Clock, SystemClock},
+    messaging::{self, MessageError, Sender},
+    storage::{Area, StorageArea, StorageError},
+};
+
+/// Message name used to ask the background for its [`Status`].
+const STATUS_MESSAGE: &str = "wext.health.status";
+/// Message name used by other contexts to report an error to the background.
+const ERROR_MESSAGE: &str = "wext.health.error";
+
+/// `storage.session` key for the [`Worker`] record.
+const WORKER_KEY: &str = "wext.health.worker";
+/// `storage.session` key for the recent errors.
+const ERRORS_KEY: &str = "wext.health.errors";
+
+/// How many errors are kept.
+pub const MAX_ERRORS: usize = 50;
+
+/// Starts of the background script.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Worker {
+    /// When the running instance started, in milliseconds since the epoch.
+    pub started_at: f64,
+    /// When the first instance of this browser session started.
+    pub first_started_at: f64,
+    /// Starts this browser session, including the running instance.
+    pub starts: u32,
+}
+
+impl Worker {
+    /// How often the worker was stopped and started again this browser session.
+    pub fn restarts(&self) -> u32 {
+        self.starts.saturating_sub(1)
+    }
+}
+
+/// A port connected to the background.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PortInfo {
+    /// The name given to `runtime.connect`.
+    pub name: String,
+    pub url: Option<String>,
+    pub tab_id: Option<i32>,
+    pub connected_at: f64,
+}
+
+/// An error reported with [`record_error`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ErrorRecord {
+    pub at: f64,
+    /// What failed, e.g. `jobs` or `panic`.
+    pub source: String,
+    pub message: String,
+}
+
+/// What the background reports about itself.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Status {
+    pub worker: Worker,
+    pub ports: Vec<PortInfo>,
+    /// Oldest first.
+    pub errors: Vec<ErrorRecord>,
+}
+
+/// Background only: the state behind [`Status`].
+#[derive(Default)]
+struct State {
+    worker: Worker,
+    ports: BTreeMap<u32, PortInfo>,
+    next_port: u32,
+    errors: VecDeque<ErrorRecord>,
+    /// Whether the records of previous instances were loaded. Until then, saving the
+    /// errors would overwrite theirs.
+    restored: bool,
+}
+
+thread_local! {
+    static STATE: RefCell<Option<State>> = const { RefCell::new(None) };
+}
+
+/// Start recording the background's health. Call this early in the background script,
+/// before [`messaging::install`].
+pub fn install() {
+    let now = SystemClock.now();
+    STATE.set(Some(State {
+        worker: Worker {
+            started_at: now,
+            first_started_at: now,
+            starts: 1,
+        },
+        ..State::default()
+    }));
+    browser::listen(&browser::runtime().on_connect(), |port, _| {
+        track(port.unchecked_into())
+    });
+    messaging::handle(STATUS_MESSAGE, |_: (), _| async move {
+        with_state(|state| Status {
+            worker: state.worker.clone(),
+            ports: state.ports.values().cloned().collect(),
+            errors: state.errors.iter().cloned().collect(),
+        })
+        .ok_or_else(|| "health isn't installed".to_string())
+    });
+    messaging::handle(ERROR_MESSAGE, |record: ErrorRecord, _| async move {
+        push(record);
+        Ok(())
+    });
+    spawn_local(async {
+        if let Err(e) = restore().await {
+            warn!(format!(
+                "Failed to restore the worker's health records: {e}"
+            ));
+        }
+    });
+}
+
+/// Report an error to the health dashboard. Outside the background, the error is
+/// sent there; failing to send it is silently ignored.
+pub fn record_error(source: &str, message: impl fmt::Display) {
+    let record = ErrorRecord {
+        at: SystemClock.now(),
+        source: source.to_string(),
+        message: message.to_string(),
+    };
+    let in_background = STATE.with(|state| {
+        // Already borrowed means the error happened while recording (e.g. a panic),
+        // which only the background does.
+        state.try_borrow().map(|state| state.is_some()).unwrap_or(true)
+    });
+    if in_background {
+        push(record);
+    } else {
+        spawn_local(async move {
+            let _ = messaging::send::<_, ()>(ERROR_MESSAGE, &record).await;
+        });
+    }
+}
+
+/// The background's health. Call this from an extension page.
+pub async fn status() -> Result<Status, MessageError> {
+    messaging::send(STATUS_MESSAGE, &()).await
+}
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> Option<R> {
+    STATE.with(|state| state.try_borrow_mut().ok()?.as_mut().map(f))
+}
+
+fn push(record: ErrorRecord) {
+    let saved = with_state(|state| {
+        state.errors.push_back(record);
+        while state.errors.len() > MAX_ERRORS {
+            state.errors.pop_front();
+        }
+        state.restored
+    });
+    if saved == Some(true) {
+        save_errors();
+    }
+}
+
+fn save_errors() {
+    let Some(errors) = with_state(|state| state.errors.iter().cloned().collect::<Vec<_>>()) else {
+        return;
+    };
+    spawn_local(async move {
+        // Not recorded as an error itself, which would try to save again.
+        if let Err(e) = Area::Session.set(ERRORS_KEY, &errors).await {
+            warn!(format!("Failed to save the health records: {e}"));
+        }
+    });
+}
+
+/// Count this start, and bring back the errors of previous instances.
+async fn restore() -> Result<(), StorageError> {
+    let previous: Option<Worker> = Area::Session.get(WORKER_KEY).await?;
+    let errors: Vec<ErrorRecord> = Area::Session.get(ERRORS_KEY).await?.unwrap_or_default();
+    let Some(worker) = with_state(|state| {
+        if let Some(previous) = previous {
+            state.worker.first_started_at = previous.first_started_at;
+            state.worker.starts = previous.starts + 1;
+        }
+        // Errors recorded since this instance started come after the restored ones.
+        let recent = std::mem::take(&mut state.errors);
+        state.errors = errors.into_iter().chain(recent).collect();
+        while state.errors.len() > MAX_ERRORS {
+            state.errors.pop_front();
+        }
+        state.restored = true;
+        state.worker.clone()
+    }) else {
+        return Ok(());
+    };
+    Area::Session.set(WORKER_KEY, &worker).await?;
+    save_errors();
+    Ok(())
+}
+
+/// Keep track of `port` until it disconnects.
+fn track(port: browser::Port) {
+    let sender = Sender::from_js(&port.sender());
+    let info = PortInfo {
+        name: port.name(),
+        url: sender.url,
+        tab_id: sender.tab_id,
+        connected_at: SystemClock.now(),
+    };
+    let Some(id) = with_state(|state| {
+        let id = state.next_port;
+        state.next_port += 1;
+        state.ports.insert(id, info);
+        id
+    }) else {
+        return;
+    };
+    browser::listen(&port.on_disconnect(), move |_, _| {
+        with_state(|state| state.ports.remove(&id));
+    });
+}
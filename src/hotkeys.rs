@@ -0,0 +1,384 @@
+//! Keyboard shortcuts inside extension pages.
+//!
+//! These are handled by the page while it has focus, unlike the browser-level
+//! `commands` in the manifest (see [`browser::commands`](crate::browser::commands)),
+//! which work anywhere in the browser but are limited to a few per extension.
+//!
+//! [`use_hotkey`] binds a key combination, written like `"Ctrl+Shift+K"`, `"Mod+S"`
+//! (Cmd on macOS, Ctrl elsewhere) or `"?"`, for as long as the calling component is
+//! mounted. Bindings are grouped in named scopes: [`use_scope`] starts one for the
+//! calling component and everything below it. A binding in an inner scope shadows the
+//! same keys in outer ones, and while an exclusive scope is mounted, e.g. an open
+//! [`Modal`](crate::dialog::Modal), only the bindings inside it work. Binding the same
+//! keys twice in one scope, or keys the browser keeps for itself (Ctrl+T, Ctrl+W, ...),
+//! logs a warning in debug builds.
+//!
+//! Single keys without Ctrl, Alt or Meta are ignored while typing in a text field.
+//! [`HotkeyHelp`] opens a list of the active shortcuts with `?`.
+
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use leptos::{ev::KeyboardEvent, prelude::*};
+use wasm_bindgen::prelude::*;
+use web_sys::HtmlElement;
+
+use crate::dialog::Modal;
+
+/// Shortcuts the browser handles before the page sees them, or that pages can't
+/// override.
+const RESERVED: &[&str] = &[
+    "Ctrl+N",
+    "Ctrl+Shift+N",
+    "Ctrl+T",
+    "Ctrl+Shift+T",
+    "Ctrl+W",
+    "Ctrl+Shift+W",
+    "Ctrl+Tab",
+    "Ctrl+Shift+Tab",
+    "Meta+N",
+    "Meta+T",
+    "Meta+W",
+    "Meta+Q",
+];
+
+/// A key combination.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Hotkey {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub meta: bool,
+    /// The key, as in `KeyboardEvent.key`, lowercased, e.g. `"k"`, `"?"` or
+    /// `"arrowdown"`.
+    pub key: String,
+}
+
+impl Hotkey {
+    /// Parse a combination like `"Ctrl+Shift+K"`. Modifiers are `Ctrl`, `Alt`,
+    /// `Shift`, `Meta` and `Mod`, which is Meta on macOS and Ctrl elsewhere. The key
+    /// comes last; `"Plus"` stands for `+`.
+    pub fn parse(keys: &str) -> Result<Hotkey, String> {
+        let mut hotkey = Hotkey {
+            ctrl: false,
+            alt: false,
+            shift: false,
+            meta: false,
+            key: String::new(),
+        };
+        let mut parts: Vec<&str> = keys.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        for modifier in parts {
+            let flag = match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => &mut hotkey.ctrl,
+                "alt" | "option" => &mut hotkey.alt,
+                "shift" => &mut hotkey.shift,
+                "meta" | "cmd" => &mut hotkey.meta,
+                "mod" if is_mac() => &mut hotkey.meta,
+                "mod" => &mut hotkey.ctrl,
+                _ => return Err(format!("Unknown modifier {modifier} in {keys}")),
+            };
+            *flag = true;
+        }
+        hotkey.key = match key {
+            Some(key) if key.eq_ignore_ascii_case("plus") => "+".to_string(),
+            Some(key) => key.to_lowercase(),
+            None => return Err(format!("No key in {keys}")),
+        };
+        Ok(hotkey)
+    }
+
+    fn matches(&self, ev: &KeyboardEvent) -> bool {
+        // For symbols, Shift is part of the key ("?" is Shift+/ on US layouts), so it
+        // only counts for letters and named keys.
+        let shift_matters = self.key.len() > 1 || self.key.chars().all(char::is_alphabetic);
+        ev.key().to_lowercase() == self.key
+            && ev.ctrl_key() == self.ctrl
+            && ev.alt_key() == self.alt
+            && ev.meta_key() == self.meta
+            && (!shift_matters || ev.shift_key() == self.shift)
+    }
+
+    /// Whether this works while typing in a text field.
+    fn works_in_fields(&self) -> bool {
+        self.ctrl || self.alt || self.meta
+    }
+}
+
+impl fmt::Display for Hotkey {
+    /// E.g. "Ctrl+Shift+K", or "Cmd+S" on macOS.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let meta = if is_mac() { "Cmd+" } else { "Meta+" };
+        for (on, name) in [
+            (self.ctrl, "Ctrl+"),
+            (self.alt, "Alt+"),
+            (self.shift, "Shift+"),
+            (self.meta, meta),
+        ] {
+            if on {
+                f.write_str(name)?;
+            }
+        }
+        let mut chars = self.key.chars();
+        match chars.next() {
+            Some(first) => write!(f, "{}{}", first.to_uppercase(), chars.as_str()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// A shortcut that works right now, for [`HotkeyHelp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ActiveHotkey {
+    pub scope: &'static str,
+    pub keys: String,
+    pub description: &'static str,
+}
+
+/// The scope bindings made in a component belong to, from [`use_scope`].
+#[derive(Debug, Clone, Copy)]
+struct ScopeId(u64);
+
+struct Scope {
+    id: u64,
+    parent: Option<u64>,
+    name: &'static str,
+    exclusive: bool,
+}
+
+struct Binding {
+    id: u64,
+    scope: Option<u64>,
+    hotkey: Hotkey,
+    description: &'static str,
+    handler: Rc<dyn Fn()>,
+}
+
+#[derive(Default)]
+struct State {
+    next_id: u64,
+    /// Mounted scopes, outermost first.
+    scopes: Vec<Scope>,
+    /// Bindings, oldest first.
+    bindings: Vec<Binding>,
+    listener: bool,
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    /// Whether `scope` is `ancestor` or inside it.
+    fn is_within(&self, mut scope: Option<u64>, ancestor: u64) -> bool {
+        while let Some(id) = scope {
+            if id == ancestor {
+                return true;
+            }
+            scope = self
+                .scopes
+                .iter()
+                .find(|other| other.id == id)
+                .and_then(|other| other.parent);
+        }
+        false
+    }
+
+    /// The bindings that work right now, innermost (most recently bound) first.
+    fn active(&self) -> impl Iterator<Item = &Binding> {
+        let exclusive = self
+            .scopes
+            .iter()
+            .rev()
+            .find(|scope| scope.exclusive)
+            .map(|scope| scope.id);
+        self.bindings
+            .iter()
+            .rev()
+            .filter(move |binding| exclusive.is_none_or(|id| self.is_within(binding.scope, id)))
+    }
+
+    fn scope_name(&self, scope: Option<u64>) -> &'static str {
+        scope
+            .and_then(|id| self.scopes.iter().find(|other| other.id == id))
+            .map_or("General", |scope| scope.name)
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::default();
+}
+
+/// Start a scope named `name` for the calling component and everything below it,
+/// until it unmounts. While an `exclusive` scope is mounted, bindings outside it
+/// don't work.
+pub fn use_scope(name: &'static str, exclusive: bool) {
+    let parent = use_context::<ScopeId>().map(|scope| scope.0);
+    let id = STATE.with_borrow_mut(|state| {
+        let id = state.next_id();
+        state.scopes.push(Scope {
+            id,
+            parent,
+            name,
+            exclusive,
+        });
+        id
+    });
+    provide_context(ScopeId(id));
+    on_cleanup(move || {
+        STATE.with_borrow_mut(|state| state.scopes.retain(|scope| scope.id != id));
+    });
+}
+
+/// Call `handler` when `keys` are pressed, while the calling component is mounted.
+/// `description` is shown in [`HotkeyHelp`].
+///
+/// Panics if `keys` can't be parsed.
+pub fn use_hotkey(keys: &str, description: &'static str, handler: impl Fn() + 'static) {
+    let hotkey = Hotkey::parse(keys).unwrap_or_else(|error| panic!("{error}"));
+    install_listener();
+    let scope = use_context::<ScopeId>().map(|scope| scope.0);
+    let id = STATE.with_borrow_mut(|state| {
+        if cfg!(debug_assertions) {
+            warn_conflicts(state, scope, &hotkey, description);
+        }
+        let id = state.next_id();
+        state.bindings.push(Binding {
+            id,
+            scope,
+            hotkey,
+            description,
+            handler: Rc::new(handler),
+        });
+        id
+    });
+    on_cleanup(move || {
+        STATE.with_borrow_mut(|state| state.bindings.retain(|binding| binding.id != id));
+    });
+}
+
+/// The shortcuts that work right now, innermost scope first, without shadowed ones.
+pub fn active() -> Vec<ActiveHotkey> {
+    STATE.with_borrow(|state| {
+        let mut seen: Vec<&Hotkey> = Vec::new();
+        let mut active = Vec::new();
+        for binding in state.active() {
+            if seen.contains(&&binding.hotkey) {
+                continue;
+            }
+            seen.push(&binding.hotkey);
+            active.push(ActiveHotkey {
+                scope: state.scope_name(binding.scope),
+                keys: binding.hotkey.to_string(),
+                description: binding.description,
+            });
+        }
+        active
+    })
+}
+
+/// A list of the active shortcuts, opened with `?` or Shift+/. Render it once per
+/// page.
+#[component]
+pub fn HotkeyHelp() -> impl IntoView {
+    let open = RwSignal::new(false);
+    let shown = RwSignal::new(Vec::<ActiveHotkey>::new());
+    use_hotkey("?", "Show keyboard shortcuts", move || {
+        // Taken before opening, as the dialog's own scope hides the page's bindings.
+        shown.set(active());
+        open.set(true);
+    });
+    view! {
+        <Modal open label="Keyboard shortcuts">
+            <h2 class="font-bold mb-2">"Keyboard shortcuts"</h2>
+            <table class="text-sm">
+                <For
+                    each=move || shown.get()
+                    key=|hotkey| hotkey.keys.clone()
+                    children=|hotkey| {
+                        view! {
+                            <tr>
+                                <td class="pe-3">
+                                    <kbd class="px-1 border rounded font-mono">{hotkey.keys}</kbd>
+                                </td>
+                                <td class="pe-3">{hotkey.description}</td>
+                                <td class="text-gray-600">{hotkey.scope}</td>
+                            </tr>
+                        }
+                    }
+                />
+            </table>
+        </Modal>
+    }
+}
+
+fn warn_conflicts(state: &State, scope: Option<u64>, hotkey: &Hotkey, description: &str) {
+    let keys = hotkey.to_string();
+    if RESERVED
+        .iter()
+        .any(|reserved| Hotkey::parse(reserved).is_ok_and(|reserved| reserved == *hotkey))
+    {
+        gloo_console::warn!(format!(
+            "The browser keeps {keys} for itself, so \"{description}\" may never run."
+        ));
+    }
+    if let Some(other) = state
+        .bindings
+        .iter()
+        .find(|binding| binding.scope == scope && binding.hotkey == *hotkey)
+    {
+        gloo_console::warn!(format!(
+            "{keys} is bound twice in the {} scope: \"{}\" and \"{description}\". Only the \
+             latest runs.",
+            state.scope_name(scope),
+            other.description,
+        ));
+    }
+}
+
+fn install_listener() {
+    if STATE.with_borrow_mut(|state| std::mem::replace(&mut state.listener, true)) {
+        return;
+    }
+    let listener = Closure::<dyn Fn(KeyboardEvent)>::new(|ev: KeyboardEvent| {
+        if ev.default_prevented() || ev.repeat() {
+            return;
+        }
+        let typing = is_typing(&ev);
+        let handler = STATE.with_borrow(|state| {
+            state
+                .active()
+                .find(|binding| {
+                    binding.hotkey.matches(&ev) && (!typing || binding.hotkey.works_in_fields())
+                })
+                .map(|binding| binding.handler.clone())
+        });
+        if let Some(handler) = handler {
+            ev.prevent_default();
+            handler();
+        }
+    });
+    window()
+        .add_event_listener_with_callback("keydown", listener.as_ref().unchecked_ref())
+        .unwrap();
+    listener.forget();
+}
+
+/// Whether the event comes from a text field, where single keys are typed text.
+fn is_typing(ev: &KeyboardEvent) -> bool {
+    let Some(target) = ev
+        .target()
+        .and_then(|target| target.dyn_into::<HtmlElement>().ok())
+    else {
+        return false;
+    };
+    matches!(target.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+        || target.is_content_editable()
+}
+
+fn is_mac() -> bool {
+    window()
+        .navigator()
+        .platform()
+        .is_ok_and(|platform| platform.starts_with("Mac"))
+}
@@ -0,0 +1,143 @@
+//! Localization helpers on top of `chrome.i18n`.
+//!
+//! Look messages up with [`t!`](crate::t), e.g. `t!("popupGreeting")`, or
+//! `t!("itemCount", count.to_string())` with substitutions. wextrunk scans the sources
+//! for these at build time and fails if a key is missing from the default locale, so
+//! keys should always be string literals. With the single-file catalog
+//! (`messages.toml`), unknown keys are also a compile error, as the build script
+//! bakes its keys in. The manifest's name and description come from the same
+//! catalog (`extName` and `extDescription`).
+//!
+//! Generated HTML pages already get `dir` and `lang` set on `<html>` from the UI
+//! locale before the wasm loads (see wextrunk). Views that need the direction
+//! themselves, e.g. to flip an icon, read it from context with [`use_direction`].
+//! For everything else, prefer Tailwind's logical utilities (`ps-*`, `ms-*`,
+//! `text-start`, `start-*`, ...) over `left`/`right` ones, so layouts mirror on
+//! their own.
+
+use leptos::prelude::*;
+use wasm_bindgen::JsValue;
+
+use crate::browser;
+
+/// The keys of `messages.toml`, if the catalog is kept there.
+const KEYS: Option<&[&str]> = include!(concat!(env!("OUT_DIR"), "/messages.rs"));
+
+/// Look up a localized message from the catalog, with optional substitutions for
+/// `$1`..`$9` placeholders.
+#[macro_export]
+macro_rules! t {
+    ($key:literal $(, $substitution:expr)* $(,)?) => {{
+        const _: () = $crate::i18n::check_key($key);
+        $crate::i18n::message($key, &[$(::std::convert::AsRef::<str>::as_ref(&$substitution)),*])
+    }};
+}
+
+/// Fail compilation if `messages.toml` doesn't have `key`. Message names are
+/// case-insensitive, like in the browser.
+#[doc(hidden)]
+pub const fn check_key(key: &str) {
+    let Some(keys) = KEYS else {
+        return;
+    };
+    let mut index = 0;
+    while index < keys.len() {
+        if same_key(key.as_bytes(), keys[index].as_bytes()) {
+            return;
+        }
+        index += 1;
+    }
+    panic!("t!() uses a key that isn't in messages.toml");
+}
+
+const fn same_key(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    let mut index = 0;
+    while index < a.len() {
+        if !a[index].eq_ignore_ascii_case(&b[index]) {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+/// Look up a localized message. Prefer [`t!`](crate::t), whose keys are checked at
+/// build time.
+///
+/// Returns an empty string for unknown keys, or where `chrome.i18n` isn't available.
+pub fn message(key: &str, substitutions: &[&str]) -> String {
+    if browser::api("i18n").is_undefined() {
+        return String::new();
+    }
+    let substitutions = if substitutions.is_empty() {
+        JsValue::UNDEFINED
+    } else {
+        substitutions
+            .iter()
+            .map(|substitution| JsValue::from_str(substitution))
+            .collect::<js_sys::Array>()
+            .into()
+    };
+    browser::i18n().get_message(key, substitutions)
+}
+
+/// Text direction of the UI locale.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Ltr,
+    Rtl,
+}
+
+impl Direction {
+    /// The value of the HTML `dir` attribute.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Direction::Ltr => "ltr",
+            Direction::Rtl => "rtl",
+        }
+    }
+
+    pub fn is_rtl(self) -> bool {
+        self == Direction::Rtl
+    }
+}
+
+/// Direction of the browser UI locale, from the predefined `@@bidi_dir` message.
+///
+/// Falls back to left-to-right where `chrome.i18n` isn't available.
+pub fn direction() -> Direction {
+    if browser::api("i18n").is_undefined() {
+        return Direction::Ltr;
+    }
+    match browser::i18n()
+        .get_message("@@bidi_dir", JsValue::UNDEFINED)
+        .as_str()
+    {
+        "rtl" => Direction::Rtl,
+        _ => Direction::Ltr,
+    }
+}
+
+/// The browser UI language, e.g. `"en-US"`.
+pub fn ui_language() -> String {
+    if browser::api("i18n").is_undefined() {
+        return String::new();
+    }
+    browser::i18n().get_ui_language()
+}
+
+/// Provide the UI [`Direction`] to the current component tree. Call this at the root of
+/// each page.
+pub fn provide_direction() {
+    provide_context(direction());
+}
+
+/// The UI [`Direction`] provided by [`provide_direction`], or left-to-right if none
+/// was provided.
+pub fn use_direction() -> Direction {
+    use_context().unwrap_or_default()
+}
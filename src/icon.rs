@@ -0,0 +1,62 @@
+//! Icons from the SVG sprite wextrunk builds from `[sprite] dir` in `wextrunk.toml`.
+//!
+//! `<Icon name="settings"/>` shows `settings.svg` from that directory, at the text's
+//! size and colour by default. Icons are decorative (hidden from screen readers)
+//! unless they have a `label`, e.g. on an icon-only button.
+
+use std::cell::OnceCell;
+
+use leptos::prelude::*;
+
+/// The sprite wextrunk writes, when it isn't inlined.
+const SPRITE_FILE: &str = "/sprite.svg";
+/// The id of the sprite wextrunk inlines in pages.
+const SPRITE_ID: &str = "wext-sprite";
+
+thread_local! {
+    /// Whether this page has the sprite inlined.
+    static INLINE: OnceCell<bool> = const { OnceCell::new() };
+}
+
+/// An icon from the sprite.
+#[component]
+pub fn Icon(
+    /// The icon's file name, without `.svg`.
+    #[prop(into)]
+    name: String,
+    /// The accessible name, for icons that aren't next to text saying the same.
+    #[prop(optional, into)]
+    label: Option<String>,
+    /// Width and height, as CSS; the text's size by default.
+    #[prop(default = "1em")]
+    size: &'static str,
+    #[prop(optional, into)] class: String,
+) -> impl IntoView {
+    let hidden = label.is_none().then_some("true");
+    let role = label.is_some().then_some("img");
+    view! {
+        <svg
+            class=class
+            width=size
+            height=size
+            fill="currentColor"
+            role=role
+            aria-label=label
+            aria-hidden=hidden
+            focusable="false"
+            // The view macro has no `<use>` element, so it's set as markup.
+            inner_html=format!("<use href=\"{}\"></use>", href(&name).replace('"', "&quot;"))
+        ></svg>
+    }
+}
+
+/// The `<use>` reference of an icon.
+fn href(name: &str) -> String {
+    let inline = INLINE
+        .with(|inline| *inline.get_or_init(|| document().get_element_by_id(SPRITE_ID).is_some()));
+    if inline {
+        format!("#icon-{name}")
+    } else {
+        format!("{SPRITE_FILE}#icon-{name}")
+    }
+}
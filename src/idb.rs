@@ -0,0 +1,159 @@
+//! A small IndexedDB wrapper, for data that's too large or too plentiful for
+//! `chrome.storage`, e.g. saved sessions.
+//!
+//! A [`Database`] is declared once, with its version and object stores, and opened on
+//! first use, creating the stores it doesn't have yet. Every store is keyed by the
+//! `id` field of its values, and assigns one when a value has none, so leave it out
+//! of new values (an `Option` with `skip_serializing_if`) rather than sending `null`:
+//!
+//! ```ignore
+//! const DB: Database = Database::new("notes", 1, &["notes"]);
+//!
+//! #[derive(Serialize, Deserialize)]
+//! struct Note {
+//!     #[serde(skip_serializing_if = "Option::is_none")]
+//!     id: Option<Key>,
+//!     text: String,
+//! }
+//!
+//! let id = DB.store("notes").put(&Note { id: None, text }).await?;
+//! let notes: Vec<Note> = DB.store("notes").get_all().await?;
+//! ```
+//!
+//! Every extension page and the background share the extension's databases. Values
+//! are stored as plain objects, so they can be inspected in the devtools.
+
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::error::WextError;
+
+/// An object store key, as assigned by the store.
+pub type Key = u64;
+
+#[wasm_bindgen(inline_js = r#"
+const opened = new Map();
+
+function request(req) {
+  return new Promise((resolve, reject) => {
+    req.onsuccess = () => resolve(req.result);
+    req.onerror = () => reject(req.error);
+  });
+}
+
+function open(name, version, stores) {
+  if (!opened.has(name)) {
+    const req = indexedDB.open(name, version);
+    req.onupgradeneeded = () => {
+      for (const store of stores) {
+        if (!req.result.objectStoreNames.contains(store)) {
+          req.result.createObjectStore(store, { keyPath: "id", autoIncrement: true });
+        }
+      }
+    };
+    const db = request(req);
+    // A failed open is retried next time.
+    db.catch(() => opened.delete(name));
+    opened.set(name, db);
+  }
+  return opened.get(name);
+}
+
+export async function idb_call(name, version, stores, store, method, arg) {
+  const db = await open(name, version, stores);
+  const mode = method === "put" || method === "delete" ? "readwrite" : "readonly";
+  const objects = db.transaction(store, mode).objectStore(store);
+  return request(arg === undefined ? objects[method]() : objects[method](arg));
+}
+"#)]
+extern "C" {
+    /// Call `method` on an object store, in a transaction of its own.
+    #[wasm_bindgen(catch)]
+    async fn idb_call(
+        name: &str,
+        version: u32,
+        stores: Vec<JsValue>,
+        store: &str,
+        method: &str,
+        arg: JsValue,
+    ) -> Result<JsValue, JsValue>;
+}
+
+/// An IndexedDB database and its object stores.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Database {
+    name: &'static str,
+    version: u32,
+    stores: &'static [&'static str],
+}
+
+impl Database {
+    /// Bump `version` when adding stores, so they're created in existing databases.
+    pub const fn new(name: &'static str, version: u32, stores: &'static [&'static str]) -> Self {
+        Database {
+            name,
+            version,
+            stores,
+        }
+    }
+
+    /// One of the database's object stores.
+    pub fn store(&self, name: &'static str) -> Store {
+        debug_assert!(
+            self.stores.contains(&name),
+            "{name} isn't an object store of the {} database",
+            self.name
+        );
+        Store { db: *self, name }
+    }
+}
+
+/// An object store.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Store {
+    db: Database,
+    name: &'static str,
+}
+
+impl Store {
+    /// Add `value`, or replace the one with the same `id`. Returns its key.
+    pub async fn put<T: Serialize>(&self, value: &T) -> Result<Key, WextError> {
+        let value = value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?;
+        let key = self.call("put", value).await?;
+        key.as_f64()
+            .map(|key| key as Key)
+            .ok_or_else(|| WextError::Other(format!("{} returned a non-numeric key", self.name)))
+    }
+
+    pub async fn get<T: DeserializeOwned>(&self, key: Key) -> Result<Option<T>, WextError> {
+        let value = self.call("get", (key as f64).into()).await?;
+        if value.is_undefined() {
+            return Ok(None);
+        }
+        Ok(Some(serde_wasm_bindgen::from_value(value)?))
+    }
+
+    /// Every value, in key order.
+    pub async fn get_all<T: DeserializeOwned>(&self) -> Result<Vec<T>, WextError> {
+        let values = self.call("getAll", JsValue::UNDEFINED).await?;
+        Ok(serde_wasm_bindgen::from_value(values)?)
+    }
+
+    pub async fn delete(&self, key: Key) -> Result<(), WextError> {
+        self.call("delete", (key as f64).into()).await?;
+        Ok(())
+    }
+
+    async fn call(&self, method: &str, arg: JsValue) -> Result<JsValue, WextError> {
+        let stores = self.db.stores.iter().map(|&store| store.into()).collect();
+        Ok(idb_call(
+            self.db.name,
+            self.db.version,
+            stores,
+            self.name,
+            method,
+            arg,
+        )
+        .await?)
+    }
+}
@@ -0,0 +1,418 @@
+use leptos::{prelude::*, spawn::spawn_local};
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    browser,
+    entry::wext_entry,
+    i18n,
+    storage::{Area, StorageArea},
+};
+
+/// Records shown per IndexedDB object store.
+const RECORD_LIMIT: u32 = 100;
+
+#[wasm_bindgen(inline_js = r#"
+function show(value) {
+  try {
+    const json = JSON.stringify(value, null, 2);
+    if (json !== undefined) return json;
+  } catch (_) {}
+  return String(value);
+}
+
+function open(name) {
+  return new Promise((resolve, reject) => {
+    const request = indexedDB.open(name);
+    // Don't create a database that was deleted in the meantime.
+    request.onupgradeneeded = () => request.transaction.abort();
+    request.onsuccess = () => resolve(request.result);
+    request.onerror = () => reject(request.error);
+  });
+}
+
+export async function idb_databases() {
+  if (!indexedDB.databases) return [];
+  return (await indexedDB.databases()).map((db) => db.name);
+}
+
+export async function idb_stores(name) {
+  const db = await open(name);
+  try {
+    return Array.from(db.objectStoreNames);
+  } finally {
+    db.close();
+  }
+}
+
+export async function idb_records(name, store, limit) {
+  const db = await open(name);
+  try {
+    const request = db.transaction(store, "readonly").objectStore(store).openCursor();
+    return await new Promise((resolve, reject) => {
+      const records = [];
+      request.onsuccess = () => {
+        const cursor = request.result;
+        if (cursor && records.length < limit) {
+          records.push([show(cursor.key), show(cursor.value)]);
+          cursor.continue();
+        } else {
+          resolve(records);
+        }
+      };
+      request.onerror = () => reject(request.error);
+    });
+  } finally {
+    db.close();
+  }
+}
+"#)]
+extern "C" {
+    /// Names of the extension's IndexedDB databases.
+    #[wasm_bindgen(catch)]
+    async fn idb_databases() -> Result<JsValue, JsValue>;
+
+    /// Object store names of a database.
+    #[wasm_bindgen(catch)]
+    async fn idb_stores(name: &str) -> Result<JsValue, JsValue>;
+
+    /// The first `limit` records of a store, as [key, value] pairs of display text.
+    #[wasm_bindgen(catch)]
+    async fn idb_records(name: &str, store: &str, limit: u32) -> Result<JsValue, JsValue>;
+}
+
+/// An in-extension version of the devtools' storage views, for debug builds.
+#[wext_entry(page)]
+pub async fn storage_page() {
+    mount_to_body(|| {
+        i18n::provide_direction();
+        if !cfg!(debug_assertions) {
+            return view! {
+                <main class="p-4">"The storage inspector is only available in debug builds."</main>
+            }
+            .into_any();
+        }
+        view! {
+            <main class="p-4 font-mono text-sm">
+                <h1 class="text-lg font-bold mb-2">"Storage"</h1>
+                <StorageInspector />
+                <IndexedDbBrowser />
+            </main>
+        }
+        .into_any()
+    })
+}
+
+/// Every key in one storage area, editable as JSON.
+#[component]
+fn StorageInspector() -> impl IntoView {
+    let area = RwSignal::new(Area::Local);
+    let items = RwSignal::new(Vec::<(String, Value)>::new());
+    let error = RwSignal::new(None::<String>);
+    let reload = move || {
+        spawn_local(async move {
+            match area.get_untracked().get_all().await {
+                Ok(all) => {
+                    items.set(all.into_iter().collect());
+                    error.set(None);
+                }
+                Err(e) => {
+                    items.set(Vec::new());
+                    error.set(Some(e.to_string()));
+                }
+            }
+        })
+    };
+    reload();
+    browser::listen(&browser::storage_on_changed(), move |_, changed| {
+        if changed.as_string().as_deref() == Some(area.get_untracked().name()) {
+            reload();
+        }
+    });
+
+    let tab = move |choice: Area| {
+        view! {
+            <button
+                type="button"
+                class="px-3 py-1 border rounded"
+                class:font-bold=move || area.get() == choice
+                aria-pressed=move || (area.get() == choice).to_string()
+                on:click=move |_| {
+                    area.set(choice);
+                    reload();
+                }
+            >
+                {choice.name().to_string()}
+            </button>
+        }
+    };
+
+    view! {
+        <section class="mb-6">
+            <h2 class="font-bold mb-2">"storage"</h2>
+            <div class="flex gap-2 mb-2" role="group" aria-label="Storage area">
+                {tab(Area::Local)}
+                {tab(Area::Sync)}
+                {tab(Area::Session)}
+            </div>
+            {move || error.get().map(|e| view! { <p class="text-red-700">{e}</p> })}
+            <p class="mb-2">{move || format!("{} keys", items.with(Vec::len))}</p>
+            <For
+                each=move || items.get()
+                key=|(key, value)| (key.clone(), value.to_string())
+                children=move |(key, value)| view! { <Entry area=area.get_untracked() key value /> }
+            />
+            <NewEntry area />
+        </section>
+    }
+}
+
+/// One stored key, with its value as editable JSON.
+#[component]
+fn Entry(area: Area, key: String, value: Value) -> impl IntoView {
+    let draft = RwSignal::new(serde_json::to_string_pretty(&value).unwrap_or_default());
+    let error = RwSignal::new(None::<String>);
+    let save = {
+        let key = key.clone();
+        move |_| {
+            let key = key.clone();
+            spawn_local(async move {
+                let result = match serde_json::from_str::<Value>(&draft.get_untracked()) {
+                    Ok(value) => area.set_value(&key, value).await.map_err(|e| e.to_string()),
+                    Err(e) => Err(format!("Invalid JSON: {e}")),
+                };
+                error.set(result.err());
+            })
+        }
+    };
+    let delete = {
+        let key = key.clone();
+        move |_| {
+            let key = key.clone();
+            spawn_local(async move {
+                error.set(area.remove(&key).await.err().map(|e| e.to_string()));
+            })
+        }
+    };
+
+    view! {
+        <details class="border-b py-1">
+            <summary class="cursor-pointer">{key.clone()}</summary>
+            <textarea
+                class="w-full border rounded p-1"
+                rows=move || draft.with(|draft| draft.lines().count().clamp(2, 20))
+                aria-label=format!("Value of {key}")
+                prop:value=move || draft.get()
+                on:input=move |ev| draft.set(event_target_value(&ev))
+            ></textarea>
+            {move || error.get().map(|e| view! { <p class="text-red-700">{e}</p> })}
+            <div class="flex gap-2">
+                <button type="button" class="px-3 py-1 underline" on:click=save>
+                    "Save"
+                </button>
+                <button type="button" class="px-3 py-1 underline" on:click=delete>
+                    "Delete"
+                </button>
+            </div>
+        </details>
+    }
+}
+
+/// A form to add a key to the selected area.
+#[component]
+fn NewEntry(area: RwSignal<Area>) -> impl IntoView {
+    let key = RwSignal::new(String::new());
+    let draft = RwSignal::new(String::new());
+    let error = RwSignal::new(None::<String>);
+    let add = move |_| {
+        spawn_local(async move {
+            let name = key.get_untracked();
+            let result = if name.is_empty() {
+                Err("The key is required.".to_string())
+            } else {
+                match serde_json::from_str::<Value>(&draft.get_untracked()) {
+                    Ok(value) => area
+                        .get_untracked()
+                        .set_value(&name, value)
+                        .await
+                        .map_err(|e| e.to_string()),
+                    Err(e) => Err(format!("Invalid JSON: {e}")),
+                }
+            };
+            if result.is_ok() {
+                key.set(String::new());
+                draft.set(String::new());
+            }
+            error.set(result.err());
+        })
+    };
+
+    view! {
+        <fieldset class="mt-2">
+            <legend class="font-medium">"Add a key"</legend>
+            <input
+                type="text"
+                class="border rounded px-2 py-1 w-full"
+                placeholder="Key"
+                aria-label="Key"
+                prop:value=move || key.get()
+                on:input=move |ev| key.set(event_target_value(&ev))
+            />
+            <textarea
+                class="w-full border rounded p-1"
+                rows="3"
+                placeholder="JSON value, e.g. {\"enabled\": true}"
+                aria-label="Value"
+                prop:value=move || draft.get()
+                on:input=move |ev| draft.set(event_target_value(&ev))
+            ></textarea>
+            {move || error.get().map(|e| view! { <p class="text-red-700">{e}</p> })}
+            <button type="button" class="px-3 py-1 underline" on:click=add>
+                "Add"
+            </button>
+        </fieldset>
+    }
+}
+
+/// Read-only browsing of the extension's IndexedDB databases.
+#[component]
+fn IndexedDbBrowser() -> impl IntoView {
+    let databases = RwSignal::new(Vec::<String>::new());
+    let stores = RwSignal::new(Vec::<String>::new());
+    let records = RwSignal::new(Vec::<(String, String)>::new());
+    let database = RwSignal::new(None::<String>);
+    let store = RwSignal::new(None::<String>);
+    let error = RwSignal::new(None::<String>);
+    let fail = move |e: JsValue| error.set(Some(browser::error_message(&e)));
+
+    spawn_local(async move {
+        match idb_databases().await {
+            Ok(names) => databases.set(serde_wasm_bindgen::from_value(names).unwrap_or_default()),
+            Err(e) => fail(e),
+        }
+    });
+    let open_database = move |name: String| {
+        database.set(Some(name.clone()));
+        store.set(None);
+        stores.set(Vec::new());
+        records.set(Vec::new());
+        spawn_local(async move {
+            match idb_stores(&name).await {
+                Ok(names) => stores.set(serde_wasm_bindgen::from_value(names).unwrap_or_default()),
+                Err(e) => fail(e),
+            }
+        });
+    };
+    let open_store = move |name: String| {
+        let Some(db) = database.get_untracked() else {
+            return;
+        };
+        store.set(Some(name.clone()));
+        spawn_local(async move {
+            match idb_records(&db, &name, RECORD_LIMIT).await {
+                Ok(list) => records.set(serde_wasm_bindgen::from_value(list).unwrap_or_default()),
+                Err(e) => fail(e),
+            }
+        });
+    };
+
+    view! {
+        <section>
+            <h2 class="font-bold mb-2">"IndexedDB"</h2>
+            {move || error.get().map(|e| view! { <p class="text-red-700">{e}</p> })}
+            <Show
+                when=move || !databases.with(Vec::is_empty)
+                fallback=|| view! { <p>"No databases."</p> }
+            >
+                <div class="flex flex-wrap gap-2 mb-2" role="group" aria-label="Database">
+                    <For
+                        each=move || databases.get()
+                        key=Clone::clone
+                        children=move |name| {
+                            let selected = {
+                                let name = name.clone();
+                                move || database.get().as_ref() == Some(&name)
+                            };
+                            view! {
+                                <button
+                                    type="button"
+                                    class="px-3 py-1 border rounded"
+                                    class:font-bold=selected.clone()
+                                    aria-pressed=move || selected().to_string()
+                                    on:click={
+                                        let name = name.clone();
+                                        move |_| open_database(name.clone())
+                                    }
+                                >
+                                    {name.clone()}
+                                </button>
+                            }
+                        }
+                    />
+                </div>
+            </Show>
+            <div class="flex flex-wrap gap-2 mb-2" role="group" aria-label="Object store">
+                <For
+                    each=move || stores.get()
+                    key=Clone::clone
+                    children=move |name| {
+                        let selected = {
+                            let name = name.clone();
+                            move || store.get().as_ref() == Some(&name)
+                        };
+                        view! {
+                            <button
+                                type="button"
+                                class="px-3 py-1 border rounded"
+                                class:font-bold=selected.clone()
+                                aria-pressed=move || selected().to_string()
+                                on:click={
+                                    let name = name.clone();
+                                    move |_| open_store(name.clone())
+                                }
+                            >
+                                {name.clone()}
+                            </button>
+                        }
+                    }
+                />
+            </div>
+            <Show when=move || store.with(Option::is_some)>
+                <p class="mb-2">
+                    {move || {
+                        let count = records.with(Vec::len);
+                        if count as u32 >= RECORD_LIMIT {
+                            format!("First {count} records")
+                        } else {
+                            format!("{count} records")
+                        }
+                    }}
+                </p>
+                <table class="w-full text-start">
+                    <thead>
+                        <tr>
+                            <th class="text-start">"Key"</th>
+                            <th class="text-start">"Value"</th>
+                        </tr>
+                    </thead>
+                    <tbody>
+                        <For
+                            each=move || records.get()
+                            key=|(key, _)| key.clone()
+                            children=|(key, value)| {
+                                view! {
+                                    <tr class="align-top border-b">
+                                        <td class="pe-4">{key}</td>
+                                        <td>
+                                            <pre class="whitespace-pre-wrap">{value}</pre>
+                                        </td>
+                                    </tr>
+                                }
+                            }
+                        />
+                    </tbody>
+                </table>
+            </Show>
+        </section>
+    }
+}
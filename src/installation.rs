@@ -0,0 +1,169 @@
+//! The install's identity and history: a random install ID, when it was installed, and
+//! which versions it has run.
+//!
+//! The background creates the record on first run with [`install`], and adds each new
+//! version as it starts. Installs from before this module get a record the first time
+//! they run it, marked [`backfilled`](Installation::backfilled), as their real install
+//! date and earlier versions are unknown.
+//!
+//! Every context loads the record before its entry point runs, so it's available
+//! synchronously with [`current`] or `entry::environment().installation()`. Use
+//! [`get`] where it must be there, e.g. on the very first run, before the background
+//! has finished creating it.
+
+use std::cell::RefCell;
+
+use futures::{
+    future::{LocalBoxFuture, Shared},
+    FutureExt,
+};
+use gloo_console::warn;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{
+    browser,
+    clock::{Clock, SystemClock},
+    error::WextError,
+    messaging,
+    storage::{Area, StorageArea},
+};
+
+/// Message name used to ask the background for the record.
+const GET_MESSAGE: &str = "wext.installation.get";
+
+/// `storage.local` key for the [`Installation`] record.
+pub const INSTALLATION_KEY: &str = "wext.installation";
+
+/// A version the install has run.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VersionRecord {
+    pub version: String,
+    /// When it first ran, in milliseconds since the epoch.
+    pub at: f64,
+}
+
+/// The install's record.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Installation {
+    /// A random UUID. Stays the same across updates, and is never derived from
+    /// anything about the user or their browser.
+    pub id: String,
+    /// When the extension was installed, in milliseconds since the epoch.
+    pub installed_at: f64,
+    /// Whether the install predates this record, in which case `installed_at` and the
+    /// first version are only when it was created.
+    pub backfilled: bool,
+    /// Every version the install has run, oldest first.
+    pub versions: Vec<VersionRecord>,
+}
+
+impl Installation {
+    /// The version running now.
+    pub fn version(&self) -> Option<&str> {
+        self.versions.last().map(|record| record.version.as_str())
+    }
+
+    /// The version that ran before this one, if the install was updated.
+    pub fn previous_version(&self) -> Option<&str> {
+        let len = self.versions.len();
+        (len >= 2).then(|| self.versions[len - 2].version.as_str())
+    }
+}
+
+type Ensure = Shared<LocalBoxFuture<'static, Result<Installation, WextError>>>;
+
+thread_local! {
+    static CURRENT: RefCell<Option<Installation>> = const { RefCell::new(None) };
+    /// Background only: creating or updating the record.
+    static ENSURE: RefCell<Option<Ensure>> = const { RefCell::new(None) };
+}
+
+/// Create or update the record, and serve it to other contexts. Call this early in the
+/// background script, before [`messaging::install`].
+pub fn install() {
+    let ensure = ensure().boxed_local().shared();
+    ENSURE.set(Some(ensure.clone()));
+    messaging::handle(GET_MESSAGE, |_: (), _| async {
+        get().await.map_err(|e| e.to_string())
+    });
+    wasm_bindgen_futures::spawn_local(async move {
+        if let Err(e) = ensure.await {
+            warn!(format!("Failed to record the installation: {e}"));
+        }
+    });
+}
+
+/// The record, as loaded when this context started, or `None` if it didn't exist yet.
+pub fn current() -> Option<Installation> {
+    CURRENT.with_borrow(Clone::clone)
+}
+
+/// The record, waiting for the background to create it if needed.
+pub async fn get() -> Result<Installation, WextError> {
+    if let Some(ensure) = ENSURE.with_borrow(Clone::clone) {
+        return ensure.await;
+    }
+    if let Some(installation) = current() {
+        return Ok(installation);
+    }
+    Ok(messaging::send(GET_MESSAGE, &()).await?)
+}
+
+/// The install ID. See [`get`].
+pub async fn id() -> Result<String, WextError> {
+    Ok(get().await?.id)
+}
+
+/// Load the record and follow changes to it. Called by [`crate::entry::start`].
+pub(crate) async fn load() {
+    // Offscreen documents have no storage.
+    if browser::storage_local().is_undefined() {
+        return;
+    }
+    match Area::Local.get(INSTALLATION_KEY).await {
+        Ok(installation) => CURRENT.set(installation),
+        Err(e) => warn!(format!("Failed to load the installation record: {e}")),
+    }
+    Area::Local.on_change(INSTALLATION_KEY, |installation: Option<Installation>| {
+        CURRENT.set(installation);
+    });
+}
+
+/// Background only: create the record on first run, or add the running version.
+async fn ensure() -> Result<Installation, WextError> {
+    let now = SystemClock.now();
+    let version = browser::extension_version();
+    let stored: Option<Installation> = Area::Local.get(INSTALLATION_KEY).await?;
+    let mut installation = match stored {
+        Some(installation) => installation,
+        None => {
+            // A fresh install has no data of its own yet, only what runtime modules
+            // may have written while starting up. Anything else predates the record.
+            let existing = Area::Local.get_all().await?;
+            Installation {
+                id: random_uuid(),
+                installed_at: now,
+                backfilled: existing.keys().any(|key| !key.starts_with("wext.")),
+                versions: Vec::new(),
+            }
+        }
+    };
+    if installation.version() == Some(version.as_str()) {
+        return Ok(installation);
+    }
+    installation
+        .versions
+        .push(VersionRecord { version, at: now });
+    Area::Local.set(INSTALLATION_KEY, &installation).await?;
+    CURRENT.set(Some(installation.clone()));
+    Ok(installation)
+}
+
+#[wasm_bindgen]
+extern "C" {
+    /// `crypto.randomUUID()`: a random version 4 UUID, from a secure random source.
+    #[wasm_bindgen(js_namespace = crypto, js_name = randomUUID)]
+    fn random_uuid() -> String;
+}
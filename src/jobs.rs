@@ -0,0 +1,348 @@
+//! Durable job queue for the background script.
+//!
+//! Jobs are written to `storage.local` as soon as they're enqueued, so a MV3 service
+//! worker being killed mid-flight doesn't lose them. On startup, [`install`] reloads
+//! the queue, resets any jobs that were running when the worker died, and resumes
+//! execution. Failed jobs are retried with exponential backoff, and an alarm is kept
+//! at the next due time so that a worker that has been shut down gets woken up to run
+//! delayed retries.
+//!
+//! Jobs only run in the background script, which owns the queue: [`enqueue`] from
+//! other contexts sends the job there. Any context can inspect the queue with [`list`]
+//! and [`on_change`].
+
+use std::{cell::RefCell, collections::HashMap, future::Future, pin::Pin, rc::Rc};
+
+use gloo_console::{error, warn};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    alarms::Wakeup,
+    clock::{Clock, SystemClock},
+    error::WextError,
+    health, messaging,
+    retry::Backoff,
+    storage,
+};
+
+/// Storage key the queue is persisted under.
+pub const STORAGE_KEY: &str = "wext.jobs";
+
+/// Alarm used to wake the worker up for delayed jobs.
+const ALARM_NAME: &str = "wext.jobs";
+/// Message name used by other contexts to enqueue a job in the background.
+const ENQUEUE_MESSAGE: &str = "wext.jobs.enqueue";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JobState {
+    /// Waiting for `run_at` to pass and a free concurrency slot.
+    Pending,
+    /// Currently executing. Reset to `Pending` if the worker restarts.
+    Running,
+    /// Ran out of attempts. Kept around for inspection until removed.
+    Failed,
+}
+
+/// A single persisted job. Completed jobs are removed from the queue.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Job {
+    pub id: String,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    pub state: JobState,
+    pub attempts: u32,
+    pub max_attempts: u32,
+    /// Milliseconds since the epoch.
+    pub created_at: f64,
+    /// Milliseconds since the epoch. The job won't start before this.
+    pub run_at: f64,
+    pub last_error: Option<String>,
+}
+
+/// Queue-wide execution settings.
+#[derive(Debug, Clone)]
+pub struct QueueConfig {
+    /// Maximum number of jobs running at once.
+    pub concurrency: usize,
+    /// Attempts per job before it's marked as failed.
+    pub max_attempts: u32,
+    /// Delay between attempts of the same job.
+    pub backoff: Backoff,
+}
+
+impl Default for QueueConfig {
+    fn default() -> Self {
+        QueueConfig {
+            concurrency: 2,
+            max_attempts: 5,
+            backoff: Backoff {
+                base_delay_ms: 1_000.0,
+                max_delay_ms: 10.0 * 60.0 * 1_000.0,
+            },
+        }
+    }
+}
+
+/// A job sent to the background by [`enqueue`].
+#[derive(Serialize, Deserialize)]
+struct Enqueue {
+    kind: String,
+    payload: serde_json::Value,
+}
+
+type Handler = Rc<dyn Fn(serde_json::Value) -> Pin<Box<dyn Future<Output = Result<(), String>>>>>;
+
+#[derive(Default)]
+struct Queue {
+    config: QueueConfig,
+    jobs: Vec<Job>,
+    handlers: HashMap<String, Handler>,
+    running: usize,
+    loaded: bool,
+}
+
+thread_local! {
+    static QUEUE: RefCell<Queue> = RefCell::default();
+    /// Wakes the worker up for delayed jobs. Set by [`install`], so only in the
+    /// background.
+    static WAKEUP: RefCell<Option<Wakeup>> = const { RefCell::new(None) };
+}
+
+/// Override the default [`QueueConfig`]. Call before [`install`].
+pub fn configure(config: QueueConfig) {
+    QUEUE.with_borrow_mut(|queue| queue.config = config);
+}
+
+/// Register the handler for jobs of `kind`.
+///
+/// Payloads that fail to deserialize count as a failed attempt.
+pub fn register<P, F, Fut>(kind: &str, handler: F)
+where
+    P: DeserializeOwned + 'static,
+    F: Fn(P) -> Fut + 'static,
+    Fut: Future<Output = Result<(), String>> + 'static,
+{
+    let handler = Rc::new(handler);
+    let handler: Handler = Rc::new(move |payload| {
+        let handler = handler.clone();
+        Box::pin(async move {
+            let payload = serde_json::from_value(payload).map_err(|e| e.to_string())?;
+            handler(payload).await
+        })
+    });
+    QUEUE.with_borrow_mut(|queue| queue.handlers.insert(kind.to_string(), handler));
+}
+
+/// Set up the queue in the background script, and take jobs enqueued by other
+/// contexts.
+///
+/// Must be called synchronously during startup, so the alarm listener is registered
+/// before the worker finishes its first turn, and before [`messaging::install`].
+pub fn install() {
+    WAKEUP.set(Some(Wakeup::browser(ALARM_NAME, pump)));
+    messaging::handle(ENQUEUE_MESSAGE, |job: Enqueue, _| async move {
+        add(&job.kind, job.payload).await.map_err(|e| e.to_string())
+    });
+    spawn_local(async {
+        if let Err(e) = load().await {
+            error!("Failed to load job queue:", e);
+        }
+    });
+}
+
+/// Add a job to the queue, returning its id. Outside the background, the job is sent
+/// there.
+pub async fn enqueue<P: Serialize>(kind: &str, payload: &P) -> Result<String, WextError> {
+    let payload = serde_json::to_value(payload).map_err(|e| e.to_string())?;
+    if WAKEUP.with_borrow(Option::is_none) {
+        let job = Enqueue {
+            kind: kind.to_string(),
+            payload,
+        };
+        return Ok(messaging::send(ENQUEUE_MESSAGE, &job).await?);
+    }
+    add(kind, payload).await
+}
+
+/// Add a job to the background's queue.
+async fn add(kind: &str, payload: serde_json::Value) -> Result<String, WextError> {
+    load().await?;
+
+    let now = SystemClock.now();
+    let id = format!(
+        "{:x}-{:08x}",
+        now as u64,
+        (js_sys::Math::random() * u32::MAX as f64) as u32
+    );
+    QUEUE.with_borrow_mut(|queue| {
+        let max_attempts = queue.config.max_attempts;
+        queue.jobs.push(Job {
+            id: id.clone(),
+            kind: kind.to_string(),
+            payload,
+            state: JobState::Pending,
+            attempts: 0,
+            max_attempts,
+            created_at: now,
+            run_at: now,
+            last_error: None,
+        });
+    });
+    persist();
+    pump();
+    Ok(id)
+}
+
+/// Read the persisted queue. Usable from any extension context.
+pub async fn list() -> Result<Vec<Job>, WextError> {
+    Ok(storage::get(STORAGE_KEY).await?.unwrap_or_default())
+}
+
+/// Call `callback` with the full queue whenever it changes.
+pub fn on_change(mut callback: impl FnMut(Vec<Job>) + 'static) {
+    storage::on_change(STORAGE_KEY, move |jobs: Option<Vec<Job>>| {
+        callback(jobs.unwrap_or_default())
+    });
+}
+
+/// Load the persisted queue once, merging in anything enqueued in the meantime.
+async fn load() -> Result<(), WextError> {
+    if QUEUE.with_borrow(|queue| queue.loaded) {
+        return Ok(());
+    }
+    let mut stored: Vec<Job> = storage::get(STORAGE_KEY).await?.unwrap_or_default();
+    let first = QUEUE.with_borrow_mut(|queue| {
+        if queue.loaded {
+            return false;
+        }
+        for job in &mut stored {
+            // Anything still marked as running was interrupted by the worker dying.
+            if job.state == JobState::Running {
+                job.state = JobState::Pending;
+                job.last_error = Some("Interrupted by worker restart".to_string());
+            }
+        }
+        stored.append(&mut queue.jobs);
+        queue.jobs = stored;
+        queue.loaded = true;
+        true
+    });
+    if first {
+        persist();
+        pump();
+    }
+    Ok(())
+}
+
+/// Write the in-memory queue back to storage.
+///
+/// The in-memory copy is authoritative within the background script, and
+/// `storage.set` calls are applied in order, so snapshots never go backwards.
+fn persist() {
+    let jobs = QUEUE.with_borrow(|queue| queue.jobs.clone());
+    spawn_local(async move {
+        if let Err(e) = storage::set(STORAGE_KEY, &jobs).await {
+            error!("Failed to persist job queue:", e);
+        }
+    });
+}
+
+/// Start as many due jobs as the concurrency limit allows, then schedule a wake-up
+/// for the next job that isn't due yet.
+fn pump() {
+    let now = SystemClock.now();
+    let (started, next_due) = QUEUE.with_borrow_mut(|queue| {
+        let mut started = Vec::new();
+        if !queue.loaded {
+            return (started, None);
+        }
+        for job in &mut queue.jobs {
+            if queue.running >= queue.config.concurrency {
+                break;
+            }
+            if job.state != JobState::Pending || job.run_at > now {
+                continue;
+            }
+            let Some(handler) = queue.handlers.get(&job.kind) else {
+                warn!(format!("No handler registered for job kind {:?}", job.kind));
+                continue;
+            };
+            job.state = JobState::Running;
+            job.attempts += 1;
+            queue.running += 1;
+            started.push((job.id.clone(), job.payload.clone(), handler.clone()));
+        }
+
+        let next_due = queue
+            .jobs
+            .iter()
+            .filter(|job| job.state == JobState::Pending && job.run_at > now)
+            .map(|job| job.run_at)
+            .min_by(f64::total_cmp);
+        (started, next_due)
+    });
+
+    if !started.is_empty() {
+        persist();
+    }
+    for (id, payload, handler) in started {
+        spawn_local(async move {
+            let result = handler(payload).await;
+            finish(&id, result);
+        });
+    }
+    if let Some(when) = next_due {
+        schedule_wake(when);
+    }
+}
+
+/// Record the outcome of a job run, scheduling a retry if attempts remain.
+fn finish(id: &str, result: Result<(), String>) {
+    QUEUE.with_borrow_mut(|queue| {
+        queue.running -= 1;
+        let Some(index) = queue.jobs.iter().position(|job| job.id == id) else {
+            return;
+        };
+        match result {
+            Ok(()) => {
+                queue.jobs.remove(index);
+            }
+            Err(e) => {
+                let delay = queue.config.backoff.delay_ms(queue.jobs[index].attempts);
+                let job = &mut queue.jobs[index];
+                let message = format!(
+                    "Job {} ({}) failed on attempt {}: {e}",
+                    job.id, job.kind, job.attempts
+                );
+                warn!(&message);
+                health::record_error("jobs", message);
+                job.last_error = Some(e);
+                if job.attempts >= job.max_attempts {
+                    job.state = JobState::Failed;
+                } else {
+                    job.state = JobState::Pending;
+                    job.run_at = SystemClock.now() + delay;
+                }
+            }
+        }
+    });
+    persist();
+    pump();
+}
+
+/// Wake up at `when`, both with a timer (for while the worker stays alive) and an
+/// alarm (for when it doesn't).
+fn schedule_wake(when: f64) {
+    let Some(wakeup) = WAKEUP.with_borrow(Clone::clone) else {
+        return;
+    };
+    spawn_local(async move {
+        if let Err(e) = wakeup.schedule(when).await {
+            let message = format!("Failed to schedule job queue alarm: {e}");
+            error!(&message);
+            health::record_error("jobs", message);
+        }
+    });
+}
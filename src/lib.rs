@@ -1,3 +1,63 @@
 mod background;
+mod debug;
+mod export;
+mod farewell;
+mod inspector;
+mod newtab;
 mod options;
 mod popup;
+mod update;
+
+pub mod a11y;
+pub mod alarms;
+pub mod audio;
+pub mod autosize;
+pub mod blobs;
+pub mod browser;
+pub mod capture;
+pub mod clock;
+pub mod content;
+pub mod diagnostics;
+pub mod dialog;
+pub mod downloads;
+pub mod entry;
+pub mod error;
+pub mod experiments;
+pub mod fetch;
+pub mod flags;
+pub mod format;
+pub mod forms;
+pub mod frames;
+pub mod health;
+pub mod hotkeys;
+pub mod i18n;
+pub mod icon;
+pub mod idb;
+pub mod installation;
+pub mod jobs;
+pub mod lifecycle;
+pub mod loading;
+pub mod match_pattern;
+pub mod messaging;
+#[cfg(any(test, feature = "mock"))]
+pub mod mock;
+pub mod observe;
+pub mod pages;
+pub mod ports;
+pub mod print;
+pub mod reader;
+pub mod registry;
+pub mod retry;
+pub mod sessions;
+pub mod settings;
+pub mod storage;
+pub mod surface;
+pub mod tab_groups;
+pub mod tabs;
+pub mod toast;
+pub mod translate;
+pub mod tts;
+pub mod ui_state;
+pub mod uninstall;
+pub mod virtual_list;
+pub mod windows;
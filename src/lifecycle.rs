@@ -0,0 +1,91 @@
+//! Routing `runtime.onInstalled` to per-reason handlers.
+//!
+//! Several features care about installs and updates (re-injecting content scripts,
+//! showing release notes, migrations, ...). Rather than each registering its own
+//! `onInstalled` listener, they register a handler here, and the background calls
+//! [`install`] once during startup.
+
+use std::{cell::RefCell, rc::Rc};
+
+use js_sys::Reflect;
+use wasm_bindgen::prelude::*;
+
+use crate::browser;
+
+type Handler = Rc<dyn Fn(&Details)>;
+
+/// Why `onInstalled` fired.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Reason {
+    Install,
+    /// The extension was updated (or reloaded in development).
+    Update {
+        previous_version: String,
+    },
+    BrowserUpdate,
+    /// Something this module doesn't know about yet.
+    Other(String),
+}
+
+/// What was installed, as passed to the handlers.
+#[derive(Debug, Clone)]
+pub struct Details {
+    pub reason: Reason,
+    /// The version now installed.
+    pub version: String,
+}
+
+impl Details {
+    fn from_js(details: &JsValue) -> Self {
+        let get = |key: &str| {
+            Reflect::get(details, &key.into())
+                .ok()
+                .and_then(|value| value.as_string())
+                .unwrap_or_default()
+        };
+        let reason = match get("reason").as_str() {
+            "install" => Reason::Install,
+            "update" => Reason::Update {
+                previous_version: get("previousVersion"),
+            },
+            "chrome_update" | "browser_update" => Reason::BrowserUpdate,
+            other => Reason::Other(other.to_string()),
+        };
+        Details {
+            reason,
+            version: browser::extension_version(),
+        }
+    }
+}
+
+thread_local! {
+    static HANDLERS: RefCell<Vec<Handler>> = RefCell::default();
+}
+
+/// Call `handler` for every `onInstalled` event. Register before calling [`install`].
+pub fn on_installed(handler: impl Fn(&Details) + 'static) {
+    HANDLERS.with_borrow_mut(|handlers| handlers.push(Rc::new(handler)));
+}
+
+/// Call `handler` with the previous version when the extension is updated to a
+/// different version.
+pub fn on_update(handler: impl Fn(&str) + 'static) {
+    on_installed(move |details| {
+        if let Reason::Update { previous_version } = &details.reason {
+            if *previous_version != details.version {
+                handler(previous_version);
+            }
+        }
+    });
+}
+
+/// Register the `onInstalled` listener. Must be called synchronously during startup.
+pub fn install() {
+    browser::listen(&browser::runtime().on_installed(), |details, _| {
+        let details = Details::from_js(&details);
+        let handlers = HANDLERS.with_borrow(|handlers| handlers.clone());
+        for handler in handlers {
+            handler(&details);
+        }
+    });
+}
@@ -0,0 +1,64 @@
+//! The page's loading bar.
+//!
+//! With Trunk's initializer (`initializer.js`, set with `data-initializer` in
+//! index.html), extension pages show a loading bar while the wasm downloads, until
+//! the entry function returns. An entry function with slow startup work of its own,
+//! e.g. loading data before mounting, can keep reporting progress on the same bar:
+//!
+//! ```ignore
+//! #[wext_entry(page)]
+//! pub async fn report_page() {
+//!     loading::set_progress(0.2);
+//!     let data = load().await;
+//!     loading::set_progress(0.8);
+//!     mount_to_body(move || view! { .. });
+//! }
+//! ```
+//!
+//! [`done`] also removes the page's skeleton markup (see wextrunk), for pages that
+//! mount early and keep loading afterwards.
+//!
+//! Without the initializer, or outside pages, these do nothing.
+
+use leptos::prelude::document;
+use wasm_bindgen::JsCast;
+use web_sys::{Element, HtmlElement};
+
+/// The id of the bar `initializer.js` adds.
+const LOADING_ID: &str = "wext-loading";
+/// The selector of the skeletons wextrunk adds.
+const SKELETON_SELECTOR: &str = "[data-wext-skeleton]";
+
+/// Show `fraction` (from 0 to 1) of the startup work as done.
+pub fn set_progress(fraction: f64) {
+    let Some(bar) = document().get_element_by_id(LOADING_ID) else {
+        return;
+    };
+    let percent = (fraction.clamp(0.0, 1.0) * 100.0).round();
+    let _ = bar.set_attribute("aria-valuenow", &percent.to_string());
+    if let Some(fill) = bar
+        .first_element_child()
+        .and_then(|fill| fill.dyn_into::<HtmlElement>().ok())
+    {
+        let _ = fill.style().set_property("width", &format!("{percent}%"));
+    }
+}
+
+/// Remove the bar and the skeleton before the entry function returns, e.g. once the
+/// page is usable while it keeps working in the background.
+pub fn done() {
+    let document = document();
+    if let Some(bar) = document.get_element_by_id(LOADING_ID) {
+        bar.remove();
+    }
+    if let Ok(skeletons) = document.query_selector_all(SKELETON_SELECTOR) {
+        for index in 0..skeletons.length() {
+            if let Some(skeleton) = skeletons
+                .item(index)
+                .and_then(|node| node.dyn_into::<Element>().ok())
+            {
+                skeleton.remove();
+            }
+        }
+    }
+}
@@ -0,0 +1,495 @@
+//! Match patterns, as used by content scripts, host permissions and
+//! `declarativeNetRequest`.
+//!
+//! A [`MatchPattern`] is parsed and validated the way browsers do it, so a typo fails
+//! here with a useful error instead of when the pattern is registered (where Chrome
+//! rejects the whole registration). Patterns serialize as their string form.
+//!
+//! ```ignore
+//! let pattern: MatchPattern = "https://*.example.com/docs/*".parse()?;
+//! assert!(pattern.matches("https://www.example.com/docs/intro?lang=en"));
+//! ```
+
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The pattern that matches every URL with a supported scheme.
+pub const ALL_URLS: &str = "<all_urls>";
+
+/// Schemes a pattern can name explicitly.
+const SCHEMES: &[&str] = &["http", "https", "ws", "wss", "ftp", "file"];
+
+/// Why a pattern is invalid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PatternError {
+    /// There's no `://` after the scheme.
+    MissingSchemeSeparator,
+    /// The scheme isn't one patterns can use.
+    InvalidScheme(String),
+    /// The host isn't valid, or is missing. Only `file` patterns have no host.
+    InvalidHost(String),
+    /// The port isn't a number or `*`.
+    InvalidPort(String),
+    /// There's no path; use `/*` to match every path.
+    MissingPath,
+}
+
+impl fmt::Display for PatternError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatternError::MissingSchemeSeparator => write!(f, "missing \"://\" after the scheme"),
+            PatternError::InvalidScheme(scheme) => write!(
+                f,
+                "invalid scheme {scheme:?}; expected \"*\" or one of {}",
+                SCHEMES.join(", ")
+            ),
+            PatternError::InvalidHost(host) if host.contains('*') => write!(
+                f,
+                "invalid host {host:?}; \"*\" may only be the whole host or a leading \"*.\""
+            ),
+            PatternError::InvalidHost(host) if host.is_empty() => {
+                write!(f, "missing host; only file patterns have none")
+            }
+            PatternError::InvalidHost(host) => write!(f, "invalid host {host:?}"),
+            PatternError::InvalidPort(port) => write!(f, "invalid port {port:?}"),
+            PatternError::MissingPath => write!(f, "missing path; use \"/*\" to match any path"),
+        }
+    }
+}
+
+impl std::error::Error for PatternError {}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Scheme {
+    /// `*`: http or https.
+    Any,
+    Exact(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Host {
+    /// `*`: any host.
+    Any,
+    /// `*.example.com`: the domain and all its subdomains.
+    Subdomains(String),
+    Exact(String),
+}
+
+/// A parsed match pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MatchPattern {
+    source: String,
+    /// `None` for `<all_urls>`.
+    parts: Option<Parts>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct Parts {
+    scheme: Scheme,
+    host: Host,
+    /// `None` matches any port, as does leaving the port out.
+    port: Option<u16>,
+    path: String,
+}
+
+impl MatchPattern {
+    /// Parse and validate a pattern.
+    pub fn parse(pattern: &str) -> Result<Self, PatternError> {
+        if pattern == ALL_URLS {
+            return Ok(MatchPattern {
+                source: pattern.to_string(),
+                parts: None,
+            });
+        }
+        let (scheme, rest) = pattern
+            .split_once("://")
+            .ok_or(PatternError::MissingSchemeSeparator)?;
+        let scheme = match scheme {
+            "*" => Scheme::Any,
+            scheme if SCHEMES.contains(&scheme) => Scheme::Exact(scheme.to_string()),
+            scheme => return Err(PatternError::InvalidScheme(scheme.to_string())),
+        };
+        let (authority, path) = rest
+            .find('/')
+            .map(|index| rest.split_at(index))
+            .ok_or(PatternError::MissingPath)?;
+        let (host, port) = match authority.rsplit_once(':') {
+            // The colons of an IPv6 address aren't a port.
+            Some((_, port)) if port.ends_with(']') => (authority, None),
+            Some((host, "*")) => (host, None),
+            Some((host, port)) => (
+                host,
+                Some(
+                    port.parse()
+                        .map_err(|_| PatternError::InvalidPort(port.to_string()))?,
+                ),
+            ),
+            None => (authority, None),
+        };
+
+        let is_file = scheme == Scheme::Exact("file".to_string());
+        if is_file != host.is_empty() {
+            return Err(PatternError::InvalidHost(host.to_string()));
+        }
+        let host = if is_file {
+            Host::Exact(String::new())
+        } else if host == "*" {
+            Host::Any
+        } else if let Some(domain) = host.strip_prefix("*.") {
+            Host::Subdomains(valid_host(domain)?.to_ascii_lowercase())
+        } else {
+            Host::Exact(valid_host(host)?.to_ascii_lowercase())
+        };
+
+        Ok(MatchPattern {
+            source: pattern.to_string(),
+            parts: Some(Parts {
+                scheme,
+                host,
+                port,
+                path: path.to_string(),
+            }),
+        })
+    }
+
+    /// Whether `url` matches. The path is matched against the URL's path and query,
+    /// but not its fragment. Unparseable URLs never match.
+    pub fn matches(&self, url: &str) -> bool {
+        let Some(url) = Url::parse(url) else {
+            return false;
+        };
+        let Some(parts) = &self.parts else {
+            return SCHEMES.contains(&url.scheme.as_str());
+        };
+        let scheme_matches = match &parts.scheme {
+            Scheme::Any => url.scheme == "http" || url.scheme == "https",
+            Scheme::Exact(scheme) => url.scheme == *scheme,
+        };
+        let host_matches = match &parts.host {
+            Host::Any => true,
+            Host::Subdomains(domain) => {
+                url.host == *domain
+                    || url
+                        .host
+                        .strip_suffix(domain.as_str())
+                        .is_some_and(|prefix| prefix.ends_with('.'))
+            }
+            Host::Exact(host) => url.host == *host,
+        };
+        let port_matches = parts
+            .port
+            .is_none_or(|port| url.port.or(default_port(&url.scheme)) == Some(port));
+        scheme_matches && host_matches && port_matches && glob(&parts.path, &url.path)
+    }
+
+    /// Whether this is `<all_urls>`.
+    pub fn is_all_urls(&self) -> bool {
+        self.parts.is_none()
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.source
+    }
+}
+
+/// Check a host has no wildcards left, and nothing but host characters.
+fn valid_host(host: &str) -> Result<&str, PatternError> {
+    let valid = !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_alphanumeric() || matches!(c, '-' | '.' | '_' | '[' | ']' | ':'));
+    if valid {
+        Ok(host)
+    } else {
+        Err(PatternError::InvalidHost(host.to_string()))
+    }
+}
+
+/// Match `text` against `pattern`, where `*` matches any run of characters.
+fn glob(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.as_bytes(), text.as_bytes());
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and how much of the text it had consumed.
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && pattern[p] == b'*' {
+            star = Some((p, t));
+            p += 1;
+        } else if p < pattern.len() && pattern[p] == text[t] {
+            p += 1;
+            t += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+/// The parts of a URL patterns match against.
+struct Url {
+    scheme: String,
+    host: String,
+    port: Option<u16>,
+    /// Path and query.
+    path: String,
+}
+
+impl Url {
+    fn parse(url: &str) -> Option<Url> {
+        let url = url.split('#').next()?;
+        let (scheme, rest) = url.split_once("://")?;
+        let (authority, path) = match rest.find(['/', '?']) {
+            Some(index) => rest.split_at(index),
+            None => (rest, "/"),
+        };
+        let path = if path.starts_with('?') {
+            format!("/{path}")
+        } else {
+            path.to_string()
+        };
+        // Drop any user info.
+        let authority = authority.rsplit('@').next()?;
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (host, Some(port.parse().ok()?)),
+            _ => (authority, None),
+        };
+        Some(Url {
+            scheme: scheme.to_ascii_lowercase(),
+            host: host.to_ascii_lowercase(),
+            port,
+            path,
+        })
+    }
+}
+
+fn default_port(scheme: &str) -> Option<u16> {
+    match scheme {
+        "http" | "ws" => Some(80),
+        "https" | "wss" => Some(443),
+        "ftp" => Some(21),
+        _ => None,
+    }
+}
+
+impl fmt::Display for MatchPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl FromStr for MatchPattern {
+    type Err = PatternError;
+
+    fn from_str(pattern: &str) -> Result<Self, Self::Err> {
+        MatchPattern::parse(pattern)
+    }
+}
+
+impl Serialize for MatchPattern {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.source)
+    }
+}
+
+impl<'de> Deserialize<'de> for MatchPattern {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let pattern = String::deserialize(deserializer)?;
+        MatchPattern::parse(&pattern).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MatchPattern, PatternError};
+
+    /// Patterns, URLs and whether they match, as in Chrome and Firefox.
+    const MATCHES: &[(&str, &str, bool)] = &[
+        // `*` is http or https, nothing else.
+        ("*://example.com/*", "http://example.com/", true),
+        ("*://example.com/*", "https://example.com/a", true),
+        ("*://example.com/*", "ws://example.com/", false),
+        ("*://example.com/*", "ftp://example.com/", false),
+        ("*://example.com/*", "file:///example.com/", false),
+        ("https://example.com/*", "http://example.com/", false),
+        ("wss://example.com/*", "wss://example.com/socket", true),
+        ("file:///*", "file:///home/user/notes.txt", true),
+        ("file:///*", "https://example.com/", false),
+        // Schemes and hosts are case-insensitive, paths aren't.
+        ("https://example.com/*", "HTTPS://Example.COM/", true),
+        (
+            "https://example.com/Docs",
+            "https://example.com/docs",
+            false,
+        ),
+        // `*.` is the domain and its subdomains.
+        ("https://*.example.com/*", "https://example.com/", true),
+        ("https://*.example.com/*", "https://www.example.com/", true),
+        ("https://*.example.com/*", "https://a.b.example.com/", true),
+        ("https://*.example.com/*", "https://notexample.com/", false),
+        (
+            "https://*.example.com/*",
+            "https://example.com.evil.test/",
+            false,
+        ),
+        ("https://example.com/*", "https://www.example.com/", false),
+        ("https://*/*", "https://anything.test/", true),
+        ("http://[::1]/*", "http://[::1]/", true),
+        ("http://[::1]:8080/*", "http://[::1]:8080/api", true),
+        // `<all_urls>` is every URL with a scheme patterns support.
+        ("<all_urls>", "https://example.com/", true),
+        ("<all_urls>", "ws://localhost:8080/", true),
+        ("<all_urls>", "file:///etc/hosts", true),
+        ("<all_urls>", "chrome://extensions/", false),
+        ("<all_urls>", "about:blank", false),
+        // Paths are globs over the path and query, but not the fragment.
+        ("https://example.com/", "https://example.com/", true),
+        ("https://example.com/", "https://example.com", true),
+        ("https://example.com/", "https://example.com/a", false),
+        (
+            "https://example.com/docs/*",
+            "https://example.com/docs/",
+            true,
+        ),
+        (
+            "https://example.com/docs/*",
+            "https://example.com/docs/a/b",
+            true,
+        ),
+        (
+            "https://example.com/docs/*",
+            "https://example.com/doc",
+            false,
+        ),
+        (
+            "https://example.com/*.pdf",
+            "https://example.com/a/b.pdf",
+            true,
+        ),
+        (
+            "https://example.com/*.pdf",
+            "https://example.com/b.pdf.html",
+            false,
+        ),
+        (
+            "https://example.com/a*b*c",
+            "https://example.com/a-b-b-c",
+            true,
+        ),
+        (
+            "https://example.com/a*b*c",
+            "https://example.com/a-c-b",
+            false,
+        ),
+        (
+            "https://example.com/find?q=*",
+            "https://example.com/find?q=rust",
+            true,
+        ),
+        ("https://example.com/*", "https://example.com?q=1", true),
+        (
+            "https://example.com/page",
+            "https://example.com/page#top",
+            true,
+        ),
+        // No port, or `*`, is any port; a port matches the scheme's default too.
+        ("https://example.com/*", "https://example.com:8443/", true),
+        ("https://example.com:*/*", "https://example.com:8443/", true),
+        (
+            "https://example.com:8443/*",
+            "https://example.com:8443/",
+            true,
+        ),
+        ("https://example.com:8443/*", "https://example.com/", false),
+        ("https://example.com:443/*", "https://example.com/", true),
+        // User info isn't part of the host.
+        ("https://example.com/*", "https://user@example.com/", true),
+        ("https://example.com/*", "example.com", false),
+    ];
+
+    #[test]
+    fn matches_like_browsers() {
+        for &(pattern, url, expected) in MATCHES {
+            let parsed = MatchPattern::parse(pattern).unwrap();
+            assert_eq!(parsed.matches(url), expected, "{pattern} against {url}");
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_patterns() {
+        let invalid = [
+            ("example.com/*", PatternError::MissingSchemeSeparator),
+            (
+                "chrome://extensions/*",
+                PatternError::InvalidScheme("chrome".to_string()),
+            ),
+            ("*//example.com/*", PatternError::MissingSchemeSeparator),
+            ("https://example.com", PatternError::MissingPath),
+            ("https:///*", PatternError::InvalidHost(String::new())),
+            (
+                "file://host/*",
+                PatternError::InvalidHost("host".to_string()),
+            ),
+            (
+                "https://www.*.com/*",
+                PatternError::InvalidHost("www.*.com".to_string()),
+            ),
+            (
+                "https://*example.com/*",
+                PatternError::InvalidHost("*example.com".to_string()),
+            ),
+            (
+                "https://exa mple.com/*",
+                PatternError::InvalidHost("exa mple.com".to_string()),
+            ),
+            (
+                "https://example.com:http/*",
+                PatternError::InvalidPort("http".to_string()),
+            ),
+            (
+                "https://example.com:70000/*",
+                PatternError::InvalidPort("70000".to_string()),
+            ),
+        ];
+        for (pattern, error) in invalid {
+            assert_eq!(MatchPattern::parse(pattern), Err(error), "{pattern}");
+        }
+    }
+
+    #[test]
+    fn describes_errors() {
+        let message = |pattern: &str| MatchPattern::parse(pattern).unwrap_err().to_string();
+        assert_eq!(
+            message("https://www.*.com/*"),
+            r#"invalid host "www.*.com"; "*" may only be the whole host or a leading "*.""#
+        );
+        assert_eq!(
+            message("https:///*"),
+            "missing host; only file patterns have none"
+        );
+        assert_eq!(
+            message("https://example.com"),
+            r#"missing path; use "/*" to match any path"#
+        );
+    }
+
+    #[test]
+    fn round_trips_through_strings() {
+        let pattern: MatchPattern = "https://*.example.com/*".parse().unwrap();
+        assert_eq!(pattern.to_string(), "https://*.example.com/*");
+        assert!(!pattern.is_all_urls());
+        assert!(MatchPattern::parse("<all_urls>").unwrap().is_all_urls());
+
+        let json = serde_json::to_string(&pattern).unwrap();
+        assert_eq!(json, r#""https://*.example.com/*""#);
+        assert_eq!(
+            serde_json::from_str::<MatchPattern>(&json).unwrap(),
+            pattern
+        );
+        assert!(serde_json::from_str::<MatchPattern>(r#""example.com""#).is_err());
+    }
+}
@@ -0,0 +1,1293 @@
+//! Request/response messaging over `runtime.sendMessage`.
+//!
+//! Requests are typed: a [`Request`] names its message and the response its handler
+//! returns, so a sender can't get either wrong. The background registers a handler
+//! with [`handle_request`], and pages and content scripts call [`request`]. A
+//! feature with several operations of the same response type can make them one enum,
+//! handled with a `match`:
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! pub enum NoteAction {
+//!     Pin(Key),
+//!     Delete(Key),
+//! }
+//!
+//! impl Request for NoteAction {
+//!     const NAME: &'static str = "notes.action";
+//!     type Response = ();
+//! }
+//!
+//! // In the background:
+//! messaging::handle_request(|action: NoteAction, _| async move {
+//!     match action {
+//!         NoteAction::Pin(id) => pin(id).await,
+//!         NoteAction::Delete(id) => delete(id).await,
+//!     }
+//! });
+//! // In the popup:
+//! messaging::request(&NoteAction::Pin(id)).await?;
+//! ```
+//!
+//! The untyped [`handle`] and [`send`], which take the name and types at each call,
+//! are what they're built on. Handler errors come back as [`MessageError::Handler`].
+//!
+//! Every message is wrapped in an [`Envelope`] carrying the sender's
+//! [`PROTOCOL_VERSION`]. After an extension update, content scripts injected by the
+//! previous version can keep running (and messaging) alongside the new background
+//! script, so the background refuses envelopes from a different protocol version with
+//! [`Reply::VersionMismatch`] instead of feeding them to handlers written for the new
+//! format.
+//!
+//! Content scripts can check compatibility up front with [`negotiate`], and react to a
+//! mismatch with [`resolve_mismatch`], which either asks the background to inject the
+//! current content scripts into the tab or shows the user a "please reload" notice.
+//!
+//! Requests that serialize to more than [`CHUNK_BYTES`] (e.g. screenshots or extracted
+//! page content) don't go through `sendMessage`, which has a size limit and handles
+//! each message in one piece. [`send`] transfers them over a port instead, in chunks:
+//! the background acknowledges each chunk as it stores it, and the sender keeps at most
+//! [`WINDOW`] chunks unacknowledged, so a busy background isn't flooded. Once all
+//! chunks have arrived, the background reassembles the request, calls the handler and
+//! replies over the same port. Responses are sent in one piece.
+//!
+//! Requests can be given a timeout with [`Channel::with_timeout`], after which they
+//! fail with [`MessageError::Timeout`]. A request whose future is dropped before it's
+//! answered, or times out, is cancelled: the background aborts its handler, by
+//! dropping the handler's future. While a context has requests in flight, it keeps a
+//! port to the background open, so the background also aborts them when the context
+//! goes away, e.g. when the popup is closed mid-request.
+//!
+//! Every handler has a [`SenderPolicy`], checked before the handler (or any
+//! middleware) runs. Handlers registered with [`handle`] only accept this extension's
+//! own contexts; [`handle_from`] narrows that down, e.g. to content scripts on given
+//! sites, or opens a handler to web pages and other extensions, whose messages
+//! (through `runtime.onMessageExternal`) are refused otherwise.
+//!
+//! Cross-cutting concerns (logging, tracing, access checks, metrics) go in
+//! [`middleware`] rather than in every handler. Each middleware gets the [`Call`] and
+//! decides whether, and how, to pass it on to the rest of the chain:
+//!
+//! ```ignore
+//! messaging::middleware(|call: Call, next: Next| async move {
+//!     if call.sender.tab_id.is_some() && call.name.starts_with("admin.") {
+//!         return Err("not allowed from content scripts".to_string());
+//!     }
+//!     next.run(call).await
+//! });
+//! ```
+//!
+//! Bodies are encoded as JSON by default, which turns byte buffers into arrays of
+//! numbers. A [`Channel`] can use [`Codec::Cbor`] instead, which moves them as a
+//! `Uint8Array` (mark them with `#[serde(with = "serde_bytes")]`). The codec is
+//! negotiated when the channel is opened, as a content script left over from a previous
+//! version may not understand it, and responses use the codec of their request.
+
+use std::{borrow::Cow, cell::RefCell, collections::HashMap, fmt, future::Future, rc::Rc};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver},
+    future::{self, AbortHandle, Aborted, Either, LocalBoxFuture},
+    StreamExt,
+};
+use gloo_console::{debug, warn};
+use js_sys::{Function, Reflect};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_bytes::ByteBuf;
+use serde_json::Value;
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    browser::{self, Port},
+    clock::{Clock, SystemClock},
+    content, health,
+    match_pattern::MatchPattern,
+};
+
+/// Version of the envelope and message formats. Bump this whenever a change would
+/// confuse a previously injected content script (renamed messages, changed payloads).
+pub const PROTOCOL_VERSION: u32 = 1;
+
+/// `PROTOCOL_VERSION` in the `wext_protocol` custom section of the wasm, for wextrunk's
+/// init failure panel, which reports to the background before any wasm has loaded.
+#[cfg_attr(target_family = "wasm", link_section = "wext_protocol")]
+#[used]
+static PROTOCOL_SECTION: [u8; 4] = PROTOCOL_VERSION.to_le_bytes();
+
+/// Handshake message, exempt from version checks.
+const HELLO: &str = "wext.hello";
+/// Re-injection request, exempt from version checks.
+const REINJECT: &str = "wext.reinject";
+/// Cancellation of a request in flight.
+const CANCEL: &str = "wext.cancel";
+/// Name of the ports large requests are transferred over.
+const TRANSFER_PORT: &str = "wext.transfer";
+/// Prefix of the name of the port a context keeps open while it has requests in
+/// flight, followed by the session in their IDs.
+const REQUESTS_PORT: &str = "wext.requests:";
+
+/// The largest serialized request sent in one message, and the size of the chunks
+/// larger ones are split into.
+pub const CHUNK_BYTES: usize = 256 * 1024;
+/// How many chunks of a transfer can be unacknowledged at once.
+pub const WINDOW: usize = 4;
+
+/// The codecs this build understands, preferred first.
+pub const CODECS: &[Codec] = &[Codec::Cbor, Codec::Json];
+
+/// How request and response bodies are encoded.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Codec {
+    /// As JSON-compatible objects. Understood by every version.
+    #[default]
+    Json,
+    /// As CBOR, in a `Uint8Array`.
+    Cbor,
+}
+
+/// Wire format for every message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Envelope {
+    pub protocol: u32,
+    pub name: String,
+    /// The body if it's encoded as JSON, `null` otherwise.
+    pub body: Value,
+    /// The body if it's encoded as CBOR.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bytes: Option<ByteBuf>,
+    /// Identifies the request, to cancel it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+}
+
+/// Wire format for every response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum Reply {
+    /// The body is encoded like [`Envelope`]'s.
+    Ok {
+        body: Value,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        bytes: Option<ByteBuf>,
+    },
+    Err {
+        message: String,
+    },
+    /// The receiver speaks a different protocol version. The message was not handled.
+    VersionMismatch {
+        expected: u32,
+    },
+}
+
+/// Response to the [`HELLO`] handshake.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Hello {
+    pub protocol: u32,
+    pub extension_version: String,
+    /// The codecs the receiver understands. Versions from before codecs only
+    /// understand JSON.
+    #[serde(default)]
+    pub codecs: Vec<Codec>,
+}
+
+/// A request or response body, encoded with a [`Codec`].
+#[derive(Debug, Clone, PartialEq)]
+enum Body {
+    Json(Value),
+    Cbor(Vec<u8>),
+}
+
+impl Body {
+    fn encode<T: Serialize>(codec: Codec, value: &T) -> Result<Body, String> {
+        match codec {
+            Codec::Json => serde_json::to_value(value)
+                .map(Body::Json)
+                .map_err(|e| e.to_string()),
+            Codec::Cbor => {
+                let mut bytes = Vec::new();
+                ciborium::into_writer(value, &mut bytes).map_err(|e| e.to_string())?;
+                Ok(Body::Cbor(bytes))
+            }
+        }
+    }
+
+    fn decode<T: DeserializeOwned>(self) -> Result<T, String> {
+        match self {
+            Body::Json(value) => serde_json::from_value(value).map_err(|e| e.to_string()),
+            Body::Cbor(bytes) => ciborium::from_reader(bytes.as_slice()).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn codec(&self) -> Codec {
+        match self {
+            Body::Json(_) => Codec::Json,
+            Body::Cbor(_) => Codec::Cbor,
+        }
+    }
+
+    /// The body as the `body` and `bytes` fields of an [`Envelope`] or [`Reply`].
+    fn into_wire(self) -> (Value, Option<ByteBuf>) {
+        match self {
+            Body::Json(value) => (value, None),
+            Body::Cbor(bytes) => (Value::Null, Some(ByteBuf::from(bytes))),
+        }
+    }
+
+    fn from_wire(body: Value, bytes: Option<ByteBuf>) -> Body {
+        match bytes {
+            Some(bytes) => Body::Cbor(bytes.into_vec()),
+            None => Body::Json(body),
+        }
+    }
+
+    /// The body serialized, as split into the chunks of a transfer.
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        match self {
+            Body::Json(value) => Cow::Owned(value.to_string().into_bytes()),
+            Body::Cbor(bytes) => Cow::Borrowed(bytes),
+        }
+    }
+
+    fn from_bytes(codec: Codec, bytes: Vec<u8>) -> Result<Body, String> {
+        match codec {
+            Codec::Json => serde_json::from_slice(&bytes)
+                .map(Body::Json)
+                .map_err(|e| e.to_string()),
+            Codec::Cbor => Ok(Body::Cbor(bytes)),
+        }
+    }
+}
+
+/// Why a message didn't produce a response body.
+#[derive(Debug, Clone, PartialEq)]
+pub enum MessageError {
+    /// `sendMessage` itself failed, e.g. because nothing is listening.
+    Send(String),
+    /// This content script outlived its extension (it was updated, reloaded or
+    /// disabled), so it can't message anything anymore.
+    ContextInvalidated,
+    /// The receiver speaks a different protocol version.
+    VersionMismatch { ours: u32, theirs: u32 },
+    /// No handler is registered for the message name.
+    NoHandler(String),
+    /// The handler returned an error.
+    Handler(String),
+    /// The request or response couldn't be (de)serialized.
+    Codec(String),
+    /// No response arrived within the timeout, in milliseconds.
+    Timeout(f64),
+    /// The request was cancelled before the handler answered it.
+    Cancelled,
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MessageError::Send(e) => write!(f, "failed to send message: {e}"),
+            MessageError::ContextInvalidated => write!(f, "extension context invalidated"),
+            MessageError::VersionMismatch { ours, theirs } => write!(
+                f,
+                "protocol version mismatch: this context speaks v{ours}, the receiver v{theirs}"
+            ),
+            MessageError::NoHandler(name) => write!(f, "no handler registered for {name:?}"),
+            MessageError::Handler(e) => write!(f, "handler failed: {e}"),
+            MessageError::Codec(e) => write!(f, "failed to (de)serialize message: {e}"),
+            MessageError::Timeout(ms) => write!(f, "no response within {ms} ms"),
+            MessageError::Cancelled => write!(f, "the request was cancelled"),
+        }
+    }
+}
+
+/// Information about the sender of a message, from `runtime.MessageSender`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Sender {
+    /// The sending extension's ID, missing for web pages.
+    pub id: Option<String>,
+    pub url: Option<String>,
+    /// The sending page's origin, where the browser provides it.
+    pub origin: Option<String>,
+    pub tab_id: Option<i32>,
+    pub frame_id: Option<i32>,
+}
+
+impl Sender {
+    pub(crate) fn from_js(sender: &JsValue) -> Self {
+        let get =
+            |target: &JsValue, key: &str| Reflect::get(target, &key.into()).unwrap_or_default();
+        let tab = get(sender, "tab");
+        Sender {
+            id: get(sender, "id").as_string(),
+            url: get(sender, "url").as_string(),
+            origin: get(sender, "origin").as_string(),
+            tab_id: get(&tab, "id").as_f64().map(|id| id as i32),
+            frame_id: get(sender, "frameId").as_f64().map(|id| id as i32),
+        }
+    }
+}
+
+impl Sender {
+    /// Whether the message comes from this extension.
+    pub fn is_own(&self) -> bool {
+        self.id.is_some() && self.id == browser::runtime().id()
+    }
+
+    /// Whether the message comes from one of this extension's pages (including the
+    /// background), rather than a content script.
+    pub fn is_own_page(&self) -> bool {
+        let base = browser::runtime().get_url("");
+        self.is_own()
+            && self
+                .url
+                .as_deref()
+                .is_some_and(|url| url.starts_with(&base))
+    }
+}
+
+/// Which senders a handler accepts, see [`handle_from`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum SenderPolicy {
+    /// Any of this extension's contexts: its pages and content scripts.
+    #[default]
+    Extension,
+    /// Only this extension's pages, not its content scripts.
+    ExtensionPages,
+    /// Only this extension's content scripts, in pages matching one of the patterns.
+    ContentScripts(Vec<MatchPattern>),
+    /// Web pages on one of these origins (e.g. `https://example.com`), which also need
+    /// to be listed in the manifest's `externally_connectable`.
+    WebOrigins(Vec<String>),
+    /// Other extensions, by ID.
+    Extensions(Vec<String>),
+    /// Senders that any of the policies accepts.
+    AnyOf(Vec<SenderPolicy>),
+}
+
+impl SenderPolicy {
+    pub fn allows(&self, sender: &Sender) -> bool {
+        match self {
+            SenderPolicy::Extension => sender.is_own(),
+            SenderPolicy::ExtensionPages => sender.is_own_page(),
+            SenderPolicy::ContentScripts(patterns) => {
+                sender.is_own()
+                    && !sender.is_own_page()
+                    && sender
+                        .url
+                        .as_deref()
+                        .is_some_and(|url| patterns.iter().any(|pattern| pattern.matches(url)))
+            }
+            SenderPolicy::WebOrigins(origins) => {
+                sender.id.is_none()
+                    && sender
+                        .origin
+                        .as_deref()
+                        .or_else(|| sender.url.as_deref().map(origin_of))
+                        .is_some_and(|origin| origins.iter().any(|allowed| allowed == origin))
+            }
+            SenderPolicy::Extensions(ids) => {
+                !sender.is_own() && sender.id.as_ref().is_some_and(|id| ids.contains(id))
+            }
+            SenderPolicy::AnyOf(policies) => policies.iter().any(|policy| policy.allows(sender)),
+        }
+    }
+}
+
+/// The origin of `url`, e.g. `https://example.com` for `https://example.com/a?b`.
+fn origin_of(url: &str) -> &str {
+    let Some(scheme_end) = url.find("://") else {
+        return url;
+    };
+    let rest = &url[scheme_end + 3..];
+    let end = rest
+        .find(['/', '?', '#'])
+        .map_or(url.len(), |end| scheme_end + 3 + end);
+    &url[..end]
+}
+
+type Handler = Rc<dyn Fn(Body, Sender) -> LocalBoxFuture<'static, Result<Body, String>>>;
+type Middleware = Rc<dyn Fn(Call, Next) -> LocalBoxFuture<'static, Result<Response, String>>>;
+
+/// A message on its way to its handler, as seen by [`middleware`].
+#[derive(Debug, Clone)]
+pub struct Call {
+    pub name: String,
+    pub sender: Sender,
+    body: Body,
+}
+
+impl Call {
+    pub fn codec(&self) -> Codec {
+        self.body.codec()
+    }
+
+    /// Decode a copy of the request.
+    pub fn request<T: DeserializeOwned>(&self) -> Result<T, String> {
+        self.body.clone().decode()
+    }
+}
+
+/// A handler's response, as seen by [`middleware`].
+#[derive(Debug, Clone)]
+pub struct Response {
+    body: Body,
+}
+
+impl Response {
+    /// Decode a copy of the response.
+    pub fn body<T: DeserializeOwned>(&self) -> Result<T, String> {
+        self.body.clone().decode()
+    }
+}
+
+/// The rest of the middleware chain, ending with the handler.
+pub struct Next {
+    index: usize,
+    handler: Handler,
+}
+
+impl Next {
+    /// Pass `call` on to the next middleware, or to the handler.
+    pub async fn run(self, call: Call) -> Result<Response, String> {
+        let middleware = MIDDLEWARE.with_borrow(|middleware| middleware.get(self.index).cloned());
+        match middleware {
+            Some(middleware) => {
+                let next = Next {
+                    index: self.index + 1,
+                    handler: self.handler,
+                };
+                middleware(call, next).await
+            }
+            None => {
+                let body = (self.handler)(call.body, call.sender).await?;
+                Ok(Response { body })
+            }
+        }
+    }
+}
+
+/// Wire format for the messages of a transfer over a [`TRANSFER_PORT`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+enum Transfer {
+    /// Sent first, announcing the request.
+    Start {
+        protocol: u32,
+        name: String,
+        #[serde(default)]
+        codec: Codec,
+        #[serde(default)]
+        id: Option<String>,
+        total: usize,
+    },
+    /// One part of the serialized request. Chunks are sent in order.
+    Chunk { index: usize, data: ByteBuf },
+    /// The number of chunks the receiver has stored so far.
+    Ack { received: usize },
+    /// The handler's reply, ending the transfer.
+    Reply { reply: Reply },
+}
+
+/// This context's requests in flight.
+#[derive(Default)]
+struct Outgoing {
+    /// Random, and prefixes the IDs of the requests sent while the port is connected.
+    session: String,
+    next: u64,
+    pending: usize,
+    /// Connected while requests are in flight, so the background notices when this
+    /// context goes away.
+    port: Option<Port>,
+}
+
+/// A request in flight, cancelled if it's dropped before it's answered.
+struct InFlight {
+    id: String,
+    answered: bool,
+}
+
+impl InFlight {
+    fn start() -> InFlight {
+        let id = OUTGOING.with_borrow_mut(|outgoing| {
+            if outgoing.pending == 0 {
+                outgoing.session = format!("{:x}", (js_sys::Math::random() * 1e15) as u64);
+                let name = format!("{REQUESTS_PORT}{}", outgoing.session);
+                let info = browser::object(&[("name", name.into())]);
+                // Fails in an invalidated content script, whose requests fail anyway.
+                outgoing.port = browser::runtime().connect(info).ok();
+            }
+            outgoing.pending += 1;
+            outgoing.next += 1;
+            format!("{}:{}", outgoing.session, outgoing.next)
+        });
+        InFlight {
+            id,
+            answered: false,
+        }
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        OUTGOING.with_borrow_mut(|outgoing| {
+            outgoing.pending -= 1;
+            if outgoing.pending == 0 {
+                if let Some(port) = outgoing.port.take() {
+                    port.disconnect();
+                }
+            }
+        });
+        if self.answered {
+            return;
+        }
+        let cancel = Envelope {
+            protocol: PROTOCOL_VERSION,
+            name: CANCEL.to_string(),
+            body: Value::String(self.id.clone()),
+            bytes: None,
+            id: None,
+        };
+        spawn_local(async move {
+            let _ = send_envelope(cancel).await;
+        });
+    }
+}
+
+thread_local! {
+    static HANDLERS: RefCell<HashMap<String, (SenderPolicy, Handler)>> = RefCell::default();
+    /// Outermost first.
+    static MIDDLEWARE: RefCell<Vec<Middleware>> = const { RefCell::new(Vec::new()) };
+    static OUTGOING: RefCell<Outgoing> = RefCell::default();
+    /// The requests being handled, by ID.
+    static RUNNING: RefCell<HashMap<String, AbortHandle>> = RefCell::default();
+}
+
+/// A typed request: the message it's sent as, and the response its handler returns.
+pub trait Request: Serialize + DeserializeOwned + 'static {
+    /// The message name, e.g. `notes.action`.
+    const NAME: &'static str;
+    type Response: Serialize + DeserializeOwned + 'static;
+}
+
+/// Register the handler for `R`, from this extension's contexts. Use
+/// [`handle_from`] with [`Request::NAME`] for other senders.
+pub fn handle_request<R, F, Fut>(handler: F)
+where
+    R: Request,
+    F: Fn(R, Sender) -> Fut + 'static,
+    Fut: Future<Output = Result<R::Response, String>> + 'static,
+{
+    handle(R::NAME, handler);
+}
+
+/// Register the handler for messages called `name`, from this extension's contexts.
+pub fn handle<Req, Res, F, Fut>(name: &str, handler: F)
+where
+    Req: DeserializeOwned + 'static,
+    Res: Serialize + 'static,
+    F: Fn(Req, Sender) -> Fut + 'static,
+    Fut: Future<Output = Result<Res, String>> + 'static,
+{
+    handle_from(name, SenderPolicy::Extension, handler);
+}
+
+/// Register the handler for messages called `name`, from the senders `policy` accepts.
+pub fn handle_from<Req, Res, F, Fut>(name: &str, policy: SenderPolicy, handler: F)
+where
+    Req: DeserializeOwned + 'static,
+    Res: Serialize + 'static,
+    F: Fn(Req, Sender) -> Fut + 'static,
+    Fut: Future<Output = Result<Res, String>> + 'static,
+{
+    let handler = Rc::new(handler);
+    let handler: Handler = Rc::new(move |body, sender| {
+        let handler = handler.clone();
+        Box::pin(async move {
+            let codec = body.codec();
+            let request = body.decode()?;
+            let response = handler(request, sender).await?;
+            Body::encode(codec, &response)
+        })
+    });
+    HANDLERS.with_borrow_mut(|handlers| handlers.insert(name.to_string(), (policy, handler)));
+}
+
+/// Run `middleware` around every handler call in this context. Middleware registered
+/// first runs outermost. Register it before [`install`].
+pub fn middleware<F, Fut>(middleware: F)
+where
+    F: Fn(Call, Next) -> Fut + 'static,
+    Fut: Future<Output = Result<Response, String>> + 'static,
+{
+    let middleware: Middleware = Rc::new(move |call, next| Box::pin(middleware(call, next)));
+    MIDDLEWARE.with_borrow_mut(|chain| chain.push(middleware));
+}
+
+/// Middleware that logs every call, with how long it took and whether it failed.
+pub async fn log_calls(call: Call, next: Next) -> Result<Response, String> {
+    let name = call.name.clone();
+    let from = call.sender.url.clone().unwrap_or_default();
+    let started = SystemClock.now();
+    let result = next.run(call).await;
+    let took = SystemClock.now() - started;
+    match &result {
+        Ok(_) => debug!(format!("{name} from {from}: ok in {took} ms")),
+        Err(e) => debug!(format!("{name} from {from}: failed in {took} ms: {e}")),
+    }
+    result
+}
+
+/// Start dispatching incoming messages to registered handlers.
+///
+/// Must be called synchronously during startup in the background script. Content
+/// scripts call it too if they handle messages sent with [`send_to_tab`].
+pub fn install() {
+    let listener = Closure::<dyn Fn(JsValue, JsValue, Function) -> JsValue>::new(
+        |message: JsValue, sender: JsValue, send_response: Function| {
+            let Ok(envelope) = serde_wasm_bindgen::from_value::<Envelope>(message) else {
+                // Not one of ours; let other listeners have a go.
+                return JsValue::FALSE;
+            };
+            let sender = Sender::from_js(&sender);
+            spawn_local(async move {
+                let reply = dispatch(envelope, sender).await;
+                let reply = serde_wasm_bindgen::to_value(&reply).unwrap_or_default();
+                if let Err(e) = send_response.call1(&JsValue::NULL, &reply) {
+                    warn!("Failed to send message response:", e);
+                }
+            });
+            // Keep the response channel open for the async reply.
+            JsValue::TRUE
+        },
+    );
+    browser::add_listener(
+        &browser::runtime().on_message(),
+        listener.as_ref().unchecked_ref(),
+    );
+    // Only extension pages receive messages from web pages and other extensions.
+    let external = browser::runtime().on_message_external();
+    if !external.is_undefined() {
+        browser::add_listener(&external, listener.as_ref().unchecked_ref());
+    }
+    listener.forget();
+    browser::listen(&browser::runtime().on_connect(), |port, _| {
+        let port: Port = port.unchecked_into();
+        let name = port.name();
+        if name == TRANSFER_PORT {
+            spawn_local(receive_transfer(port));
+        } else if let Some(session) = name.strip_prefix(REQUESTS_PORT) {
+            // The sender went away, or has no requests in flight anymore.
+            let prefix = format!("{session}:");
+            browser::listen(&port.on_disconnect(), move |_, _| {
+                abort(|id| id.starts_with(&prefix));
+            });
+        }
+    });
+}
+
+/// Abort the handlers of the requests whose IDs match.
+fn abort(matches: impl Fn(&str) -> bool) {
+    RUNNING.with_borrow_mut(|running| {
+        running.retain(|id, handle| {
+            let matched = matches(id);
+            if matched {
+                handle.abort();
+            }
+            !matched
+        })
+    });
+}
+
+async fn dispatch(envelope: Envelope, sender: Sender) -> Reply {
+    // Built-in messages are only for this extension.
+    if !sender.is_own() && [HELLO, REINJECT, CANCEL].contains(&envelope.name.as_str()) {
+        return Reply::Err {
+            message: MessageError::NoHandler(envelope.name).to_string(),
+        };
+    }
+    match envelope.name.as_str() {
+        HELLO => {
+            return Reply::Ok {
+                body: serde_json::to_value(Hello {
+                    protocol: PROTOCOL_VERSION,
+                    extension_version: browser::extension_version(),
+                    codecs: CODECS.to_vec(),
+                })
+                .unwrap_or_default(),
+                bytes: None,
+            };
+        }
+        REINJECT => {
+            return match reinject(&sender).await {
+                Ok(()) => Reply::Ok {
+                    body: Value::Null,
+                    bytes: None,
+                },
+                Err(message) => Reply::Err { message },
+            };
+        }
+        _ => {}
+    }
+
+    if envelope.protocol != PROTOCOL_VERSION {
+        return Reply::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+        };
+    }
+    if envelope.name == CANCEL {
+        if let Value::String(cancelled) = envelope.body {
+            abort(|id| id == cancelled);
+        }
+        return Reply::Ok {
+            body: Value::Null,
+            bytes: None,
+        };
+    }
+    let body = Body::from_wire(envelope.body, envelope.bytes);
+    call_handler(envelope.name, body, sender, envelope.id).await
+}
+
+async fn call_handler(name: String, body: Body, sender: Sender, id: Option<String>) -> Reply {
+    let Some((policy, handler)) = HANDLERS.with_borrow(|handlers| handlers.get(&name).cloned())
+    else {
+        return Reply::Err {
+            message: MessageError::NoHandler(name).to_string(),
+        };
+    };
+    if !policy.allows(&sender) {
+        let from = sender
+            .url
+            .as_deref()
+            .or(sender.id.as_deref())
+            .unwrap_or("?");
+        health::record_error(
+            "messaging",
+            format!("{name}: refused a message from {from}"),
+        );
+        // Answer as if there was no handler, so senders can't probe for them.
+        return Reply::Err {
+            message: MessageError::NoHandler(name).to_string(),
+        };
+    }
+    let call = Next { index: 0, handler }.run(Call {
+        name: name.clone(),
+        sender,
+        body,
+    });
+    let result = match id {
+        Some(id) => {
+            let (call, handle) = future::abortable(call);
+            RUNNING.with_borrow_mut(|running| running.insert(id.clone(), handle));
+            let result = call.await;
+            RUNNING.with_borrow_mut(|running| running.remove(&id));
+            match result {
+                Ok(result) => result,
+                Err(Aborted) => {
+                    return Reply::Err {
+                        message: MessageError::Cancelled.to_string(),
+                    }
+                }
+            }
+        }
+        None => call.await,
+    };
+    match result {
+        Ok(Response { body }) => {
+            let (body, bytes) = body.into_wire();
+            Reply::Ok { body, bytes }
+        }
+        Err(message) => {
+            health::record_error("messaging", format!("{name}: {message}"));
+            Reply::Err { message }
+        }
+    }
+}
+
+/// Receive a request transferred over `port`, and reply to it there.
+async fn receive_transfer(port: Port) {
+    let sender = Sender::from_js(&port.sender());
+    let mut messages = PortMessages::new(port);
+    let reply = match receive_request(&mut messages).await {
+        Ok((name, body, id)) => call_handler(name, body, sender, id).await,
+        Err(MessageError::VersionMismatch { .. }) => Reply::VersionMismatch {
+            expected: PROTOCOL_VERSION,
+        },
+        // The sender went away.
+        Err(MessageError::Send(_)) => return,
+        Err(e) => Reply::Err {
+            message: e.to_string(),
+        },
+    };
+    if let Err(e) = messages.post(&Transfer::Reply { reply }) {
+        warn!(format!("Failed to reply to a transfer: {e}"));
+    }
+}
+
+/// Store the chunks of a transfer as they arrive, and return the message name and
+/// request once all of them have.
+async fn receive_request(
+    messages: &mut PortMessages,
+) -> Result<(String, Body, Option<String>), MessageError> {
+    let Transfer::Start {
+        protocol,
+        name,
+        codec,
+        id,
+        total,
+    } = messages.next().await?
+    else {
+        return Err(MessageError::Codec(
+            "expected the start of a transfer".to_string(),
+        ));
+    };
+    if protocol != PROTOCOL_VERSION {
+        return Err(MessageError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: protocol,
+        });
+    }
+    let mut bytes = Vec::new();
+    for expected in 0..total {
+        match messages.next().await? {
+            Transfer::Chunk { index, data } if index == expected => bytes.extend(data),
+            _ => return Err(MessageError::Codec(format!("expected chunk {expected}"))),
+        }
+        messages.post(&Transfer::Ack {
+            received: expected + 1,
+        })?;
+    }
+    let body = Body::from_bytes(codec, bytes).map_err(MessageError::Codec)?;
+    Ok((name, body, id))
+}
+
+/// Send `request` to the background script's `name` handler and wait for its response.
+pub async fn send<Req, Res>(name: &str, request: &Req) -> Result<Res, MessageError>
+where
+    Req: Serialize,
+    Res: DeserializeOwned,
+{
+    Channel::default().send(name, request).await
+}
+
+/// Send `request` to the background script's handler for it and wait for its response.
+pub async fn request<R: Request>(request: &R) -> Result<R::Response, MessageError> {
+    Channel::default().request(request).await
+}
+
+/// Messages to the background, with bodies encoded by a codec both sides understand.
+///
+/// The default channel, used by [`send`], encodes them as JSON and waits for
+/// responses as long as it takes.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Channel {
+    codec: Codec,
+    timeout: Option<f64>,
+}
+
+impl Channel {
+    /// Open a channel with `preferred` if the background understands it, or JSON if it
+    /// doesn't.
+    pub async fn negotiate(preferred: Codec) -> Result<Channel, MessageError> {
+        let hello = negotiate().await?;
+        let codec = if hello.codecs.contains(&preferred) {
+            preferred
+        } else {
+            Codec::Json
+        };
+        Ok(Channel {
+            codec,
+            timeout: None,
+        })
+    }
+
+    /// Give up on requests not answered within `ms` milliseconds, and cancel them.
+    pub fn with_timeout(mut self, ms: f64) -> Self {
+        self.timeout = Some(ms);
+        self
+    }
+
+    pub fn codec(&self) -> Codec {
+        self.codec
+    }
+
+    /// Send `request` to the background script's `name` handler and wait for its
+    /// response.
+    pub async fn send<Req, Res>(&self, name: &str, request: &Req) -> Result<Res, MessageError>
+    where
+        Req: Serialize,
+        Res: DeserializeOwned,
+    {
+        let body = Body::encode(self.codec, request).map_err(MessageError::Codec)?;
+        let body = send_body(name, body, self.timeout).await?;
+        body.decode().map_err(MessageError::Codec)
+    }
+
+    /// Send `request` to the background script's handler for it and wait for its
+    /// response.
+    pub async fn request<R: Request>(&self, request: &R) -> Result<R::Response, MessageError> {
+        self.send(R::NAME, request).await
+    }
+}
+
+/// Something that delivers requests to the handlers registered with [`handle`]:
+/// [`Runtime`] in the extension, or `mock::MockMessaging` in tests.
+pub trait Messenger {
+    /// Send a request body to the handler for `name`, returning its response body.
+    fn send_value<'a>(
+        &'a self,
+        name: &'a str,
+        body: Value,
+    ) -> LocalBoxFuture<'a, Result<Value, MessageError>>;
+
+    /// Send a typed request to the handler for `name`, like [`send`].
+    fn send<'a, Req, Res>(
+        &'a self,
+        name: &'a str,
+        request: &Req,
+    ) -> LocalBoxFuture<'a, Result<Res, MessageError>>
+    where
+        Self: Sized,
+        Req: Serialize,
+        Res: DeserializeOwned + 'a,
+    {
+        match serde_json::to_value(request) {
+            Ok(body) => decode(self.send_value(name, body)),
+            Err(e) => Box::pin(future::ready(Err(MessageError::Codec(e.to_string())))),
+        }
+    }
+
+    /// Send a [`Request`] to its handler, like [`request`].
+    fn request<'a, R: Request>(
+        &'a self,
+        request: &R,
+    ) -> LocalBoxFuture<'a, Result<R::Response, MessageError>>
+    where
+        Self: Sized,
+    {
+        self.send(R::NAME, request)
+    }
+}
+
+/// Deserialize the response body of `reply`.
+fn decode<'a, Res: DeserializeOwned + 'a>(
+    reply: LocalBoxFuture<'a, Result<Value, MessageError>>,
+) -> LocalBoxFuture<'a, Result<Res, MessageError>> {
+    Box::pin(async move {
+        serde_json::from_value(reply.await?).map_err(|e| MessageError::Codec(e.to_string()))
+    })
+}
+
+/// Messaging over `runtime.sendMessage`, as done by [`send`].
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Runtime;
+
+impl Messenger for Runtime {
+    fn send_value<'a>(
+        &'a self,
+        name: &'a str,
+        body: Value,
+    ) -> LocalBoxFuture<'a, Result<Value, MessageError>> {
+        Box::pin(async move {
+            let body = send_body(name, Body::Json(body), None).await?;
+            body.decode().map_err(MessageError::Codec)
+        })
+    }
+}
+
+/// Send a request body to the background's `name` handler, and wait at most
+/// `timeout` milliseconds for the response.
+async fn send_body(name: &str, body: Body, timeout: Option<f64>) -> Result<Body, MessageError> {
+    let mut request = InFlight::start();
+    let delivery = deliver(name, body, request.id.clone());
+    let result = match timeout {
+        Some(ms) => match future::select(Box::pin(delivery), SystemClock.sleep(ms)).await {
+            Either::Left((result, _)) => result,
+            Either::Right(_) => Err(MessageError::Timeout(ms)),
+        },
+        None => delivery.await,
+    };
+    request.answered = !matches!(result, Err(MessageError::Timeout(_)));
+    result
+}
+
+/// Send a request, over a port if it's large.
+async fn deliver(name: &str, body: Body, id: String) -> Result<Body, MessageError> {
+    let serialized = body.to_bytes();
+    if serialized.len() > CHUNK_BYTES {
+        let info = browser::object(&[("name", TRANSFER_PORT.into())]);
+        let port = content::guard(async { browser::runtime().connect(info) })
+            .await
+            .map_err(send_error)?;
+        let mut messages = PortMessages::new(port);
+        messages.disconnect_on_drop = true;
+        return transfer(&mut messages, name, body.codec(), id, &serialized).await;
+    }
+    drop(serialized);
+    let (body, bytes) = body.into_wire();
+    send_envelope(Envelope {
+        protocol: PROTOCOL_VERSION,
+        name: name.to_string(),
+        body,
+        bytes,
+        id: Some(id),
+    })
+    .await
+}
+
+/// Send the chunks of a serialized request, and wait for the reply.
+async fn transfer(
+    messages: &mut PortMessages,
+    name: &str,
+    codec: Codec,
+    id: String,
+    serialized: &[u8],
+) -> Result<Body, MessageError> {
+    let parts: Vec<&[u8]> = serialized.chunks(CHUNK_BYTES).collect();
+    messages.post(&Transfer::Start {
+        protocol: PROTOCOL_VERSION,
+        name: name.to_string(),
+        codec,
+        id: Some(id),
+        total: parts.len(),
+    })?;
+    let (mut sent, mut received) = (0, 0);
+    loop {
+        while sent < parts.len() && sent - received < WINDOW {
+            messages.post(&Transfer::Chunk {
+                index: sent,
+                data: ByteBuf::from(parts[sent]),
+            })?;
+            sent += 1;
+        }
+        match messages.next().await? {
+            Transfer::Ack { received: count } => received = count,
+            Transfer::Reply { reply } => return unwrap_reply(reply),
+            _ => {
+                return Err(MessageError::Codec(
+                    "unexpected message during a transfer".to_string(),
+                ))
+            }
+        }
+    }
+}
+
+/// The messages arriving on a port, until it disconnects.
+struct PortMessages {
+    port: Port,
+    /// Whether to disconnect the port when done, which the side that connected it does.
+    disconnect_on_drop: bool,
+    messages: UnboundedReceiver<JsValue>,
+    on_message: Closure<dyn FnMut(JsValue)>,
+    on_disconnect: Closure<dyn FnMut()>,
+}
+
+impl PortMessages {
+    fn new(port: Port) -> Self {
+        let (sink, messages) = mpsc::unbounded();
+        let on_message = Closure::<dyn FnMut(JsValue)>::new({
+            let sink = sink.clone();
+            move |message| {
+                let _ = sink.unbounded_send(message);
+            }
+        });
+        let on_disconnect = Closure::<dyn FnMut()>::new(move || sink.close_channel());
+        port.on_message()
+            .add_listener(on_message.as_ref().unchecked_ref());
+        port.on_disconnect()
+            .add_listener(on_disconnect.as_ref().unchecked_ref());
+        PortMessages {
+            port,
+            disconnect_on_drop: false,
+            messages,
+            on_message,
+            on_disconnect,
+        }
+    }
+
+    fn post(&self, transfer: &Transfer) -> Result<(), MessageError> {
+        let message = transfer
+            .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+            .map_err(|e| MessageError::Codec(e.to_string()))?;
+        self.port.post_message(&message).map_err(send_error)
+    }
+
+    /// The next message, or [`MessageError::Send`] once the port is disconnected.
+    async fn next(&mut self) -> Result<Transfer, MessageError> {
+        let message = self
+            .messages
+            .next()
+            .await
+            .ok_or_else(|| MessageError::Send("the port was disconnected".to_string()))?;
+        serde_wasm_bindgen::from_value(message).map_err(|e| MessageError::Codec(e.to_string()))
+    }
+}
+
+impl Drop for PortMessages {
+    fn drop(&mut self) {
+        self.port
+            .on_message()
+            .remove_listener(self.on_message.as_ref().unchecked_ref());
+        self.port
+            .on_disconnect()
+            .remove_listener(self.on_disconnect.as_ref().unchecked_ref());
+        if self.disconnect_on_drop {
+            self.port.disconnect();
+        }
+    }
+}
+
+async fn send_envelope(envelope: Envelope) -> Result<Body, MessageError> {
+    let name = envelope.name.clone();
+    let message = envelope
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| MessageError::Codec(e.to_string()))?;
+    let reply = content::guard(browser::runtime().send_message(message))
+        .await
+        .map_err(send_error)?;
+    read_reply(name, reply)
+}
+
+fn send_error(error: JsValue) -> MessageError {
+    if content::is_invalidation_error(&error) {
+        MessageError::ContextInvalidated
+    } else {
+        MessageError::Send(format!("{error:?}"))
+    }
+}
+
+/// Send `request` to the `name` handler of the content scripts in a tab, or only in
+/// one of its frames. The content scripts need [`install`] and their own handlers.
+pub async fn send_to_tab<Req, Res>(
+    tab_id: i32,
+    frame_id: Option<i32>,
+    name: &str,
+    request: &Req,
+) -> Result<Res, MessageError>
+where
+    Req: Serialize,
+    Res: DeserializeOwned,
+{
+    let body = serde_json::to_value(request).map_err(|e| MessageError::Codec(e.to_string()))?;
+    let message = Envelope {
+        protocol: PROTOCOL_VERSION,
+        name: name.to_string(),
+        body,
+        bytes: None,
+        id: None,
+    }
+    .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+    .map_err(|e| MessageError::Codec(e.to_string()))?;
+    let options = match frame_id {
+        Some(frame_id) => browser::object(&[("frameId", frame_id.into())]),
+        None => browser::object(&[]),
+    };
+    let reply = browser::tabs()
+        .send_message(tab_id, message, options)
+        .await
+        .map_err(|e| MessageError::Send(format!("{e:?}")))?;
+    let body = read_reply(name.to_string(), reply)?;
+    body.decode().map_err(MessageError::Codec)
+}
+
+/// Unwrap the [`Reply`] to a message called `name`.
+fn read_reply(name: String, reply: JsValue) -> Result<Body, MessageError> {
+    if reply.is_undefined() {
+        return Err(MessageError::NoHandler(name));
+    }
+    unwrap_reply(
+        serde_wasm_bindgen::from_value(reply).map_err(|e| MessageError::Codec(e.to_string()))?,
+    )
+}
+
+fn unwrap_reply(reply: Reply) -> Result<Body, MessageError> {
+    match reply {
+        Reply::Ok { body, bytes } => Ok(Body::from_wire(body, bytes)),
+        Reply::Err { message } => Err(MessageError::Handler(message)),
+        Reply::VersionMismatch { expected } => Err(MessageError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: expected,
+        }),
+    }
+}
+
+/// Ask the background which protocol version it speaks.
+///
+/// Returns [`MessageError::VersionMismatch`] if it differs from this context's.
+pub async fn negotiate() -> Result<Hello, MessageError> {
+    let body = send_envelope(Envelope {
+        protocol: PROTOCOL_VERSION,
+        name: HELLO.to_string(),
+        body: Value::Null,
+        bytes: None,
+        id: None,
+    })
+    .await?;
+    let hello: Hello = body.decode().map_err(MessageError::Codec)?;
+    if hello.protocol != PROTOCOL_VERSION {
+        return Err(MessageError::VersionMismatch {
+            ours: PROTOCOL_VERSION,
+            theirs: hello.protocol,
+        });
+    }
+    Ok(hello)
+}
+
+/// What an outdated content script should do once it notices a mismatch.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MismatchAction {
+    /// Ask the background to inject the current version's content scripts into this
+    /// frame. The outdated script should stop doing work afterwards.
+    Reinject,
+    /// Show a small notice asking the user to reload the page.
+    Prompt,
+}
+
+/// Handle a detected protocol mismatch in a content script.
+///
+/// Falls back to [`MismatchAction::Prompt`] if re-injection fails (e.g. missing host
+/// permissions for the page).
+pub async fn resolve_mismatch(action: MismatchAction) {
+    if action == MismatchAction::Reinject {
+        let result = send_envelope(Envelope {
+            protocol: PROTOCOL_VERSION,
+            name: REINJECT.to_string(),
+            body: Value::Null,
+            bytes: None,
+            id: None,
+        })
+        .await;
+        match result {
+            Ok(_) => return,
+            Err(e) => warn!(format!("Re-injection failed, prompting instead: {e}")),
+        }
+    }
+    content::show_notice("The extension was updated. Click to reload the page.");
+}
+
+/// Inject the manifest's content scripts into the sender's frame.
+async fn reinject(sender: &Sender) -> Result<(), String> {
+    let Some(tab_id) = sender.tab_id else {
+        return Err("re-injection is only possible for content scripts".to_string());
+    };
+    let manifest = browser::runtime().get_manifest();
+    let content_scripts = Reflect::get(&manifest, &"content_scripts".into()).unwrap_or_default();
+    let files = js_sys::Array::new();
+    for entry in js_sys::Array::from(&content_scripts).iter() {
+        let js = Reflect::get(&entry, &"js".into()).unwrap_or_default();
+        for file in js_sys::Array::from(&js).iter() {
+            files.push(&file);
+        }
+    }
+    if files.length() == 0 {
+        return Err("the manifest declares no content scripts".to_string());
+    }
+
+    let frame_ids = js_sys::Array::of1(&sender.frame_id.unwrap_or(0).into());
+    let target = browser::object(&[("tabId", tab_id.into()), ("frameIds", frame_ids.into())]);
+    let injection = browser::object(&[("target", target), ("files", files.into())]);
+    browser::scripting()
+        .execute_script(injection)
+        .await
+        .map_err(|e| format!("{e:?}"))?;
+    Ok(())
+}
@@ -0,0 +1,734 @@
+//! In-memory stand-ins for the browser facades, for unit tests that run on the host
+//! with plain `cargo test` rather than in a browser.
+//!
+//! Business logic written against the facade traits takes the browser
+//! implementation in the extension and a mock in tests:
+//!
+//! | Facade                   | Browser                     | Mock              |
+//! |--------------------------|-----------------------------|-------------------|
+//! | `storage::StorageArea`   | `storage::Area`             | [`MemoryArea`]    |
+//! | `messaging::Messenger`   | `messaging::Runtime`        | [`MockMessaging`] |
+//! | `tabs::Tabs`             | `tabs::BrowserTabs`         | [`MockTabs`]      |
+//! | `alarms::Alarms`         | `alarms::BrowserAlarms`     | [`MockAlarms`]    |
+//! | `clock::Clock`           | `clock::SystemClock`        | [`MockClock`]     |
+//!
+//! The module is only built for this crate's tests, or with the `mock` feature, so it
+//! doesn't ship in the extension. Nothing here touches `wasm-bindgen` imports. Clones
+//! of a mock share its state, so a test can keep one to inspect while the code under
+//! test owns another:
+//!
+//! ```ignore
+//! #[test]
+//! fn remembers_the_last_site() {
+//!     let storage = MemoryArea::default();
+//!     let tabs = MockTabs::with_tabs([MockTabs::tab(1, "https://example.com/")]);
+//!     block_on(remember_active_site(&storage, &tabs)).unwrap();
+//!     assert_eq!(storage.snapshot()["lastSite"], "example.com");
+//! }
+//! ```
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::{BTreeMap, HashMap},
+    future::Future,
+    rc::Rc,
+};
+
+use futures::future::{self, LocalBoxFuture};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+
+use crate::{
+    alarms::{Alarm, AlarmSchedule, Alarms, MIN_ALARM_DELAY_MS},
+    clock::Clock,
+    error::WextError,
+    match_pattern::MatchPattern,
+    messaging::{MessageError, Messenger, Request, Sender},
+    tabs::{Tab, TabQuery, TabUpdate, Tabs},
+};
+
+pub use crate::storage::MemoryArea;
+
+type Handler = Rc<dyn Fn(Value, Sender) -> LocalBoxFuture<'static, Result<Value, String>>>;
+
+/// A [`Messenger`] that calls handlers registered on it directly, without a
+/// background script. Requests and responses still go through JSON, like real
+/// messages.
+#[derive(Clone, Default)]
+pub struct MockMessaging {
+    handlers: Rc<RefCell<HashMap<String, Handler>>>,
+    sent: Rc<RefCell<Vec<(String, Value)>>>,
+    sender: Rc<RefCell<Sender>>,
+}
+
+impl MockMessaging {
+    /// Register the handler for messages called `name`, like
+    /// [`messaging::handle`](crate::messaging::handle).
+    pub fn handle<Req, Res, F, Fut>(&self, name: &str, handler: F)
+    where
+        Req: DeserializeOwned + 'static,
+        Res: Serialize + 'static,
+        F: Fn(Req, Sender) -> Fut + 'static,
+        Fut: Future<Output = Result<Res, String>> + 'static,
+    {
+        let handler = Rc::new(handler);
+        let handler: Handler = Rc::new(move |body, sender| {
+            let handler = handler.clone();
+            Box::pin(async move {
+                let request = serde_json::from_value(body).map_err(|e| e.to_string())?;
+                let response = handler(request, sender).await?;
+                serde_json::to_value(response).map_err(|e| e.to_string())
+            })
+        });
+        self.handlers.borrow_mut().insert(name.to_string(), handler);
+    }
+
+    /// Register the handler for `R`, like
+    /// [`messaging::handle_request`](crate::messaging::handle_request).
+    pub fn handle_request<R, F, Fut>(&self, handler: F)
+    where
+        R: Request,
+        F: Fn(R, Sender) -> Fut + 'static,
+        Fut: Future<Output = Result<R::Response, String>> + 'static,
+    {
+        self.handle(R::NAME, handler);
+    }
+
+    /// Who handlers are told sent the messages, e.g. a content script's tab.
+    pub fn set_sender(&self, sender: Sender) {
+        *self.sender.borrow_mut() = sender;
+    }
+
+    /// Every message sent so far, as (name, request body).
+    pub fn sent(&self) -> Vec<(String, Value)> {
+        self.sent.borrow().clone()
+    }
+}
+
+impl Messenger for MockMessaging {
+    fn send_value<'a>(
+        &'a self,
+        name: &'a str,
+        body: Value,
+    ) -> LocalBoxFuture<'a, Result<Value, MessageError>> {
+        self.sent
+            .borrow_mut()
+            .push((name.to_string(), body.clone()));
+        let Some(handler) = self.handlers.borrow().get(name).cloned() else {
+            return Box::pin(future::ready(Err(MessageError::NoHandler(
+                name.to_string(),
+            ))));
+        };
+        let sender = self.sender.borrow().clone();
+        Box::pin(async move { handler(body, sender).await.map_err(MessageError::Handler) })
+    }
+}
+
+#[derive(Debug)]
+struct TabState {
+    tabs: Vec<Tab>,
+    current_window: i32,
+}
+
+/// [`Tabs`] over an in-memory list of tabs. Query results are in tab order, and the
+/// current window is window 1 unless changed.
+#[derive(Debug, Clone)]
+pub struct MockTabs {
+    state: Rc<RefCell<TabState>>,
+}
+
+impl Default for MockTabs {
+    fn default() -> Self {
+        MockTabs::with_tabs([])
+    }
+}
+
+impl MockTabs {
+    pub fn with_tabs(tabs: impl IntoIterator<Item = Tab>) -> Self {
+        MockTabs {
+            state: Rc::new(RefCell::new(TabState {
+                tabs: tabs.into_iter().collect(),
+                current_window: 1,
+            })),
+        }
+    }
+
+    /// An inactive tab in window 1.
+    pub fn tab(id: i32, url: &str) -> Tab {
+        Tab {
+            id,
+            window_id: 1,
+            url: Some(url.to_string()),
+            title: None,
+            fav_icon_url: None,
+            active: false,
+        }
+    }
+
+    /// The tabs as they are now.
+    pub fn tabs(&self) -> Vec<Tab> {
+        self.state.borrow().tabs.clone()
+    }
+
+    pub fn set_current_window(&self, window_id: i32) {
+        self.state.borrow_mut().current_window = window_id;
+    }
+
+    /// Make `tab_id` the only active tab in its window.
+    fn activate(tabs: &mut [Tab], tab_id: i32) {
+        let Some(window_id) = tabs
+            .iter()
+            .find(|tab| tab.id == tab_id)
+            .map(|tab| tab.window_id)
+        else {
+            return;
+        };
+        for tab in tabs.iter_mut().filter(|tab| tab.window_id == window_id) {
+            tab.active = tab.id == tab_id;
+        }
+    }
+}
+
+impl Tabs for MockTabs {
+    fn query<'a>(&'a self, query: &'a TabQuery) -> LocalBoxFuture<'a, Result<Vec<Tab>, WextError>> {
+        let result = query
+            .url
+            .iter()
+            .map(|pattern| {
+                MatchPattern::parse(pattern).map_err(|e| WextError::Other(e.to_string()))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|patterns| {
+                let state = self.state.borrow();
+                let tabs = state
+                    .tabs
+                    .iter()
+                    .filter(|tab| query.active.is_none_or(|active| tab.active == active))
+                    .filter(|tab| query.window_id.is_none_or(|id| tab.window_id == id))
+                    .filter(|tab| {
+                        query.current_window.is_none_or(|current| {
+                            (tab.window_id == state.current_window) == current
+                        })
+                    })
+                    .filter(|tab| {
+                        patterns.is_empty()
+                            || tab.url.as_deref().is_some_and(|url| {
+                                patterns.iter().any(|pattern| pattern.matches(url))
+                            })
+                    })
+                    .cloned()
+                    .collect();
+                tabs
+            });
+        Box::pin(future::ready(result))
+    }
+
+    fn create<'a>(
+        &'a self,
+        url: &'a str,
+        active: bool,
+    ) -> LocalBoxFuture<'a, Result<Tab, WextError>> {
+        let mut state = self.state.borrow_mut();
+        let tab = Tab {
+            id: state.tabs.iter().map(|tab| tab.id).max().unwrap_or(0) + 1,
+            window_id: state.current_window,
+            url: Some(url.to_string()),
+            title: None,
+            fav_icon_url: None,
+            active,
+        };
+        state.tabs.push(tab.clone());
+        if active {
+            MockTabs::activate(&mut state.tabs, tab.id);
+        }
+        Box::pin(future::ready(Ok(tab)))
+    }
+
+    fn update<'a>(
+        &'a self,
+        tab_id: i32,
+        update: &'a TabUpdate,
+    ) -> LocalBoxFuture<'a, Result<Tab, WextError>> {
+        let mut state = self.state.borrow_mut();
+        let Some(tab) = state.tabs.iter_mut().find(|tab| tab.id == tab_id) else {
+            return Box::pin(future::ready(Err(no_tab(tab_id))));
+        };
+        if let Some(url) = &update.url {
+            tab.url = Some(url.clone());
+        }
+        if update.active == Some(true) {
+            MockTabs::activate(&mut state.tabs, tab_id);
+        }
+        let tab = state.tabs.iter().find(|tab| tab.id == tab_id).cloned();
+        Box::pin(future::ready(tab.ok_or_else(|| no_tab(tab_id))))
+    }
+
+    fn remove(&self, tab_id: i32) -> LocalBoxFuture<'_, Result<(), WextError>> {
+        let mut state = self.state.borrow_mut();
+        let before = state.tabs.len();
+        state.tabs.retain(|tab| tab.id != tab_id);
+        let result = if state.tabs.len() < before {
+            Ok(())
+        } else {
+            Err(no_tab(tab_id))
+        };
+        Box::pin(future::ready(result))
+    }
+}
+
+/// The browser's error for a missing tab.
+fn no_tab(tab_id: i32) -> WextError {
+    WextError::Other(format!("No tab with id: {tab_id}."))
+}
+
+type Timer = (f64, u64, Box<dyn FnOnce()>);
+
+#[derive(Default)]
+struct ClockState {
+    now: f64,
+    timers: Vec<Timer>,
+    /// Orders timers due at the same time by when they were set.
+    sequence: u64,
+}
+
+/// A [`Clock`] that only moves when the test calls [`advance`](MockClock::advance) or
+/// [`advance_to`](MockClock::advance_to). Time starts at 0.
+#[derive(Clone, Default)]
+pub struct MockClock {
+    state: Rc<RefCell<ClockState>>,
+}
+
+impl MockClock {
+    /// A clock starting at `now`.
+    pub fn at(now: f64) -> Self {
+        let clock = MockClock::default();
+        clock.state.borrow_mut().now = now;
+        clock
+    }
+
+    /// Move time forward by `ms`.
+    pub fn advance(&self, ms: f64) {
+        let now = self.now();
+        self.advance_to(now + ms);
+    }
+
+    /// Move time forward to `now`, running every timer that's due on the way, in
+    /// order, with the clock set to its due time.
+    pub fn advance_to(&self, now: f64) {
+        loop {
+            let timer = {
+                let mut state = self.state.borrow_mut();
+                let due = state
+                    .timers
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (when, _, _))| *when <= now)
+                    .min_by(|(_, a), (_, b)| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)))
+                    .map(|(index, _)| index);
+                due.map(|index| {
+                    let timer = state.timers.remove(index);
+                    state.now = state.now.max(timer.0);
+                    timer
+                })
+            };
+            // Timers may set more timers, so they run without the state borrowed.
+            match timer {
+                Some((_, _, callback)) => callback(),
+                None => break,
+            }
+        }
+        let mut state = self.state.borrow_mut();
+        state.now = state.now.max(now);
+    }
+
+    /// How many timers are waiting.
+    pub fn pending_timers(&self) -> usize {
+        self.state.borrow().timers.len()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> f64 {
+        self.state.borrow().now
+    }
+
+    fn set_timeout(&self, ms: f64, callback: Box<dyn FnOnce()>) {
+        let mut state = self.state.borrow_mut();
+        let when = state.now + ms.max(0.0);
+        state.sequence += 1;
+        let sequence = state.sequence;
+        state.timers.push((when, sequence, callback));
+    }
+}
+
+type AlarmListeners = Rc<RefCell<Vec<Box<dyn FnMut(&Alarm)>>>>;
+
+/// [`Alarms`] driven by a [`MockClock`]: they fire as the test advances it. Schedules
+/// are clamped like in the browser.
+#[derive(Clone, Default)]
+pub struct MockAlarms {
+    clock: MockClock,
+    /// Scheduled alarms, with the generation of the timer that fires them.
+    alarms: Rc<RefCell<BTreeMap<String, (Alarm, u64)>>>,
+    generation: Rc<Cell<u64>>,
+    listeners: AlarmListeners,
+}
+
+impl MockAlarms {
+    /// Alarms that fire by `clock`.
+    pub fn new(clock: &MockClock) -> Self {
+        MockAlarms {
+            clock: clock.clone(),
+            ..Default::default()
+        }
+    }
+
+    /// The clock the alarms fire by.
+    pub fn clock(&self) -> &MockClock {
+        &self.clock
+    }
+
+    /// The alarms scheduled now.
+    pub fn scheduled(&self) -> Vec<Alarm> {
+        self.alarms
+            .borrow()
+            .values()
+            .map(|(alarm, _)| alarm.clone())
+            .collect()
+    }
+
+    /// Set a timer for `alarm`, replacing any earlier one for its name.
+    fn arm(&self, alarm: Alarm) {
+        let generation = self.generation.get() + 1;
+        self.generation.set(generation);
+        let delay = alarm.scheduled_time - self.clock.now();
+        self.alarms
+            .borrow_mut()
+            .insert(alarm.name.clone(), (alarm.clone(), generation));
+        let alarms = self.clone();
+        self.clock.set_timeout(
+            delay,
+            Box::new(move || {
+                let current = alarms.alarms.borrow().get(&alarm.name).map(|(_, g)| *g);
+                if current != Some(generation) {
+                    // Cleared or replaced since.
+                    return;
+                }
+                match alarm.period_in_minutes {
+                    Some(period) => alarms.arm(Alarm {
+                        scheduled_time: alarm.scheduled_time + period * 60_000.0,
+                        ..alarm.clone()
+                    }),
+                    None => {
+                        alarms.alarms.borrow_mut().remove(&alarm.name);
+                    }
+                }
+                alarms.fire(&alarm);
+            }),
+        );
+    }
+
+    fn fire(&self, alarm: &Alarm) {
+        // Listeners may schedule alarms, so they're taken out while they run.
+        let mut listeners = self.listeners.take();
+        for listener in &mut listeners {
+            listener(alarm);
+        }
+        let mut current = self.listeners.borrow_mut();
+        listeners.append(&mut current);
+        *current = listeners;
+    }
+}
+
+impl Alarms for MockAlarms {
+    fn create<'a>(
+        &'a self,
+        name: &'a str,
+        schedule: &'a AlarmSchedule,
+    ) -> LocalBoxFuture<'a, Result<(), WextError>> {
+        let now = self.clock.now();
+        let schedule = schedule.clamped(now);
+        let scheduled_time = schedule
+            .when
+            .or_else(|| {
+                schedule
+                    .delay_in_minutes
+                    .or(schedule.period_in_minutes)
+                    .map(|minutes| now + minutes * 60_000.0)
+            })
+            .unwrap_or(now + MIN_ALARM_DELAY_MS);
+        self.arm(Alarm {
+            name: name.to_string(),
+            scheduled_time,
+            period_in_minutes: schedule.period_in_minutes,
+        });
+        Box::pin(future::ready(Ok(())))
+    }
+
+    fn clear<'a>(&'a self, name: &'a str) -> LocalBoxFuture<'a, Result<bool, WextError>> {
+        let cleared = self.alarms.borrow_mut().remove(name).is_some();
+        Box::pin(future::ready(Ok(cleared)))
+    }
+
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Vec<Alarm>, WextError>> {
+        Box::pin(future::ready(Ok(self.scheduled())))
+    }
+
+    fn on_alarm(&self, callback: Box<dyn FnMut(&Alarm)>) {
+        self.listeners.borrow_mut().push(callback);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::executor::block_on;
+    use serde::Deserialize;
+    use serde_json::json;
+
+    use super::*;
+    use crate::storage::{StorageArea, StorageError};
+
+    /// A callback that records what it's called with.
+    fn recorder<T: 'static>() -> (Rc<RefCell<Vec<T>>>, impl FnMut(T)) {
+        let calls = Rc::new(RefCell::new(Vec::new()));
+        let record = {
+            let calls = calls.clone();
+            move |value| calls.borrow_mut().push(value)
+        };
+        (calls, record)
+    }
+
+    #[test]
+    fn memory_area_round_trips_values() {
+        let area = MemoryArea::default();
+        block_on(area.set("count", &3)).unwrap();
+        assert_eq!(block_on(area.get::<i32>("count")).unwrap(), Some(3));
+        assert_eq!(block_on(area.get::<i32>("missing")).unwrap(), None);
+        assert!(matches!(
+            block_on(area.get::<String>("count")),
+            Err(StorageError::Codec(_))
+        ));
+
+        // Clones share the contents.
+        let clone = area.clone();
+        block_on(clone.remove("count")).unwrap();
+        assert!(area.snapshot().is_empty());
+    }
+
+    #[test]
+    fn memory_area_notifies_changes() {
+        let area = MemoryArea::default();
+        let (changes, record) = recorder::<Option<i32>>();
+        area.on_change("count", record);
+        block_on(area.set("count", &1)).unwrap();
+        block_on(area.set("count", &1)).unwrap();
+        block_on(area.set("other", &2)).unwrap();
+        block_on(area.remove("count")).unwrap();
+        block_on(area.set("count", &4)).unwrap();
+        block_on(area.clear()).unwrap();
+        // Unchanged writes and other keys aren't changes.
+        assert_eq!(*changes.borrow(), [Some(1), None, Some(4), None]);
+    }
+
+    #[test]
+    fn mock_tabs_query_filters() {
+        let mut active = MockTabs::tab(2, "https://docs.example.com/intro");
+        active.active = true;
+        let mut other_window = MockTabs::tab(3, "https://example.com/");
+        other_window.window_id = 2;
+        let tabs = MockTabs::with_tabs([
+            MockTabs::tab(1, "http://example.org/"),
+            active,
+            other_window,
+        ]);
+        let ids = |query: TabQuery| -> Vec<i32> {
+            let found = block_on(tabs.query(&query)).unwrap();
+            found.iter().map(|tab| tab.id).collect()
+        };
+
+        assert_eq!(ids(TabQuery::default()), [1, 2, 3]);
+        let active = TabQuery {
+            active: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(ids(active), [2]);
+        let current = TabQuery {
+            current_window: Some(true),
+            ..Default::default()
+        };
+        assert_eq!(ids(current), [1, 2]);
+        let example_com = TabQuery {
+            url: vec!["*://*.example.com/*".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(ids(example_com), [2, 3]);
+
+        let invalid = TabQuery {
+            url: vec!["example.com".to_string()],
+            ..Default::default()
+        };
+        assert!(block_on(tabs.query(&invalid)).is_err());
+    }
+
+    #[test]
+    fn mock_tabs_create_update_and_remove() {
+        let tabs = MockTabs::with_tabs([MockTabs::tab(4, "https://example.com/")]);
+        let created = block_on(tabs.create("https://example.org/", true)).unwrap();
+        assert_eq!(created.id, 5);
+        assert_eq!(created.window_id, 1);
+
+        let update = TabUpdate {
+            url: Some("https://example.net/".to_string()),
+            active: Some(true),
+        };
+        let updated = block_on(tabs.update(4, &update)).unwrap();
+        assert_eq!(updated.url.as_deref(), Some("https://example.net/"));
+        // Activating a tab deactivates the others in its window.
+        let active: Vec<_> = tabs.tabs().into_iter().map(|tab| tab.active).collect();
+        assert_eq!(active, [true, false]);
+
+        block_on(tabs.remove(5)).unwrap();
+        assert_eq!(tabs.tabs().len(), 1);
+        assert_eq!(block_on(tabs.remove(5)), Err(no_tab(5)));
+        assert_eq!(block_on(tabs.update(5, &update)), Err(no_tab(5)));
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Add {
+        a: i32,
+        b: i32,
+    }
+
+    impl Request for Add {
+        const NAME: &'static str = "add";
+        type Response = i32;
+    }
+
+    #[test]
+    fn mock_messaging_calls_handlers() {
+        let messaging = MockMessaging::default();
+        messaging.handle_request(|Add { a, b }: Add, _| async move { Ok(a + b) });
+        messaging.handle("tab", |(): (), sender: Sender| async move {
+            sender.tab_id.ok_or_else(|| "not from a tab".to_string())
+        });
+
+        assert_eq!(block_on(messaging.request(&Add { a: 2, b: 3 })), Ok(5));
+        assert_eq!(
+            block_on(messaging.send::<_, i32>("tab", &())),
+            Err(MessageError::Handler("not from a tab".to_string()))
+        );
+        messaging.set_sender(Sender {
+            tab_id: Some(7),
+            ..Default::default()
+        });
+        assert_eq!(block_on(messaging.send::<_, i32>("tab", &())), Ok(7));
+        assert_eq!(
+            block_on(messaging.send::<_, ()>("missing", &())),
+            Err(MessageError::NoHandler("missing".to_string()))
+        );
+        // A request the handler can't read fails like a real one would.
+        assert!(matches!(
+            block_on(messaging.send::<_, i32>("add", &"two and three")),
+            Err(MessageError::Handler(_))
+        ));
+
+        let names: Vec<_> = messaging.sent().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, ["add", "tab", "tab", "missing", "add"]);
+        assert_eq!(messaging.sent()[0].1, json!({ "a": 2, "b": 3 }));
+    }
+
+    #[test]
+    fn mock_clock_runs_due_timers_in_order() {
+        let clock = MockClock::at(1_000.0);
+        let fired = Rc::new(RefCell::new(Vec::new()));
+        for (name, ms) in [("b", 20.0), ("a", 10.0), ("c", 20.0)] {
+            let fired = fired.clone();
+            let clock_in_timer = clock.clone();
+            clock.set_timeout(
+                ms,
+                Box::new(move || fired.borrow_mut().push((name, clock_in_timer.now()))),
+            );
+        }
+
+        clock.advance(15.0);
+        assert_eq!(*fired.borrow(), [("a", 1_010.0)]);
+        assert_eq!(clock.now(), 1_015.0);
+        // Timers due at the same time run in the order they were set.
+        clock.advance_to(1_100.0);
+        assert_eq!(
+            *fired.borrow(),
+            [("a", 1_010.0), ("b", 1_020.0), ("c", 1_020.0)]
+        );
+        assert_eq!(clock.now(), 1_100.0);
+        assert_eq!(clock.pending_timers(), 0);
+    }
+
+    #[test]
+    fn mock_clock_runs_timers_set_by_timers() {
+        let clock = MockClock::default();
+        let fired = Rc::new(RefCell::new(Vec::new()));
+        let inner = {
+            let clock = clock.clone();
+            let fired = fired.clone();
+            move || {
+                let clock_in_timer = clock.clone();
+                clock.set_timeout(
+                    5.0,
+                    Box::new(move || fired.borrow_mut().push(clock_in_timer.now())),
+                );
+            }
+        };
+        clock.set_timeout(5.0, Box::new(inner));
+        clock.advance(12.0);
+        assert_eq!(*fired.borrow(), [10.0]);
+    }
+
+    #[test]
+    fn mock_alarms_clamp_and_fire() {
+        let clock = MockClock::default();
+        let alarms = MockAlarms::new(&clock);
+        let (fired, mut record) = recorder::<String>();
+        alarms.on_alarm(Box::new(move |alarm| record(alarm.name.clone())));
+
+        block_on(alarms.create("soon", &AlarmSchedule::at(1_000.0))).unwrap();
+        block_on(alarms.create("every", &AlarmSchedule::every(1.0))).unwrap();
+        let soon = alarms
+            .scheduled()
+            .into_iter()
+            .find(|alarm| alarm.name == "soon");
+        assert_eq!(soon.unwrap().scheduled_time, MIN_ALARM_DELAY_MS);
+
+        // Clamped to 30 seconds, so nothing fires at one second.
+        clock.advance(1_000.0);
+        assert!(fired.borrow().is_empty());
+        clock.advance_to(MIN_ALARM_DELAY_MS);
+        assert_eq!(*fired.borrow(), ["soon"]);
+        clock.advance_to(3.0 * 60_000.0);
+        assert_eq!(*fired.borrow(), ["soon", "every", "every", "every"]);
+
+        // Periodic alarms stay scheduled, one-off ones don't.
+        let scheduled = block_on(alarms.get_all()).unwrap();
+        assert_eq!(scheduled.len(), 1);
+        assert_eq!(scheduled[0].name, "every");
+        assert_eq!(scheduled[0].scheduled_time, 4.0 * 60_000.0);
+
+        assert_eq!(block_on(alarms.clear("every")), Ok(true));
+        assert_eq!(block_on(alarms.clear("every")), Ok(false));
+        clock.advance(10.0 * 60_000.0);
+        assert_eq!(fired.borrow().len(), 4);
+    }
+
+    #[test]
+    fn mock_alarms_replace_alarms_of_the_same_name() {
+        let clock = MockClock::default();
+        let alarms = MockAlarms::new(&clock);
+        let (fired, mut record) = recorder::<f64>();
+        let clock_in_listener = clock.clone();
+        alarms.on_alarm(Box::new(move |_| record(clock_in_listener.now())));
+
+        block_on(alarms.create("sync", &AlarmSchedule::at(60_000.0))).unwrap();
+        block_on(alarms.create("sync", &AlarmSchedule::at(120_000.0))).unwrap();
+        clock.advance(300_000.0);
+        assert_eq!(*fired.borrow(), [120_000.0]);
+    }
+}
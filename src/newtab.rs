@@ -0,0 +1,49 @@
+use gloo_timers::callback::Interval;
+use leptos::{prelude::*, spawn::spawn_local};
+
+use crate::{
+    clock::{Clock, SystemClock},
+    entry::wext_entry,
+    format::{self, DateStyle},
+    i18n,
+    pages::{self, PageId},
+    t,
+};
+
+/// How often the clock is refreshed. Often enough that the minute changes on time.
+const CLOCK_TICK_MS: u32 = 1_000;
+
+#[wext_entry(newtab)]
+pub async fn newtab_page() {
+    mount_to_body(|| {
+        i18n::provide_direction();
+        let open_options = |_| {
+            spawn_local(async {
+                if let Err(e) = pages::open(PageId::Options).await {
+                    gloo_console::warn!("Failed to open the options page:", e);
+                }
+            })
+        };
+        view! {
+            <main class="h-screen flex flex-col items-center justify-center gap-2 bg-slate-900 text-white">
+                <TimeOfDay />
+                <p class="text-lg">{t!("newTabGreeting")}</p>
+                <button type="button" class="text-sm underline" on:click=open_options>
+                    "Options"
+                </button>
+            </main>
+        }
+    })
+}
+
+/// The current time and date, large.
+#[component]
+fn TimeOfDay() -> impl IntoView {
+    let now = RwSignal::new(SystemClock.now());
+    // The clock shows as long as the page does.
+    Interval::new(CLOCK_TICK_MS, move || now.set(SystemClock.now())).forget();
+    view! {
+        <p class="text-6xl font-light tabular-nums">{move || format::date(now.get(), DateStyle::Time)}</p>
+        <p class="text-xl">{move || format::date(now.get(), DateStyle::Date)}</p>
+    }
+}
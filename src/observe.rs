@@ -0,0 +1,230 @@
+//! Reacting to dynamic host pages from content scripts.
+//!
+//! Pages render and re-render long after a content script starts, and single-page
+//! apps change routes without loading a new document. [`Observer`] wraps
+//! `MutationObserver` with debouncing, [`wait_for_element`] and [`watch_elements`]
+//! find elements as they appear, and [`on_navigate`] (or [`use_url`] in Leptos)
+//! notices route changes.
+//!
+//! ```ignore
+//! let toolbar = observe::wait_for_element("#toolbar").await;
+//! observe::on_navigate(|url| log!("Navigated to", url));
+//! ```
+
+use std::{cell::RefCell, rc::Rc};
+
+use futures::{
+    channel::oneshot,
+    future::{select, Either},
+};
+use gloo_timers::{callback::Timeout, future::TimeoutFuture};
+use js_sys::{Reflect, WeakSet};
+use leptos::prelude::*;
+use wasm_bindgen::prelude::*;
+use web_sys::{Element, EventTarget, MutationObserver, MutationObserverInit, Node};
+
+/// Debounce for [`watch_elements`], so a burst of insertions is scanned once.
+const WATCH_DEBOUNCE_MS: u32 = 50;
+
+/// Debounce for the DOM changes [`on_navigate`] checks the URL after.
+const NAVIGATE_DEBOUNCE_MS: u32 = 100;
+
+/// What an [`Observer`] watches, as in `MutationObserverInit`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Watch {
+    pub child_list: bool,
+    pub subtree: bool,
+    pub attributes: bool,
+    pub character_data: bool,
+}
+
+impl Default for Watch {
+    /// Elements being added or removed anywhere below the target.
+    fn default() -> Self {
+        Watch {
+            child_list: true,
+            subtree: true,
+            attributes: false,
+            character_data: false,
+        }
+    }
+}
+
+/// A `MutationObserver` that calls its callback at most once per `debounce_ms`, after
+/// the mutations settle. It disconnects when dropped, unless [`forget`](Self::forget)ten.
+pub struct Observer {
+    observer: MutationObserver,
+    pending: Rc<RefCell<Option<Timeout>>>,
+    _callback: Closure<dyn FnMut()>,
+}
+
+impl Observer {
+    /// Observe `target`. With a `debounce_ms` of 0, `callback` runs for every batch of
+    /// mutations the browser delivers.
+    pub fn new(
+        target: &Node,
+        watch: Watch,
+        debounce_ms: u32,
+        callback: impl FnMut() + 'static,
+    ) -> Self {
+        let callback = Rc::new(RefCell::new(callback));
+        let pending = Rc::new(RefCell::new(None::<Timeout>));
+        let closure = {
+            let pending = pending.clone();
+            Closure::<dyn FnMut()>::new(move || {
+                if debounce_ms == 0 {
+                    (callback.borrow_mut())();
+                    return;
+                }
+                let callback = callback.clone();
+                // Replacing the pending timeout cancels it.
+                pending.replace(Some(Timeout::new(debounce_ms, move || {
+                    (callback.borrow_mut())();
+                })));
+            })
+        };
+        let observer = MutationObserver::new(closure.as_ref().unchecked_ref()).unwrap();
+        let init = MutationObserverInit::new();
+        init.set_child_list(watch.child_list);
+        init.set_subtree(watch.subtree);
+        init.set_attributes(watch.attributes);
+        init.set_character_data(watch.character_data);
+        observer.observe_with_options(target, &init).unwrap();
+        Observer {
+            observer,
+            pending,
+            _callback: closure,
+        }
+    }
+
+    /// Keep observing for the lifetime of the page.
+    pub fn forget(self) {
+        std::mem::forget(self);
+    }
+}
+
+impl Drop for Observer {
+    fn drop(&mut self) {
+        self.observer.disconnect();
+        self.pending.take();
+    }
+}
+
+/// The first element matching `selector`, waiting for one to be added if there's
+/// none yet.
+pub async fn wait_for_element(selector: &str) -> Element {
+    if let Some(element) = query(selector) {
+        return element;
+    }
+    let (sender, receiver) = oneshot::channel();
+    let sender = RefCell::new(Some(sender));
+    let observer = {
+        let selector = selector.to_string();
+        Observer::new(&document(), Watch::default(), 0, move || {
+            if let Some(element) = query(&selector) {
+                if let Some(sender) = sender.take() {
+                    let _ = sender.send(element);
+                }
+            }
+        })
+    };
+    let element = receiver.await.expect("the observer outlives the receiver");
+    drop(observer);
+    element
+}
+
+/// Like [`wait_for_element`], but gives up with `None` after `timeout_ms`.
+pub async fn wait_for_element_timeout(selector: &str, timeout_ms: u32) -> Option<Element> {
+    let element = Box::pin(wait_for_element(selector));
+    match select(element, TimeoutFuture::new(timeout_ms)).await {
+        Either::Left((element, _)) => Some(element),
+        Either::Right(_) => None,
+    }
+}
+
+/// Call `callback` once for every element matching `selector`: those already in the
+/// page, and those added later. Stops when the returned [`Observer`] is dropped.
+pub fn watch_elements(selector: &str, callback: impl FnMut(Element) + 'static) -> Observer {
+    let seen = WeakSet::new();
+    let selector = selector.to_string();
+    let mut callback = callback;
+    let mut scan = move || {
+        let Ok(elements) = document().query_selector_all(&selector) else {
+            return;
+        };
+        for index in 0..elements.length() {
+            let Some(element) = elements.item(index) else {
+                continue;
+            };
+            let element: Element = element.unchecked_into();
+            if !seen.has(&element) {
+                seen.add(&element);
+                callback(element);
+            }
+        }
+    };
+    scan();
+    Observer::new(&document(), Watch::default(), WATCH_DEBOUNCE_MS, scan)
+}
+
+/// Call `callback` with the new URL whenever the page's URL changes without a new
+/// document being loaded: history navigation, hash changes, and SPA route changes.
+///
+/// Content scripts can't see the page's `history.pushState` calls, so besides
+/// `popstate`, `hashchange` and the Navigation API's `navigatesuccess` (where
+/// available), the URL is also checked whenever the DOM changes. Listens for the
+/// lifetime of the page.
+pub fn on_navigate(callback: impl FnMut(&str) + 'static) {
+    let last = RefCell::new(href());
+    let callback = RefCell::new(callback);
+    let check = Rc::new(move || {
+        let href = href();
+        if *last.borrow() != href {
+            last.replace(href.clone());
+            (callback.borrow_mut())(&href);
+        }
+    });
+
+    let listener = {
+        let check = check.clone();
+        Closure::<dyn Fn()>::new(move || check())
+    };
+    let window = window();
+    for event in ["popstate", "hashchange"] {
+        window
+            .add_event_listener_with_callback(event, listener.as_ref().unchecked_ref())
+            .unwrap();
+    }
+    let navigation = Reflect::get(&window, &"navigation".into()).unwrap_or_default();
+    if navigation.is_object() {
+        navigation
+            .unchecked_into::<EventTarget>()
+            .add_event_listener_with_callback("navigatesuccess", listener.as_ref().unchecked_ref())
+            .unwrap();
+    }
+    listener.forget();
+
+    Observer::new(
+        &document(),
+        Watch::default(),
+        NAVIGATE_DEBOUNCE_MS,
+        move || check(),
+    )
+    .forget();
+}
+
+/// The page's URL as a signal, updated by [`on_navigate`]. Call it once, e.g. at the
+/// root of the content script's view.
+pub fn use_url() -> ReadSignal<String> {
+    let (url, set_url) = signal(href());
+    on_navigate(move |href| set_url.set(href.to_string()));
+    url
+}
+
+fn query(selector: &str) -> Option<Element> {
+    document().query_selector(selector).ok().flatten()
+}
+
+fn href() -> String {
+    window().location().href().unwrap_or_default()
+}
@@ -1,13 +1,444 @@
-use leptos::prelude::*;
-use wasm_bindgen::prelude::*;
+use leptos::{prelude::*, spawn::spawn_local};
+use serde::{Deserialize, Serialize};
 
-#[wasm_bindgen]
+use crate::{
+    browser, diagnostics,
+    dialog::Confirm,
+    downloads::{self, Rule},
+    entry::wext_entry,
+    flags::{self, Flag},
+    forms::{ErrorSummary, Field, Form, SaveBar},
+    hotkeys::HotkeyHelp,
+    i18n,
+    settings::Settings,
+    storage, t,
+    toast::{self, Toasts},
+    translate::{self, Settings as TranslateSettings},
+};
+
+/// The example preferences, saved as they're edited and shared with every context.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Preferences {
+    pub display_name: String,
+}
+
+pub const PREFERENCES: Settings<Preferences> = Settings::new("options.preferences");
+
+/// The display name's maximum length.
+const MAX_DISPLAY_NAME: usize = 40;
+
+#[wext_entry(options)]
 pub async fn options_page() {
     mount_to_body(|| {
+        i18n::provide_direction();
+        toast::provide_toasts();
         view! {
-            <p class="bg-green-200 h-screen flex items-center justify-center">
-                "Hello, options page!"
-            </p>
+            <Toasts />
+            <HotkeyHelp />
+            <main class="bg-green-200 h-screen p-4">
+                <h1 class="text-lg font-bold mb-2">{t!("optionsGreeting")}</h1>
+                <PreferencesSection />
+                <OptionsForm />
+                <About />
+                <a href="farewell.html" class="underline text-sm">"Uninstall…"</a>
+                {(cfg!(debug_assertions) && !flags::FLAGS.is_empty()).then(|| view! { <FlagsPanel /> })}
+            </main>
         }
     })
 }
+
+/// The preferences, which need no saving: they're stored as they're edited, and
+/// follow edits made in another options tab.
+#[component]
+fn PreferencesSection() -> impl IntoView {
+    let preferences = PREFERENCES.use_signal();
+    let display_name = move || preferences.with(|p| p.display_name.clone());
+    view! {
+        <section class="mb-3">
+            <label class="block mb-1">
+                <span class="block text-sm">"Display name"</span>
+                <input
+                    type="text"
+                    class="border rounded px-2 py-1 w-full"
+                    maxlength=MAX_DISPLAY_NAME
+                    prop:value=display_name
+                    on:input=move |ev| {
+                        preferences.update(|p| p.display_name = event_target_value(&ev))
+                    }
+                />
+            </label>
+        </section>
+    }
+}
+
+#[component]
+fn OptionsForm() -> impl IntoView {
+    let download_rules = Field::new("Download rules", Vec::<Rule>::new())
+        .validate(|rules: &Vec<Rule>| downloads::check_rules(rules));
+    let translation =
+        Field::new("Translation", TranslateSettings::default()).validate(TranslateSettings::check);
+    let form = Form::new().with(&download_rules).with(&translation);
+
+    {
+        let download_rules = download_rules.clone();
+        let translation = translation.clone();
+        spawn_local(async move {
+            match downloads::rules().await {
+                Ok(rules) => download_rules.load(rules),
+                Err(e) => gloo_console::error!("Failed to load the download rules:", e),
+            }
+            match translate::settings().await {
+                Ok(settings) => translation.load(settings),
+                Err(e) => gloo_console::error!("Failed to load the translation settings:", e),
+            }
+        });
+    }
+
+    let rules = download_rules.value;
+    let translate_settings = translation.value;
+    let on_save = move || async move {
+        storage::set(downloads::RULES_KEY, &rules.get_untracked())
+            .await
+            .map_err(|e| format!("Couldn't save: {e}"))?;
+        storage::set(translate::SETTINGS_KEY, &translate_settings.get_untracked())
+            .await
+            .map_err(|e| format!("Couldn't save: {e}"))
+    };
+
+    let toaster = toast::use_toaster();
+    let resetting = RwSignal::new(false);
+    let reset = {
+        let download_rules = download_rules.clone();
+        let translation = translation.clone();
+        move |_| {
+            let download_rules = download_rules.clone();
+            let translation = translation.clone();
+            spawn_local(async move {
+                // The preferences' signal follows the reset by itself.
+                let mut removed = PREFERENCES.reset().await;
+                for key in [downloads::RULES_KEY, translate::SETTINGS_KEY] {
+                    if removed.is_err() {
+                        break;
+                    }
+                    removed = storage::remove(key).await;
+                }
+                if removed.is_ok() {
+                    download_rules.load(Vec::new());
+                    translation.load(TranslateSettings::default());
+                }
+                let Some(toaster) = toaster else {
+                    return;
+                };
+                match removed {
+                    Ok(()) => toaster.success("Settings reset."),
+                    Err(e) => toaster.error(format!("Couldn't reset the settings: {e}")),
+                };
+            });
+        }
+    };
+
+    view! {
+        <ErrorSummary form=form.clone() />
+        <DownloadRulesField field=download_rules />
+        <TranslationField field=translation />
+        <button type="button" class="px-3 py-1 mb-3 border rounded" on:click=move |_| resetting.set(true)>
+            "Reset all settings"
+        </button>
+        <Confirm
+            open=resetting
+            title="Reset all settings?"
+            confirm_label="Reset"
+            destructive=true
+            on_confirm=reset
+        >
+            "Your display name, download rules and translation settings will be deleted. This \
+             can't be undone."
+        </Confirm>
+        <SaveBar form on_save />
+    }
+}
+
+/// Where the "Translate" context menu item sends selections.
+#[component]
+fn TranslationField(field: Field<TranslateSettings>) -> impl IntoView {
+    let id = field.id();
+    let settings = field.value;
+    let error = field.error;
+    let input = move |label: &'static str,
+                      kind: &'static str,
+                      get: fn(&TranslateSettings) -> &String,
+                      set: fn(&mut TranslateSettings, String)| {
+        let field = field.clone();
+        view! {
+            <label class="block mb-1">
+                <span class="block text-sm">{label}</span>
+                <input
+                    type=kind
+                    class="border rounded px-2 py-1 w-full"
+                    prop:value=move || settings.with(|settings| get(settings).clone())
+                    on:input=move |ev| {
+                        let mut value = settings.get_untracked();
+                        set(&mut value, event_target_value(&ev));
+                        field.set(value);
+                    }
+                />
+            </label>
+        }
+    };
+    view! {
+        <fieldset id=id class="mb-3">
+            <legend class="font-medium">"Translation"</legend>
+            <p class="text-sm">
+                "Selected text is translated with a LibreTranslate server, e.g. "
+                <code>{translate::DEFAULT_ENDPOINT}</code> " or your own."
+            </p>
+            {input("Service URL", "url", |s| &s.endpoint, |s, value| s.endpoint = value)}
+            {input("API key", "password", |s| &s.api_key, |s, value| s.api_key = value)}
+            {input(
+                "Target language (empty for the browser's)",
+                "text",
+                |s| &s.target,
+                |s, value| s.target = value,
+            )}
+            <p class="text-sm text-red-700" aria-live="polite">
+                {move || error.get().unwrap_or_default()}
+            </p>
+        </fieldset>
+    }
+}
+
+/// The version, and the diagnostics report for support requests.
+#[component]
+fn About() -> impl IntoView {
+    let toaster = toast::use_toaster();
+    let busy = RwSignal::new(false);
+    let run = move |copy: bool| {
+        busy.set(true);
+        spawn_local(async move {
+            let progress = toaster.map(|toaster| toaster.progress("Gathering diagnostics…"));
+            let result = if copy {
+                diagnostics::copy().await
+            } else {
+                diagnostics::save().await
+            };
+            busy.set(false);
+            let (Some(toaster), Some(progress)) = (toaster, progress) else {
+                return;
+            };
+            match result {
+                Ok(()) if copy => toaster.succeed(progress, "Diagnostics copied to the clipboard."),
+                Ok(()) => toaster.dismiss(progress),
+                Err(e) => toaster.fail(progress, format!("Couldn't gather diagnostics: {e}")),
+            }
+        });
+    };
+    view! {
+        <section class="mb-3">
+            <h2 class="font-medium">"About"</h2>
+            <p class="text-sm">{format!("Version {}", browser::extension_version())}</p>
+            <p class="text-sm mb-1">
+                "Asking for help? Include the diagnostics report: it has the version, your browser, your settings without \
+                 secrets, and recent errors and logs."
+            </p>
+            <div class="flex gap-2">
+                <button
+                    type="button"
+                    class="px-3 py-1 border rounded"
+                    disabled=move || busy.get()
+                    on:click=move |_| run(true)
+                >
+                    "Copy diagnostics"
+                </button>
+                <button
+                    type="button"
+                    class="px-3 py-1 border rounded"
+                    disabled=move || busy.get()
+                    on:click=move |_| run(false)
+                >
+                    "Save diagnostics…"
+                </button>
+            </div>
+        </section>
+    }
+}
+
+/// An editable list of download rules.
+#[component]
+fn DownloadRulesField(field: Field<Vec<Rule>>) -> impl IntoView {
+    let id = field.id();
+    let rules = field.value;
+    let error = field.error;
+    let update = {
+        let field = field.clone();
+        move |index: usize, edit: &dyn Fn(&mut Rule)| {
+            let mut value = rules.get_untracked();
+            if let Some(rule) = value.get_mut(index) {
+                edit(rule);
+            }
+            field.set(value);
+        }
+    };
+    let add = {
+        let field = field.clone();
+        move |_| {
+            let mut value = rules.get_untracked();
+            value.push(Rule::default());
+            field.set(value);
+        }
+    };
+    // The rule waiting for confirmation to be removed.
+    let removing = RwSignal::new(None::<usize>);
+    let confirming = RwSignal::new(false);
+    let remove = {
+        let field = field.clone();
+        move |_| {
+            let Some(index) = removing.get_untracked() else {
+                return;
+            };
+            let mut value = rules.get_untracked();
+            if index < value.len() {
+                value.remove(index);
+            }
+            field.set(value);
+        }
+    };
+    let removing_url = move || {
+        let url = removing
+            .get()
+            .and_then(|index| rules.with(|rules| Some(rules.get(index)?.url.clone())))
+            .unwrap_or_default();
+        if url.is_empty() {
+            "The rule will be removed once you save.".to_string()
+        } else {
+            format!("The rule for {url} will be removed once you save.")
+        }
+    };
+
+    view! {
+        <fieldset id=id class="mb-3">
+            <legend class="font-medium">{field.label}</legend>
+            <p class="text-sm">
+                "Save matching downloads elsewhere, e.g. target " <code>"Papers/{host}/"</code>
+                " for URLs " <code>"https://*.example.com/*"</code> " and extensions "
+                <code>"pdf"</code> "."
+            </p>
+            <For
+                each=move || 0..rules.with(Vec::len)
+                key=|index| *index
+                children=move |index| {
+                    let update = update.clone();
+                    let input = move |label: &'static str, get: fn(&Rule) -> &String, set: fn(&mut Rule, String)| {
+                        let update = update.clone();
+                        view! {
+                            <input
+                                type="text"
+                                class="border rounded px-2 py-1"
+                                placeholder=label
+                                aria-label=label
+                                prop:value=move || {
+                                    rules.with(|rules| rules.get(index).map(get).cloned().unwrap_or_default())
+                                }
+                                on:input=move |ev| {
+                                    let value = event_target_value(&ev);
+                                    update(index, &|rule| set(rule, value.clone()));
+                                }
+                            />
+                        }
+                    };
+                    let remove = move |_| {
+                        removing.set(Some(index));
+                        confirming.set(true);
+                    };
+                    view! {
+                        <div class="flex gap-2 mb-1">
+                            {input("URL pattern", |rule| &rule.url, |rule, value| rule.url = value)}
+                            {input("Extensions", |rule| &rule.extensions, |rule, value| rule.extensions = value)}
+                            {input("Target", |rule| &rule.target, |rule, value| rule.target = value)}
+                            <button type="button" class="px-2 border rounded" on:click=remove>
+                                "Remove"
+                            </button>
+                        </div>
+                    }
+                }
+            />
+            <button type="button" class="px-3 py-1 border rounded" on:click=add>
+                "Add rule"
+            </button>
+            <Confirm
+                open=confirming
+                title="Remove this rule?"
+                confirm_label="Remove"
+                destructive=true
+                on_confirm=remove
+            >
+                {removing_url}
+            </Confirm>
+            <p class="text-sm text-red-700" aria-live="polite">
+                {move || error.get().unwrap_or_default()}
+            </p>
+        </fieldset>
+    }
+}
+
+/// The feature flags of this build, for development: soft flags can be overridden
+/// in every context from here.
+#[component]
+fn FlagsPanel() -> impl IntoView {
+    view! {
+        <section class="mt-6 border-t pt-2">
+            <h2 class="font-medium">"Feature flags"</h2>
+            <table class="text-sm">
+                {flags::FLAGS.iter().map(|flag| view! { <FlagRow flag=*flag /> }).collect_view()}
+            </table>
+        </section>
+    }
+}
+
+#[component]
+fn FlagRow(flag: Flag) -> impl IntoView {
+    let baked = if flag.baked { "on" } else { "off" };
+    let setting = RwSignal::new(flags::override_of(flag));
+    let control = if flag.soft {
+        let on_change = move |ev| {
+            let value = match event_target_value(&ev).as_str() {
+                "on" => Some(true),
+                "off" => Some(false),
+                _ => None,
+            };
+            spawn_local(async move {
+                match flags::set_override(flag, value).await {
+                    Ok(()) => setting.set(value),
+                    Err(e) => gloo_console::error!("Failed to override the flag:", e),
+                }
+            });
+        };
+        view! {
+            <select
+                class="border rounded"
+                aria-label=flag.name
+                prop:value=move || match setting.get() {
+                    Some(true) => "on",
+                    Some(false) => "off",
+                    None => "default",
+                }
+                on:change=on_change
+            >
+                <option value="default">{format!("Default ({baked})")}</option>
+                <option value="on">"On"</option>
+                <option value="off">"Off"</option>
+            </select>
+        }
+        .into_any()
+    } else {
+        view! { <span>{format!("{baked} (hard)")}</span> }.into_any()
+    };
+
+    view! {
+        <tr>
+            <td class="pr-2 font-mono">{flag.name}</td>
+            <td class="pr-2">{control}</td>
+            <td>{flag.description}</td>
+        </tr>
+    }
+}
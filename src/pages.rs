@@ -0,0 +1,114 @@
+//! Opening extension pages.
+//!
+//! [`open`] focuses the page's tab if it's already open, instead of opening another
+//! one. The options page goes through `runtime.openOptionsPage`, which opens it
+//! wherever the browser shows it (a tab in Chrome, `about:addons` in Firefox when it
+//! uses `options_ui`). Content scripts can't use either API, so they ask the
+//! background, which needs [`install`].
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    browser,
+    entry::{environment, Context},
+    error::WextError,
+    messaging,
+};
+
+pub use crate::registry::ContextId as PageId;
+
+/// Message name used by content scripts to ask the background to open a page.
+const OPEN_MESSAGE: &str = "wext.pages.open";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenRequest {
+    page: String,
+    query: Option<String>,
+}
+
+/// The parts of `tabs.Tab` needed to find and focus a tab.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Tab {
+    id: Option<i32>,
+    window_id: i32,
+    url: Option<String>,
+}
+
+/// Open `page`, or focus its tab if it's already open.
+pub async fn open(page: PageId) -> Result<(), WextError> {
+    open_with_query(page, None).await
+}
+
+/// Open `page` with a query string (without the leading `?`), e.g. `"from=1.0"`.
+///
+/// An already open tab of the page is focused and navigated to the new query.
+pub async fn open_with_query(page: PageId, query: Option<&str>) -> Result<(), WextError> {
+    if !page.is_page() {
+        return Err(WextError::Other(format!("{page:?} isn't an HTML page")));
+    }
+    if environment().context == Context::Content {
+        let request = OpenRequest {
+            page: page.spec().name.to_string(),
+            query: query.map(str::to_string),
+        };
+        return Ok(messaging::send::<_, ()>(OPEN_MESSAGE, &request).await?);
+    }
+    if page.spec().kind == Context::Options && query.is_none() {
+        browser::runtime().open_options_page().await?;
+        return Ok(());
+    }
+
+    let url = match query {
+        Some(query) => format!("{}?{query}", page.url()),
+        None => page.url(),
+    };
+    match find_tab(page).await? {
+        Some(Tab {
+            id: Some(id),
+            window_id,
+            ..
+        }) => {
+            let mut properties = vec![("active", true.into())];
+            if query.is_some() {
+                properties.push(("url", url.into()));
+            }
+            browser::tabs()
+                .update(id, browser::object(&properties))
+                .await?;
+            browser::windows()
+                .update(window_id, browser::object(&[("focused", true.into())]))
+                .await?;
+        }
+        _ => {
+            browser::tabs()
+                .create(browser::object(&[("url", url.into())]))
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Find a tab showing `page`.
+///
+/// Extension URLs can't be used in `tabs.query` match patterns, so every tab is
+/// checked. Tabs of the extension's own pages always include their URL.
+async fn find_tab(page: PageId) -> Result<Option<Tab>, WextError> {
+    let tabs = browser::tabs().query(browser::object(&[])).await?;
+    let tabs: Vec<Tab> = serde_wasm_bindgen::from_value(tabs)?;
+    Ok(tabs
+        .into_iter()
+        .find(|tab| tab.url.as_deref().is_some_and(|url| page.matches_url(url))))
+}
+
+/// Open pages on behalf of content scripts. Call this in the background script,
+/// before [`messaging::install`].
+pub fn install() {
+    messaging::handle(OPEN_MESSAGE, |request: OpenRequest, _| async move {
+        let page = PageId::from_name(&request.page)
+            .ok_or_else(|| format!("Unknown page {:?}", request.page))?;
+        open_with_query(page, request.query.as_deref())
+            .await
+            .map_err(|e| format!("Failed to open {:?}: {e}", request.page))
+    });
+}
@@ -1,13 +1,106 @@
-use leptos::prelude::*;
-use wasm_bindgen::prelude::*;
+use leptos::{prelude::*, spawn::spawn_local};
 
-#[wasm_bindgen]
+use crate::{
+    a11y,
+    autosize::AutoSize,
+    capture,
+    entry::wext_entry,
+    hotkeys::{self, HotkeyHelp},
+    i18n,
+    icon::Icon,
+    pages::{self, PageId},
+    print,
+    sessions::Sessions,
+    surface, t,
+    tab_groups::GroupByDomain,
+    toast::{self, Toasts},
+    tts::{self, SpeakOptions},
+    windows::{self, Bounds},
+};
+
+#[wext_entry(popup)]
 pub async fn popup_page() {
+    a11y::close_on_escape();
+    windows::remember_bounds();
+    surface::connect();
     mount_to_body(|| {
+        i18n::provide_direction();
+        let toaster = toast::provide_toasts();
+        let open_options = move || {
+            spawn_local(async move {
+                if let Err(e) = pages::open(PageId::Options).await {
+                    toaster.error(format!("Couldn't open the options: {e}"));
+                }
+            })
+        };
+        let detach = move || {
+            spawn_local(async move {
+                match windows::open_app_window(PageId::Popup, Bounds::size(240, 320)).await {
+                    Ok(_) => {
+                        let _ = window().close();
+                    }
+                    Err(e) => {
+                        toaster.error(format!("Couldn't open a window: {e}"));
+                    }
+                }
+            })
+        };
+        let read_aloud = move || {
+            spawn_local(async move {
+                match capture::active_tab_text().await {
+                    Ok(Some(text)) => {
+                        if let Err(e) = tts::speak(&text, &SpeakOptions::default()).await {
+                            toaster.error(format!("Couldn't read the selection aloud: {e}"));
+                        }
+                    }
+                    Ok(None) => {
+                        toaster.error("Select some text on the page first.");
+                    }
+                    Err(e) => {
+                        toaster.error(format!("Couldn't get the selection: {e}"));
+                    }
+                }
+            })
+        };
+        let print_article = move || {
+            spawn_local(async move {
+                let opening = toaster.progress("Opening the article report…");
+                match print::open_and_print(PageId::Export).await {
+                    Ok(_) => toaster.dismiss(opening),
+                    Err(e) => {
+                        toaster.fail(opening, format!("Couldn't open the article report: {e}"))
+                    }
+                }
+            })
+        };
+        hotkeys::use_hotkey("o", "Open the options", open_options);
+        hotkeys::use_hotkey("w", "Open in a window", detach);
+        hotkeys::use_hotkey("r", "Read the selection aloud", read_aloud);
+        hotkeys::use_hotkey("p", "Print the last article", print_article);
         view! {
-            <p class="bg-blue-200 h-[200px] w-[200px] flex items-center justify-center">
-                "Hello, popup page!"
-            </p>
+            <Toasts />
+            <HotkeyHelp />
+            <AutoSize>
+                <p class="bg-blue-200 h-[200px] w-[200px] flex items-center justify-center">
+                    {t!("popupGreeting")}
+                </p>
+                <button type="button" class="w-full px-3 py-1 underline" on:click=move |_| open_options()>
+                    <Icon name="settings" class="inline mr-1" />
+                    "Options"
+                </button>
+                <button type="button" class="w-full px-3 py-1 underline" on:click=move |_| detach()>
+                    <Icon name="window" class="inline mr-1" />
+                    "Open in a window"
+                </button>
+                <button type="button" class="w-full px-3 py-1 underline" on:click=move |_| read_aloud()>
+                    "Read selection aloud"
+                </button>
+                <button type="button" class="w-full px-3 py-1 underline" on:click=move |_| print_article()>
+                    "Print last article"
+                </button>
+                <GroupByDomain />
+                <Sessions />
+            </AutoSize>
         }
     })
 }
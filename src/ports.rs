@@ -0,0 +1,451 @@
+//! Long-lived channels over `runtime.connect` ports.
+//!
+//! One-shot messages ([`messaging`](crate::messaging)) suit requests; a page that
+//! follows something as it happens (a download's progress, a log, a live count)
+//! keeps a port open instead. [`connect`] opens a named port to the background as a
+//! [`Connection`]: a [`Stream`] of the messages it receives and a [`Sink`] of the
+//! ones it sends (or [`Connection::post`]), both serde types, sent as JSON-compatible
+//! objects.
+//!
+//! The background registers a handler per name with [`serve`] (before [`install`]),
+//! which gets one [`Connection`] per page or content script that connects, with its
+//! [`Sender`], and runs until it returns. [`broadcast`] and [`send_to_tab`] reach
+//! every open connection of a name, or those of one tab, e.g. to push an update to
+//! every popup and content script at once:
+//!
+//! ```ignore
+//! // In the background:
+//! ports::serve("progress", |mut connection: Connection<Subscribe, Progress>| async move {
+//!     while let Some(Subscribe { job }) = connection.next().await {
+//!         connection.post(&progress_of(job))?;
+//!     }
+//! });
+//! ports::install();
+//! ports::broadcast("progress", &Progress { job, done: 3, total: 10 })?;
+//!
+//! // In the popup:
+//! let mut progress = ports::connect::<Progress, Subscribe>("progress");
+//! progress.post(&Subscribe { job })?;
+//! while let Some(update) = progress.next().await { .. }
+//! ```
+//!
+//! A port disconnects when the background restarts (an update, a reload, Firefox
+//! unloading an event page), so connections opened with [`connect`] reconnect by
+//! themselves, with backoff, and messages posted in between are sent once they're
+//! back. The background sees each reconnection as a new connection. They give up,
+//! and the stream ends, after [`MAX_RECONNECTS`] disconnects in a row that came soon
+//! after connecting (e.g. nothing serves the name), when the extension context is
+//! invalidated, or when [`Connection::close`] is called. Connections the background
+//! accepted end when the other side goes away.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::HashMap,
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    rc::{Rc, Weak},
+    task::{Context, Poll},
+};
+
+use futures::{
+    channel::mpsc::{self, UnboundedReceiver, UnboundedSender},
+    Sink, Stream,
+};
+use gloo_timers::callback::Timeout;
+use serde::{de::DeserializeOwned, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{
+    browser::{self, Port},
+    clock::{Clock, SystemClock},
+    content,
+    messaging::{MessageError, Sender},
+    retry::Backoff,
+};
+
+/// Prefix of the ports' names, keeping them apart from the runtime's own ports.
+const PORT_PREFIX: &str = "wext.port:";
+
+/// How many disconnects in a row, each soon after connecting, before a connection
+/// gives up.
+pub const MAX_RECONNECTS: u32 = 5;
+/// How long a port has to stay connected for its disconnect not to count as a failed
+/// reconnect.
+const STABLE_MS: f64 = 10_000.0;
+/// The delays between reconnects.
+const RECONNECT_BACKOFF: Backoff = Backoff {
+    base_delay_ms: 200.0,
+    max_delay_ms: 5_000.0,
+};
+
+type Server = Rc<dyn Fn(Port, Sender)>;
+
+/// An open connection the background accepted.
+struct Peer {
+    id: u64,
+    port: Port,
+    sender: Sender,
+}
+
+thread_local! {
+    static SERVERS: RefCell<HashMap<String, Server>> = RefCell::default();
+    /// The open connections, by port name.
+    static PEERS: RefCell<HashMap<String, Vec<Peer>>> = RefCell::default();
+    static NEXT_PEER: Cell<u64> = const { Cell::new(0) };
+}
+
+/// A named port, as a stream of `In` messages and a sink of `Out` ones.
+pub struct Connection<In, Out> {
+    shared: Rc<Shared>,
+    messages: UnboundedReceiver<JsValue>,
+    _types: PhantomData<fn(Out) -> In>,
+}
+
+/// The state the port's listeners share with the [`Connection`].
+struct Shared {
+    name: String,
+    /// The accepting side's peer ID and the other side, in the background.
+    accepted: Option<(u64, Sender)>,
+    incoming: UnboundedSender<JsValue>,
+    state: RefCell<State>,
+}
+
+/// A port's `onMessage` and `onDisconnect` listeners.
+type Listeners = (Closure<dyn FnMut(JsValue)>, Closure<dyn FnMut()>);
+
+#[derive(Default)]
+struct State {
+    /// Unset while reconnecting.
+    port: Option<Port>,
+    /// Messages posted while reconnecting.
+    queue: Vec<JsValue>,
+    closed: bool,
+    /// Reconnects since the port last stayed connected for [`STABLE_MS`].
+    attempts: u32,
+    connected_at: f64,
+    /// The current port's listeners.
+    listeners: Option<Listeners>,
+    reconnect: Option<Timeout>,
+}
+
+/// Open a connection to the background's [`serve`] handler for `name`.
+pub fn connect<In, Out>(name: &str) -> Connection<In, Out>
+where
+    In: DeserializeOwned,
+    Out: Serialize,
+{
+    let connection = Connection::new(format!("{PORT_PREFIX}{name}"), None);
+    open(&connection.shared);
+    connection
+}
+
+/// In the background, run `handler` for every connection to `name`. Register before
+/// calling [`install`].
+pub fn serve<In, Out, F, Fut>(name: &str, handler: F)
+where
+    In: DeserializeOwned + 'static,
+    Out: Serialize + 'static,
+    F: Fn(Connection<In, Out>) -> Fut + 'static,
+    Fut: Future<Output = ()> + 'static,
+{
+    let name = format!("{PORT_PREFIX}{name}");
+    let server: Server = Rc::new({
+        let name = name.clone();
+        move |port, sender| {
+            let id = NEXT_PEER.get();
+            NEXT_PEER.set(id + 1);
+            PEERS.with_borrow_mut(|peers| {
+                peers.entry(name.clone()).or_default().push(Peer {
+                    id,
+                    port: port.clone(),
+                    sender: sender.clone(),
+                })
+            });
+            // Attached right away, so messages sent along with the connect aren't lost.
+            let connection = Connection::new(name.clone(), Some((id, sender)));
+            attach(&connection.shared, port);
+            spawn_local(handler(connection));
+        }
+    });
+    SERVERS.with_borrow_mut(|servers| servers.insert(name, server));
+}
+
+/// Register the `onConnect` listener. Must be called synchronously during startup.
+pub fn install() {
+    browser::listen(&browser::runtime().on_connect(), |port, _| {
+        let port: Port = port.unchecked_into();
+        let name = port.name();
+        let Some(server) = SERVERS.with_borrow(|servers| servers.get(&name).cloned()) else {
+            return;
+        };
+        let sender = Sender::from_js(&port.sender());
+        server(port, sender);
+    });
+}
+
+/// Send `message` to every open connection to `name`. Returns how many it reached.
+pub fn broadcast<T: Serialize>(name: &str, message: &T) -> Result<usize, MessageError> {
+    post_to_peers(name, message, |_| true)
+}
+
+/// Send `message` to the open connections to `name` from the content scripts (or
+/// pages) in a tab. Returns how many it reached.
+pub fn send_to_tab<T: Serialize>(
+    name: &str,
+    tab_id: i32,
+    message: &T,
+) -> Result<usize, MessageError> {
+    post_to_peers(name, message, |sender| sender.tab_id == Some(tab_id))
+}
+
+/// The senders of the open connections to `name`.
+pub fn peers(name: &str) -> Vec<Sender> {
+    PEERS.with_borrow(|peers| {
+        peers
+            .get(&format!("{PORT_PREFIX}{name}"))
+            .into_iter()
+            .flatten()
+            .map(|peer| peer.sender.clone())
+            .collect()
+    })
+}
+
+fn post_to_peers<T: Serialize>(
+    name: &str,
+    message: &T,
+    matches: impl Fn(&Sender) -> bool,
+) -> Result<usize, MessageError> {
+    let message = to_js(message)?;
+    let ports: Vec<Port> = PEERS.with_borrow(|peers| {
+        peers
+            .get(&format!("{PORT_PREFIX}{name}"))
+            .into_iter()
+            .flatten()
+            .filter(|peer| matches(&peer.sender))
+            .map(|peer| peer.port.clone())
+            .collect()
+    });
+    // A port that disconnected since is left to its onDisconnect listener.
+    Ok(ports
+        .iter()
+        .filter(|port| port.post_message(&message).is_ok())
+        .count())
+}
+
+impl<In, Out> Connection<In, Out> {
+    fn new(name: String, accepted: Option<(u64, Sender)>) -> Self {
+        let (incoming, messages) = mpsc::unbounded();
+        Connection {
+            shared: Rc::new(Shared {
+                name,
+                accepted,
+                incoming,
+                state: RefCell::default(),
+            }),
+            messages,
+            _types: PhantomData,
+        }
+    }
+
+    /// Who connected, for connections the background accepted.
+    pub fn sender(&self) -> Option<&Sender> {
+        self.shared.accepted.as_ref().map(|(_, sender)| sender)
+    }
+
+    /// Whether the connection is open or reconnecting, rather than closed for good.
+    pub fn is_open(&self) -> bool {
+        !self.shared.state.borrow().closed
+    }
+
+    /// Disconnect for good. The stream ends, and posting fails.
+    pub fn close(&self) {
+        close(&self.shared);
+    }
+}
+
+impl<In, Out: Serialize> Connection<In, Out> {
+    /// Send `message`, or queue it while reconnecting.
+    pub fn post(&self, message: &Out) -> Result<(), MessageError> {
+        let message = to_js(message)?;
+        let mut state = self.shared.state.borrow_mut();
+        if state.closed {
+            return Err(MessageError::Send("the connection is closed".to_string()));
+        }
+        match &state.port {
+            Some(port) => port
+                .post_message(&message)
+                .map_err(|e| MessageError::Send(format!("{e:?}"))),
+            None => {
+                state.queue.push(message);
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<In, Out> Drop for Connection<In, Out> {
+    fn drop(&mut self) {
+        close(&self.shared);
+    }
+}
+
+impl<In: DeserializeOwned, Out> Stream for Connection<In, Out> {
+    type Item = In;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<In>> {
+        loop {
+            let Poll::Ready(message) = Pin::new(&mut self.messages).poll_next(cx) else {
+                return Poll::Pending;
+            };
+            let Some(message) = message else {
+                return Poll::Ready(None);
+            };
+            match serde_wasm_bindgen::from_value(message) {
+                Ok(message) => return Poll::Ready(Some(message)),
+                Err(e) => {
+                    gloo_console::warn!(format!("Ignoring a message on {}: {e}", self.shared.name))
+                }
+            }
+        }
+    }
+}
+
+impl<In, Out: Serialize> Sink<Out> for Connection<In, Out> {
+    type Error = MessageError;
+
+    fn poll_ready(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), MessageError>> {
+        if self.is_open() {
+            Poll::Ready(Ok(()))
+        } else {
+            Poll::Ready(Err(MessageError::Send(
+                "the connection is closed".to_string(),
+            )))
+        }
+    }
+
+    fn start_send(self: Pin<&mut Self>, message: Out) -> Result<(), MessageError> {
+        self.post(&message)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), MessageError>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn poll_close(self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Result<(), MessageError>> {
+        self.close();
+        Poll::Ready(Ok(()))
+    }
+}
+
+/// Connect a new port, and send what was queued while reconnecting.
+fn open(shared: &Rc<Shared>) {
+    let info = browser::object(&[("name", shared.name.clone().into())]);
+    match browser::runtime().connect(info) {
+        Ok(port) => {
+            attach(shared, port.clone());
+            let queue = std::mem::take(&mut shared.state.borrow_mut().queue);
+            for message in queue {
+                let _ = port.post_message(&message);
+            }
+        }
+        Err(e) => {
+            gloo_console::warn!(format!("Failed to connect {}:", shared.name), e);
+            disconnected(shared);
+        }
+    }
+}
+
+fn attach(shared: &Rc<Shared>, port: Port) {
+    let on_message = Closure::<dyn FnMut(JsValue)>::new({
+        let incoming = shared.incoming.clone();
+        move |message| {
+            let _ = incoming.unbounded_send(message);
+        }
+    });
+    let on_disconnect = Closure::<dyn FnMut()>::new({
+        let shared = Rc::downgrade(shared);
+        move || {
+            if let Some(shared) = shared.upgrade() {
+                disconnected(&shared);
+            }
+        }
+    });
+    port.on_message()
+        .add_listener(on_message.as_ref().unchecked_ref());
+    port.on_disconnect()
+        .add_listener(on_disconnect.as_ref().unchecked_ref());
+    let mut state = shared.state.borrow_mut();
+    state.port = Some(port);
+    state.connected_at = SystemClock.now();
+    // The previous port's listeners can go: it's disconnected, and this doesn't run
+    // from its onDisconnect.
+    state.listeners = Some((on_message, on_disconnect));
+}
+
+/// The other side went away: reconnect, or end the stream.
+fn disconnected(shared: &Rc<Shared>) {
+    let mut state = shared.state.borrow_mut();
+    state.port = None;
+    if state.closed {
+        return;
+    }
+    if SystemClock.now() - state.connected_at > STABLE_MS {
+        state.attempts = 0;
+    }
+    state.attempts += 1;
+    let reconnect = shared.accepted.is_none() && content::is_context_valid();
+    if !reconnect || state.attempts > MAX_RECONNECTS {
+        if reconnect {
+            gloo_console::warn!(format!(
+                "Giving up on {} after {MAX_RECONNECTS} reconnects",
+                shared.name
+            ));
+        }
+        drop(state);
+        close(shared);
+        return;
+    }
+    let delay = RECONNECT_BACKOFF.delay_ms(state.attempts);
+    let weak: Weak<Shared> = Rc::downgrade(shared);
+    state.reconnect = Some(Timeout::new(delay as u32, move || {
+        let Some(shared) = weak.upgrade() else {
+            return;
+        };
+        // Dropping a timeout cancels it, which it can't do while it runs, and a failed
+        // connect schedules the next one.
+        if let Some(timeout) = shared.state.borrow_mut().reconnect.take() {
+            timeout.forget();
+        }
+        open(&shared);
+    }));
+}
+
+fn close(shared: &Shared) {
+    let mut state = shared.state.borrow_mut();
+    if state.closed {
+        return;
+    }
+    state.closed = true;
+    state.queue.clear();
+    // Dropping the timeout cancels the reconnect.
+    state.reconnect = None;
+    if let Some(port) = state.port.take() {
+        port.disconnect();
+    }
+    shared.incoming.close_channel();
+    if let Some((id, _)) = &shared.accepted {
+        PEERS.with_borrow_mut(|peers| {
+            if let Some(peers) = peers.get_mut(&shared.name) {
+                peers.retain(|peer| peer.id != *id);
+            }
+        });
+    }
+}
+
+fn to_js(message: &impl Serialize) -> Result<JsValue, MessageError> {
+    message
+        .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+        .map_err(|e| MessageError::Codec(e.to_string()))
+}
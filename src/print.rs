@@ -0,0 +1,89 @@
+//! Printing and PDF export of extension pages.
+//!
+//! Pages meant to be printed are registered as `Report`s, e.g. `Export: Report,
+//! "export_page", "export.html", reload = true;`, with `#[wext_entry(report)]`.
+//! wextrunk links a print stylesheet into them, with defaults for paper (no
+//! backgrounds, no page breaks inside blocks, link targets spelled out) and anything
+//! in `print.css` (see `[print]` in `wextrunk.toml`). Elements with the `no-print`
+//! class, like the page's own print button, are left out of the printout; Tailwind's
+//! `print:` variants work as well.
+//!
+//! The browser's print dialog offers saving as PDF, so there's no separate export:
+//!
+//! ```ignore
+//! // Open the report, which prints itself once it has rendered.
+//! print::open_and_print(PageId::Export).await?;
+//!
+//! // In the report page:
+//! if print::requested() {
+//!     print::print_when_rendered().await?;
+//! }
+//! ```
+
+use js_sys::{Promise, Reflect};
+use leptos::{ev, prelude::*};
+use wasm_bindgen_futures::JsFuture;
+
+use crate::{
+    error::WextError,
+    pages::{self, PageId},
+};
+
+/// Query string that asks a report to print itself.
+const PRINT_QUERY: &str = "print";
+
+/// Open the print dialog for the current page. Returns once the dialog is closed.
+pub fn print() -> Result<(), WextError> {
+    Ok(window().print()?)
+}
+
+/// Open the print dialog once the page has rendered and its fonts have loaded, e.g.
+/// right after mounting a report whose data is already loaded.
+pub async fn print_when_rendered() -> Result<(), WextError> {
+    let fonts = Reflect::get(&document(), &"fonts".into())?;
+    if fonts.is_object() {
+        let ready = Reflect::get(&fonts, &"ready".into())?;
+        JsFuture::from(Promise::from(ready)).await?;
+    }
+    // The first frame runs pending layout, the second is after it's painted.
+    next_frame().await?;
+    next_frame().await?;
+    print()
+}
+
+/// Open `page` (a report) in a tab, asking it to print itself.
+pub async fn open_and_print(page: PageId) -> Result<(), WextError> {
+    pages::open_with_query(page, Some(PRINT_QUERY)).await
+}
+
+/// Whether the current page was opened with [`open_and_print`].
+pub fn requested() -> bool {
+    window()
+        .location()
+        .search()
+        .unwrap_or_default()
+        .trim_start_matches('?')
+        .split('&')
+        .any(|param| param == PRINT_QUERY)
+}
+
+/// Whether the page is being printed, e.g. to render a table in full rather than
+/// paginated.
+pub fn use_printing() -> ReadSignal<bool> {
+    let (printing, set_printing) = signal(false);
+    let before = window_event_listener(ev::beforeprint, move |_| set_printing.set(true));
+    let after = window_event_listener(ev::afterprint, move |_| set_printing.set(false));
+    on_cleanup(move || {
+        before.remove();
+        after.remove();
+    });
+    printing
+}
+
+async fn next_frame() -> Result<(), WextError> {
+    let frame = Promise::new(&mut |resolve, _| {
+        let _ = window().request_animation_frame(&resolve);
+    });
+    JsFuture::from(frame).await?;
+    Ok(())
+}
@@ -0,0 +1,341 @@
+//! Reader-style extraction of a page's main content, for content scripts.
+//!
+//! [`extract`] scores the page's paragraphs and their containers with the usual
+//! readability heuristics (text length, commas, link density, and hints in class
+//! names and ids), picks the best container and turns it into a list of text
+//! [`Block`]s. The result is sent to the background with [`send`]; long articles
+//! exceed a comfortable message size, so [`messaging::send`] transfers them in chunks.
+//! The background keeps the latest one (see [`install`] and [`latest`]).
+//!
+//! ```ignore
+//! if let Some(article) = reader::extract() {
+//!     reader::send(&article).await?;
+//! }
+//! ```
+
+use js_sys::{Map, Reflect};
+use leptos::prelude::document;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{Document, Element};
+
+use crate::{
+    error::WextError,
+    messaging::{self, MessageError},
+    storage,
+};
+
+/// Message name used to send an article to the background.
+const ARTICLE_MESSAGE: &str = "wext.reader.article";
+
+/// Storage key for the latest article.
+const LATEST_KEY: &str = "wext.reader.latest";
+
+/// Elements whose text is scored.
+const SCORED: &str = "p, pre, td, blockquote";
+
+/// Elements that become [`Block`]s.
+const BLOCKS: &str = "h1, h2, h3, h4, h5, h6, p, pre, blockquote, li";
+
+/// Paragraphs shorter than this don't count.
+const MIN_PARAGRAPH_CHARS: usize = 25;
+
+/// Class name and id hints that a container is (or isn't) the content.
+const POSITIVE_HINTS: &[&str] = &[
+    "article", "body", "content", "entry", "main", "page", "post", "text", "blog", "story",
+];
+const NEGATIVE_HINTS: &[&str] = &[
+    "ad-", "banner", "comment", "footer", "header", "masthead", "menu", "meta", "nav", "promo",
+    "related", "share", "sidebar", "social", "sponsor", "widget",
+];
+
+/// A page's main content.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Article {
+    pub url: String,
+    pub title: String,
+    pub byline: Option<String>,
+    pub excerpt: Option<String>,
+    pub blocks: Vec<Block>,
+    pub word_count: usize,
+}
+
+/// A block of an article's text. Whitespace is collapsed, except in code.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "text", rename_all = "snake_case")]
+pub enum Block {
+    Heading(String),
+    Paragraph(String),
+    Code(String),
+    Quote(String),
+    ListItem(String),
+}
+
+impl Block {
+    pub fn text(&self) -> &str {
+        match self {
+            Block::Heading(text)
+            | Block::Paragraph(text)
+            | Block::Code(text)
+            | Block::Quote(text)
+            | Block::ListItem(text) => text,
+        }
+    }
+}
+
+impl Article {
+    /// The article as plain text, one block per paragraph.
+    pub fn text(&self) -> String {
+        self.blocks
+            .iter()
+            .map(Block::text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Extract the main content of the current page. Returns `None` if nothing on the
+/// page looks like an article.
+pub fn extract() -> Option<Article> {
+    extract_from(&document())
+}
+
+/// Extract the main content of `document`.
+pub fn extract_from(document: &Document) -> Option<Article> {
+    let container = best_container(document)?;
+    let blocks = blocks(&container);
+    if blocks.is_empty() {
+        return None;
+    }
+    let word_count = blocks
+        .iter()
+        .map(|block| block.text().split_whitespace().count())
+        .sum();
+    Some(Article {
+        url: document.url().unwrap_or_default(),
+        title: meta(document, "meta[property='og:title']")
+            .unwrap_or_else(|| clean_title(&document.title())),
+        byline: meta(document, "meta[name='author']"),
+        excerpt: meta(document, "meta[name='description']")
+            .or_else(|| meta(document, "meta[property='og:description']")),
+        blocks,
+        word_count,
+    })
+}
+
+/// Send `article` to the background, in chunks if it's large.
+pub async fn send(article: &Article) -> Result<(), MessageError> {
+    messaging::send(ARTICLE_MESSAGE, article).await
+}
+
+/// Keep the latest article sent by a content script. Call this in the background
+/// script, before [`messaging::install`].
+pub fn install() {
+    messaging::handle(ARTICLE_MESSAGE, |article: Article, _| async move {
+        storage::set(LATEST_KEY, &article)
+            .await
+            .map_err(|e| format!("Failed to store the article: {e}"))
+    });
+}
+
+/// The latest article sent to the background, e.g. for the popup to show.
+pub async fn latest() -> Result<Option<Article>, WextError> {
+    storage::get(LATEST_KEY).await
+}
+
+/// A scored container.
+struct Candidate {
+    element: Element,
+    score: f64,
+}
+
+/// The element that most likely holds the content.
+fn best_container(document: &Document) -> Option<Element> {
+    let mut candidates: Vec<Candidate> = Vec::new();
+    // Candidate index by element.
+    let indices = Map::new();
+    let mut add_score = |element: Element, score: f64| {
+        let index = match indices.get(&element).as_f64() {
+            Some(index) => index as usize,
+            None => {
+                indices.set(&element, &(candidates.len() as f64).into());
+                let initial = tag_weight(&element.tag_name()) + class_weight(&element);
+                candidates.push(Candidate {
+                    element,
+                    score: initial,
+                });
+                candidates.len() - 1
+            }
+        };
+        candidates[index].score += score;
+    };
+
+    let paragraphs = document.query_selector_all(SCORED).ok()?;
+    for index in 0..paragraphs.length() {
+        let Some(paragraph) = paragraphs.item(index) else {
+            continue;
+        };
+        let text = collapse(&paragraph.text_content().unwrap_or_default());
+        if text.chars().count() < MIN_PARAGRAPH_CHARS {
+            continue;
+        }
+        let score = paragraph_score(&text);
+        let Some(parent) = paragraph.parent_element() else {
+            continue;
+        };
+        let grandparent = parent.parent_element();
+        add_score(parent, score);
+        if let Some(grandparent) = grandparent {
+            add_score(grandparent, score / 2.0);
+        }
+    }
+
+    candidates
+        .into_iter()
+        .map(|candidate| {
+            let score = candidate.score * (1.0 - link_density(&candidate.element));
+            (candidate.element, score)
+        })
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(element, _)| element)
+}
+
+/// The blocks of text in `container`, skipping boilerplate inside it.
+fn blocks(container: &Element) -> Vec<Block> {
+    let Ok(elements) = container.query_selector_all(BLOCKS) else {
+        return Vec::new();
+    };
+    let mut blocks = Vec::new();
+    for index in 0..elements.length() {
+        let Some(element) = elements.item(index) else {
+            continue;
+        };
+        let element: Element = element.unchecked_into();
+        // A block inside another block (e.g. a paragraph in a list item) is part of
+        // the outer one's text.
+        let nested = element
+            .parent_element()
+            .and_then(|parent| parent.closest(BLOCKS).ok().flatten())
+            .is_some_and(|outer| container.contains(Some(outer.as_ref())));
+        if nested || is_boilerplate(&element, container) || link_density(&element) > 0.5 {
+            continue;
+        }
+        let text = element.text_content().unwrap_or_default();
+        let block = match element.tag_name().to_ascii_lowercase().as_str() {
+            // Code keeps its whitespace.
+            "pre" => Block::Code(text.trim_matches('\n').to_string()),
+            tag => {
+                let text = collapse(&text);
+                if text.is_empty() {
+                    continue;
+                }
+                match tag {
+                    "blockquote" => Block::Quote(text),
+                    "li" => Block::ListItem(text),
+                    "p" => Block::Paragraph(text),
+                    _ => Block::Heading(text),
+                }
+            }
+        };
+        blocks.push(block);
+    }
+    blocks
+}
+
+/// Whether `element`, or an ancestor up to `container`, looks like navigation,
+/// comments, ads or similar.
+fn is_boilerplate(element: &Element, container: &Element) -> bool {
+    let mut current = Some(element.clone());
+    while let Some(element) = current {
+        if element == *container {
+            return false;
+        }
+        if class_weight(&element) < 0.0
+            || matches!(
+                element.tag_name().to_ascii_lowercase().as_str(),
+                "nav" | "aside" | "footer" | "form" | "script" | "style"
+            )
+        {
+            return true;
+        }
+        current = element.parent_element();
+    }
+    false
+}
+
+/// How much a paragraph's text contributes to its containers.
+fn paragraph_score(text: &str) -> f64 {
+    let commas = text.matches([',', '，', '、']).count() as f64;
+    let length = (text.chars().count() as f64 / 100.0).min(3.0);
+    1.0 + commas + length
+}
+
+/// Starting score for a container, by tag.
+fn tag_weight(tag: &str) -> f64 {
+    match tag.to_ascii_lowercase().as_str() {
+        "article" => 10.0,
+        "div" | "main" | "section" => 5.0,
+        "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "dl" | "dd" | "dt" | "li" | "form" | "address" => -3.0,
+        "h1" | "h2" | "h3" | "h4" | "h5" | "h6" | "th" => -5.0,
+        _ => 0.0,
+    }
+}
+
+/// Score adjustment from hints in the class name and id.
+fn class_weight(element: &Element) -> f64 {
+    let hints = format!("{} {}", element.class_name(), element.id()).to_ascii_lowercase();
+    let mut weight = 0.0;
+    if NEGATIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight -= 25.0;
+    }
+    if POSITIVE_HINTS.iter().any(|hint| hints.contains(hint)) {
+        weight += 25.0;
+    }
+    weight
+}
+
+/// The share of `element`'s text that's inside links.
+fn link_density(element: &Element) -> f64 {
+    let length = collapse(&element.text_content().unwrap_or_default()).len();
+    if length == 0 {
+        return 0.0;
+    }
+    let Ok(links) = element.query_selector_all("a") else {
+        return 0.0;
+    };
+    let link_length: usize = (0..links.length())
+        .filter_map(|index| links.item(index))
+        .map(|link| collapse(&link.text_content().unwrap_or_default()).len())
+        .sum();
+    link_length as f64 / length as f64
+}
+
+/// The `content` of the first element matching `selector`, if it's not empty.
+pub(crate) fn meta(document: &Document, selector: &str) -> Option<String> {
+    let element = document.query_selector(selector).ok()??;
+    let content = Reflect::get(&element, &"content".into())
+        .ok()?
+        .as_string()?;
+    let content = collapse(&content);
+    (!content.is_empty()).then_some(content)
+}
+
+/// Drop a site name suffix like " | Example News" from a page title, if what's left
+/// is still a reasonable title.
+fn clean_title(title: &str) -> String {
+    let title = collapse(title);
+    for separator in [" | ", " - ", " – ", " — ", " :: "] {
+        if let Some((head, _)) = title.rsplit_once(separator) {
+            if head.split_whitespace().count() >= 3 {
+                return head.to_string();
+            }
+        }
+    }
+    title
+}
+
+/// Collapse runs of whitespace into single spaces, and trim.
+pub(crate) fn collapse(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
@@ -0,0 +1,125 @@
+//! The registry of every extension context: its entry function, kind, output file
+//! and reload policy.
+//!
+//! [`CONTEXTS`] is the single place pages and scripts are declared. It's recorded in
+//! the `wext_contexts` custom section of the wasm, from which wextrunk generates the
+//! HTML pages and scripts and fills in the manifest's `action.default_popup`, options
+//! page, `background` and `chrome_url_overrides`. At runtime, contexts can be
+//! looked up by [`ContextId`] or by name, e.g. to open a page.
+//!
+//! A context's `<title>` and other head tags stay in index.html, included with
+//! `data-wextrunk-include="WEXTRUNK_<NAME>"`, e.g. `WEXTRUNK_POPUP`.
+
+use crate::{browser, entry::Context};
+
+/// A registered context.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContextSpec {
+    pub id: ContextId,
+    /// The context's name, e.g. `Popup`.
+    pub name: &'static str,
+    pub kind: Context,
+    /// The `#[wext_entry]` function that starts it.
+    pub entry: &'static str,
+    /// The output file, relative to the extension root: an HTML file for pages, a
+    /// JavaScript file for scripts.
+    pub file: &'static str,
+    /// Whether it reloads when `trunk serve` rebuilds.
+    pub reload: bool,
+}
+
+macro_rules! contexts {
+    ($($id:ident: $kind:ident, $entry:literal, $file:literal, reload = $reload:literal;)*) => {
+        /// Identifies a context in [`CONTEXTS`].
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ContextId {
+            $($id,)*
+        }
+
+        /// Every context of the extension.
+        pub const CONTEXTS: &[ContextSpec] = &[
+            $(ContextSpec {
+                id: ContextId::$id,
+                name: stringify!($id),
+                kind: Context::$kind,
+                entry: $entry,
+                file: $file,
+                reload: $reload,
+            },)*
+        ];
+
+        /// The registry as read by wextrunk: one `name kind entry file reload` line
+        /// per context.
+        const SECTION: &str = concat!(
+            $(stringify!($id), " ", stringify!($kind), " ", $entry, " ", $file, " ",
+              stringify!($reload), "\n",)*
+        );
+    };
+}
+
+contexts! {
+    Popup: Popup, "popup_page", "popup.html", reload = true;
+    Options: Options, "options_page", "options.html", reload = true;
+    Debug: Page, "debug_page", "debug.html", reload = true;
+    Storage: Page, "storage_page", "storage.html", reload = true;
+    Update: Page, "update_page", "update.html", reload = true;
+    Farewell: Page, "farewell_page", "farewell.html", reload = true;
+    Export: Report, "export_page", "export.html", reload = true;
+    NewTab: NewTab, "newtab_page", "newtab.html", reload = true;
+    Offscreen: Page, "offscreen_page", "offscreen.html", reload = false;
+    Background: Background, "background_script", "background.js", reload = false;
+}
+
+#[cfg_attr(target_family = "wasm", link_section = "wext_contexts")]
+#[used]
+static REGISTRY: [u8; SECTION.len()] = section_bytes();
+
+const fn section_bytes<const N: usize>() -> [u8; N] {
+    let section = SECTION.as_bytes();
+    let mut bytes = [0; N];
+    let mut i = 0;
+    while i < N {
+        bytes[i] = section[i];
+        i += 1;
+    }
+    bytes
+}
+
+impl ContextId {
+    pub fn spec(self) -> &'static ContextSpec {
+        CONTEXTS.iter().find(|spec| spec.id == self).unwrap()
+    }
+
+    /// Look a context up by name, ignoring case.
+    pub fn from_name(name: &str) -> Option<ContextId> {
+        CONTEXTS
+            .iter()
+            .find(|spec| spec.name.eq_ignore_ascii_case(name))
+            .map(|spec| spec.id)
+    }
+
+    /// The context the current instance is running as.
+    pub fn current() -> Option<ContextId> {
+        let entry = crate::entry::environment().entry;
+        CONTEXTS
+            .iter()
+            .find(|spec| spec.entry == entry)
+            .map(|spec| spec.id)
+    }
+
+    /// Whether this context is an HTML page, rather than a script.
+    pub fn is_page(self) -> bool {
+        self.spec().file.ends_with(".html")
+    }
+
+    /// The full URL of the context's file, e.g. `chrome-extension://<id>/options.html`.
+    pub fn url(self) -> String {
+        browser::runtime().get_url(self.spec().file)
+    }
+
+    /// Whether `url` shows this context's file, with any query or fragment.
+    pub fn matches_url(self, url: &str) -> bool {
+        url.strip_prefix(&self.url())
+            .is_some_and(|rest| rest.is_empty() || rest.starts_with(['?', '#']))
+    }
+}
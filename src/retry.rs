@@ -0,0 +1,77 @@
+//! Retry policies shared by the job queue and the fetch layer.
+
+/// Exponential backoff with jitter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Backoff {
+    /// Delay before the first retry. Doubles on each subsequent attempt.
+    pub base_delay_ms: f64,
+    /// Upper bound for a single delay.
+    pub max_delay_ms: f64,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            base_delay_ms: 500.0,
+            max_delay_ms: 30_000.0,
+        }
+    }
+}
+
+impl Backoff {
+    /// Delay to wait after `attempts` failed attempts (starting at 1).
+    ///
+    /// Uses "equal jitter": half of the exponential delay is fixed, the other half
+    /// random, so retries from many callers don't line up.
+    pub fn delay_ms(&self, attempts: u32) -> f64 {
+        let exponent = attempts.saturating_sub(1).min(30) as i32;
+        let delay = (self.base_delay_ms * 2f64.powi(exponent)).min(self.max_delay_ms);
+        delay * (0.5 + js_sys::Math::random() * 0.5)
+    }
+}
+
+/// How many times to retry, how long to wait in between, and when to give up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    /// Total attempts, including the first one.
+    pub max_attempts: u32,
+    pub backoff: Backoff,
+    /// Overall time budget in milliseconds, measured from the first attempt.
+    /// No retry is started if its delay would run past the deadline.
+    pub deadline_ms: Option<f64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            backoff: Backoff::default(),
+            deadline_ms: Some(60_000.0),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Never retry.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..RetryPolicy::default()
+        }
+    }
+
+    /// The delay before the next attempt, or `None` if the policy is exhausted.
+    ///
+    /// `elapsed_ms` is the time since the first attempt started, and `min_delay_ms`
+    /// lets callers honour server hints such as `Retry-After`.
+    pub fn next_delay_ms(&self, attempts: u32, elapsed_ms: f64, min_delay_ms: f64) -> Option<f64> {
+        if attempts >= self.max_attempts {
+            return None;
+        }
+        let delay = self.backoff.delay_ms(attempts).max(min_delay_ms);
+        match self.deadline_ms {
+            Some(deadline) if elapsed_ms + delay > deadline => None,
+            _ => Some(delay),
+        }
+    }
+}
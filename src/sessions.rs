@@ -0,0 +1,292 @@
+//! An end-to-end example: saving all tabs of a window as a session, and restoring it
+//! later.
+//!
+//! [`install`] in the background saves the current window's tabs (title, URL and
+//! favicon) when the [`COMMAND`] keyboard shortcut is pressed, and answers the popup's
+//! messages. Sessions are kept in IndexedDB (see [`idb`](crate::idb)), which only the
+//! background writes to. The popup's [`Sessions`] lists them in a
+//! [`VirtualList`], with buttons to save the window, and to restore or delete a
+//! session. Only tabs with `http(s)` and `file` URLs are saved, as browsers don't let
+//! extensions open their own pages in a new window.
+//!
+//! The shortcut needs a `commands` entry in the manifest:
+//!
+//! ```json
+//! "commands": {
+//!   "save-tabs": {
+//!     "suggested_key": { "default": "Alt+Shift+T" },
+//!     "description": "Save all tabs in the window"
+//!   }
+//! }
+//! ```
+
+use js_sys::{Array, Reflect};
+use leptos::{prelude::*, spawn::spawn_local};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::JsValue;
+
+use crate::{
+    browser,
+    clock::{Clock, SystemClock},
+    error::WextError,
+    format, health,
+    idb::{Database, Key},
+    messaging::{self, Request},
+    tabs::{BrowserTabs, TabQuery, Tabs},
+    toast,
+    virtual_list::VirtualList,
+};
+
+/// The `commands` entry that saves the current window.
+pub const COMMAND: &str = "save-tabs";
+
+/// The database the sessions are kept in, in its [`STORE`].
+const DB: Database = Database::new("wext.sessions", 1, &[STORE]);
+const STORE: &str = "sessions";
+
+/// Save the current window.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SaveWindow;
+
+impl Request for SaveWindow {
+    const NAME: &'static str = "wext.sessions.save";
+    type Response = Session;
+}
+
+/// List the saved sessions, newest first.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ListSessions;
+
+impl Request for ListSessions {
+    const NAME: &'static str = "wext.sessions.list";
+    type Response = Vec<Session>;
+}
+
+/// Do something with a saved session.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SessionAction {
+    /// Open its tabs in a new window.
+    Restore(Key),
+    Delete(Key),
+}
+
+impl Request for SessionAction {
+    const NAME: &'static str = "wext.sessions.action";
+    type Response = ();
+}
+
+/// URL schemes of the tabs that are saved.
+const SCHEMES: &[&str] = &["https://", "http://", "file://"];
+
+/// A saved tab.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedTab {
+    pub title: String,
+    pub url: String,
+    pub fav_icon_url: Option<String>,
+}
+
+/// The tabs of a window, as they were when it was saved.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Session {
+    /// Assigned when it's saved.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<Key>,
+    /// When it was saved, in milliseconds since the epoch.
+    pub saved_at: f64,
+    pub tabs: Vec<SavedTab>,
+}
+
+/// Save the shortcut's window, and answer the popup. Call this in the background.
+pub fn install() {
+    browser::listen(&browser::commands().on_command(), |command, tab| {
+        if command.as_string().as_deref() != Some(COMMAND) {
+            return;
+        }
+        let window_id = Reflect::get(&tab, &"windowId".into())
+            .ok()
+            .and_then(|id| id.as_f64())
+            .map(|id| id as i32);
+        spawn_local(async move {
+            match save_window(&BrowserTabs, window_id).await {
+                Ok(session) => gloo_console::log!(format!("Saved {} tabs.", session.tabs.len())),
+                Err(e) => health::record_error("sessions", format!("Couldn't save the tabs: {e}")),
+            }
+        });
+    });
+    messaging::handle_request(|_: SaveWindow, _| async move {
+        save_window(&BrowserTabs, None)
+            .await
+            .map_err(|e| e.to_string())
+    });
+    messaging::handle_request(|_: ListSessions, _| async move {
+        let mut sessions: Vec<Session> =
+            DB.store(STORE).get_all().await.map_err(|e| e.to_string())?;
+        sessions.reverse();
+        Ok(sessions)
+    });
+    messaging::handle_request(|action: SessionAction, _| async move {
+        let done = match action {
+            SessionAction::Restore(id) => restore(id).await,
+            SessionAction::Delete(id) => DB.store(STORE).delete(id).await,
+        };
+        done.map_err(|e| e.to_string())
+    });
+}
+
+/// Save the tabs of a window, or of the current one.
+pub async fn save_window(tabs: &impl Tabs, window_id: Option<i32>) -> Result<Session, WextError> {
+    let query = TabQuery {
+        window_id,
+        current_window: window_id.is_none().then_some(true),
+        ..Default::default()
+    };
+    let tabs: Vec<SavedTab> = tabs
+        .query(&query)
+        .await?
+        .into_iter()
+        .filter_map(|tab| {
+            let url = tab.url?;
+            SCHEMES
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+                .then(|| SavedTab {
+                    title: tab.title.unwrap_or_else(|| url.clone()),
+                    url,
+                    fav_icon_url: tab.fav_icon_url,
+                })
+        })
+        .collect();
+    if tabs.is_empty() {
+        return Err(WextError::Other(
+            "There are no web pages to save in this window.".to_string(),
+        ));
+    }
+    let mut session = Session {
+        id: None,
+        saved_at: SystemClock.now(),
+        tabs,
+    };
+    session.id = Some(DB.store(STORE).put(&session).await?);
+    Ok(session)
+}
+
+/// Open a session's tabs in a new window.
+async fn restore(id: Key) -> Result<(), WextError> {
+    let session: Session = DB
+        .store(STORE)
+        .get(id)
+        .await?
+        .ok_or_else(|| WextError::Other("The session was deleted.".to_string()))?;
+    let urls: Array = session
+        .tabs
+        .iter()
+        .map(|tab| JsValue::from(&tab.url))
+        .collect();
+    browser::windows()
+        .create(browser::object(&[
+            ("url", urls.into()),
+            ("focused", true.into()),
+        ]))
+        .await?;
+    Ok(())
+}
+
+/// The saved sessions, with buttons to save the current window and to restore or delete
+/// them. Needs [`toast::provide_toasts`].
+#[component]
+pub fn Sessions() -> impl IntoView {
+    let toaster = toast::use_toaster();
+    let sessions = RwSignal::new(Vec::<Session>::new());
+    let refresh = move || {
+        spawn_local(async move {
+            match messaging::request(&ListSessions).await {
+                Ok(list) => sessions.set(list),
+                Err(e) => gloo_console::error!(format!("Failed to list the sessions: {e}")),
+            }
+        })
+    };
+    refresh();
+
+    let report = move |result: Result<(), String>| match (result, toaster) {
+        (Err(e), Some(toaster)) => {
+            toaster.error(e);
+        }
+        (Err(e), None) => gloo_console::error!(e),
+        (Ok(()), _) => refresh(),
+    };
+    let save = move |_| {
+        spawn_local(async move {
+            let saved = messaging::request(&SaveWindow).await;
+            report(
+                saved
+                    .map(|_| ())
+                    .map_err(|e| format!("Couldn't save the tabs: {e}")),
+            );
+        })
+    };
+    let act = move |action: SessionAction, failed: &'static str| {
+        spawn_local(async move {
+            let done = messaging::request(&action).await;
+            report(done.map_err(|e| format!("{failed}: {e}")));
+        })
+    };
+    let now = SystemClock.now();
+    let row = move |session: Session| {
+        let id = session.id.unwrap_or_default();
+        let icons: Vec<_> = session
+            .tabs
+            .iter()
+            .filter_map(|tab| tab.fav_icon_url.clone())
+            .take(5)
+            .map(|src| view! { <img src=src alt="" width="16" height="16" /> })
+            .collect();
+        view! {
+            <div class="flex items-center gap-2 h-full px-2 border-b">
+                <div class="flex-1 min-w-0">
+                    <p class="truncate">{format!("{} tabs", session.tabs.len())}</p>
+                    <p class="flex gap-1 items-center text-xs">
+                        {icons} {format::relative_time(session.saved_at, now)}
+                    </p>
+                </div>
+                <button
+                    type="button"
+                    class="underline"
+                    on:click=move |_| act(SessionAction::Restore(id), "Couldn't restore the session")
+                >
+                    "Restore"
+                </button>
+                <button
+                    type="button"
+                    class="underline"
+                    on:click=move |_| act(SessionAction::Delete(id), "Couldn't delete the session")
+                >
+                    "Delete"
+                </button>
+            </div>
+        }
+    };
+    view! {
+        <section class="mt-2">
+            <button type="button" class="w-full px-3 py-1 underline" on:click=save>
+                "Save all tabs"
+            </button>
+            <Show
+                when=move || sessions.with(|sessions| !sessions.is_empty())
+                fallback=|| view! { <p class="px-2 text-sm">"No saved sessions yet."</p> }
+            >
+                <VirtualList
+                    items=sessions
+                    row_height=44.0
+                    height=176.0
+                    label="Saved sessions"
+                    key=|session: &Session| session.id
+                    row=row
+                />
+            </Show>
+        </section>
+    }
+}
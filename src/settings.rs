@@ -0,0 +1,110 @@
+//! Settings shared between the options page, other pages and the background.
+//!
+//! A [`Settings`] is a serde struct stored under one key, in `storage.sync` by
+//! default so it follows the user across browsers. Declare it once, next to the
+//! struct:
+//!
+//! ```ignore
+//! #[derive(Clone, Default, PartialEq, Serialize, Deserialize)]
+//! #[serde(default)]
+//! pub struct Preferences {
+//!     pub display_name: String,
+//! }
+//!
+//! pub const PREFERENCES: Settings<Preferences> = Settings::new("options.preferences");
+//! ```
+//!
+//! Pages bind to [`Settings::use_signal`], a signal that starts with the default,
+//! switches to the stored value once it's read, writes edits back shortly after the
+//! last one (and when the page closes), and follows changes made in other contexts,
+//! so an open popup updates as the options page is edited. The background, which has
+//! no components, uses [`Settings::load`] and [`Settings::on_change`]. With
+//! `#[serde(default)]`, fields added later take their default instead of making the
+//! stored value unreadable.
+
+use std::marker::PhantomData;
+
+use leptos::prelude::*;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    error::WextError,
+    storage::{self, Area, StorageArea},
+};
+
+/// How long after the last edit a page writes the settings, so typing doesn't write
+/// every keystroke (`storage.sync` allows 120 writes a minute).
+const DEBOUNCE_MS: u32 = 500;
+
+/// A settings struct `T`, stored under a key.
+#[derive(Debug)]
+pub struct Settings<T> {
+    key: &'static str,
+    area: Area,
+    _value: PhantomData<fn() -> T>,
+}
+
+impl<T> Clone for Settings<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for Settings<T> {}
+
+impl<T> Settings<T> {
+    /// Settings stored under `key` in `storage.sync`.
+    pub const fn new(key: &'static str) -> Self {
+        Settings {
+            key,
+            area: Area::Sync,
+            _value: PhantomData,
+        }
+    }
+
+    /// Store them in `area` instead, e.g. `storage.local` for settings that are
+    /// specific to the device or too large for `storage.sync`'s 8 KB per item.
+    pub const fn in_area(mut self, area: Area) -> Self {
+        self.area = area;
+        self
+    }
+
+    pub fn key(&self) -> &'static str {
+        self.key
+    }
+}
+
+impl<T> Settings<T>
+where
+    T: Serialize + DeserializeOwned + Default + Send + Sync + 'static,
+{
+    /// The stored settings, or the defaults if there are none.
+    pub async fn load(&self) -> Result<T, WextError> {
+        Ok(self.area.get(self.key).await?.unwrap_or_default())
+    }
+
+    /// Store `value` right away. Signals from [`use_signal`](Self::use_signal) in
+    /// every context update.
+    pub async fn save(&self, value: &T) -> Result<(), WextError> {
+        Ok(self.area.set(self.key, value).await?)
+    }
+
+    /// Remove the stored settings, so they're back to the defaults everywhere.
+    pub async fn reset(&self) -> Result<(), WextError> {
+        Ok(self.area.remove(self.key).await?)
+    }
+
+    /// Call `callback` with the new settings whenever they change, from any context.
+    pub fn on_change(&self, mut callback: impl FnMut(T) + 'static) {
+        self.area.on_change(self.key, move |value: Option<T>| {
+            callback(value.unwrap_or_default())
+        });
+    }
+
+    /// A signal holding the settings, saved shortly after it's set and updated when
+    /// they change elsewhere. Call it in a component: the pending write is flushed
+    /// when the page closes.
+    pub fn use_signal(&self) -> RwSignal<T> {
+        storage::stored_signal(self.area, self.key, T::default, DEBOUNCE_MS)
+    }
+}
@@ -0,0 +1,696 @@
+//! Typed access to `chrome.storage`.
+//!
+//! Every storage area implements [`StorageArea`]: the browser's `local`, `sync` and
+//! `session` areas ([`Area`]), and [`MemoryArea`], an in-memory stand-in that needs no
+//! browser, for tests. Code that takes a `&impl StorageArea` (or an
+//! `Rc<dyn StorageArea>`) can switch areas, or be tested against the mock, without
+//! changes:
+//!
+//! ```ignore
+//! async fn load_theme(area: &impl StorageArea) -> Result<Theme, StorageError> {
+//!     Ok(area.get("options.theme").await?.unwrap_or_default())
+//! }
+//!
+//! load_theme(&Area::Sync).await?;
+//! load_theme(&MemoryArea::default()).await?;
+//! ```
+//!
+//! The free functions ([`get`], [`set`], ...) use `storage.local`. Values are stored
+//! as plain JS objects via `serde-wasm-bindgen`, so they remain readable from the
+//! devtools storage viewer.
+//!
+//! [`Namespaced`] keeps a feature's keys apart in a shared area by prefixing them,
+//! and [`use_stored`] turns a key into a signal, for settings that pages edit
+//! directly:
+//!
+//! ```ignore
+//! let options = storage::namespace("options");
+//! let theme = use_stored(options.clone(), "theme", || Theme::System);
+//! // Saved as `options.theme`, and updated when another page changes it.
+//! theme.set(Theme::Dark);
+//! ```
+
+use std::{cell::RefCell, collections::BTreeMap, fmt, rc::Rc};
+
+use futures::future::{self, LocalBoxFuture};
+use gloo_timers::callback::Timeout;
+use js_sys::{Object, Reflect};
+use leptos::{prelude::*, spawn::spawn_local};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::{Map, Value};
+use wasm_bindgen::prelude::*;
+
+use crate::{browser, content, error::WextError, surface};
+
+/// Why a storage call failed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StorageError {
+    /// The browser's storage API failed, e.g. because the quota is exceeded.
+    Browser(String),
+    /// The value couldn't be (de)serialized.
+    Codec(String),
+}
+
+impl fmt::Display for StorageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StorageError::Browser(e) => write!(f, "storage failed: {e}"),
+            StorageError::Codec(e) => write!(f, "failed to (de)serialize stored value: {e}"),
+        }
+    }
+}
+
+impl From<StorageError> for JsValue {
+    fn from(error: StorageError) -> Self {
+        JsValue::from_str(&error.to_string())
+    }
+}
+
+impl From<JsValue> for StorageError {
+    fn from(error: JsValue) -> Self {
+        StorageError::Browser(browser::error_message(&error))
+    }
+}
+
+type ChangeCallback = Box<dyn FnMut(Option<Value>)>;
+
+/// A key-value storage area, holding JSON values.
+///
+/// Implementations only deal in [`Value`]s; the typed [`get`](StorageArea::get) and
+/// [`set`](StorageArea::set) are built on top.
+pub trait StorageArea {
+    /// The area's name, e.g. `local`.
+    fn name(&self) -> &str;
+
+    /// Read a single key, returning `None` if it isn't set.
+    fn get_value<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Value>, StorageError>>;
+
+    /// Read every key.
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Map<String, Value>, StorageError>>;
+
+    /// Write a single key.
+    fn set_value<'a>(
+        &'a self,
+        key: &'a str,
+        value: Value,
+    ) -> LocalBoxFuture<'a, Result<(), StorageError>>;
+
+    /// Remove a single key.
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), StorageError>>;
+
+    /// Remove every key.
+    fn clear(&self) -> LocalBoxFuture<'_, Result<(), StorageError>>;
+
+    /// Call `callback` with the new value whenever `key` changes, from any context.
+    fn on_change_value(&self, key: &str, callback: ChangeCallback);
+
+    /// Read a single key, returning `None` if it isn't set.
+    fn get<'a, T: DeserializeOwned + 'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<T>, StorageError>>
+    where
+        Self: Sized,
+    {
+        Box::pin(async move {
+            self.get_value(key)
+                .await?
+                .map(|value| {
+                    serde_json::from_value(value).map_err(|e| StorageError::Codec(e.to_string()))
+                })
+                .transpose()
+        })
+    }
+
+    /// Write a single key.
+    fn set<'a, T: Serialize>(
+        &'a self,
+        key: &'a str,
+        value: &T,
+    ) -> LocalBoxFuture<'a, Result<(), StorageError>>
+    where
+        Self: Sized,
+    {
+        match serde_json::to_value(value) {
+            Ok(value) => self.set_value(key, value),
+            Err(e) => Box::pin(future::ready(Err(StorageError::Codec(e.to_string())))),
+        }
+    }
+
+    /// Call `callback` with the new value whenever `key` changes. Values that don't
+    /// deserialize as `T` are skipped.
+    fn on_change<T, F>(&self, key: &str, mut callback: F)
+    where
+        Self: Sized,
+        T: DeserializeOwned,
+        F: FnMut(Option<T>) + 'static,
+    {
+        self.on_change_value(
+            key,
+            Box::new(move |value| match value {
+                None => callback(None),
+                Some(value) => {
+                    if let Ok(value) = serde_json::from_value(value) {
+                        callback(Some(value));
+                    }
+                }
+            }),
+        );
+    }
+}
+
+macro_rules! forward_area {
+    ($($ty:ty),*) => {$(
+        impl<A: StorageArea + ?Sized> StorageArea for $ty {
+            fn name(&self) -> &str {
+                (**self).name()
+            }
+
+            fn get_value<'a>(
+                &'a self,
+                key: &'a str,
+            ) -> LocalBoxFuture<'a, Result<Option<Value>, StorageError>> {
+                (**self).get_value(key)
+            }
+
+            fn get_all(&self) -> LocalBoxFuture<'_, Result<Map<String, Value>, StorageError>> {
+                (**self).get_all()
+            }
+
+            fn set_value<'a>(
+                &'a self,
+                key: &'a str,
+                value: Value,
+            ) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+                (**self).set_value(key, value)
+            }
+
+            fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+                (**self).remove(key)
+            }
+
+            fn clear(&self) -> LocalBoxFuture<'_, Result<(), StorageError>> {
+                (**self).clear()
+            }
+
+            fn on_change_value(&self, key: &str, callback: ChangeCallback) {
+                (**self).on_change_value(key, callback)
+            }
+        }
+    )*};
+}
+
+forward_area!(&A, Rc<A>, Box<A>);
+
+/// One of the browser's storage areas.
+///
+/// `session` is kept in memory by the browser and cleared when it quits. It's only
+/// available to extension pages and the background, unless the background calls
+/// `storage.session.setAccessLevel`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Area {
+    Local,
+    Sync,
+    Session,
+}
+
+impl Area {
+    fn browser_area(self) -> browser::StorageArea {
+        match self {
+            Area::Local => browser::storage_local(),
+            Area::Sync => browser::storage_sync(),
+            Area::Session => browser::storage_session(),
+        }
+    }
+}
+
+impl StorageArea for Area {
+    fn name(&self) -> &str {
+        match self {
+            Area::Local => "local",
+            Area::Sync => "sync",
+            Area::Session => "session",
+        }
+    }
+
+    fn get_value<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Value>, StorageError>> {
+        Box::pin(async move {
+            let items = content::guard(self.browser_area().get(key.into())).await?;
+            let value = Reflect::get(&items, &key.into())?;
+            if value.is_undefined() {
+                return Ok(None);
+            }
+            Ok(Some(from_js(value)?))
+        })
+    }
+
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Map<String, Value>, StorageError>> {
+        Box::pin(async move {
+            let items = content::guard(self.browser_area().get(JsValue::NULL)).await?;
+            let mut all = Map::new();
+            for key in Object::keys(items.unchecked_ref()).iter() {
+                let value = Reflect::get(&items, &key)?;
+                if let Some(key) = key.as_string() {
+                    all.insert(key, from_js(value)?);
+                }
+            }
+            Ok(all)
+        })
+    }
+
+    fn set_value<'a>(
+        &'a self,
+        key: &'a str,
+        value: Value,
+    ) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move {
+            let value = value
+                .serialize(&serde_wasm_bindgen::Serializer::json_compatible())
+                .map_err(|e| StorageError::Codec(e.to_string()))?;
+            content::guard(self.browser_area().set(browser::object(&[(key, value)]))).await?;
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move {
+            content::guard(self.browser_area().remove(key.into())).await?;
+            Ok(())
+        })
+    }
+
+    fn clear(&self) -> LocalBoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            content::guard(self.browser_area().clear()).await?;
+            Ok(())
+        })
+    }
+
+    fn on_change_value(&self, key: &str, mut callback: ChangeCallback) {
+        let name = self.name().to_string();
+        let key = key.to_string();
+        browser::listen(&browser::storage_on_changed(), move |changes, area| {
+            if area.as_string().as_deref() != Some(name.as_str()) {
+                return;
+            }
+            let Ok(change) = Reflect::get(&changes, &key.as_str().into()) else {
+                return;
+            };
+            if change.is_undefined() {
+                return;
+            }
+            let new_value = Reflect::get(&change, &"newValue".into()).unwrap_or_default();
+            if new_value.is_undefined() {
+                callback(None);
+            } else if let Ok(value) = from_js(new_value) {
+                callback(Some(value));
+            }
+        });
+    }
+}
+
+fn from_js(value: JsValue) -> Result<Value, StorageError> {
+    serde_wasm_bindgen::from_value(value).map_err(|e| StorageError::Codec(e.to_string()))
+}
+
+/// An in-memory storage area, e.g. for tests. Clones share the same contents, and
+/// change callbacks are called synchronously as values are written.
+#[derive(Clone, Default)]
+pub struct MemoryArea {
+    items: Rc<RefCell<BTreeMap<String, Value>>>,
+    listeners: Rc<RefCell<Vec<(String, ChangeCallback)>>>,
+}
+
+impl MemoryArea {
+    /// A copy of everything stored.
+    pub fn snapshot(&self) -> BTreeMap<String, Value> {
+        self.items.borrow().clone()
+    }
+
+    fn notify(&self, key: &str, value: Option<&Value>) {
+        // Callbacks may write to the area, so they're taken out while they run.
+        let mut listeners = self.listeners.take();
+        for (watched, callback) in &mut listeners {
+            if watched == key {
+                callback(value.cloned());
+            }
+        }
+        let mut current = self.listeners.borrow_mut();
+        listeners.append(&mut current);
+        *current = listeners;
+    }
+}
+
+impl fmt::Debug for MemoryArea {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MemoryArea")
+            .field("items", &self.items.borrow())
+            .finish_non_exhaustive()
+    }
+}
+
+impl StorageArea for MemoryArea {
+    fn name(&self) -> &str {
+        "memory"
+    }
+
+    fn get_value<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Value>, StorageError>> {
+        let value = self.items.borrow().get(key).cloned();
+        Box::pin(async move { Ok(value) })
+    }
+
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Map<String, Value>, StorageError>> {
+        let all = self.items.borrow().clone().into_iter().collect();
+        Box::pin(async move { Ok(all) })
+    }
+
+    fn set_value<'a>(
+        &'a self,
+        key: &'a str,
+        value: Value,
+    ) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+        let changed = self.items.borrow().get(key) != Some(&value);
+        self.items
+            .borrow_mut()
+            .insert(key.to_string(), value.clone());
+        // Like the browser, writing an unchanged value isn't a change.
+        if changed {
+            self.notify(key, Some(&value));
+        }
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+        if self.items.borrow_mut().remove(key).is_some() {
+            self.notify(key, None);
+        }
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn clear(&self) -> LocalBoxFuture<'_, Result<(), StorageError>> {
+        let removed = std::mem::take(&mut *self.items.borrow_mut());
+        for key in removed.keys() {
+            self.notify(key, None);
+        }
+        Box::pin(async move { Ok(()) })
+    }
+
+    fn on_change_value(&self, key: &str, callback: ChangeCallback) {
+        self.listeners
+            .borrow_mut()
+            .push((key.to_string(), callback));
+    }
+}
+
+/// An area whose keys are all prefixed with a namespace and a dot, e.g. `theme` is
+/// stored as `options.theme`. [`get_all`](StorageArea::get_all) and
+/// [`clear`](StorageArea::clear) only see the namespace's keys, without the prefix.
+#[derive(Debug, Clone)]
+pub struct Namespaced<A> {
+    area: A,
+    prefix: String,
+}
+
+impl<A: StorageArea> Namespaced<A> {
+    pub fn new(area: A, namespace: &str) -> Self {
+        Namespaced {
+            area,
+            prefix: format!("{namespace}."),
+        }
+    }
+
+    fn key(&self, key: &str) -> String {
+        format!("{}{key}", self.prefix)
+    }
+}
+
+impl<A: StorageArea> StorageArea for Namespaced<A> {
+    fn name(&self) -> &str {
+        self.area.name()
+    }
+
+    fn get_value<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> LocalBoxFuture<'a, Result<Option<Value>, StorageError>> {
+        Box::pin(async move { self.area.get_value(&self.key(key)).await })
+    }
+
+    fn get_all(&self) -> LocalBoxFuture<'_, Result<Map<String, Value>, StorageError>> {
+        Box::pin(async move {
+            let all = self.area.get_all().await?;
+            Ok(all
+                .into_iter()
+                .filter_map(|(key, value)| {
+                    Some((key.strip_prefix(&self.prefix)?.to_string(), value))
+                })
+                .collect())
+        })
+    }
+
+    fn set_value<'a>(
+        &'a self,
+        key: &'a str,
+        value: Value,
+    ) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move { self.area.set_value(&self.key(key), value).await })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> LocalBoxFuture<'a, Result<(), StorageError>> {
+        Box::pin(async move { self.area.remove(&self.key(key)).await })
+    }
+
+    fn clear(&self) -> LocalBoxFuture<'_, Result<(), StorageError>> {
+        Box::pin(async move {
+            for key in self.get_all().await?.keys() {
+                self.remove(key).await?;
+            }
+            Ok(())
+        })
+    }
+
+    fn on_change_value(&self, key: &str, callback: ChangeCallback) {
+        self.area.on_change_value(&self.key(key), callback);
+    }
+}
+
+/// `namespace`'s keys in `storage.local`.
+pub fn namespace(namespace: &str) -> Namespaced<Area> {
+    Namespaced::new(Area::Local, namespace)
+}
+
+/// A signal holding `key`'s value in `area`, or `default` until it's read and if it
+/// isn't set. Setting the signal writes the value back, and changes from other
+/// contexts (another page, the background) update it; removing the key resets it to
+/// `default`. Values that don't deserialize as `T` are logged and left alone.
+pub fn use_stored<A, T>(area: A, key: &str, default: impl Fn() -> T + 'static) -> RwSignal<T>
+where
+    A: StorageArea + 'static,
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    stored_signal(area, key, default, 0)
+}
+
+/// [`use_stored`], writing changes `debounce_ms` after the last one (and when the
+/// page closes) rather than each one, e.g. for a text field. Changes from other
+/// contexts are ignored while a write is pending: it overwrites them.
+pub(crate) fn stored_signal<A, T>(
+    area: A,
+    key: &str,
+    default: impl Fn() -> T + 'static,
+    debounce_ms: u32,
+) -> RwSignal<T>
+where
+    A: StorageArea + 'static,
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    let area = Rc::new(area);
+    let key = key.to_string();
+    let state = RwSignal::new(default());
+    let (loaded, set_loaded) = signal(false);
+    // The value last read or written, so writes don't echo back as changes and
+    // changes aren't written back.
+    let stored: Rc<RefCell<Option<Value>>> = Rc::default();
+    // The debounced write, with the value it writes.
+    let pending: Rc<RefCell<Option<(Timeout, Value)>>> = Rc::default();
+    let write = {
+        let (area, key) = (area.clone(), key.clone());
+        move |value: Value| {
+            let (area, key) = (area.clone(), key.clone());
+            spawn_local(async move {
+                if let Err(e) = area.set_value(&key, value).await {
+                    gloo_console::warn!(format!("Failed to save {key}: {e}"));
+                }
+            });
+        }
+    };
+
+    spawn_local({
+        let (area, key, stored) = (area.clone(), key.clone(), stored.clone());
+        async move {
+            match area.get_value(&key).await {
+                Ok(Some(value)) => match serde_json::from_value(value.clone()) {
+                    Ok(typed) => {
+                        *stored.borrow_mut() = Some(value);
+                        state.try_set(typed);
+                    }
+                    Err(e) => gloo_console::warn!(format!("Ignoring the stored {key}: {e}")),
+                },
+                Ok(None) => {}
+                Err(e) => gloo_console::warn!(format!("Failed to read {key}: {e}")),
+            }
+            set_loaded.try_set(true);
+        }
+    });
+    area.on_change_value(&key, {
+        let (key, stored, pending) = (key.clone(), stored.clone(), pending.clone());
+        Box::new(move |value| {
+            if pending.borrow().is_some() {
+                return;
+            }
+            *stored.borrow_mut() = value.clone();
+            match value.map(serde_json::from_value).transpose() {
+                Ok(typed) => {
+                    state.try_set(typed.unwrap_or_else(&default));
+                }
+                Err(e) => gloo_console::warn!(format!("Ignoring the stored {key}: {e}")),
+            }
+        })
+    });
+    if debounce_ms > 0 {
+        let (pending, write) = (pending.clone(), write.clone());
+        surface::on_close(move || {
+            // Dropping the timeout cancels it.
+            if let Some((_, value)) = pending.take() {
+                write(value);
+            }
+        });
+    }
+    // Nothing is written until the stored value is in, so it isn't overwritten with
+    // the default.
+    Effect::new(move |_| {
+        let value = state.with(|value| serde_json::to_value(value));
+        if !loaded.get() {
+            return;
+        }
+        let value = match value {
+            Ok(value) => value,
+            Err(e) => {
+                gloo_console::warn!(format!("Failed to save {key}: {e}"));
+                return;
+            }
+        };
+        if stored.borrow().as_ref() == Some(&value) {
+            return;
+        }
+        *stored.borrow_mut() = Some(value.clone());
+        if debounce_ms == 0 {
+            write(value);
+            return;
+        }
+        let timeout = Timeout::new(debounce_ms, {
+            let (pending, write) = (pending.clone(), write.clone());
+            move || {
+                // Dropping a timeout cancels it, which it can't do while it runs.
+                if let Some((timeout, value)) = pending.take() {
+                    timeout.forget();
+                    write(value);
+                }
+            }
+        });
+        // Replacing the pending write cancels it.
+        pending.replace(Some((timeout, value)));
+    });
+    state
+}
+
+/// Read a single key from `storage.local`, returning `None` if it isn't set.
+pub async fn get<T: DeserializeOwned>(key: &str) -> Result<Option<T>, WextError> {
+    Ok(Area::Local.get(key).await?)
+}
+
+/// Write a single key to `storage.local`.
+pub async fn set<T: Serialize>(key: &str, value: &T) -> Result<(), WextError> {
+    Ok(Area::Local.set(key, value).await?)
+}
+
+/// Remove a single key from `storage.local`.
+pub async fn remove(key: &str) -> Result<(), WextError> {
+    Ok(Area::Local.remove(key).await?)
+}
+
+/// Call `callback` with the new value whenever `key` changes in `storage.local`.
+pub fn on_change<T, F>(key: &'static str, callback: F)
+where
+    T: DeserializeOwned,
+    F: FnMut(Option<T>) + 'static,
+{
+    Area::Local.on_change(key, callback);
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use futures::executor::block_on;
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+
+    use super::{MemoryArea, Namespaced, StorageArea};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Theme {
+        dark: bool,
+        accent: String,
+    }
+
+    #[test]
+    fn namespaced_round_trips_under_the_prefix() {
+        let area = MemoryArea::default();
+        let options = Namespaced::new(area.clone(), "options");
+        let theme = Theme {
+            dark: true,
+            accent: "teal".to_string(),
+        };
+        block_on(options.set("theme", &theme)).unwrap();
+        block_on(area.set("theme", &"unrelated")).unwrap();
+
+        assert_eq!(
+            block_on(options.get::<Theme>("theme")).unwrap(),
+            Some(theme)
+        );
+        assert_eq!(
+            area.snapshot()["options.theme"],
+            json!({ "dark": true, "accent": "teal" })
+        );
+        let all = block_on(options.get_all()).unwrap();
+        assert_eq!(all.keys().collect::<Vec<_>>(), ["theme"]);
+    }
+
+    #[test]
+    fn namespaced_clear_leaves_other_keys() {
+        let area = MemoryArea::default();
+        let options = Namespaced::new(area.clone(), "options");
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        options.on_change("count", {
+            let changes = changes.clone();
+            move |value: Option<u32>| changes.borrow_mut().push(value)
+        });
+        block_on(options.set("count", &1)).unwrap();
+        block_on(area.set("options.count", &2)).unwrap();
+        block_on(area.set("optionsCount", &3)).unwrap();
+        block_on(options.clear()).unwrap();
+
+        assert_eq!(*changes.borrow(), [Some(1), Some(2), None]);
+        assert_eq!(area.snapshot().keys().collect::<Vec<_>>(), ["optionsCount"]);
+    }
+}
@@ -0,0 +1,110 @@
+//! Lifecycle hooks for the extension's pages: popups, sidebars and side panels,
+//! options pages.
+//!
+//! A popup is destroyed as soon as it loses focus, and a sidebar when it's closed,
+//! with no chance to finish async work. [`on_close`] (or [`on_popup_close`]) runs a
+//! handler on `pagehide`, the last event the page gets, which is the place to flush
+//! state: storage writes started there still reach the browser. [`use_visible`]
+//! tracks whether the page is shown, e.g. a sidebar whose window is minimised, or an
+//! options tab in the background, to pause work nobody sees.
+//!
+//! `pagehide` isn't guaranteed (a popup's renderer can be killed), so pages that hold
+//! resources in the background also call [`connect`], and the background runs the
+//! handlers registered with [`on_closed`] when the page's port disconnects, which
+//! always happens when the page goes away. The background needs [`install`].
+
+use std::{cell::RefCell, rc::Rc};
+
+use leptos::{ev, prelude::*};
+use wasm_bindgen::JsCast;
+use web_sys::VisibilityState;
+
+use crate::{
+    browser::{self, Port},
+    entry::{environment, Context},
+};
+
+/// Prefix of the ports pages connect with, followed by the page's entry function.
+const PORT_PREFIX: &str = "wext.surface:";
+
+type Handler = Rc<dyn Fn(&str)>;
+
+thread_local! {
+    /// The page's port to the background, kept open for as long as the page lives.
+    static PORT: RefCell<Option<Port>> = RefCell::default();
+    static HANDLERS: RefCell<Vec<Handler>> = RefCell::default();
+}
+
+/// Call `handler` when the page goes away, while the calling component is mounted.
+/// It runs synchronously: async work it starts may not finish.
+pub fn on_close(handler: impl Fn() + 'static) {
+    let handle = window_event_listener(ev::pagehide, move |_| handler());
+    on_cleanup(move || handle.remove());
+}
+
+/// [`on_close`], but only in the popup, for components shared with other pages.
+pub fn on_popup_close(handler: impl Fn() + 'static) {
+    if environment().context == Context::Popup {
+        on_close(handler);
+    }
+}
+
+/// Whether the page is shown, updated as it's hidden and shown again.
+pub fn use_visible() -> ReadSignal<bool> {
+    let (visible, set_visible) = signal(is_visible());
+    let handle =
+        window_event_listener(ev::visibilitychange, move |_| set_visible.set(is_visible()));
+    on_cleanup(move || handle.remove());
+    visible
+}
+
+/// Call `shown` and `hidden` as the page is shown and hidden, while the calling
+/// component is mounted.
+pub fn on_visibility_change(shown: impl Fn() + 'static, hidden: impl Fn() + 'static) {
+    let handle = window_event_listener(ev::visibilitychange, move |_| {
+        if is_visible() {
+            shown()
+        } else {
+            hidden()
+        }
+    });
+    on_cleanup(move || handle.remove());
+}
+
+fn is_visible() -> bool {
+    document().visibility_state() == VisibilityState::Visible
+}
+
+/// Tell the background when this page goes away, through the [`on_closed`] handlers.
+/// Call once, from the page's entry function.
+pub fn connect() {
+    let name = format!("{PORT_PREFIX}{}", environment().entry);
+    let info = browser::object(&[("name", name.into())]);
+    match browser::runtime().connect(info) {
+        Ok(port) => PORT.set(Some(port)),
+        Err(e) => gloo_console::warn!("Failed to connect to the background:", e),
+    }
+}
+
+/// In the background, call `handler` with the entry function of every page that
+/// called [`connect`] when it goes away, e.g. `"popup_page"`. Register before calling
+/// [`install`].
+pub fn on_closed(handler: impl Fn(&str) + 'static) {
+    HANDLERS.with_borrow_mut(|handlers| handlers.push(Rc::new(handler)));
+}
+
+/// Register the `onConnect` listener. Must be called synchronously during startup.
+pub fn install() {
+    browser::listen(&browser::runtime().on_connect(), |port, _| {
+        let port: Port = port.unchecked_into();
+        let Some(entry) = port.name().strip_prefix(PORT_PREFIX).map(str::to_string) else {
+            return;
+        };
+        browser::listen(&port.on_disconnect(), move |_, _| {
+            let handlers = HANDLERS.with_borrow(|handlers| handlers.clone());
+            for handler in handlers {
+                handler(&entry);
+            }
+        });
+    });
+}
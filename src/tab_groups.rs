@@ -0,0 +1,297 @@
+//! Tab groups: typed `chrome.tabGroups`, and grouping a window's tabs by domain.
+//!
+//! Chrome 89 and Firefox 139 have tab groups, with the `tabGroups` permission (tabs
+//! are grouped through `tabs.group`, the groups' titles, colours and places are
+//! changed through `tabGroups`). Check [`is_supported`] before offering anything that
+//! needs them: older Firefox versions have neither API, and the popup's
+//! [`GroupByDomain`] button isn't shown there.
+//!
+//! ```ignore
+//! let groups = tab_groups::query(&GroupQuery { title: Some("Docs".into()), ..Default::default() }).await?;
+//! for group in groups {
+//!     tab_groups::update(group.id, &GroupUpdate { collapsed: Some(true), ..Default::default() }).await?;
+//! }
+//! ```
+
+use std::collections::BTreeMap;
+
+use js_sys::Array;
+use leptos::{prelude::*, spawn::spawn_local};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::Url;
+
+use crate::{browser, error::WextError, toast};
+
+/// A group's colour, from the browser's fixed palette.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Color {
+    Grey,
+    Blue,
+    Red,
+    Yellow,
+    Green,
+    Pink,
+    Purple,
+    Cyan,
+    Orange,
+}
+
+impl Color {
+    pub const ALL: [Color; 9] = [
+        Color::Grey,
+        Color::Blue,
+        Color::Red,
+        Color::Yellow,
+        Color::Green,
+        Color::Pink,
+        Color::Purple,
+        Color::Cyan,
+        Color::Orange,
+    ];
+
+    /// A colour picked from `name`, the same one every time, so a domain's group keeps
+    /// its colour.
+    pub fn for_name(name: &str) -> Self {
+        let hash = name.bytes().fold(0u32, |hash, byte| {
+            hash.wrapping_mul(31).wrapping_add(byte.into())
+        });
+        Color::ALL[hash as usize % Color::ALL.len()]
+    }
+}
+
+/// A `tabGroups.TabGroup`.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabGroup {
+    pub id: i32,
+    pub window_id: i32,
+    pub collapsed: bool,
+    pub color: Color,
+    /// Unset or empty for untitled groups.
+    pub title: Option<String>,
+}
+
+/// Which groups to list, as in `tabGroups.query`. Unset fields match every group.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collapsed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    /// A pattern the title must match, where `*` matches anything.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_id: Option<i32>,
+}
+
+/// Changes to a group, as in `tabGroups.update`. Unset fields are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GroupUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub collapsed: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub color: Option<Color>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+}
+
+/// Whether the browser has tab groups.
+pub fn is_supported() -> bool {
+    !browser::api("tabGroups").is_undefined() && !browser::api("tabs.group").is_undefined()
+}
+
+fn check_supported() -> Result<(), WextError> {
+    if is_supported() {
+        Ok(())
+    } else {
+        Err(WextError::Other(
+            "This browser doesn't support tab groups".to_string(),
+        ))
+    }
+}
+
+pub async fn get(group_id: i32) -> Result<TabGroup, WextError> {
+    check_supported()?;
+    let group = browser::tab_groups().get(group_id).await?;
+    Ok(serde_wasm_bindgen::from_value(group)?)
+}
+
+pub async fn query(query: &GroupQuery) -> Result<Vec<TabGroup>, WextError> {
+    check_supported()?;
+    let groups = browser::tab_groups().query(to_js(query)?).await?;
+    Ok(serde_wasm_bindgen::from_value(groups)?)
+}
+
+pub async fn update(group_id: i32, update: &GroupUpdate) -> Result<TabGroup, WextError> {
+    check_supported()?;
+    let group = browser::tab_groups()
+        .update(group_id, to_js(update)?)
+        .await?;
+    Ok(serde_wasm_bindgen::from_value(group)?)
+}
+
+/// Move a group to `index` among the tabs of its window, or of `window_id`. An index
+/// of -1 moves it to the end.
+pub async fn move_group(
+    group_id: i32,
+    index: i32,
+    window_id: Option<i32>,
+) -> Result<TabGroup, WextError> {
+    check_supported()?;
+    let mut properties = vec![("index", index.into())];
+    if let Some(window_id) = window_id {
+        properties.push(("windowId", window_id.into()));
+    }
+    let group = browser::tab_groups()
+        .move_group(group_id, browser::object(&properties))
+        .await?;
+    Ok(serde_wasm_bindgen::from_value(group)?)
+}
+
+/// Add tabs to the group `group_id`, or to a new group. Returns the group's id.
+pub async fn group(tab_ids: &[i32], group_id: Option<i32>) -> Result<i32, WextError> {
+    check_supported()?;
+    let mut options = vec![("tabIds", tab_id_array(tab_ids))];
+    if let Some(group_id) = group_id {
+        options.push(("groupId", group_id.into()));
+    }
+    let id = browser::tabs().group(browser::object(&options)).await?;
+    id.as_f64()
+        .map(|id| id as i32)
+        .ok_or_else(|| WextError::Other("tabs.group returned no group id".to_string()))
+}
+
+/// Take tabs out of their groups. Groups left empty are removed.
+pub async fn ungroup(tab_ids: &[i32]) -> Result<(), WextError> {
+    check_supported()?;
+    browser::tabs().ungroup(tab_id_array(tab_ids)).await?;
+    Ok(())
+}
+
+/// The parts of `tabs.Tab` grouping by domain needs.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GroupedTab {
+    id: i32,
+    window_id: i32,
+    url: Option<String>,
+    #[serde(default)]
+    pinned: bool,
+    /// -1 for tabs outside any group.
+    #[serde(default = "no_group")]
+    group_id: i32,
+}
+
+fn no_group() -> i32 {
+    -1
+}
+
+/// Group the current window's tabs by domain: every domain with more than one tab
+/// gets a group titled with the domain, reusing the window's group of that title if
+/// there is one. Pinned tabs can't be grouped and are left alone, as are tabs whose
+/// URL the extension can't see. Returns the number of groups.
+pub async fn group_by_domain() -> Result<usize, WextError> {
+    check_supported()?;
+    let tabs = browser::tabs()
+        .query(browser::object(&[("currentWindow", true.into())]))
+        .await?;
+    let tabs: Vec<GroupedTab> = Array::from(&tabs)
+        .iter()
+        .filter_map(|tab| serde_wasm_bindgen::from_value(tab).ok())
+        .collect();
+    let mut domains: BTreeMap<String, Vec<&GroupedTab>> = BTreeMap::new();
+    for tab in tabs.iter().filter(|tab| !tab.pinned) {
+        if let Some(domain) = tab.url.as_deref().and_then(domain) {
+            domains.entry(domain).or_default().push(tab);
+        }
+    }
+
+    let Some(window_id) = tabs.first().map(|tab| tab.window_id) else {
+        return Ok(0);
+    };
+    let groups = query(&GroupQuery {
+        window_id: Some(window_id),
+        ..Default::default()
+    })
+    .await?;
+    let mut count = 0;
+    for (domain, tabs) in domains.iter().filter(|(_, tabs)| tabs.len() > 1) {
+        let existing = groups
+            .iter()
+            .find(|group| group.title.as_deref() == Some(domain))
+            .map(|group| group.id);
+        let ungrouped: Vec<i32> = tabs
+            .iter()
+            .filter(|tab| existing.is_none() || Some(tab.group_id) != existing)
+            .map(|tab| tab.id)
+            .collect();
+        if !ungrouped.is_empty() {
+            let id = group(&ungrouped, existing).await?;
+            if existing.is_none() {
+                let update_group = GroupUpdate {
+                    title: Some(domain.clone()),
+                    color: Some(Color::for_name(domain)),
+                    collapsed: None,
+                };
+                update(id, &update_group).await?;
+            }
+        }
+        count += 1;
+    }
+    Ok(count)
+}
+
+/// A URL's host without `www.`, for `http(s)` URLs only.
+fn domain(url: &str) -> Option<String> {
+    let url = Url::new(url).ok()?;
+    if !matches!(url.protocol().as_str(), "http:" | "https:") {
+        return None;
+    }
+    let host = url.hostname();
+    Some(host.strip_prefix("www.").unwrap_or(&host).to_string())
+}
+
+fn tab_id_array(tab_ids: &[i32]) -> JsValue {
+    tab_ids
+        .iter()
+        .map(|id| JsValue::from(*id))
+        .collect::<Array>()
+        .into()
+}
+
+fn to_js(value: &impl Serialize) -> Result<JsValue, WextError> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+/// A popup button that groups the window's tabs by domain, where the browser has
+/// tab groups.
+#[component]
+pub fn GroupByDomain() -> impl IntoView {
+    let toaster = toast::use_toaster();
+    let group_tabs = move |_| {
+        spawn_local(async move {
+            let result = group_by_domain().await;
+            let Some(toaster) = toaster else {
+                return;
+            };
+            match result {
+                Ok(0) => toaster.success("No domain has more than one tab."),
+                Ok(1) => toaster.success("Grouped the tabs of 1 domain."),
+                Ok(count) => toaster.success(format!("Grouped the tabs of {count} domains.")),
+                Err(e) => toaster.error(format!("Couldn't group the tabs: {e}")),
+            };
+        })
+    };
+    is_supported().then(|| {
+        view! {
+            <button type="button" class="w-full px-3 py-1 underline" on:click=group_tabs>
+                "Group tabs by domain"
+            </button>
+        }
+    })
+}
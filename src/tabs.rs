@@ -0,0 +1,139 @@
+//! A facade over `chrome.tabs`, so code that works with tabs can be tested against
+//! `mock::MockTabs` instead of a browser.
+//!
+//! ```ignore
+//! async fn close_duplicates(tabs: &impl Tabs, url: &str) -> Result<(), WextError> {
+//!     let query = TabQuery { url: vec![url.to_string()], ..Default::default() };
+//!     for tab in tabs.query(&query).await?.iter().skip(1) {
+//!         tabs.remove(tab.id).await?;
+//!     }
+//!     Ok(())
+//! }
+//!
+//! close_duplicates(&BrowserTabs, "https://example.com/*").await?;
+//! ```
+
+use futures::future::LocalBoxFuture;
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use crate::{browser, error::WextError};
+
+/// The parts of `tabs.Tab` this facade deals with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tab {
+    /// Tabs without an id (e.g. devtools windows) are left out of query results.
+    pub id: i32,
+    pub window_id: i32,
+    /// Only set with the `tabs` permission or host permissions for the page.
+    pub url: Option<String>,
+    pub title: Option<String>,
+    /// Only set with the same permissions as `url`.
+    pub fav_icon_url: Option<String>,
+    pub active: bool,
+}
+
+/// Which tabs to list, as in `tabs.query`. Unset fields match every tab.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabQuery {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_window: Option<bool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub window_id: Option<i32>,
+    /// Match patterns for the tab's URL, e.g. `https://*.example.com/*`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub url: Vec<String>,
+}
+
+/// Changes to a tab, as in `tabs.update`. Unset fields are left alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TabUpdate {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+}
+
+/// Tab operations.
+pub trait Tabs {
+    fn query<'a>(&'a self, query: &'a TabQuery) -> LocalBoxFuture<'a, Result<Vec<Tab>, WextError>>;
+
+    /// Open a tab at `url`, in the current window.
+    fn create<'a>(
+        &'a self,
+        url: &'a str,
+        active: bool,
+    ) -> LocalBoxFuture<'a, Result<Tab, WextError>>;
+
+    fn update<'a>(
+        &'a self,
+        tab_id: i32,
+        update: &'a TabUpdate,
+    ) -> LocalBoxFuture<'a, Result<Tab, WextError>>;
+
+    fn remove(&self, tab_id: i32) -> LocalBoxFuture<'_, Result<(), WextError>>;
+}
+
+/// The browser's `chrome.tabs`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BrowserTabs;
+
+impl Tabs for BrowserTabs {
+    fn query<'a>(&'a self, query: &'a TabQuery) -> LocalBoxFuture<'a, Result<Vec<Tab>, WextError>> {
+        Box::pin(async move {
+            let tabs = browser::tabs().query(to_js(query)?).await?;
+            // Tabs without an id don't deserialize, and are skipped.
+            Ok(js_sys::Array::from(&tabs)
+                .iter()
+                .filter_map(|tab| serde_wasm_bindgen::from_value(tab).ok())
+                .collect())
+        })
+    }
+
+    fn create<'a>(
+        &'a self,
+        url: &'a str,
+        active: bool,
+    ) -> LocalBoxFuture<'a, Result<Tab, WextError>> {
+        Box::pin(async move {
+            let tab = browser::tabs()
+                .create(browser::object(&[
+                    ("url", url.into()),
+                    ("active", active.into()),
+                ]))
+                .await?;
+            from_js(tab)
+        })
+    }
+
+    fn update<'a>(
+        &'a self,
+        tab_id: i32,
+        update: &'a TabUpdate,
+    ) -> LocalBoxFuture<'a, Result<Tab, WextError>> {
+        Box::pin(async move {
+            let tab = browser::tabs().update(tab_id, to_js(update)?).await?;
+            from_js(tab)
+        })
+    }
+
+    fn remove(&self, tab_id: i32) -> LocalBoxFuture<'_, Result<(), WextError>> {
+        Box::pin(async move {
+            browser::tabs().remove(tab_id).await?;
+            Ok(())
+        })
+    }
+}
+
+fn to_js(value: &impl Serialize) -> Result<JsValue, WextError> {
+    Ok(value.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?)
+}
+
+fn from_js(tab: JsValue) -> Result<Tab, WextError> {
+    Ok(serde_wasm_bindgen::from_value(tab)?)
+}
@@ -0,0 +1,189 @@
+//! Toasts: short notifications at the top of extension pages.
+//!
+//! Call [`provide_toasts`] at the root of a page and render [`Toasts`] once in it.
+//! Anywhere below, [`use_toaster`] returns a [`Toaster`] to show success, error or
+//! progress toasts. At most [`MAX_VISIBLE`] are shown at once, and the rest wait their
+//! turn. Success and error toasts dismiss themselves after a few seconds once shown;
+//! progress toasts stay until they're updated, e.g. with [`Toaster::succeed`], or
+//! dismissed.
+//!
+//! [`SaveBar`](crate::forms::SaveBar) reports saves with a toast when the page provides
+//! them.
+
+use std::time::Duration;
+
+use leptos::prelude::*;
+
+/// How many toasts are shown at once.
+pub const MAX_VISIBLE: usize = 3;
+
+/// What a toast reports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ToastKind {
+    Success,
+    Error,
+    /// Something still going on, e.g. an import. Never dismissed automatically.
+    Progress,
+}
+
+impl ToastKind {
+    /// How long the toast is shown, if it dismisses itself.
+    fn duration(self) -> Option<Duration> {
+        match self {
+            ToastKind::Success => Some(Duration::from_secs(4)),
+            ToastKind::Error => Some(Duration::from_secs(8)),
+            ToastKind::Progress => None,
+        }
+    }
+
+    fn class(self) -> &'static str {
+        match self {
+            ToastKind::Success => "bg-green-700 text-white",
+            ToastKind::Error => "bg-red-700 text-white",
+            ToastKind::Progress => "bg-gray-800 text-white",
+        }
+    }
+}
+
+/// Identifies a toast, to update or dismiss it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ToastId(u64);
+
+#[derive(Debug, Clone)]
+struct Toast {
+    id: ToastId,
+    kind: ToastKind,
+    message: String,
+}
+
+/// Shows toasts in the page's [`Toasts`].
+#[derive(Debug, Clone, Copy)]
+pub struct Toaster {
+    /// Oldest first; the first [`MAX_VISIBLE`] are shown.
+    toasts: RwSignal<Vec<Toast>>,
+    next_id: StoredValue<u64>,
+}
+
+impl Toaster {
+    pub fn success(&self, message: impl Into<String>) -> ToastId {
+        self.show(ToastKind::Success, message.into())
+    }
+
+    pub fn error(&self, message: impl Into<String>) -> ToastId {
+        self.show(ToastKind::Error, message.into())
+    }
+
+    pub fn progress(&self, message: impl Into<String>) -> ToastId {
+        self.show(ToastKind::Progress, message.into())
+    }
+
+    pub fn show(&self, kind: ToastKind, message: String) -> ToastId {
+        let id = ToastId(self.next_id.get_value());
+        self.next_id.update_value(|next| *next += 1);
+        self.toasts
+            .update(|toasts| toasts.push(Toast { id, kind, message }));
+        id
+    }
+
+    /// Change a toast, e.g. a progress toast's message. Does nothing if it's gone.
+    pub fn update(&self, id: ToastId, kind: ToastKind, message: impl Into<String>) {
+        let message = message.into();
+        self.toasts.update(|toasts| {
+            if let Some(toast) = toasts.iter_mut().find(|toast| toast.id == id) {
+                toast.kind = kind;
+                toast.message = message;
+            }
+        });
+    }
+
+    /// Turn a progress toast into a success toast.
+    pub fn succeed(&self, id: ToastId, message: impl Into<String>) {
+        self.update(id, ToastKind::Success, message);
+    }
+
+    /// Turn a progress toast into an error toast.
+    pub fn fail(&self, id: ToastId, message: impl Into<String>) {
+        self.update(id, ToastKind::Error, message);
+    }
+
+    pub fn dismiss(&self, id: ToastId) {
+        self.toasts
+            .update(|toasts| toasts.retain(|toast| toast.id != id));
+    }
+}
+
+/// Provide a [`Toaster`] to the current component tree. Call this at the root of each
+/// page that shows toasts.
+pub fn provide_toasts() -> Toaster {
+    let toaster = Toaster {
+        toasts: RwSignal::new(Vec::new()),
+        next_id: StoredValue::new(0),
+    };
+    provide_context(toaster);
+    toaster
+}
+
+/// The [`Toaster`] provided by [`provide_toasts`], if any.
+pub fn use_toaster() -> Option<Toaster> {
+    use_context()
+}
+
+/// The visible toasts, stacked at the top of the page, clear of the [`SaveBar`](crate::forms::SaveBar).
+#[component]
+pub fn Toasts() -> impl IntoView {
+    let toaster = use_toaster().expect("Toasts needs provide_toasts() above it");
+    view! {
+        <div
+            role="status"
+            aria-live="polite"
+            class="fixed top-2 inset-x-2 z-50 flex flex-col items-center gap-1 pointer-events-none"
+        >
+            <For
+                each=move || {
+                    toaster.toasts.with(|toasts| toasts.iter().take(MAX_VISIBLE).cloned().collect::<Vec<_>>())
+                }
+                // Changing a toast re-renders it, which restarts its timer.
+                key=|toast| (toast.id, toast.kind, toast.message.clone())
+                children=move |toast| view! { <ToastItem toast toaster /> }
+            />
+        </div>
+    }
+}
+
+#[component]
+fn ToastItem(toast: Toast, toaster: Toaster) -> impl IntoView {
+    let id = toast.id;
+    if let Some(duration) = toast.kind.duration() {
+        if let Ok(handle) = set_timeout_with_handle(move || toaster.dismiss(id), duration) {
+            on_cleanup(move || handle.clear());
+        }
+    }
+    view! {
+        <div
+            role=(toast.kind == ToastKind::Error).then_some("alert")
+            class=format!(
+                "pointer-events-auto flex items-center gap-2 max-w-full px-3 py-2 rounded shadow {}",
+                toast.kind.class(),
+            )
+        >
+            {(toast.kind == ToastKind::Progress)
+                .then(|| {
+                    view! {
+                        <span
+                            aria-hidden="true"
+                            class="inline-block size-3 border-2 border-current border-t-transparent rounded-full animate-spin"
+                        ></span>
+                    }
+                })}
+            <span>{toast.message}</span>
+            <button
+                type="button"
+                class="ms-2 px-1"
+                aria-label="Dismiss"
+                on:click=move |_| toaster.dismiss(id)
+            >
+                "×"
+            </button>
+        </div>
+    }
+}
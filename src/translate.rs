@@ -0,0 +1,366 @@
+//! Translating selected text: a worked example of a complete feature, from the
+//! context menu to the page.
+//!
+//! [`install`] adds a context menu item for selections. Choosing it sends the selected
+//! text to the background, which translates it with a LibreTranslate-compatible API
+//! (the endpoint, API key and target language are [`Settings`], edited on the options
+//! page). Requests go through [`fetch`](crate::fetch) layers that retry transient
+//! failures and space requests a second apart, and translations are cached in
+//! `storage.local` for a week, so translating the same text again is instant and
+//! doesn't count against the API's quota. The result is shown in a small overlay next
+//! to the selection, injected with `scripting.executeScript` under the `activeTab`
+//! grant the click gives. Where pages can't be scripted, e.g. the browser's own pages
+//! or other-origin frames, it's shown as a notification instead.
+//!
+//! Needs the `contextMenus`, `notifications`, `scripting` and `activeTab` permissions.
+//! LibreTranslate servers allow cross-origin requests, so no host permission is
+//! needed.
+
+use std::rc::Rc;
+
+use js_sys::{Array, Function, Reflect};
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Request, RequestInit, Response};
+
+use crate::{
+    browser,
+    clock::{Clock, SystemClock},
+    error::WextError,
+    fetch::{Fetch, Network, RateLimit, Retry},
+    health, i18n, lifecycle,
+    retry::RetryPolicy,
+    storage,
+};
+
+/// `storage.local` key for the [`Settings`].
+pub const SETTINGS_KEY: &str = "translate.settings";
+/// `storage.local` key for cached translations.
+const CACHE_KEY: &str = "wext.translate.cache";
+
+/// The context menu item's id.
+const MENU_ID: &str = "wext-translate-selection";
+
+/// The public LibreTranslate instance. It needs an API key; self-hosted ones may not.
+pub const DEFAULT_ENDPOINT: &str = "https://libretranslate.com/translate";
+
+/// Longer selections are cut to this many characters before translating.
+const MAX_TEXT_CHARS: usize = 2_000;
+/// How long translations are cached.
+const CACHE_TTL_MS: f64 = 7.0 * 24.0 * 60.0 * 60.0 * 1000.0;
+/// How many translations are cached; the oldest go first.
+const MAX_CACHED: usize = 200;
+/// The least time between two requests to the API.
+const MIN_REQUEST_INTERVAL_MS: f64 = 1_000.0;
+
+/// Notifications need an icon in Chrome. A transparent pixel; use the extension's icon
+/// instead once it has one.
+const NOTIFICATION_ICON: &str = "data:image/png;base64,iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAQAAAC1HAwCAAAAC0lEQVR42mNkYAAAAAYAAjCB0C8AAAAASUVORK5CYII=";
+
+#[wasm_bindgen(inline_js = r##"
+export function overlay_renderer() {
+  return (original, translation, error) => {
+    const ID = "wext-translate-overlay";
+    document.getElementById(ID)?.remove();
+    const host = document.createElement("div");
+    host.id = ID;
+    host.style.all = "initial";
+    const shadow = host.attachShadow({ mode: "closed" });
+
+    const selection = getSelection();
+    const rect = selection && selection.rangeCount ? selection.getRangeAt(0).getBoundingClientRect() : null;
+    const box = document.createElement("div");
+    box.setAttribute("role", "dialog");
+    box.setAttribute("aria-label", "Translation");
+    Object.assign(box.style, {
+      position: "fixed",
+      zIndex: "2147483647",
+      top: `${rect && rect.height ? Math.min(rect.bottom + 8, innerHeight - 120) : 16}px`,
+      left: `${rect && rect.width ? Math.max(8, Math.min(rect.left, innerWidth - 340)) : 16}px`,
+      maxWidth: "320px",
+      padding: "10px 12px",
+      borderRadius: "6px",
+      background: "#1f2937",
+      color: "#fff",
+      font: "14px/1.4 system-ui, sans-serif",
+      boxShadow: "0 4px 16px rgba(0, 0, 0, 0.3)",
+    });
+    const text = document.createElement("p");
+    text.style.margin = "0 16px 4px 0";
+    text.textContent = error ?? translation;
+    if (error) text.style.color = "#fca5a5";
+    const source = document.createElement("p");
+    source.style.cssText = "margin: 0; opacity: 0.7; font-size: 12px; max-height: 4.2em; overflow: hidden";
+    source.textContent = original;
+    const close = document.createElement("button");
+    close.textContent = "×";
+    close.setAttribute("aria-label", "Close");
+    close.style.cssText = "position: absolute; top: 4px; right: 6px; border: 0; background: none; color: inherit; font-size: 16px; cursor: pointer";
+    box.append(text, source, close);
+    shadow.append(box);
+    document.documentElement.append(host);
+
+    const dismiss = () => {
+      host.remove();
+      removeEventListener("keydown", onKey, true);
+      removeEventListener("mousedown", onClick, true);
+    };
+    const onKey = (event) => event.key === "Escape" && dismiss();
+    const onClick = (event) => event.composedPath().includes(host) || dismiss();
+    close.addEventListener("click", dismiss);
+    addEventListener("keydown", onKey, true);
+    addEventListener("mousedown", onClick, true);
+  };
+}
+"##)]
+extern "C" {
+    /// A function showing a translation (or an error) next to the selection, for
+    /// `scripting.executeScript`.
+    fn overlay_renderer() -> Function;
+}
+
+/// Where and how to translate, from the options page.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Settings {
+    /// A LibreTranslate-compatible `/translate` endpoint.
+    pub endpoint: String,
+    /// Empty if the server doesn't need one.
+    pub api_key: String,
+    /// A language code, e.g. `de`. Empty for the browser UI language.
+    pub target: String,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            endpoint: DEFAULT_ENDPOINT.to_string(),
+            api_key: String::new(),
+            target: String::new(),
+        }
+    }
+}
+
+impl Settings {
+    /// Check the settings, for the options form.
+    pub fn check(&self) -> Result<(), String> {
+        let local = ["http://localhost", "http://127.0.0.1"]
+            .iter()
+            .any(|prefix| self.endpoint.starts_with(prefix));
+        if !self.endpoint.starts_with("https://") && !local {
+            return Err("The translation service must be an https:// URL.".to_string());
+        }
+        let valid_target = self
+            .target
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || c == '-');
+        if self.target.len() > 10 || !valid_target {
+            return Err("The target language must be a code like de or pt-BR.".to_string());
+        }
+        Ok(())
+    }
+
+    /// The language to translate to.
+    fn target(&self) -> String {
+        if !self.target.is_empty() {
+            return self.target.clone();
+        }
+        // LibreTranslate takes bare language codes, e.g. `pt` rather than `pt-BR`.
+        let language = i18n::ui_language();
+        match language.split(['-', '_']).next() {
+            Some(code) if !code.is_empty() => code.to_string(),
+            _ => "en".to_string(),
+        }
+    }
+}
+
+/// A translation from the cache.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct Cached {
+    /// The target language and the text.
+    key: String,
+    translation: String,
+    at: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct TranslateResponse {
+    translated_text: String,
+}
+
+#[derive(Deserialize)]
+struct ErrorResponse {
+    error: String,
+}
+
+type Client = Retry<RateLimit<Network>>;
+
+thread_local! {
+    /// Shared, so the rate limit applies across translations.
+    static CLIENT: Rc<Client> = Rc::new(Retry::new(
+        RateLimit::new(Network, MIN_REQUEST_INTERVAL_MS),
+        RetryPolicy::default(),
+    ));
+}
+
+/// The settings, or the defaults if they were never saved.
+pub async fn settings() -> Result<Settings, WextError> {
+    Ok(storage::get(SETTINGS_KEY).await?.unwrap_or_default())
+}
+
+/// Translate `text` with the configured service, or from the cache.
+pub async fn translate(text: &str) -> Result<String, WextError> {
+    let settings = settings().await?;
+    let text: String = text.trim().chars().take(MAX_TEXT_CHARS).collect();
+    let target = settings.target();
+    let key = format!("{target}\n{text}");
+    if let Some(translation) = cached(&key).await {
+        return Ok(translation);
+    }
+
+    let body = serde_json::json!({
+        "q": text,
+        "source": "auto",
+        "target": target,
+        "format": "text",
+        "api_key": settings.api_key,
+    });
+    let init = RequestInit::new();
+    init.set_method("POST");
+    init.set_headers(&browser::object(&[(
+        "Content-Type",
+        "application/json".into(),
+    )]));
+    init.set_body(&body.to_string().into());
+    let request = Request::new_with_str_and_init(&settings.endpoint, &init)?;
+    let client = CLIENT.with(Rc::clone);
+    let response: Response = client.fetch(&request).await?;
+    let json = JsFuture::from(response.json()?).await?;
+    if !response.ok() {
+        let message = serde_wasm_bindgen::from_value::<ErrorResponse>(json)
+            .map_or_else(|_| response.status_text(), |error| error.error);
+        return Err(WextError::Other(format!(
+            "The translation service answered {}: {message}",
+            response.status()
+        )));
+    }
+    let translation = serde_wasm_bindgen::from_value::<TranslateResponse>(json)?.translated_text;
+    cache(key, translation.clone()).await;
+    Ok(translation)
+}
+
+/// Add the context menu item (titled `menu_title`, where `%s` stands for the
+/// selection) and translate what it's chosen for. Call this in the background script,
+/// before [`lifecycle::install`].
+pub fn install(menu_title: &str) {
+    let menu_title = menu_title.to_string();
+    // Menu items persist, so they're only created on install and update.
+    lifecycle::on_installed(move |_| {
+        browser::context_menus().create(browser::object(&[
+            ("id", MENU_ID.into()),
+            ("title", menu_title.as_str().into()),
+            ("contexts", Array::of1(&"selection".into()).into()),
+        ]));
+    });
+    browser::listen(&browser::context_menus().on_clicked(), |info, tab| {
+        let get =
+            |target: &JsValue, key: &str| Reflect::get(target, &key.into()).unwrap_or_default();
+        if get(&info, "menuItemId").as_string().as_deref() != Some(MENU_ID) {
+            return;
+        }
+        let Some(text) = get(&info, "selectionText").as_string() else {
+            return;
+        };
+        let tab_id = get(&tab, "id").as_f64().map(|id| id as i32);
+        let frame_id = get(&info, "frameId").as_f64().unwrap_or(0.0) as i32;
+        spawn_local(async move {
+            let result = translate(&text).await;
+            if let Err(e) = &result {
+                health::record_error("translate", e);
+            }
+            show(tab_id, frame_id, &text, result).await;
+        });
+    });
+}
+
+/// Show a translation next to the selection, or as a notification if the page can't
+/// be scripted.
+async fn show(tab_id: Option<i32>, frame_id: i32, text: &str, result: Result<String, WextError>) {
+    let (translation, error) = match &result {
+        Ok(translation) => (JsValue::from_str(translation), JsValue::NULL),
+        Err(e) => (JsValue::NULL, JsValue::from_str(&e.to_string())),
+    };
+    if let Some(tab_id) = tab_id {
+        let injected = browser::scripting()
+            .execute_script(browser::object(&[
+                (
+                    "target",
+                    browser::object(&[
+                        ("tabId", tab_id.into()),
+                        ("frameIds", Array::of1(&frame_id.into()).into()),
+                    ]),
+                ),
+                ("func", overlay_renderer().into()),
+                (
+                    "args",
+                    Array::of3(&text.into(), &translation, &error).into(),
+                ),
+            ]))
+            .await;
+        if injected.is_ok() {
+            return;
+        }
+    }
+    let (title, message) = match result {
+        Ok(translation) => ("Translation".to_string(), translation),
+        Err(e) => ("Couldn't translate".to_string(), e.to_string()),
+    };
+    let notified = browser::notifications()
+        .create(
+            "",
+            browser::object(&[
+                ("type", "basic".into()),
+                ("iconUrl", NOTIFICATION_ICON.into()),
+                ("title", title.into()),
+                ("message", message.into()),
+            ]),
+        )
+        .await;
+    if let Err(e) = notified {
+        gloo_console::warn!("Failed to show the translation:", e);
+    }
+}
+
+/// The cached translation for `key`, if it's fresh.
+async fn cached(key: &str) -> Option<String> {
+    let entries: Vec<Cached> = storage::get(CACHE_KEY).await.ok()??;
+    let now = SystemClock.now();
+    entries
+        .into_iter()
+        .find(|entry| entry.key == key && now - entry.at < CACHE_TTL_MS)
+        .map(|entry| entry.translation)
+}
+
+/// Cache a translation, dropping expired and the oldest entries. Failing to is only
+/// logged, since the translation itself succeeded.
+async fn cache(key: String, translation: String) {
+    let now = SystemClock.now();
+    let mut entries: Vec<Cached> = storage::get(CACHE_KEY)
+        .await
+        .ok()
+        .flatten()
+        .unwrap_or_default();
+    entries.retain(|entry| entry.key != key && now - entry.at < CACHE_TTL_MS);
+    entries.push(Cached {
+        key,
+        translation,
+        at: now,
+    });
+    let excess = entries.len().saturating_sub(MAX_CACHED);
+    entries.drain(..excess);
+    if let Err(e) = storage::set(CACHE_KEY, &entries).await {
+        gloo_console::warn!(format!("Failed to cache the translation: {e}"));
+    }
+}
@@ -0,0 +1,129 @@
+//! Text to speech.
+//!
+//! Uses `chrome.tts` (which needs the `tts` permission) where the browser has it, so
+//! speech keeps going after the popup closes. Firefox has no `chrome.tts`, so there
+//! the Web Speech API is used instead, which only works in contexts with a `window`
+//! and stops with the page.
+//!
+//! ```ignore
+//! tts::speak("Hello", &SpeakOptions { rate: Some(1.2), ..Default::default() }).await?;
+//! ```
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use web_sys::{SpeechSynthesis, SpeechSynthesisUtterance, SpeechSynthesisVoice};
+
+use crate::{browser, error::WextError};
+
+/// How to speak an utterance. Unset fields use the browser's defaults.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SpeakOptions {
+    /// A language like `en-US`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lang: Option<String>,
+    /// A voice from [`voices`].
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub voice_name: Option<String>,
+    /// 0.1 to 10, where 1 is normal speed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rate: Option<f64>,
+    /// 0 to 2, where 1 is normal pitch.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub pitch: Option<f64>,
+    /// 0 to 1.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub volume: Option<f64>,
+    /// Queue the utterance after the current one, rather than interrupting it.
+    pub enqueue: bool,
+}
+
+/// A voice the browser can speak with.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Voice {
+    pub voice_name: Option<String>,
+    pub lang: Option<String>,
+    /// Whether the voice is a network service.
+    #[serde(default)]
+    pub remote: bool,
+}
+
+/// Whether `chrome.tts` is available, rather than the Web Speech fallback.
+pub fn is_native() -> bool {
+    !browser::api("tts").is_undefined()
+}
+
+/// Speak `text`. Resolves once the utterance is queued, not when it's finished.
+pub async fn speak(text: &str, options: &SpeakOptions) -> Result<(), WextError> {
+    if is_native() {
+        let options = options.serialize(&serde_wasm_bindgen::Serializer::json_compatible())?;
+        browser::tts().speak(text, options).await?;
+        return Ok(());
+    }
+
+    let synthesis = synthesis()?;
+    let utterance = SpeechSynthesisUtterance::new_with_text(text)?;
+    if let Some(lang) = &options.lang {
+        utterance.set_lang(lang);
+    }
+    if let Some(rate) = options.rate {
+        utterance.set_rate(rate as f32);
+    }
+    if let Some(pitch) = options.pitch {
+        utterance.set_pitch(pitch as f32);
+    }
+    if let Some(volume) = options.volume {
+        utterance.set_volume(volume as f32);
+    }
+    if let Some(name) = &options.voice_name {
+        let voice = synthesis
+            .get_voices()
+            .iter()
+            .map(|voice| voice.unchecked_into::<SpeechSynthesisVoice>())
+            .find(|voice| voice.name() == *name);
+        utterance.set_voice(voice.as_ref());
+    }
+    if !options.enqueue {
+        synthesis.cancel();
+    }
+    synthesis.speak(&utterance);
+    Ok(())
+}
+
+/// Stop speaking, and drop anything queued.
+pub fn stop() {
+    if is_native() {
+        browser::tts().stop();
+    } else if let Ok(synthesis) = synthesis() {
+        synthesis.cancel();
+    }
+}
+
+/// The voices available.
+///
+/// With the Web Speech fallback, the list can be empty until the browser has loaded
+/// its voices, shortly after the page loads.
+pub async fn voices() -> Result<Vec<Voice>, WextError> {
+    if is_native() {
+        let voices = browser::tts().get_voices().await?;
+        return Ok(serde_wasm_bindgen::from_value(voices)?);
+    }
+    Ok(synthesis()?
+        .get_voices()
+        .iter()
+        .map(|voice| {
+            let voice: SpeechSynthesisVoice = voice.unchecked_into();
+            Voice {
+                voice_name: Some(voice.name()),
+                lang: Some(voice.lang()),
+                remote: !voice.local_service(),
+            }
+        })
+        .collect())
+}
+
+fn synthesis() -> Result<SpeechSynthesis, WextError> {
+    let window = web_sys::window().ok_or("speech synthesis needs a window")?;
+    Ok(window.speech_synthesis()?)
+}
@@ -0,0 +1,183 @@
+//! UI state that survives the popup closing.
+//!
+//! The popup's page is destroyed every time it closes, so anything only kept in
+//! signals (the scroll position, the selected tab, a half-written form) is gone when
+//! it's opened again. [`use_persistent_state`] is a signal whose value is kept in
+//! `storage.session`, per page: it starts with the default, switches to the saved
+//! value as soon as it's read back, and saves changes shortly after they happen and
+//! when the page closes. `storage.session` is cleared when the browser quits, so
+//! state doesn't outlive the browsing session.
+//!
+//! ```ignore
+//! let tab = use_persistent_state("tab", || Tab::Recent);
+//! let draft = use_persistent_state("note", String::new);
+//! // Once the note is sent, start over with an empty draft.
+//! draft.set(String::new());
+//!
+//! let list = NodeRef::<html::Div>::new();
+//! use_persistent_scroll("list", list);
+//! view! { <div node_ref=list class="overflow-y-auto">..</div> }
+//! ```
+
+use std::{cell::RefCell, collections::BTreeMap};
+
+use gloo_timers::callback::Timeout;
+use leptos::{ev, html::ElementType, prelude::*, spawn::spawn_local};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use wasm_bindgen::JsCast;
+use web_sys::Element;
+
+use crate::storage::{Area, StorageArea};
+
+/// Prefix of every `storage.session` key, followed by the page's path and the key.
+const KEY_PREFIX: &str = "wext.ui";
+/// How long after the last change values are saved.
+const SAVE_DELAY_MS: u32 = 250;
+
+/// The state of the page, shared by every persistent value on it.
+#[derive(Default)]
+struct Page {
+    /// Values changed since they were last saved, by storage key.
+    pending: BTreeMap<String, Value>,
+    /// Records values that aren't signals (e.g. scroll positions) before saving.
+    snapshots: Vec<Box<dyn Fn()>>,
+    /// The delayed save, if one is scheduled.
+    save: Option<Timeout>,
+    /// Whether the page's close is listened to.
+    installed: bool,
+}
+
+thread_local! {
+    static PAGE: RefCell<Page> = RefCell::default();
+}
+
+/// A signal kept in `storage.session` under `key`, which must be unique on the page.
+///
+/// `default` is used until the saved value has been read, and if there's none.
+pub fn use_persistent_state<T>(key: &str, default: impl FnOnce() -> T) -> RwSignal<T>
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    persist(key, default).0
+}
+
+/// Keep the scroll position of the `node` element under `key`, restoring it when
+/// the element is there and the saved position has been read. Content that loads
+/// later (e.g. a list fetched from the background) may not be tall enough yet; give
+/// the element a height, or render it once the content is there.
+pub fn use_persistent_scroll<E>(key: &str, node: NodeRef<E>)
+where
+    E: ElementType + 'static,
+    E::Output: JsCast + Clone + 'static,
+{
+    let (position, loaded, storage_key) = persist(key, || 0);
+    let mut restored = false;
+    Effect::new(move |_| {
+        if restored || !loaded.get() {
+            return;
+        }
+        if let Some(element) = node.get() {
+            element
+                .unchecked_ref::<Element>()
+                .set_scroll_top(position.get_untracked());
+            restored = true;
+        }
+    });
+    PAGE.with_borrow_mut(|page| {
+        page.snapshots.push(Box::new(move || {
+            if let Some(element) = node.get_untracked() {
+                let top = element.unchecked_ref::<Element>().scroll_top();
+                record(&storage_key, Value::from(top));
+            }
+        }))
+    });
+}
+
+/// The signal, whether its saved value has been read, and its storage key.
+fn persist<T>(key: &str, default: impl FnOnce() -> T) -> (RwSignal<T>, ReadSignal<bool>, String)
+where
+    T: Serialize + DeserializeOwned + Send + Sync + 'static,
+{
+    let path = window().location().pathname().unwrap_or_default();
+    let storage_key = format!("{KEY_PREFIX}.{}.{key}", path.trim_start_matches('/'));
+    let state = RwSignal::new(default());
+    let (loaded, set_loaded) = signal(false);
+    install();
+
+    spawn_local({
+        let storage_key = storage_key.clone();
+        async move {
+            match Area::Session.get::<T>(&storage_key).await {
+                Ok(Some(value)) => state.set(value),
+                Ok(None) => {}
+                Err(e) => gloo_console::warn!(format!("Failed to restore {storage_key}: {e}")),
+            }
+            set_loaded.set(true);
+        }
+    });
+    // Changes are only saved once the saved value is in, so a page closed while it's
+    // read doesn't overwrite it with the default.
+    Effect::new({
+        let storage_key = storage_key.clone();
+        move |_| {
+            let value = state.with(|value| serde_json::to_value(value));
+            if !loaded.get() {
+                return;
+            }
+            match value {
+                Ok(value) => {
+                    record(&storage_key, value);
+                    schedule_save();
+                }
+                Err(e) => gloo_console::warn!(format!("Failed to save {storage_key}: {e}")),
+            }
+        }
+    });
+    (state, loaded, storage_key)
+}
+
+/// Save everything when the page closes.
+fn install() {
+    let installed = PAGE.with_borrow_mut(|page| std::mem::replace(&mut page.installed, true));
+    if !installed {
+        // Dropping the handle keeps the listener.
+        let _ = window_event_listener(ev::pagehide, |_| save());
+    }
+}
+
+fn record(storage_key: &str, value: Value) {
+    PAGE.with_borrow_mut(|page| page.pending.insert(storage_key.to_string(), value));
+}
+
+fn schedule_save() {
+    let timeout = Timeout::new(SAVE_DELAY_MS, || {
+        // Dropping a timeout cancels it, which it can't do while it runs.
+        if let Some(timeout) = PAGE.with_borrow_mut(|page| page.save.take()) {
+            timeout.forget();
+        }
+        save();
+    });
+    PAGE.with_borrow_mut(|page| page.save = Some(timeout));
+}
+
+/// Write every pending value. Each write reaches the browser when its future is first
+/// polled, before the page gets to close, so this also works from `pagehide`.
+fn save() {
+    let snapshots = PAGE.with_borrow_mut(|page| std::mem::take(&mut page.snapshots));
+    for snapshot in &snapshots {
+        snapshot();
+    }
+    let pending = PAGE.with_borrow_mut(|page| {
+        page.snapshots = snapshots;
+        page.save = None;
+        std::mem::take(&mut page.pending)
+    });
+    for (key, value) in pending {
+        spawn_local(async move {
+            if let Err(e) = Area::Session.set_value(&key, value).await {
+                gloo_console::warn!(format!("Failed to save {key}: {e}"));
+            }
+        });
+    }
+}
@@ -0,0 +1,64 @@
+//! Uninstall feedback.
+//!
+//! The page opened after uninstalling is configured per channel in `wextrunk.toml`
+//! (`uninstall_url`, overridable per profile) and set by [`install`] in the
+//! background. Nothing of the extension runs after it's removed, so the "before you
+//! go" page (`farewell.html`) does the rest beforehand: it asks for a reason, adds it
+//! to the uninstall URL, clears synced data (which would otherwise follow the user to
+//! other devices) and then asks the browser to uninstall.
+
+use js_sys::Reflect;
+use wasm_bindgen_futures::{spawn_local, JsFuture};
+use web_sys::{Request, Response};
+
+use crate::{
+    browser,
+    error::WextError,
+    fetch::{Fetch, Network},
+    storage::{Area, StorageArea},
+};
+
+/// The uninstall URL from `build-info.json`, if this build has one.
+pub async fn uninstall_url() -> Result<Option<String>, WextError> {
+    let url = browser::runtime().get_url("build-info.json");
+    let response: Response = Network.fetch(&Request::new_with_str(&url)?).await?;
+    let info = JsFuture::from(response.json()?).await?;
+    Ok(Reflect::get(&info, &"uninstall_url".into())?.as_string())
+}
+
+/// Set the configured uninstall URL. Call this in the background script.
+pub fn install() {
+    spawn_local(async {
+        let result = async {
+            if let Some(url) = uninstall_url().await? {
+                browser::runtime().set_uninstall_url(&url).await?;
+            }
+            Ok::<_, WextError>(())
+        };
+        if let Err(e) = result.await {
+            gloo_console::warn!("Failed to set the uninstall URL:", e);
+        }
+    });
+}
+
+/// Uninstall the extension after the user confirms, reporting `reason` to the
+/// uninstall URL as a `reason` query parameter.
+///
+/// Synced data is cleared first, so it's removed from the user's other devices too.
+/// Returns an error if the user cancels the confirmation.
+pub async fn uninstall(reason: Option<&str>) -> Result<(), WextError> {
+    if let Some(reason) = reason {
+        if let Some(url) = uninstall_url().await? {
+            let separator = if url.contains('?') { '&' } else { '?' };
+            let reason = String::from(js_sys::encode_uri_component(reason));
+            browser::runtime()
+                .set_uninstall_url(&format!("{url}{separator}reason={reason}"))
+                .await?;
+        }
+    }
+    Area::Sync.clear().await?;
+    browser::management()
+        .uninstall_self(browser::object(&[("showConfirmDialog", true.into())]))
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,186 @@
+use leptos::{prelude::*, spawn::spawn_local};
+use serde::Deserialize;
+use wasm_bindgen_futures::JsFuture;
+use web_sys::{Request, Response};
+
+use crate::{
+    browser,
+    entry::wext_entry,
+    error::WextError,
+    fetch::{Fetch, Network},
+    i18n, lifecycle,
+    pages::{self, PageId},
+    storage,
+};
+
+/// Storage key for the "don't show again" preference.
+const DISABLED_KEY: &str = "wext.updatePage.disabled";
+
+/// A release from `changelog.json`, as written by wextrunk.
+#[derive(Debug, Clone, Deserialize)]
+struct Release {
+    version: String,
+    date: Option<String>,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct Section {
+    title: String,
+    items: Vec<String>,
+}
+
+/// Open the update page after updating to a new version, unless the user opted out.
+/// Call this in the background script, before [`lifecycle::install`].
+pub fn open_on_update() {
+    lifecycle::on_update(|previous_version| {
+        let query = format!("from={previous_version}");
+        spawn_local(async move {
+            if let Ok(Some(true)) = storage::get::<bool>(DISABLED_KEY).await {
+                return;
+            }
+            if let Err(e) = pages::open_with_query(PageId::Update, Some(&query)).await {
+                gloo_console::warn!("Failed to open the update page:", e);
+            }
+        });
+    });
+}
+
+#[wext_entry(page)]
+pub async fn update_page() {
+    mount_to_body(|| {
+        i18n::provide_direction();
+        view! {
+            <main class="max-w-prose mx-auto p-4">
+                <ReleaseNotes />
+                <DontShowAgain />
+            </main>
+        }
+    })
+}
+
+/// The notes for every version after the previously installed one (from the `from`
+/// query parameter), up to the current version.
+#[component]
+fn ReleaseNotes() -> impl IntoView {
+    let current = browser::extension_version();
+    let from = window()
+        .location()
+        .search()
+        .unwrap_or_default()
+        .trim_start_matches('?')
+        .split('&')
+        .find_map(|pair| pair.strip_prefix("from=").map(str::to_string));
+
+    let (releases, set_releases) = signal(None::<Vec<Release>>);
+    {
+        let current = current.clone();
+        spawn_local(async move {
+            match load_changelog().await {
+                Ok(all) => {
+                    let shown = all
+                        .into_iter()
+                        .filter(|release| {
+                            let version = parse_version(&release.version);
+                            version <= parse_version(&current)
+                                && from.as_deref().map_or(release.version == current, |from| {
+                                    version > parse_version(from)
+                                })
+                        })
+                        .collect();
+                    set_releases.set(Some(shown));
+                }
+                Err(e) => {
+                    gloo_console::error!("Failed to load the changelog:", e);
+                    set_releases.set(Some(Vec::new()));
+                }
+            }
+        });
+    }
+
+    view! {
+        <h1 class="text-xl font-bold mb-4">"Updated to version " {current}</h1>
+        {move || {
+            releases
+                .get()
+                .map(|releases| {
+                    if releases.is_empty() {
+                        return view! { <p>"No release notes for this version."</p> }.into_any();
+                    }
+                    releases
+                        .into_iter()
+                        .map(|release| view! { <ReleaseEntry release /> })
+                        .collect_view()
+                        .into_any()
+                })
+        }}
+    }
+}
+
+#[component]
+fn ReleaseEntry(release: Release) -> impl IntoView {
+    view! {
+        <section class="mb-4">
+            <h2 class="text-lg font-bold">
+                {release.version} {release.date.map(|date| format!(" ({date})"))}
+            </h2>
+            {release
+                .sections
+                .into_iter()
+                .map(|section| {
+                    view! {
+                        <h3 class="font-bold mt-2">{section.title}</h3>
+                        <ul class="list-disc ps-5">
+                            {section
+                                .items
+                                .into_iter()
+                                .map(|item| view! { <li>{item}</li> })
+                                .collect_view()}
+                        </ul>
+                    }
+                })
+                .collect_view()}
+        </section>
+    }
+}
+
+#[component]
+fn DontShowAgain() -> impl IntoView {
+    let (disabled, set_disabled) = signal(false);
+    spawn_local(async move {
+        if let Ok(Some(value)) = storage::get::<bool>(DISABLED_KEY).await {
+            set_disabled.set(value);
+        }
+    });
+    let on_change = move |ev| {
+        let checked = event_target_checked(&ev);
+        set_disabled.set(checked);
+        spawn_local(async move {
+            if let Err(e) = storage::set(DISABLED_KEY, &checked).await {
+                gloo_console::error!("Failed to save the preference:", e);
+            }
+        });
+    };
+    view! {
+        <label class="flex items-center gap-2 mt-6">
+            <input type="checkbox" prop:checked=disabled on:change=on_change />
+            "Don't show this page after updates"
+        </label>
+    }
+}
+
+async fn load_changelog() -> Result<Vec<Release>, WextError> {
+    let url = browser::runtime().get_url("changelog.json");
+    let request = Request::new_with_str(&url)?;
+    let response: Response = Network.fetch(&request).await?;
+    let json = JsFuture::from(response.json()?).await?;
+    Ok(serde_wasm_bindgen::from_value(json)?)
+}
+
+/// Parse a dotted version (e.g. `1.2.10`) for comparison.
+fn parse_version(version: &str) -> Vec<u64> {
+    version
+        .split('.')
+        .map(|part| part.parse().unwrap_or(0))
+        .collect()
+}
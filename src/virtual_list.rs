@@ -0,0 +1,91 @@
+//! A list that only renders the rows in view, so long lists (history, saved sessions,
+//! logs) stay fast in a popup.
+//!
+//! Every row has the same height; the list scrolls within its own height:
+//!
+//! ```ignore
+//! view! {
+//!     <VirtualList items=sessions row_height=48.0 height=240.0 label="Saved sessions"
+//!         key=|session: &Session| session.id row=|session| view! { <SessionRow session /> } />
+//! }
+//! ```
+
+use std::hash::Hash;
+
+use leptos::{ev::Event, prelude::*};
+use web_sys::Element;
+
+/// Rows rendered above and below the visible ones, so fast scrolling doesn't show gaps.
+const OVERSCAN: usize = 3;
+
+/// A scrolling list of `items`, rendering only the visible rows with `row`.
+#[component]
+pub fn VirtualList<T, K, KF, N, RF>(
+    #[prop(into)] items: Signal<Vec<T>>,
+    /// Every row's height, in pixels.
+    row_height: f64,
+    /// The list's height, in pixels.
+    height: f64,
+    /// Identifies an item, so its row is kept while it stays in the same place.
+    key: KF,
+    row: RF,
+    /// The list's accessible name.
+    #[prop(into)]
+    label: String,
+) -> impl IntoView
+where
+    T: Clone + Send + Sync + 'static,
+    K: Eq + Hash + Send + Sync + 'static,
+    KF: Fn(&T) -> K + Clone + Send + Sync + 'static,
+    N: IntoView + 'static,
+    RF: Fn(T) -> N + Clone + Send + Sync + 'static,
+{
+    let scroll_top = RwSignal::new(0.0);
+    let visible = move || {
+        let first = ((scroll_top.get() / row_height) as usize).saturating_sub(OVERSCAN);
+        let count = (height / row_height).ceil() as usize + 2 * OVERSCAN;
+        items.with(|items| {
+            items
+                .iter()
+                .cloned()
+                .enumerate()
+                .skip(first)
+                .take(count)
+                .collect::<Vec<_>>()
+        })
+    };
+    let len = move || items.with(Vec::len);
+    let on_scroll = move |ev: Event| {
+        scroll_top.set(event_target::<Element>(&ev).scroll_top() as f64);
+    };
+    view! {
+        <div
+            role="list"
+            aria-label=label
+            class="relative overflow-y-auto"
+            style:height=format!("{height}px")
+            on:scroll=on_scroll
+        >
+            <div class="relative" style:height=move || format!("{}px", len() as f64 * row_height)>
+                <For
+                    each=visible
+                    key=move |(index, item)| (*index, key(item))
+                    children=move |(index, item)| {
+                        view! {
+                            <div
+                                role="listitem"
+                                aria-posinset=index + 1
+                                aria-setsize=len
+                                class="absolute inset-x-0"
+                                style:top=format!("{}px", index as f64 * row_height)
+                                style:height=format!("{row_height}px")
+                            >
+                                {row(item)}
+                            </div>
+                        }
+                    }
+                />
+            </div>
+        </div>
+    }
+}
@@ -0,0 +1,194 @@
+//! Detached "app windows": extension pages in a small window of their own, e.g. a
+//! picture-in-picture style player or a popup the user wants to keep open.
+//!
+//! [`open_app_window`] opens a page in a `popup`-type window, sized and placed where
+//! the user last left it, or focuses the window if it's already open. The page itself
+//! calls [`remember_bounds`] so its size and position are saved as they change.
+//!
+//! ```ignore
+//! windows::open_app_window(PageId::Popup, Bounds::size(360, 480)).await?;
+//! ```
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+use wasm_bindgen_futures::spawn_local;
+
+use crate::{browser, error::WextError, pages::PageId, storage};
+
+/// How often an app window checks whether it was resized or moved.
+const BOUNDS_POLL_MS: u32 = 1000;
+
+/// A window's position and size, in screen pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bounds {
+    /// Leave unset to let the browser place the window.
+    pub left: Option<i32>,
+    pub top: Option<i32>,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Bounds {
+    /// Bounds with only a size, placed by the browser.
+    pub fn size(width: i32, height: i32) -> Self {
+        Bounds {
+            left: None,
+            top: None,
+            width,
+            height,
+        }
+    }
+}
+
+/// The parts of `windows.Window` needed here.
+#[derive(Debug, Deserialize)]
+struct Window {
+    id: Option<i32>,
+    #[serde(rename = "type")]
+    kind: Option<String>,
+    #[serde(default)]
+    tabs: Vec<Tab>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tab {
+    url: Option<String>,
+}
+
+/// Storage key for a page's saved window bounds.
+fn bounds_key(page: PageId) -> String {
+    format!("wext.appWindow.{}", page.spec().name)
+}
+
+/// Open `page` in a detached window, or focus it if one is already open. The window
+/// uses the saved bounds, or `default` the first time. Returns the window id.
+pub async fn open_app_window(page: PageId, default: Bounds) -> Result<i32, WextError> {
+    if let Some(id) = find_window(page).await? {
+        focus(id).await?;
+        return Ok(id);
+    }
+    let bounds = storage::get::<Bounds>(&bounds_key(page))
+        .await?
+        .unwrap_or(default);
+    let mut create_data = vec![
+        ("url", page.url().into()),
+        ("type", "popup".into()),
+        ("width", bounds.width.into()),
+        ("height", bounds.height.into()),
+    ];
+    if let (Some(left), Some(top)) = (bounds.left, bounds.top) {
+        create_data.push(("left", left.into()));
+        create_data.push(("top", top.into()));
+    }
+    let window = browser::windows()
+        .create(browser::object(&create_data))
+        .await?;
+    let window: Window = serde_wasm_bindgen::from_value(window)?;
+    window
+        .id
+        .ok_or_else(|| "windows.create returned a window without an id".into())
+}
+
+/// Bring a window to the front.
+pub async fn focus(window_id: i32) -> Result<(), WextError> {
+    browser::windows()
+        .update(window_id, browser::object(&[("focused", true.into())]))
+        .await?;
+    Ok(())
+}
+
+/// Resize or move a window.
+pub async fn set_bounds(window_id: i32, bounds: Bounds) -> Result<(), WextError> {
+    let mut update_info = vec![
+        ("width", bounds.width.into()),
+        ("height", bounds.height.into()),
+    ];
+    if let (Some(left), Some(top)) = (bounds.left, bounds.top) {
+        update_info.push(("left", left.into()));
+        update_info.push(("top", top.into()));
+    }
+    browser::windows()
+        .update(window_id, browser::object(&update_info))
+        .await?;
+    Ok(())
+}
+
+/// Close a window.
+pub async fn close(window_id: i32) -> Result<(), WextError> {
+    browser::windows().remove(window_id).await?;
+    Ok(())
+}
+
+/// The id of a detached window showing `page`, if there is one.
+async fn find_window(page: PageId) -> Result<Option<i32>, WextError> {
+    let windows = browser::windows()
+        .get_all(browser::object(&[
+            ("populate", true.into()),
+            ("windowTypes", serde_wasm_bindgen::to_value(&["popup"])?),
+        ]))
+        .await?;
+    let windows: Vec<Window> = serde_wasm_bindgen::from_value(windows)?;
+    Ok(windows
+        .into_iter()
+        .find(|window| {
+            window
+                .tabs
+                .iter()
+                .any(|tab| tab.url.as_deref().is_some_and(|url| page.matches_url(url)))
+        })
+        .and_then(|window| window.id))
+}
+
+/// Save this page's window bounds whenever the window is resized or moved.
+///
+/// Call this from the page's entry point; it does nothing when the page isn't in a
+/// detached window (e.g. shown as the action popup or in a tab). There's no DOM event
+/// for moves, so the bounds are checked every second.
+pub fn remember_bounds() {
+    let Some(page) = PageId::current() else {
+        return;
+    };
+    spawn_local(async move {
+        let Ok(current) = browser::windows().get_current().await else {
+            return;
+        };
+        let Ok(current) = serde_wasm_bindgen::from_value::<Window>(current) else {
+            return;
+        };
+        if current.kind.as_deref() != Some("popup") {
+            return;
+        }
+        let key = bounds_key(page);
+        let mut saved = current_bounds();
+        gloo_timers::callback::Interval::new(BOUNDS_POLL_MS, move || {
+            let bounds = current_bounds();
+            if bounds != saved {
+                saved = bounds;
+                let key = key.clone();
+                spawn_local(async move {
+                    if let Err(e) = storage::set(&key, &bounds).await {
+                        gloo_console::warn!("Failed to save the window bounds:", e);
+                    }
+                });
+            }
+        })
+        .forget();
+    });
+}
+
+/// This window's bounds, from the DOM.
+fn current_bounds() -> Bounds {
+    let window = web_sys::window().unwrap();
+    let pixels = |value: Result<JsValue, JsValue>| {
+        value
+            .ok()
+            .and_then(|value| value.as_f64())
+            .map(|value| value as i32)
+    };
+    Bounds {
+        left: pixels(window.screen_x()),
+        top: pixels(window.screen_y()),
+        width: pixels(window.outer_width()).unwrap_or_default(),
+        height: pixels(window.outer_height()).unwrap_or_default(),
+    }
+}